@@ -13,20 +13,212 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-use std::mem::size_of;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::mem::{replace, size_of, transmute};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use bytes::{BufMut, Bytes, BytesMut};
+use spin::Mutex;
+
+use super::pmem::PmemSegment;
+use super::shm::{HeapStore, Segment};
+use super::snapshot::Crc32;
+use super::table::Layout;
+use super::wal::Wal;
+
+use time;
+
+// The size of each hot arena in bytes. Chosen to match the size of a huge page so that all hot
+// objects in an arena can be mapped by a single TLB entry.
+const HOT_ARENA_SIZE: usize = 2 * 1024 * 1024;
+
+// Objects larger than this many bytes are never promoted into a hot arena. Such objects already
+// span multiple cache lines, and there is little to gain from packing them together.
+const HOT_OBJECT_MAX: usize = 4096;
+
+// The offset of the version on an object's metadata.
+const VERSION_OFFSET: usize = 14;
+
+// The offset of the value checksum on an object's metadata, when checksums are enabled.
+const CRC_OFFSET: usize = 22;
+
+// The offset of the expiration time on an object's metadata, when expiration is enabled and
+// checksums are not. It follows the checksum otherwise.
+const EXPIRY_OFFSET: usize = 22;
+
+// The set of arenas that hot objects are migrated into. Hot objects are packed densely into the
+// current arena; a new arena is allocated once the current one fills up.
+struct HotArenas {
+    // The unused region of the arena that objects are currently being packed into.
+    current: BytesMut,
+
+    // Every arena that has not been freed yet. Required to determine if an object has already
+    // been promoted.
+    arenas: Vec<Arena>,
+}
+
+// An arena that hot objects are packed into.
+struct Arena {
+    // The address range `[start, end)` of the arena.
+    start: usize,
+    end: usize,
+
+    // An empty handle on the arena's memory. Once it is the only handle left, every object in
+    // the arena has been dropped, and the arena can be freed (refer to `reclaim_hot()`).
+    witness: Bytes,
+
+    // The number of bytes promoted into the arena.
+    bytes: usize,
+}
+
+impl Arena {
+    // Allocates a new arena, and returns it along with the region objects are packed into.
+    fn new() -> (Arena, BytesMut) {
+        let mut current = BytesMut::with_capacity(HOT_ARENA_SIZE);
+        let start = current.as_ptr() as usize;
+        let witness = current.split_to(0).freeze();
+
+        let arena = Arena {
+            start: start,
+            end: start + HOT_ARENA_SIZE,
+            witness: witness,
+            bytes: 0,
+        };
+
+        (arena, current)
+    }
+}
+
+/// The memory quota shared by a group of tenants. Refer to `Allocator::set_group_quota()`.
+/// Objects are charged to the group once they are added to a table of one of it's tenants, and
+/// credited back as soon as they are removed from it (refer to `Table::set_quota()`), so that
+/// allocations that never make it into a table, and objects that were overwritten, do not count
+/// against the quota.
+pub struct Quota {
+    // The number of bytes the objects of the group's tenants can occupy.
+    limit: usize,
+
+    // The number of bytes occupied by the objects in the tables of the group's tenants.
+    used: AtomicUsize,
+
+    // The number of allocations refused because the group was over it's quota.
+    refused: AtomicUsize,
+}
+
+// Implementation of methods on Quota.
+impl Quota {
+    /// Charges an object of `size` bytes that was added to a table of one of the group's tenants.
+    pub fn charge(&self, size: usize) {
+        self.used.fetch_add(size, Ordering::Relaxed);
+    }
+
+    /// Credits back an object of `size` bytes that was removed from a table of one of the group's
+    /// tenants, after having been charged to the group.
+    pub fn credit(&self, size: usize) {
+        self.used.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    // Returns true if an allocation of `size` bytes fits in what is left of the quota. Nothing
+    // is charged until the object is added to a table, so concurrent allocations can take the
+    // group past it's quota by at most their own size.
+    fn admit(&self, size: usize) -> bool {
+        if self.used.load(Ordering::Relaxed) + size > self.limit {
+            self.refused.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        true
+    }
+}
 
 /// This type represents the memory allocator in Sandstorm. The allocator
 /// allocates and initializes objects that can then be inserted into a
 /// particular tenant's hash table. Each allocated object has the following
 /// layout in memory (Metadata is written in little-endian):
-///      ______________________________________________________________________
-///     |           |           |            |             |                   |
-///     | Tenant-ID | Table-ID  | Key-Length |     Key     |       Value       |
-///     |___________|___________|____________|_____________|___________________|
-///        4 Bytes     8 Bytes     2 Bytes      Var Length       Var Length
-pub struct Allocator {}
+///      __________________________________________________________________________________
+///     |           |           |            |           |             |                   |
+///     | Tenant-ID | Table-ID  | Key-Length |  Version  |     Key     |       Value       |
+///     |___________|___________|____________|___________|_____________|___________________|
+///        4 Bytes     8 Bytes     2 Bytes      8 Bytes     Var Length       Var Length
+///
+/// Every allocation is stamped with a version larger than that of any earlier allocation, so an
+/// object written under a key always carries a newer version than the one it replaced. Versions
+/// allow a write to be made conditional on the object it replaces (ex: by a cas() RPC).
+///
+/// If value checksums are enabled, a 4 byte CRC-32 of the value follows the key length. A stored
+/// checksum of zero means that the value has not been checksummed (ex: it was updated in place),
+/// and is never verified; values whose checksum works out to zero are stored with a checksum of
+/// one instead.
+///
+/// If expiration is enabled, an 8 byte expiration time follows the key length (and checksum, if
+/// any). It is the wall-clock time in milliseconds since the unix epoch after which the object is
+/// considered missing, or zero if the object never expires. Objects are stamped with one when
+/// allocated for a table that has a time-to-live (refer to `set_ttl()`).
+///
+/// Objects are initially allocated individually on the heap. Objects that are frequently read can
+/// later be promoted (copied) into dense "hot" arenas, so that they share cache and TLB pages.
+///
+/// An allocator can optionally be backed by a persistent heap segment. Objects are then copied
+/// into the segment when they are committed to a table, so that a restarted server can rebuild
+/// it's tables from the segment. The segment can either live in shared memory (`Segment`) or on
+/// persistent memory (`PmemSegment`), and can be restricted to a subset of tables. Alternatively,
+/// or additionally, committed objects and deletions can be appended to a write-ahead log (`Wal`)
+/// while the objects themselves stay in process memory.
+pub struct Allocator {
+    // Arenas holding promoted (hot) objects.
+    hot: Mutex<HotArenas>,
+
+    // The total number of objects promoted into the hot arenas, and the total number of bytes
+    // promoted into arenas that have not been freed yet.
+    promoted: AtomicUsize,
+    hot_bytes: AtomicUsize,
+
+    // The persistent segment that committed objects are copied into, if any.
+    segment: Option<Box<HeapStore>>,
+
+    // The (tenant, table) pairs whose objects are copied into the persistent segment. Objects
+    // of every table are copied if empty.
+    durable: HashSet<(u32, u64)>,
+
+    // Set once the persistent segment fills up and an object could not be persisted.
+    segment_full: AtomicBool,
+
+    // Computes value checksums. None if objects are not checksummed.
+    crc: Option<Crc32>,
+
+    // Checksums are verified on one in every `verify_every` reads.
+    verify_every: usize,
+
+    // The number of reads that could have been verified, the number that were, and the number
+    // that found a value that did not match it's checksum.
+    reads: AtomicUsize,
+    verified: AtomicUsize,
+    corrupt: AtomicUsize,
+
+    // The version the next allocation will be stamped with.
+    next_version: AtomicUsize,
+
+    // The total number of bytes allocated for objects. Objects are freed once the last handle to
+    // them is dropped, which the allocator never hears of, so this only ever grows.
+    allocated: AtomicUsize,
+
+    // The write-ahead log that committed objects and deletions are appended to, if any.
+    wal: Option<Arc<Wal>>,
+
+    // True if objects carry an expiration time.
+    expiry: bool,
+
+    // The time-to-live in milliseconds of objects allocated for a (tenant, table) pair.
+    ttls: HashMap<(u32, u64), u64>,
+
+    // The group every tenant with a memory quota belongs to, and the quota of every group,
+    // indexed by group. Tenants that do not belong to a group are not limited.
+    groups: HashMap<u32, usize>,
+    quotas: Vec<Arc<Quota>>,
+}
 
 // Implementation of methods on Allocator.
 impl Allocator {
@@ -36,7 +228,415 @@ impl Allocator {
     /// # Return
     /// An allocator of type `Allocator`.
     pub fn new() -> Allocator {
-        Allocator {}
+        // Allocate the first hot arena upfront.
+        let (arena, current) = Arena::new();
+
+        Allocator {
+            hot: Mutex::new(HotArenas {
+                current: current,
+                arenas: vec![arena],
+            }),
+            promoted: AtomicUsize::new(0),
+            hot_bytes: AtomicUsize::new(0),
+            segment: None,
+            durable: HashSet::new(),
+            segment_full: AtomicBool::new(false),
+            crc: None,
+            verify_every: 1,
+            reads: AtomicUsize::new(0),
+            verified: AtomicUsize::new(0),
+            corrupt: AtomicUsize::new(0),
+            next_version: AtomicUsize::new(1),
+            allocated: AtomicUsize::new(0),
+            wal: None,
+            expiry: false,
+            ttls: HashMap::new(),
+            groups: HashMap::new(),
+            quotas: Vec::new(),
+        }
+    }
+
+    /// This method returns an allocator backed by a persistent heap segment.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the shared-memory or DAX file backing the segment.
+    /// * `size`: The size of the segment in bytes.
+    ///
+    /// # Return
+    /// An allocator that persists committed objects into the segment. Objects committed by an
+    /// earlier run can be walked through `recover()`.
+    pub fn persistent(path: &str, size: usize) -> io::Result<Allocator> {
+        Ok(Allocator::with_store(Box::new(Segment::open(path, size)?)))
+    }
+
+    /// This method returns an allocator backed by a heap segment on persistent memory. Every
+    /// object is flushed out to the segment before `commit()` returns, so puts survive a crash
+    /// of the machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the DAX file backing the segment.
+    /// * `size`: The size of the segment in bytes.
+    ///
+    /// # Return
+    /// An allocator that persists committed objects into the segment. Objects committed by an
+    /// earlier run can be walked through `recover()`.
+    pub fn pmem(path: &str, size: usize) -> io::Result<Allocator> {
+        Ok(Allocator::with_store(Box::new(PmemSegment::open(path, size)?)))
+    }
+
+    /// This method returns an allocator that persists committed objects into a given store.
+    ///
+    /// # Arguments
+    ///
+    /// * `store`: The store backing the allocator's persistent heap segment.
+    pub fn with_store(store: Box<HeapStore>) -> Allocator {
+        let mut heap = Allocator::new();
+        heap.segment = Some(store);
+        heap
+    }
+
+    /// This method restricts the persistent segment to the objects of a set of tables. Objects
+    /// of other tables stay in memory only, and are lost on a restart, but avoid the cost of
+    /// being copied (and on persistent memory, flushed) into the segment on every put. Must be
+    /// called before the allocator is shared.
+    ///
+    /// # Arguments
+    ///
+    /// * `tables`: The (tenant, table) pairs whose objects are persisted. Empty persists the
+    ///             objects of every table.
+    pub fn set_durable_tables(&mut self, tables: Vec<(u32, u64)>) {
+        self.durable = tables.into_iter().collect();
+    }
+
+    // Returns true if objects of a tenant's table are copied into the persistent segment.
+    fn is_durable(&self, tenant: u32, table: u64) -> bool {
+        self.durable.is_empty() || self.durable.contains(&(tenant, table))
+    }
+
+    /// This method enables value checksums. Every object allocated afterwards carries a CRC-32
+    /// of it's value, which is verified on reads through `checksum_ok()`. Must be called before
+    /// any object is allocated or recovered, since it changes the layout of every object.
+    ///
+    /// # Arguments
+    ///
+    /// * `verify_every`: Checksums are verified on one in every this many reads. Zero or one
+    ///                   verifies every read.
+    pub fn set_checksums(&mut self, verify_every: u64) {
+        self.crc = Some(Crc32::new());
+        self.verify_every = verify_every.max(1) as usize;
+    }
+
+    /// This method enables expiration times on objects, and gives objects allocated for a table
+    /// from here on a time-to-live. Such objects expire once it elapses after their allocation,
+    /// and are treated as missing by reads that check `is_expired()`. Must be called before any
+    /// object is allocated or recovered, since it changes the layout of every object.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: An identifier for the tenant owning the table.
+    /// * `table`:  An identifier for the table.
+    /// * `ttl_ms`: The time-to-live in milliseconds. Zero only enables expiration times, leaving
+    ///             the table's objects to never expire.
+    pub fn set_ttl(&mut self, tenant: u32, table: u64, ttl_ms: u64) {
+        self.expiry = true;
+        if ttl_ms > 0 {
+            self.ttls.insert((tenant, table), ttl_ms);
+        }
+    }
+
+    /// This method limits the memory the objects of a group of tenants can occupy together.
+    /// Allocations for a tenant in the group are refused once the group is over it's quota. The
+    /// quota is only charged for objects in tables that it was handed to (refer to
+    /// `Table::set_quota()`). A tenant belongs to at most one group; tenants already in one are
+    /// left there. Must be called before the allocator is shared.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenants`: The tenants in the group.
+    /// * `limit`:   The number of bytes the group's objects can occupy.
+    ///
+    /// # Return
+    /// The group's quota, to be handed to the tables of it's tenants.
+    pub fn set_group_quota(&mut self, tenants: &[u32], limit: usize) -> Arc<Quota> {
+        let group = self.quotas.len();
+        self.quotas.push(Arc::new(Quota {
+            limit: limit,
+            used: AtomicUsize::new(0),
+            refused: AtomicUsize::new(0),
+        }));
+
+        for tenant in tenants.iter() {
+            self.groups.entry(*tenant).or_insert(group);
+        }
+
+        self.quotas[group].clone()
+    }
+
+    /// This method returns the quota of the group a tenant belongs to, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: An identifier for the tenant.
+    pub fn group_quota(&self, tenant: u32) -> Option<Arc<Quota>> {
+        self.groups.get(&tenant).map(| group | { self.quotas[*group].clone() })
+    }
+
+    /// This method returns true if a tenant belongs to a group whose objects have used up the
+    /// group's memory quota.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: An identifier for the tenant.
+    pub fn over_quota(&self, tenant: u32) -> bool {
+        self.groups.get(&tenant).map_or(false, | group | {
+            let quota = &self.quotas[*group];
+            quota.used.load(Ordering::Relaxed) >= quota.limit
+        })
+    }
+
+    /// This method returns the number of bytes every group's objects are taken to occupy, the
+    /// group's quota, and the number of allocations refused to the group, indexed by group.
+    pub fn group_stats(&self) -> Vec<(usize, usize, usize)> {
+        self.quotas
+            .iter()
+            .map(| quota | {
+                (
+                    quota.used.load(Ordering::Relaxed),
+                    quota.limit,
+                    quota.refused.load(Ordering::Relaxed),
+                )
+            }).collect()
+    }
+
+    // Returns false if an allocation of `size` bytes would take the group a tenant belongs to
+    // over it's quota. Tenants that do not belong to a group are never refused.
+    fn admit(&self, tenant: u32, size: usize) -> bool {
+        match self.groups.get(&tenant) {
+            Some(group) => self.quotas[*group].admit(size),
+            None => true,
+        }
+    }
+
+    /// This method commits an object that is about to be added to a table. If the allocator is
+    /// backed by a persistent segment, the object is copied into the segment, and the copy must
+    /// be added to the table instead. If the allocator has a write-ahead log, the object is
+    /// appended to the log, stamped with a fresh version so that the log is replayed in the order
+    /// objects were committed in (refer to `replay()`). Must therefore be called with the key's
+    /// bucket locked, from the closure handed to `Table::put_if()` or `Table::put_stamped()`, so
+    /// that objects are committed in the order they enter the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`:    A `Bytes` handle to the object's key.
+    /// * `object`: A `Bytes` handle to the entire object.
+    ///
+    /// # Return
+    /// A tupule of handles to the key and object that must be added to the table. None if the
+    /// object had to be logged, but the write-ahead log is full; the object must not be added
+    /// to the table then.
+    pub fn commit(&self, key: Bytes, object: Bytes) -> Option<(Bytes, Bytes)> {
+        if !self.durable.is_empty() && object.len() >= 12 {
+            let (tenant, table) = owner(&object[..]);
+            if !self.is_durable(tenant, table) {
+                return Some((key, object));
+            }
+        }
+
+        if let Some(ref wal) = self.wal {
+            let stamp = self.next_version.fetch_add(1, Ordering::Relaxed) as u64;
+            if !wal.append_stamped(&object[..], false, VERSION_OFFSET, stamp) {
+                return None;
+            }
+        }
+
+        let segment = match self.segment {
+            Some(ref segment) => segment,
+            None => return Some((key, object)),
+        };
+
+        match segment.append(&object[..], false) {
+            Some(copy) => {
+                let meta = self.meta_size();
+                let copy = Bytes::from_static(copy);
+                Some((copy.slice(meta, meta + key.len()), copy))
+            }
+
+            None => {
+                if !self.segment_full.swap(true, Ordering::Relaxed) {
+                    warn!("Persistent heap segment is full. Objects will not survive a restart.");
+                }
+                Some((key, object))
+            }
+        }
+    }
+
+    /// This method records the deletion of an object from a table in the persistent segment and
+    /// write-ahead log, so that the object is not resurrected on a restart. Does nothing if the
+    /// allocator is backed by neither. Just like `commit()`, must be called with the key's
+    /// bucket locked, from the closure handed to `Table::delete_with()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: An identifier for the tenant the object belonged to.
+    /// * `table`:  An identifier for the table the object was deleted from.
+    /// * `key`:    The key of the deleted object.
+    ///
+    /// # Return
+    /// False if the deletion had to be logged, but the write-ahead log is full; the object must
+    /// not be deleted from the table then.
+    pub fn commit_delete(&self, tenant: u32, table: u64, key: &[u8]) -> bool {
+        if !self.is_durable(tenant, table) {
+            return true;
+        }
+
+        if self.segment.is_none() && self.wal.is_none() {
+            return true;
+        }
+
+        if let Some(mut tombstone) = self.alloc(tenant, table, key.len() as u16, 0) {
+            tombstone.put_slice(key);
+
+            if let Some(ref wal) = self.wal {
+                let stamp = self.next_version.fetch_add(1, Ordering::Relaxed) as u64;
+                if !wal.append_stamped(&tombstone[..], true, VERSION_OFFSET, stamp) {
+                    return false;
+                }
+            }
+
+            if let Some(ref segment) = self.segment {
+                let _ = segment.append(&tombstone[..], true);
+            }
+        }
+
+        true
+    }
+
+    /// This method walks every object committed to the persistent segment, in the order they
+    /// were committed. Does nothing if the allocator is not backed by a persistent segment.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`: Closure invoked with the tenant, table, key, and object for every committed
+    ///        object. The object is None if the record marks the deletion of the key.
+    ///
+    /// # Return
+    /// An error if the segment was malformed.
+    pub fn recover<F>(&self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(u32, u64, Bytes, Option<Bytes>),
+    {
+        let segment = match self.segment {
+            Some(ref segment) => segment,
+            None => return Ok(()),
+        };
+
+        let meta = self.meta_size();
+        let mut malformed = false;
+
+        segment.recover(&mut |tombstone, record| {
+            if record.len() < meta {
+                malformed = true;
+                return;
+            }
+
+            let (tenant, table) = owner(record);
+            let key_len = (record[12] as usize) + (record[13] as usize) * 256;
+            if record.len() < meta + key_len {
+                malformed = true;
+                return;
+            }
+
+            self.observe(record);
+            let object = Bytes::from_static(record);
+            let key = object.slice(meta, meta + key_len);
+            f(tenant, table, key, if tombstone { None } else { Some(object) });
+        })?;
+
+        if malformed {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Malformed heap object."));
+        }
+
+        Ok(())
+    }
+
+    /// This method appends every object committed from here on, and every deletion, to a
+    /// write-ahead log. Objects of tables outside those set through `set_durable_tables()` are
+    /// not logged. Must be called before the allocator is shared, and after anything recovered
+    /// from the log has been replayed (refer to `replay()`), so that replayed objects are not
+    /// logged all over again.
+    ///
+    /// # Arguments
+    ///
+    /// * `wal`: The write-ahead log.
+    pub fn set_wal(&mut self, wal: Arc<Wal>) {
+        self.wal = Some(wal);
+    }
+
+    /// This method returns true if objects committed to a table are appended to the write-ahead
+    /// log. Refer to `set_wal()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: An identifier for the tenant the table belongs to.
+    /// * `table`:  An identifier for the table.
+    pub fn is_logged(&self, tenant: u32, table: u64) -> bool {
+        self.wal.is_some() && self.is_durable(tenant, table)
+    }
+
+    /// This method walks every record in a write-ahead log in the order they were committed in,
+    /// irrespective of the core that logged them; records are ordered by the version they were
+    /// stamped with when committed (refer to `commit()`). Versions handed out from here on are
+    /// larger than those on the records.
+    ///
+    /// # Arguments
+    ///
+    /// * `wal`: The write-ahead log, written with the same object layout as this allocator's.
+    /// * `f`:   Closure invoked with the tenant, table, key, and value of every record, along with
+    ///          the time the record expires at (refer to `expires()`). The value is None if the
+    ///          record marks the deletion of the key.
+    ///
+    /// # Return
+    /// An error if the log was malformed.
+    pub fn replay<F>(&self, wal: &Wal, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(u32, u64, &[u8], Option<&[u8]>, u64),
+    {
+        let meta = self.meta_size();
+        let mut records = wal.records()?;
+
+        let malformed = records.iter().any(|&(_, record)| {
+            record.len() < meta
+                || record.len() < meta + (record[12] as usize) + (record[13] as usize) * 256
+        });
+        if malformed {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Malformed log record."));
+        }
+
+        records.sort_by_key(|&(_, record)| {
+            let mut v: [u8; 8] = [0; 8];
+            v.copy_from_slice(&record[VERSION_OFFSET..VERSION_OFFSET + 8]);
+            u64::from_le(unsafe { transmute(v) })
+        });
+
+        for (tombstone, record) in records.into_iter() {
+            self.observe(record);
+
+            let (tenant, table) = owner(record);
+            let key_len = (record[12] as usize) + (record[13] as usize) * 256;
+            let (key, val) = record[meta..].split_at(key_len);
+            let expires = self.expires(record);
+            f(tenant, table, key, if tombstone { None } else { Some(val) }, expires);
+        }
+
+        Ok(())
+    }
+
+    /// This method returns the number of bytes in use in the persistent segment and it's size,
+    /// or None if the allocator is not backed by a persistent segment.
+    pub fn segment_stats(&self) -> Option<(usize, usize)> {
+        self.segment.as_ref().map(|segment| segment.stats())
     }
 
     /// This method allocates space for an object, and writes metadata and only
@@ -66,6 +666,11 @@ impl Allocator {
     pub fn raw(&self, tenant: u32, table: u64, key: &[u8], val_len: u64)
                -> Option<BytesMut>
     {
+        // Allocations that would take the tenant's group over it's memory quota are refused.
+        if !self.admit(tenant, self.meta_size() + key.len() + val_len as usize) {
+            return None;
+        }
+
         // Allocate space for the object.
         match self.alloc(tenant, table, key.len() as u16, val_len) {
             // The allocation was successfull.
@@ -106,6 +711,11 @@ impl Allocator {
     pub fn object(&self, tenant: u32, table: u64, key: &[u8], val: &[u8])
                   -> Option<(Bytes, Bytes)>
     {
+        // Allocations that would take the tenant's group over it's memory quota are refused.
+        if !self.admit(tenant, self.meta_size() + key.len() + val.len()) {
+            return None;
+        }
+
         // Allocate space for the object.
         match self.alloc(tenant, table, key.len() as u16, val.len() as u64) {
             // The allocation was successfull.
@@ -114,6 +724,7 @@ impl Allocator {
                 // read-only.
                 object.put_slice(key);
                 object.put_slice(val);
+                self.write_checksum(&mut object[..], val);
                 let object: Bytes = object.freeze();
 
                 // Return a view to the key and the object.
@@ -128,6 +739,31 @@ impl Allocator {
         }
     }
 
+    /// This method copies an object into a new allocation belonging to a different table, so
+    /// that the copy's metadata matches the table it is about to be moved into. The copy is
+    /// checksummed afresh if checksums are enabled, and keeps the object's expiration time if it
+    /// has one.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: An identifier for the tenant the object belongs to.
+    /// * `table`:  An identifier for the table the copy will be added to.
+    /// * `object`: A previously allocated object.
+    ///
+    /// # Return
+    /// A tupule of handles to the copy's key and the entire copy, just like `object()`.
+    pub fn relocate(&self, tenant: u32, table: u64, object: &Bytes) -> Option<(Bytes, Bytes)> {
+        let expires = self.expires(object);
+        self.resolve(object.clone())
+            .and_then(| (key, val) | { self.object(tenant, table, &key, &val) })
+            .map(| (key, copy) | {
+                if expires != 0 {
+                    self.set_expires(&copy, expires);
+                }
+                (key, copy)
+            })
+    }
+
     // This is an internal method the performs the actual allocation. The head
     // of each allocated piece of memory consists of metadata identifying the
     // tenant this object belongs to, the data table the object belongs to, and
@@ -154,11 +790,24 @@ impl Allocator {
         // Allocate space for the object.
         // XXX This could actually allocate more than size bytes.
         let mut object = BytesMut::with_capacity(size);
+        self.allocated.fetch_add(size, Ordering::Relaxed);
 
         // Write metadata into the object.
         object.put_u32_le(tenant);
         object.put_u64_le(table);
         object.put_u16_le(key_len);
+        object.put_u64_le(self.next_version.fetch_add(1, Ordering::Relaxed) as u64);
+
+        // Leave room for the checksum. It is filled in once the value has been written.
+        if self.crc.is_some() {
+            object.put_u32_le(0);
+        }
+
+        // Stamp the object with the time it expires at, if it's table has a time-to-live.
+        if self.expiry {
+            let ttl = self.ttls.get(&(tenant, table));
+            object.put_u64_le(ttl.map_or(0, |ttl| now_ms() + ttl));
+        }
 
         return Some(object);
     }
@@ -176,7 +825,7 @@ impl Allocator {
     pub fn resolve(&self, object: Bytes) -> Option<(Bytes, Bytes)> {
         // Read the two bytes corresponding to the key length from the object.
         let meta = self.meta_size();
-        let (left, right) = (object.get(meta - 2), object.get(meta - 1));
+        let (left, right) = (object.get(12), object.get(13));
 
         match (left, right) {
             // The above read was successfull. Compute the key length assuming
@@ -196,48 +845,823 @@ impl Allocator {
         }
     }
 
-    // This method returns the amount of metadata on each allocated object.
-    #[inline]
-    fn meta_size(&self) -> usize {
-        let meta = size_of::<u32>() +  // To store tenant id.
-                    size_of::<u64>() + // To store table id.
-                    size_of::<u16>();  // To store key length.
-        return meta;
-    }
-}
+    /// This method checksums the value of an object allocated through `raw()`, once the value
+    /// has been written. Does nothing if checksums are disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: The object, which must not be shared with any other handle.
+    ///
+    /// # Return
+    /// The checksummed object. If the object was shared, this is a checksummed copy.
+    pub fn seal(&self, object: Bytes) -> Bytes {
+        if self.crc.is_none() {
+            return object;
+        }
 
-// This module contains simple unit tests for Allocator.
-#[cfg(test)]
-mod tests {
-    use super::Allocator;
-    use bytes::{BufMut, BytesMut};
+        let mut object = match object.try_mut() {
+            Ok(object) => object,
+            Err(shared) => BytesMut::from(&shared[..]),
+        };
 
-    // This unit test verifies the return value of the "meta_size()" method
-    // on Allocator.
-    #[test]
-    fn test_meta_size() {
-        let heap = Allocator::new();
-        assert_eq!(14, heap.meta_size());
+        if let Some(start) = self.value_offset(&object[..]) {
+            let (meta, value) = object.split_at_mut(start);
+            self.write_checksum(meta, value);
+        }
+
+        object.freeze()
     }
 
-    // This unit test tests the functionality of the "resolve()" method on
-    // Allocator.
-    #[test]
-    fn test_resolve() {
-        let heap = Allocator::new();
+    /// This method verifies the value of an object against it's checksum. Only one in every
+    /// `verify_every` calls actually verifies the value; the rest succeed right away. Values that
+    /// do not match their checksum are logged and counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: A previously allocated object that was just read out of a table.
+    ///
+    /// # Return
+    /// False if the value was verified and did not match it's checksum. The value must not be
+    /// served in this case.
+    pub fn checksum_ok(&self, object: &Bytes) -> bool {
+        if self.crc.is_none() {
+            return true;
+        }
 
-        let key: &[u8] = &[12, 24, 24, 12];
-        let val: &[u8] = &[48, 96, 96, 48];
+        let read = self.reads.fetch_add(1, Ordering::Relaxed);
+        if read % self.verify_every != 0 {
+            return true;
+        }
 
-        // Allocate an object, and resolve the allocation into it's key
-        // and value.
-        let (_, obj) = heap.object(0, 0, key, val)
-                            .expect("Failed to allocate object.");
-        let (k, v) = heap.resolve(obj)
-                            .expect("Failed to resolve object.");
+        self.verified.fetch_add(1, Ordering::Relaxed);
+        if self.checksum_matches(&object[..]) {
+            return true;
+        }
 
-        // Check the contents of the resolved key and value against their
-        // expected values.
+        let count = self.corrupt.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!("Object at {:p} does not match it's checksum ({} so far)", object.as_ptr(), count);
+        false
+    }
+
+    /// This method marks the value of an object as no longer checksummed. Must be called before
+    /// the value is modified in place.
+    ///
+    /// XXX: A read that races with this call and verifies a partially cleared checksum will
+    /// report the object as corrupted.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: A previously allocated object inside a table.
+    pub fn invalidate_checksum(&self, object: &Bytes) {
+        if self.crc.is_none() || object.len() < self.meta_size() {
+            return;
+        }
+
+        unsafe {
+            let dst = (object.as_ptr() as *mut u8).offset(CRC_OFFSET as isize);
+            ptr::write_bytes(dst, 0, size_of::<u32>());
+        }
+    }
+
+    /// This method returns the version of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: A previously allocated object.
+    ///
+    /// # Return
+    /// The version the object was stamped with when allocated or last updated in place. Zero if
+    /// the object is too short to carry one.
+    pub fn version(&self, object: &Bytes) -> u64 {
+        if object.len() < self.meta_size() {
+            return 0;
+        }
+
+        let mut v: [u8; 8] = [0; 8];
+        v.copy_from_slice(&object[VERSION_OFFSET..VERSION_OFFSET + 8]);
+        u64::from_le(unsafe { transmute(v) })
+    }
+
+    /// This method stamps an object with a new version. Must be called before the value is
+    /// modified in place, so that the modification cannot go unnoticed by a conditional write.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: A previously allocated object inside a table.
+    pub fn bump_version(&self, object: &Bytes) {
+        if object.len() < self.meta_size() {
+            return;
+        }
+
+        let version = self.next_version.fetch_add(1, Ordering::Relaxed) as u64;
+        let v: [u8; 8] = unsafe { transmute(version.to_le()) };
+        unsafe {
+            let dst = (object.as_ptr() as *mut u8).offset(VERSION_OFFSET as isize);
+            ptr::copy_nonoverlapping(v.as_ptr(), dst, v.len());
+        }
+    }
+
+    /// This method returns the time at which an object expires.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: A previously allocated object.
+    ///
+    /// # Return
+    /// The wall-clock time in milliseconds since the unix epoch after which the object is
+    /// considered missing. Zero if the object never expires, or expiration is not enabled.
+    pub fn expires(&self, object: &[u8]) -> u64 {
+        if !self.expiry || object.len() < self.meta_size() {
+            return 0;
+        }
+
+        let offset = self.expiry_offset();
+        let mut e: [u8; 8] = [0; 8];
+        e.copy_from_slice(&object[offset..offset + 8]);
+        u64::from_le(unsafe { transmute(e) })
+    }
+
+    /// This method overrides the time at which an object expires. Must be called before the
+    /// object is committed or added to a table. Does nothing if expiration is not enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`:  A previously allocated object.
+    /// * `expires`: The wall-clock time in milliseconds since the unix epoch after which the
+    ///              object is considered missing. Zero if it should never expire.
+    pub fn set_expires(&self, object: &Bytes, expires: u64) {
+        if !self.expiry || object.len() < self.meta_size() {
+            return;
+        }
+
+        let e: [u8; 8] = unsafe { transmute(expires.to_le()) };
+        unsafe {
+            let dst = (object.as_ptr() as *mut u8).offset(self.expiry_offset() as isize);
+            ptr::copy_nonoverlapping(e.as_ptr(), dst, e.len());
+        }
+    }
+
+    /// This method determines whether an object has expired. Expired objects must be treated as
+    /// if they did not exist, until they are removed from their table.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: A previously allocated object.
+    ///
+    /// # Return
+    /// True if the object has an expiration time, and it has passed.
+    pub fn is_expired(&self, object: &[u8]) -> bool {
+        let expires = self.expires(object);
+        expires != 0 && expires <= now_ms()
+    }
+
+    /// This method makes sure that versions handed out from here on are larger than the version
+    /// on an object written by an earlier run of the server (ex: recovered from a snapshot).
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: An object laid out by the allocator.
+    pub fn observe(&self, object: &[u8]) {
+        if object.len() < self.meta_size() {
+            return;
+        }
+
+        let mut v: [u8; 8] = [0; 8];
+        v.copy_from_slice(&object[VERSION_OFFSET..VERSION_OFFSET + 8]);
+        let next = u64::from_le(unsafe { transmute(v) }) as usize + 1;
+
+        let mut curr = self.next_version.load(Ordering::Relaxed);
+        while curr < next {
+            match self.next_version.compare_exchange_weak(
+                curr,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(c) => curr = c,
+            }
+        }
+    }
+
+    /// This method returns the number of reads whose values were verified against their
+    /// checksums, and the number of those that did not match.
+    pub fn checksum_stats(&self) -> (usize, usize) {
+        (
+            self.verified.load(Ordering::Relaxed),
+            self.corrupt.load(Ordering::Relaxed),
+        )
+    }
+
+    /// This method copies a previously allocated object into a hot arena, packing it next to
+    /// other frequently accessed objects.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: A previously allocated object.
+    ///
+    /// # Return
+    /// A `Bytes` handle to the copy of the object inside the hot arena. The caller is responsible
+    /// for replacing the original object with this copy in the table. If the object is too large
+    /// to be promoted, this method returns None.
+    pub fn promote(&self, object: &Bytes) -> Option<Bytes> {
+        let len = object.len();
+        if len > HOT_OBJECT_MAX || len < self.meta_size() {
+            return None;
+        }
+
+        // Objects in the persistent segment stay there. In-place updates to a copy outside the
+        // segment would not survive a restart.
+        if self.is_persistent(object) {
+            return None;
+        }
+
+        let mut hot = self.hot.lock();
+
+        // Allocate a new arena if the current one cannot hold the object.
+        if hot.current.remaining_mut() < len {
+            let (arena, current) = Arena::new();
+            hot.current = current;
+            hot.arenas.push(arena);
+        }
+
+        // Copy the object into the arena, and split it off. The returned handle shares the
+        // arena's underlying buffer.
+        hot.current.put_slice(&object[..]);
+        let copy = hot.current.split_to(len).freeze();
+        if let Some(arena) = hot.arenas.last_mut() {
+            arena.bytes += len;
+        }
+
+        self.promoted.fetch_add(1, Ordering::Relaxed);
+        self.hot_bytes.fetch_add(len, Ordering::Relaxed);
+
+        return Some(copy);
+    }
+
+    /// This method determines whether an object lives inside a hot arena.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: A previously allocated object.
+    ///
+    /// # Return
+    /// True if the object was previously promoted into a hot arena.
+    pub fn is_hot(&self, object: &Bytes) -> bool {
+        let addr = object.as_ptr() as usize;
+        let hot = self.hot.lock();
+
+        hot.arenas
+            .iter()
+            .any(|arena| addr >= arena.start && addr < arena.end)
+    }
+
+    /// This method frees every hot arena whose objects have all been dropped, so that it's
+    /// addresses are no longer taken to be hot once they are reused by the heap. Must be called
+    /// before `is_hot()` is relied upon after objects may have been freed (ex: at the start of
+    /// every migration pass).
+    ///
+    /// # Return
+    /// The number of arenas that were freed.
+    pub fn reclaim_hot(&self) -> usize {
+        let mut hot = self.hot.lock();
+        let mut freed = 0;
+
+        // The arena currently being packed is never freed, since `current` holds on to it.
+        let arenas = replace(&mut hot.arenas, Vec::new());
+        for arena in arenas.into_iter() {
+            let Arena { start, end, witness, bytes } = arena;
+            match witness.try_mut() {
+                Ok(_) => {
+                    self.hot_bytes.fetch_sub(bytes, Ordering::Relaxed);
+                    freed += 1;
+                }
+
+                Err(witness) => hot.arenas.push(Arena {
+                    start: start,
+                    end: end,
+                    witness: witness,
+                    bytes: bytes,
+                }),
+            }
+        }
+
+        freed
+    }
+
+    /// This method determines whether an object lives inside the persistent segment.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: A previously allocated object.
+    ///
+    /// # Return
+    /// True if the object was committed into the persistent segment.
+    pub fn is_persistent(&self, object: &Bytes) -> bool {
+        self.segment
+            .as_ref()
+            .map_or(false, |segment| segment.contains(object.as_ptr() as usize))
+    }
+
+    /// This method verifies that the metadata on an object inside a table is consistent with
+    /// where it was found. Corrupted metadata typically indicates that an extension wrote past
+    /// the end of an object it was handed.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant owning the table the object was found in.
+    /// * `table`:  The table the object was found in.
+    /// * `key`:    The key the object was found under.
+    /// * `object`: The object.
+    ///
+    /// # Return
+    /// A description of the first inconsistency found on the object, if any.
+    pub fn verify(&self, tenant: u32, table: u64, key: &[u8], object: &Bytes)
+                  -> Result<(), &'static str>
+    {
+        let meta = self.meta_size();
+        if object.len() < meta {
+            return Err("object shorter than it's metadata");
+        }
+
+        let mut t: [u8; 4] = [0; 4];
+        let mut id: [u8; 8] = [0; 8];
+        t.copy_from_slice(&object[0..4]);
+        id.copy_from_slice(&object[4..12]);
+        if u32::from_le(unsafe { transmute(t) }) != tenant {
+            return Err("tenant id does not match the owning tenant");
+        }
+        if u64::from_le(unsafe { transmute(id) }) != table {
+            return Err("table id does not match the owning table");
+        }
+
+        let key_len = (object[12] as usize) + (object[13] as usize) * 256;
+        if key_len != key.len() {
+            return Err("key length does not match the table's key");
+        }
+        if object.len() < meta + key_len {
+            return Err("object shorter than it's key");
+        }
+        if &object[meta..meta + key_len] != key {
+            return Err("key does not match the table's key");
+        }
+        if !self.checksum_matches(&object[..]) {
+            return Err("value does not match it's checksum");
+        }
+
+        Ok(())
+    }
+
+    /// This method verifies the allocator's own invariants. Every hot arena must be of the
+    /// same size and disjoint from every other arena, the arena currently being packed must lie
+    /// inside the most recently allocated one, promoted objects must fit inside the arenas, and
+    /// the persistent segment must not be in use beyond it's size.
+    ///
+    /// # Return
+    /// A description of every violated invariant.
+    pub fn check(&self) -> Vec<&'static str> {
+        let mut violations = Vec::new();
+
+        {
+            let hot = self.hot.lock();
+
+            if hot.arenas.iter().any(|arena| arena.end - arena.start != HOT_ARENA_SIZE) {
+                violations.push("hot arena of unexpected size");
+            }
+
+            for (i, a1) in hot.arenas.iter().enumerate() {
+                if hot.arenas[i + 1..].iter().any(|a2| a1.start < a2.end && a2.start < a1.end) {
+                    violations.push("overlapping hot arenas");
+                    break;
+                }
+            }
+
+            let start = hot.current.as_ptr() as usize;
+            let end = start + hot.current.capacity();
+            match hot.arenas.last() {
+                Some(arena) if start >= arena.start && end <= arena.end => {}
+                _ => violations.push("current hot arena outside allocated ranges"),
+            }
+
+            if self.hot_bytes.load(Ordering::Relaxed) > hot.arenas.len() * HOT_ARENA_SIZE {
+                violations.push("promoted bytes exceed hot arena capacity");
+            }
+        }
+
+        if let Some((used, size)) = self.segment_stats() {
+            if used > size {
+                violations.push("persistent segment in use beyond it's size");
+            }
+        }
+
+        violations
+    }
+
+    /// This method returns the number of objects promoted into hot arenas so far, and the
+    /// number of bytes promoted into arenas that have not been freed yet.
+    pub fn hot_stats(&self) -> (usize, usize) {
+        (
+            self.promoted.load(Ordering::Relaxed),
+            self.hot_bytes.load(Ordering::Relaxed),
+        )
+    }
+
+    /// This method returns the total number of bytes allocated for objects so far. Bytes are
+    /// not given back when objects are freed, so this is an upper bound on the bytes in use.
+    pub fn allocated(&self) -> usize {
+        self.allocated.load(Ordering::Relaxed)
+    }
+
+    /// This method returns the amount of metadata on each allocated object.
+    #[inline]
+    pub fn meta_size(&self) -> usize {
+        let meta = size_of::<u32>() +  // To store tenant id.
+                    size_of::<u64>() + // To store table id.
+                    size_of::<u16>() + // To store key length.
+                    size_of::<u64>();  // To store version.
+
+        // To store the value's checksum.
+        let meta = if self.crc.is_some() {
+            meta + size_of::<u32>()
+        } else {
+            meta
+        };
+
+        // To store the expiration time.
+        if self.expiry {
+            return meta + size_of::<u64>();
+        }
+
+        return meta;
+    }
+
+    /// This method describes the layout of allocated objects to tables, so that they can keep
+    /// small values inline (refer to `Table::with_inline()`). Like `meta_size()`, the layout
+    /// changes when checksums or expiration times are enabled.
+    pub fn layout(&self) -> Layout {
+        Layout {
+            key_len: 12,
+            meta: self.meta_size(),
+            version: VERSION_OFFSET,
+            expiry: if self.expiry { Some(self.expiry_offset()) } else { None },
+        }
+    }
+
+    // Returns the offset of the expiration time on an object's metadata.
+    fn expiry_offset(&self) -> usize {
+        if self.crc.is_some() {
+            return CRC_OFFSET + size_of::<u32>();
+        }
+
+        EXPIRY_OFFSET
+    }
+
+    // Returns the offset of the value on an object, or None if the object is too short to have
+    // a value.
+    fn value_offset(&self, object: &[u8]) -> Option<usize> {
+        let meta = self.meta_size();
+        if object.len() < meta {
+            return None;
+        }
+
+        let start = meta + (object[12] as usize) + (object[13] as usize) * 256;
+        if object.len() < start {
+            return None;
+        }
+
+        Some(start)
+    }
+
+    // Writes the checksum of a value into an object's metadata. Does nothing if checksums are
+    // disabled.
+    fn write_checksum(&self, object: &mut [u8], value: &[u8]) {
+        if let Some(ref crc) = self.crc {
+            let sum = crc.checksum(value).max(1);
+            let sum: [u8; 4] = unsafe { transmute(sum.to_le()) };
+            object[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&sum);
+        }
+    }
+
+    // Returns true if an object's value matches it's checksum, or if there is no checksum to
+    // match against.
+    fn checksum_matches(&self, object: &[u8]) -> bool {
+        let crc = match self.crc {
+            Some(ref crc) => crc,
+            None => return true,
+        };
+
+        let start = match self.value_offset(object) {
+            Some(start) => start,
+            None => return false,
+        };
+
+        let mut c: [u8; 4] = [0; 4];
+        c.copy_from_slice(&object[CRC_OFFSET..CRC_OFFSET + 4]);
+        let stored = u32::from_le(unsafe { transmute(c) });
+
+        stored == 0 || stored == crc.checksum(&object[start..]).max(1)
+    }
+}
+
+/// Returns the wall-clock time in milliseconds since the unix epoch. Expiration times on objects
+/// are kept on this clock, so that they hold across restarts.
+pub fn now_ms() -> u64 {
+    let now = time::get_time();
+    (now.sec as u64) * 1000 + (now.nsec as u64) / 1_000_000
+}
+
+// Reads the tenant and table identifiers off an object's metadata. The object must be atleast
+// twelve bytes long.
+fn owner(object: &[u8]) -> (u32, u64) {
+    let mut t: [u8; 4] = [0; 4];
+    let mut id: [u8; 8] = [0; 8];
+    t.copy_from_slice(&object[0..4]);
+    id.copy_from_slice(&object[4..12]);
+    (
+        u32::from_le(unsafe { transmute(t) }),
+        u64::from_le(unsafe { transmute(id) }),
+    )
+}
+
+// This module contains simple unit tests for Allocator.
+#[cfg(test)]
+mod tests {
+    use super::{now_ms, Allocator};
+    use table::{Found, Table};
+    use wal::Wal;
+    use bytes::{BufMut, BytesMut};
+    use std::fs::{remove_dir_all, remove_file};
+    use std::sync::Arc;
+
+    // This unit test verifies that promoted objects are packed densely into a hot arena, and that
+    // they retain their contents.
+    #[test]
+    fn test_promote() {
+        let heap = Allocator::new();
+
+        let (_, a) = heap.object(0, 1, &[1; 4], &[2; 10]).expect("Failed to allocate object.");
+        let (_, b) = heap.object(0, 1, &[3; 4], &[4; 10]).expect("Failed to allocate object.");
+        assert!(!heap.is_hot(&a));
+
+        let ha = heap.promote(&a).expect("Failed to promote object.");
+        let hb = heap.promote(&b).expect("Failed to promote object.");
+
+        // The promoted objects must be identical to the originals, and adjacent in memory.
+        assert_eq!(a[..], ha[..]);
+        assert_eq!(b[..], hb[..]);
+        assert_eq!(ha.as_ptr() as usize + ha.len(), hb.as_ptr() as usize);
+        assert!(heap.is_hot(&ha) && heap.is_hot(&hb));
+        assert_eq!((2, a.len() + b.len()), heap.hot_stats());
+    }
+
+    // This unit test verifies that a hot arena is freed only once every object promoted into it
+    // has been dropped, and never while objects are still being packed into it.
+    #[test]
+    fn test_reclaim_hot() {
+        let heap = Allocator::new();
+
+        let (_, a) = heap.object(0, 1, &[1; 4], &[2; 10]).expect("Failed to allocate object.");
+        let ha = heap.promote(&a).expect("Failed to promote object.");
+
+        // Fill up the first arena with objects that are dropped right away.
+        let (_, big) = heap.object(0, 1, &[3; 4], &[4; 4000]).expect("Failed to allocate.");
+        for _ in 0..600 {
+            heap.promote(&big).expect("Failed to promote object.");
+        }
+        assert!(heap.check().is_empty());
+
+        // The first arena still holds `ha`, and the second one is still being packed.
+        assert_eq!(0, heap.reclaim_hot());
+        assert!(heap.is_hot(&ha));
+
+        let (_, bytes) = heap.hot_stats();
+        drop(ha);
+        assert_eq!(1, heap.reclaim_hot());
+        assert!(heap.hot_stats().1 < bytes);
+        assert!(heap.check().is_empty());
+    }
+
+    // This unit test verifies that verify() accepts an object found where it was allocated
+    // for, and flags objects whose metadata disagrees with the table they were found in.
+    #[test]
+    fn test_verify() {
+        let heap = Allocator::new();
+
+        let (key, obj) = heap.object(7, 1, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        assert_eq!(Ok(()), heap.verify(7, 1, &key, &obj));
+        assert!(heap.verify(8, 1, &key, &obj).is_err());
+        assert!(heap.verify(7, 2, &key, &obj).is_err());
+        assert!(heap.verify(7, 1, &[1; 3], &obj).is_err());
+        assert!(heap.verify(7, 1, &[9; 4], &obj).is_err());
+        assert!(heap.verify(7, 1, &key, &obj.slice(0, 10)).is_err());
+
+        heap.promote(&obj).expect("Failed to promote object.");
+        assert!(heap.check().is_empty());
+    }
+
+    // This unit test verifies that a relocated object carries the metadata of the table it was
+    // relocated to, along with the original key and value.
+    #[test]
+    fn test_relocate() {
+        let mut heap = Allocator::new();
+        heap.set_checksums(1);
+
+        let (_, obj) = heap.object(7, 1, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        let (key, copy) = heap.relocate(7, 2, &obj).expect("Failed to relocate.");
+
+        assert_eq!(&[1; 4], &key[..]);
+        assert_eq!(Ok(()), heap.verify(7, 2, &key, &copy));
+        assert!(heap.verify(7, 1, &key, &copy).is_err());
+        assert!(heap.checksum_ok(&copy));
+
+        let (_, val) = heap.resolve(copy).expect("Failed to resolve object.");
+        assert_eq!(&[2; 10], &val[..]);
+    }
+
+    // This unit test verifies that checksummed objects resolve as usual, and that corrupted
+    // values are caught by verification while values updated in place are not.
+    #[test]
+    fn test_checksums() {
+        let mut heap = Allocator::new();
+        heap.set_checksums(1);
+        assert_eq!(26, heap.meta_size());
+
+        let (key, obj) = heap.object(7, 1, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        let (k, v) = heap.resolve(obj.clone()).expect("Failed to resolve object.");
+        assert_eq!(&[1; 4], &k[..]);
+        assert_eq!(&[2; 10], &v[..]);
+        assert!(heap.checksum_ok(&obj));
+        assert_eq!(Ok(()), heap.verify(7, 1, &key, &obj));
+
+        // Flip a bit in the value.
+        let mut bad = BytesMut::from(&obj[..]);
+        let last = bad.len() - 1;
+        bad[last] ^= 0x01;
+        let bad = bad.freeze();
+        assert!(!heap.checksum_ok(&bad));
+        assert!(heap.verify(7, 1, &key, &bad).is_err());
+        assert_eq!((2, 1), heap.checksum_stats());
+
+        // Values written after a raw() allocation are checksummed once sealed.
+        let mut raw = heap.raw(7, 1, &[3; 4], 4).expect("Failed to allocate.");
+        raw.put_slice(&[4; 4]);
+        let raw = heap.seal(raw.freeze());
+        assert!(heap.checksum_ok(&raw));
+
+        heap.invalidate_checksum(&bad);
+        assert!(heap.checksum_ok(&bad));
+    }
+
+    // This unit test verifies that objects of a table with a time-to-live are stamped with an
+    // expiration time, which can be overridden, and that they expire once it has passed.
+    #[test]
+    fn test_expiry() {
+        let mut heap = Allocator::new();
+        heap.set_checksums(1);
+        heap.set_ttl(7, 1, 60 * 1000);
+        assert_eq!(34, heap.meta_size());
+
+        let start = now_ms();
+        let (key, obj) = heap.object(7, 1, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        let (_, other) = heap.object(7, 2, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        let expires = heap.expires(&obj);
+        assert!(expires >= start + 60 * 1000 && expires <= now_ms() + 60 * 1000);
+        assert_eq!(0, heap.expires(&other));
+        assert!(!heap.is_expired(&obj) && !heap.is_expired(&other));
+
+        // Expiring an object leaves it's key, value, and checksum alone.
+        heap.set_expires(&obj, start - 1);
+        assert!(heap.is_expired(&obj));
+        assert!(heap.checksum_ok(&obj));
+        assert_eq!(Ok(()), heap.verify(7, 1, &key, &obj));
+        let (k, v) = heap.resolve(obj).expect("Failed to resolve object.");
+        assert_eq!(&[1; 4], &k[..]);
+        assert_eq!(&[2; 10], &v[..]);
+    }
+
+    // This unit test verifies that a sampled allocator only verifies one in every few reads.
+    #[test]
+    fn test_checksums_sampled() {
+        let mut heap = Allocator::new();
+        heap.set_checksums(4);
+
+        let (_, obj) = heap.object(0, 1, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        for _ in 0..8 {
+            assert!(heap.checksum_ok(&obj));
+        }
+        assert_eq!((2, 0), heap.checksum_stats());
+    }
+
+    // This unit test verifies that every allocation is stamped with a newer version than the
+    // ones before it, that in-place updates bump the version, and that versions handed out after
+    // an object is observed are newer than the object's.
+    #[test]
+    fn test_versions() {
+        let heap = Allocator::new();
+
+        let (_, a) = heap.object(7, 1, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        let (_, b) = heap.object(7, 1, &[1; 4], &[3; 10]).expect("Failed to allocate.");
+        assert_eq!(1, heap.version(&a));
+        assert_eq!(2, heap.version(&b));
+        assert_eq!(0, heap.version(&a.slice(0, 10)));
+
+        heap.bump_version(&a);
+        assert_eq!(3, heap.version(&a));
+
+        // Promoted copies are the same object, and keep the version.
+        let hot = heap.promote(&a).expect("Failed to promote object.");
+        assert_eq!(3, heap.version(&hot));
+
+        // Objects written by an earlier run push the next version past theirs.
+        let other = Allocator::new();
+        other.observe(&a[..]);
+        let (_, c) = other.object(7, 1, &[1; 4], &[4; 10]).expect("Failed to allocate.");
+        assert_eq!(4, other.version(&c));
+    }
+
+    // This unit test verifies that allocations for the tenants of a group are refused once the
+    // objects charged to the group would take it over it's memory quota, that allocations alone
+    // are not charged, that other tenants are not limited, and that credits let allocations
+    // through again.
+    #[test]
+    fn test_group_quota() {
+        let mut heap = Allocator::new();
+        let quota = heap.set_group_quota(&[7, 8], 100);
+        let size = heap.meta_size() + 4 + 10;
+
+        assert!(heap.object(7, 1, &[1; 4], &[2; 10]).is_some());
+        assert!(heap.raw(8, 1, &[1; 4], 10).is_some());
+        assert_eq!(vec![(0, 100, 0)], heap.group_stats());
+
+        quota.charge(2 * size);
+        assert!(!heap.over_quota(7));
+        assert!(heap.object(8, 1, &[1; 4], &[2; 10]).is_none());
+        assert!(heap.object(9, 1, &[1; 4], &[2; 10]).is_some());
+        assert_eq!(vec![(2 * size, 100, 1)], heap.group_stats());
+
+        quota.charge(100 - 2 * size);
+        assert!(heap.over_quota(8));
+        assert!(!heap.over_quota(9));
+
+        quota.credit(100);
+        assert!(heap.object(8, 1, &[1; 4], &[2; 10]).is_some());
+
+        // A tenant stays in the first group it was added to.
+        let other = heap.set_group_quota(&[8, 9], 10);
+        assert!(Arc::ptr_eq(&quota, &heap.group_quota(8).unwrap()));
+        assert!(Arc::ptr_eq(&other, &heap.group_quota(9).unwrap()));
+        assert!(heap.group_quota(10).is_none());
+    }
+
+    // This unit test verifies the return value of the "meta_size()" method
+    // on Allocator.
+    #[test]
+    fn test_meta_size() {
+        let heap = Allocator::new();
+        assert_eq!(22, heap.meta_size());
+    }
+
+    // This unit test verifies that a table laid out like the allocator's objects keeps the
+    // value, version and expiration time of small objects inline.
+    #[test]
+    fn test_layout() {
+        let mut heap = Allocator::new();
+        heap.set_checksums(1);
+        heap.set_ttl(7, 1, 60 * 1000);
+        let table = Table::with_inline(16, heap.layout());
+
+        let (key, obj) = heap.object(7, 1, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        heap.set_expires(&obj, 1234);
+        table.put(key, obj.clone());
+
+        match table.lookup(&[1; 4]) {
+            Some(Found::Inline(inline)) => {
+                assert_eq!(&[2; 10], inline.value());
+                assert_eq!(heap.version(&obj), inline.version);
+                assert_eq!(1234, inline.expires);
+            }
+            _ => panic!("Small value was not kept inline."),
+        }
+    }
+
+    // This unit test tests the functionality of the "resolve()" method on
+    // Allocator.
+    #[test]
+    fn test_resolve() {
+        let heap = Allocator::new();
+
+        let key: &[u8] = &[12, 24, 24, 12];
+        let val: &[u8] = &[48, 96, 96, 48];
+
+        // Allocate an object, and resolve the allocation into it's key
+        // and value.
+        let (_, obj) = heap.object(0, 0, key, val)
+                            .expect("Failed to allocate object.");
+        let (k, v) = heap.resolve(obj)
+                            .expect("Failed to resolve object.");
+
+        // Check the contents of the resolved key and value against their
+        // expected values.
         assert_eq!(key[..], k[..]);
         assert_eq!(val[..], v[..]);
     }
@@ -255,8 +1679,9 @@ mod tests {
         let val_len: u64 = 100;
 
         // The expected result of the allocation.
-        let mut expected = BytesMut::with_capacity(144);
+        let mut expected = BytesMut::with_capacity(152);
         expected.put_slice(&[0, 0, 0, 0, 11, 0, 0, 0, 0, 0, 0, 0, 30, 0]);
+        expected.put_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
 
         // Request for an allocation.
         match heap.alloc(tenant, table, key_len, val_len) {
@@ -289,8 +1714,9 @@ mod tests {
         let val_len: u64 = 100;
 
         // The expected result.
-        let mut expected = BytesMut::with_capacity(118);
+        let mut expected = BytesMut::with_capacity(126);
         expected.put_slice(&[0, 0, 0, 0, 11, 0, 0, 0, 0, 0, 0, 0, 4, 0]);
+        expected.put_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
         expected.put_slice(&key);
 
         // Perform the request.
@@ -323,8 +1749,9 @@ mod tests {
         let val: [u8; 100] = [100; 100];
 
         // The expected result.
-        let mut expected = BytesMut::with_capacity(118);
+        let mut expected = BytesMut::with_capacity(126);
         expected.put_slice(&[0, 0, 0, 0, 11, 0, 0, 0, 0, 0, 0, 0, 4, 0]);
+        expected.put_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
         expected.put_slice(&key);
         expected.put_slice(&val);
 
@@ -343,4 +1770,119 @@ mod tests {
             }
         }
     }
+
+    // This unit test verifies that objects committed to a persistent allocator are recovered
+    // by a new allocator attached to the same segment, along with deletions.
+    #[test]
+    fn test_persistent_recover() {
+        let path = "/tmp/sandstorm_alloc_recover.test";
+        let _ = remove_file(path);
+
+        {
+            let heap = Allocator::persistent(path, 4096).expect("Failed to create heap.");
+            let (key, obj) = heap.object(7, 1, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+            let (key, obj) = heap.commit(key, obj).expect("Failed to commit.");
+            assert!(heap.is_persistent(&obj));
+            assert!(heap.promote(&obj).is_none());
+            assert_eq!([1; 4], key[..]);
+
+            heap.commit_delete(7, 1, &[3; 4]);
+        }
+
+        let heap = Allocator::persistent(path, 4096).expect("Failed to re-attach heap.");
+        let mut recovered = Vec::new();
+        heap.recover(|tenant, table, key, obj| {
+            recovered.push((tenant, table, key.to_vec(), obj.map(|o| o.len())))
+        }).expect("Failed to recover heap.");
+
+        assert_eq!(
+            vec![(7, 1, vec![1; 4], Some(36)), (7, 1, vec![3; 4], None)],
+            recovered
+        );
+        let _ = remove_file(path);
+    }
+
+    // This unit test verifies that a write-ahead log is replayed in the order objects and
+    // deletions were committed in, even if the objects were allocated in a different order.
+    #[test]
+    fn test_replay_order() {
+        let dir = "/tmp/sandstorm_alloc_replay.test";
+        let _ = remove_dir_all(dir);
+
+        {
+            let mut heap = Allocator::new();
+            heap.set_wal(Arc::new(Wal::open(dir, 1, 4096, 0).expect("Failed to create log.")));
+
+            let (k1, o1) = heap.object(7, 1, &[1; 4], &[1; 4]).expect("Failed to allocate.");
+            let (k2, o2) = heap.object(7, 1, &[1; 4], &[2; 4]).expect("Failed to allocate.");
+            heap.commit(k2, o2).expect("Failed to commit.");
+            heap.commit(k1, o1).expect("Failed to commit.");
+            assert!(heap.commit_delete(7, 1, &[3; 4]));
+        }
+
+        let wal = Wal::open(dir, 1, 4096, 0).expect("Failed to re-open log.");
+        let heap = Allocator::new();
+        let mut replayed = Vec::new();
+        heap.replay(&wal, |_, _, key, val, _| {
+            replayed.push((key.to_vec(), val.map(|v| v.to_vec())))
+        }).expect("Failed to replay log.");
+
+        assert_eq!(
+            vec![
+                (vec![1; 4], Some(vec![2; 4])),
+                (vec![1; 4], Some(vec![1; 4])),
+                (vec![3; 4], None),
+            ],
+            replayed
+        );
+        let _ = remove_dir_all(dir);
+    }
+
+    // This unit test verifies that tables are only reported as logged once the heap has a
+    // write-ahead log, and only if they are durable.
+    #[test]
+    fn test_logged_tables() {
+        let dir = "/tmp/sandstorm_alloc_logged.test";
+        let _ = remove_dir_all(dir);
+
+        let mut heap = Allocator::new();
+        assert!(!heap.is_logged(7, 1));
+
+        heap.set_wal(Arc::new(Wal::open(dir, 1, 4096, 0).expect("Failed to create log.")));
+        assert!(heap.is_logged(7, 1));
+        assert!(heap.is_logged(7, 2));
+
+        heap.set_durable_tables(vec![(7, 1)]);
+        assert!(heap.is_logged(7, 1));
+        assert!(!heap.is_logged(7, 2));
+        let _ = remove_dir_all(dir);
+    }
+
+    // This unit test verifies that only the objects and deletions of durable tables are copied
+    // into the persistent segment once the segment is restricted to them.
+    #[test]
+    fn test_durable_tables() {
+        let path = "/tmp/sandstorm_alloc_durable.test";
+        let _ = remove_file(path);
+
+        let mut heap = Allocator::persistent(path, 4096).expect("Failed to create heap.");
+        heap.set_durable_tables(vec![(7, 1)]);
+
+        let (key, obj) = heap.object(7, 1, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        let (_, obj) = heap.commit(key, obj).expect("Failed to commit.");
+        assert!(heap.is_persistent(&obj));
+
+        let (key, obj) = heap.object(7, 2, &[1; 4], &[2; 10]).expect("Failed to allocate.");
+        let (_, obj) = heap.commit(key, obj).expect("Failed to commit.");
+        assert!(!heap.is_persistent(&obj));
+
+        heap.commit_delete(7, 2, &[1; 4]);
+        heap.commit_delete(7, 1, &[1; 4]);
+
+        let mut recovered = Vec::new();
+        heap.recover(|tenant, table, _, obj| recovered.push((tenant, table, obj.is_some())))
+            .expect("Failed to recover heap.");
+        assert_eq!(vec![(7, 1, true), (7, 1, false)], recovered);
+        let _ = remove_file(path);
+    }
 }
@@ -0,0 +1,269 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+/// The maximum number of associations held by a single segment of an association list. Inserts
+/// and deletes shift at most this many associations.
+pub const SEGMENT_LEN: usize = 64;
+
+/// A single association from an object to `id2`, as in TAO.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assoc {
+    /// The object the association points to.
+    pub id2: u64,
+
+    /// The time of the association. Lists are ordered on it, newest first.
+    pub time: u64,
+
+    /// Data attached to the association.
+    pub data: Bytes,
+}
+
+/// The associations of a given type out of a single object (a TAO association list), ordered by
+/// time, newest first. The list is split into segments of at most `SEGMENT_LEN` associations each
+/// so that associations can be inserted and deleted in place, and range queries find their
+/// starting point with a binary search over segments rather than a scan over the list.
+pub struct AssocList {
+    // Segments of the list. Associations are ordered newest first within and across segments.
+    // No segment is ever empty.
+    segments: Vec<Vec<Assoc>>,
+
+    // The time of every association on the list, indexed by the object it points to. Allows
+    // deletes to go straight to the association's segment.
+    index: HashMap<u64, u64>,
+}
+
+// Implementation of methods on AssocList.
+impl AssocList {
+    /// Returns an empty association list.
+    pub fn new() -> AssocList {
+        AssocList {
+            segments: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of associations on the list.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Adds an association to the list. An existing association to the same object is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `id2`:  The object the association points to.
+    /// * `time`: The time of the association.
+    /// * `data`: Data attached to the association.
+    ///
+    /// # Return
+    ///
+    /// True if the association did not already exist.
+    pub fn add(&mut self, id2: u64, time: u64, data: Bytes) -> bool {
+        let new = !self.del(id2);
+
+        let assoc = Assoc {
+            id2: id2,
+            time: time,
+            data: data,
+        };
+
+        if self.segments.is_empty() {
+            self.segments.push(vec![assoc]);
+        } else {
+            // Insert after every association at least as new, so that equal times are ordered by
+            // when they were added.
+            let s = self.segment(time).unwrap_or(self.segments.len() - 1);
+            let pos = {
+                let seg = &self.segments[s];
+                seg.iter().position(|a| a.time < time).unwrap_or(seg.len())
+            };
+            self.segments[s].insert(pos, assoc);
+
+            // Split the segment if it has outgrown it's bound.
+            if self.segments[s].len() > SEGMENT_LEN {
+                let half = self.segments[s].len() / 2;
+                let tail = self.segments[s].split_off(half);
+                self.segments.insert(s + 1, tail);
+            }
+        }
+
+        self.index.insert(id2, time);
+        new
+    }
+
+    /// Deletes the association to an object from the list.
+    ///
+    /// # Arguments
+    ///
+    /// * `id2`: The object the association points to.
+    ///
+    /// # Return
+    ///
+    /// True if the association existed.
+    pub fn del(&mut self, id2: u64) -> bool {
+        let time = match self.index.remove(&id2) {
+            Some(time) => time,
+            None => return false,
+        };
+
+        // Associations with the same time could span segments, so keep looking in the segments
+        // that follow the first one that could hold the association.
+        let mut s = self.segment(time).unwrap_or(self.segments.len());
+        while s < self.segments.len() {
+            let found = self.segments[s]
+                .iter()
+                .position(|a| a.id2 == id2 && a.time == time);
+
+            if let Some(pos) = found {
+                self.segments[s].remove(pos);
+                if self.segments[s].is_empty() {
+                    self.segments.remove(s);
+                }
+                return true;
+            }
+
+            s += 1;
+        }
+
+        // The index and segments disagree. This should never happen.
+        false
+    }
+
+    /// Returns associations on the list that are no newer than a given time, newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `high`:  The time of the newest association that can be returned. Passing in the time of
+    ///            the oldest association from a previous call pages through the list.
+    /// * `limit`: The maximum number of associations to return.
+    ///
+    /// # Return
+    ///
+    /// Upto `limit` associations, ordered by time, newest first.
+    pub fn range(&self, high: u64, limit: usize) -> Vec<Assoc> {
+        let mut ret = Vec::new();
+
+        let start = match self.segment(high) {
+            Some(s) => s,
+            None => return ret,
+        };
+
+        for seg in self.segments[start..].iter() {
+            for assoc in seg.iter().filter(|a| a.time <= high) {
+                if ret.len() >= limit {
+                    return ret;
+                }
+                ret.push(assoc.clone());
+            }
+        }
+
+        ret
+    }
+
+    // Returns the first segment holding an association no newer than `time`. Since segments are
+    // ordered, this is the first segment whose oldest association is no newer than `time`.
+    fn segment(&self, time: u64) -> Option<usize> {
+        let (mut lo, mut hi) = (0, self.segments.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let oldest = self.segments[mid].last().map_or(0, |a| a.time);
+            if oldest <= time {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+
+        if lo < self.segments.len() {
+            Some(lo)
+        } else {
+            None
+        }
+    }
+}
+
+// This module contains simple unit tests for AssocList.
+#[cfg(test)]
+mod tests {
+    use super::{AssocList, SEGMENT_LEN};
+
+    use bytes::Bytes;
+
+    // This test verifies that associations are returned newest first, and that replaced and
+    // deleted associations are not returned.
+    #[test]
+    fn test_assoc_add_del() {
+        let mut list = AssocList::new();
+        assert!(list.add(1, 10, Bytes::from(vec![1])));
+        assert!(list.add(2, 30, Bytes::from(vec![2])));
+        assert!(list.add(3, 20, Bytes::from(vec![3])));
+        assert!(!list.add(1, 40, Bytes::from(vec![4])));
+        assert_eq!(3, list.len());
+
+        let ids: Vec<u64> = list.range(u64::max_value(), 10).iter().map(|a| a.id2).collect();
+        assert_eq!(vec![1, 2, 3], ids);
+        assert_eq!(Bytes::from(vec![4]), list.range(40, 1)[0].data);
+
+        assert!(list.del(2));
+        assert!(!list.del(2));
+        let ids: Vec<u64> = list.range(u64::max_value(), 10).iter().map(|a| a.id2).collect();
+        assert_eq!(vec![1, 3], ids);
+    }
+
+    // This test verifies that range queries page correctly across many segments.
+    #[test]
+    fn test_assoc_range() {
+        let mut list = AssocList::new();
+        let n = (SEGMENT_LEN * 4) as u64;
+        for i in 0..n {
+            list.add(i, i, Bytes::new());
+        }
+        assert_eq!(n as usize, list.len());
+
+        let mut high = u64::max_value();
+        let mut seen = Vec::new();
+        loop {
+            let page = list.range(high, 50);
+            if page.is_empty() {
+                break;
+            }
+            for a in page.iter() {
+                seen.push(a.time);
+            }
+            let oldest = page.last().unwrap().time;
+            if oldest == 0 {
+                break;
+            }
+            high = oldest - 1;
+        }
+
+        let expected: Vec<u64> = (0..n).rev().collect();
+        assert_eq!(expected, seen);
+
+        let page = list.range(SEGMENT_LEN as u64 + 3, 2);
+        assert_eq!(SEGMENT_LEN as u64 + 3, page[0].time);
+        assert_eq!(SEGMENT_LEN as u64 + 2, page[1].time);
+
+        for i in 0..n {
+            assert!(list.del(i));
+        }
+        assert_eq!(0, list.len());
+        assert!(list.range(u64::max_value(), 10).is_empty());
+    }
+}
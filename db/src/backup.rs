@@ -0,0 +1,693 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+// Snapshots can be streamed over TCP to a backup instead of being written to a local directory,
+// so that servers without a disk of their own can still be backed up. The stream carries the
+// exact bytes of a snapshot file (refer to `snapshot::Writer`), which the backup writes into a
+// snapshot directory of it's own, so that the directory can be restored from like any other.
+//
+// A stream starts with a hello from the server (Magic, Epoch, Resume flag; 17 bytes), answered
+// by the backup with the latest epoch on it's manifest and the number of bytes of the epoch's
+// snapshot it already holds (16 bytes). A snapshot then follows as frames with the layout below
+// (little-endian), each of which is acknowledged with the number of bytes the backup has made
+// durable so far (8 bytes):
+//      __________________________________________________________
+//     |        |            |            |            |          |
+//     |  Kind  |   Offset   |   Length   |   CRC-32   |   Body   |
+//     |________|____________|____________|____________|__________|
+//      1 Byte     8 Bytes      4 Bytes      4 Bytes    Var Length
+//
+// Chunk frames carry the bytes of the snapshot at an offset. The finish frame carries the
+// snapshot's manifest entry, and is acknowledged with `COMMITTED` once the backup has verified
+// the snapshot and listed it on it's manifest. Chunks are retained by the server until they are
+// acknowledged, so a transfer cut short by a dropped connection is resumed on a new one, from
+// whatever the backup holds.
+
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::mem::{replace, transmute};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use super::snapshot::{self, Crc32, ManifestEntry, Target};
+
+// Identifies a snapshot stream, and the version of it's protocol.
+const MAGIC: &[u8; 8] = b"SPLBKUP1";
+
+/// The number of bytes of a snapshot carried by a single chunk frame.
+pub const CHUNK_BYTES: usize = 1 << 20;
+
+// The number of chunks that can be outstanding before the server waits for the backup to
+// acknowledge them.
+const WINDOW: usize = 8;
+
+// The number of times a dropped connection is re-established before a transfer is given up on.
+const RETRIES: u32 = 5;
+
+// The frame kind of a chunk of the snapshot.
+const FRAME_CHUNK: u8 = 0x01;
+
+// The frame kind that ends a snapshot. The body is the snapshot's manifest entry: whether it is
+// full (1 byte), the number of records (8 bytes), and the checksum over them (4 bytes).
+const FRAME_FINISH: u8 = 0x02;
+
+// The length of the header on every frame.
+const FRAME_HEADER_LEN: usize = 17;
+
+/// The acknowledgement of a finish frame, once the snapshot has been listed on the manifest.
+pub const COMMITTED: u64 = u64::max_value();
+
+/// Streams a snapshot to a backup. Used as the target of a `snapshot::Writer`, with the
+/// snapshot listed on the backup's manifest once the writer is finished.
+pub struct Stream {
+    // The address (IPv4:Port) of the backup.
+    addr: String,
+
+    // The epoch of the snapshot being streamed.
+    epoch: u64,
+
+    // The connection to the backup. None until connected.
+    conn: Option<TcpStream>,
+
+    // Bytes of the snapshot that have not filled a chunk yet.
+    pending: Vec<u8>,
+
+    // Chunks that were sent out but not acknowledged yet, with their offsets, oldest first.
+    unacked: VecDeque<(u64, Vec<u8>)>,
+
+    // The number of bytes of the snapshot sent out so far.
+    sent: u64,
+
+    // The rate in bytes per second the snapshot is sent out at. Zero does not throttle.
+    rate: u64,
+
+    // The time the stream was created at. Throttling is relative to it.
+    start: Instant,
+
+    // The snapshot's manifest entry once it is being committed. Refer to `Target::commit()`.
+    entry: Option<ManifestEntry>,
+
+    // Set once the backup has listed the snapshot on it's manifest.
+    committed: bool,
+
+    // The number of times the transfer was resumed over a new connection.
+    resumes: u32,
+
+    // Checksum generator.
+    crc: Crc32,
+}
+
+// Implementation of methods on Stream.
+impl Stream {
+    /// Connects to a backup, and starts a fresh transfer of a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr`:  The address (IPv4:Port) of the backup.
+    /// * `epoch`: The epoch of the snapshot. Must be larger than any on the backup's manifest.
+    /// * `rate`:  The rate in bytes per second the snapshot is sent out at. Zero does not
+    ///            throttle.
+    ///
+    /// # Return
+    ///
+    /// The stream, or an error if the backup could not be reached or already has the epoch.
+    pub fn connect(addr: &str, epoch: u64, rate: u64) -> Result<Stream> {
+        let mut stream = Stream {
+            addr: addr.to_string(),
+            epoch: epoch,
+            conn: None,
+            pending: Vec::with_capacity(CHUNK_BYTES),
+            unacked: VecDeque::new(),
+            sent: 0,
+            rate: rate,
+            start: Instant::now(),
+            entry: None,
+            committed: false,
+            resumes: 0,
+            crc: Crc32::new(),
+        };
+
+        stream.reconnect(false)?;
+        Ok(stream)
+    }
+
+    /// Returns the number of bytes of the snapshot sent out so far.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Returns the number of times the transfer was resumed over a new connection.
+    pub fn resumes(&self) -> u32 {
+        self.resumes
+    }
+
+    // Connects to the backup. If resuming, chunks the backup does not hold yet are sent out
+    // again, followed by the finish frame if the snapshot is being committed.
+    fn reconnect(&mut self, resume: bool) -> Result<()> {
+        let (mut conn, latest, held) = hello(&self.addr, self.epoch, resume)?;
+
+        // The backup lists a snapshot only after it's finish frame, whose acknowledgement was
+        // lost along with the previous connection.
+        if latest >= self.epoch {
+            if self.entry.is_some() && resume {
+                self.committed = true;
+                return Ok(());
+            }
+
+            return Err(Error::new(ErrorKind::AlreadyExists, "Backup already has the epoch."));
+        }
+
+        let base = self.unacked.front().map_or(self.sent, |&(offset, _)| offset);
+        if held < base || held > self.sent {
+            return Err(Error::new(ErrorKind::InvalidData, "Backup cannot resume the snapshot."));
+        }
+
+        self.acknowledge(held);
+        for &(offset, ref chunk) in self.unacked.iter() {
+            let skip = held.max(offset) - offset;
+            let rest = &chunk[skip as usize..];
+            write_frame(&mut conn, &self.crc, FRAME_CHUNK, offset + skip, rest)?;
+        }
+
+        if let Some(ref entry) = self.entry {
+            write_frame(&mut conn, &self.crc, FRAME_FINISH, self.sent, &encode_entry(entry))?;
+        }
+
+        self.conn = Some(conn);
+        Ok(())
+    }
+
+    // Reconnects to the backup after an error on the connection, and resumes the transfer.
+    // Returns the error if the transfer could not be resumed.
+    fn recover(&mut self, err: Error) -> Result<()> {
+        let mut err = err;
+        self.conn = None;
+
+        for attempt in 0..RETRIES {
+            if err.kind() == ErrorKind::InvalidData || err.kind() == ErrorKind::AlreadyExists {
+                break;
+            }
+
+            warn!("Snapshot stream to {} failed: {}. Resuming.", self.addr, err);
+            sleep(Duration::from_millis(100 << attempt));
+            match self.reconnect(true) {
+                Ok(()) => {
+                    self.resumes += 1;
+                    return Ok(());
+                }
+
+                Err(e) => err = e,
+            }
+        }
+
+        Err(err)
+    }
+
+    // Sends out the pending bytes as a chunk, and waits for acknowledgements if too many chunks
+    // are outstanding.
+    fn send_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = replace(&mut self.pending, Vec::with_capacity(CHUNK_BYTES));
+        let offset = self.sent;
+        self.sent += chunk.len() as u64;
+
+        let res = match self.conn {
+            Some(ref mut conn) => write_frame(conn, &self.crc, FRAME_CHUNK, offset, &chunk),
+            None => Err(Error::new(ErrorKind::NotConnected, "Not connected to the backup.")),
+        };
+        self.unacked.push_back((offset, chunk));
+
+        // On a new connection, every chunk that was not acknowledged goes out again.
+        if let Err(err) = res {
+            self.recover(err)?;
+        }
+
+        self.pace();
+        while self.unacked.len() > WINDOW {
+            self.await_ack()?;
+        }
+
+        Ok(())
+    }
+
+    // Waits for the next acknowledgement from the backup, resuming the transfer if the
+    // connection was dropped.
+    fn await_ack(&mut self) -> Result<()> {
+        let res = match self.conn {
+            Some(ref mut conn) => {
+                let mut ack = [0u8; 8];
+                conn.read_exact(&mut ack).map(|_| read_u64(&ack))
+            }
+
+            None => Err(Error::new(ErrorKind::NotConnected, "Not connected to the backup.")),
+        };
+
+        match res {
+            Ok(COMMITTED) => {
+                self.committed = true;
+                Ok(())
+            }
+
+            Ok(held) => {
+                self.acknowledge(held);
+                Ok(())
+            }
+
+            Err(err) => self.recover(err),
+        }
+    }
+
+    // Releases every chunk the backup holds in full.
+    fn acknowledge(&mut self, held: u64) {
+        while self
+            .unacked
+            .front()
+            .map_or(false, |&(offset, ref chunk)| offset + chunk.len() as u64 <= held)
+        {
+            self.unacked.pop_front();
+        }
+    }
+
+    // Sleeps for as long as the stream is ahead of it's rate.
+    fn pace(&self) {
+        if self.rate == 0 {
+            return;
+        }
+
+        let due = Duration::from_millis(self.sent * 1000 / self.rate);
+        let elapsed = self.start.elapsed();
+        if due > elapsed {
+            sleep(due - elapsed);
+        }
+    }
+}
+
+// Implementation of the Write trait for Stream. Bytes are sent out once they fill a chunk, or
+// once the stream is flushed.
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = buf.len().min(CHUNK_BYTES - self.pending.len());
+        self.pending.extend_from_slice(&buf[..len]);
+
+        if self.pending.len() == CHUNK_BYTES {
+            self.send_pending()?;
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send_pending()
+    }
+}
+
+// Implementation of the Target trait for Stream.
+impl Target for Stream {
+    /// Sends out the rest of the snapshot, and waits for the backup to verify it and list it on
+    /// it's manifest.
+    fn commit(mut self, entry: &ManifestEntry) -> Result<()> {
+        self.send_pending()?;
+        self.entry = Some(entry.clone());
+
+        let res = match self.conn {
+            Some(ref mut conn) => {
+                write_frame(conn, &self.crc, FRAME_FINISH, self.sent, &encode_entry(entry))
+            }
+
+            None => Err(Error::new(ErrorKind::NotConnected, "Not connected to the backup.")),
+        };
+        if let Err(err) = res {
+            self.recover(err)?;
+        }
+
+        while !self.committed {
+            self.await_ack()?;
+        }
+
+        if let Some(ref conn) = self.conn {
+            let _ = conn.shutdown(Shutdown::Both);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the latest epoch on a backup's manifest, so that the epochs of snapshots streamed to
+/// it can continue from there. Zero if the backup holds no snapshots.
+///
+/// # Arguments
+///
+/// * `addr`: The address (IPv4:Port) of the backup.
+pub fn latest_epoch(addr: &str) -> Result<u64> {
+    let (conn, latest, _) = hello(addr, 0, false)?;
+    let _ = conn.shutdown(Shutdown::Both);
+    Ok(latest)
+}
+
+/// Receives snapshots streamed by servers (refer to `Stream`) into a snapshot directory, so
+/// that this server acts as their backup. Snapshots are verified in full before they are listed
+/// on the directory's manifest.
+pub struct Receiver {
+    // The listener snapshots are streamed to.
+    listener: TcpListener,
+
+    // The snapshot directory snapshots are received into.
+    dir: String,
+}
+
+// Implementation of methods on Receiver.
+impl Receiver {
+    /// Binds a listener for snapshot streams.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr`: The address (IPv4:Port) to listen on.
+    /// * `dir`:  The snapshot directory to receive snapshots into. Must already exist.
+    pub fn new(addr: &str, dir: &str) -> Result<Receiver> {
+        Ok(Receiver {
+            listener: TcpListener::bind(addr)?,
+            dir: dir.to_string(),
+        })
+    }
+
+    /// Returns the address the listener is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Receives snapshot streams one after the other, forever.
+    pub fn execute(&self) {
+        for conn in self.listener.incoming() {
+            let res = conn.and_then(|conn| self.receive(conn));
+            match res {
+                Ok(Some(entry)) => info!(
+                    "Received snapshot: epoch {} ({}), {} records",
+                    entry.epoch,
+                    if entry.full { "full" } else { "incremental" },
+                    entry.records
+                ),
+
+                Ok(None) => {}
+
+                Err(ref err) => warn!("Failed to receive snapshot stream: {}", err),
+            }
+        }
+    }
+
+    /// Receives a single snapshot stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn`: The connection the snapshot is being streamed over.
+    ///
+    /// # Return
+    ///
+    /// The snapshot's manifest entry once it was received in full and listed on the manifest, or
+    /// None if the stream ended earlier without an error (ex: a query for the latest epoch).
+    pub fn receive(&self, mut conn: TcpStream) -> Result<Option<ManifestEntry>> {
+        let mut hello = [0u8; 17];
+        conn.read_exact(&mut hello)?;
+        if &hello[0..8] != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a snapshot stream."));
+        }
+
+        let epoch = read_u64(&hello[8..16]);
+        let latest = snapshot::read_manifest(&self.dir)?
+            .last()
+            .map_or(0, |entry| entry.epoch);
+        if epoch <= latest {
+            conn.write_all(&encode_u64(latest))?;
+            conn.write_all(&encode_u64(0))?;
+            return Ok(None);
+        }
+
+        // A fresh transfer starts over, discarding whatever an earlier one left behind.
+        let path = snapshot::snapshot_path(&self.dir, epoch);
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if hello[16] != 1 {
+            file.set_len(0)?;
+        }
+
+        let mut held = file.metadata()?.len();
+        conn.write_all(&encode_u64(latest))?;
+        conn.write_all(&encode_u64(held))?;
+
+        let crc = Crc32::new();
+        loop {
+            let mut header = [0u8; FRAME_HEADER_LEN];
+            if let Err(err) = conn.read_exact(&mut header) {
+                // The server went away. Whatever was received is kept for it to resume from.
+                if err.kind() == ErrorKind::UnexpectedEof {
+                    return Ok(None);
+                }
+                return Err(err);
+            }
+
+            let offset = read_u64(&header[1..9]);
+            let len = read_u32(&header[9..13]) as usize;
+            if len > CHUNK_BYTES {
+                return Err(Error::new(ErrorKind::InvalidData, "Snapshot frame too large."));
+            }
+
+            let mut body = vec![0u8; len];
+            conn.read_exact(&mut body)?;
+            if crc.checksum(&body) != read_u32(&header[13..17]) {
+                return Err(Error::new(ErrorKind::InvalidData, "Snapshot frame checksum mismatch."));
+            }
+
+            if offset != held {
+                return Err(Error::new(ErrorKind::InvalidData, "Snapshot frame out of order."));
+            }
+
+            match header[0] {
+                FRAME_CHUNK => {
+                    file.write_all(&body)?;
+                    file.sync_data()?;
+                    held += len as u64;
+                    conn.write_all(&encode_u64(held))?;
+                }
+
+                FRAME_FINISH if len == 13 => {
+                    let entry = ManifestEntry {
+                        epoch: epoch,
+                        full: body[0] == 1,
+                        records: read_u64(&body[1..9]),
+                        crc: read_u32(&body[9..13]),
+                    };
+                    file.sync_all()?;
+
+                    // Make sure the snapshot can be restored from before listing it.
+                    let mut reader = snapshot::Reader::new(&self.dir, &entry)?;
+                    while let Some(_) = reader.next()? {}
+
+                    snapshot::append_manifest(&self.dir, &entry)?;
+                    conn.write_all(&encode_u64(COMMITTED))?;
+                    return Ok(Some(entry));
+                }
+
+                _ => return Err(Error::new(ErrorKind::InvalidData, "Unknown snapshot frame.")),
+            }
+        }
+    }
+}
+
+// Connects to a backup and exchanges hellos. Returns the connection, the latest epoch on the
+// backup's manifest, and the number of bytes of the epoch's snapshot it holds.
+fn hello(addr: &str, epoch: u64, resume: bool) -> Result<(TcpStream, u64, u64)> {
+    let mut conn = TcpStream::connect(addr)?;
+    conn.set_nodelay(true)?;
+
+    let mut hello = Vec::with_capacity(17);
+    hello.extend_from_slice(MAGIC);
+    hello.extend_from_slice(&encode_u64(epoch));
+    hello.push(resume as u8);
+    conn.write_all(&hello)?;
+
+    let mut reply = [0u8; 16];
+    conn.read_exact(&mut reply)?;
+    Ok((conn, read_u64(&reply[0..8]), read_u64(&reply[8..16])))
+}
+
+// Writes out a frame. Refer to the top of this module for it's layout.
+fn write_frame<W>(out: &mut W, crc: &Crc32, kind: u8, offset: u64, body: &[u8]) -> Result<()>
+where
+    W: Write,
+{
+    let l: [u8; 4] = unsafe { transmute((body.len() as u32).to_le()) };
+    let c: [u8; 4] = unsafe { transmute(crc.checksum(body).to_le()) };
+    out.write_all(&[kind])?;
+    out.write_all(&encode_u64(offset))?;
+    out.write_all(&l)?;
+    out.write_all(&c)?;
+    out.write_all(body)
+}
+
+// Lays out a manifest entry on the body of a finish frame.
+fn encode_entry(entry: &ManifestEntry) -> Vec<u8> {
+    let c: [u8; 4] = unsafe { transmute(entry.crc.to_le()) };
+    let mut body = Vec::with_capacity(13);
+    body.push(entry.full as u8);
+    body.extend_from_slice(&encode_u64(entry.records));
+    body.extend_from_slice(&c);
+    body
+}
+
+// Lays out a u64 in little-endian.
+fn encode_u64(value: u64) -> [u8; 8] {
+    unsafe { transmute(value.to_le()) }
+}
+
+// Reads a little-endian u64 off the first 8 bytes of a slice.
+fn read_u64(buf: &[u8]) -> u64 {
+    let mut v: [u8; 8] = [0; 8];
+    v.copy_from_slice(&buf[0..8]);
+    u64::from_le(unsafe { transmute(v) })
+}
+
+// Reads a little-endian u32 off the first 4 bytes of a slice.
+fn read_u32(buf: &[u8]) -> u32 {
+    let mut v: [u8; 4] = [0; 4];
+    v.copy_from_slice(&buf[0..4]);
+    u32::from_le(unsafe { transmute(v) })
+}
+
+// This module contains simple unit tests for streaming snapshots to a backup.
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::io::{Read, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::thread;
+
+    use super::{latest_epoch, Receiver, Stream, CHUNK_BYTES};
+    use snapshot::{read_manifest, restore_chain, Reader, Record, Writer};
+
+    // Creates an empty snapshot directory for a test.
+    fn empty_dir(name: &str) -> String {
+        let dir = format!("/tmp/sandstorm_backup_{}", name);
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Streams a snapshot with objects spanning several chunks to a backup.
+    fn stream(addr: &str, epoch: u64) {
+        let stream = Stream::connect(addr, epoch, 0).expect("Failed to connect to backup.");
+        let mut writer = Writer::with_target(stream, epoch, true).unwrap();
+        for i in 0..5 {
+            writer.object(&vec![i as u8; CHUNK_BYTES / 2 + 7]).unwrap();
+        }
+        writer.tombstone(7, 9, &[1; 6]).unwrap();
+
+        let entry = writer.finish().expect("Failed to commit snapshot.");
+        assert_eq!(6, entry.records);
+    }
+
+    // Verifies the snapshot written out by `stream()` in a backup's directory.
+    fn verify(dir: &str, epoch: u64) {
+        let chain = restore_chain(&read_manifest(dir).unwrap());
+        assert_eq!(vec![epoch], chain.iter().map(|e| e.epoch).collect::<Vec<u64>>());
+
+        let mut reader = Reader::new(dir, &chain[0]).unwrap();
+        for i in 0..5 {
+            let object = vec![i as u8; CHUNK_BYTES / 2 + 7];
+            assert_eq!(Some(Record::Object(object)), reader.next().unwrap());
+        }
+        assert_eq!(Some(Record::Tombstone(7, 9, vec![1; 6])), reader.next().unwrap());
+        assert_eq!(None, reader.next().unwrap());
+    }
+
+    // This test streams a snapshot to a backup, and restores it from the backup's directory.
+    #[test]
+    fn test_stream() {
+        let dir = empty_dir("stream");
+        let receiver = Receiver::new("127.0.0.1:0", &dir).unwrap();
+        let addr = receiver.local_addr().unwrap().to_string();
+
+        let backup = thread::spawn(move || {
+            let mut entries = Vec::new();
+            for conn in receiver.listener.incoming().take(3) {
+                entries.push(receiver.receive(conn.unwrap()).unwrap().map(|e| e.epoch));
+            }
+            entries
+        });
+
+        assert_eq!(0, latest_epoch(&addr).unwrap());
+        stream(&addr, 1);
+        assert_eq!(1, latest_epoch(&addr).unwrap());
+
+        assert_eq!(vec![None, Some(1), None], backup.join().unwrap());
+        verify(&dir, 1);
+    }
+
+    // This test verifies that a transfer whose connection is dropped part way through is resumed
+    // over a new connection from whatever the backup holds.
+    #[test]
+    fn test_stream_resume() {
+        let dir = empty_dir("resume");
+        let receiver = Receiver::new("127.0.0.1:0", &dir).unwrap();
+        let backup = receiver.local_addr().unwrap();
+        thread::spawn(move || receiver.execute());
+
+        // A proxy in front of the backup that drops the first connection after it has carried
+        // a little over a chunk, and forwards every later one in full.
+        let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = proxy.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for (i, conn) in proxy.incoming().enumerate() {
+                let client = conn.unwrap();
+                let server = TcpStream::connect(backup).unwrap();
+                let limit = if i == 0 {
+                    CHUNK_BYTES + CHUNK_BYTES / 2
+                } else {
+                    usize::max_value()
+                };
+                forward(client.try_clone().unwrap(), server.try_clone().unwrap(), limit);
+                forward(server, client, usize::max_value());
+            }
+        });
+
+        stream(&addr, 1);
+        verify(&dir, 1);
+    }
+
+    // Forwards bytes from one connection to another on a thread of it's own, shutting both down
+    // once `limit` bytes were forwarded or either end closed.
+    fn forward(mut from: TcpStream, mut to: TcpStream, limit: usize) {
+        thread::spawn(move || {
+            let mut buf = vec![0u8; 64 * 1024];
+            let mut forwarded = 0;
+            while forwarded < limit {
+                let n = match from.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n.min(limit - forwarded),
+                };
+
+                if to.write_all(&buf[..n]).is_err() {
+                    break;
+                }
+                forwarded += n;
+            }
+
+            let _ = from.shutdown(Shutdown::Both);
+            let _ = to.shutdown(Shutdown::Both);
+        });
+    }
+}
@@ -20,17 +20,18 @@ extern crate rand;
 extern crate zipf;
 
 mod dispatch;
+mod histogram;
+mod report;
 mod setup;
 
 use std::mem::{size_of, transmute};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use db::config;
 use db::cycles;
-use db::e2d2::allocators::CacheAligned;
-use db::e2d2::interface::PortQueue;
 use db::e2d2::scheduler::*;
 use db::log::*;
+use db::transport::Port;
 use db::wireformat::*;
 
 use rand::distributions::Sample;
@@ -93,7 +94,7 @@ impl AggregateSend {
     /// * `ord`:       Order of the final polynomial to be computed.
     pub fn new(
         config: &config::ClientConfig,
-        port: CacheAligned<PortQueue>,
+        port: Port,
         reqs: u64,
         dst_ports: u16,
         num: u32,
@@ -117,7 +118,7 @@ impl AggregateSend {
         n_buff.resize(config.key_len, 0);
 
         AggregateSend {
-            random: XorShiftRng::from_seed(rand::random::<[u32; 4]>()),
+            random: XorShiftRng::from_seed(report::rng_seed()),
             k_dist: ZipfDistribution::new(config.n_keys, config.skew)
                 .expect("Failed to init key generator."),
             t_dist: ZipfDistribution::new(config.num_tenants as usize, config.tenant_skew)
@@ -200,11 +201,11 @@ impl Executable for AggregateSend {
 /// server and aggregates the returned value into a single 64 bit integer.
 struct AggregateRecv {
     /// The network stack required to receives RPC response packets from a network port.
-    receiver: dispatch::Receiver<CacheAligned<PortQueue>>,
+    receiver: dispatch::Receiver<Port>,
 
     /// Second receiver stack to receive multiget RPC response packets when operating in
     /// native mode.
-    multi_rx: dispatch::Receiver<CacheAligned<PortQueue>>,
+    multi_rx: dispatch::Receiver<Port>,
 
     /// Network stack that can actually send an RPC over the network. Required for the native case.
     sender: dispatch::Sender,
@@ -231,6 +232,10 @@ struct AggregateRecv {
 
     /// Order of the final polynomial to be computed.
     ord: u32,
+
+    /// Results merged across the receivers of all queues, and written out once the client is
+    /// done.
+    report: Arc<Mutex<report::Report>>,
 }
 
 // Implementation of methods on AggregateRecv.
@@ -249,19 +254,21 @@ impl AggregateRecv {
     ///                well as network related (Server and Client MAC address etc.) parameters.
     /// * `num`:       The number of keys aggregations are to be performed across.
     /// * `ord`:       Order of the final polynomial to be computed.
+    /// * `report`:    Results merged across the receivers of all queues.
     ///
     /// # Return
     ///
     /// A receiver that measures the median latency and throughput of a Sandstorm server.
     fn new(
-        port: CacheAligned<PortQueue>,
+        port: Port,
         resps: u64,
         native: bool,
-        send: CacheAligned<PortQueue>,
+        send: Port,
         dst_ports: u16,
         config: &config::ClientConfig,
         num: u32,
         ord: u32,
+        report: Arc<Mutex<report::Report>>,
     ) -> AggregateRecv {
         AggregateRecv {
             receiver: dispatch::Receiver::new(port),
@@ -274,6 +281,7 @@ impl AggregateRecv {
             latencies: Vec::with_capacity(2 * 1000 * 1000),
             num: num,
             ord: ord,
+            report: report,
         }
     }
 
@@ -312,13 +320,19 @@ impl AggregateRecv {
         self.latencies.sort();
         let median = self.latencies[self.latencies.len() / 2];
         let tail = self.latencies[(self.latencies.len() * 99) / 100];
+        let throughput = self.recvd as f64 / cycles::to_seconds(stop - self.start);
 
         info!(
             "Median(ns): {} Tail(ns): {} Throughput(Kops/s): {}",
             cycles::to_seconds(median) * 1e9,
             cycles::to_seconds(tail) * 1e9,
-            self.recvd as f64 / cycles::to_seconds(stop - self.start)
+            throughput
         );
+
+        // Merge the throughput and latencies into the client's results.
+        let mut report = self.report.lock().unwrap();
+        report.record(self.recvd, throughput);
+        report.samples(&self.latencies);
     }
 }
 
@@ -396,7 +410,7 @@ impl Executable for AggregateRecv {
 /// * `ord`:       Order of the final polynomial to be computed.
 fn setup_send<S>(
     config: &config::ClientConfig,
-    ports: Vec<CacheAligned<PortQueue>>,
+    ports: Vec<Port>,
     scheduler: &mut S,
     _core: i32,
     num: u32,
@@ -444,15 +458,17 @@ fn setup_send<S>(
 /// * `config`:    Network related configuration such as the MAC and IP address.
 /// * `num`:       Number of keys aggregations are to be performed across.
 /// * `ord`:       Order of the final polynomial to be computed.
+/// * `report`:    Results merged across the receivers of all queues.
 fn setup_recv<S>(
-    ports: Vec<CacheAligned<PortQueue>>,
+    ports: Vec<Port>,
     scheduler: &mut S,
     _core: i32,
     native: bool,
-    send: Vec<CacheAligned<PortQueue>>,
+    send: Vec<Port>,
     config: &config::ClientConfig,
     num: u32,
     ord: u32,
+    report: Arc<Mutex<report::Report>>,
 ) where
     S: Scheduler + Sized,
 {
@@ -471,6 +487,7 @@ fn setup_recv<S>(
         config,
         num,
         ord,
+        report,
     )) {
         Ok(_) => {
             info!(
@@ -491,6 +508,7 @@ fn main() {
 
     let config = config::ClientConfig::load();
     info!("Starting up Sandstorm client with config {:?}", config);
+    info!("Seeding workloads with {}", report::set_seed(&config));
 
     // Based on the supplied client configuration, compute the amount of time it will take to send
     // out `num_reqs` requests at a rate of `req_rate` requests per second.
@@ -502,12 +520,8 @@ fn main() {
     // Setup the client pipeline.
     net_context.start_schedulers();
 
-    // The core id's which will run the sender and receiver threads.
-    // XXX The following two arrays heavily depend on the set of cores
-    // configured in setup.rs
-    let senders = [0, 2, 4, 6];
-    let receive = [1, 3, 5, 7];
-    assert!((senders.len() == 4) && (receive.len() == 4));
+    // The cores which will run the sender and receiver threads, and the queues they share.
+    let pipelines = setup::pipelines(&config, &net_context);
 
     // Required by AggregateRecv.
     let native = !config.use_invoke;
@@ -516,30 +530,33 @@ fn main() {
     let num = config.num_aggr;
     let ord = config.order;
 
-    // Setup 4 senders and 4 receivers.
-    for i in 0..4 {
-        // First, retrieve a tx-rx queue pair from Netbricks
-        let port = net_context
-            .rx_queues
-            .get(&senders[i])
-            .expect("Failed to retrieve network port!")
-            .clone();
+    // Receivers on every queue merge their results into this once they are done.
+    let report = report::Report::new();
+
+    // Setup a sender and a receiver for every pipeline.
+    for pipeline in pipelines {
+        // First, retrieve the tx-rx queue pair shared by the pipeline.
+        let port = pipeline.port;
+        let send_port = port.clone();
+        let reply = pipeline.reply;
+        let merged = Arc::clone(&report);
 
         // Setup the receive side.
         net_context
             .add_pipeline_to_core(
-                receive[i],
+                pipeline.receiver,
                 Arc::new(
-                    move |send, sched: &mut StandaloneScheduler, core: i32, _sibling| {
+                    move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
                         setup_recv(
                             port.clone(),
                             sched,
                             core,
                             native,
-                            send,
+                            reply.clone(),
                             &config::ClientConfig::load(),
                             num,
                             ord,
+                            Arc::clone(&merged),
                         )
                     },
                 ),
@@ -549,10 +566,17 @@ fn main() {
         // Setup the send side.
         net_context
             .add_pipeline_to_core(
-                senders[i],
+                pipeline.sender,
                 Arc::new(
-                    move |ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
-                        setup_send(&config::ClientConfig::load(), ports, sched, core, num, ord)
+                    move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
+                        setup_send(
+                            &config::ClientConfig::load(),
+                            send_port.clone(),
+                            sched,
+                            core,
+                            num,
+                            ord,
+                        )
                     },
                 ),
             )
@@ -568,4 +592,9 @@ fn main() {
 
     // Stop the client.
     net_context.stop();
+
+    // Write the client's results out for analysis scripts.
+    if let Err(err) = report.lock().unwrap().emit("aggregate", &config) {
+        warn!("Failed to write out results: {}", err);
+    }
 }
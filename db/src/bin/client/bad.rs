@@ -21,20 +21,22 @@ extern crate time;
 extern crate zipf;
 
 mod dispatch;
+mod histogram;
+mod report;
 mod setup;
 
 use std::cell::RefCell;
 use std::fmt::Display;
 use std::mem;
 use std::mem::transmute;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use db::config;
 use db::cycles;
-use db::e2d2::allocators::*;
 use db::e2d2::interface::*;
 use db::e2d2::scheduler::*;
 use db::log::*;
+use db::transport::Port;
 use db::wireformat::*;
 
 use rand::distributions::Sample;
@@ -80,7 +82,7 @@ impl Bad {
         n_tenants: u32,
         tenant_skew: f64,
     ) -> Bad {
-        let seed: [u32; 4] = rand::random::<[u32; 4]>();
+        let seed: [u32; 4] = report::rng_seed();
 
         let mut key_buf: Vec<u8> = Vec::with_capacity(key_len);
         key_buf.resize(key_len, 0);
@@ -182,7 +184,7 @@ impl BadSend {
     /// A Bad request generator.
     fn new(
         config: &config::ClientConfig,
-        port: CacheAligned<PortQueue>,
+        port: Port,
         reqs: u64,
         dst_ports: u16,
     ) -> BadSend {
@@ -300,6 +302,10 @@ where
 
     // Time stamp in cycles at which measurement stopped.
     stop: u64,
+
+    // Results merged across the receivers of all queues, and written out once the client is
+    // done.
+    report: Arc<Mutex<report::Report>>,
 }
 
 // Implementation of methods on BadRecv.
@@ -314,12 +320,13 @@ where
     /// * `port` :  Network port on which responses will be polled for.
     /// * `resps`:  The number of responses to wait for before calculating statistics.
     /// * `master`: Boolean indicating if the receiver should make latency measurements.
+    /// * `report`: Results merged across the receivers of all queues.
     ///
     /// # Return
     ///
     /// A response receiver that measures the median latency and throughput of a Sandstorm
     /// server.
-    fn new(port: T, resps: u64, master: bool) -> BadRecv<T> {
+    fn new(port: T, resps: u64, master: bool, report: Arc<Mutex<report::Report>>) -> BadRecv<T> {
         BadRecv {
             receiver: dispatch::Receiver::new(port),
             responses: resps,
@@ -328,6 +335,7 @@ where
             latencies: Vec::with_capacity(resps as usize),
             master: master,
             stop: 0,
+            report: report,
         }
     }
 }
@@ -339,10 +347,15 @@ where
 {
     fn drop(&mut self) {
         // Calculate & print the throughput for all client threads.
-        println!(
-            "BAD Throughput {}",
-            self.recvd as f64 / cycles::to_seconds(self.stop - self.start)
-        );
+        let throughput = self.recvd as f64 / cycles::to_seconds(self.stop - self.start);
+        println!("BAD Throughput {}", throughput);
+
+        // Merge the throughput and latencies into the client's results.
+        {
+            let mut report = self.report.lock().unwrap();
+            report.record(self.recvd, throughput);
+            report.samples(&self.latencies);
+        }
 
         // Calculate & print median & tail latency only on the master thread.
         if self.master {
@@ -422,7 +435,7 @@ where
 /// * `scheduler`: Netbricks scheduler to which BadSend will be added.
 fn setup_send<S>(
     config: &config::ClientConfig,
-    ports: Vec<CacheAligned<PortQueue>>,
+    ports: Vec<Port>,
     scheduler: &mut S,
     _core: i32,
 ) where
@@ -461,8 +474,14 @@ fn setup_send<S>(
 /// * `ports`:     Network port on which packets will be sent.
 /// * `scheduler`: Netbricks scheduler to which BadRecv will be added.
 /// * `master`:    If true, the added BadRecv will make latency measurements.
-fn setup_recv<S>(ports: Vec<CacheAligned<PortQueue>>, scheduler: &mut S, _core: i32, master: bool)
-where
+/// * `report`:    Results merged across the receivers of all queues.
+fn setup_recv<S>(
+    ports: Vec<Port>,
+    scheduler: &mut S,
+    _core: i32,
+    master: bool,
+    report: Arc<Mutex<report::Report>>,
+) where
     S: Scheduler + Sized,
 {
     if ports.len() != 1 {
@@ -475,6 +494,7 @@ where
         ports[0].clone(),
         34 * 1000 * 1000 as u64,
         master,
+        report,
     )) {
         Ok(_) => {
             info!(
@@ -495,6 +515,7 @@ fn main() {
 
     let config = config::ClientConfig::load();
     info!("Starting up Sandstorm client with config {:?}", config);
+    info!("Seeding workloads with {}", report::set_seed(&config));
 
     // Based on the supplied client configuration, compute the amount of time it will take to send
     // out `num_reqs` requests at a rate of `req_rate` requests per second.
@@ -506,34 +527,31 @@ fn main() {
     // Setup the client pipeline.
     net_context.start_schedulers();
 
-    // The core id's which will run the sender and receiver threads.
-    // XXX The following two arrays heavily depend on the set of cores
-    // configured in setup.rs
-    let senders = [0, 2, 4, 6];
-    let receive = [1, 3, 5, 7];
-    assert!((senders.len() == 4) && (receive.len() == 4));
-
-    // Setup 4 senders, and 4 receivers.
-    for i in 0..4 {
-        // First, retrieve a tx-rx queue pair from Netbricks
-        let port = net_context
-            .rx_queues
-            .get(&senders[i])
-            .expect("Failed to retrieve network port!")
-            .clone();
+    // The cores which will run the sender and receiver threads, and the queues they share.
+    let pipelines = setup::pipelines(&config, &net_context);
+
+    // Receivers on every queue merge their results into this once they are done.
+    let report = report::Report::new();
+
+    // Setup a sender and a receiver for every pipeline.
+    for (i, pipeline) in pipelines.into_iter().enumerate() {
+        // First, retrieve the tx-rx queue pair shared by the pipeline.
+        let port = pipeline.port;
+        let send_port = port.clone();
 
         let mut master = false;
         if i == 0 {
             master = true;
         }
+        let merged = Arc::clone(&report);
 
         // Setup the receive side.
         net_context
             .add_pipeline_to_core(
-                receive[i],
+                pipeline.receiver,
                 Arc::new(
                     move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
-                        setup_recv(port.clone(), sched, core, master)
+                        setup_recv(port.clone(), sched, core, master, Arc::clone(&merged))
                     },
                 ),
             ).expect("Failed to initialize receive side.");
@@ -541,10 +559,10 @@ fn main() {
         // Setup the send side.
         net_context
             .add_pipeline_to_core(
-                senders[i],
+                pipeline.sender,
                 Arc::new(
-                    move |ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
-                        setup_send(&config::ClientConfig::load(), ports, sched, core)
+                    move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
+                        setup_send(&config::ClientConfig::load(), send_port.clone(), sched, core)
                     },
                 ),
             ).expect("Failed to initialize send side.");
@@ -562,4 +580,9 @@ fn main() {
 
     // Stop the client.
     net_context.stop();
+
+    // Write the client's results out for analysis scripts.
+    if let Err(err) = report.lock().unwrap().emit("bad", &config) {
+        warn!("Failed to write out results: {}", err);
+    }
 }
@@ -0,0 +1,393 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::sleep;
+use std::time::Duration;
+
+use db::config;
+use db::log::*;
+
+/// The number of quantiles of it's latency distribution a client reports to the leader. Enough
+/// to merge the 99th percentile across clients to within a tenth of a percentile.
+pub const QUANTILES: usize = 1000;
+
+/// The number of seconds a follower keeps trying to reach the leader for, before giving up.
+const CONNECT_TIMEOUT_S: u64 = 120;
+
+/// The number of seconds the leader waits on a follower's summary once it's own run is done.
+/// Followers run for as long as the leader, so anything longer points at a wedged client.
+const FINISH_TIMEOUT_S: u64 = 60;
+
+/// The results of a single client's run, as reported to the leader.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    /// The number of responses the client received.
+    pub responses: u64,
+
+    /// The throughput in responses per second measured by the client.
+    pub throughput: f64,
+
+    /// The number of latency samples the client took.
+    pub samples: u64,
+
+    /// Evenly spaced quantiles of the client's latency samples in nanoseconds, in ascending
+    /// order. Empty if the client did not take any samples.
+    pub quantiles: Vec<u64>,
+}
+
+// Implementation of methods on Summary.
+impl Summary {
+    /// Encodes the summary into a single line of the protocol.
+    fn encode(&self) -> String {
+        let mut line = format!(
+            "DONE {} {} {}",
+            self.responses, self.throughput, self.samples
+        );
+        for q in self.quantiles.iter() {
+            line.push_str(&format!(" {}", q));
+        }
+        line.push('\n');
+        line
+    }
+
+    /// Decodes a line produced by `encode()`. Returns None if the line is malformed.
+    fn decode(line: &str) -> Option<Summary> {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("DONE") {
+            return None;
+        }
+
+        let responses = words.next()?.parse().ok()?;
+        let throughput = words.next()?.parse().ok()?;
+        let samples = words.next()?.parse().ok()?;
+        let mut quantiles = Vec::new();
+        for word in words {
+            quantiles.push(word.parse().ok()?);
+        }
+
+        Some(Summary {
+            responses: responses,
+            throughput: throughput,
+            samples: samples,
+            quantiles: quantiles,
+        })
+    }
+}
+
+/// The results of a run merged across every client.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Merged {
+    /// The number of clients that took part in the run.
+    pub clients: usize,
+
+    /// The total number of responses received across all clients.
+    pub responses: u64,
+
+    /// The sum of the throughputs measured by every client, in responses per second.
+    pub throughput: f64,
+
+    /// The median and 99th percentile latency across all clients' samples, in nanoseconds.
+    pub median: u64,
+    pub tail: u64,
+}
+
+// Implementation of methods on Merged.
+impl Merged {
+    /// Encodes the merged results into a single line of the protocol.
+    fn encode(&self) -> String {
+        format!(
+            "MERGED {} {} {} {} {}\n",
+            self.clients, self.responses, self.throughput, self.median, self.tail
+        )
+    }
+
+    /// Decodes a line produced by `encode()`. Returns None if the line is malformed.
+    fn decode(line: &str) -> Option<Merged> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.len() != 6 || words[0] != "MERGED" {
+            return None;
+        }
+
+        Some(Merged {
+            clients: words[1].parse().ok()?,
+            responses: words[2].parse().ok()?,
+            throughput: words[3].parse().ok()?,
+            median: words[4].parse().ok()?,
+            tail: words[5].parse().ok()?,
+        })
+    }
+}
+
+/// Merges the summaries of every client's run. Throughputs are summed, since clients run over
+/// the same window. Each client's quantiles stand in for an equal share of it's samples, so
+/// that clients that took more samples weigh more on the merged percentiles.
+pub fn merge(summaries: &[Summary]) -> Merged {
+    let mut weighted: Vec<(u64, f64)> = Vec::new();
+    for summary in summaries.iter() {
+        let n = summary.quantiles.len();
+        for q in summary.quantiles.iter() {
+            weighted.push((*q, summary.samples as f64 / n as f64));
+        }
+    }
+    weighted.sort_by_key(|&(q, _)| q);
+
+    // Walk the merged distribution upto the median and the 99th percentile.
+    let total: f64 = weighted.iter().map(|&(_, w)| w).sum();
+    let (mut median, mut tail) = (None, 0);
+    let mut seen = 0.0;
+    for &(q, w) in weighted.iter() {
+        seen += w;
+        if median.is_none() && seen >= 0.5 * total {
+            median = Some(q);
+        }
+        if seen >= 0.99 * total {
+            tail = q;
+            break;
+        }
+    }
+
+    Merged {
+        clients: summaries.len(),
+        responses: summaries.iter().map(|s| s.responses).sum(),
+        throughput: summaries.iter().map(|s| s.throughput).sum(),
+        median: median.unwrap_or(0),
+        tail: tail,
+    }
+}
+
+/// Coordinates a run across multiple client machines, so that all of them measure over the same
+/// window and their results are reported as one. One client is the leader, and listens on the
+/// coordination address for the others (followers) to connect. The protocol is line based over
+/// TCP:
+///
+/// 1. Every follower connects to the leader and sends "READY".
+/// 2. Once every follower is ready, the leader sends "START" to all of them, and every client
+///    starts sending requests the moment it hands out or receives it.
+/// 3. Once done, every follower sends it's `Summary` ("DONE ...") to the leader.
+/// 4. The leader merges them with it's own, and sends the `Merged` results ("MERGED ...") back
+///    to every follower.
+pub struct Coordinator {
+    // The address the leader listens on.
+    addr: String,
+
+    // The total number of clients taking part in the run, including the leader. Zero on
+    // followers.
+    clients: usize,
+
+    // Connections to every follower on the leader, or to the leader on a follower.
+    peers: Vec<BufReader<TcpStream>>,
+}
+
+// Implementation of methods on Coordinator.
+impl Coordinator {
+    /// Returns a coordinator if the client is configured to run alongside others.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`: Client configuration. `coord_addr` is the address of the leader, and
+    ///             `coord_clients` the number of clients if this one is the leader.
+    pub fn new(config: &config::ClientConfig) -> Option<Coordinator> {
+        if config.coord_addr.is_empty() {
+            return None;
+        }
+
+        Some(Coordinator {
+            addr: config.coord_addr.clone(),
+            clients: if config.coord_leader {
+                config.coord_clients.max(1)
+            } else {
+                0
+            },
+            peers: Vec::new(),
+        })
+    }
+
+    // Returns true if this client is the leader.
+    fn leader(&self) -> bool {
+        self.clients > 0
+    }
+
+    /// Blocks until every client taking part in the run is ready to start sending requests.
+    pub fn start(&mut self) -> Result<()> {
+        if self.leader() {
+            let listener = TcpListener::bind(&self.addr[..])?;
+            while self.peers.len() < self.clients - 1 {
+                let (stream, from) = listener.accept()?;
+                let mut peer = BufReader::new(stream);
+                expect(&mut peer, "READY")?;
+                info!("Client {} ready ({} of {}).", from, self.peers.len() + 2, self.clients);
+                self.peers.push(peer);
+            }
+
+            for peer in self.peers.iter_mut() {
+                peer.get_mut().write_all(b"START\n")?;
+            }
+        } else {
+            let mut stream = self.connect()?;
+            stream.write_all(b"READY\n")?;
+            let mut peer = BufReader::new(stream);
+            expect(&mut peer, "START")?;
+            self.peers.push(peer);
+        }
+
+        Ok(())
+    }
+
+    // Connects to the leader, retrying once a second since it might not be listening yet.
+    fn connect(&self) -> Result<TcpStream> {
+        let mut attempts = 0;
+        loop {
+            match TcpStream::connect(&self.addr[..]) {
+                Ok(stream) => return Ok(stream),
+
+                Err(err) => {
+                    attempts += 1;
+                    if attempts >= CONNECT_TIMEOUT_S {
+                        return Err(err);
+                    }
+                    sleep(Duration::from_secs(1));
+                }
+            }
+        }
+    }
+
+    /// Exchanges the results of the run with the other clients once this one is done.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary`: The results of this client's run.
+    ///
+    /// # Return
+    ///
+    /// The results merged across every client.
+    pub fn finish(&mut self, summary: Summary) -> Result<Merged> {
+        if !self.leader() {
+            let peer = &mut self.peers[0];
+            peer.get_mut().write_all(summary.encode().as_bytes())?;
+            let line = read_line(peer)?;
+            return Merged::decode(&line).ok_or_else(|| malformed(&line));
+        }
+
+        let mut summaries = vec![summary];
+        for peer in self.peers.iter_mut() {
+            peer.get_ref()
+                .set_read_timeout(Some(Duration::from_secs(FINISH_TIMEOUT_S)))?;
+            let line = read_line(peer)?;
+            summaries.push(Summary::decode(&line).ok_or_else(|| malformed(&line))?);
+        }
+
+        let merged = merge(&summaries);
+        for peer in self.peers.iter_mut() {
+            peer.get_mut().write_all(merged.encode().as_bytes())?;
+        }
+        Ok(merged)
+    }
+}
+
+// Reads a line off a peer, failing if the peer disconnected.
+fn read_line(peer: &mut BufReader<TcpStream>) -> Result<String> {
+    let mut line = String::new();
+    if peer.read_line(&mut line)? == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Peer disconnected."));
+    }
+    Ok(line)
+}
+
+// Reads a line off a peer, failing unless it holds exactly `word`.
+fn expect(peer: &mut BufReader<TcpStream>, word: &str) -> Result<()> {
+    let line = read_line(peer)?;
+    if line.trim() != word {
+        return Err(malformed(&line));
+    }
+    Ok(())
+}
+
+// Returns the error for a line that does not follow the protocol.
+fn malformed(line: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Unexpected message from peer: {}", line.trim()),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{merge, Merged, Summary, QUANTILES};
+
+    // Returns `QUANTILES` evenly spaced quantiles of a sorted list of latencies, the way a
+    // client's histogram would.
+    fn quantiles(sorted: &[u64]) -> Vec<u64> {
+        if sorted.is_empty() {
+            return Vec::new();
+        }
+
+        (0..QUANTILES)
+            .map(|i| sorted[(i * sorted.len()) / QUANTILES])
+            .collect()
+    }
+
+    // This test verifies that summaries and merged results survive the protocol.
+    #[test]
+    fn coord_encode() {
+        let summary = Summary {
+            responses: 1000,
+            throughput: 2500.5,
+            samples: 10,
+            quantiles: vec![1, 2, 3],
+        };
+        assert_eq!(Some(summary.clone()), Summary::decode(&summary.encode()));
+        assert_eq!(None, Summary::decode("DONE 1000 x 10"));
+
+        let merged = merge(&[summary]);
+        assert_eq!(Some(merged.clone()), Merged::decode(&merged.encode()));
+        assert_eq!(None, Merged::decode("MERGED 1 2"));
+    }
+
+    // This test verifies that latencies are merged in proportion to the number of samples every
+    // client took, and that throughputs are summed.
+    #[test]
+    fn coord_merge() {
+        let fast: Vec<u64> = (0..1000).map(|i| 1000 + i).collect();
+        let slow: Vec<u64> = (0..1000).map(|i| 5000 + i).collect();
+        assert_eq!(QUANTILES, quantiles(&fast).len());
+        assert!(quantiles(&[]).is_empty());
+
+        let summaries = vec![
+            Summary {
+                responses: 300,
+                throughput: 300.0,
+                samples: 3000,
+                quantiles: quantiles(&fast),
+            },
+            Summary {
+                responses: 100,
+                throughput: 100.0,
+                samples: 1000,
+                quantiles: quantiles(&slow),
+            },
+        ];
+
+        // Three quarters of all samples came off the fast client, so the median lies among it's
+        // samples and the tail among the slow one's.
+        let merged = merge(&summaries);
+        assert_eq!(2, merged.clients);
+        assert_eq!(400, merged.responses);
+        assert_eq!(400.0, merged.throughput);
+        assert_eq!(1666, merged.median);
+        assert_eq!(5959, merged.tail);
+    }
+}
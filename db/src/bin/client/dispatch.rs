@@ -13,23 +13,31 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::net::Ipv4Addr;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 use db::config;
-use db::e2d2::allocators::*;
+use db::cycles;
+use db::crypt::{self, Keys};
 use db::e2d2::common::EmptyMetadata;
 use db::e2d2::headers::*;
 use db::e2d2::interface::*;
 use db::log::*;
-use db::rpc;
+use db::rpc::{self, Reassembler};
+use db::transport::Port;
+use db::wireformat::{
+    request_header_len, response_header_len, OpCode, RpcStatus, TenantAction, FLAG_TRACE,
+};
 
 /// A simple RPC request generator for Sandstorm.
 pub struct Sender {
     // The network interface over which requests will be sent out.
-    net_port: CacheAligned<PortQueue>,
+    net_port: Port,
 
     // The UDP header on each packet generated by the request generator.
     req_udp_header: UdpHeader,
@@ -44,8 +52,30 @@ pub struct Sender {
     // Tracks number of packets sent to the server for occasional debug messages.
     requests_sent: Cell<u64>,
 
-    // The number of destination UDP ports a packet can be sent to.
-    dst_ports: u16,
+    // The number of destination UDP ports a packet can be sent to. Refer to `set_dst_ports()`.
+    dst_ports: Cell<u16>,
+
+    // The priority set on every request sent out. Refer to `set_class()`.
+    priority: Cell<u8>,
+
+    // The deadline in microseconds set on every request sent out. Refer to `set_class()`.
+    deadline: Cell<u32>,
+
+    // Keys of tenants whose request payloads are sealed before they are sent out.
+    keys: Keys,
+
+    // Tenants whose requests are sent to their steered UDP port. Refer to `get_dst_port()`.
+    steered: Vec<u32>,
+
+    // The time in cycles each request waited inside the client between being generated and
+    // being handed to the NIC. Only recorded if enabled. Refer to `track_queueing()`.
+    queued: Option<RefCell<Vec<u64>>>,
+
+    // If true, the stamp on every request is replaced with the time it was sent out at.
+    stamp_on_send: bool,
+
+    // The flags set on every request sent out. Refer to `trace_requests` on the configuration.
+    flags: u8,
 }
 
 impl Sender {
@@ -56,7 +86,8 @@ impl Sender {
     ///
     /// # Arguments
     ///
-    /// * `config`:    Network related configuration such as the MAC and IP address.
+    /// * `config`:    Network related configuration such as the MAC and IP address. Tenant keys
+    ///                are loaded from the key file on this configuration.
     /// * `port`:      Network port on which packets will be sent.
     /// * `dst_ports`: The number of destination UDP ports a packet can be sent to.
     ///
@@ -65,7 +96,7 @@ impl Sender {
     /// A Sender that can be used to send RPC requests to a Sandstorm server.
     pub fn new(
         config: &config::ClientConfig,
-        port: CacheAligned<PortQueue>,
+        port: Port,
         dst_ports: u16,
     ) -> Sender {
         // Create UDP, IP, and MAC headers that are placed on all outgoing packets.
@@ -106,10 +137,78 @@ impl Sender {
             req_ip_header: ip_header,
             req_mac_header: mac_header,
             requests_sent: Cell::new(0),
-            dst_ports: dst_ports,
+            dst_ports: Cell::new(dst_ports),
+            priority: Cell::new(0),
+            deadline: Cell::new(0),
+            keys: crypt::load_keys(&config.key_file, None).expect("Failed to load key file."),
+            steered: config.steered_tenants.clone(),
+            queued: None,
+            stamp_on_send: false,
+            flags: if config.trace_requests { FLAG_TRACE } else { 0 },
         }
     }
 
+    /// Starts recording how long each request sent out after this call waits between being
+    /// generated and being handed to the NIC. At high offered rates, this delay is part of the
+    /// latency measured off a request's stamp, even though it is incurred at the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp_on_send`: If true, the stamp on every request is replaced with the time it was
+    ///                    sent out at, excluding the delay from latencies measured off it. The
+    ///                    class and core sweep bits on the stamp are preserved. Should not be
+    ///                    set if stamps are used to identify requests.
+    #[allow(dead_code)]
+    pub fn track_queueing(&mut self, stamp_on_send: bool) {
+        self.queued = Some(RefCell::new(Vec::new()));
+        self.stamp_on_send = stamp_on_send;
+    }
+
+    /// Returns true if the stamp on every request is replaced with the time it was sent out at.
+    #[allow(dead_code)]
+    pub fn stamps_on_send(&self) -> bool {
+        self.stamp_on_send
+    }
+
+    /// Returns the median and 99th percentile delay in cycles between requests being generated
+    /// and being handed to the NIC, or None if no delays were recorded.
+    #[allow(dead_code)]
+    pub fn queueing(&self) -> Option<(u64, u64)> {
+        let mut delays = self.queued.as_ref()?.borrow_mut();
+        if delays.is_empty() {
+            return None;
+        }
+
+        delays.sort();
+        let n = delays.len();
+        Some((delays[n / 2], delays[(n * 99) / 100]))
+    }
+
+    /// Sets the priority and deadline on all requests sent out after this call.
+    ///
+    /// # Arguments
+    ///
+    /// * `priority`: The priority of the requests. Larger values are more urgent, zero is the
+    ///               default.
+    /// * `deadline`: The time in microseconds after arrival at the server within which the
+    ///               requests should complete. Zero indicates no deadline.
+    #[allow(dead_code)]
+    pub fn set_class(&self, priority: u8, deadline: u32) {
+        self.priority.set(priority);
+        self.deadline.set(deadline);
+    }
+
+    /// Changes the number of destination UDP ports that requests sent out after this call are
+    /// spread across, and hence the number of server cores they are dispatched to.
+    ///
+    /// # Arguments
+    ///
+    /// * `dst_ports`: The number of destination UDP ports. Must be a power of two.
+    #[allow(dead_code)]
+    pub fn set_dst_ports(&self, dst_ports: u16) {
+        self.dst_ports.set(dst_ports);
+    }
+
     /// Creates and sends out a get() RPC request. Network headers are populated based on arguments
     /// passed into new() above.
     ///
@@ -162,6 +261,57 @@ impl Sender {
         self.send_req(request);
     }
 
+    /// Creates and sends out a bulk_put() RPC request. Network headers are populated based on
+    /// arguments passed into new() above. The response carries the number of records applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:      Id of the tenant requesting the insertion.
+    /// * `num_records`: The number of records on `records`.
+    /// * `records`:     Records encoded with `migrate::encode()`. Limit `BULK_PUT_PAYLOAD` bytes.
+    /// * `id`:          RPC identifier.
+    #[allow(dead_code)]
+    pub fn send_bulk_put(&self, tenant: u32, num_records: u32, records: &[u8], id: u64) {
+        let request = rpc::create_bulk_put_rpc(
+            &self.req_mac_header,
+            &self.req_ip_header,
+            &self.req_udp_header,
+            tenant,
+            num_records,
+            records,
+            id,
+            self.get_dst_port(tenant),
+        );
+
+        self.send_req(request);
+    }
+
+    /// Creates and sends out a delete() RPC request. Network headers are populated based on
+    /// arguments passed into new() above. The response's status is `StatusOk` if the key existed,
+    /// and `StatusObjectDoesNotExist` otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Id of the tenant requesting the deletion.
+    /// * `table`:  Id of the table from which the key is to be deleted.
+    /// * `key`:    Byte string of the key to be deleted. Limit 64 KB.
+    /// * `id`:     RPC identifier.
+    #[allow(dead_code)]
+    pub fn send_delete(&self, tenant: u32, table: u64, key: &[u8], id: u64) {
+        let request = rpc::create_delete_rpc(
+            &self.req_mac_header,
+            &self.req_ip_header,
+            &self.req_udp_header,
+            tenant,
+            table,
+            key,
+            id,
+            self.get_dst_port(tenant),
+        );
+
+        self.send_req(request);
+    }
+
     /// Creates and sends out a multiget() RPC request. Network headers are populated based on
     /// arguments passed into new() above.
     ///
@@ -200,6 +350,161 @@ impl Sender {
         self.send_req(request);
     }
 
+    /// Creates and sends out a multiget() RPC request that looks up keys across multiple tables.
+    /// Network headers are populated based on arguments passed into new() above.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Id of the tenant requesting the items.
+    /// * `k_len`:  The length of each key to be looked up at the server. All keys are
+    ///             assumed to be of equal length.
+    /// * `n_keys`: The number of (table, key) pairs to be looked up at the server.
+    /// * `keys`:   Byte string of (table, key) pairs whose values are to be fetched.
+    /// * `id`:     RPC identifier.
+    #[allow(dead_code)]
+    pub fn send_multitable_get(&self, tenant: u32, k_len: u16, n_keys: u32, keys: &[u8], id: u64) {
+        let request = rpc::create_multitable_get_rpc(
+            &self.req_mac_header,
+            &self.req_ip_header,
+            &self.req_udp_header,
+            tenant,
+            k_len,
+            n_keys,
+            keys,
+            id,
+            self.get_dst_port(tenant),
+        );
+
+        self.send_req(request);
+    }
+
+    /// Creates and sends out a list_tables() RPC request. Network headers are populated based on
+    /// arguments passed into new() above.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Id of the tenant whose tables should be listed.
+    /// * `id`:     RPC identifier.
+    #[allow(dead_code)]
+    pub fn send_list_tables(&self, tenant: u32, id: u64) {
+        let request = rpc::create_list_tables_rpc(
+            &self.req_mac_header,
+            &self.req_ip_header,
+            &self.req_udp_header,
+            tenant,
+            id,
+            self.get_dst_port(tenant),
+        );
+
+        self.send_req(request);
+    }
+
+    /// Creates and sends out a move_key() RPC request. Network headers are populated based on
+    /// arguments passed into new() above.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:    Id of the tenant issuing the request.
+    /// * `src_table`: Id of the table the object is currently in.
+    /// * `dst_table`: Id of the table the object should be moved into.
+    /// * `key`:       Byte string of the key of the object to be moved. Limit 64 KB.
+    /// * `id`:        RPC identifier.
+    #[allow(dead_code)]
+    pub fn send_move_key(&self, tenant: u32, src_table: u64, dst_table: u64, key: &[u8], id: u64) {
+        let request = rpc::create_move_key_rpc(
+            &self.req_mac_header,
+            &self.req_ip_header,
+            &self.req_udp_header,
+            tenant,
+            src_table,
+            dst_table,
+            key,
+            id,
+            self.get_dst_port(tenant),
+        );
+
+        self.send_req(request);
+    }
+
+    /// Creates and sends out a server_info() RPC request. Network headers are populated based on
+    /// arguments passed into new() above.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Id of the tenant issuing the request.
+    /// * `id`:     RPC identifier.
+    #[allow(dead_code)]
+    pub fn send_server_info(&self, tenant: u32, id: u64) {
+        let request = rpc::create_server_info_rpc(
+            &self.req_mac_header,
+            &self.req_ip_header,
+            &self.req_udp_header,
+            tenant,
+            id,
+            self.get_dst_port(tenant),
+        );
+
+        self.send_req(request);
+    }
+
+    /// Creates and sends out a get_statistics() RPC request. Network headers are populated based
+    /// on arguments passed into new() above. The statistics on the response can be parsed with
+    /// `rpc::parse_statistics()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Id of the tenant issuing the request.
+    /// * `id`:     RPC identifier.
+    #[allow(dead_code)]
+    pub fn send_get_statistics(&self, tenant: u32, id: u64) {
+        let request = rpc::create_get_statistics_rpc(
+            &self.req_mac_header,
+            &self.req_ip_header,
+            &self.req_udp_header,
+            tenant,
+            id,
+            self.get_dst_port(tenant),
+        );
+
+        self.send_req(request);
+    }
+
+    /// Creates and sends out a tenant_admin() RPC request. Network headers are populated based on
+    /// arguments passed into new() above. The server only accepts the request from it's admin
+    /// tenant.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:   Id of the tenant issuing the request.
+    /// * `target`:   Id of the tenant to be created or deleted, or whose table is to be created or
+    ///               dropped.
+    /// * `action`:   The operation to be performed on the target tenant.
+    /// * `table_id`: Id of the table to be created or dropped, if any.
+    /// * `id`:       RPC identifier.
+    #[allow(dead_code)]
+    pub fn send_tenant_admin(
+        &self,
+        tenant: u32,
+        target: u32,
+        action: TenantAction,
+        table_id: u64,
+        id: u64,
+    ) {
+        let request = rpc::create_tenant_admin_rpc(
+            &self.req_mac_header,
+            &self.req_ip_header,
+            &self.req_udp_header,
+            tenant,
+            target,
+            action,
+            table_id,
+            id,
+            self.get_dst_port(tenant),
+        );
+
+        self.send_req(request);
+    }
+
     /// Creates and sends out an invoke() RPC request. Network headers are populated based on
     /// arguments passed into new() above.
     ///
@@ -230,14 +535,62 @@ impl Sender {
     /// Computes the destination UDP port given a tenant identifier.
     #[inline]
     fn get_dst_port(&self, tenant: u32) -> u16 {
+        // Tenants with a dedicated receive queue on the server have a port of their own.
+        if self.steered.contains(&tenant) {
+            return config::steered_udp_port(tenant);
+        }
+
         // The two least significant bytes of the tenant id % the total number of destination
         // ports.
-        (tenant & 0xffff) as u16 & (self.dst_ports - 1)
+        (tenant & 0xffff) as u16 & (self.dst_ports.get() - 1)
+    }
+
+    /// Seals the payload on a request/packet parsed upto IP if the tenant it was issued by has a
+    /// key, returning the request parsed upto IP with it's length fields fixed up.
+    fn seal_req(
+        &self,
+        request: Packet<IpHeader, EmptyMetadata>,
+    ) -> Packet<IpHeader, EmptyMetadata> {
+        let mut request = request.parse_header::<UdpHeader>();
+
+        let key = self.keys.get(&rpc::parse_rpc_tenant(&request)).cloned();
+        if let Some(key) = key {
+            let hdr_len = request_header_len(&rpc::parse_rpc_opcode(&request));
+            if !crypt::seal_payload(&mut request, hdr_len, &key) {
+                warn!("Failed to seal request payload.");
+            }
+        }
+
+        rpc::fixup_header_length_fields(request)
     }
 
     /// Sends a request/packet parsed upto IP out the network interface.
     #[inline]
-    fn send_req(&self, request: Packet<IpHeader, EmptyMetadata>) {
+    fn send_req(&self, mut request: Packet<IpHeader, EmptyMetadata>) {
+        // Set the priority and deadline if they aren't the defaults.
+        let (priority, deadline) = (self.priority.get(), self.deadline.get());
+        if priority != 0 || deadline != 0 {
+            rpc::set_rpc_class(&mut request, priority, deadline);
+        }
+
+        if self.flags != 0 {
+            rpc::set_rpc_flags(&mut request, self.flags);
+        }
+
+        // The stamp the request was generated with, and the time at which it is being sent out.
+        // The stamp is only replaced before sealing, since the header is authenticated.
+        let generated = rpc::parse_request_stamp(&request);
+        if self.stamp_on_send {
+            let curr = cycles::rdtsc() & !SWEEP_STAMP_MASK;
+            rpc::set_rpc_stamp(&mut request, curr | (generated & SWEEP_STAMP_MASK));
+        }
+
+        // Seal the payload if the tenant has a key. This has to happen last, since the header
+        // is authenticated along with the payload.
+        if !self.keys.is_empty() {
+            request = self.seal_req(request);
+        }
+
         // Send the request out the network.
         unsafe {
             let mut pkts = [request.get_mbuf()];
@@ -251,6 +604,12 @@ impl Sender {
             }
         }
 
+        // Record how long the request waited inside the client before it was handed to the NIC.
+        if let Some(ref queued) = self.queued {
+            let delay = cycles::rdtsc().saturating_sub(generated & !SWEEP_STAMP_MASK);
+            queued.borrow_mut().push(delay);
+        }
+
         // Update the number of requests sent out by this generator.
         let r = self.requests_sent.get();
         if r & 0xffffff == 0 {
@@ -260,6 +619,515 @@ impl Sender {
     }
 }
 
+/// The time requests spent waiting to run and running at the server, as stamped by it on the
+/// responses to requests sent with `trace_requests` configured. Subtracting both from a
+/// request's end-to-end latency leaves the time it spent in the network and the client.
+pub struct StageTimes {
+    // The time in nanoseconds each request spent waiting to run at the server.
+    queued: Vec<u64>,
+
+    // The time in nanoseconds each request spent running at the server.
+    executed: Vec<u64>,
+}
+
+// Implementation of methods on StageTimes.
+#[allow(dead_code)]
+impl StageTimes {
+    /// Returns an empty set of stage times.
+    pub fn new() -> StageTimes {
+        StageTimes {
+            queued: Vec::new(),
+            executed: Vec::new(),
+        }
+    }
+
+    /// Records the stage times on a response. Responses without any are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `response`: The response, parsed upto it's UDP header.
+    pub fn record(&mut self, response: &Packet<UdpHeader, EmptyMetadata>) {
+        let (queued, executed) = rpc::parse_rpc_stage_times(response);
+        if queued == 0 && executed == 0 {
+            return;
+        }
+
+        self.queued.push(queued as u64);
+        self.executed.push(executed as u64);
+    }
+
+    /// Returns the median and 99th percentile time in nanoseconds requests spent waiting to run,
+    /// followed by that for the time they spent running, or None if no responses carried them.
+    pub fn quantiles(&mut self) -> Option<((u64, u64), (u64, u64))> {
+        if self.queued.is_empty() {
+            return None;
+        }
+
+        self.queued.sort();
+        self.executed.sort();
+        let n = self.queued.len();
+        Some((
+            (self.queued[n / 2], self.queued[(n * 99) / 100]),
+            (self.executed[n / 2], self.executed[(n * 99) / 100]),
+        ))
+    }
+}
+
+/// Statistics merged across a set of Receivers, each polling a different receive queue. When
+/// the client NIC spreads responses across multiple queues (RSS), a single receiver caps the
+/// throughput that can be measured; sharing one of these between per-queue receivers allows
+/// throughput to be reported for the client as a whole.
+pub struct RecvStats {
+    // The total number of responses received across all queues.
+    responses: AtomicUsize,
+
+    // The number of receivers sharing these statistics.
+    queues: AtomicUsize,
+
+    // The number of receivers that have stopped receiving.
+    finished: AtomicUsize,
+
+    // The time-stamp in cycles at which the statistics were created, or at which measurement
+    // restarted. Refer to `restart()`.
+    start: AtomicUsize,
+
+    // The time-stamp in cycles at which the last receiver stopped receiving.
+    stop: AtomicUsize,
+
+    // The number of latency samples taken across receivers, and quantiles of them in
+    // nanoseconds. Refer to `set_latencies()`.
+    latencies: Mutex<(u64, Vec<u64>)>,
+}
+
+// Implementation of methods on RecvStats.
+#[allow(dead_code)]
+impl RecvStats {
+    /// Returns an empty set of statistics that can be shared between receivers.
+    pub fn new() -> Arc<RecvStats> {
+        Arc::new(RecvStats {
+            responses: AtomicUsize::new(0),
+            queues: AtomicUsize::new(0),
+            finished: AtomicUsize::new(0),
+            start: AtomicUsize::new(cycles::rdtsc() as usize),
+            stop: AtomicUsize::new(0),
+            latencies: Mutex::new((0, Vec::new())),
+        })
+    }
+
+    /// Marks a receiver as done, once it has received all the responses it expects to.
+    ///
+    /// # Return
+    ///
+    /// True if this was the last of the receivers sharing these statistics to finish. Merged
+    /// statistics are complete only after this returns true.
+    pub fn finish(&self) -> bool {
+        let now = cycles::rdtsc() as usize;
+        let mut stop = self.stop.load(Ordering::Relaxed);
+        while stop < now {
+            match self.stop
+                .compare_exchange_weak(stop, now, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(curr) => stop = curr,
+            }
+        }
+
+        self.finished.fetch_add(1, Ordering::AcqRel) + 1 == self.queues.load(Ordering::Acquire)
+    }
+
+    /// Returns the total number of responses received across all queues.
+    pub fn responses(&self) -> u64 {
+        self.responses.load(Ordering::Relaxed) as u64
+    }
+
+    /// Returns the number of receive queues contributing to these statistics.
+    pub fn queues(&self) -> usize {
+        self.queues.load(Ordering::Relaxed)
+    }
+
+    /// Returns the throughput in responses per second across all queues, measured upto the time
+    /// the last receiver finished.
+    pub fn throughput(&self) -> f64 {
+        let start = self.start.load(Ordering::Relaxed) as u64;
+        let stop = self.stop.load(Ordering::Relaxed) as u64;
+        self.responses() as f64 / cycles::to_seconds(stop.max(start + 1) - start)
+    }
+
+    /// Measures throughput from a later point in time, ex: the end of a warm-up window.
+    /// Responses received before it should not have been counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `start`: The time-stamp in cycles to measure throughput from.
+    pub fn restart(&self, start: u64) {
+        let mut curr = self.start.load(Ordering::Relaxed);
+        while curr < start as usize {
+            match self.start.compare_exchange_weak(
+                curr,
+                start as usize,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(prev) => curr = prev,
+            }
+        }
+    }
+
+    /// Records the latency distribution merged across receivers, so that it can be reported
+    /// alongside the merged throughput.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples`:   The number of latency samples the receivers took.
+    /// * `quantiles`: Evenly spaced quantiles of the samples in nanoseconds, in ascending order.
+    pub fn set_latencies(&self, samples: u64, quantiles: Vec<u64>) {
+        *self.latencies.lock().unwrap() = (samples, quantiles);
+    }
+
+    /// Returns the number of latency samples, and the quantiles set by `set_latencies()`.
+    pub fn latencies(&self) -> (u64, Vec<u64>) {
+        self.latencies.lock().unwrap().clone()
+    }
+}
+
+/// Congestion signals observed by a set of Receivers, shared with the senders whose rate is
+/// adapted to them. A response signals congestion if the server pushed back on it's request
+/// (`StatusThrottled`), or if it arrived later than a timeout after it's request was stamped.
+pub struct Congestion {
+    // The number of responses the server pushed back on.
+    pushbacks: AtomicUsize,
+
+    // The number of responses that arrived later than `timeout`.
+    late: AtomicUsize,
+
+    // The time in cycles after which a response is considered to have timed out.
+    timeout: u64,
+}
+
+// Implementation of methods on Congestion.
+#[allow(dead_code)]
+impl Congestion {
+    /// Returns an empty set of congestion signals that can be shared between receivers and
+    /// senders.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout`: The time in cycles after which a response is considered to have timed out.
+    pub fn new(timeout: u64) -> Arc<Congestion> {
+        Arc::new(Congestion {
+            pushbacks: AtomicUsize::new(0),
+            late: AtomicUsize::new(0),
+            timeout: timeout,
+        })
+    }
+
+    /// Records the congestion signalled by a response, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `response`: The response, parsed upto it's UDP header.
+    /// * `curr`:     The time stamp in cycles at which the response was received.
+    pub fn record(&self, response: &Packet<UdpHeader, EmptyMetadata>, curr: u64) {
+        if response.get_payload()[0] == RpcStatus::StatusThrottled as u8 {
+            self.pushbacks.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let stamp = rpc::parse_rpc_stamp(response) & !SWEEP_STAMP_MASK;
+        if curr.saturating_sub(stamp) > self.timeout {
+            self.late.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the time in cycles after which a response is considered to have timed out.
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+
+    /// Returns the total number of congestion signals observed so far.
+    pub fn signals(&self) -> u64 {
+        (self.pushbacks.load(Ordering::Relaxed) + self.late.load(Ordering::Relaxed)) as u64
+    }
+
+    /// Returns the number of responses pushed back on, and the number that timed out so far.
+    pub fn counts(&self) -> (u64, u64) {
+        (
+            self.pushbacks.load(Ordering::Relaxed) as u64,
+            self.late.load(Ordering::Relaxed) as u64,
+        )
+    }
+}
+
+/// A warm-up window at the start of a run, shared by the senders and receivers of every queue.
+/// Requests flow as usual during the window, but receivers discard the responses to them before
+/// they are counted towards throughput or sampled for latency, and senders do not count them
+/// towards the requests they were asked to send. The window starts once the first request is
+/// sent, and ends for every sender and receiver at once, after either a period of time or a
+/// number of responses across receivers, whichever comes first.
+pub struct Warmup {
+    // The time in cycles the window lasts for. Zero if the window is not bounded in time.
+    duration: u64,
+
+    // The number of responses after which the window ends. Zero if it is not bounded in them.
+    responses: usize,
+
+    // The time-stamp in cycles at which the first request was sent. Zero until then.
+    start: AtomicUsize,
+
+    // The number of responses discarded during the window.
+    discarded: AtomicUsize,
+
+    // The time-stamp in cycles at which the window ended. Zero until then.
+    end: AtomicUsize,
+
+    // Set once the window has ended. Refer to `over()`.
+    done: AtomicBool,
+}
+
+// Implementation of methods on Warmup.
+#[allow(dead_code)]
+impl Warmup {
+    /// Returns a warm-up window that can be shared between senders and receivers.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration`:  The time in cycles the window lasts for. Zero if unbounded.
+    /// * `responses`: The number of responses after which the window ends. Zero if unbounded.
+    pub fn new(duration: u64, responses: usize) -> Arc<Warmup> {
+        Arc::new(Warmup {
+            duration: duration,
+            responses: responses,
+            start: AtomicUsize::new(0),
+            discarded: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+            done: AtomicBool::new(false),
+        })
+    }
+
+    /// Starts the window if it has not started already. Called by senders before every request.
+    pub fn start(&self) {
+        if self.start.load(Ordering::Relaxed) == 0 {
+            let now = cycles::rdtsc() as usize;
+            let _ = self.start
+                .compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns true once the window has ended, ending it if it is due to.
+    pub fn over(&self) -> bool {
+        if self.done.load(Ordering::Acquire) {
+            return true;
+        }
+
+        let now = cycles::rdtsc();
+        let start = self.start.load(Ordering::Relaxed) as u64;
+        let discarded = self.discarded.load(Ordering::Relaxed);
+        let timed = self.duration > 0 && start > 0 && now - start >= self.duration;
+        let counted = self.responses > 0 && discarded >= self.responses;
+        if !timed && !counted {
+            return false;
+        }
+
+        if self.end
+            .compare_exchange(0, now as usize, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            info!("Warm-up over after {} responses.", discarded);
+        }
+        self.done.store(true, Ordering::Release);
+        true
+    }
+
+    /// Decides whether a received response should be discarded.
+    ///
+    /// # Return
+    ///
+    /// True if the response was received during the window. It is counted towards the window,
+    /// and should be dropped.
+    pub fn discard(&self) -> bool {
+        if self.over() {
+            return false;
+        }
+
+        self.discarded.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Returns the time-stamp in cycles at which the window ended, if it has.
+    pub fn ended(&self) -> Option<u64> {
+        if self.over() {
+            Some(self.end.load(Ordering::Relaxed) as u64)
+        } else {
+            None
+        }
+    }
+}
+
+/// The maximum number of phases in a core sweep. The phase a request was sent in is carried in
+/// bits one through three of it's stamp. Refer to `CoreSweep::phase_of()`.
+pub const SWEEP_MAX_PHASES: usize = 8;
+
+/// The bits on a request's stamp that carry it's class and core sweep phase.
+pub const SWEEP_STAMP_MASK: u64 = 0xf;
+
+/// State shared by the senders and receivers of a core sweep. A sweep runs the same offered load
+/// against an increasing number of server cores: 1, 2, 4 etc. upto the number of cores the
+/// server advertises in response to a server_info() RPC. Senders split their requests evenly
+/// across phases, spreading requests in the i'th phase across 2^i destination ports, and tag
+/// every request's stamp with it's phase. Receivers count responses per phase, and merge their
+/// counts in here once they are done, so that results can be reported per core count.
+pub struct CoreSweep {
+    // The number of cores advertised by the server. Zero until a server_info() response arrives.
+    server_cores: AtomicUsize,
+
+    // The number of responses received in each phase across all queues.
+    responses: Vec<AtomicUsize>,
+
+    // The time stamps in cycles of the first and last response received in each phase.
+    first: Vec<AtomicUsize>,
+    last: Vec<AtomicUsize>,
+
+    // The median and 99th percentile latency in cycles of each phase, as measured across
+    // receivers.
+    median: Vec<AtomicUsize>,
+    tail: Vec<AtomicUsize>,
+}
+
+// Implementation of methods on CoreSweep.
+#[allow(dead_code)]
+impl CoreSweep {
+    /// Returns sweep state that can be shared between senders and receivers.
+    pub fn new() -> Arc<CoreSweep> {
+        let counters = || (0..SWEEP_MAX_PHASES).map(|_| AtomicUsize::new(0)).collect();
+
+        Arc::new(CoreSweep {
+            server_cores: AtomicUsize::new(0),
+            responses: counters(),
+            first: (0..SWEEP_MAX_PHASES)
+                .map(|_| AtomicUsize::new(usize::max_value()))
+                .collect(),
+            last: counters(),
+            median: counters(),
+            tail: counters(),
+        })
+    }
+
+    /// Records the number of cores advertised by the server in response to a server_info() RPC.
+    pub fn set_server_cores(&self, cores: u32) {
+        self.server_cores.store(cores as usize, Ordering::Release);
+    }
+
+    /// Returns the number of cores advertised by the server, or zero if it isn't known yet.
+    pub fn server_cores(&self) -> usize {
+        self.server_cores.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of phases in the sweep, one per power of two upto the number of
+    /// cores advertised by the server. Zero if the server hasn't advertised it's cores yet.
+    pub fn phases(&self) -> usize {
+        let mut phases = 0;
+        while phases < SWEEP_MAX_PHASES && (1 << phases) <= self.server_cores() {
+            phases += 1;
+        }
+
+        phases
+    }
+
+    /// Returns the number of server cores requests are sent to in a given phase.
+    pub fn cores(phase: usize) -> u16 {
+        1 << phase
+    }
+
+    /// Returns the phase a request was sent in, given the stamp on it's response.
+    pub fn phase_of(stamp: u64) -> usize {
+        ((stamp >> 1) & 0x7) as usize
+    }
+
+    /// Returns the bits a request sent in a given phase should be tagged with.
+    pub fn tag(phase: usize) -> u64 {
+        (phase as u64 & 0x7) << 1
+    }
+
+    /// Merges a receiver's counts for one phase into these statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `phase`:     The phase the counts were made over.
+    /// * `responses`: The number of responses received in the phase.
+    /// * `first`:     The time stamp in cycles of the first response received in the phase.
+    /// * `last`:      The time stamp in cycles of the last response received in the phase.
+    pub fn merge(&self, phase: usize, responses: u64, first: u64, last: u64) {
+        if responses == 0 {
+            return;
+        }
+
+        self.responses[phase].fetch_add(responses as usize, Ordering::Relaxed);
+
+        let mut curr = self.first[phase].load(Ordering::Relaxed);
+        while (first as usize) < curr {
+            match self.first[phase].compare_exchange_weak(
+                curr,
+                first as usize,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(c) => curr = c,
+            }
+        }
+
+        let mut curr = self.last[phase].load(Ordering::Relaxed);
+        while (last as usize) > curr {
+            match self.last[phase].compare_exchange_weak(
+                curr,
+                last as usize,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(c) => curr = c,
+            }
+        }
+    }
+
+    /// Records the latency measured across receivers over a phase.
+    ///
+    /// # Arguments
+    ///
+    /// * `phase`:  The phase the latencies were measured over.
+    /// * `median`: The median latency in cycles.
+    /// * `tail`:   The 99th percentile latency in cycles.
+    pub fn set_latency(&self, phase: usize, median: u64, tail: u64) {
+        self.median[phase].store(median as usize, Ordering::Relaxed);
+        self.tail[phase].store(tail as usize, Ordering::Relaxed);
+    }
+
+    /// Returns the throughput in responses per second across all queues over a phase, along
+    /// with the median and 99th percentile latency in nanoseconds. Complete only after every
+    /// receiver has merged it's counts in.
+    pub fn results(&self, phase: usize) -> (f64, f64, f64) {
+        let responses = self.responses[phase].load(Ordering::Relaxed) as u64;
+        let first = self.first[phase].load(Ordering::Relaxed) as u64;
+        let last = self.last[phase].load(Ordering::Relaxed) as u64;
+
+        let thrpt = if responses > 1 && last > first {
+            responses as f64 / cycles::to_seconds(last - first)
+        } else {
+            0.0
+        };
+
+        (
+            thrpt,
+            cycles::to_seconds(self.median[phase].load(Ordering::Relaxed) as u64) * 1e9,
+            cycles::to_seconds(self.tail[phase].load(Ordering::Relaxed) as u64) * 1e9,
+        )
+    }
+}
+
+/// The largest number of responses split into fragments that a Receiver reassembles at once.
+const MAX_REASSEMBLING: usize = 1024;
+
 /// A Receiver of responses to RPC requests.
 pub struct Receiver<T>
 where
@@ -273,6 +1141,24 @@ where
 
     // The total number of responses received.
     responses_recv: Cell<u64>,
+
+    // Keys of tenants whose response payloads are sealed, and need to be opened on receipt.
+    keys: Keys,
+
+    // Statistics shared with receivers polling other queues.
+    stats: Arc<RecvStats>,
+
+    // Reassembles responses that were too large for a single packet from their fragments.
+    fragments: RefCell<Reassembler>,
+
+    // If present, congestion signalled by received responses is recorded into this.
+    congestion: Option<Arc<Congestion>>,
+
+    // If present, responses received during this warm-up window are discarded.
+    warmup: Option<Arc<Warmup>>,
+
+    // Set once a response has been received after the warm-up window ended.
+    measuring: Cell<bool>,
 }
 
 // Implementation of methods on Receiver.
@@ -290,13 +1176,73 @@ where
     ///
     /// A Receiver capable of receiving RPC responses over the network.
     pub fn new(port: T) -> Receiver<T> {
+        Receiver::with_keys(port, HashMap::new())
+    }
+
+    /// Constructs a Receiver that opens sealed responses.
+    ///
+    /// # Arguments
+    ///
+    /// * `port`: Network port on which packets will be received.
+    /// * `keys`: Keys of tenants whose responses are sealed. Refer to `crypt::load_keys()`.
+    ///
+    /// # Return
+    ///
+    /// A Receiver capable of receiving RPC responses over the network. Sealed responses are
+    /// opened before they are handed out, and dropped if they cannot be authenticated.
+    pub fn with_keys(port: T, keys: Keys) -> Receiver<T> {
+        Receiver::with_stats(port, keys, RecvStats::new())
+    }
+
+    /// Constructs a Receiver for one of many receive queues, each polled by it's own receiver.
+    ///
+    /// # Arguments
+    ///
+    /// * `port`:  Network port/queue on which packets will be received.
+    /// * `keys`:  Keys of tenants whose responses are sealed. Refer to `crypt::load_keys()`.
+    /// * `stats`: Statistics shared by the receivers of all queues.
+    ///
+    /// # Return
+    ///
+    /// A Receiver capable of receiving RPC responses over the network. Responses received by it
+    /// are counted towards `stats`.
+    pub fn with_stats(port: T, keys: Keys, stats: Arc<RecvStats>) -> Receiver<T> {
+        stats.queues.fetch_add(1, Ordering::AcqRel);
+
         Receiver {
             net_port: port.clone(),
             max_rx_packets: 32,
             responses_recv: Cell::new(0),
+            keys: keys,
+            stats: stats,
+            fragments: RefCell::new(Reassembler::new(MAX_REASSEMBLING)),
+            congestion: None,
+            warmup: None,
+            measuring: Cell::new(false),
         }
     }
 
+    /// Returns the statistics this receiver contributes to.
+    #[allow(dead_code)]
+    pub fn stats(&self) -> &Arc<RecvStats> {
+        &self.stats
+    }
+
+    /// Records congestion signalled by every response received from here on into `congestion`,
+    /// so that senders can adapt their rate to it.
+    #[allow(dead_code)]
+    pub fn set_congestion(&mut self, congestion: Arc<Congestion>) {
+        self.congestion = Some(congestion);
+    }
+
+    /// Discards every response received during a warm-up window, so that they are neither
+    /// handed out nor counted towards `stats()`. Throughput on `stats()` is measured from the
+    /// end of the window.
+    #[allow(dead_code)]
+    pub fn set_warmup(&mut self, warmup: Arc<Warmup>) {
+        self.warmup = Some(warmup);
+    }
+
     /// Receives responses/packets from the network interface.
     #[inline]
     pub fn recv_res(&self) -> Option<Vec<Packet<UdpHeader, EmptyMetadata>>> {
@@ -319,12 +1265,13 @@ where
                 return None;
             }
 
-            // Update the number of responses received.
+            // Update the number of responses received on this queue.
             let r = self.responses_recv.get();
             if r & 0xffffff == 0 {
                 info!("Received many responses...");
             }
             self.responses_recv.set(r + 1);
+            let mut discarded = 0;
 
             // Clear out any dangling pointers in mbuf_vector.
             mbuf_vector.drain(recvd..self.max_rx_packets as usize);
@@ -336,15 +1283,93 @@ where
             // DPDK, and do not need to be bumped up here. Hence, the call to
             // packet_from_mbuf_no_increment().
             for mbuf in mbuf_vector.iter_mut() {
-                let packet = packet_from_mbuf_no_increment(*mbuf, 0)
+                let mut packet = packet_from_mbuf_no_increment(*mbuf, 0)
                     .parse_header::<MacHeader>()
                     .parse_header::<IpHeader>()
                     .parse_header::<UdpHeader>();
 
+                // Responses whose header length does not fit their opcode or the packet are
+                // malformed, and are dropped before they can be parsed.
+                let opcode = rpc::parse_rpc_opcode(&packet);
+                if !rpc::header_len_ok(&packet, response_header_len(&opcode)) {
+                    warn!("Dropping response with a malformed header length.");
+                    packet.free_packet();
+                    continue;
+                }
+
+                // Responses too large for a single packet arrive as fragments, and are only handed
+                // out once every one of them has been received. The reassembled response is
+                // checked just like any other.
+                if opcode == OpCode::SandstormFragment {
+                    packet = match self.fragments.borrow_mut().add(packet) {
+                        Some(packet) => packet,
+                        None => continue,
+                    };
+
+                    let known = response_header_len(&rpc::parse_rpc_opcode(&packet));
+                    if !rpc::header_len_ok(&packet, known) {
+                        warn!("Dropping reassembled response with a malformed header length.");
+                        packet.free_packet();
+                        continue;
+                    }
+                }
+
+                // Open the payload if the tenant has a key. Responses that cannot be
+                // authenticated are dropped.
+                if !self.keys.is_empty() && !self.open_res(&mut packet) {
+                    warn!("Dropping response that could not be authenticated.");
+                    packet.free_packet();
+                    continue;
+                }
+
+                if let Some(ref congestion) = self.congestion {
+                    congestion.record(&packet, cycles::rdtsc());
+                }
+
+                // Responses received during the warm-up window are dropped, except for those to
+                // server_info() probes that a core sweep waits on. Once the window is over,
+                // throughput across queues is measured from the time it ended.
+                if let Some(ref warmup) = self.warmup {
+                    if rpc::parse_rpc_opcode(&packet) != OpCode::SandstormServerInfoRpc {
+                        if warmup.discard() {
+                            packet.free_packet();
+                            discarded += 1;
+                            continue;
+                        }
+
+                        if !self.measuring.get() {
+                            self.measuring.set(true);
+                            self.stats.restart(warmup.ended().unwrap_or(0));
+                        }
+                    }
+                }
+
                 packets.push(packet);
             }
 
+            // Update the number of responses received across queues.
+            self.stats.responses.fetch_add(recvd - discarded, Ordering::Relaxed);
+
             return Some(packets);
         }
     }
+
+    /// Opens the payload on a response parsed upto UDP if the tenant it was sent to has a key.
+    /// Returns false if the payload could not be authenticated.
+    fn open_res(&self, response: &mut Packet<UdpHeader, EmptyMetadata>) -> bool {
+        match self.keys.get(&rpc::parse_rpc_tenant(response)).cloned() {
+            Some(key) => {
+                // The server stamps the times on the header after sealing the response, so they
+                // are cleared while it is being opened.
+                let (queued, executed) = rpc::parse_rpc_stage_times(response);
+                rpc::set_rpc_stage_times(response, 0, 0);
+                let hdr_len = rpc::parse_rpc_header_len(response);
+                let opened = crypt::open_payload(response, hdr_len, &key);
+                rpc::set_rpc_stage_times(response, queued, executed);
+                opened
+            }
+
+            None => true,
+        }
+    }
 }
@@ -0,0 +1,246 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+// The number of bits of a value that are kept when it is recorded. Every power of two is split
+// into 2^(SUB_BUCKET_BITS - 1) buckets, so values are off by less than one part in 128.
+const SUB_BUCKET_BITS: u32 = 8;
+
+// The number of buckets every power of two is split into.
+const SUB_BUCKETS: usize = 1 << (SUB_BUCKET_BITS - 1);
+
+// The number of buckets required to cover every u64. Values below 2^SUB_BUCKET_BITS get a bucket
+// each, and every power of two above that gets SUB_BUCKETS of them.
+const NUM_BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 2) * SUB_BUCKETS;
+
+/// The percentiles reported by `Histogram::percentiles()`: p50, p90, p99 and p999.
+pub const PERCENTILES: [f64; 4] = [0.5, 0.9, 0.99, 0.999];
+
+/// A histogram of latencies with buckets that grow logarithmically, in the style of an HDR
+/// histogram. Memory is fixed no matter how many values are recorded, so a run can go on for as
+/// long as required, and histograms recorded on different cores can be merged into one.
+#[derive(Clone)]
+pub struct Histogram {
+    // The number of values recorded in every bucket. Refer to `bucket()`.
+    counts: Vec<u64>,
+
+    // The number of values recorded.
+    total: u64,
+
+    // The smallest and largest values recorded. Percentiles are clamped to these.
+    min: u64,
+    max: u64,
+}
+
+// Implementation of methods on Histogram.
+#[allow(dead_code)]
+impl Histogram {
+    /// Returns an empty histogram.
+    pub fn new() -> Histogram {
+        Histogram {
+            counts: vec![0; NUM_BUCKETS],
+            total: 0,
+            min: u64::max_value(),
+            max: 0,
+        }
+    }
+
+    /// Records a value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value`: The value, ex: a latency in cycles.
+    #[inline]
+    pub fn record(&mut self, value: u64) {
+        self.counts[bucket(value)] += 1;
+        self.total += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Adds every value recorded on another histogram to this one.
+    ///
+    /// # Arguments
+    ///
+    /// * `other`: The histogram to be merged into this one.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, add) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += *add;
+        }
+
+        self.total += other.total;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Returns the number of values recorded.
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns true if no values were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Returns the value at a quantile, i.e, the smallest value that a given fraction of all
+    /// recorded values are less than or equal to, to within the precision of a bucket. Zero if
+    /// no values were recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `quantile`: The quantile, between 0 and 1. Ex: 0.99 for the 99th percentile.
+    pub fn value_at(&self, quantile: f64) -> u64 {
+        let rank = (quantile * self.total as f64).ceil() as u64;
+        self.value_at_rank(rank)
+    }
+
+    /// Returns the median, 90th, 99th and 99.9th percentile, in that order. Refer to
+    /// `PERCENTILES`.
+    pub fn percentiles(&self) -> Vec<u64> {
+        PERCENTILES.iter().map(|q| self.value_at(*q)).collect()
+    }
+
+    /// Returns `n` evenly spaced quantiles of the recorded values in ascending order, so that the
+    /// distribution can be shipped elsewhere without shipping the histogram. Empty if no values
+    /// were recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `n`: The number of quantiles.
+    pub fn quantiles(&self, n: usize) -> Vec<u64> {
+        if self.total == 0 {
+            return Vec::new();
+        }
+
+        (0..n as u64)
+            .map(|i| self.value_at_rank((i * self.total) / n as u64 + 1))
+            .collect()
+    }
+
+    // Returns the value of the `rank`th smallest recorded value (starting at one), to within the
+    // precision of a bucket. Ranks outside the recorded values are clamped to them.
+    fn value_at_rank(&self, rank: u64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+
+        let rank = rank.max(1).min(self.total);
+        let mut seen = 0;
+        for (idx, count) in self.counts.iter().enumerate() {
+            seen += *count;
+            if seen >= rank {
+                return highest(idx).max(self.min).min(self.max);
+            }
+        }
+
+        self.max
+    }
+}
+
+// Returns the bucket a value is recorded in. Values below 2^SUB_BUCKET_BITS are recorded exactly.
+// Larger values keep only their SUB_BUCKET_BITS most significant bits, and are recorded in the
+// bucket of those bits inside the group of buckets for their power of two.
+#[inline]
+fn bucket(value: u64) -> usize {
+    let msb = 63 - (value | 1).leading_zeros();
+    if msb < SUB_BUCKET_BITS {
+        return value as usize;
+    }
+
+    let shift = msb - SUB_BUCKET_BITS + 1;
+    (shift as usize) * SUB_BUCKETS + (value >> shift) as usize
+}
+
+// Returns the largest value that is recorded in a bucket.
+fn highest(idx: usize) -> u64 {
+    if idx < 2 * SUB_BUCKETS {
+        return idx as u64;
+    }
+
+    let shift = idx / SUB_BUCKETS - 1;
+    let sub = (idx - shift * SUB_BUCKETS) as u64;
+    ((sub + 1) << shift).wrapping_sub(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bucket, highest, Histogram, NUM_BUCKETS};
+
+    // Tests that every value lands in a bucket whose range covers it, and that buckets are off
+    // by less than one part in 128.
+    #[test]
+    fn histogram_buckets() {
+        let mut values: Vec<u64> = (0..1024).collect();
+        values.extend((10..64).map(|s| (1u64 << s) + 12345));
+        values.push(u64::max_value());
+
+        for value in values {
+            let idx = bucket(value);
+            assert!(idx < NUM_BUCKETS);
+            assert!(highest(idx) >= value);
+            assert!(highest(idx) - value <= value / 128);
+            assert!(idx == 0 || highest(idx - 1) < value);
+        }
+
+        assert_eq!(NUM_BUCKETS - 1, bucket(u64::max_value()));
+        assert_eq!(u64::max_value(), highest(NUM_BUCKETS - 1));
+    }
+
+    // Tests that percentiles are exact for small values, and within a bucket for large ones.
+    #[test]
+    fn histogram_percentiles() {
+        let mut hist = Histogram::new();
+        assert!(hist.is_empty());
+        assert_eq!(0, hist.value_at(0.5));
+        assert!(hist.quantiles(10).is_empty());
+
+        for value in 1..201 {
+            hist.record(value);
+        }
+        assert_eq!(vec![100, 180, 198, 200], hist.percentiles());
+        assert_eq!(vec![1, 41, 81, 121, 161], hist.quantiles(5));
+        assert_eq!(1, hist.value_at(0.0));
+        assert_eq!(200, hist.value_at(1.0));
+
+        hist.record(1000000);
+        assert_eq!(201, hist.count());
+        assert_eq!(1000000, hist.value_at(1.0));
+        let p = hist.value_at(0.999);
+        assert!(p >= 1000000 - 1000000 / 128 && p <= 1000000);
+    }
+
+    // Tests that a merged histogram matches one that recorded every value itself.
+    #[test]
+    fn histogram_merge() {
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+        let mut all = Histogram::new();
+        for value in 0..5000 {
+            let value = value * 37;
+            if value % 3 == 0 {
+                a.record(value);
+            } else {
+                b.record(value);
+            }
+            all.record(value);
+        }
+
+        a.merge(&b);
+        a.merge(&Histogram::new());
+        assert_eq!(all.count(), a.count());
+        assert_eq!(all.percentiles(), a.percentiles());
+        assert_eq!(all.quantiles(100), a.quantiles(100));
+    }
+}
@@ -0,0 +1,771 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+#![feature(use_extern_macros)]
+
+extern crate db;
+extern crate rand;
+
+mod dispatch;
+mod histogram;
+mod report;
+mod setup;
+
+use std::fmt::Display;
+use std::mem::transmute;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use db::config;
+use db::cycles;
+use db::e2d2::common::EmptyMetadata;
+use db::e2d2::headers::UdpHeader;
+use db::e2d2::interface::*;
+use db::e2d2::scheduler::*;
+use db::log::*;
+use db::migrate;
+use db::rpc::*;
+use db::transport::Port;
+use db::wireformat::*;
+
+// The table that records are loaded into. Matches the table that the YCSB client issues requests
+// against.
+const LOAD_TABLE: u64 = 1;
+
+// If no responses are received for this many seconds while requests are outstanding, the
+// outstanding requests are assumed to be lost. Responses to them that arrive later are dropped.
+const STALL_TIMEOUT: f64 = 0.5;
+
+/// State shared between a LoaderSend and it's corresponding LoaderRecv.
+struct Progress {
+    // The number of requests issued so far by the sender. Used by the receiver to tell how many
+    // requests are outstanding.
+    sent: AtomicUsize,
+
+    // The number of responses received so far by the receiver. Used by the sender to limit the
+    // number of outstanding requests.
+    acked: AtomicUsize,
+
+    // The number of responses that the receiver has given up waiting for. Responses to these
+    // requests are never counted in `acked`, refer to `LoaderRecv::expire()`.
+    lost: AtomicUsize,
+}
+
+/// Returns the number of records loaded by each bulk_put() request, or zero if a single record
+/// is too large to fit on a request.
+///
+/// # Arguments
+///
+/// * `key_len`:   The length of every key in bytes.
+/// * `value_len`: The length of every value in bytes.
+fn batch_len(key_len: usize, value_len: usize) -> usize {
+    BULK_PUT_PAYLOAD / (BULK_PUT_RECORD_HEADER_LEN + key_len + value_len)
+}
+
+/// Returns the range of keys `[first, last)` owned by a particular sender. Keys start at 1 to
+/// match the records created by the server's `fill_test()`.
+///
+/// # Arguments
+///
+/// * `n_keys`: The total number of keys to be loaded per tenant.
+/// * `part`:   The partition (sender) whose range should be computed.
+/// * `parts`:  The total number of partitions (senders).
+fn partition(n_keys: usize, part: usize, parts: usize) -> (usize, usize) {
+    let per = n_keys / parts;
+    let first = 1 + part * per;
+    let last = if part == parts - 1 {
+        n_keys + 1
+    } else {
+        first + per
+    };
+
+    (first, last)
+}
+
+/// Writes a key and it's corresponding value into the supplied buffers. The key and value both
+/// start with the key's index, and are zero everywhere else.
+fn fill_record(idx: u32, key: &mut [u8], val: &mut [u8]) {
+    let temp: [u8; 4] = unsafe { transmute(idx.to_le()) };
+    key[0..4].copy_from_slice(&temp);
+    val[0..4].copy_from_slice(&temp);
+}
+
+/// Send side of the loader. Issues bulk_put() requests for every key in it's range, for every
+/// tenant, followed by get() requests for a sample of these keys so that the load can be verified.
+///
+/// Records are loaded in batches of consecutive keys, as many as fit on a single request. Each
+/// batch belongs to a single tenant, and is authenticated like any other request of it's tenant.
+struct LoaderSend {
+    // RPC request generator required to send RPC requests to a Sandstorm server.
+    sender: dispatch::Sender,
+
+    // The range of keys `[first, last)` that this sender must load.
+    first: usize,
+    last: usize,
+
+    // The total number of tenants to load records for. Tenant ids start at 1.
+    tenants: u32,
+
+    // The tenant and key that the next request will be issued for.
+    tenant: u32,
+    key: usize,
+
+    // One out of every `verify` keys is read back once all puts have been issued. Zero disables
+    // verification.
+    verify: usize,
+
+    // True once all puts have been issued, and the sender has moved on to verification.
+    verifying: bool,
+
+    // The number of records loaded by each bulk_put() request. Refer to `batch_len()`.
+    batch: usize,
+
+    // The maximum number of requests that can be outstanding at the server at any point of time.
+    window: usize,
+
+    // The total number of requests issued so far.
+    sent: usize,
+
+    // Buffers for the key and value on the next record, and the records on the next request.
+    key_buf: Vec<u8>,
+    val_buf: Vec<u8>,
+    records: Vec<u8>,
+
+    // State shared with the receiver.
+    progress: Arc<Progress>,
+}
+
+// Implementation of methods on LoaderSend.
+impl LoaderSend {
+    /// Constructs a LoaderSend.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`:   Client configuration with the number of keys, tenants etc.
+    /// * `port`:     Network port over which requests will be sent out.
+    /// * `part`:     The partition of the key space this sender is responsible for.
+    /// * `progress`: State shared with this sender's receiver.
+    ///
+    /// # Return
+    ///
+    /// A loader that issues bulk_put() requests for it's partition of the key space.
+    fn new(
+        config: &config::ClientConfig,
+        port: Port,
+        part: usize,
+        progress: Arc<Progress>,
+    ) -> LoaderSend {
+        let (first, last) = partition(config.n_keys, part, config.pipelines().len());
+
+        LoaderSend {
+            sender: dispatch::Sender::new(config, port, config.server_udp_ports as u16),
+            first: first,
+            last: last,
+            tenants: config.num_tenants,
+            tenant: 1,
+            key: first,
+            verify: config.load_verify,
+            verifying: false,
+            batch: batch_len(config.key_len, config.value_len),
+            window: config.load_window,
+            sent: 0,
+            key_buf: vec![0; config.key_len],
+            val_buf: vec![0; config.value_len],
+            records: Vec::with_capacity(BULK_PUT_PAYLOAD),
+            progress: progress,
+        }
+    }
+
+    /// Advances the sender to the (tenant, key) pair that the next request starts at.
+    ///
+    /// # Return
+    ///
+    /// False if there are no more requests to be issued by this sender.
+    fn advance(&mut self) -> bool {
+        // During verification, skip over keys that are not part of the sample. Otherwise, skip
+        // over the batch that was just issued.
+        let step = if self.verifying { self.verify } else { self.batch };
+
+        self.key += step;
+        if self.key < self.last {
+            return true;
+        }
+
+        self.key = self.first;
+        self.tenant += 1;
+        if self.tenant <= self.tenants {
+            return true;
+        }
+
+        // All puts have been issued. Move on to verification if required.
+        if !self.verifying && self.verify > 0 {
+            self.verifying = true;
+            self.tenant = 1;
+            return true;
+        }
+
+        false
+    }
+}
+
+// The Executable trait allowing LoaderSend to be scheduled by Netbricks.
+impl Executable for LoaderSend {
+    // Called internally by Netbricks.
+    fn execute(&mut self) {
+        // Return if all requests have been issued.
+        if self.tenant > self.tenants || self.first >= self.last {
+            return;
+        }
+
+        // Do not issue a request if the window of outstanding requests is full. The receiver
+        // reopens it if the outstanding requests are lost.
+        let acked = self.progress.acked.load(Ordering::Relaxed)
+            + self.progress.lost.load(Ordering::Relaxed);
+        if self.sent.saturating_sub(acked) >= self.window {
+            return;
+        }
+
+        let curr = cycles::rdtsc();
+        if self.verifying {
+            fill_record(self.key as u32, &mut self.key_buf, &mut self.val_buf);
+            self.sender
+                .send_get(self.tenant, LOAD_TABLE, &self.key_buf, curr);
+        } else {
+            // Batches end at the last key in the range, so that they never span tenants.
+            let end = self.last.min(self.key + self.batch);
+            self.records.clear();
+            for key in self.key..end {
+                fill_record(key as u32, &mut self.key_buf, &mut self.val_buf);
+                let val = Some(&self.val_buf[..]);
+                migrate::encode(&mut self.records, LOAD_TABLE, &self.key_buf, val);
+            }
+
+            let num_records = (end - self.key) as u32;
+            self.sender
+                .send_bulk_put(self.tenant, num_records, &self.records, curr);
+        }
+
+        // Publish the request only after it is stamped, so that every request counted by the
+        // receiver carries a stamp taken before it read the count. Refer to `LoaderRecv::expire()`.
+        self.sent += 1;
+        self.progress.sent.store(self.sent, Ordering::Release);
+        if !self.advance() {
+            // Mark this sender as done.
+            self.tenant = self.tenants + 1;
+            info!(
+                "Loader issued all {} requests for keys [{}, {}).",
+                self.sent, self.first, self.last
+            );
+        }
+    }
+
+    fn dependencies(&mut self) -> Vec<usize> {
+        vec![]
+    }
+}
+
+/// Receive side of the loader. Tracks the progress of the load, and verifies the responses to
+/// get() requests issued by LoaderSend.
+struct LoaderRecv<T>
+where
+    T: PacketTx + PacketRx + Display + Clone + 'static,
+{
+    // The network stack required to receives RPC response packets from a network port.
+    receiver: dispatch::Receiver<T>,
+
+    // The total number of responses expected by this receiver.
+    expected: usize,
+
+    // The number of responses received so far.
+    recvd: usize,
+
+    // The number of records the bulk_put() responses report as applied, and the number of
+    // bulk_put() responses that did not succeed. The records following the applied ones on a
+    // failed request were not loaded.
+    loaded: usize,
+    failed_puts: usize,
+
+    // The number of get() responses that were checked, and the number that failed verification.
+    verified: usize,
+    mismatched: usize,
+
+    // Buffers for the expected key and value on a verified record.
+    key_buf: Vec<u8>,
+    val_buf: Vec<u8>,
+
+    // State shared with the sender.
+    progress: Arc<Progress>,
+
+    // Incremented by this receiver once all it's responses have been received or given up on.
+    done: Arc<AtomicUsize>,
+
+    // Time stamp in cycles at which the last response was received, or at which outstanding
+    // requests were last given up on.
+    last_recv: u64,
+
+    // Responses to requests stamped before this time stamp in cycles are dropped, since the
+    // requests were counted as lost.
+    cutoff: u64,
+
+    // True once this receiver has incremented `done`.
+    finished: bool,
+
+    // Time stamp in cycles at which the load started.
+    start: u64,
+
+    // Results merged across the receivers of all queues, and written out once the load is done.
+    report: Arc<Mutex<report::Report>>,
+}
+
+// Implementation of methods on LoaderRecv.
+impl<T> LoaderRecv<T>
+where
+    T: PacketTx + PacketRx + Display + Clone + 'static,
+{
+    /// Constructs a LoaderRecv.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`:   Client configuration with the number of keys, tenants etc.
+    /// * `port`:     Network port on which responses will be polled for.
+    /// * `part`:     The partition of the key space this receiver's sender is responsible for.
+    /// * `progress`: State shared with this receiver's sender.
+    /// * `done`:     Counter incremented once this receiver is done.
+    /// * `report`:   Results merged across the receivers of all queues.
+    ///
+    /// # Return
+    ///
+    /// A receiver that reports the progress of the load and verifies sampled records.
+    fn new(
+        config: &config::ClientConfig,
+        port: T,
+        part: usize,
+        progress: Arc<Progress>,
+        done: Arc<AtomicUsize>,
+        report: Arc<Mutex<report::Report>>,
+    ) -> LoaderRecv<T> {
+        let (first, last) = partition(config.n_keys, part, config.pipelines().len());
+        let keys = last - first;
+        let batch = batch_len(config.key_len, config.value_len);
+        let mut expected = (keys + batch - 1) / batch;
+        if config.load_verify > 0 {
+            expected += (keys + config.load_verify - 1) / config.load_verify;
+        }
+
+        LoaderRecv {
+            receiver: dispatch::Receiver::new(port),
+            expected: expected * config.num_tenants as usize,
+            recvd: 0,
+            loaded: 0,
+            failed_puts: 0,
+            verified: 0,
+            mismatched: 0,
+            key_buf: vec![0; config.key_len],
+            val_buf: vec![0; config.value_len],
+            progress: progress,
+            done: done,
+            last_recv: cycles::rdtsc(),
+            cutoff: 0,
+            finished: false,
+            start: cycles::rdtsc(),
+            report: report,
+        }
+    }
+
+    /// Checks if the value on a get() response matches what was loaded for it's key.
+    fn verify(&mut self, packet: Packet<UdpHeader, EmptyMetadata>) {
+        let p = packet.parse_header::<GetResponse>();
+        self.verified += 1;
+
+        let mut valid = p.get_header().common_header.status == RpcStatus::StatusOk;
+        if valid {
+            let value = p.get_payload();
+            if value.len() < 4 {
+                valid = false;
+            } else {
+                let mut idx: [u8; 4] = [0; 4];
+                idx.copy_from_slice(&value[0..4]);
+                let idx = u32::from_le(unsafe { transmute(idx) });
+                fill_record(idx, &mut self.key_buf, &mut self.val_buf);
+                valid = value == &self.val_buf[..];
+            }
+        }
+
+        if !valid {
+            self.mismatched += 1;
+        }
+
+        p.free_packet();
+    }
+
+    /// Gives up on the outstanding requests if no responses were received for `STALL_TIMEOUT`
+    /// seconds. The count of requests issued is read before the cut-off is stamped, so every
+    /// request it covers was stamped before the cut-off; responses to them that arrive later
+    /// are dropped instead of being counted a second time. Requests issued after the count was
+    /// read may be stamped before the cut-off too, in which case their responses are dropped,
+    /// and they are given up on by the next call.
+    fn expire(&mut self) {
+        let now = cycles::rdtsc();
+        if cycles::to_seconds(now.saturating_sub(self.last_recv)) <= STALL_TIMEOUT {
+            return;
+        }
+
+        let sent = self.progress.sent.load(Ordering::Acquire);
+        let lost = self.progress.lost.load(Ordering::Relaxed);
+        let outstanding = sent.saturating_sub(self.recvd + lost);
+        if outstanding == 0 {
+            return;
+        }
+
+        warn!("No responses for {} outstanding requests.", outstanding);
+        self.cutoff = cycles::rdtsc();
+        self.last_recv = self.cutoff;
+        self.progress.lost.fetch_add(outstanding, Ordering::Relaxed);
+    }
+}
+
+// Implementation of the `Drop` trait on LoaderRecv.
+impl<T> Drop for LoaderRecv<T>
+where
+    T: PacketTx + PacketRx + Display + Clone + 'static,
+{
+    fn drop(&mut self) {
+        println!(
+            "Loader: {} responses, {} lost, {} records loaded, {} failed bulk puts, {} of {} \
+             verified records mismatched",
+            self.recvd,
+            self.progress.lost.load(Ordering::Relaxed),
+            self.loaded,
+            self.failed_puts,
+            self.mismatched,
+            self.verified
+        );
+    }
+}
+
+// Executable trait allowing LoaderRecv to be scheduled by Netbricks.
+impl<T> Executable for LoaderRecv<T>
+where
+    T: PacketTx + PacketRx + Display + Clone + 'static,
+{
+    // Called internally by Netbricks.
+    fn execute(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        if let Some(mut packets) = self.receiver.recv_res() {
+            while let Some(packet) = packets.pop() {
+                // Requests given up on were already counted.
+                if parse_rpc_stamp(&packet) < self.cutoff {
+                    packet.free_packet();
+                    continue;
+                }
+
+                self.recvd += 1;
+                self.last_recv = cycles::rdtsc();
+
+                match parse_rpc_opcode(&packet) {
+                    OpCode::SandstormBulkPutRpc => {
+                        let p = packet.parse_header::<BulkPutResponse>();
+                        self.loaded += p.get_header().num_records as usize;
+                        if p.get_header().common_header.status != RpcStatus::StatusOk {
+                            self.failed_puts += 1;
+                        }
+                        p.free_packet();
+                    }
+
+                    OpCode::SandstormGetRpc => self.verify(packet),
+
+                    _ => packet.free_packet(),
+                }
+
+                // Report progress every million responses.
+                if self.recvd % (1000 * 1000) == 0 {
+                    info!(
+                        "Loaded {:.1}% at {:.0} K/s",
+                        (self.recvd as f64 * 100.0) / self.expected as f64,
+                        (self.loaded as f64 / 1e3)
+                            / cycles::to_seconds(cycles::rdtsc() - self.start)
+                    );
+                }
+            }
+
+            self.progress.acked.store(self.recvd, Ordering::Relaxed);
+        }
+
+        self.expire();
+
+        // Let main know once all responses have been received, after merging this receiver's
+        // counts into the loader's results.
+        let lost = self.progress.lost.load(Ordering::Relaxed);
+        if self.recvd + lost >= self.expected {
+            {
+                let elapsed = cycles::to_seconds(cycles::rdtsc() - self.start);
+                let mut report = self.report.lock().unwrap();
+                report.record(self.recvd as u64, self.recvd as f64 / elapsed);
+                report.lost(lost as u64);
+                report.count("loaded", self.loaded as u64);
+                report.count("failed_puts", self.failed_puts as u64);
+                report.count("verified", self.verified as u64);
+                report.count("mismatched", self.mismatched as u64);
+            }
+
+            self.finished = true;
+            self.done.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn dependencies(&mut self) -> Vec<usize> {
+        vec![]
+    }
+}
+
+/// Sets up LoaderSend by adding it to a Netbricks scheduler.
+///
+/// # Arguments
+///
+/// * `config`:    Network related configuration such as the MAC and IP address.
+/// * `ports`:     Network port on which packets will be sent.
+/// * `scheduler`: Netbricks scheduler to which LoaderSend will be added.
+/// * `part`:      The partition of the key space the sender is responsible for.
+/// * `progress`:  State shared between the sender and it's receiver.
+fn setup_send<S>(
+    config: &config::ClientConfig,
+    ports: Vec<Port>,
+    scheduler: &mut S,
+    part: usize,
+    progress: Arc<Progress>,
+) where
+    S: Scheduler + Sized,
+{
+    if ports.len() != 1 {
+        error!("Client should be configured with exactly 1 port!");
+        std::process::exit(1);
+    }
+
+    // Add the sender to a netbricks pipeline.
+    match scheduler.add_task(LoaderSend::new(config, ports[0].clone(), part, progress)) {
+        Ok(_) => {
+            info!(
+                "Successfully added LoaderSend with tx queue {}.",
+                ports[0].txq()
+            );
+        }
+
+        Err(ref err) => {
+            error!("Error while adding to Netbricks pipeline {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Sets up LoaderRecv by adding it to a Netbricks scheduler.
+///
+/// # Arguments
+///
+/// * `config`:    Client configuration with the number of keys, tenants etc.
+/// * `ports`:     Network port on which packets will be received.
+/// * `scheduler`: Netbricks scheduler to which LoaderRecv will be added.
+/// * `part`:      The partition of the key space the receiver's sender is responsible for.
+/// * `progress`:  State shared between the receiver and it's sender.
+/// * `done`:      Counter incremented once the receiver is done.
+/// * `report`:    Results merged across the receivers of all queues.
+fn setup_recv<S>(
+    config: &config::ClientConfig,
+    ports: Vec<Port>,
+    scheduler: &mut S,
+    part: usize,
+    progress: Arc<Progress>,
+    done: Arc<AtomicUsize>,
+    report: Arc<Mutex<report::Report>>,
+) where
+    S: Scheduler + Sized,
+{
+    if ports.len() != 1 {
+        error!("Client should be configured with exactly 1 port!");
+        std::process::exit(1);
+    }
+
+    // Add the receiver to a netbricks pipeline.
+    match scheduler.add_task(LoaderRecv::new(
+        config,
+        ports[0].clone(),
+        part,
+        progress,
+        done,
+        report,
+    )) {
+        Ok(_) => {
+            info!(
+                "Successfully added LoaderRecv with rx queue {}.",
+                ports[0].rxq()
+            );
+        }
+
+        Err(ref err) => {
+            error!("Error while adding to Netbricks pipeline {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Preloads `n_keys` records for each of `num_tenants` tenants into table 1 on the server. The
+/// server is expected to have created these tenants and tables with `num_records` set to zero.
+fn main() {
+    db::env_logger::init().expect("ERROR: failed to initialize logger!");
+
+    let config = config::ClientConfig::load();
+    info!("Starting up Sandstorm loader with config {:?}", config);
+    report::set_seed(&config);
+
+    if config.load_window == 0 {
+        error!("load_window must be greater than zero!");
+        std::process::exit(1);
+    }
+
+    if batch_len(config.key_len, config.value_len) == 0 {
+        error!("Records are too large to be loaded with bulk_put()!");
+        std::process::exit(1);
+    }
+
+    // Setup Netbricks.
+    let mut net_context = setup::config_and_init_netbricks(&config);
+
+    // Setup the client pipeline.
+    net_context.start_schedulers();
+
+    // The cores which will run the sender and receiver threads, and the queues they share.
+    let pipelines = setup::pipelines(&config, &net_context);
+    let num_pairs = pipelines.len();
+
+    // Counter of receivers that have received all their responses.
+    let done = Arc::new(AtomicUsize::new(0));
+
+    // Receivers on every queue merge their results into this once they are done.
+    let report = report::Report::new();
+
+    // Setup the senders and receivers. Each pipeline loads a disjoint range of keys.
+    for (i, pipeline) in pipelines.into_iter().enumerate() {
+        // First, retrieve the tx-rx queue pair shared by the pipeline.
+        let port = pipeline.port;
+        let send_port = port.clone();
+
+        let progress = Arc::new(Progress {
+            sent: AtomicUsize::new(0),
+            acked: AtomicUsize::new(0),
+            lost: AtomicUsize::new(0),
+        });
+
+        // Setup the receive side.
+        let p = progress.clone();
+        let d = done.clone();
+        let r = report.clone();
+        net_context
+            .add_pipeline_to_core(
+                pipeline.receiver,
+                Arc::new(
+                    move |_ports, sched: &mut StandaloneScheduler, _core: i32, _sibling| {
+                        setup_recv(
+                            &config::ClientConfig::load(),
+                            port.clone(),
+                            sched,
+                            i,
+                            p.clone(),
+                            d.clone(),
+                            r.clone(),
+                        )
+                    },
+                ),
+            ).expect("Failed to initialize receive side.");
+
+        // Setup the send side.
+        net_context
+            .add_pipeline_to_core(
+                pipeline.sender,
+                Arc::new(
+                    move |_ports, sched: &mut StandaloneScheduler, _core: i32, _sibling| {
+                        setup_send(
+                            &config::ClientConfig::load(),
+                            send_port.clone(),
+                            sched,
+                            i,
+                            progress.clone(),
+                        )
+                    },
+                ),
+            ).expect("Failed to initialize send side.");
+    }
+
+    // Allow the system to bootup fully.
+    std::thread::sleep(std::time::Duration::from_secs(1));
+
+    // Run the loader.
+    net_context.execute();
+
+    // Wait for all receivers to finish, and then shutdown the loader.
+    while done.load(Ordering::Relaxed) < num_pairs {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
+
+    // Stop the loader.
+    net_context.stop();
+
+    // Write the loader's results out for analysis scripts.
+    if let Err(err) = report.lock().unwrap().emit("loader", &config) {
+        warn!("Failed to write out results: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{batch_len, partition};
+    use db::migrate;
+    use db::wireformat::BULK_PUT_PAYLOAD;
+
+    // Tests that the key space is fully covered by disjoint partitions.
+    #[test]
+    fn partition_covers_keys() {
+        let n_keys = 1003;
+        let parts = 4;
+
+        let mut next = 1;
+        for part in 0..parts {
+            let (first, last) = partition(n_keys, part, parts);
+            assert_eq!(next, first);
+            next = last;
+        }
+
+        assert_eq!(n_keys + 1, next);
+    }
+
+    // Tests that a full batch of records fits on a bulk_put() request, and that one more does not.
+    #[test]
+    fn batch_fits_payload() {
+        let (key, val) = (vec![0; 30], vec![0; 100]);
+        let batch = batch_len(key.len(), val.len());
+
+        let mut records = Vec::new();
+        for _ in 0..batch {
+            migrate::encode(&mut records, 1, &key, Some(&val[..]));
+        }
+        assert!(records.len() <= BULK_PUT_PAYLOAD);
+
+        migrate::encode(&mut records, 1, &key, Some(&val[..]));
+        assert!(records.len() > BULK_PUT_PAYLOAD);
+
+        assert_eq!(0, batch_len(30, BULK_PUT_PAYLOAD));
+    }
+}
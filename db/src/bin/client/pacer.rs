@@ -0,0 +1,363 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use db::cycles;
+
+/// If the next request is due within these many nanoseconds, the pacer busy-waits on the
+/// timestamp counter instead of returning to the caller's scheduler.
+const SPIN_NS: u64 = 2000;
+
+/// A credit based pacer for request generators. Credits accrue at the configured rate, and each
+/// credit allows one request to be sent out. Credits that accumulate beyond a batch (for example,
+/// when the generator was descheduled) are forfeited instead of being sent out in a burst, so
+/// that the offered load stays close to the configured rate. The number of forfeited credits is
+/// tracked so that the achieved rate can be reported alongside the intended one.
+pub struct Pacer {
+    // The interval between two requests in cycles.
+    rate_inv: u64,
+
+    // The maximum number of requests that can be sent out in one go.
+    max_batch: u64,
+
+    // The interval in cycles below which the pacer busy-waits for the next credit.
+    spin: u64,
+
+    // The time stamp in cycles at which the pacer handed out it's first credit.
+    start: u64,
+
+    // The time stamp in cycles at which the pacer last handed out credits.
+    last: u64,
+
+    // The number of credits handed out so far.
+    issued: u64,
+
+    // The number of credits forfeited so far.
+    forfeited: u64,
+
+    // The time stamp in cycles from which credits accrue at the current rate, and the number of
+    // credits that were due by then. Refer to `set_rate()`.
+    epoch: u64,
+    epoch_base: u64,
+}
+
+// Implementation of methods on Pacer.
+impl Pacer {
+    /// Constructs a Pacer.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate`:      The rate at which requests must be sent out in requests per second.
+    /// * `max_batch`: The maximum number of requests that can be sent out in one go. Higher
+    ///                values allow rates that exceed what a single send per call can achieve.
+    ///
+    /// # Return
+    ///
+    /// A Pacer that hands out credits at `rate` per second.
+    pub fn new(rate: u64, max_batch: u64) -> Pacer {
+        let cps = cycles::cycles_per_second();
+
+        Pacer {
+            rate_inv: cps / rate,
+            max_batch: if max_batch == 0 { 1 } else { max_batch },
+            spin: (cps * SPIN_NS) / 1000000000,
+            start: 0,
+            last: 0,
+            issued: 0,
+            forfeited: 0,
+            epoch: 0,
+            epoch_base: 0,
+        }
+    }
+
+    /// Changes the rate at which credits accrue. Credits due at the earlier rate that have not
+    /// been handed out yet are dropped, and the next credit is handed out one interval at the new
+    /// rate after the latest one.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate`: The new rate in requests per second.
+    pub fn set_rate(&mut self, rate: u64) {
+        self.rate_inv = cycles::cycles_per_second() / rate.max(1);
+
+        if self.start != 0 {
+            self.epoch = self.last;
+            self.epoch_base = self.issued + self.forfeited - 1;
+        }
+    }
+
+    /// Returns the number of requests that can be sent out right now. If the next request is due
+    /// shortly, this method busy-waits until it is.
+    pub fn credits(&mut self) -> u64 {
+        let curr = cycles::rdtsc();
+        let mut c = self.credits_at(curr);
+
+        if c == 0 {
+            let used = self.issued + self.forfeited - self.epoch_base;
+            let next = self.epoch + used * self.rate_inv;
+            if next - curr <= self.spin {
+                while cycles::rdtsc() < next {}
+                c = self.credits_at(next);
+            }
+        }
+
+        c
+    }
+
+    /// Returns the number of requests that can be sent out at a particular time stamp, and
+    /// marks them as issued.
+    ///
+    /// # Arguments
+    ///
+    /// * `curr`: The time stamp in cycles at which the credits are requested.
+    fn credits_at(&mut self, curr: u64) -> u64 {
+        // The first credit is always handed out immediately.
+        if self.start == 0 {
+            self.start = curr;
+            self.epoch = curr;
+        }
+
+        // The total number of credits that should have been handed out by now.
+        let due = self.epoch_base + (curr - self.epoch) / self.rate_inv + 1;
+        let used = self.issued + self.forfeited;
+        if due <= used {
+            return 0;
+        }
+
+        // Forfeit credits that exceed a batch.
+        let mut c = due - used;
+        if c > self.max_batch {
+            self.forfeited += c - self.max_batch;
+            c = self.max_batch;
+        }
+
+        self.issued += c;
+        self.last = curr;
+
+        c
+    }
+
+    /// Returns the intended and achieved rates in requests per second.
+    pub fn rates(&self) -> (f64, f64) {
+        let intended = cycles::cycles_per_second() as f64 / self.rate_inv as f64;
+        let achieved = if self.last > self.start {
+            self.issued as f64 / cycles::to_seconds(self.last - self.start)
+        } else {
+            0.0
+        };
+
+        (intended, achieved)
+    }
+
+    /// Returns the number of credits forfeited so far.
+    pub fn forfeited(&self) -> u64 {
+        self.forfeited
+    }
+}
+
+/// The interval in microseconds at which an `Aimd` controller adjusts the rate.
+const AIMD_INTERVAL_US: u64 = 1000;
+
+/// The lowest rate in requests per second an `Aimd` controller backs off to.
+const AIMD_MIN_RATE: f64 = 1000.0;
+
+/// An additive-increase/multiplicative-decrease controller for the rate a request generator
+/// sends out requests at. Every interval, the rate is halved if congestion was signalled since
+/// the last one (ex: the server pushed back on a request, or a response took too long to
+/// arrive), and raised by a fixed step otherwise. After backing off, further signals are
+/// ignored for a hold period, since responses to requests sent out at the earlier rate are
+/// still arriving. Once the controller has backed off for the first time, the rate saws around
+/// the highest the server can sustain; the time-averaged rate from then on is reported as the
+/// equilibrium.
+pub struct Aimd {
+    // The current rate in requests per second.
+    rate: f64,
+
+    // The amount in requests per second the rate is raised by every uncongested interval.
+    step: f64,
+
+    // The interval in cycles at which the rate is adjusted.
+    interval: u64,
+
+    // The period in cycles after a backoff during which congestion signals are ignored.
+    hold: u64,
+
+    // The time stamp in cycles at which the rate was last adjusted.
+    last: u64,
+
+    // The time stamp in cycles until which congestion signals are ignored.
+    held: u64,
+
+    // The number of congestion signals seen as of the last adjustment.
+    signals: u64,
+
+    // The number of times the controller backed off.
+    backoffs: u64,
+
+    // The time stamp in cycles of the first backoff, and the integral of the rate over time from
+    // then on in requests.
+    settled: u64,
+    area: f64,
+}
+
+// Implementation of methods on Aimd.
+impl Aimd {
+    /// Constructs an Aimd controller.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate`: The rate in requests per second to start out at.
+    /// * `step`: The amount in requests per second to raise the rate by every interval.
+    /// * `hold`: The period in cycles after a backoff during which congestion is ignored.
+    ///
+    /// # Return
+    ///
+    /// A controller starting out at `rate`.
+    pub fn new(rate: u64, step: u64, hold: u64) -> Aimd {
+        Aimd {
+            rate: (rate as f64).max(AIMD_MIN_RATE),
+            step: step.max(1) as f64,
+            interval: (cycles::cycles_per_second() * AIMD_INTERVAL_US) / 1000000,
+            hold: hold,
+            last: 0,
+            held: 0,
+            signals: 0,
+            backoffs: 0,
+            settled: 0,
+            area: 0.0,
+        }
+    }
+
+    /// Adjusts the rate if an interval has passed since it was last adjusted.
+    ///
+    /// # Arguments
+    ///
+    /// * `curr`:    The current time stamp in cycles.
+    /// * `signals`: The total number of congestion signals seen so far.
+    ///
+    /// # Return
+    ///
+    /// The new rate in requests per second if it was adjusted.
+    pub fn update(&mut self, curr: u64, signals: u64) -> Option<u64> {
+        if self.last == 0 {
+            self.last = curr;
+            self.signals = signals;
+            return None;
+        }
+
+        if curr - self.last < self.interval {
+            return None;
+        }
+
+        if self.settled != 0 {
+            self.area += self.rate * cycles::to_seconds(curr - self.last);
+        }
+        self.last = curr;
+
+        let congested = signals > self.signals;
+        self.signals = signals;
+
+        if congested && curr >= self.held {
+            self.rate = (self.rate / 2.0).max(AIMD_MIN_RATE);
+            self.held = curr + self.hold;
+            self.backoffs += 1;
+            if self.settled == 0 {
+                self.settled = curr;
+            }
+        } else if !congested {
+            self.rate += self.step;
+        }
+
+        Some(self.rate as u64)
+    }
+
+    /// Returns the number of times the controller backed off.
+    pub fn backoffs(&self) -> u64 {
+        self.backoffs
+    }
+
+    /// Returns the time-averaged rate in requests per second since the first backoff, upto the
+    /// last adjustment. None if the controller never backed off.
+    pub fn equilibrium(&self) -> Option<f64> {
+        if self.settled == 0 || self.last <= self.settled {
+            return None;
+        }
+
+        Some(self.area / cycles::to_seconds(self.last - self.settled))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Aimd, Pacer};
+
+    // Tests that credits accrue at the configured rate, and that excess credits are forfeited.
+    #[test]
+    fn credits_accrue_and_forfeit() {
+        let mut pacer = Pacer::new(1000, 4);
+        let inv = pacer.rate_inv;
+
+        // The first credit is handed out immediately, the next one only after an interval.
+        assert_eq!(1, pacer.credits_at(100));
+        assert_eq!(0, pacer.credits_at(100 + inv - 1));
+        assert_eq!(1, pacer.credits_at(100 + inv));
+
+        // After a long stall, only a batch worth of credits is handed out.
+        assert_eq!(4, pacer.credits_at(100 + 11 * inv));
+        assert_eq!(6, pacer.forfeited());
+        assert_eq!(0, pacer.credits_at(100 + 11 * inv));
+        assert_eq!(1, pacer.credits_at(100 + 12 * inv));
+    }
+
+    // Tests that a change in rate takes effect from the latest credit onwards.
+    #[test]
+    fn rate_change() {
+        let mut pacer = Pacer::new(1000, 4);
+        let inv = pacer.rate_inv;
+        assert_eq!(1, pacer.credits_at(100));
+
+        pacer.set_rate(500);
+        let slow = pacer.rate_inv;
+        assert!(slow > inv);
+        assert_eq!(0, pacer.credits_at(100 + slow - 1));
+        assert_eq!(1, pacer.credits_at(100 + slow));
+        assert_eq!(0, pacer.credits_at(100 + 2 * slow - 1));
+        assert_eq!(1, pacer.credits_at(100 + 2 * slow));
+        assert_eq!(0, pacer.forfeited());
+    }
+
+    // Tests that the controller halves the rate on congestion, ignores congestion while held,
+    // and ramps up otherwise.
+    #[test]
+    fn aimd_adjusts() {
+        let mut aimd = Aimd::new(100000, 1000, 0);
+        let i = aimd.interval;
+        aimd.hold = 2 * i;
+
+        assert_eq!(None, aimd.update(10, 0));
+        assert_eq!(None, aimd.update(10 + i - 1, 0));
+        assert_eq!(Some(101000), aimd.update(10 + i, 0));
+        assert_eq!(None, aimd.equilibrium());
+
+        // A backoff, followed by congestion that is ignored while held.
+        assert_eq!(Some(50500), aimd.update(10 + 2 * i, 3));
+        assert_eq!(Some(50500), aimd.update(10 + 3 * i, 4));
+        assert_eq!(Some(51500), aimd.update(10 + 4 * i, 4));
+        assert_eq!(Some(25750), aimd.update(10 + 5 * i, 5));
+        assert_eq!(2, aimd.backoffs());
+
+        let equilibrium = aimd.equilibrium().unwrap();
+        assert!(equilibrium > 25750.0 && equilibrium < 51500.0);
+    }
+}
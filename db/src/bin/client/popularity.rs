@@ -0,0 +1,176 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result};
+
+use rand::Rng;
+
+/// An empirical key popularity distribution, loaded from a histogram of how often each key was
+/// accessed (ex: one derived from a production trace). Keys are sampled in proportion to their
+/// counts, reproducing the trace's heavy head and long tail instead of a parametric Zipfian.
+///
+/// The histogram is a text file with one key per line, consisting of the key (a 32-bit integer
+/// that the client writes into requests like the keys it draws from a Zipfian distribution),
+/// followed by whitespace, followed by the number of times it was accessed. Blank lines and lines
+/// starting with '#' are ignored. For example:
+///
+/// ```text
+/// # key count
+/// 17 90211
+/// 3 4502
+/// 88 1
+/// ```
+pub struct Popularity {
+    // The keys in the histogram, in the order they were listed.
+    keys: Vec<u32>,
+
+    // The running total of the counts of the keys above, i.e, the i'th entry is the sum of the
+    // counts of the first i + 1 keys. Sampling picks the first entry above a uniform draw.
+    cdf: Vec<u64>,
+}
+
+// Implementation of methods on Popularity.
+impl Popularity {
+    /// Loads a histogram from a file. Refer to `Popularity` for the format.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: Path to the file.
+    ///
+    /// # Return
+    ///
+    /// The distribution, or an error if the file could not be read or was malformed.
+    pub fn load(path: &str) -> Result<Popularity> {
+        Popularity::parse(BufReader::new(File::open(path)?))
+    }
+
+    /// Parses a histogram. Refer to `Popularity` for the format.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader`: The histogram.
+    ///
+    /// # Return
+    ///
+    /// The distribution, or an error if the histogram was malformed, or had no accesses at all.
+    pub fn parse<R: BufRead>(reader: R) -> Result<Popularity> {
+        let mut keys = Vec::new();
+        let mut cdf = Vec::new();
+        let mut total: u64 = 0;
+
+        for (num, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let malformed = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Malformed popularity entry on line {}: {}", num + 1, line),
+                )
+            };
+
+            let mut fields = line.split_whitespace();
+            let key = fields.next().and_then(|k| k.parse::<u32>().ok());
+            let count = fields.next().and_then(|c| c.parse::<u64>().ok());
+            let (key, count) = match (key, count, fields.next()) {
+                (Some(key), Some(count), None) => (key, count),
+                _ => return Err(malformed()),
+            };
+
+            // Keys that were never accessed can never be sampled.
+            if count == 0 {
+                continue;
+            }
+
+            total = total.checked_add(count).ok_or_else(|| malformed())?;
+            keys.push(key);
+            cdf.push(total);
+        }
+
+        if total == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Popularity file has no accesses"));
+        }
+
+        Ok(Popularity {
+            keys: keys,
+            cdf: cdf,
+        })
+    }
+
+    /// Returns the number of keys that can be sampled.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Samples a key in proportion to it's count.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng`: The random number generator to draw from.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> u32 {
+        let total = self.cdf[self.cdf.len() - 1];
+        let draw = rng.gen_range(0, total);
+
+        // The sampled key is the first one whose running total exceeds the draw.
+        let idx = match self.cdf.binary_search(&(draw + 1)) {
+            Ok(idx) => idx,
+            Err(idx) => idx,
+        };
+
+        self.keys[idx]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Popularity;
+    use rand::{SeedableRng, XorShiftRng};
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    // Tests that keys are sampled in proportion to their counts, and that keys that were never
+    // accessed are never sampled.
+    #[test]
+    fn popularity_sample() {
+        let hist = "# key count\n\n7 600\n3 300\n9 0\n42 100\n";
+        let pop = Popularity::parse(Cursor::new(hist)).expect("Failed to parse histogram.");
+        assert_eq!(3, pop.len());
+
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut seen = HashMap::new();
+        for _ in 0..100000 {
+            *seen.entry(pop.sample(&mut rng)).or_insert(0u64) += 1;
+        }
+
+        assert_eq!(None, seen.get(&9));
+        for &(key, expected) in [(7, 60000), (3, 30000), (42, 10000)].iter() {
+            let found = *seen.get(&key).expect("Key never sampled.");
+            assert!(found > expected * 9 / 10 && found < expected * 11 / 10);
+        }
+    }
+
+    // Tests that malformed histograms, and histograms without accesses, are rejected.
+    #[test]
+    fn popularity_malformed() {
+        assert!(Popularity::parse(Cursor::new("7 600 1\n")).is_err());
+        assert!(Popularity::parse(Cursor::new("x 600\n")).is_err());
+        assert!(Popularity::parse(Cursor::new("7\n")).is_err());
+        assert!(Popularity::parse(Cursor::new("# nothing\n7 0\n")).is_err());
+    }
+}
@@ -0,0 +1,420 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{Result, Write};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::{Arc, Mutex};
+
+use db::config;
+use db::cycles;
+
+use rand;
+
+use histogram::Histogram;
+
+/// The version of the layout of the report. Bumped whenever a field is renamed or removed, so
+/// that analysis scripts can tell reports apart.
+pub const VERSION: u64 = 1;
+
+// The names latency percentiles are reported under, one per `histogram::PERCENTILES`.
+const NAMES: [&str; 4] = ["p50", "p90", "p99", "p999"];
+
+// The seed that every random number generator of the run is derived from. Zero until
+// `set_seed()` is called, or a generator asks for a seed.
+static SEED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// The number of generators seeded so far. Every one of them gets a different seed.
+static SEEDED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Sets the seed that the random number generators of the run are derived from, so that it can
+/// be reported, and the run repeated with it.
+///
+/// # Arguments
+///
+/// * `config`: Client configuration. A random seed is picked if `seed` is zero on it.
+///
+/// # Return
+///
+/// The seed the run uses.
+pub fn set_seed(config: &config::ClientConfig) -> u64 {
+    let seed = if config.seed == 0 {
+        random_seed()
+    } else {
+        config.seed
+    };
+    SEED.store(seed as usize, Ordering::Relaxed);
+    seed
+}
+
+/// Returns the seed that the random number generators of the run are derived from.
+pub fn seed() -> u64 {
+    let seed = SEED.load(Ordering::Relaxed) as u64;
+    if seed != 0 {
+        return seed;
+    }
+
+    // No seed was set, so pick one. If another thread beat us to it, use theirs.
+    match SEED.compare_exchange(0, random_seed() as usize, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => SEED.load(Ordering::Relaxed) as u64,
+        Err(curr) => curr as u64,
+    }
+}
+
+/// Returns a seed for a XorShiftRng, derived from the seed of the run. Every call returns a
+/// different one, so generators on different cores do not produce the same sequence.
+#[allow(dead_code)]
+pub fn rng_seed() -> [u32; 4] {
+    let n = SEEDED.fetch_add(1, Ordering::Relaxed) as u64;
+    let mut state = seed().wrapping_add(n.wrapping_mul(0x9e3779b97f4a7c15));
+    let hi = splitmix(&mut state);
+    let lo = splitmix(&mut state);
+
+    // XorShiftRng cannot be seeded with all zeros.
+    let lo = if hi | lo == 0 { 1 } else { lo };
+    [(hi >> 32) as u32, hi as u32, (lo >> 32) as u32, lo as u32]
+}
+
+// Returns a non-zero random seed.
+fn random_seed() -> u64 {
+    rand::random::<u64>().max(1)
+}
+
+// Advances a SplitMix64 generator, returning it's next output.
+fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// The results of a run, merged across the receivers of every queue, and emitted as a single
+/// line of JSON once the client is done. Refer to `results_file` on the client's configuration.
+pub struct Report {
+    // The number of responses received.
+    responses: u64,
+
+    // The throughput in responses per second, summed across receivers.
+    throughput: f64,
+
+    // Sampled latencies in cycles.
+    latencies: Histogram,
+
+    // The number of requests that were lost and retried. None if the client does not count them.
+    lost: Option<u64>,
+    retries: Option<u64>,
+
+    // Counts particular to a client, ex: the number of failed puts while loading a table.
+    counts: Vec<(&'static str, u64)>,
+}
+
+// Implementation of methods on Report.
+#[allow(dead_code)]
+impl Report {
+    /// Returns an empty report that can be shared between receivers.
+    pub fn new() -> Arc<Mutex<Report>> {
+        Arc::new(Mutex::new(Report {
+            responses: 0,
+            throughput: 0.0,
+            latencies: Histogram::new(),
+            lost: None,
+            retries: None,
+            counts: Vec::new(),
+        }))
+    }
+
+    /// Adds the responses received by a receiver to the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `responses`:  The number of responses the receiver received.
+    /// * `throughput`: The throughput in responses per second the receiver measured.
+    pub fn record(&mut self, responses: u64, throughput: f64) {
+        self.responses += responses;
+        self.throughput += throughput;
+    }
+
+    /// Adds a histogram of latencies in cycles to the report.
+    pub fn latencies(&mut self, latencies: &Histogram) {
+        self.latencies.merge(latencies);
+    }
+
+    /// Adds latency samples in cycles to the report.
+    pub fn samples(&mut self, samples: &[u64]) {
+        for sample in samples.iter() {
+            self.latencies.record(*sample);
+        }
+    }
+
+    /// Counts requests that never received a response.
+    pub fn lost(&mut self, n: u64) {
+        self.lost = Some(self.lost.unwrap_or(0) + n);
+    }
+
+    /// Counts requests that were sent out again.
+    pub fn retried(&mut self, n: u64) {
+        self.retries = Some(self.retries.unwrap_or(0) + n);
+    }
+
+    /// Adds to a count particular to the client, reported under `counts`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The name of the count.
+    /// * `n`:    The amount to add to it.
+    pub fn count(&mut self, name: &'static str, n: u64) {
+        if let Some(count) = self.counts.iter_mut().find(|c| c.0 == name) {
+            count.1 += n;
+            return;
+        }
+        self.counts.push((name, n));
+    }
+
+    /// Returns the report as a JSON object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bench`:  The name of the client, ex: "ycsb".
+    /// * `config`: Client configuration the run used.
+    pub fn json(&self, bench: &str, config: &config::ClientConfig) -> Object {
+        let mut workload = Object::new();
+        workload
+            .string("transport", &config.transport)
+            .integer("pipelines", config.pipelines().len() as u64)
+            .integer("num_tenants", config.num_tenants as u64)
+            .boolean("use_invoke", config.use_invoke)
+            .integer("key_len", config.key_len as u64)
+            .integer("value_len", config.value_len as u64)
+            .integer("n_keys", config.n_keys as u64)
+            .integer("put_pct", config.put_pct as u64)
+            .number("skew", config.skew)
+            .number("tenant_skew", config.tenant_skew)
+            .integer("num_reqs", config.num_reqs as u64)
+            .integer("req_rate", config.req_rate as u64)
+            .integer("warmup_secs", config.warmup_secs)
+            .integer("warmup_reqs", config.warmup_reqs as u64);
+
+        let ns = |l: u64| cycles::to_seconds(l) * 1e9;
+        let mut latency = Object::new();
+        latency.integer("samples", self.latencies.count());
+        for (name, l) in NAMES.iter().zip(self.latencies.percentiles().into_iter()) {
+            if self.latencies.is_empty() {
+                latency.null(name);
+            } else {
+                latency.number(name, ns(l));
+            }
+        }
+
+        let mut counts = Object::new();
+        for &(name, n) in self.counts.iter() {
+            counts.integer(name, n);
+        }
+
+        let mut report = Object::new();
+        report
+            .integer("version", VERSION)
+            .string("bench", bench)
+            .string("git_hash", config::git_hash())
+            .integer("seed", seed())
+            .object("workload", &workload)
+            .integer("responses", self.responses)
+            .number("throughput", self.throughput)
+            .object("latency_ns", &latency)
+            .optional("lost", self.lost)
+            .optional("retries", self.retries)
+            .object("counts", &counts);
+        report
+    }
+
+    /// Writes the report out as a single line of JSON, appended to `results_file` on the
+    /// client's configuration, or printed to stdout if it is not set.
+    ///
+    /// # Arguments
+    ///
+    /// * `bench`:  The name of the client, ex: "ycsb".
+    /// * `config`: Client configuration the run used.
+    pub fn emit(&self, bench: &str, config: &config::ClientConfig) -> Result<()> {
+        let line = format!("{}\n", self.json(bench, config));
+        if config.results_file.is_empty() {
+            let stdout = ::std::io::stdout();
+            let mut stdout = stdout.lock();
+            return stdout.write_all(line.as_bytes()).and_then(|_| stdout.flush());
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.results_file)?
+            .write_all(line.as_bytes())
+    }
+}
+
+/// A JSON object, built up field by field. Fields are written out in the order they are added.
+pub struct Object {
+    // The fields written out so far, separated by commas.
+    fields: String,
+}
+
+// Implementation of methods on Object.
+#[allow(dead_code)]
+impl Object {
+    /// Returns an object without any fields.
+    pub fn new() -> Object {
+        Object {
+            fields: String::new(),
+        }
+    }
+
+    /// Adds a string field.
+    pub fn string(&mut self, name: &str, value: &str) -> &mut Object {
+        let value = quote(value);
+        self.field(name, &value)
+    }
+
+    /// Adds an integer field.
+    pub fn integer(&mut self, name: &str, value: u64) -> &mut Object {
+        self.field(name, &value.to_string())
+    }
+
+    /// Adds a number field. JSON cannot represent infinities and NaNs, so they are written out
+    /// as null.
+    pub fn number(&mut self, name: &str, value: f64) -> &mut Object {
+        if value.is_finite() {
+            self.field(name, &value.to_string())
+        } else {
+            self.null(name)
+        }
+    }
+
+    /// Adds a boolean field.
+    pub fn boolean(&mut self, name: &str, value: bool) -> &mut Object {
+        self.field(name, if value { "true" } else { "false" })
+    }
+
+    /// Adds an integer field that is null if the value is missing.
+    pub fn optional(&mut self, name: &str, value: Option<u64>) -> &mut Object {
+        match value {
+            Some(value) => self.integer(name, value),
+            None => self.null(name),
+        }
+    }
+
+    /// Adds a null field.
+    pub fn null(&mut self, name: &str) -> &mut Object {
+        self.field(name, "null")
+    }
+
+    /// Adds a field holding another object.
+    pub fn object(&mut self, name: &str, value: &Object) -> &mut Object {
+        let value = value.to_string();
+        self.field(name, &value)
+    }
+
+    // Adds a field whose value was already written out as JSON.
+    fn field(&mut self, name: &str, value: &str) -> &mut Object {
+        if !self.fields.is_empty() {
+            self.fields.push(',');
+        }
+        self.fields.push_str(&quote(name));
+        self.fields.push(':');
+        self.fields.push_str(value);
+        self
+    }
+}
+
+// Implementation of the Display trait on Object, writing it out as JSON.
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{{}}}", self.fields)
+    }
+}
+
+// Returns a string as a JSON string literal, escaping quotes, backslashes, and control
+// characters.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::{quote, rng_seed, Object, Report};
+
+    // Tests that strings are escaped, and that fields are written out in order.
+    #[test]
+    fn report_object() {
+        assert_eq!("\"a\\\"b\\\\c\\n\\u0001\"", quote("a\"b\\c\n\u{1}"));
+
+        let mut inner = Object::new();
+        assert_eq!("{}", inner.to_string());
+        inner.integer("n", 7).boolean("b", false);
+
+        let mut outer = Object::new();
+        outer
+            .string("s", "x")
+            .number("f", 1.5)
+            .number("nan", ::std::f64::NAN)
+            .optional("none", None)
+            .object("inner", &inner);
+        assert_eq!(
+            "{\"s\":\"x\",\"f\":1.5,\"nan\":null,\"none\":null,\"inner\":{\"n\":7,\"b\":false}}",
+            outer.to_string()
+        );
+    }
+
+    // Tests that counts and samples merged from different receivers add up.
+    #[test]
+    fn report_merge() {
+        let report = Report::new();
+        let mut report = report.lock().unwrap();
+        report.record(10, 100.0);
+        report.record(20, 50.0);
+        report.samples(&[1, 2, 3]);
+        report.count("failed", 1);
+        report.count("failed", 2);
+        report.lost(4);
+
+        assert_eq!(30, report.responses);
+        assert_eq!(150.0, report.throughput);
+        assert_eq!(3, report.latencies.count());
+        assert_eq!(vec![("failed", 3)], report.counts);
+        assert_eq!(Some(4), report.lost);
+        assert_eq!(None, report.retries);
+    }
+
+    // Tests that every generator is handed a different, valid seed.
+    #[test]
+    fn report_rng_seed() {
+        let a = rng_seed();
+        let b = rng_seed();
+        assert!(a != b);
+        assert!(a.iter().any(|w| *w != 0));
+    }
+}
@@ -30,11 +30,14 @@ use std::net::{Shutdown, TcpStream};
 use std::sync::Arc;
 
 use db::config;
-use db::e2d2::allocators::*;
+use db::crypt::{self, Keys};
 use db::e2d2::interface::*;
 use db::e2d2::scheduler::*;
 use db::log::*;
-use db::wireformat::InstallRequest;
+use db::rpc::{parse_extension_error, parse_partial_result, parse_rpc_opcode};
+use db::task::CostClass;
+use db::transport::Port;
+use db::wireformat::{InstallRequest, InvokeResponse, OpCode};
 
 /// Send side logic for a simple client that issues put() and get() requests.
 struct SanitySend {
@@ -69,7 +72,7 @@ impl SanitySend {
     /// # Return
     ///
     /// A SanitySend that can issue simple get() and put() RPCs to a remote Sandstorm server.
-    fn new(config: &config::ClientConfig, port: CacheAligned<PortQueue>) -> SanitySend {
+    fn new(config: &config::ClientConfig, port: Port) -> SanitySend {
         SanitySend {
             sender: dispatch::Sender::new(config, port, 1),
             puts: 1 * 1000,
@@ -97,8 +100,10 @@ impl Executable for SanitySend {
                 .expect("Failed to open .so for install.");
             let _ = get.read_to_end(&mut buf);
 
-            // Next, construct the RPC (header and payload).
-            let hdr = InstallRequest::new(100, 4, buf.len() as u32, 0);
+            // Next, construct the RPC (header and payload). The get() extension is short and
+            // read-only.
+            let short = CostClass::SHORT as u8;
+            let hdr = InstallRequest::new(100, 4, buf.len() as u32, 0, 0, short, true, 0);
             let hdr: [u8; size_of::<InstallRequest>()] = unsafe { transmute(hdr) };
             let mut req: Vec<u8> = Vec::new();
             req.extend_from_slice(&hdr);
@@ -195,13 +200,14 @@ where
     /// # Arguments
     ///
     /// * `port`: Network port over which responses will be received. Required by the receiver.
+    /// * `keys`: Keys of tenants whose responses are sealed.
     ///
     /// # Return
     ///
     /// A SanityRecv capable of receiving responses to RPC requests generated by SanitySend.
-    fn new(port: T) -> SanityRecv<T> {
+    fn new(port: T, keys: Keys) -> SanityRecv<T> {
         SanityRecv {
-            receiver: dispatch::Receiver::new(port),
+            receiver: dispatch::Receiver::with_keys(port, keys),
         }
     }
 }
@@ -217,6 +223,20 @@ where
         // and free them.
         if let Some(mut packets) = self.receiver.recv_res() {
             while let Some(packet) = packets.pop() {
+                // Surface any errors returned by extensions.
+                if parse_rpc_opcode(&packet) == OpCode::SandstormInvokeRpc {
+                    let p = packet.parse_header::<InvokeResponse>();
+                    match (parse_extension_error(&p), parse_partial_result(&p)) {
+                        (Some(err), _) => println!("Extension error: {:?}", err),
+
+                        (None, Some(partial)) => println!("Partial response: {:?}", partial),
+
+                        (None, None) => println!("Response: {:?}", p.get_payload()),
+                    }
+                    p.free_packet();
+                    continue;
+                }
+
                 println!("Response: {:?}", packet.get_payload());
                 packet.free_packet();
             }
@@ -237,7 +257,7 @@ where
 /// * `scheduler`: Netbricks scheduler to which SanitySend will be added.
 fn setup_send<S>(
     config: &config::ClientConfig,
-    ports: Vec<CacheAligned<PortQueue>>,
+    ports: Vec<Port>,
     scheduler: &mut S,
     _core: i32,
 ) where
@@ -267,7 +287,8 @@ fn setup_send<S>(
 ///
 /// * `ports`:     Network port on which packets will be sent.
 /// * `scheduler`: Netbricks scheduler to which SanityRecv will be added.
-fn setup_recv<T, S>(ports: Vec<T>, scheduler: &mut S, _core: i32)
+/// * `keys`:      Keys of tenants whose responses are sealed.
+fn setup_recv<T, S>(ports: Vec<T>, scheduler: &mut S, _core: i32, keys: Keys)
 where
     T: PacketTx + PacketRx + Display + Clone + 'static,
     S: Scheduler + Sized,
@@ -278,7 +299,7 @@ where
     }
 
     // Add the receiver to a netbricks pipeline.
-    match scheduler.add_task(SanityRecv::new(ports[0].clone())) {
+    match scheduler.add_task(SanityRecv::new(ports[0].clone(), keys)) {
         Ok(_) => {
             info!("Successfully added SanityRecv to a Netbricks pipeline.");
         }
@@ -296,45 +317,42 @@ fn main() {
     let config = config::ClientConfig::load();
     info!("Starting up Sandstorm client with config {:?}", config);
 
+    // Load the keys of tenants whose responses will be sealed by the server.
+    let keys = crypt::load_keys(&config.key_file, None).expect("Failed to load key file.");
+
     // Setup Netbricks.
     let mut net_context = setup::config_and_init_netbricks(&config);
 
     // Setup the client pipeline.
     net_context.start_schedulers();
 
-    // Retrieve one port-queue from Netbricks, and setup the Send side.
-    let port = net_context
-        .rx_queues
-        .get(&0)
-        .expect("Failed to retrieve network port!")
-        .clone();
+    // Run a single sender and receiver, on the cores of the first pipeline.
+    let pipeline = setup::pipelines(&config, &net_context)
+        .into_iter()
+        .next()
+        .expect("Failed to retrieve network port!");
+    let port = pipeline.port;
+    let send_port = port.clone();
 
-    // Setup the send side on core 0.
+    // Setup the send side.
     net_context
         .add_pipeline_to_core(
-            0,
+            pipeline.sender,
             Arc::new(
                 move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
-                    setup_send(&config, port.clone(), sched, core)
+                    setup_send(&config, send_port.clone(), sched, core)
                 },
             ),
         )
         .expect("Failed to initialize send side.");
 
-    // Retrieve one port-queue from Netbricks, and setup the Receive side.
-    let port = net_context
-        .rx_queues
-        .get(&0)
-        .expect("Failed to retrieve network port!")
-        .clone();
-
-    // Setup the receive side on core 2.
+    // Setup the receive side.
     net_context
         .add_pipeline_to_core(
-            2,
+            pipeline.receiver,
             Arc::new(
                 move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
-                    setup_recv(port.clone(), sched, core)
+                    setup_recv(port.clone(), sched, core, keys.clone())
                 },
             ),
         )
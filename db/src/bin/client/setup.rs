@@ -13,28 +13,59 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-use db::config::ClientConfig;
+use std::iter;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::process;
+use std::str::FromStr;
+
+use db::config::{self, ClientConfig};
 
 use db::e2d2::config::{NetbricksConfiguration, PortConfiguration};
+use db::e2d2::interface::dpdk;
 use db::e2d2::scheduler::*;
+use db::log::*;
+use db::transport::{KernelPort, Port};
+
+// The core that runs the parent client thread.
+const PRIMARY_CORE: i32 = 9;
+
+/// A sender and receiver sharing a transmit-receive queue pair. Refer to
+/// `ClientConfig::pipelines()`.
+#[allow(dead_code)]
+pub struct Pipeline {
+    /// The core running the sender.
+    pub sender: i32,
+
+    /// The core running the receiver.
+    pub receiver: i32,
+
+    /// The queue pair the sender transmits requests on, and the receiver receives responses on.
+    pub port: Vec<Port>,
+
+    /// A queue pair on the receiver's core, for receivers that send out requests of their own.
+    pub reply: Vec<Port>,
+}
 
 /// Returns a struct of type NetbricksConfiguration which can be used to
 /// initialize Netbricks with a default set of parameters.
 ///
 /// If used to initialize Netbricks, this struct will run the parent client
-/// thread on core 0, and one scheduler on core 1. Packet buffers will be
-/// allocated from a 2 GB memory pool, with 64 MB cached at core 1. DPDK will
-/// be initialized as a primary process without any additional arguments. A
-/// single network interface/port with 1 transmit queue, 1 receive queue, 256
-/// receive descriptors, and 256 transmit descriptors will be made available to
+/// thread on core 9, and one scheduler on every sender and receiver core in the
+/// client config. DPDK will be initialized as a primary process without any
+/// additional arguments. A single network interface/port with `queues_per_core`
+/// transmit and receive queues on every one of these cores, 256 receive
+/// descriptors, and 256 transmit descriptors will be made available to
 /// Netbricks. Loopback, hardware transmit segementation offload, and hardware
-/// checksum offload will be disabled on this port.
+/// checksum offload will be disabled on this port. If requests are sent over
+/// kernel sockets, no port is made available at all.
 fn get_default_netbricks_config(config: &ClientConfig) -> NetbricksConfiguration {
     // General arguments supplied to netbricks.
     let net_config_name = String::from("client");
     let dpdk_secondary: bool = false;
-    let net_primary_core: i32 = 9;
-    let net_cores: Vec<i32> = (0i32..8i32).collect();
+    let net_primary_core: i32 = PRIMARY_CORE;
+    let mut net_cores: Vec<i32> = config.senders();
+    net_cores.extend(config.receivers());
+    net_cores.sort();
     let net_strict_cores: bool = true;
     let net_pool_size: u32 = 8192 - 1;
     let net_cache_size: u32 = 128;
@@ -42,8 +73,11 @@ fn get_default_netbricks_config(config: &ClientConfig) -> NetbricksConfiguration
 
     // Port configuration. Required to configure the physical network interface.
     let net_port_name = config.nic_pci.clone();
-    let net_port_rx_queues: Vec<i32> = net_cores.clone();
-    let net_port_tx_queues: Vec<i32> = net_cores.clone();
+    let net_port_rx_queues: Vec<i32> = net_cores
+        .iter()
+        .flat_map(|core| iter::repeat(*core).take(config.queues()))
+        .collect();
+    let net_port_tx_queues: Vec<i32> = net_port_rx_queues.clone();
     let net_port_rxd: i32 = 256;
     let net_port_txd: i32 = 256;
     let net_port_loopback: bool = false;
@@ -62,7 +96,11 @@ fn get_default_netbricks_config(config: &ClientConfig) -> NetbricksConfiguration
     };
 
     // The set of ports used by netbricks.
-    let net_ports: Vec<PortConfiguration> = vec![net_port_config];
+    let net_ports: Vec<PortConfiguration> = if config.kernel_transport() {
+        vec![]
+    } else {
+        vec![net_port_config]
+    };
 
     NetbricksConfiguration {
         name: net_config_name,
@@ -84,7 +122,92 @@ fn get_default_netbricks_config(config: &ClientConfig) -> NetbricksConfiguration
 ///
 /// Netbricks context which can be used to setup and start the client.
 pub fn config_and_init_netbricks(config: &ClientConfig) -> NetBricksContext {
+    // Make sure the pipelines fit on this machine before handing their cores to Netbricks.
+    if let Err(err) = config.check_pipelines(PRIMARY_CORE, config::available_cores()) {
+        error!("Invalid sender_cores or receiver_cores in client config: {}", err);
+        process::exit(1);
+    }
+
+    // Timestamping packets on arrival requires timesync to be enabled when ports are started.
+    dpdk::set_hw_timestamps(config.nic_timestamps() && !config.kernel_transport());
+
     // Initialize Netbricks and return a handle.
     let net_config = get_default_netbricks_config(config);
     initialize_system(&net_config).expect("Failed to initialize Netbricks")
 }
+
+/// Returns the client's pipelines, along with the queue pair each of them was handed by
+/// Netbricks. Refer to `ClientConfig::pipelines()`. If requests are sent over kernel sockets,
+/// every pipeline is handed a pair of sockets of it's own instead, that send to the server's.
+///
+/// # Arguments
+///
+/// * `config`:      The client config the pipelines were laid out from.
+/// * `net_context`: Netbricks context returned by `config_and_init_netbricks()`.
+pub fn pipelines(config: &ClientConfig, net_context: &NetBricksContext) -> Vec<Pipeline> {
+    if config.kernel_transport() {
+        return kernel_pipelines(config);
+    }
+
+    let mut pipelines: Vec<Pipeline> = Vec::new();
+    for (sender, receiver) in config.pipelines() {
+        // Pipelines sharing a sender core are handed that core's queues in order.
+        let queue = pipelines.iter().filter(|p| p.sender == sender).count();
+        let port = |core: i32| {
+            net_context
+                .rx_queues
+                .get(&core)
+                .and_then(|ports| ports.get(queue))
+                .expect("Failed to retrieve network port!")
+                .clone()
+        };
+
+        pipelines.push(Pipeline {
+            sender: sender,
+            receiver: receiver,
+            port: vec![Port::Dpdk(port(sender))],
+            reply: vec![Port::Dpdk(port(receiver))],
+        });
+    }
+
+    pipelines
+}
+
+// Returns the client's pipelines, each with a pair of kernel sockets. Exits if a socket could
+// not be bound.
+fn kernel_pipelines(config: &ClientConfig) -> Vec<Pipeline> {
+    let server = config.server_kernel_addr();
+    let mac = config.parse_mac();
+    let ip = u32::from(Ipv4Addr::from_str(&config.ip_address).expect("Failed to parse client IP."));
+
+    // Every socket is bound to a free port, and stands in for a queue of it's own.
+    let mut queue = 0;
+    let mut socket = || {
+        let any = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0);
+        queue += 1;
+        match KernelPort::bind(any, Some(server), mac, ip, queue) {
+            Ok(port) => Port::Kernel(port),
+
+            Err(ref err) => {
+                error!("Failed to bind kernel socket: {}", err);
+                process::exit(1);
+            }
+        }
+    };
+
+    let mut pipelines: Vec<Pipeline> = Vec::new();
+    for (sender, receiver) in config.pipelines() {
+        let port = socket();
+        let reply = socket();
+        info!("Sending requests from core {} over {} to {}", sender, port, server);
+
+        pipelines.push(Pipeline {
+            sender: sender,
+            receiver: receiver,
+            port: vec![port],
+            reply: vec![reply],
+        });
+    }
+
+    pipelines
+}
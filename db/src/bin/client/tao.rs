@@ -20,17 +20,21 @@ extern crate rand;
 extern crate zipf;
 
 mod dispatch;
+mod histogram;
+mod pacer;
+mod report;
 mod setup;
+mod workload;
 
+use std::collections::HashMap;
 use std::mem::{size_of, transmute};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use db::config;
 use db::cycles;
-use db::e2d2::allocators::CacheAligned;
-use db::e2d2::interface::PortQueue;
 use db::e2d2::scheduler::*;
 use db::log::*;
+use db::transport::Port;
 use db::wireformat::*;
 
 use rand::distributions::Sample;
@@ -38,8 +42,10 @@ use rand::{Rng, SeedableRng, XorShiftRng};
 
 use zipf::ZipfDistribution;
 
-/// This type implements the send half of a TAO client.
-struct TaoSend {
+use workload::{Op, Workload, WorkloadSend};
+
+/// This type generates requests for the send half of a TAO client.
+struct TaoWorkload {
     /// Random number generator required to seed the Zipfian distribution.
     random: XorShiftRng,
 
@@ -52,24 +58,6 @@ struct TaoSend {
     /// Flag indicating whether requests should be native (true) or invocations (false).
     native: bool,
 
-    /// The total number of requests to be sent out.
-    requests: u64,
-
-    /// The total number of requests that have been sent so far.
-    sent: u64,
-
-    /// The time interval between two requests (in cycles).
-    delay: u64,
-
-    /// The time (in cycles) at which the workload started generating requests.
-    start: u64,
-
-    /// The time (in cycles) at which the next request should be issued/generated.
-    next: u64,
-
-    /// Network stack that can actually send an RPC over the network.
-    sender: dispatch::Sender,
-
     /// Request buffer for a native obj_get operation. Helps reduce heap allocations.
     no_buff: Vec<u8>,
 
@@ -89,23 +77,15 @@ struct TaoSend {
     combine: bool,
 }
 
-// Implementation of methods on TaoSend.
-impl TaoSend {
-    /// Constructs a `TaoSend` that can be added to a Netbricks pipeline.
+// Implementation of methods on TaoWorkload.
+impl TaoWorkload {
+    /// Constructs a `TaoWorkload` that can be driven by a `WorkloadSend`.
     ///
     /// # Arguments
     ///
-    /// * `config`:    Client configuration with Workload related (key and value length etc.) as
-    ///                well as network related (Server and Client MAC address etc.) parameters.
-    /// * `port`:      Network port over which requests will be sent out.
-    /// * `reqs`:      The number of requests to be issued to the server.
-    /// * `dst_ports`: The total number of UDP ports the server is listening on.
-    pub fn new(
-        config: &config::ClientConfig,
-        port: CacheAligned<PortQueue>,
-        reqs: u64,
-        dst_ports: u16,
-    ) -> TaoSend {
+    /// * `config`: Client configuration with Workload related (key and value length etc.)
+    ///             parameters.
+    pub fn new(config: &config::ClientConfig) -> TaoWorkload {
         // Allocate a vector for the obj_get invoke() RPC's payload. The payload consists of the
         // name of the extension, an opcode, the table id (8 bytes) and the key length.
         let len = "tao".as_bytes().len() + 1 + size_of::<u64>() + 8;
@@ -136,19 +116,13 @@ impl TaoSend {
         let mut na_buff = Vec::with_capacity(10);
         na_buff.resize(10, 0);
 
-        TaoSend {
-            random: XorShiftRng::from_seed(rand::random::<[u32; 4]>()),
+        TaoWorkload {
+            random: XorShiftRng::from_seed(report::rng_seed()),
             k_dist: ZipfDistribution::new(config.n_keys, config.skew)
                 .expect("Failed to init key generator."),
             t_dist: ZipfDistribution::new(config.num_tenants as usize, config.tenant_skew)
                 .expect("Failed to init tenant generator."),
             native: !config.use_invoke,
-            requests: reqs,
-            sent: 0,
-            delay: cycles::cycles_per_second() / config.req_rate as u64,
-            start: cycles::rdtsc(),
-            next: 0,
-            sender: dispatch::Sender::new(config, port, dst_ports),
             no_buff: no_buff,
             na_buff: na_buff,
             io_buff: io_buff,
@@ -161,40 +135,50 @@ impl TaoSend {
     /// Samples distributions for a tenant id key, and opcode.
     ///
     /// # Return
-    /// A 3-tupule consisting of a 4 byte tenant id, 4 byte key, and boolean. If the boolean is
-    /// true, the op should be an obj_get.
+    /// A 3-tupule consisting of a 4 byte tenant id, 8 byte object id, and boolean. If the boolean
+    /// is true, the op should be an obj_get. Every tenant has it's own graph, so object ids are
+    /// scoped to the tenant: the upper four bytes of an id are the tenant's id.
     #[inline]
-    fn sample(&mut self) -> (u32, [u8; 4], bool) {
+    fn sample(&mut self) -> (u32, [u8; 8], bool) {
         let t = self.t_dist.sample(&mut self.random) as u32;
 
-        let k = self.k_dist.sample(&mut self.random) as u32;
-        let k: [u8; 4] = unsafe { transmute(k.to_le()) };
+        let k = self.k_dist.sample(&mut self.random) as u64 | (t as u64) << 32;
+        let k: [u8; 8] = unsafe { transmute(k.to_le()) };
 
         let o = self.random.gen::<u32>() % 100 >= self.assoc_p as u32;
 
         (t, k, o)
     }
+}
 
-    /// Generates an RPC request out on the network.
-    ///
-    /// # Arguments
-    ///
-    /// * `curr`: Timestamp to attach onto the RPC.
-    #[inline]
-    fn generate(&mut self, curr: u64) {
+// Implementation of the Workload trait so that TAO requests can be sent out by a WorkloadSend.
+impl Workload for TaoWorkload {
+    fn name(&self) -> &'static str {
+        "TAO"
+    }
+
+    fn next_op(&mut self) -> Op {
         let (t, k, o) = self.sample();
 
         match self.native {
             // Native request.
             true => match o {
                 true => {
-                    self.no_buff[0..size_of::<u32>()].copy_from_slice(&k);
-                    self.sender.send_get(t, 1, &self.no_buff, curr);
+                    self.no_buff[0..size_of::<u64>()].copy_from_slice(&k);
+                    Op::Get {
+                        tenant: t,
+                        table: 1,
+                        key: &self.no_buff,
+                    }
                 }
 
                 false => {
-                    self.na_buff[0..size_of::<u32>()].copy_from_slice(&k);
-                    self.sender.send_get(t, 2, &self.na_buff, curr);
+                    self.na_buff[0..size_of::<u64>()].copy_from_slice(&k);
+                    Op::Get {
+                        tenant: t,
+                        table: 2,
+                        key: &self.na_buff,
+                    }
                 }
             },
 
@@ -202,57 +186,77 @@ impl TaoSend {
             false => match o {
                 true => match self.combine {
                     true => {
-                        self.no_buff[0..size_of::<u32>()].copy_from_slice(&k);
-                        self.sender.send_get(t, 1, &self.no_buff, curr);
+                        self.no_buff[0..size_of::<u64>()].copy_from_slice(&k);
+                        Op::Get {
+                            tenant: t,
+                            table: 1,
+                            key: &self.no_buff,
+                        }
                     }
 
                     false => {
-                        self.io_buff[12..16].copy_from_slice(&k);
-                        self.sender.send_invoke(t, 3, &self.io_buff, curr);
+                        self.io_buff[12..20].copy_from_slice(&k);
+                        Op::Invoke {
+                            tenant: t,
+                            name_len: 3,
+                            payload: &self.io_buff,
+                        }
                     }
                 },
 
                 false => {
-                    self.ia_buff[12..16].copy_from_slice(&k);
-                    self.sender.send_invoke(t, 3, &self.ia_buff, curr);
+                    self.ia_buff[12..20].copy_from_slice(&k);
+                    Op::Invoke {
+                        tenant: t,
+                        name_len: 3,
+                        payload: &self.ia_buff,
+                    }
                 }
             },
         }
     }
 }
 
-// Implementation of the Executable trait so that we can use Netbrick's DPDK bindings.
-impl Executable for TaoSend {
-    fn execute(&mut self) {
-        // Return if there are no more requests to generate.
-        if self.requests <= self.sent {
-            return;
-        }
+/// Responses received on behalf of a single tenant, along with a sample of their latencies.
+struct TenantStats {
+    /// The number of responses received for the tenant.
+    recvd: u64,
 
-        // Send out a request at the configured request rate.
-        let curr = cycles::rdtsc();
-        if curr >= self.next || self.next == 0 {
-            self.generate(curr);
+    /// Sampled obj_get latencies of the tenant's requests.
+    o_latencies: Vec<u64>,
 
-            self.sent += 1;
-            self.next = self.start + self.sent * self.delay;
-        }
-    }
+    /// Sampled assoc_get latencies of the tenant's requests.
+    a_latencies: Vec<u64>,
+}
 
-    fn dependencies(&mut self) -> Vec<usize> {
-        vec![]
+/// Sorts a vector of sampled latencies, and returns their mean, median, and 99th percentile in
+/// nanoseconds. All zero if there are no samples.
+fn summarize(latencies: &mut Vec<u64>) -> (f64, f64, f64) {
+    if latencies.is_empty() {
+        return (0.0, 0.0, 0.0);
     }
+
+    latencies.sort();
+    let median = latencies[latencies.len() / 2];
+    let tail = latencies[(latencies.len() * 99) / 100];
+    let mean = latencies.iter().sum::<u64>() / latencies.len() as u64;
+
+    (
+        cycles::to_seconds(mean) * 1e9,
+        cycles::to_seconds(median) * 1e9,
+        cycles::to_seconds(tail) * 1e9,
+    )
 }
 
 /// This type implements the receive half of a client that issues back to back TAO reads to a
 /// server.
 struct TaoRecv {
     /// The network stack required to receives RPC response packets from a network port.
-    receiver: dispatch::Receiver<CacheAligned<PortQueue>>,
+    receiver: dispatch::Receiver<Port>,
 
     /// Second receiver stack to receive multiget RPC response packets when operating in
     /// native mode.
-    multi_rx: dispatch::Receiver<CacheAligned<PortQueue>>,
+    multi_rx: dispatch::Receiver<Port>,
 
     /// Network stack that can actually send an RPC over the network. Required for the native case.
     sender: dispatch::Sender,
@@ -283,6 +287,14 @@ struct TaoRecv {
 
     /// If true and native is false, then obj_gets are sent out as native gets.
     combine: bool,
+
+    /// Responses and latencies of each tenant, so that tenants can be compared against each
+    /// other once all responses have been received.
+    tenants: HashMap<u32, TenantStats>,
+
+    /// Results merged across the receivers of all queues, and written out once the client is
+    /// done.
+    report: Arc<Mutex<report::Report>>,
 }
 
 // Implementation of methods on TaoRecv.
@@ -299,17 +311,19 @@ impl TaoRecv {
     /// * `dst_ports`: The total number of UDP ports the server is listening on.
     /// * `config`:    Client configuration with Workload related (key and value length etc.) as
     ///                well as network related (Server and Client MAC address etc.) parameters.
+    /// * `report`:    Results merged across the receivers of all queues.
     ///
     /// # Return
     ///
     /// A receiver that measures the median latency and throughput of a Sandstorm server.
     fn new(
-        port: CacheAligned<PortQueue>,
+        port: Port,
         resps: u64,
         native: bool,
-        send: CacheAligned<PortQueue>,
+        send: Port,
         dst_ports: u16,
         config: &config::ClientConfig,
+        report: Arc<Mutex<report::Report>>,
     ) -> TaoRecv {
         // Pre-populate a vector for assoc keys.
         let mut a_keys = Vec::with_capacity(72);
@@ -327,6 +341,37 @@ impl TaoRecv {
             a_latencies: Vec::with_capacity(2 * 1000 * 1000),
             assoc_keys: a_keys,
             combine: config.combined,
+            tenants: HashMap::new(),
+            report: report,
+        }
+    }
+
+    /// Records a response, sampling it's latency once every sixteen responses.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant the request was issued on behalf of.
+    /// * `obj`:    True if the response is for an obj_get, false if for an assoc_get.
+    /// * `stamp`:  The time stamp in cycles at which the request was sent out.
+    fn record(&mut self, tenant: u32, obj: bool, stamp: u64) {
+        self.recvd += 1;
+
+        let stats = self.tenants.entry(tenant).or_insert_with(|| TenantStats {
+            recvd: 0,
+            o_latencies: Vec::new(),
+            a_latencies: Vec::new(),
+        });
+        stats.recvd += 1;
+
+        if self.recvd & 0xf == 0 {
+            let latency = cycles::rdtsc() - stamp;
+            if obj {
+                self.o_latencies.push(latency);
+                stats.o_latencies.push(latency);
+            } else {
+                self.a_latencies.push(latency);
+                stats.a_latencies.push(latency);
+            }
         }
     }
 
@@ -380,6 +425,42 @@ impl TaoRecv {
             cycles::to_seconds(o_tail) * 1e9,
             self.recvd as f64 / cycles::to_seconds(stop - self.start)
         );
+
+        // Merge the throughput and latencies of both RPCs into the client's results.
+        {
+            let mut report = self.report.lock().unwrap();
+            report.record(
+                self.recvd,
+                self.recvd as f64 / cycles::to_seconds(stop - self.start),
+            );
+            report.samples(&self.o_latencies);
+            report.samples(&self.a_latencies);
+        }
+
+        // Next, print out measurements for every tenant in order of tenant id.
+        let elapsed = cycles::to_seconds(stop - self.start);
+        let mut tenants: Vec<u32> = self.tenants.keys().cloned().collect();
+        tenants.sort();
+
+        for tenant in tenants.iter() {
+            let stats = self.tenants.get_mut(tenant).expect("Missing tenant stats.");
+            let (a_mean, a_median, a_tail) = summarize(&mut stats.a_latencies);
+            let (o_mean, o_median, o_tail) = summarize(&mut stats.o_latencies);
+
+            info!(
+                "Tenant {} Responses {} AMean(ns) {} AMedian(ns): {} ATail(ns) {} OMean(ns) {} \
+                 OMedian(ns): {} OTail(ns): {} Throughput(Kops/s): {}",
+                tenant,
+                stats.recvd,
+                a_mean,
+                a_median,
+                a_tail,
+                o_mean,
+                o_median,
+                o_tail,
+                stats.recvd as f64 / elapsed
+            );
+        }
     }
 }
 
@@ -399,12 +480,9 @@ impl Executable for TaoRecv {
 
                     // Response to obj_get.
                     if p.get_payload().len() < 50 {
-                        self.recvd += 1;
-
-                        if self.recvd & 0xf == 0 {
-                            self.o_latencies
-                                .push(cycles::rdtsc() - p.get_header().common_header.stamp);
-                        }
+                        let tenant = p.get_header().common_header.tenant;
+                        let stamp = p.get_header().common_header.stamp;
+                        self.record(tenant, true, stamp);
 
                         p.free_packet();
                         continue;
@@ -422,28 +500,20 @@ impl Executable for TaoRecv {
                     );
                     p.free_packet();
                 } else {
-                    self.recvd += 1;
-
                     if self.combine && packet.get_payload().len() <= 50 {
                         let p = packet.parse_header::<GetResponse>();
-                        if self.recvd & 0xf == 0 {
-                            self.o_latencies
-                                .push(cycles::rdtsc() - p.get_header().common_header.stamp);
-                        }
+                        let tenant = p.get_header().common_header.tenant;
+                        let stamp = p.get_header().common_header.stamp;
+                        self.record(tenant, true, stamp);
                         p.free_packet();
                         continue;
                     }
 
                     let p = packet.parse_header::<InvokeResponse>();
-                    if self.recvd & 0xf == 0 {
-                        if p.get_payload().len() < 50 {
-                            self.o_latencies
-                                .push(cycles::rdtsc() - p.get_header().common_header.stamp);
-                        } else {
-                            self.a_latencies
-                                .push(cycles::rdtsc() - p.get_header().common_header.stamp);
-                        }
-                    }
+                    let obj = p.get_payload().len() < 50;
+                    let tenant = p.get_header().common_header.tenant;
+                    let stamp = p.get_header().common_header.stamp;
+                    self.record(tenant, obj, stamp);
                     p.free_packet();
                 }
             }
@@ -453,13 +523,10 @@ impl Executable for TaoRecv {
         if self.native {
             if let Some(mut resps) = self.multi_rx.recv_res() {
                 while let Some(packet) = resps.pop() {
-                    self.recvd += 1;
-
                     let p = packet.parse_header::<MultiGetResponse>();
-                    if self.recvd & 0xf == 0 {
-                        self.a_latencies
-                            .push(cycles::rdtsc() - p.get_header().common_header.stamp);
-                    }
+                    let tenant = p.get_header().common_header.tenant;
+                    let stamp = p.get_header().common_header.stamp;
+                    self.record(tenant, false, stamp);
                     p.free_packet();
                 }
             }
@@ -482,10 +549,10 @@ impl Executable for TaoRecv {
 ///
 /// * `config`:    Network related configuration such as the MAC and IP address.
 /// * `ports`:     Network port on which packets will be sent.
-/// * `scheduler`: Netbricks scheduler to which TaoSend will be added.
+/// * `scheduler`: Netbricks scheduler to which the sender will be added.
 fn setup_send<S>(
     config: &config::ClientConfig,
-    ports: Vec<CacheAligned<PortQueue>>,
+    ports: Vec<Port>,
     scheduler: &mut S,
     _core: i32,
 ) where
@@ -497,15 +564,16 @@ fn setup_send<S>(
     }
 
     // Add the sender to a netbricks pipeline.
-    match scheduler.add_task(TaoSend::new(
+    match scheduler.add_task(WorkloadSend::new(
         config,
+        TaoWorkload::new(config),
         ports[0].clone(),
         config.num_reqs as u64,
         config.server_udp_ports as u16,
     )) {
         Ok(_) => {
             info!(
-                "Successfully added TaoSend with tx queue {}.",
+                "Successfully added TAO WorkloadSend with tx queue {}.",
                 ports[0].txq()
             );
         }
@@ -527,13 +595,15 @@ fn setup_send<S>(
 ///                RPCs.
 /// * `send`:      Network port on which packets will be sent.
 /// * `config`:    Network related configuration such as the MAC and IP address.
+/// * `report`:    Results merged across the receivers of all queues.
 fn setup_recv<S>(
-    ports: Vec<CacheAligned<PortQueue>>,
+    ports: Vec<Port>,
     scheduler: &mut S,
     _core: i32,
     native: bool,
-    send: Vec<CacheAligned<PortQueue>>,
+    send: Vec<Port>,
     config: &config::ClientConfig,
+    report: Arc<Mutex<report::Report>>,
 ) where
     S: Scheduler + Sized,
 {
@@ -550,6 +620,7 @@ fn setup_recv<S>(
         send[0].clone(),
         config.server_udp_ports as u16,
         config,
+        report,
     )) {
         Ok(_) => {
             info!(
@@ -570,6 +641,7 @@ fn main() {
 
     let config = config::ClientConfig::load();
     info!("Starting up Sandstorm client with config {:?}", config);
+    info!("Seeding workloads with {}", report::set_seed(&config));
 
     // Based on the supplied client configuration, compute the amount of time it will take to send
     // out `num_reqs` requests at a rate of `req_rate` requests per second.
@@ -581,38 +653,37 @@ fn main() {
     // Setup the client pipeline.
     net_context.start_schedulers();
 
-    // The core id's which will run the sender and receiver threads.
-    // XXX The following two arrays heavily depend on the set of cores
-    // configured in setup.rs
-    let senders = [0, 2, 4, 6];
-    let receive = [1, 3, 5, 7];
-    assert!((senders.len() == 4) && (receive.len() == 4));
+    // The cores which will run the sender and receiver threads, and the queues they share.
+    let pipelines = setup::pipelines(&config, &net_context);
 
     // Required by AggregateRecv.
     let native = !config.use_invoke;
 
-    // Setup 4 senders and 4 receivers.
-    for i in 0..4 {
-        // First, retrieve a tx-rx queue pair from Netbricks
-        let port = net_context
-            .rx_queues
-            .get(&senders[i])
-            .expect("Failed to retrieve network port!")
-            .clone();
+    // Receivers on every queue merge their results into this once they are done.
+    let report = report::Report::new();
+
+    // Setup a sender and a receiver for every pipeline.
+    for pipeline in pipelines {
+        // First, retrieve the tx-rx queue pair shared by the pipeline.
+        let port = pipeline.port;
+        let send_port = port.clone();
+        let reply = pipeline.reply;
+        let merged = Arc::clone(&report);
 
         // Setup the receive side.
         net_context
             .add_pipeline_to_core(
-                receive[i],
+                pipeline.receiver,
                 Arc::new(
-                    move |send, sched: &mut StandaloneScheduler, core: i32, _sibling| {
+                    move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
                         setup_recv(
                             port.clone(),
                             sched,
                             core,
                             native,
-                            send,
+                            reply.clone(),
                             &config::ClientConfig::load(),
+                            Arc::clone(&merged),
                         )
                     },
                 ),
@@ -622,10 +693,10 @@ fn main() {
         // Setup the send side.
         net_context
             .add_pipeline_to_core(
-                senders[i],
+                pipeline.sender,
                 Arc::new(
-                    move |ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
-                        setup_send(&config::ClientConfig::load(), ports, sched, core)
+                    move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
+                        setup_send(&config::ClientConfig::load(), send_port.clone(), sched, core)
                     },
                 ),
             )
@@ -641,4 +712,9 @@ fn main() {
 
     // Stop the client.
     net_context.stop();
+
+    // Write the client's results out for analysis scripts.
+    if let Err(err) = report.lock().unwrap().emit("tao", &config) {
+        warn!("Failed to write out results: {}", err);
+    }
 }
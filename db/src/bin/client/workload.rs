@@ -0,0 +1,422 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use db::config;
+use db::cycles;
+use db::e2d2::scheduler::Executable;
+use db::transport::Port;
+
+use std::sync::Arc;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use dispatch;
+use pacer;
+use report;
+
+// The priority set on requests in the high priority class. Requests in the other class are sent
+// with the default priority.
+const HIGH_PRIORITY: u8 = 1;
+
+// The tenant that server_info() requests are issued on behalf of during a core sweep. The
+// server does not require it to exist.
+const PROBE_TENANT: u32 = 1;
+
+// The latency in microseconds beyond which a response signals congestion, if not configured.
+const AIMD_TIMEOUT_US: u64 = 1000;
+
+/// Returns the congestion signals that receivers should record, and senders adapt their rate to,
+/// if the client is configured to. Refer to `WorkloadSend::set_congestion()`.
+///
+/// # Arguments
+///
+/// * `config`: Client configuration.
+#[allow(dead_code)]
+pub fn congestion(config: &config::ClientConfig) -> Option<Arc<dispatch::Congestion>> {
+    if !config.aimd {
+        return None;
+    }
+
+    let timeout = if config.aimd_timeout_us == 0 {
+        AIMD_TIMEOUT_US
+    } else {
+        config.aimd_timeout_us
+    };
+
+    Some(dispatch::Congestion::new((cycles::cycles_per_second() * timeout) / 1000000))
+}
+
+/// Returns the warm-up window that senders and receivers should share, if the client is
+/// configured with one. Refer to `WorkloadSend::set_warmup()`.
+///
+/// # Arguments
+///
+/// * `config`: Client configuration.
+#[allow(dead_code)]
+pub fn warmup(config: &config::ClientConfig) -> Option<Arc<dispatch::Warmup>> {
+    if config.warmup_secs == 0 && config.warmup_reqs == 0 {
+        return None;
+    }
+
+    Some(dispatch::Warmup::new(
+        cycles::cycles_per_second() * config.warmup_secs,
+        config.warmup_reqs,
+    ))
+}
+
+/// A single request generated by a workload. Keys, values, and payloads are borrowed from the
+/// workload so that it can reuse it's buffers across requests.
+#[allow(dead_code)]
+pub enum Op<'a> {
+    /// A native get() of `key` from `table`.
+    Get {
+        tenant: u32,
+        table: u64,
+        key: &'a [u8],
+    },
+
+    /// A native put() of `value` under `key` into `table`.
+    Put {
+        tenant: u32,
+        table: u64,
+        key: &'a [u8],
+        value: &'a [u8],
+    },
+
+    /// An invoke() of an extension. The payload begins with the extension's name, which is
+    /// `name_len` bytes long, followed by the extension's arguments.
+    Invoke {
+        tenant: u32,
+        name_len: u32,
+        payload: &'a [u8],
+    },
+}
+
+/// Implemented by request generators that can be driven by a `WorkloadSend`. A workload only
+/// decides what to send; pacing, request classes, and the network are handled by the sender.
+pub trait Workload {
+    /// Returns the name of the workload. Used to label statistics printed by the sender.
+    fn name(&self) -> &'static str;
+
+    /// Generates the next request to be sent out to the server.
+    fn next_op(&mut self) -> Op;
+}
+
+/// Sends out requests generated by a `Workload` to a Sandstorm server at the configured rate.
+pub struct WorkloadSend<W>
+where
+    W: Workload,
+{
+    // The workload deciding what each request should be.
+    workload: W,
+
+    // Network stack required to actually send RPC requests out the network.
+    sender: dispatch::Sender,
+
+    // Total number of requests to be sent out.
+    requests: u64,
+
+    // Number of requests that have been sent out so far.
+    sent: u64,
+
+    // Determines when, and how many requests are to be sent out so that they are generated at
+    // the configured rate.
+    pacer: pacer::Pacer,
+
+    // The percentage of requests sent out at high priority.
+    high_pct: u32,
+
+    // The deadline in microseconds on high priority requests.
+    high_deadline: u32,
+
+    // Decides the class of every request.
+    class_rng: XorShiftRng,
+
+    // If present, requests are sent out as part of a core sweep. Refer to `set_sweep()`.
+    sweep: Option<Arc<dispatch::CoreSweep>>,
+
+    // The time stamp in cycles at which the server was last probed for it's core count.
+    probed: u64,
+
+    // If present, the pacer's rate is adapted to the congestion signalled by responses. Refer
+    // to `set_congestion()`.
+    aimd: Option<(pacer::Aimd, Arc<dispatch::Congestion>)>,
+
+    // If present, requests sent during this warm-up window do not count towards `requests`.
+    // Refer to `set_warmup()`.
+    warmup: Option<Arc<dispatch::Warmup>>,
+}
+
+// Implementation of methods on WorkloadSend.
+impl<W> WorkloadSend<W>
+where
+    W: Workload,
+{
+    /// Constructs a WorkloadSend.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`:    Client configuration with network related (Server and Client MAC address
+    ///                etc.) as well as rate related parameters.
+    /// * `workload`:  The workload that will generate requests.
+    /// * `port`:      Network port over which requests will be sent out.
+    /// * `reqs`:      The number of requests to be issued to the server.
+    /// * `dst_ports`: The total number of UDP ports the server is listening on.
+    ///
+    /// # Return
+    ///
+    /// A request generator for the workload.
+    pub fn new(
+        config: &config::ClientConfig,
+        workload: W,
+        port: Port,
+        reqs: u64,
+        dst_ports: u16,
+    ) -> WorkloadSend<W> {
+        // Track how long requests wait before they are handed to the NIC, so that it can be
+        // reported alongside, or excluded from the latencies measured by the receivers.
+        let mut sender = dispatch::Sender::new(config, port, dst_ports);
+        sender.track_queueing(config.stamp_on_send);
+
+        WorkloadSend {
+            workload: workload,
+            sender: sender,
+            requests: reqs,
+            sent: 0,
+            pacer: pacer::Pacer::new(config.req_rate as u64, config.send_batch as u64),
+            high_pct: config.high_pct as u32,
+            high_deadline: config.high_deadline_us,
+            class_rng: XorShiftRng::from_seed(report::rng_seed()),
+            sweep: None,
+            probed: 0,
+            aimd: None,
+            warmup: None,
+        }
+    }
+
+    /// Adapts the rate requests are sent out at to congestion, starting at the configured rate.
+    /// Refer to `pacer::Aimd`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`:     Client configuration. `aimd_step` sets the rate of increase.
+    /// * `congestion`: Congestion signals recorded by the receivers.
+    #[allow(dead_code)]
+    pub fn set_congestion(
+        &mut self,
+        config: &config::ClientConfig,
+        congestion: Arc<dispatch::Congestion>,
+    ) {
+        let step = if config.aimd_step == 0 {
+            config.req_rate / 100
+        } else {
+            config.aimd_step
+        };
+
+        let aimd = pacer::Aimd::new(config.req_rate as u64, step as u64, congestion.timeout());
+        self.aimd = Some((aimd, congestion));
+    }
+
+    /// Sends requests out as part of a core sweep. Nothing is sent until the server's core count
+    /// is known, and the server is probed for it once a second until then. Requests are then
+    /// split evenly across the sweep's phases, with every request's stamp tagged with the
+    /// phase it was sent in. Refer to `dispatch::CoreSweep`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sweep`: Sweep state shared with the receivers.
+    #[allow(dead_code)]
+    pub fn set_sweep(&mut self, sweep: Arc<dispatch::CoreSweep>) {
+        self.sweep = Some(sweep);
+    }
+
+    /// Starts a warm-up window with the first request sent out. Requests sent during the window
+    /// do not count towards the number of requests to be issued. Refer to `dispatch::Warmup`.
+    ///
+    /// # Arguments
+    ///
+    /// * `warmup`: Warm-up window shared with the receivers.
+    #[allow(dead_code)]
+    pub fn set_warmup(&mut self, warmup: Arc<dispatch::Warmup>) {
+        self.warmup = Some(warmup);
+    }
+
+    /// Picks the phase of a core sweep the next request is sent in, and spreads it across as many
+    /// destination ports as there are server cores in that phase.
+    ///
+    /// # Return
+    ///
+    /// The bits that the request's stamp should be tagged with. Zero outside of a sweep.
+    fn next_phase(&mut self, phases: usize) -> u64 {
+        if phases == 0 {
+            return 0;
+        }
+
+        let phase = ((self.sent * phases as u64) / self.requests) as usize;
+        self.sender.set_dst_ports(dispatch::CoreSweep::cores(phase));
+        dispatch::CoreSweep::tag(phase)
+    }
+
+    /// Picks the class of the next request, and sets it's priority and deadline on the sender.
+    ///
+    /// # Return
+    ///
+    /// The class of the request. Zero for default priority requests, one for high priority.
+    fn next_class(&mut self) -> u64 {
+        if self.high_pct == 0 {
+            return 0;
+        }
+
+        if self.class_rng.gen::<u32>() % 100 < self.high_pct {
+            self.sender.set_class(HIGH_PRIORITY, self.high_deadline);
+            1
+        } else {
+            self.sender.set_class(0, 0);
+            0
+        }
+    }
+}
+
+// The Executable trait allowing WorkloadSend to be scheduled by Netbricks.
+impl<W> Executable for WorkloadSend<W>
+where
+    W: Workload,
+{
+    // Called internally by Netbricks.
+    fn execute(&mut self) {
+        // Return if there are no more requests to generate.
+        if self.requests <= self.sent {
+            return;
+        }
+
+        // During a core sweep, wait until the server has advertised it's core count.
+        let mut phases = 0;
+        if let Some(ref sweep) = self.sweep {
+            phases = sweep.phases();
+            if phases == 0 {
+                let now = cycles::rdtsc();
+                if now - self.probed > cycles::cycles_per_second() {
+                    self.sender.send_server_info(PROBE_TENANT, now);
+                    self.probed = now;
+                }
+                return;
+            }
+        }
+
+        // Adapt the rate to congestion signalled since it was last adjusted.
+        if let Some((ref mut aimd, ref congestion)) = self.aimd {
+            if let Some(rate) = aimd.update(cycles::rdtsc(), congestion.signals()) {
+                self.pacer.set_rate(rate);
+            }
+        }
+
+        // Determine how many requests can be sent out right now without exceeding the configured
+        // rate, and send them out.
+        let credits = self.pacer.credits();
+        for _ in 0..credits {
+            if self.requests <= self.sent {
+                break;
+            }
+
+            // The time stamp on the request. Used to measure latency at the receiver. The least
+            // significant bit carries the class of the request, and the next three the phase of
+            // the core sweep it was sent in.
+            let class = self.next_class();
+            let phase = self.next_phase(phases);
+            let curr = (cycles::rdtsc() & !dispatch::SWEEP_STAMP_MASK) | phase | class;
+
+            match self.workload.next_op() {
+                Op::Get { tenant, table, key } => self.sender.send_get(tenant, table, key, curr),
+
+                Op::Put {
+                    tenant,
+                    table,
+                    key,
+                    value,
+                } => self.sender.send_put(tenant, table, key, value, curr),
+
+                Op::Invoke {
+                    tenant,
+                    name_len,
+                    payload,
+                } => self.sender.send_invoke(tenant, name_len, payload, curr),
+            }
+
+            // Requests sent during the warm-up window do not count towards those asked for.
+            if let Some(ref warmup) = self.warmup {
+                warmup.start();
+                if !warmup.over() {
+                    continue;
+                }
+            }
+
+            self.sent += 1;
+        }
+    }
+
+    fn dependencies(&mut self) -> Vec<usize> {
+        vec![]
+    }
+}
+
+// Implementation of the `Drop` trait on WorkloadSend.
+impl<W> Drop for WorkloadSend<W>
+where
+    W: Workload,
+{
+    fn drop(&mut self) {
+        // Report the offered load so that it can be compared against the configured rate.
+        let (intended, achieved) = self.pacer.rates();
+        println!(
+            "{} Offered {:.0} of {:.0} req/s ({} send slots missed)",
+            self.workload.name(),
+            achieved,
+            intended,
+            self.pacer.forfeited()
+        );
+
+        // Report the rate the sender settled around when adapting to congestion.
+        if let Some((ref aimd, ref congestion)) = self.aimd {
+            let (pushbacks, late) = congestion.counts();
+            match aimd.equilibrium() {
+                Some(rate) => println!(
+                    "{} AIMD Equilibrium {:.0} req/s ({} backoffs, {} pushbacks, {} timeouts)",
+                    self.workload.name(),
+                    rate,
+                    aimd.backoffs(),
+                    pushbacks,
+                    late
+                ),
+
+                None => println!(
+                    "{} AIMD never backed off, raise req_rate or aimd_step",
+                    self.workload.name()
+                ),
+            }
+        }
+
+        // Report the delay between requests being generated and handed to the NIC. If requests
+        // were stamped when sent out, this delay is not part of the latencies measured off them.
+        if let Some((m, t)) = self.sender.queueing() {
+            println!(
+                "{} Send Queueing ({}) {} {}",
+                self.workload.name(),
+                if self.sender.stamps_on_send() { "excluded" } else { "included" },
+                cycles::to_seconds(m) * 1e9,
+                cycles::to_seconds(t) * 1e9
+            );
+        }
+    }
+}
@@ -20,28 +20,92 @@ extern crate rand;
 extern crate time;
 extern crate zipf;
 
+mod coord;
 mod dispatch;
+mod histogram;
+mod pacer;
+mod popularity;
+mod report;
 mod setup;
+mod workload;
 
-use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::mem;
 use std::mem::transmute;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use db::config;
 use db::cycles;
-use db::e2d2::allocators::*;
 use db::e2d2::interface::*;
 use db::e2d2::scheduler::*;
 use db::log::*;
 use db::rpc::*;
+use db::transport::Port;
 use db::wireformat::*;
 
 use rand::distributions::Sample;
 use rand::{Rng, SeedableRng, XorShiftRng};
 use zipf::ZipfDistribution;
 
+use histogram::Histogram;
+use popularity::Popularity;
+use workload::{self, Op, Workload, WorkloadSend};
+
+// The number of request classes latencies are reported for. The class of a request is encoded
+// in the least significant bit of it's stamp, so that it can be recovered from the response.
+const NUM_CLASSES: usize = 2;
+
+// The distribution keys are drawn from: either a parametric Zipfian, or an empirical one loaded
+// from a key popularity file.
+enum KeyDist {
+    Zipf(Box<ZipfDistribution>),
+    Empirical(Popularity),
+}
+
+/// Latency histograms merged across the receivers of all queues. Every receiver merges it's own
+/// histograms in once it is done, and the last one to finish reports the merged latencies.
+struct Latencies {
+    // Histograms of request latencies in cycles, one per request class.
+    classes: Vec<Histogram>,
+
+    // Histograms of request latencies in cycles, one per phase of a core sweep. Empty unless a
+    // core sweep is running.
+    phases: Vec<Histogram>,
+}
+
+// Implementation of methods on Latencies.
+impl Latencies {
+    /// Returns empty histograms that can be shared between receivers.
+    ///
+    /// # Arguments
+    ///
+    /// * `sweep`: If true, latencies are also merged per phase of a core sweep.
+    fn new(sweep: bool) -> Arc<Mutex<Latencies>> {
+        let phases = if sweep { dispatch::SWEEP_MAX_PHASES } else { 0 };
+        Arc::new(Mutex::new(Latencies {
+            classes: (0..NUM_CLASSES).map(|_| Histogram::new()).collect(),
+            phases: (0..phases).map(|_| Histogram::new()).collect(),
+        }))
+    }
+
+    /// Merges the histograms of a receiver in.
+    ///
+    /// # Arguments
+    ///
+    /// * `classes`: The receiver's histograms, one per request class.
+    /// * `phases`:  The receiver's histograms, one per phase of a core sweep.
+    fn merge(&mut self, classes: &[Histogram], phases: &[Histogram]) {
+        for (merged, latencies) in self.classes.iter_mut().zip(classes.iter()) {
+            merged.merge(latencies);
+        }
+
+        for (merged, latencies) in self.phases.iter_mut().zip(phases.iter()) {
+            merged.merge(latencies);
+        }
+    }
+}
+
 // YCSB A, B, and C benchmark.
 // The benchmark is created and parameterized with `new()`. Many threads
 // share the same benchmark instance. Each thread can call `abc()` which
@@ -54,7 +118,7 @@ use zipf::ZipfDistribution;
 pub struct Ycsb {
     put_pct: usize,
     rng: Box<Rng>,
-    key_rng: Box<ZipfDistribution>,
+    key_rng: KeyDist,
     tenant_rng: Box<ZipfDistribution>,
     key_buf: Vec<u8>,
     value_buf: Vec<u8>,
@@ -72,6 +136,8 @@ impl Ycsb {
     //  - skew: Zipfian skew parameter. 0.99 is YCSB default.
     //  - n_tenants: The number of tenants from which the tenant id is chosen.
     //  - tenant_skew: The skew in the Zipfian distribution from which tenant id's are drawn.
+    //  - popularity: If supplied, keys are drawn from this distribution instead of a Zipfian
+    //                distribution over `n_keys` keys with skew `skew`.
     // # Return
     //  A new instance of YCSB that threads can call `abc()` on to run.
     fn new(
@@ -82,8 +148,9 @@ impl Ycsb {
         skew: f64,
         n_tenants: u32,
         tenant_skew: f64,
+        popularity: Option<Popularity>,
     ) -> Ycsb {
-        let seed: [u32; 4] = rand::random::<[u32; 4]>();
+        let seed: [u32; 4] = report::rng_seed();
 
         let mut key_buf: Vec<u8> = Vec::with_capacity(key_len);
         key_buf.resize(key_len, 0);
@@ -93,9 +160,12 @@ impl Ycsb {
         Ycsb {
             put_pct: put_pct,
             rng: Box::new(XorShiftRng::from_seed(seed)),
-            key_rng: Box::new(
-                ZipfDistribution::new(n_keys, skew).expect("Couldn't create key RNG."),
-            ),
+            key_rng: match popularity {
+                Some(popularity) => KeyDist::Empirical(popularity),
+                None => KeyDist::Zipf(Box::new(
+                    ZipfDistribution::new(n_keys, skew).expect("Couldn't create key RNG."),
+                )),
+            },
             tenant_rng: Box::new(
                 ZipfDistribution::new(n_tenants as usize, tenant_skew)
                     .expect("Couldn't create tenant RNG."),
@@ -120,82 +190,69 @@ impl Ycsb {
         G: FnMut(u32, &[u8]) -> R,
         P: FnMut(u32, &[u8], &[u8]) -> R,
     {
+        let (t, is_get) = self.sample();
+
+        if is_get {
+            get(t, self.key_buf.as_slice())
+        } else {
+            put(t, self.key_buf.as_slice(), self.value_buf.as_slice())
+        }
+    }
+
+    // Sample the next operation. The sampled key is written into `self.key_buf`.
+    //
+    // # Return
+    //  A tuple consisting of the tenant the operation should be issued on behalf of, and a
+    //  boolean that is true if the operation is a get, and false if it is a put.
+    fn sample(&mut self) -> (u32, bool) {
         let is_get = (self.rng.gen::<u32>() % 100) >= self.put_pct as u32;
 
         // Sample a tenant.
         let t = self.tenant_rng.sample(&mut self.rng) as u32;
 
         // Sample a key, and convert into a little endian byte array.
-        let k = self.key_rng.sample(&mut self.rng) as u32;
+        let k = match self.key_rng {
+            KeyDist::Zipf(ref mut zipf) => zipf.sample(&mut self.rng) as u32,
+            KeyDist::Empirical(ref popularity) => popularity.sample(&mut self.rng),
+        };
         let k: [u8; 4] = unsafe { transmute(k.to_le()) };
         self.key_buf[0..mem::size_of::<u32>()].copy_from_slice(&k);
 
-        if is_get {
-            get(t, self.key_buf.as_slice())
-        } else {
-            put(t, self.key_buf.as_slice(), self.value_buf.as_slice())
-        }
+        (t, is_get)
     }
 }
 
-/// Sends out YCSB based RPC requests to a Sandstorm server.
-struct YcsbSend {
+/// Generates YCSB based RPC requests for a `WorkloadSend`.
+struct YcsbWorkload {
     // The actual YCSB workload. Required to generate keys and values for get() and put() requests.
-    workload: RefCell<Ycsb>,
-
-    // Network stack required to actually send RPC requests out the network.
-    sender: dispatch::Sender,
-
-    // Total number of requests to be sent out.
-    requests: u64,
-
-    // Number of requests that have been sent out so far.
-    sent: u64,
-
-    // The inverse of the rate at which requests are to be generated. Basically, the time interval
-    // between two request generations in cycles.
-    rate_inv: u64,
-
-    // The time stamp at which the workload started generating requests in cycles.
-    start: u64,
-
-    // The time stamp at which the next request must be issued in cycles.
-    next: u64,
+    ycsb: Ycsb,
 
-    // If true, RPC requests corresponding to native get() and put() operations are sent out. If
-    // false, invoke() based RPC requests are sent out.
+    // If true, RPC requests corresponding to native get() and put() operations are generated. If
+    // false, invoke() based RPC requests are generated.
     native: bool,
 
     // Payload for an invoke() based get operation. Required in order to avoid making intermediate
     // copies of the extension name, table id, and key.
-    payload_get: RefCell<Vec<u8>>,
+    payload_get: Vec<u8>,
 
     // Payload for an invoke() based put operation. Required in order to avoid making intermediate
     // copies of the extension name, table id, key length, key, and value.
-    payload_put: RefCell<Vec<u8>>,
+    payload_put: Vec<u8>,
 }
 
-// Implementation of methods on YcsbSend.
-impl YcsbSend {
-    /// Constructs a YcsbSend.
+// Implementation of methods on YcsbWorkload.
+impl YcsbWorkload {
+    /// Constructs a YcsbWorkload.
     ///
     /// # Arguments
     ///
-    /// * `config`:    Client configuration with YCSB related (key and value length etc.) as well as
-    ///                Network related (Server and Client MAC address etc.) parameters.
-    /// * `port`:      Network port over which requests will be sent out.
-    /// * `reqs`:      The number of requests to be issued to the server.
-    /// * `dst_ports`: The total number of UDP ports the server is listening on.
+    /// * `config`: Client configuration with YCSB related (key and value length etc.)
+    ///             parameters.
     ///
     /// # Return
     ///
     /// A YCSB request generator.
-    fn new(
-        config: &config::ClientConfig,
-        port: CacheAligned<PortQueue>,
-        reqs: u64,
-        dst_ports: u16,
-    ) -> YcsbSend {
+    fn new(config: &config::ClientConfig) -> YcsbWorkload {
         // The payload on an invoke() based get request consists of the extensions name ("get"),
         // the table id to perform the lookup on, and the key to lookup.
         let payload_len = "get".as_bytes().len() + mem::size_of::<u64>() + config.key_len;
@@ -220,8 +277,24 @@ impl YcsbSend {
         });
         payload_put.resize(payload_len, 0);
 
-        YcsbSend {
-            workload: RefCell::new(Ycsb::new(
+        // If configured, draw keys from the popularity file instead of a Zipfian distribution.
+        let popularity = if config.key_popularity_file.is_empty() {
+            None
+        } else {
+            let popularity = Popularity::load(&config.key_popularity_file).unwrap_or_else(|err| {
+                error!("Failed to load {}: {}", config.key_popularity_file, err);
+                std::process::exit(1);
+            });
+            info!(
+                "Sampling keys from {} keys in {}",
+                popularity.len(),
+                config.key_popularity_file
+            );
+            Some(popularity)
+        };
+
+        YcsbWorkload {
+            ycsb: Ycsb::new(
                 config.key_len,
                 config.value_len,
                 config.n_keys,
@@ -229,80 +302,72 @@ impl YcsbSend {
                 config.skew,
                 config.num_tenants,
                 config.tenant_skew,
-            )),
-            sender: dispatch::Sender::new(config, port, dst_ports),
-            requests: reqs,
-            sent: 0,
-            rate_inv: cycles::cycles_per_second() / config.req_rate as u64,
-            start: cycles::rdtsc(),
-            next: 0,
+                popularity,
+            ),
             native: !config.use_invoke,
-            payload_get: RefCell::new(payload_get),
-            payload_put: RefCell::new(payload_put),
+            payload_get: payload_get,
+            payload_put: payload_put,
         }
     }
 }
 
-// The Executable trait allowing YcsbSend to be scheduled by Netbricks.
-impl Executable for YcsbSend {
-    // Called internally by Netbricks.
-    fn execute(&mut self) {
-        // Return if there are no more requests to generate.
-        if self.requests <= self.sent {
-            return;
-        }
-
-        // Get the current time stamp so that we can determine if it is time to issue the next RPC.
-        let curr = cycles::rdtsc();
+// Implementation of the Workload trait so that YCSB requests can be sent out by a WorkloadSend.
+impl Workload for YcsbWorkload {
+    fn name(&self) -> &'static str {
+        "YCSB"
+    }
 
-        // If it is either time to send out a request, or if a request has never been sent out,
-        // then, do so.
-        if curr >= self.next || self.next == 0 {
-            if self.native == true {
-                // Configured to issue native RPCs, issue a regular get()/put() operation.
-                self.workload.borrow_mut().abc(
-                    |tenant, key| self.sender.send_get(tenant, 1, key, curr),
-                    |tenant, key, val| self.sender.send_put(tenant, 1, key, val, curr),
-                );
-            } else {
-                // Configured to issue invoke() RPCs.
-                let mut p_get = self.payload_get.borrow_mut();
-                let mut p_put = self.payload_put.borrow_mut();
-
-                // XXX Heavily dependent on how `Ycsb` creates a key. Only the first four
-                // bytes of the key matter, the rest are zero. The value is always zero.
-                self.workload.borrow_mut().abc(
-                    |tenant, key| {
-                        // First 11 bytes on the payload were already pre-populated with the
-                        // extension name (3 bytes), and the table id (8 bytes). Just write in the
-                        // first 4 bytes of the key.
-                        p_get[11..15].copy_from_slice(&key[0..4]);
-                        self.sender.send_invoke(tenant, 3, &p_get, curr)
-                    },
-                    |tenant, key, _val| {
-                        // First 13 bytes on the payload were already pre-populated with the
-                        // extension name (3 bytes), the table id (8 bytes), and the key length (2
-                        // bytes). Just write in the first 4 bytes of the key. The value is anyway
-                        // always zero.
-                        p_put[13..17].copy_from_slice(&key[0..4]);
-                        self.sender.send_invoke(tenant, 3, &p_put, curr)
-                    },
-                );
+    fn next_op(&mut self) -> Op {
+        let (tenant, is_get) = self.ycsb.sample();
+        let ycsb = &self.ycsb;
+
+        match (self.native, is_get) {
+            // Configured to issue native RPCs, issue a regular get()/put() operation.
+            (true, true) => Op::Get {
+                tenant: tenant,
+                table: 1,
+                key: &ycsb.key_buf,
+            },
+
+            (true, false) => Op::Put {
+                tenant: tenant,
+                table: 1,
+                key: &ycsb.key_buf,
+                value: &ycsb.value_buf,
+            },
+
+            // Configured to issue invoke() RPCs.
+            //
+            // XXX Heavily dependent on how `Ycsb` creates a key. Only the first four bytes of the
+            // key matter, the rest are zero. The value is always zero.
+            (false, true) => {
+                // First 11 bytes on the payload were already pre-populated with the extension
+                // name (3 bytes), and the table id (8 bytes). Just write in the first 4 bytes of
+                // the key.
+                self.payload_get[11..15].copy_from_slice(&ycsb.key_buf[0..4]);
+                Op::Invoke {
+                    tenant: tenant,
+                    name_len: 3,
+                    payload: &self.payload_get,
+                }
             }
 
-            // Update the time stamp at which the next request should be generated, assuming that
-            // the first request was sent out at self.start.
-            self.sent += 1;
-            self.next = self.start + self.sent * self.rate_inv;
+            (false, false) => {
+                // First 13 bytes on the payload were already pre-populated with the extension
+                // name (3 bytes), the table id (8 bytes), and the key length (2 bytes). Just
+                // write in the first 4 bytes of the key. The value is anyway always zero.
+                self.payload_put[13..17].copy_from_slice(&ycsb.key_buf[0..4]);
+                Op::Invoke {
+                    tenant: tenant,
+                    name_len: 3,
+                    payload: &self.payload_put,
+                }
+            }
         }
     }
-
-    fn dependencies(&mut self) -> Vec<usize> {
-        vec![]
-    }
 }
 
-/// Receives responses to YCSB requests sent out by YcsbSend.
+/// Receives responses to YCSB requests generated by YcsbWorkload.
 struct YcsbRecv<T>
 where
     T: PacketTx + PacketRx + Display + Clone + 'static,
@@ -320,11 +385,15 @@ where
     // The total number of responses received so far.
     recvd: u64,
 
-    // Vector of sampled request latencies. Required to calculate distributions once all responses
-    // have been received.
-    latencies: Vec<u64>,
+    // Histograms of sampled request latencies, one per request class. Merged with those of the
+    // other receivers once all responses have been received.
+    latencies: Vec<Histogram>,
+
+    // Latencies merged across the receivers of all queues.
+    merged: Arc<Mutex<Latencies>>,
 
-    // If true, this receiver will make latency measurements.
+    // If true, this receiver breaks latencies down by where requests spent their time at the
+    // server.
     master: bool,
 
     // If true, then responses will be considered to correspond to native gets and puts.
@@ -332,6 +401,32 @@ where
 
     // Time stamp in cycles at which measurement stopped.
     stop: u64,
+
+    // Statistics merged across the receivers of all queues.
+    stats: Arc<dispatch::RecvStats>,
+
+    // If present, the arrival of responses is timed off the NIC's hardware timestamps.
+    clock: Option<cycles::NicClock>,
+
+    // If present, responses are counted per phase of a core sweep, and merged into this once
+    // the receiver is done.
+    sweep: Option<Arc<dispatch::CoreSweep>>,
+
+    // The number of responses received in each phase of a core sweep, along with the time stamps
+    // in cycles of the first and last of them.
+    phases: Vec<(u64, u64, u64)>,
+
+    // Histograms of sampled request latencies, one per phase of a core sweep. Empty unless a
+    // core sweep is running.
+    phase_latencies: Vec<Histogram>,
+
+    // The time sampled requests spent waiting and running at the server, if the server reported
+    // it. Refer to `trace_requests` on the client's configuration.
+    stages: dispatch::StageTimes,
+
+    // If present, responses received during this warm-up window were discarded by `receiver`,
+    // and measurement starts once it is over.
+    warmup: Option<Arc<dispatch::Warmup>>,
 }
 
 // Implementation of methods on YcsbRecv.
@@ -345,24 +440,89 @@ where
     ///
     /// * `port` :  Network port on which responses will be polled for.
     /// * `resps`:  The number of responses to wait for before calculating statistics.
-    /// * `master`: Boolean indicating if the receiver should make latency measurements.
+    /// * `master`: If true, the receiver breaks latencies down by where requests spent their time
+    ///             at the server.
     /// * `native`: If true, responses will be considered to correspond to native gets and puts.
+    /// * `stats`:  Statistics shared with the receivers of other queues.
+    /// * `merged`: Latencies merged across the receivers of all queues.
+    /// * `clock`:  If present, converts hardware timestamps on responses into cycles.
+    /// * `sweep`:  If present, responses are counted per phase of this core sweep.
+    /// * `warmup`: If present, responses are discarded until this warm-up window is over.
     ///
     /// # Return
     ///
     /// A YCSB response receiver that measures the median latency and throughput of a Sandstorm
     /// server.
-    fn new(port: T, resps: u64, master: bool, native: bool) -> YcsbRecv<T> {
+    fn new(
+        port: T,
+        resps: u64,
+        master: bool,
+        native: bool,
+        stats: Arc<dispatch::RecvStats>,
+        merged: Arc<Mutex<Latencies>>,
+        clock: Option<cycles::NicClock>,
+        sweep: Option<Arc<dispatch::CoreSweep>>,
+        warmup: Option<Arc<dispatch::Warmup>>,
+    ) -> YcsbRecv<T> {
+        let phases = if sweep.is_some() {
+            dispatch::SWEEP_MAX_PHASES
+        } else {
+            0
+        };
+
+        let mut receiver = dispatch::Receiver::with_stats(port, HashMap::new(), Arc::clone(&stats));
+        if let Some(ref warmup) = warmup {
+            receiver.set_warmup(Arc::clone(warmup));
+        }
+
         YcsbRecv {
-            receiver: dispatch::Receiver::new(port),
+            receiver: receiver,
             responses: resps,
             start: cycles::rdtsc(),
             recvd: 0,
-            latencies: Vec::with_capacity(resps as usize),
+            latencies: (0..NUM_CLASSES).map(|_| Histogram::new()).collect(),
+            merged: merged,
             master: master,
             native: native,
             stop: 0,
+            stats: stats,
+            clock: clock,
+            sweep: sweep,
+            phases: vec![(0, 0, 0); dispatch::SWEEP_MAX_PHASES],
+            phase_latencies: (0..phases).map(|_| Histogram::new()).collect(),
+            stages: dispatch::StageTimes::new(),
+            warmup: warmup,
+        }
+    }
+
+    /// Records the latency of a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`: The stamp on the request's response.
+    /// * `curr`:  The time stamp in cycles at which the response was received.
+    fn record(&mut self, stamp: u64, curr: u64) {
+        self.latencies[(stamp & 1) as usize].record(curr - stamp);
+
+        if self.sweep.is_some() {
+            self.phase_latencies[dispatch::CoreSweep::phase_of(stamp)].record(curr - stamp);
+        }
+    }
+
+    /// Counts a response towards the phase of the core sweep it's request was sent in.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`: The stamp on the response.
+    fn count(&mut self, stamp: u64) {
+        let now = cycles::rdtsc();
+        let phase = &mut self.phases[dispatch::CoreSweep::phase_of(stamp)];
+
+        if phase.0 == 0 {
+            phase.1 = now;
         }
+        phase.0 += 1;
+        phase.2 = now;
     }
 }
 
@@ -378,26 +538,101 @@ where
             self.recvd as f64 / cycles::to_seconds(self.stop - self.start)
         );
 
-        // Calculate & print median & tail latency only on the master thread.
+        // Merge counts made over a core sweep, and latencies into those of the other receivers.
+        if let Some(ref sweep) = self.sweep {
+            for (phase, &(n, first, last)) in self.phases.iter().enumerate() {
+                sweep.merge(phase, n, first, last);
+            }
+        }
+        self.merged
+            .lock()
+            .unwrap()
+            .merge(&self.latencies, &self.phase_latencies);
+
+        // Break the latencies down by where requests spent their time at the server.
         if self.master {
-            self.latencies.sort();
-
-            let m;
-            let t = self.latencies[(self.latencies.len() * 99) / 100];
-            match self.latencies.len() % 2 {
-                0 => {
-                    let n = self.latencies.len();
-                    m = (self.latencies[n / 2] + self.latencies[(n / 2) + 1]) / 2;
+            if let Some(((qm, qt), (em, et))) = self.stages.quantiles() {
+                println!("YCSB Server Queueing {} {}", qm, qt);
+                println!("YCSB Server Execution {} {}", em, et);
+            }
+        }
+
+        // Once the receivers of all queues are done, print the throughput and latencies across
+        // all of them.
+        if !self.stats.finish() {
+            return;
+        }
+
+        println!(
+            "YCSB Aggregate Throughput {} ({} responses over {} queues)",
+            self.stats.throughput(),
+            self.stats.responses(),
+            self.stats.queues()
+        );
+
+        let merged = self.merged.lock().unwrap();
+
+        // Print the results of every phase of a core sweep, one line per core count.
+        if let Some(ref sweep) = self.sweep {
+            for (phase, latencies) in merged.phases.iter().enumerate() {
+                if !latencies.is_empty() {
+                    sweep.set_latency(phase, latencies.value_at(0.5), latencies.value_at(0.99));
                 }
+            }
 
-                _ => m = self.latencies[self.latencies.len() / 2],
+            for phase in 0..sweep.phases() {
+                let (thrpt, m, t) = sweep.results(phase);
+                println!(
+                    "YCSB Sweep Cores {} of {} Throughput {} Median {} Tail {}",
+                    dispatch::CoreSweep::cores(phase),
+                    sweep.server_cores(),
+                    thrpt,
+                    m,
+                    t
+                );
             }
+        }
 
-            println!(
-                ">>> {} {}",
-                cycles::to_seconds(m) * 1e9,
-                cycles::to_seconds(t) * 1e9
-            );
+        let mut all = Histogram::new();
+        for latencies in merged.classes.iter() {
+            all.merge(latencies);
+        }
+        if all.is_empty() {
+            return;
+        }
+
+        // Hand the distribution over so that it can be merged with that of other clients taking
+        // part in the run.
+        let ns = |l: u64| cycles::to_seconds(l) * 1e9;
+        let quantiles = all
+            .quantiles(coord::QUANTILES)
+            .into_iter()
+            .map(|l| ns(l) as u64)
+            .collect();
+        self.stats.set_latencies(all.count(), quantiles);
+
+        let p: Vec<f64> = all.percentiles().into_iter().map(|l| ns(l)).collect();
+        println!(">>> {} {}", p[0], p[2]);
+        println!(
+            "YCSB Latency ({} samples) p50 {} p90 {} p99 {} p999 {}",
+            all.count(),
+            p[0],
+            p[1],
+            p[2],
+            p[3]
+        );
+
+        // If requests were sent out at different priorities, break latencies out per class.
+        if merged.classes.iter().all(|l| !l.is_empty()) {
+            for (class, latencies) in merged.classes.iter().enumerate() {
+                println!(
+                    "YCSB Class {} ({} samples) {} {}",
+                    if class == 1 { "high" } else { "default" },
+                    latencies.count(),
+                    ns(latencies.value_at(0.5)),
+                    ns(latencies.value_at(0.99))
+                );
+            }
         }
     }
 }
@@ -418,19 +653,54 @@ where
         // If there are packets, sample the latency of the server.
         if let Some(mut packets) = self.receiver.recv_res() {
             while let Some(packet) = packets.pop() {
+                // During a core sweep, the server's core count arrives on the response to a
+                // server_info() RPC. Every other response is counted towards it's phase.
+                if self.sweep.is_some() {
+                    if parse_rpc_opcode(&packet) == OpCode::SandstormServerInfoRpc {
+                        let p = packet.parse_header::<ServerInfoResponse>();
+                        let cores = p.get_header().num_cores;
+                        if let Some(ref sweep) = self.sweep {
+                            if sweep.server_cores() == 0 {
+                                info!("Sweeping upto {} server cores.", cores);
+                            }
+                            sweep.set_server_cores(cores);
+                        }
+                        p.free_packet();
+                        continue;
+                    }
+
+                    self.count(parse_rpc_stamp(&packet));
+                }
+
                 self.recvd += 1;
 
-                // Measure latency on the master client after the first 2 million requests.
-                // The start timestamp is present on the RPC response header.
-                if self.recvd > 2 * 1000 * 1000 && self.master {
-                    let curr = cycles::rdtsc();
+                // Responses received during a warm-up window never make it here, so throughput
+                // is measured from the time it ended.
+                if self.recvd == 1 {
+                    if let Some(ref warmup) = self.warmup {
+                        self.start = warmup.ended().unwrap_or(self.start);
+                    }
+                }
+
+                // Measure latency once warmed up, which without a warm-up window is after the
+                // first 2 million requests. The start timestamp is present on the RPC response
+                // header.
+                if self.warmup.is_some() || self.recvd > 2 * 1000 * 1000 {
+                    // Prefer the time at which the NIC received the response, if it has one.
+                    let curr = match (self.clock.as_mut(), packet.rx_timestamp()) {
+                        (Some(clock), Some(stamp)) => clock.to_cycles(stamp),
+                        _ => cycles::rdtsc(),
+                    };
+                    if self.master {
+                        self.stages.record(&packet);
+                    }
 
                     match self.native {
                         // The response corresponds to an invoke() RPC.
                         false => {
                             let p = packet.parse_header::<InvokeResponse>();
-                            self.latencies
-                                .push(curr - p.get_header().common_header.stamp);
+                            let stamp = p.get_header().common_header.stamp;
+                            self.record(stamp, curr);
                             p.free_packet();
                         }
 
@@ -439,15 +709,15 @@ where
                         true => match parse_rpc_opcode(&packet) {
                             OpCode::SandstormGetRpc => {
                                 let p = packet.parse_header::<GetResponse>();
-                                self.latencies
-                                    .push(curr - p.get_header().common_header.stamp);
+                                let stamp = p.get_header().common_header.stamp;
+                                self.record(stamp, curr);
                                 p.free_packet();
                             }
 
                             OpCode::SandstormPutRpc => {
                                 let p = packet.parse_header::<PutResponse>();
-                                self.latencies
-                                    .push(curr - p.get_header().common_header.stamp);
+                                let stamp = p.get_header().common_header.stamp;
+                                self.record(stamp, curr);
                                 p.free_packet();
                             }
 
@@ -472,18 +742,24 @@ where
     }
 }
 
-/// Sets up YcsbSend by adding it to a Netbricks scheduler.
+/// Sets up a WorkloadSend generating YCSB requests by adding it to a Netbricks scheduler.
 ///
 /// # Arguments
 ///
 /// * `config`:    Network related configuration such as the MAC and IP address.
 /// * `ports`:     Network port on which packets will be sent.
-/// * `scheduler`: Netbricks scheduler to which YcsbSend will be added.
+/// * `scheduler`: Netbricks scheduler to which the sender will be added.
+/// * `sweep`:     If present, requests are sent out as part of this core sweep.
+/// * `congestion`: If present, the sender adapts it's rate to the congestion signalled in here.
+/// * `warmup`:    If present, requests sent during this warm-up window are not counted.
 fn setup_send<S>(
     config: &config::ClientConfig,
-    ports: Vec<CacheAligned<PortQueue>>,
+    ports: Vec<Port>,
     scheduler: &mut S,
     _core: i32,
+    sweep: Option<Arc<dispatch::CoreSweep>>,
+    congestion: Option<Arc<dispatch::Congestion>>,
+    warmup: Option<Arc<dispatch::Warmup>>,
 ) where
     S: Scheduler + Sized,
 {
@@ -492,16 +768,28 @@ fn setup_send<S>(
         std::process::exit(1);
     }
 
-    // Add the sender to a netbricks pipeline.
-    match scheduler.add_task(YcsbSend::new(
+    let mut send = WorkloadSend::new(
         config,
+        YcsbWorkload::new(config),
         ports[0].clone(),
         config.num_reqs as u64,
         config.server_udp_ports as u16,
-    )) {
+    );
+    if let Some(sweep) = sweep {
+        send.set_sweep(sweep);
+    }
+    if let Some(congestion) = congestion {
+        send.set_congestion(config, congestion);
+    }
+    if let Some(warmup) = warmup {
+        send.set_warmup(warmup);
+    }
+
+    // Add the sender to a netbricks pipeline.
+    match scheduler.add_task(send) {
         Ok(_) => {
             info!(
-                "Successfully added YcsbSend with tx queue {}.",
+                "Successfully added YCSB WorkloadSend with tx queue {}.",
                 ports[0].txq()
             );
         }
@@ -519,15 +807,28 @@ fn setup_send<S>(
 ///
 /// * `ports`:     Network port on which packets will be sent.
 /// * `scheduler`: Netbricks scheduler to which YcsbRecv will be added.
-/// * `master`:    If true, the added YcsbRecv will make latency measurements.
+/// * `master`:    If true, the added YcsbRecv breaks latencies down by where requests spent
+///                their time at the server.
 /// * `native`:    If true, the added YcsbRecv will assume that responses correspond to gets
 ///                and puts.
+/// * `nic`:       If true, the arrival of responses is timed off the NIC's hardware timestamps.
+/// * `stats`:     Statistics shared by the receivers of all queues.
+/// * `merged`:    Latencies merged across the receivers of all queues.
+/// * `sweep`:     If present, responses are counted per phase of this core sweep.
+/// * `congestion`: If present, congestion signalled by responses is recorded in here.
+/// * `warmup`:    If present, responses are discarded until this warm-up window is over.
 fn setup_recv<S>(
-    ports: Vec<CacheAligned<PortQueue>>,
+    ports: Vec<Port>,
     scheduler: &mut S,
     _core: i32,
     master: bool,
     native: bool,
+    nic: bool,
+    stats: Arc<dispatch::RecvStats>,
+    merged: Arc<Mutex<Latencies>>,
+    sweep: Option<Arc<dispatch::CoreSweep>>,
+    congestion: Option<Arc<dispatch::Congestion>>,
+    warmup: Option<Arc<dispatch::Warmup>>,
 ) where
     S: Scheduler + Sized,
 {
@@ -536,13 +837,40 @@ fn setup_recv<S>(
         std::process::exit(1);
     }
 
-    // Add the receiver to a netbricks pipeline.
-    match scheduler.add_task(YcsbRecv::new(
+    let clock = match (nic, ports[0].nic()) {
+        (true, Some(nic)) => {
+            let clock = cycles::NicClock::new(nic);
+            if clock.is_none() {
+                warn!("Could not read the clock on port {}, using TSC", nic);
+            }
+            clock
+        }
+
+        (true, None) => {
+            warn!("No NIC to timestamp responses on {}, using TSC", ports[0]);
+            None
+        }
+
+        _ => None,
+    };
+
+    let mut recv = YcsbRecv::new(
         ports[0].clone(),
         34 * 1000 * 1000 as u64,
         master,
         native,
-    )) {
+        stats,
+        merged,
+        clock,
+        sweep,
+        warmup,
+    );
+    if let Some(congestion) = congestion {
+        recv.receiver.set_congestion(congestion);
+    }
+
+    // Add the receiver to a netbricks pipeline.
+    match scheduler.add_task(recv) {
         Ok(_) => {
             info!(
                 "Successfully added YcsbRecv with rx queue {}.",
@@ -562,10 +890,12 @@ fn main() {
 
     let config = config::ClientConfig::load();
     info!("Starting up Sandstorm client with config {:?}", config);
+    info!("Seeding workloads with {}", report::set_seed(&config));
 
     // Based on the supplied client configuration, compute the amount of time it will take to send
-    // out `num_reqs` requests at a rate of `req_rate` requests per second.
-    let exec = config.num_reqs / config.req_rate;
+    // out `num_reqs` requests at a rate of `req_rate` requests per second, after warming up.
+    let warmup = config.warmup_secs as usize + config.warmup_reqs / config.req_rate;
+    let exec = config.num_reqs / config.req_rate + warmup;
 
     // Setup Netbricks.
     let mut net_context = setup::config_and_init_netbricks(&config);
@@ -573,21 +903,35 @@ fn main() {
     // Setup the client pipeline.
     net_context.start_schedulers();
 
-    // The core id's which will run the sender and receiver threads.
-    // XXX The following two arrays heavily depend on the set of cores
-    // configured in setup.rs
-    let senders = [0, 2, 4, 6];
-    let receive = [1, 3, 5, 7];
-    assert!((senders.len() == 4) && (receive.len() == 4));
-
-    // Setup 4 senders, and 4 receivers.
-    for i in 0..4 {
-        // First, retrieve a tx-rx queue pair from Netbricks
-        let port = net_context
-            .rx_queues
-            .get(&senders[i])
-            .expect("Failed to retrieve network port!")
-            .clone();
+    // The cores which will run the sender and receiver threads, and the queues they share.
+    let pipelines = setup::pipelines(&config, &net_context);
+
+    // Receivers on every queue report throughput into these, so that it can be reported for the
+    // client as a whole.
+    let stats = dispatch::RecvStats::new();
+
+    // Receivers on every queue merge their latencies into these once they are done.
+    let latencies = Latencies::new(config.core_sweep);
+
+    // If configured, the senders and receivers of every queue share a core sweep.
+    let sweep = if config.core_sweep {
+        Some(dispatch::CoreSweep::new())
+    } else {
+        None
+    };
+
+    // If configured, the senders adapt their rate to congestion signalled by the responses
+    // received on every queue.
+    let congestion = workload::congestion(&config);
+
+    // If configured, the senders and receivers of every queue share a warm-up window.
+    let warmup = workload::warmup(&config);
+
+    // Setup a sender and a receiver for every pipeline.
+    for (i, pipeline) in pipelines.into_iter().enumerate() {
+        // First, retrieve the tx-rx queue pair shared by the pipeline.
+        let port = pipeline.port;
+        let send_port = port.clone();
 
         let mut master = false;
         if i == 0 {
@@ -595,14 +939,35 @@ fn main() {
         }
 
         let native = !config.use_invoke;
+        let nic = config.nic_timestamps();
+        let stats = Arc::clone(&stats);
+        let merged = Arc::clone(&latencies);
+        let recv_sweep = sweep.clone();
+        let send_sweep = sweep.clone();
+        let recv_congestion = congestion.clone();
+        let send_congestion = congestion.clone();
+        let recv_warmup = warmup.clone();
+        let send_warmup = warmup.clone();
 
         // Setup the receive side.
         net_context
             .add_pipeline_to_core(
-                receive[i],
+                pipeline.receiver,
                 Arc::new(
                     move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
-                        setup_recv(port.clone(), sched, core, master, native)
+                        setup_recv(
+                            port.clone(),
+                            sched,
+                            core,
+                            master,
+                            native,
+                            nic,
+                            Arc::clone(&stats),
+                            Arc::clone(&merged),
+                            recv_sweep.clone(),
+                            recv_congestion.clone(),
+                            recv_warmup.clone(),
+                        )
                     },
                 ),
             ).expect("Failed to initialize receive side.");
@@ -610,10 +975,18 @@ fn main() {
         // Setup the send side.
         net_context
             .add_pipeline_to_core(
-                senders[i],
+                pipeline.sender,
                 Arc::new(
-                    move |ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
-                        setup_send(&config::ClientConfig::load(), ports, sched, core)
+                    move |_ports, sched: &mut StandaloneScheduler, core: i32, _sibling| {
+                        setup_send(
+                            &config::ClientConfig::load(),
+                            send_port.clone(),
+                            sched,
+                            core,
+                            send_sweep.clone(),
+                            send_congestion.clone(),
+                            send_warmup.clone(),
+                        )
                     },
                 ),
             ).expect("Failed to initialize send side.");
@@ -622,6 +995,12 @@ fn main() {
     // Allow the system to bootup fully.
     std::thread::sleep(std::time::Duration::from_secs(1));
 
+    // If running alongside other clients, wait for all of them before starting.
+    let mut coord = coord::Coordinator::new(&config);
+    if let Some(ref mut coord) = coord {
+        coord.start().expect("Failed to synchronize with other clients.");
+    }
+
     // Run the client.
     net_context.execute();
 
@@ -631,6 +1010,36 @@ fn main() {
 
     // Stop the client.
     net_context.stop();
+
+    // Write the client's results out for analysis scripts.
+    let report = report::Report::new();
+    {
+        let mut report = report.lock().unwrap();
+        report.record(stats.responses(), stats.throughput());
+        for latencies in latencies.lock().unwrap().classes.iter() {
+            report.latencies(latencies);
+        }
+        if let Err(err) = report.emit("ycsb", &config) {
+            warn!("Failed to write out results: {}", err);
+        }
+    }
+
+    // Merge results with the other clients, and report them as those of a single run.
+    if let Some(ref mut coord) = coord {
+        let (samples, quantiles) = stats.latencies();
+        let summary = coord::Summary {
+            responses: stats.responses(),
+            throughput: stats.throughput(),
+            samples: samples,
+            quantiles: quantiles,
+        };
+
+        let merged = coord.finish(summary).expect("Failed to merge results with other clients.");
+        println!(
+            "YCSB Merged Throughput {} ({} responses over {} clients) {} {}",
+            merged.throughput, merged.responses, merged.clients, merged.median, merged.tail
+        );
+    }
 }
 
 #[cfg(test)]
@@ -20,6 +20,7 @@ extern crate libc;
 extern crate nix;
 extern crate spin;
 
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
 use std::sync::Arc;
 use std::thread::{sleep, spawn};
 use std::time::Duration;
@@ -28,19 +29,27 @@ use db::log::*;
 
 use db::e2d2::allocators::CacheAligned;
 use db::e2d2::config::{NetbricksConfiguration, PortConfiguration};
+use db::e2d2::interface::dpdk;
 use db::e2d2::interface::*;
 use db::e2d2::native::zcsi;
 use db::e2d2::scheduler::Executable;
 use db::e2d2::scheduler::NetBricksContext as NetbricksContext;
 use db::e2d2::scheduler::*;
 
+use db::backup;
 use db::config;
+use db::crypt;
 use db::cycles::*;
-use db::dispatch::Dispatch;
+use db::dispatch::{express_tx, Checksums, Dispatch, Ipv6Clients};
 use db::install::Installer;
 use db::master::Master;
-use db::sched::RoundRobin;
+use db::sched::{Overflow, RoundRobin};
+use db::snapshot;
+use db::stats::{SchedStats, StatsPusher};
+use db::statspage::StatsPage;
 use db::task::TaskPriority;
+use db::transport::{KernelPort, Port};
+use db::wal::Wal;
 
 use spin::RwLock;
 
@@ -55,9 +64,24 @@ const SCAN_INTERVAL_MS: u64 = 10;
 /// milliseconds.
 const MALICIOUS_LIMIT_MS: f64 = 1f64;
 
+/// Schedulers running an extension whose manifest declared it long running are given this many
+/// times `MALICIOUS_LIMIT_MS` before they are considered compromised.
+const LONG_LIMIT_FACTOR: u64 = 10;
+
+/// Set by the admin signal handler when the database should be in read-only mode. Applied to
+/// Master by the main thread on every scan, since a signal handler cannot safely do much else.
+static READ_ONLY: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Set by the admin signal handler when hot migration's pacing should be re-read from the
+/// server's config. Applied by the migration thread before it's next pass.
+static RELOAD_PACING: AtomicBool = ATOMIC_BOOL_INIT;
+
 /// The identifier of the core that all misbehaving schedulers will be migrated to.
 const GHETTO: u64 = 20;
 
+/// The cores that requests are dispatched and serviced on, one receive queue per core.
+const SERVER_CORES: [i32; 8] = [10, 11, 12, 13, 14, 15, 16, 17];
+
 /// A simple wrapper around the scheduler, allowing it to be added to a Netbricks pipeline.
 struct Server {
     scheduler: Arc<RoundRobin>,
@@ -97,12 +121,14 @@ impl Executable for Server {
 /// of Netbricks.
 fn setup_server<S>(
     config: &config::ServerConfig,
-    ports: Vec<CacheAligned<PortQueue>>,
-    sibling: CacheAligned<PortQueue>,
+    ports: Vec<Port>,
+    sibling: Port,
     scheduler: &mut S,
     core: i32,
     master: &Arc<Master>,
     handles: &Arc<RwLock<Vec<Arc<RoundRobin>>>>,
+    page: &Option<Arc<StatsPage>>,
+    ipv6: &Option<Arc<Ipv6Clients>>,
 ) where
     S: Scheduler + Sized,
 {
@@ -115,18 +141,78 @@ fn setup_server<S>(
     let tid = unsafe { zcsi::get_thread_id() };
 
     // Create a dispatcher for the server if needed.
-    let sched = Arc::new(RoundRobin::new(tid, core));
-    let dispatch = Dispatch::new(
+    let sched = if config.edf_scheduler() {
+        Arc::new(RoundRobin::with_deadlines(tid, core, config.edf_slack_us))
+    } else {
+        Arc::new(RoundRobin::new(tid, core, config.tenant_weights()))
+    };
+    sched.set_budget(config.tenant_budget_us, config.tenant_budget_interval_ms);
+    for group in config.tenant_groups.iter() {
+        sched.set_group_budget(&group.tenants, group.budget_us);
+    }
+
+    let overflow = if config.drop_on_overflow() {
+        Overflow::DropLowest
+    } else {
+        Overflow::Reject
+    };
+    sched.set_queue_limit(config.queue_limit, overflow);
+
+    // If requested, send latency-critical responses out on the core's transmit queue right away.
+    // The dispatcher on this core sends on the same queue, but from the same thread.
+    if config.express_priority > 0 {
+        sched.set_express(
+            express_tx(
+                ports[0].clone(),
+                ipv6.clone(),
+                Checksums::parse(&config.response_checksums),
+            ),
+            config.express_priority,
+            config.express_ab,
+        );
+    }
+
+    // If requested, time the arrival of requests off the NIC's hardware timestamps.
+    // There is no NIC to read a clock off when requests come in over a kernel socket.
+    let clock = match (config.nic_timestamps(), ports[0].nic()) {
+        (true, Some(nic)) => {
+            let clock = NicClock::new(nic);
+            if clock.is_none() {
+                warn!("Could not read the clock on port {}, using TSC", nic);
+            }
+            clock
+        }
+
+        (true, None) => {
+            warn!("No NIC to timestamp requests on {}, using TSC", ports[0]);
+            None
+        }
+
+        _ => None,
+    };
+
+    let mut dispatch = Dispatch::new(
         config,
         ports[0].clone(),
         sibling.clone(),
         Arc::clone(master),
         Arc::clone(&sched),
         ports[0].rxq(),
+        clock,
+        ipv6.clone(),
     );
-    sched.enqueue(Box::new(dispatch));
 
-    // Add the scheduler to the passed in `handles` vector.
+    // If there is a stats page, have the dispatcher publish into the slot for this core.
+    if let Some(ref page) = *page {
+        match SERVER_CORES.iter().position(|c| *c == core).and_then(|i| page.slot(i)) {
+            Some(slot) => dispatch.set_stats_slot(slot),
+            None => warn!("No slot on the stats page for core {}", core),
+        }
+    }
+    sched.enqueue(0, Box::new(dispatch));
+
+    // Add the scheduler to the passed in `handles` vector, and let it steal tasks off the others.
+    sched.set_peers(Arc::clone(handles), config.steal_imbalance);
     handles.write().push(Arc::clone(&sched));
 
     // Add the server to a netbricks pipeline.
@@ -147,6 +233,48 @@ fn setup_server<S>(
     }
 }
 
+/// Returns the ports a server's dispatch thread on a core polls. These are the queues Netbricks
+/// handed to the core, unless requests are received over a kernel socket, in which case every
+/// core polls the same socket, and steals from it too.
+fn server_ports(
+    kernel: &Option<KernelPort>,
+    core: i32,
+    ports: Vec<CacheAligned<PortQueue>>,
+    sibling: CacheAligned<PortQueue>,
+) -> (Vec<Port>, Port) {
+    match *kernel {
+        Some(ref socket) => (
+            vec![Port::Kernel(socket.share(core))],
+            Port::Kernel(socket.share(core)),
+        ),
+
+        None => (ports.into_iter().map(Port::Dpdk).collect(), Port::Dpdk(sibling)),
+    }
+}
+
+/// Binds the kernel socket the server receives requests over, if it was configured to use one.
+/// Exits if the socket could not be bound.
+fn kernel_port(config: &config::ServerConfig) -> Option<KernelPort> {
+    if !config.kernel_transport() {
+        return None;
+    }
+
+    let ip = config
+        .parse_ipv4()
+        .expect("ip_address must be set in the server's config to use the kernel transport.");
+    match KernelPort::bind(config.kernel_addr(), None, config.parse_mac(), ip, 0) {
+        Ok(port) => {
+            info!("Receiving requests over {}", port);
+            Some(port)
+        }
+
+        Err(ref err) => {
+            error!("Failed to bind kernel socket to {}: {}", config.kernel_addr(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
 /// Returns a struct of type NetbricksConfiguration which can be used to
 /// initialize Netbricks with a default set of parameters.
 ///
@@ -156,16 +284,17 @@ fn setup_server<S>(
 /// be initialized as a primary process without any additional arguments. A
 /// single network interface/port with 1 transmit queue, 1 receive queue, 256
 /// receive descriptors, and 256 transmit descriptors will be made available to
-/// Netbricks. Loopback, hardware transmit segementation offload, and hardware
-/// checksum offload will be disabled on this port.
+/// Netbricks. Loopback and hardware transmit segementation offload will be
+/// disabled on this port. So will hardware checksum offload, unless responses
+/// are configured to be checksummed by the NIC. If requests are received over a kernel socket,
+/// no port is made available at all.
 fn get_default_netbricks_config(config: &config::ServerConfig) -> NetbricksConfiguration {
     // General arguments supplied to netbricks.
     let net_config_name = String::from("server");
     let dpdk_secondary: bool = false;
     let net_primary_core: i32 = 19;
-    let net_cores: Vec<i32> = vec![10, 11, 12, 13, 14, 15, 16, 17];
+    let net_cores: Vec<i32> = SERVER_CORES.to_vec();
     let net_strict_cores: bool = true;
-    let net_pool_size: u32 = 8192 - 1;
     let net_cache_size: u32 = 128;
     let net_dpdk_args: Option<String> = None;
 
@@ -177,7 +306,20 @@ fn get_default_netbricks_config(config: &config::ServerConfig) -> NetbricksConfi
     let net_port_txd: i32 = 256;
     let net_port_loopback: bool = false;
     let net_port_tcp_tso: bool = false;
-    let net_port_csum_offload: bool = false;
+    let net_port_csum_offload: bool = config.checksum_offload();
+
+    // With per-core pools, `pool_size` is the size of every core's pool instead of the one
+    // shared by all cores on a socket.
+    let net_pool_size: u32 = if config.per_core_pools {
+        per_core_pool_size(
+            config.mbufs_per_core,
+            net_port_rxd as u32,
+            net_port_txd as u32,
+            net_cache_size,
+        )
+    } else {
+        8192 - 1
+    };
 
     let net_port_config = PortConfiguration {
         name: net_port_name,
@@ -191,7 +333,11 @@ fn get_default_netbricks_config(config: &config::ServerConfig) -> NetbricksConfi
     };
 
     // The set of ports used by netbricks.
-    let net_ports: Vec<PortConfiguration> = vec![net_port_config];
+    let net_ports: Vec<PortConfiguration> = if config.kernel_transport() {
+        vec![]
+    } else {
+        vec![net_port_config]
+    };
 
     NetbricksConfiguration {
         name: net_config_name,
@@ -206,6 +352,40 @@ fn get_default_netbricks_config(config: &config::ServerConfig) -> NetbricksConfi
     }
 }
 
+/// Returns the number of packet buffers in every core's pool when pools are per-core.
+///
+/// Every buffer posted to a core's RX ring, every buffer waiting on it's TX ring, and every
+/// buffer sitting in it's share of the pool's cache comes out of the core's pool. On top of
+/// that, every request outstanding on the core's scheduler holds a request and a response
+/// buffer until it completes. A core receives at most `RX_BURST` requests per poll, and
+/// `RX_BATCHES_IN_FLIGHT` worth of batches are budgeted for, giving
+///
+///     rxd + txd + cache + 2 * RX_BURST * RX_BATCHES_IN_FLIGHT
+///
+/// which is rounded up to one less than a power of two, the size DPDK pools are most memory
+/// efficient at. The defaults (256 + 256 + 128 + 2 * 32 * 32) work out to 4095 per core.
+///
+/// # Arguments
+///
+/// * `configured`: The size from the server's config. Used as is if non-zero.
+/// * `rxd`:        The number of descriptors on every RX ring.
+/// * `txd`:        The number of descriptors on every TX ring.
+/// * `cache`:      The size of the pool's per-core cache.
+fn per_core_pool_size(configured: u32, rxd: u32, txd: u32, cache: u32) -> u32 {
+    // The number of requests a dispatcher receives per poll.
+    const RX_BURST: u32 = 32;
+
+    // The number of received batches whose requests can be outstanding at once.
+    const RX_BATCHES_IN_FLIGHT: u32 = 32;
+
+    if configured > 0 {
+        return configured;
+    }
+
+    let min = rxd + txd + cache + 2 * RX_BURST * RX_BATCHES_IN_FLIGHT;
+    (min + 1).next_power_of_two() - 1
+}
+
 /// This function configures and initializes Netbricks. In the case of a
 /// failure, it causes the program to exit.
 ///
@@ -214,6 +394,32 @@ fn get_default_netbricks_config(config: &config::ServerConfig) -> NetbricksConfi
 fn config_and_init_netbricks(config: &config::ServerConfig) -> NetbricksContext {
     let net_config: NetbricksConfiguration = get_default_netbricks_config(config);
 
+    // Response packets are allocated by dispatchers on every core. Per-core pools keep these
+    // allocations from contending on a pool shared by all cores.
+    dpdk::set_per_core_pools(config.per_core_pools);
+
+    // Timestamping packets on arrival requires timesync to be enabled when ports are started.
+    dpdk::set_hw_timestamps(config.nic_timestamps() && !config.kernel_transport());
+
+    // Dedicate receive queues to tenants that were configured to have one. A kernel socket has
+    // only the one queue.
+    if config.kernel_transport() && !config.tenant_queues.is_empty() {
+        warn!("Tenant queues are ignored when receiving requests over a kernel socket");
+    }
+
+    for steer in config.tenant_queues.iter().filter(|_| !config.kernel_transport()) {
+        let port = config::steered_udp_port(steer.tenant);
+        if !dpdk::steer_udp_port(port, steer.queue) {
+            error!("Failed to steer tenant {} to rx queue {}", steer.tenant, steer.queue);
+            std::process::exit(1);
+        }
+
+        info!(
+            "Steering tenant {} (UDP port {}) to rx queue {}",
+            steer.tenant, port, steer.queue
+        );
+    }
+
     // Initialize Netbricks and return a handle.
     match initialize_system(&net_config) {
         Ok(net_context) => {
@@ -241,6 +447,33 @@ extern "C" fn handle_sigsegv(
     loop {}
 }
 
+/// Signal handler for the read-only admin toggle. SIGUSR1 puts the database into read-only mode,
+/// and SIGUSR2 takes it out.
+extern "C" fn handle_sigusr(signum: i32) {
+    READ_ONLY.store(signum == libc::SIGUSR1, Ordering::SeqCst);
+}
+
+/// Signal handler for SIGHUP, which re-reads hot migration's pacing from server.toml.
+extern "C" fn handle_sighup(_signum: i32) {
+    RELOAD_PACING.store(true, Ordering::SeqCst);
+}
+
+/// This function keeps small values inline in tables if configured to. Values kept inline are
+/// not verified against their checksums, so they are left off the heap only if checksums are
+/// disabled. Must be called before any tenant is created.
+fn set_inline_values(master: &mut Master, config: &config::ServerConfig) {
+    if config.inline_value_max == 0 {
+        return;
+    }
+
+    if config.value_checksums {
+        warn!("Ignoring inline_value_max since value_checksums is enabled.");
+        return;
+    }
+
+    master.set_inline_values(config.inline_value_max);
+}
+
 fn main() {
     // First off, install a signal handler to catch stack overflows. On catching a
     // SIGSEGV, we allocate a new stack to the thread to prevent a segmentation fault
@@ -256,15 +489,224 @@ fn main() {
             .expect("Failed to install custom handler for stack overflow.");
     }
 
+    // Install a handler for the admin signals that toggle read-only mode.
+    let sig_action = signal::SigAction::new(
+        signal::SigHandler::Handler(handle_sigusr),
+        signal::SaFlags::empty(),
+        signal::SigSet::empty(),
+    );
+
+    unsafe {
+        let _ret = signal::sigaction(signal::SIGUSR1, &sig_action)
+            .expect("Failed to install handler for SIGUSR1.");
+        let _ret = signal::sigaction(signal::SIGUSR2, &sig_action)
+            .expect("Failed to install handler for SIGUSR2.");
+    }
+
+    // Install a handler for the admin signal that reloads hot migration's pacing.
+    let sig_action = signal::SigAction::new(
+        signal::SigHandler::Handler(handle_sighup),
+        signal::SaFlags::empty(),
+        signal::SigSet::empty(),
+    );
+
+    unsafe {
+        let _ret = signal::sigaction(signal::SIGHUP, &sig_action)
+            .expect("Failed to install handler for SIGHUP.");
+    }
+
     // Basic setup and initialization.
-    db::env_logger::init().expect("ERROR: failed to initialize logger!");
+    db::logsink::init().expect("ERROR: failed to initialize logger!");
 
     let config = config::ServerConfig::load();
+    if let Err(err) = db::logsink::configure(&config.log_sink, &config.log_dir, config.log_flush_ms)
+    {
+        error!("Failed to configure log sink {}: {}", config.log_sink, err);
+        std::process::exit(1);
+    }
     info!("Starting up Sandstorm server with config {:?}", config);
+    info!(
+        "Config version {:016x}, built from commit {} with features {:#x} (dpdk: {}, pmem: {})",
+        config.version(),
+        config::git_hash(),
+        config::features(),
+        cfg!(feature = "dpdk"),
+        cfg!(feature = "pmem")
+    );
+
+    // If configured, re-attach to the persistent table heap, and rebuild tables from it.
+    let mut recovered = 0;
+    let mut master = if config.heap_file.is_empty() {
+        let mut master = Master::new();
+        if config.value_checksums {
+            master.set_checksums(config.checksum_verify_every);
+        }
+        set_inline_values(&mut master, &config);
+        for table in config.expiring_tables.iter() {
+            master.set_table_ttl(table.tenant, table.table, table.ttl_ms);
+        }
+        master
+    } else {
+        let size = (config.heap_file_mb as usize) * 1024 * 1024;
+        let mut master = match Master::persistent(&config.heap_file, size, config.pmem_heap()) {
+            Ok(master) => master,
+            Err(ref err) => {
+                error!("Failed to map heap file {}: {}", config.heap_file, err);
+                std::process::exit(1);
+            }
+        };
+
+        // Checksums and expiration times change the layout of objects, so they must be enabled
+        // before recovery.
+        if config.value_checksums {
+            master.set_checksums(config.checksum_verify_every);
+        }
+        set_inline_values(&mut master, &config);
+        for table in config.expiring_tables.iter() {
+            master.set_table_ttl(table.tenant, table.table, table.ttl_ms);
+        }
+
+        let durable = config.durable_tables.iter().map(|t| (t.tenant, t.table)).collect();
+        master.set_durable_tables(durable);
+
+        match master.recover() {
+            Ok(n) => recovered = n,
+            Err(ref err) => {
+                error!("Failed to recover heap file {}: {}", config.heap_file, err);
+                std::process::exit(1);
+            }
+        }
+
+        info!("Recovered {} objects from {}", recovered, config.heap_file);
+        master
+    };
+
+    // If configured, rebuild tables from the write-ahead log, and log every write from here on.
+    let wal = if config.wal_dir.is_empty() {
+        None
+    } else {
+        let size_mb = if config.wal_file_mb > 0 {
+            config.wal_file_mb
+        } else {
+            1024
+        };
+        let size = (size_mb as usize) * 1024 * 1024;
+        let group = (config.wal_group_kb as usize) * 1024;
+
+        let wal = match Wal::open(&config.wal_dir, SERVER_CORES.len(), size, group) {
+            Ok(wal) => wal,
+            Err(ref err) => {
+                error!("Failed to open write-ahead log in {}: {}", config.wal_dir, err);
+                std::process::exit(1);
+            }
+        };
+
+        match master.replay_wal(&wal) {
+            Ok(n) => {
+                info!("Replayed {} objects from the log in {}", n, config.wal_dir);
+                recovered += n;
+            }
 
-    let master = Arc::new(Master::new());
+            Err(ref err) => {
+                error!("Failed to replay write-ahead log in {}: {}", config.wal_dir, err);
+                std::process::exit(1);
+            }
+        }
+
+        let durable = config.durable_tables.iter().map(|t| (t.tenant, t.table)).collect();
+        master.set_durable_tables(durable);
+
+        let wal = Arc::new(wal);
+        master.set_wal(Arc::clone(&wal));
+        Some(wal)
+    };
+    let wal_sync_ms = if config.wal_sync_ms > 0 {
+        config.wal_sync_ms
+    } else {
+        10
+    };
+
+    // If configured, load the keys of tenants whose RPC payloads are sealed. Nonces are salted
+    // with a salt persisted across restarts, so that no two runs reuse a nonce.
+    let salt = if config.key_file.is_empty() {
+        None
+    } else {
+        let salt_file = if config.salt_file.is_empty() {
+            format!("{}.salt", config.key_file)
+        } else {
+            config.salt_file.clone()
+        };
+
+        match crypt::next_salt(&salt_file) {
+            Ok(salt) => Some(salt),
+
+            Err(ref err) => {
+                error!("Failed to pick a salt from {}: {}", salt_file, err);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    match crypt::load_keys(&config.key_file, salt) {
+        Ok(keys) => {
+            if !keys.is_empty() {
+                info!("Loaded keys for {} tenants from {}", keys.len(), config.key_file);
+            }
+            master.set_keys(keys);
+        }
 
-    // Create tenants with data and extensions.
+        Err(ref err) => {
+            error!("Failed to load key file {}: {}", config.key_file, err);
+            std::process::exit(1);
+        }
+    }
+
+    // Limit the rate at which tables can be written to, if configured.
+    for limit in config.table_write_limits.iter() {
+        info!(
+            "Throttling writes to table {} of tenant {} at {}/s (burst {})",
+            limit.table, limit.tenant, limit.rate, limit.burst
+        );
+        master.set_write_limit(limit.tenant, limit.table, limit.rate, limit.burst);
+    }
+
+    // Limit the memory the objects of tenant groups can occupy together, if configured. Objects
+    // that were recovered count against the quotas.
+    for group in config.tenant_groups.iter().filter(|group| group.memory_mb > 0) {
+        info!("Limiting tenants {:?} to {} MB of objects", group.tenants, group.memory_mb);
+        let limit = (group.memory_mb as usize) * 1024 * 1024;
+        master.set_group_quota(group.tenants.clone(), limit);
+    }
+    let grouped = config.tenant_groups.iter().any(|group| group.memory_mb > 0);
+
+    // Decide how extension manifests are enforced at install and invocation.
+    master.set_manifest_policy(config.require_manifest, config.long_invoke_limit);
+
+    // Advertise the number of cores requests are serviced on, and the version of the config to
+    // clients.
+    master.set_num_cores(SERVER_CORES.len() as u32);
+    master.set_config_version(config.version());
+
+    // If requested, hint that get() and put() tasks run on the core their key's partition maps to.
+    master.set_key_affinity(config.key_affinity);
+
+    // Cap the number of objects a single scan() can return.
+    master.set_scan_limit(config.scan_limit);
+
+    // If configured, make deletes leave tombstones behind that stale writes are checked against.
+    master.set_tombstone_window(config.tombstone_window_ms);
+
+    // If configured, let a tenant create and delete tenants and tables over the tenant_admin() RPC.
+    master.set_admin_tenant(config.admin_tenant);
+
+    // A handle to every scheduler for pre-emption, and for Master to sum up their counters.
+    let handles = Arc::new(RwLock::new(Vec::with_capacity(8)));
+    master.set_schedulers(Arc::clone(&handles));
+
+    let master = Arc::new(master);
+
+    // Create tenants with data and extensions. Data is only populated if nothing was recovered
+    // from the persistent table heap.
     match config.workload.as_str() {
         "YCSB" => {
             info!(
@@ -272,7 +714,9 @@ fn main() {
                 config.num_tenants, config.num_records
             );
             for tenant in 1..(config.num_tenants + 1) {
-                master.fill_test(tenant, 1, config.num_records);
+                if recovered == 0 {
+                    master.fill_test(tenant, 1, config.num_records);
+                }
                 master.load_test(tenant);
             }
         }
@@ -283,7 +727,9 @@ fn main() {
                 config.num_tenants, config.num_records
             );
             for tenant in 1..(config.num_tenants + 1) {
-                master.fill_tao(tenant, config.num_records);
+                if recovered == 0 {
+                    master.fill_tao(tenant, config.num_records);
+                }
                 master.load_test(tenant);
             }
         }
@@ -294,39 +740,168 @@ fn main() {
                 config.num_tenants, config.num_records
             );
             for tenant in 1..(config.num_tenants + 1) {
-                master.fill_aggregate(tenant, 1, config.num_records);
+                if recovered == 0 {
+                    master.fill_aggregate(tenant, 1, config.num_records);
+                }
                 master.load_test(tenant);
             }
         }
 
         _ => {
             info!("Populating SANITY data for tenant 100");
-            master.fill_test(100, 100, 0);
+            if recovered == 0 {
+                master.fill_test(100, 100, 0);
+            }
             master.load_test(100);
         }
     }
 
+    // If configured, restore the database from the most recent snapshots.
+    if config.snapshot_restore && !config.snapshot_dir.is_empty() {
+        match master.restore(&config.snapshot_dir) {
+            Ok(Some(epoch)) => info!("Restored snapshots upto epoch {}", epoch),
+            Ok(None) => info!("No snapshots to restore in {}", config.snapshot_dir),
+            Err(ref err) => {
+                error!("Failed to restore snapshots: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Build ordered indexes over the tables that can be scanned, now that they are populated.
+    for table in config.ordered_tables.iter() {
+        master.order_table(table.tenant, table.table);
+    }
+
+    // Start up in read-only mode if configured to. Signals received from here on override it.
+    let mut read_only = config.read_only;
+    READ_ONLY.store(read_only, Ordering::SeqCst);
+    master.set_read_only(read_only);
+    if read_only {
+        warn!("Starting up in read-only mode");
+    }
+
     // Setup Netbricks.
     let mut net_context: NetbricksContext = config_and_init_netbricks(&config);
 
-    // A handle to every scheduler for pre-emption.
-    let handles = Arc::new(RwLock::new(Vec::with_capacity(8)));
-
     // Clone `master` and `handle` so that they are still around after the schedulers are
     // initialized.
     let cmaster = Arc::clone(&master);
     let chandle = Arc::clone(&handles);
 
+    // If configured, map the stats page that dispatchers publish their core's counters into.
+    let page = if config.stats_page.is_empty() {
+        None
+    } else {
+        match StatsPage::open(&config.stats_page, SERVER_CORES.len(), cycles_per_second()) {
+            Ok(page) => {
+                info!("Publishing per-core stats to {}", config.stats_page);
+                Some(Arc::new(page))
+            }
+            Err(ref err) => {
+                warn!("Failed to map stats page {}: {}", config.stats_page, err);
+                None
+            }
+        }
+    };
+    let cpage = page.clone();
+
+    // Clients that talk to the server over IPv6 are tracked across all dispatchers, since
+    // requests and their responses can move between cores.
+    let ipv6 = config
+        .parse_ipv6()
+        .map(|addr| Arc::new(Ipv6Clients::new(addr)));
+    let cipv6 = ipv6.clone();
+
+    // If configured, bind the kernel socket every dispatcher receives requests over.
+    let kernel = kernel_port(&config);
+    let ckernel = kernel.clone();
+
     // Copy out the network address that install() RPCs will be received on.
     let install_addr = config.install_addr.clone();
 
+    // Copy out the interval at which hot objects should be migrated, and pace migration.
+    let hot_migrate_secs = config.hot_migrate_secs;
+    master.set_migrate_pacing(
+        (config.hot_migrate_max_kb as usize) * 1024,
+        config.hot_migrate_target_pct as usize,
+    );
+
+    // Copy out how long tombstones are kept for.
+    let tombstone_window_ms = config.tombstone_window_ms;
+
+    // Copy out how often expired objects are swept for. Sweeps default to once a second.
+    let expire_sweep_ms = if config.expire_sweep_ms > 0 {
+        config.expire_sweep_ms
+    } else {
+        1000
+    };
+    let expiring = !config.expiring_tables.is_empty();
+
+    // Copy out how often the memory used by tenant groups is reported. Defaults to once a
+    // second.
+    let group_usage_ms = if config.group_usage_ms > 0 {
+        config.group_usage_ms
+    } else {
+        1000
+    };
+
+    // Copy out the interval at which the database checks itself, and how much it checks.
+    let self_check_secs = config.self_check_secs;
+    let self_check_samples = config.self_check_samples;
+
+    // Copy out where and how often stats are pushed to. Pushes default to once a second.
+    let stats_collector = config.stats_collector.clone();
+    let stats_push_ms = if config.stats_push_ms > 0 {
+        config.stats_push_ms
+    } else {
+        1000
+    };
+
+    // Copy out how imbalanced schedulers must be for tasks to be stolen against their affinity.
+    let steal_imbalance = config.steal_imbalance;
+
+    // Copy out the snapshot directory and intervals.
+    let snapshot_dir = config.snapshot_dir.clone();
+    let snapshot_secs = config.snapshot_secs;
+    let snapshot_full_every = config.snapshot_full_every;
+
+    // Copy out where snapshots are streamed to and received from, and how fast they are streamed.
+    let snapshot_backup = config.snapshot_backup.clone();
+    let snapshot_backup_rate = config.snapshot_backup_kbps * 1024;
+    let snapshot_backup_listen = config.snapshot_backup_listen.clone();
+    let snapshot_backup_dir = config.snapshot_dir.clone();
+
     // Setup the server pipeline.
     net_context.start_schedulers();
-    net_context.add_pipeline_to_run(Arc::new(
+    let pipeline = Arc::new(
         move |ports, scheduler: &mut StandaloneScheduler, core: i32, sibling| {
-            setup_server(&config, ports, sibling, scheduler, core, &cmaster, &chandle)
+            let (ports, sibling) = server_ports(&ckernel, core, ports, sibling);
+            setup_server(
+                &config,
+                ports,
+                sibling,
+                scheduler,
+                core,
+                &cmaster,
+                &chandle,
+                &cpage,
+                &cipv6,
+            )
         },
-    ));
+    );
+
+    // Without a port, Netbricks hands no queues to the cores, so every one is set up explicitly.
+    if kernel.is_some() {
+        for core in SERVER_CORES.iter() {
+            if let Err(ref err) = net_context.add_pipeline_to_core(*core, Arc::clone(&pipeline)) {
+                error!("Error while adding the server to core {}: {}", core, err);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        net_context.add_pipeline_to_run(pipeline);
+    }
 
     // Create a thread to handle the install() RPC request.
     let imaster = Arc::clone(&master);
@@ -340,6 +915,302 @@ fn main() {
         installer.execute();
     });
 
+    // If configured, create a thread to periodically migrate hot objects into dense arenas.
+    if hot_migrate_secs > 0 {
+        let hmaster = Arc::clone(&master);
+        let _migrate = spawn(move || {
+            // Pin to the ghetto core.
+            let tid = unsafe { zcsi::get_thread_id() };
+            unsafe { zcsi::set_affinity(tid, GHETTO) };
+
+            loop {
+                sleep(Duration::from_secs(hot_migrate_secs));
+
+                // Pick up pacing changes made to the config while the server is running. A
+                // config that cannot be parsed leaves the current pacing in place.
+                if RELOAD_PACING.swap(false, Ordering::SeqCst) {
+                    match config::ServerConfig::try_load() {
+                        Ok(config) => {
+                            hmaster.set_migrate_pacing(
+                                (config.hot_migrate_max_kb as usize) * 1024,
+                                config.hot_migrate_target_pct as usize,
+                            );
+                            info!(
+                                "Heap: migration paced at {} KB/pass, {}% hot target",
+                                config.hot_migrate_max_kb, config.hot_migrate_target_pct
+                            );
+                        }
+
+                        Err(e) => {
+                            warn!("Heap: failed to reload config, keeping pacing: {}", e);
+                        }
+                    }
+                }
+
+                let stats = hmaster.migrate_hot();
+                let (objects, bytes) = hmaster.hot_stats();
+                if stats.sampled > 0 {
+                    info!(
+                        "Heap: {} sampled reads, {:.1}% hot, {:.2} pages/read, {} promoted ({} total, {} KB)",
+                        stats.sampled,
+                        (stats.hot as f64 * 100.0) / stats.sampled as f64,
+                        stats.pages as f64 / stats.sampled as f64,
+                        stats.promoted,
+                        objects,
+                        bytes / 1024
+                    );
+                    info!(
+                        "Heap: {} KB moved, {} deferred over budget, {} tables at target",
+                        stats.bytes / 1024,
+                        stats.deferred,
+                        stats.settled
+                    );
+                }
+            }
+        });
+    }
+
+    // If configured, create a thread to periodically check the database for corrupted objects.
+    // The check runs on the ghetto core, so that it never competes with request processing.
+    if self_check_secs > 0 {
+        let vmaster = Arc::clone(&master);
+        let _check = spawn(move || {
+            // Pin to the ghetto core.
+            let tid = unsafe { zcsi::get_thread_id() };
+            unsafe { zcsi::set_affinity(tid, GHETTO) };
+
+            let mut total = 0;
+
+            loop {
+                sleep(Duration::from_secs(self_check_secs));
+
+                let stats = vmaster.self_check(self_check_samples);
+                total += stats.violations;
+                if stats.violations > 0 {
+                    warn!(
+                        "Self-check: {} violations across {} objects in {} tables ({} total)",
+                        stats.violations, stats.sampled, stats.tables, total
+                    );
+                }
+
+                let (verified, corrupt) = vmaster.checksum_stats();
+                if corrupt > 0 {
+                    warn!(
+                        "Self-check: {} of {} reads verified against checksums were corrupted",
+                        corrupt, verified
+                    );
+                }
+            }
+        });
+    }
+
+    // If configured, create a thread to periodically flush out the write-ahead log, so that the
+    // last group of writes on a core that went quiet does not stay unflushed.
+    if let Some(wal) = wal {
+        let _sync = spawn(move || {
+            // Pin to the ghetto core.
+            let tid = unsafe { zcsi::get_thread_id() };
+            unsafe { zcsi::set_affinity(tid, GHETTO) };
+
+            loop {
+                sleep(Duration::from_millis(wal_sync_ms));
+                wal.sync();
+            }
+        });
+    }
+
+    // If configured, create a thread to periodically purge tombstones that have outlived their
+    // window. Tombstones live for between one and two windows.
+    if tombstone_window_ms > 0 {
+        let gmaster = Arc::clone(&master);
+        let _gc = spawn(move || {
+            // Pin to the ghetto core.
+            let tid = unsafe { zcsi::get_thread_id() };
+            unsafe { zcsi::set_affinity(tid, GHETTO) };
+
+            loop {
+                sleep(Duration::from_millis(tombstone_window_ms));
+
+                let (purged, kept) = gmaster.purge_tombstones();
+                if purged > 0 {
+                    debug!("Tombstones: {} purged, {} kept", purged, kept);
+                }
+            }
+        });
+    }
+
+    // If any tenant group has a memory quota, create a thread to periodically report the groups
+    // that are over it.
+    if grouped {
+        let qmaster = Arc::clone(&master);
+        let _quota = spawn(move || {
+            // Pin to the ghetto core.
+            let tid = unsafe { zcsi::get_thread_id() };
+            unsafe { zcsi::set_affinity(tid, GHETTO) };
+
+            loop {
+                sleep(Duration::from_millis(group_usage_ms));
+
+                for (group, (used, limit, refused)) in
+                    qmaster.group_stats().into_iter().enumerate()
+                {
+                    if used >= limit {
+                        debug!(
+                            "Tenant group {} over quota: {}/{} bytes, {} allocations refused",
+                            group, used, limit, refused
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    // If any table has a time-to-live, create a thread to periodically hand a task reclaiming
+    // expired objects to the schedulers, one after the other.
+    if expiring {
+        let emaster = Arc::clone(&master);
+        let ehandles = Arc::clone(&handles);
+        let _expire = spawn(move || {
+            // Pin to the ghetto core.
+            let tid = unsafe { zcsi::get_thread_id() };
+            unsafe { zcsi::set_affinity(tid, GHETTO) };
+
+            let mut next = 0;
+            loop {
+                sleep(Duration::from_millis(expire_sweep_ms));
+
+                let scheds = ehandles.read();
+                if scheds.is_empty() {
+                    continue;
+                }
+
+                if let Some(task) = emaster.reclaim_expired() {
+                    scheds[next % scheds.len()].enqueue(0, task);
+                    next += 1;
+                }
+            }
+        });
+    }
+
+    // If configured, create a thread to periodically snapshot the database. The first snapshot
+    // is always a full one, so that snapshots from earlier runs are never built upon. Snapshots
+    // are streamed to a backup instead of the snapshot directory if one was configured.
+    if snapshot_secs > 0 && (!snapshot_dir.is_empty() || !snapshot_backup.is_empty()) {
+        let smaster = Arc::clone(&master);
+        let _snapshot = spawn(move || {
+            // Pin to the ghetto core.
+            let tid = unsafe { zcsi::get_thread_id() };
+            unsafe { zcsi::set_affinity(tid, GHETTO) };
+
+            let mut epoch = if snapshot_backup.is_empty() {
+                snapshot::read_manifest(&snapshot_dir)
+                    .ok()
+                    .and_then(|entries| entries.last().map(|entry| entry.epoch))
+                    .unwrap_or(0)
+            } else {
+                backup::latest_epoch(&snapshot_backup).unwrap_or_else(|err| {
+                    warn!("Failed to query backup {}: {}", snapshot_backup, err);
+                    0
+                })
+            };
+            let mut taken = 0;
+
+            loop {
+                sleep(Duration::from_secs(snapshot_secs));
+
+                epoch += 1;
+                let full =
+                    taken == 0 || (snapshot_full_every > 0 && taken % snapshot_full_every == 0);
+                let start = rdtsc();
+
+                let res = if snapshot_backup.is_empty() {
+                    smaster.snapshot(&snapshot_dir, epoch, full)
+                } else {
+                    backup::Stream::connect(&snapshot_backup, epoch, snapshot_backup_rate)
+                        .and_then(|stream| snapshot::Writer::with_target(stream, epoch, full))
+                        .and_then(|writer| smaster.snapshot_to(writer))
+                };
+
+                match res {
+                    Ok(entry) => info!(
+                        "Snapshot: epoch {} ({}), {} records in {:.2} s",
+                        entry.epoch,
+                        if entry.full { "full" } else { "incremental" },
+                        entry.records,
+                        to_seconds(rdtsc() - start)
+                    ),
+
+                    Err(ref err) => error!("Snapshot at epoch {} failed: {}", epoch, err),
+                }
+
+                taken += 1;
+            }
+        });
+    }
+
+    // If configured, create a thread to receive snapshots streamed by other servers into the
+    // snapshot directory.
+    if !snapshot_backup_listen.is_empty() && !snapshot_backup_dir.is_empty() {
+        let _backup = spawn(move || {
+            // Pin to the ghetto core.
+            let tid = unsafe { zcsi::get_thread_id() };
+            unsafe { zcsi::set_affinity(tid, GHETTO) };
+
+            match backup::Receiver::new(&snapshot_backup_listen, &snapshot_backup_dir) {
+                Ok(receiver) => receiver.execute(),
+                Err(ref err) => error!(
+                    "Failed to listen for snapshots on {}: {}",
+                    snapshot_backup_listen, err
+                ),
+            }
+        });
+    }
+
+    // If configured, create a thread to periodically push stats to a collector. The pusher runs
+    // on the ghetto core, so that it never competes with request processing.
+    if !stats_collector.is_empty() {
+        let pmaster = Arc::clone(&master);
+        let phandles = Arc::clone(&handles);
+        let _stats = spawn(move || {
+            // Pin to the ghetto core.
+            let tid = unsafe { zcsi::get_thread_id() };
+            unsafe { zcsi::set_affinity(tid, GHETTO) };
+
+            let mut pusher = match StatsPusher::new(&stats_collector) {
+                Ok(pusher) => pusher,
+                Err(ref err) => {
+                    error!("Failed to setup stats pusher to {}: {}", stats_collector, err);
+                    return;
+                }
+            };
+
+            loop {
+                sleep(Duration::from_millis(stats_push_ms));
+
+                let scheds: Vec<SchedStats> = phandles
+                    .read()
+                    .iter()
+                    .map(|sched| {
+                        let (dispatch_busy, dispatch_idle) = sched.dispatch_cycles();
+                        SchedStats {
+                            core: sched.core(),
+                            pending: sched.pending() as u32,
+                            parked: sched.parked() as u32,
+                            completed: sched.completed(),
+                            busy: sched.busy(),
+                            dispatch_busy: dispatch_busy,
+                            dispatch_idle: dispatch_idle,
+                        }
+                    }).collect();
+
+                let reads = pmaster.read_stats();
+                if let Err(ref err) = pusher.push(pmaster.is_read_only(), scheds, reads) {
+                    warn!("Failed to push stats to {}: {}", stats_collector, err);
+                }
+            }
+        });
+    }
+
     // Run the server, and give it some time to bootup.
     net_context.execute();
     sleep(Duration::from_millis(1000));
@@ -352,6 +1223,18 @@ fn main() {
         // Scan schedulers every few milliseconds.
         sleep(Duration::from_millis(SCAN_INTERVAL_MS));
 
+        // Apply any change to read-only mode requested over a signal.
+        let requested = READ_ONLY.load(Ordering::SeqCst);
+        if requested != read_only {
+            read_only = requested;
+            master.set_read_only(read_only);
+            if read_only {
+                warn!("Entered read-only mode, rejecting mutating requests");
+            } else {
+                info!("Left read-only mode");
+            }
+        }
+
         for sched in handles.write().iter_mut() {
             // Get the current time stamp to compare scheduler time stamps against.
             let current = rdtsc();
@@ -365,8 +1248,13 @@ fn main() {
             }
 
             // If this scheduler executed less than "MALICIOUS_LIMIT_MS" milliseconds before, then
-            // continue checking others.
-            if current - latest < limit as u64 {
+            // continue checking others. Extensions declared long running get more leeway.
+            let allowed = if sched.running_long() {
+                limit as u64 * LONG_LIMIT_FACTOR
+            } else {
+                limit as u64
+            };
+            if current - latest < allowed {
                 continue;
             }
 
@@ -387,7 +1275,7 @@ fn main() {
             let mut resps = sched.responses();
 
             // Retain only non-dispatch tasks.
-            tasks.retain(|task| task.priority() != TaskPriority::DISPATCH);
+            tasks.retain(|&(_, ref task)| task.priority() != TaskPriority::DISPATCH);
 
             // Set the compromised flag on the scheduler and then migrate it. Stop the scheduler.
             sched.compromised();
@@ -398,11 +1286,15 @@ fn main() {
             let temp = Arc::new(RwLock::new(Vec::with_capacity(1)));
             let cmaster = Arc::clone(&master);
             let ctemp = Arc::clone(&temp);
+            let cpage = page.clone();
+            let cipv6 = ipv6.clone();
+            let ckernel = kernel.clone();
             net_context.start_scheduler(core);
             let _res = net_context.add_pipeline_to_core(
                 core,
                 Arc::new(
                     move |ports, scheduler: &mut StandaloneScheduler, core: i32, sibling| {
+                        let (ports, sibling) = server_ports(&ckernel, core, ports, sibling);
                         setup_server(
                             &config::ServerConfig::load(),
                             ports,
@@ -411,6 +1303,8 @@ fn main() {
                             core,
                             &cmaster,
                             &ctemp,
+                            &cpage,
+                            &cipv6,
                         )
                     },
                 ),
@@ -428,6 +1322,7 @@ fn main() {
                 .pop()
                 .expect("Failed to retrieve added scheduler.");
             *sched = new;
+            sched.set_peers(Arc::clone(&handles), steal_imbalance);
             sched.enqueue_many(tasks);
             sched.append_resps(&mut resps);
         }
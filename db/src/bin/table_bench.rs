@@ -16,14 +16,17 @@
 extern crate db;
 extern crate time;
 extern crate rand;
+extern crate spin;
 
+use std::collections::HashMap;
 use std::thread;
 use std::sync::{Arc, Barrier};
 use std::time::{Duration, Instant};
 
 use rand::Rng;
-use db::table::Table;
-use db::bytes::{BytesMut, BufMut};
+use spin::RwLock;
+use db::table::{Found, Layout, Table};
+use db::bytes::{Bytes, BytesMut, BufMut};
 
 // The number of iterations to run per thread.
 const N_ITERS : u32 = (1u32 << 24);
@@ -44,6 +47,23 @@ const VALUE : &str =
     concat!("1000000000000000000000000000000000000000000000000000000000000000",
             "0000000000000000000000000000000000000000000000000000000000000000");
 
+// The size of the value on every object in the small value benchmark. Small enough to be kept
+// inline in the table.
+const SMALL_VALUE_LEN : usize = 8;
+
+// The size of the metadata on every object in the small value benchmark. Objects are laid out
+// the way the allocator lays them out by default, with the key length at offset 12 and the
+// version at offset 14.
+const SMALL_META_LEN : usize = 22;
+
+// The size of keys in the fingerprint benchmark.
+const PRINT_KEY_LEN : usize = 30;
+
+// The number of objects in the fingerprint benchmark. Keys spread evenly across the table's 128
+// buckets, so that the hash map of each bucket holds 13107 of them in 16384 slots, a load factor
+// of 80%.
+const PRINT_N_KEYS : u32 = 128 * 13107;
+
 // Converts a Duration type to floating point in seconds.
 //
 // # Arguments
@@ -226,6 +246,246 @@ fn parallel_bench_db(n_threads: usize) -> (Duration, u32) {
         })
 }
 
+// Returns the layout of objects in the small value benchmark.
+fn small_layout() -> Layout {
+    Layout {
+        key_len: 12,
+        meta: SMALL_META_LEN,
+        version: 14,
+        expiry: None,
+    }
+}
+
+// Populates the database with N_ITERS number of objects with small values, laid out the way the
+// allocator would lay them out.
+fn populate_small(db: &Table) {
+    let value = [1u8; SMALL_VALUE_LEN];
+
+    for i in 0..N_ITERS {
+        let key : &[u8] = unsafe {
+            std::slice::from_raw_parts(&i as *const u32 as *const _,
+                                       std::mem::size_of::<u32>())
+        };
+
+        let mut object = BytesMut::with_capacity(SMALL_META_LEN + key.len() +
+                                                 value.len());
+        object.put_slice(&[0; 12]);
+        object.put_u16_le(key.len() as u16);
+        object.put_u64_le(i as u64);
+        object.put_slice(key);
+        object.put_slice(&value);
+        let object = object.freeze();
+
+        let key = object.slice(SMALL_META_LEN, SMALL_META_LEN + key.len());
+        db.put(key, object);
+    }
+}
+
+// Sets up a database that keeps small values on their objects only.
+fn setup_small_heap(db: &mut Table) {
+    populate_small(db);
+}
+
+// Sets up a database that keeps small values inline, next to their key.
+fn setup_small_inline(db: &mut Table) {
+    *db = Table::with_inline(SMALL_VALUE_LEN, small_layout());
+    populate_small(db);
+}
+
+// Reads the first byte of an object's value off the object, the way get() requests on a table
+// without inline values do.
+fn read_small_heap(db: &Table, key: &[u8]) -> u8 {
+    db.get(key).unwrap()[SMALL_META_LEN + key.len()]
+}
+
+// Reads the first byte of an object's value off it's inline copy.
+fn read_small_inline(db: &Table, key: &[u8]) -> u8 {
+    match db.lookup(key) {
+        Some(Found::Inline(inline)) => inline.value()[0],
+        _ => panic!("ERROR: Small value was not kept inline."),
+    }
+}
+
+// This function issues back to back reads of small values against a database.
+//
+// # Arguments
+//
+// * `barrier`: Barrier to wait on before starting the benchmark.
+// * `db`:      The database.
+// * `read`:    The function that reads a value.
+//
+// # Return
+//
+// A tupule of the form (Duration, u32), refer to `parallel_bench()`.
+fn run_small(barrier: Arc<Barrier>, db: Arc<Table>, read: fn (&Table, &[u8]) -> u8)
+    -> (Duration, u32)
+{
+    barrier.wait();
+
+    let start = Instant::now();
+    let mut sum: u64 = 0;
+    for _ in 0..N_ITERS {
+        let v = rand::thread_rng().gen::<u32>() & (N_ITERS - 1);
+        let key : &[u8] = unsafe {
+            std::slice::from_raw_parts(&v as *const u32 as *const _,
+                                       std::mem::size_of::<u32>())
+        };
+
+        sum += read(&db, key) as u64;
+    }
+    let get_time = start.elapsed();
+
+    // Every value is made up of ones, so this also keeps the reads from being optimized away.
+    assert_eq!(N_ITERS as u64, sum);
+
+    (get_time, N_ITERS)
+}
+
+// Returns the key of an object in the fingerprint benchmark. Keys share every byte but the ones
+// that decide their bucket and the last four, so comparing two of them in full reads them end to
+// end.
+fn print_key(i: u32) -> [u8; PRINT_KEY_LEN] {
+    let mut key = [b'k'; PRINT_KEY_LEN];
+    key[0] = i as u8;
+    key[PRINT_KEY_LEN - 4..].copy_from_slice(&[i as u8, (i >> 8) as u8, (i >> 16) as u8,
+                                               (i >> 24) as u8]);
+    key
+}
+
+// Returns the key and object of an object in the fingerprint benchmark.
+fn print_object(i: u32) -> (Bytes, Bytes) {
+    let key = print_key(i);
+    let value = [1u8; SMALL_VALUE_LEN];
+
+    let mut object = BytesMut::with_capacity(key.len() + value.len());
+    object.put_slice(&key);
+    object.put_slice(&value);
+    let mut object = object.freeze();
+
+    let key = object.split_to(PRINT_KEY_LEN);
+    (key, object)
+}
+
+// The buckets of a table the way they were laid out before keys carried a fingerprint: every
+// key is hashed by the bucket's map, on every call into it. The baseline for the fingerprint
+// benchmark.
+struct Unprinted {
+    maps: Vec<RwLock<HashMap<Bytes, Bytes>>>,
+}
+
+impl Unprinted {
+    fn new() -> Unprinted {
+        Unprinted {
+            maps: (0..128).map(|_| RwLock::new(HashMap::new())).collect(),
+        }
+    }
+
+    // Mirrors `Table::get()`.
+    fn get(&self, key: &[u8]) -> Option<Bytes> {
+        let map = self.maps[key[0] as usize & 127].read();
+        map.get(key).cloned()
+    }
+
+    // Mirrors `Table::put()`, which checks for, removes, and inserts the key.
+    fn put(&self, key: Bytes, object: Bytes) {
+        let mut map = self.maps[key[0] as usize & 127].write();
+        if map.contains_key(&key) {
+            let _val = map.remove(&key);
+        }
+        let _obj = map.insert(key, object);
+    }
+}
+
+// The operations the fingerprint benchmark issues, on either layout.
+trait PrintBench: Send + Sync {
+    fn bench_get(&self, key: &[u8]) -> Option<Bytes>;
+    fn bench_put(&self, key: Bytes, object: Bytes);
+}
+
+impl PrintBench for Table {
+    fn bench_get(&self, key: &[u8]) -> Option<Bytes> {
+        self.get(key)
+    }
+
+    fn bench_put(&self, key: Bytes, object: Bytes) {
+        self.put(key, object)
+    }
+}
+
+impl PrintBench for Unprinted {
+    fn bench_get(&self, key: &[u8]) -> Option<Bytes> {
+        self.get(key)
+    }
+
+    fn bench_put(&self, key: Bytes, object: Bytes) {
+        self.put(key, object)
+    }
+}
+
+// This function issues back to back gets, or puts to existing keys, on 30 Byte keys against a
+// database holding PRINT_N_KEYS number of objects, on multiple threads.
+//
+// # Arguments
+//
+// * `n_threads`: The number of threads to run the benchmark on.
+// * `db`:        The database, populated with the objects by this function.
+// * `puts`:      If true, puts are issued instead of gets.
+//
+// # Return
+//
+// A tupule of the form (Duration, u32), refer to `parallel_bench()`.
+fn parallel_bench_prints<T>(n_threads: usize, db: T, puts: bool) -> (Duration, u32)
+    where T: PrintBench + 'static
+{
+    for i in 0..PRINT_N_KEYS {
+        let (key, object) = print_object(i);
+        db.bench_put(key, object);
+    }
+    let db = Arc::new(db);
+
+    let mut threads = Vec::with_capacity(n_threads);
+    let barrier = Arc::new(Barrier::new(n_threads));
+
+    for _ in 0..n_threads {
+        let barrier = barrier.clone();
+        let db = db.clone();
+        threads.push(thread::spawn(move || {
+            // Objects are built before the clock starts, so that only the table is measured.
+            let objects: Vec<(Bytes, Bytes)> = if puts {
+                (0..PRINT_N_KEYS).map(print_object).collect()
+            } else {
+                Vec::new()
+            };
+
+            barrier.wait();
+
+            let start = Instant::now();
+            let mut sum: u64 = 0;
+            for _ in 0..N_ITERS {
+                let v = rand::thread_rng().gen::<u32>() % PRINT_N_KEYS;
+                if puts {
+                    let (ref key, ref object) = objects[v as usize];
+                    db.bench_put(key.clone(), object.clone());
+                    sum += 1;
+                } else {
+                    sum += db.bench_get(&print_key(v)).unwrap()[PRINT_KEY_LEN] as u64;
+                }
+            }
+            let time = start.elapsed();
+
+            // Every value is made up of ones, so this also keeps the reads from being optimized
+            // away.
+            assert_eq!(N_ITERS as u64, sum);
+
+            (time, N_ITERS)
+        }));
+    }
+
+    threads.into_iter().map(|t| t.join().expect("ERROR: Thread join failed."))
+        .fold((Duration::new(0, 0), 0),
+              |(ll, lr), (rl, rr)| (std::cmp::max(ll, rl), lr + rr))
+}
+
 // Baseline to gauge cost of thread-local PRNG. Gets about 100 millions u32s per
 // second per core. Royal can do about 100 million u32's per core per second.
 fn bench_prng_scale() {
@@ -259,11 +519,56 @@ fn bench_db_scale() {
     println!("");
 }
 
+// This function compares the performance of reading small values off their objects with reading
+// them off their inline copy in the table.
+fn bench_small_scale() {
+    // Make sure that the number of iterations is a power of two.
+    assert_eq!(N_ITERS.checked_next_power_of_two(), Some(N_ITERS));
+
+    println!("Benchmarking {} Byte values on objects and inline.", SMALL_VALUE_LEN);
+    for n in 1..N_THREADS+1 {
+        let (heap_time, heap_ops) = parallel_bench(n, setup_small_heap,
+            |barrier, db| { run_small(barrier, db, read_small_heap) });
+        let (inline_time, inline_ops) = parallel_bench(n, setup_small_inline,
+            |barrier, db| { run_small(barrier, db, read_small_inline) });
+
+        println!("{} threads: {:.0} gets/s (object), {:.0} gets/s (inline)", n,
+                 heap_ops as f64 / to_seconds(&heap_time),
+                 inline_ops as f64 / to_seconds(&inline_time));
+    }
+    println!("");
+}
+
+// This function compares gets and puts on 30 Byte keys in a table whose buckets are at a load
+// factor of 80%, with keys hashed by the bucket's map (before) and off their fingerprint (after).
+fn bench_print_scale() {
+    // Make sure that the number of iterations is a power of two.
+    assert_eq!(N_ITERS.checked_next_power_of_two(), Some(N_ITERS));
+
+    println!("Benchmarking {} Byte keys at 80% load factor, before and after fingerprints.",
+             PRINT_KEY_LEN);
+    for n in 1..N_THREADS+1 {
+        for &(puts, op) in [(false, "gets"), (true, "puts")].iter() {
+            let (before, before_ops) = parallel_bench_prints(n, Unprinted::new(), puts);
+            let (after, after_ops) = parallel_bench_prints(n, Table::default(), puts);
+
+            println!("{} threads: {:.0} {}/s (before), {:.0} {}/s (after)", n,
+                     before_ops as f64 / to_seconds(&before), op,
+                     after_ops as f64 / to_seconds(&after), op);
+        }
+    }
+    println!("");
+}
+
 fn main() {
     // Set to true to enable random number generation benchmark.
     let bench_prng: bool = true;
     // Set to true to enable database benchmark.
     let bench_table: bool = true;
+    // Set to true to enable the small value benchmark.
+    let bench_small: bool = true;
+    // Set to true to enable the fingerprint benchmark.
+    let bench_prints: bool = true;
 
     // Benchmark random number generation if enabled.
     if bench_prng {
@@ -274,4 +579,14 @@ fn main() {
     if bench_table {
         bench_db_scale();
     }
+
+    // Benchmark small values kept on objects and inline if enabled.
+    if bench_small {
+        bench_small_scale();
+    }
+
+    // Benchmark gets on 30 Byte keys at 80% load factor if enabled.
+    if bench_prints {
+        bench_print_scale();
+    }
 }
@@ -27,5 +27,27 @@ pub const PACKET_IP_IHL: u8 = 5;
 pub const PACKET_IP_LEN: u16 = 20 + PACKET_UDP_LEN;
 pub const PACKET_ETYPE: u16 = 0x0800;
 
+// The ethertype of ARP packets. ARP requests for the server's IP address are answered by
+// dispatchers, so that clients and switches can resolve the server's MAC address.
+pub const ARP_ETYPE: u16 = 0x0806;
+
+// The ethertype of IPv6 packets, and the length of the IPv6 and UDP headers on them. Requests
+// received over IPv6 are answered over IPv6 by dispatchers.
+pub const PACKET_IPV6_ETYPE: u16 = 0x86DD;
+pub const PACKET_IPV6_LEN: u16 = 40 + PACKET_UDP_LEN;
+
+// The largest IP packet that can be sent between a server and client. Responses larger than
+// this are split across multiple packets (ex: scan()).
+pub const PACKET_MTU: u16 = 1500;
+
+// The number of bytes of packet data held by a single packet buffer (DPDK's default data room).
+pub const PACKET_BUF_LEN: usize = 2048;
+
+// The largest response, starting at it's RPC header, that the server can return. Responses are
+// built in a single packet buffer, behind it's MAC, IP and UDP headers, before they are
+// fragmented to fit the MTU; anything larger fails with `StatusInternalError`. Clients also
+// reassemble fragments into a single buffer.
+pub const MAX_RESPONSE_LEN: usize = PACKET_BUF_LEN - 14 - PACKET_IP_LEN as usize;
+
 // The following are constants required to identify packets sent by the client.
 pub const CLIENT_UDP_PORT: u16 = 0;
@@ -13,14 +13,21 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
 use std::io::Read;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4};
+use std::str::FromStr;
 
 use super::e2d2::headers::*;
 use super::toml;
 
+use libc;
+
+use ring::digest::{digest, SHA256};
+
 #[derive(Debug, Clone)]
 pub struct ParseError;
 
@@ -53,11 +60,7 @@ pub fn parse_mac(mac: &str) -> Result<MacAddress, ParseError> {
 
 /// Load a config from `filename` otherwise return a default structure.
 fn load_config(filename: &str) -> ServerConfig {
-    let mut contents = String::new();
-
-    let _ = File::open(filename).and_then(|mut file| file.read_to_string(&mut contents));
-
-    match toml::from_str(&contents) {
+    match try_load_config(filename) {
         Ok(config) => config,
         Err(e) => {
             warn!("Failure paring config file {}: {}", filename, e);
@@ -66,6 +69,17 @@ fn load_config(filename: &str) -> ServerConfig {
     }
 }
 
+/// Load a config from `filename`, or return why it could not be read or parsed.
+fn try_load_config(filename: &str) -> Result<ServerConfig, String> {
+    let mut contents = String::new();
+
+    File::open(filename)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| e.to_string())?;
+
+    toml::from_str(&contents).map_err(|e| e.to_string())
+}
+
 /// Load a config from `filename` otherwise return a default structure.
 fn load_config_cl(filename: &str) -> ClientConfig {
     let mut contents = String::new();
@@ -88,15 +102,198 @@ fn load_config_cl(filename: &str) -> ClientConfig {
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct ServerConfig {
     mac_address: String,
+    #[serde(default)]
     pub ip_address: String,
     pub udp_port: u16,
     pub nic_pci: String,
-    client_mac: String,
-    pub client_ip: String,
+
+    #[serde(default)]
+    pub ipv6_address: String,
+
+    #[serde(default)]
+    pub transport: String,
+    #[serde(default)]
+    pub kernel_udp_port: u16,
+
     pub num_tenants: u32,
     pub install_addr: String,
     pub workload: String,
     pub num_records: u32,
+
+    #[serde(default)]
+    pub sample_rate: u64,
+    #[serde(default)]
+    pub sample_headers_only: bool,
+    #[serde(default)]
+    pub sample_file: String,
+
+    #[serde(default)]
+    pub rate_alert_factor: f64,
+    #[serde(default)]
+    pub rate_alert_secs: u64,
+
+    #[serde(default)]
+    pub egress_report_secs: u64,
+
+    #[serde(default)]
+    pub bulk_dispatch_polls: u64,
+
+    #[serde(default)]
+    pub group_requests: bool,
+    #[serde(default)]
+    pub group_requests_ab: u64,
+
+    #[serde(default)]
+    pub trace_requests: bool,
+
+    #[serde(default)]
+    pub timestamp_source: String,
+
+    #[serde(default)]
+    pub log_sink: String,
+    #[serde(default)]
+    pub log_dir: String,
+    #[serde(default)]
+    pub log_flush_ms: u64,
+
+    #[serde(default)]
+    pub key_affinity: bool,
+    #[serde(default)]
+    pub steal_imbalance: usize,
+
+    #[serde(default)]
+    pub stats_collector: String,
+    #[serde(default)]
+    pub stats_push_ms: u64,
+    #[serde(default)]
+    pub stats_page: String,
+
+    #[serde(default)]
+    pub per_core_pools: bool,
+    #[serde(default)]
+    pub mbufs_per_core: u32,
+
+    #[serde(default)]
+    pub hot_migrate_secs: u64,
+    #[serde(default)]
+    pub hot_migrate_max_kb: u64,
+    #[serde(default)]
+    pub hot_migrate_target_pct: u64,
+
+    #[serde(default)]
+    pub self_check_secs: u64,
+    #[serde(default)]
+    pub self_check_samples: usize,
+
+    #[serde(default)]
+    pub heap_file: String,
+    #[serde(default)]
+    pub heap_file_mb: u64,
+    #[serde(default)]
+    pub heap_backend: String,
+    #[serde(default)]
+    pub wal_dir: String,
+    #[serde(default)]
+    pub wal_file_mb: u64,
+    #[serde(default)]
+    pub wal_group_kb: u64,
+    #[serde(default)]
+    pub wal_sync_ms: u64,
+    #[serde(default)]
+    pub value_checksums: bool,
+    #[serde(default)]
+    pub checksum_verify_every: u64,
+    #[serde(default)]
+    pub inline_value_max: usize,
+
+    #[serde(default)]
+    pub key_file: String,
+    #[serde(default)]
+    pub salt_file: String,
+
+    #[serde(default)]
+    pub admin_tenant: u32,
+
+    #[serde(default)]
+    pub snapshot_dir: String,
+    #[serde(default)]
+    pub snapshot_secs: u64,
+    #[serde(default)]
+    pub snapshot_full_every: u64,
+    #[serde(default)]
+    pub snapshot_restore: bool,
+    #[serde(default)]
+    pub snapshot_backup: String,
+    #[serde(default)]
+    pub snapshot_backup_kbps: u64,
+    #[serde(default)]
+    pub snapshot_backup_listen: String,
+
+    #[serde(default)]
+    pub read_only: bool,
+
+    #[serde(default)]
+    pub require_manifest: bool,
+    #[serde(default)]
+    pub long_invoke_limit: usize,
+
+    #[serde(default)]
+    pub scan_limit: usize,
+
+    #[serde(default)]
+    pub tombstone_window_ms: u64,
+
+    #[serde(default)]
+    pub expire_sweep_ms: u64,
+
+    #[serde(default)]
+    pub scheduler: String,
+    #[serde(default)]
+    pub edf_slack_us: u64,
+
+    #[serde(default)]
+    pub inline_short_ns: u64,
+
+    #[serde(default)]
+    pub express_priority: u8,
+    #[serde(default)]
+    pub express_ab: u64,
+
+    #[serde(default)]
+    pub tenant_budget_us: u64,
+    #[serde(default)]
+    pub tenant_budget_interval_ms: u64,
+
+    #[serde(default)]
+    pub response_checksums: String,
+
+    #[serde(default)]
+    pub queue_limit: usize,
+    #[serde(default)]
+    pub queue_overflow: String,
+
+    #[serde(default)]
+    pub tenant_weights: Vec<TenantWeight>,
+
+    #[serde(default)]
+    pub group_usage_ms: u64,
+    #[serde(default)]
+    pub tenant_groups: Vec<TenantGroup>,
+
+    #[serde(default)]
+    pub tenant_queues: Vec<TenantQueue>,
+
+    #[serde(default)]
+    pub table_write_limits: Vec<TableWriteLimit>,
+
+    #[serde(default)]
+    pub durable_tables: Vec<DurableTable>,
+
+    #[serde(default)]
+    pub ordered_tables: Vec<OrderedTable>,
+
+    #[serde(default)]
+    pub expiring_tables: Vec<ExpiringTable>,
 }
 
 impl ServerConfig {
@@ -106,6 +303,13 @@ impl ServerConfig {
         load_config("server.toml")
     }
 
+    /// Load server config from server.toml file in the current directory, or return why it could
+    /// not be read or parsed. Used to reload the config while the server is running, when falling
+    /// back to the defaults would silently undo settings already in effect.
+    pub fn try_load() -> Result<ServerConfig, String> {
+        try_load_config("server.toml")
+    }
+
     /// Parse `mac_address` into NetBrick's format or panic if malformed.
     /// Linear time, so ideally we'd store this in ServerConfig, but TOML parsing makes that tricky.
     pub fn parse_mac(&self) -> MacAddress {
@@ -113,12 +317,203 @@ impl ServerConfig {
             .expect("Missing or malformed mac_address field in server config.")
     }
 
-    /// Parse `client_mac` into NetBrick's format or panic if malformed.
-    /// Linear time, so ideally we'd store this in ServerConfig, but TOML parsing makes that tricky.
-    pub fn parse_client_mac(&self) -> MacAddress {
-        parse_mac(&self.client_mac)
-            .expect("Missing or malformed mac_address field in server config.")
+    /// Parse `ip_address` into the server's IPv4 address (in host order) or panic if malformed.
+    /// None if `ip_address` is empty, in which case the server is not reachable over IPv4.
+    pub fn parse_ipv4(&self) -> Option<u32> {
+        if self.ip_address.is_empty() {
+            return None;
+        }
+
+        let server = Ipv4Addr::from_str(&self.ip_address)
+            .expect("Malformed ip_address field in server config.");
+        Some(u32::from(server))
+    }
+
+    /// Parse `ipv6_address` into the server's IPv6 address or panic if malformed. None if
+    /// `ipv6_address` is empty, in which case the server is not reachable over IPv6.
+    pub fn parse_ipv6(&self) -> Option<[u8; 16]> {
+        if self.ipv6_address.is_empty() {
+            return None;
+        }
+
+        let server = Ipv6Addr::from_str(&self.ipv6_address)
+            .expect("Malformed ipv6_address field in server config.");
+        Some(server.octets())
+    }
+
+    /// Returns a version identifying this config: the leading 8 bytes of a SHA-256 digest over
+    /// every option in it. Servers running with the same options report the same version,
+    /// regardless of how their server.toml was laid out.
+    pub fn version(&self) -> u64 {
+        let hash = digest(&SHA256, format!("{:?}", self).as_bytes());
+        hash.as_ref()[..8]
+            .iter()
+            .fold(0, |version, byte| (version << 8) | *byte as u64)
+    }
+
+    /// Returns true if latencies should be measured off hardware timestamps taken by the NIC.
+    pub fn nic_timestamps(&self) -> bool {
+        self.timestamp_source == "nic"
+    }
+
+    /// Returns true if requests are received over a UDP socket of the kernel instead of a NIC
+    /// driven by DPDK.
+    pub fn kernel_transport(&self) -> bool {
+        self.transport == "kernel"
+    }
+
+    /// Returns the address the server's kernel socket is bound to: every interface, on
+    /// `kernel_udp_port` or `KERNEL_UDP_PORT` if that is zero.
+    pub fn kernel_addr(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), kernel_udp_port(self.kernel_udp_port))
+    }
+
+    /// Returns true if the heap file is on persistent memory, and puts must be flushed to it.
+    pub fn pmem_heap(&self) -> bool {
+        self.heap_backend == "pmem"
+    }
+
+    /// Returns true if the NIC should checksum responses on transmit.
+    pub fn checksum_offload(&self) -> bool {
+        self.response_checksums == "offload"
+    }
+
+    /// Returns true if tasks should be scheduled in the order of their deadlines, instead of
+    /// sharing the CPU between tenants by weight.
+    pub fn edf_scheduler(&self) -> bool {
+        self.scheduler == "edf"
+    }
+
+    /// Returns true if waiting tasks should be dropped to make room for new requests once a
+    /// core's run-queues are full, instead of turning the new requests away.
+    pub fn drop_on_overflow(&self) -> bool {
+        self.queue_overflow == "drop"
+    }
+
+    /// Returns a map from tenant identifier to the tenant's share of the CPU on every core.
+    pub fn tenant_weights(&self) -> HashMap<u32, u64> {
+        self.tenant_weights
+            .iter()
+            .map(|w| (w.tenant, w.weight))
+            .collect()
+    }
+}
+
+/// The weight of a tenant on the server's schedulers. A tenant with weight two is entitled to
+/// twice the CPU time of a tenant with weight one when both have work to do.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TenantWeight {
+    pub tenant: u32,
+    pub weight: u64,
+}
+
+/// A group of tenants (ex: every tenant of one customer) that share quotas on top of the limits
+/// of each tenant. `memory_mb` caps the memory the objects of the group's tenants occupy
+/// together, and `budget_us` caps the CPU time their tasks run for together on every core each
+/// budget interval (refer to `tenant_budget_interval_ms`). Zero leaves either unlimited. A
+/// tenant belongs to at most one group.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TenantGroup {
+    pub tenants: Vec<u32>,
+    #[serde(default)]
+    pub memory_mb: u64,
+    #[serde(default)]
+    pub budget_us: u64,
+}
+
+/// A receive queue dedicated to a tenant. The NIC steers every request the tenant sends to it's
+/// steered UDP port (refer to `steered_udp_port()`) to the queue, and the queue receives no
+/// requests from other tenants. `queue` is an index into the server's receive queues, which are
+/// assigned to cores in order.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TenantQueue {
+    pub tenant: u32,
+    pub queue: i32,
+}
+
+/// The maximum rate at which put() requests can write to a tenant's table. Writes are admitted
+/// by a token bucket holding upto `burst` writes and refilling at `rate` writes per second;
+/// writes that find the bucket empty are rejected with `StatusThrottled`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TableWriteLimit {
+    pub tenant: u32,
+    pub table: u64,
+    pub rate: u64,
+    pub burst: u64,
+}
+
+/// A table whose objects are persisted into the heap file. If any table is listed, objects of
+/// tables that are not are kept in memory only.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct DurableTable {
+    pub tenant: u32,
+    pub table: u64,
+}
+
+/// A table that maintains an ordered index over it's keys, and can be scanned with the scan()
+/// RPC. Tables that are not listed reject scan() requests with `StatusInvalidOperation`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct OrderedTable {
+    pub tenant: u32,
+    pub table: u64,
+}
+
+/// A table whose objects expire `ttl_ms` milliseconds after they were written. Expired objects
+/// are treated as missing by reads, and are reclaimed in the background.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ExpiringTable {
+    pub tenant: u32,
+    pub table: u64,
+    pub ttl_ms: u64,
+}
+
+/// Returns the UDP port that requests from a tenant with a dedicated receive queue are sent to.
+/// These ports lie above the range any server's default UDP ports can occupy.
+pub fn steered_udp_port(tenant: u32) -> u16 {
+    0x8000 | (tenant & 0x7fff) as u16
+}
+
+/// The UDP port the server's kernel socket is bound to if none was configured.
+pub const KERNEL_UDP_PORT: u16 = 7800;
+
+// Returns the UDP port of the server's kernel socket, given the configured one.
+fn kernel_udp_port(port: u16) -> u16 {
+    if port == 0 {
+        KERNEL_UDP_PORT
+    } else {
+        port
+    }
+}
+
+/// The bit set in `features()` if the server was built against Netbricks and DPDK.
+pub const FEATURE_DPDK: u32 = 1 << 0;
+
+/// The bit set in `features()` if the server was built to flush persistent memory through
+/// libpmem.
+pub const FEATURE_PMEM: u32 = 1 << 1;
+
+/// Returns the git commit the server was built from, as recorded by the build script. "unknown"
+/// if it was not built from a git checkout.
+pub fn git_hash() -> &'static str {
+    option_env!("SANDSTORM_GIT_HASH").unwrap_or("unknown")
+}
+
+/// Returns the leading 16 hex digits of `git_hash()` as an integer, or zero if it is unknown.
+pub fn git_hash_prefix() -> u64 {
+    let hash = git_hash();
+    u64::from_str_radix(&hash[..hash.len().min(16)], 16).unwrap_or(0)
+}
+
+/// Returns the cargo features the server was built with, as a set of `FEATURE_*` bits.
+pub fn features() -> u32 {
+    let mut features = 0;
+    if cfg!(feature = "dpdk") {
+        features |= FEATURE_DPDK;
     }
+    if cfg!(feature = "pmem") {
+        features |= FEATURE_PMEM;
+    }
+    features
 }
 
 /// All of the various configuration options needed to run a client, both optional and required.
@@ -130,14 +525,30 @@ pub struct ClientConfig {
     mac_address: String,
     pub ip_address: String,
     pub nic_pci: String,
+    #[serde(default)]
+    pub sender_cores: Vec<i32>,
+    #[serde(default)]
+    pub receiver_cores: Vec<i32>,
+    #[serde(default)]
+    pub queues_per_core: usize,
 
     server_mac_address: String,
     pub server_ip_address: String,
     pub server_udp_ports: u16,
+    #[serde(default)]
+    pub transport: String,
+    #[serde(default)]
+    pub server_kernel_udp_port: u16,
     pub num_tenants: u32,
     pub install_addr: String,
 
     pub use_invoke: bool,
+    #[serde(default)]
+    pub key_file: String,
+    #[serde(default)]
+    pub steered_tenants: Vec<u32>,
+    #[serde(default)]
+    pub timestamp_source: String,
 
     pub key_len: usize,
     pub value_len: usize,
@@ -145,9 +556,33 @@ pub struct ClientConfig {
     pub put_pct: usize,
     pub skew: f64,
     pub tenant_skew: f64,
+    #[serde(default)]
+    pub key_popularity_file: String,
+    #[serde(default)]
+    pub core_sweep: bool,
 
     pub num_reqs: usize,
     pub req_rate: usize,
+    #[serde(default)]
+    pub warmup_secs: u64,
+    #[serde(default)]
+    pub warmup_reqs: usize,
+    #[serde(default)]
+    pub send_batch: usize,
+    #[serde(default)]
+    pub high_pct: usize,
+    #[serde(default)]
+    pub high_deadline_us: u32,
+    #[serde(default)]
+    pub stamp_on_send: bool,
+    #[serde(default)]
+    pub trace_requests: bool,
+    #[serde(default)]
+    pub aimd: bool,
+    #[serde(default)]
+    pub aimd_step: usize,
+    #[serde(default)]
+    pub aimd_timeout_us: u64,
 
     pub num_aggr: u32,
     pub order: u32,
@@ -159,6 +594,22 @@ pub struct ClientConfig {
     pub yield_f: u8,
 
     pub bad_ptm: usize,
+
+    #[serde(default)]
+    pub load_window: usize,
+    #[serde(default)]
+    pub load_verify: usize,
+
+    #[serde(default)]
+    pub coord_addr: String,
+    #[serde(default)]
+    pub coord_leader: bool,
+    #[serde(default)]
+    pub coord_clients: usize,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub results_file: String,
 }
 
 impl ClientConfig {
@@ -175,17 +626,121 @@ impl ClientConfig {
             .expect("Missing or malformed mac_address field in client config.")
     }
 
+    /// Returns true if latencies should be measured off hardware timestamps taken by the NIC.
+    pub fn nic_timestamps(&self) -> bool {
+        self.timestamp_source == "nic"
+    }
+
+    /// Returns true if requests are sent over UDP sockets of the kernel instead of a NIC driven
+    /// by DPDK.
+    pub fn kernel_transport(&self) -> bool {
+        self.transport == "kernel"
+    }
+
+    /// Returns the address of the server's kernel socket (refer to `ServerConfig::kernel_addr()`)
+    /// or panic if `server_ip_address` is malformed.
+    pub fn server_kernel_addr(&self) -> SocketAddrV4 {
+        let server = Ipv4Addr::from_str(&self.server_ip_address)
+            .expect("Malformed server_ip_address field in client config.");
+        SocketAddrV4::new(server, kernel_udp_port(self.server_kernel_udp_port))
+    }
+
     /// Parse `server_mac_address` into NetBrick's format or panic if malformed.
     /// Linear time, so ideally we'd store this in ClientConfig, but TOML parsing makes that tricky.
     pub fn parse_server_mac(&self) -> MacAddress {
         parse_mac(&self.server_mac_address)
             .expect("Missing or malformed server_mac_address field in client config.")
     }
+
+    /// Returns the cores that run the client's senders. Cores 0, 2, 4 and 6 if none were
+    /// configured.
+    pub fn senders(&self) -> Vec<i32> {
+        if self.sender_cores.is_empty() {
+            vec![0, 2, 4, 6]
+        } else {
+            self.sender_cores.clone()
+        }
+    }
+
+    /// Returns the cores that run the client's receivers, one per sender core. Cores 1, 3, 5 and
+    /// 7 if none were configured.
+    pub fn receivers(&self) -> Vec<i32> {
+        if self.receiver_cores.is_empty() {
+            vec![1, 3, 5, 7]
+        } else {
+            self.receiver_cores.clone()
+        }
+    }
+
+    /// Returns the number of transmit and receive queues opened on every sender core.
+    pub fn queues(&self) -> usize {
+        self.queues_per_core.max(1)
+    }
+
+    /// Returns the client's pipelines, one per queue. Each pipeline consists of a sender and a
+    /// receiver sharing a queue, and is identified by the cores they run on. The pipelines of a
+    /// sender core all run their receivers on the receiver core at the same index.
+    pub fn pipelines(&self) -> Vec<(i32, i32)> {
+        let mut pipelines = Vec::new();
+        for (send, recv) in self.senders().into_iter().zip(self.receivers().into_iter()) {
+            for _ in 0..self.queues() {
+                pipelines.push((send, recv));
+            }
+        }
+
+        pipelines
+    }
+
+    /// Checks that the client's pipelines can be laid out on the machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary`:   The core running the client's main thread. Cannot run a pipeline.
+    /// * `available`: The number of cores available on the machine.
+    ///
+    /// # Return
+    ///
+    /// An error describing the first problem found with the configured cores, if any.
+    pub fn check_pipelines(&self, primary: i32, available: usize) -> Result<(), String> {
+        let senders = self.senders();
+        let receivers = self.receivers();
+        if senders.len() != receivers.len() {
+            return Err(format!(
+                "{} sender cores configured, but {} receiver cores",
+                senders.len(),
+                receivers.len()
+            ));
+        }
+
+        let mut used = Vec::new();
+        for core in Some(primary).iter().chain(senders.iter()).chain(receivers.iter()) {
+            if *core < 0 || *core as usize >= available {
+                return Err(format!("core {} is not one of the {} available", core, available));
+            }
+
+            if used.contains(core) {
+                return Err(format!("core {} is configured to run more than once", core));
+            }
+            used.push(*core);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the number of cores that are online on the machine.
+pub fn available_cores() -> usize {
+    let cores = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    if cores > 0 {
+        cores as usize
+    } else {
+        1
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse_mac;
+    use super::{parse_mac, steered_udp_port, ClientConfig, ServerConfig, KERNEL_UDP_PORT};
 
     #[test]
     fn empty_str() {
@@ -230,4 +785,71 @@ mod tests {
         }
     }
 
+    #[test]
+    fn steered_ports() {
+        assert_eq!(0x8001, steered_udp_port(1));
+        assert_eq!(0xffff, steered_udp_port(0x7fff));
+        assert!(steered_udp_port(0) >= 0x8000);
+    }
+
+    #[test]
+    fn kernel_transport() {
+        let mut server = ServerConfig::default();
+        assert!(!server.kernel_transport());
+        assert_eq!(KERNEL_UDP_PORT, server.kernel_addr().port());
+
+        server.transport = String::from("kernel");
+        server.kernel_udp_port = 9000;
+        assert!(server.kernel_transport());
+        assert_eq!("0.0.0.0:9000", server.kernel_addr().to_string());
+
+        let mut client = ClientConfig::default();
+        client.transport = String::from("kernel");
+        client.server_ip_address = String::from("10.0.0.2");
+        assert!(client.kernel_transport());
+        assert_eq!("10.0.0.2:7800", client.server_kernel_addr().to_string());
+    }
+
+    #[test]
+    fn address_families() {
+        let mut config = ServerConfig::default();
+        config.ipv6_address = String::from("fd00::2");
+        assert_eq!(None, config.parse_ipv4());
+
+        let server = config.parse_ipv6().expect("Missing IPv6 address.");
+        assert_eq!([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2], server);
+
+        config.ip_address = String::from("10.0.0.2");
+        assert_eq!(Some(0x0a000002), config.parse_ipv4());
+    }
+
+    #[test]
+    fn config_version() {
+        let mut config = ServerConfig::default();
+        let version = config.version();
+        assert_eq!(version, ServerConfig::default().version());
+
+        config.udp_port = 1;
+        assert!(version != config.version());
+    }
+
+    #[test]
+    fn client_pipelines() {
+        let mut config = ClientConfig::default();
+        assert_eq!(vec![(0, 1), (2, 3), (4, 5), (6, 7)], config.pipelines());
+        assert!(config.check_pipelines(9, 10).is_ok());
+        assert!(config.check_pipelines(9, 8).is_err());
+        assert!(config.check_pipelines(4, 10).is_err());
+
+        config.sender_cores = vec![3, 5];
+        config.receiver_cores = vec![4, 6];
+        config.queues_per_core = 2;
+        assert_eq!(vec![(3, 4), (3, 4), (5, 6), (5, 6)], config.pipelines());
+        assert!(config.check_pipelines(0, 7).is_ok());
+
+        config.receiver_cores = vec![4];
+        assert!(config.check_pipelines(0, 7).is_err());
+        config.receiver_cores = vec![4, 3];
+        assert!(config.check_pipelines(0, 7).is_err());
+    }
 }
@@ -24,7 +24,7 @@ use super::context::Context;
 use super::cycles;
 use super::ext::Extension;
 use super::task::TaskState::*;
-use super::task::{Task, TaskPriority, TaskState};
+use super::task::{CostClass, Task, TaskPriority, TaskState};
 
 use e2d2::common::EmptyMetadata;
 use e2d2::headers::UdpHeader;
@@ -58,6 +58,10 @@ pub struct Container {
     // The actual generator/coroutine containing the extension's code to be
     // executed inside the database.
     gen: Box<Generator<Yield = u64, Return = u64>>,
+
+    // The time stamp in cycles by which the task should complete. Refer to
+    // `Task::deadline()`.
+    deadline: Option<u64>,
 }
 
 // Implementation of methods on Container.
@@ -78,6 +82,12 @@ impl Container {
     ///
     /// A container that when scheduled, runs the extension.
     pub fn new(prio: TaskPriority, context: Rc<Context>, ext: Arc<Extension>) -> Container {
+        // Invocations of long running extensions are counted until the container is dropped, so
+        // that Master can limit how many of them are admitted at once.
+        if ext.manifest().cost == CostClass::LONG {
+            ext.track(true);
+        }
+
         // The generator is initialized to a dummy. The first call to run() will
         // retrieve the actual generator from the extension.
         Container {
@@ -90,6 +100,23 @@ impl Container {
                 yield 0;
                 return 0;
             }),
+            deadline: None,
+        }
+    }
+}
+
+// Implementation of private methods on Container.
+impl Container {
+    // Drops the extension's generator, and marks it's invocation as aborted.
+    fn abort(&mut self) {
+        self.gen = Box::new(|| {
+            yield 0;
+            return 0;
+        });
+
+        if let Some(context) = self.db.replace(None) {
+            context.abort();
+            self.db.set(Some(context));
         }
     }
 }
@@ -114,6 +141,12 @@ impl Task for Container {
         if self.state == INITIALIZED || self.state == YIELDED {
             self.state = RUNNING;
 
+            // Start a fresh slice for the extension's asynchronous operations.
+            if let Some(context) = self.db.replace(None) {
+                context.resumed();
+                self.db.set(Some(context));
+            }
+
             // As of 04/02/2018, calling resume() on a generator requires an unsafe block.
             unsafe {
                 // Catch any panics thrown from within the extension.
@@ -128,9 +161,11 @@ impl Task for Container {
                 }));
 
                 // If there was a panic thrown, then mark the container as COMPLETED so that it
-                // does not get run again.
+                // does not get run again, and the invocation as aborted so that the tenant is
+                // told. Dropping the generator right away releases anything it had allocated.
                 if let Err(_) = res {
                     self.state = COMPLETED;
+                    self.abort();
                 }
             }
         }
@@ -160,6 +195,21 @@ impl Task for Container {
         self.priority.clone()
     }
 
+    /// Refer to the Task trait for Documentation. Declared by the extension's manifest.
+    fn cost(&self) -> CostClass {
+        self.ext.manifest().cost
+    }
+
+    /// Refer to the Task trait for Documentation.
+    fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Refer to the Task trait for Documentation.
+    fn set_deadline(&mut self, deadline: u64) {
+        self.deadline = Some(deadline);
+    }
+
     /// Refer to the Task trait for Documentation.
     unsafe fn tear(
         &mut self,
@@ -176,7 +226,11 @@ impl Task for Container {
 
         // Next, unwrap the execution context, and, retrieve and return the
         // request and response packets.
-        let context = self.db.replace(None).unwrap();
+        let context = match self.db.replace(None) {
+            Some(context) => context,
+            None => return None,
+        };
+
         match Rc::try_unwrap(context) {
             Ok(db) => {
                 let (req, res) = db.commit();
@@ -187,9 +241,22 @@ impl Task for Container {
                 return Some((req, res));
             }
 
+            // The extension held on to a handle to it's context beyond the generator (ex: by
+            // leaking it). The packets cannot be recovered, but that is no reason to bring down
+            // the scheduler.
             Err(_) => {
-                panic!("Failed to unwrap context!");
+                error!("Extension leaked it's context, dropping the response to it's invocation");
+                return None;
             }
         }
     }
 }
+
+// Implementation of the Drop trait for Container.
+impl Drop for Container {
+    fn drop(&mut self) {
+        if self.ext.manifest().cost == CostClass::LONG {
+            self.ext.track(false);
+        }
+    }
+}
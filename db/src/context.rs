@@ -14,23 +14,52 @@
  */
 
 use std::cell::{Cell, RefCell};
+use std::mem::{size_of, transmute};
+use std::ptr;
 use std::str;
+use std::sync::atomic::{self, Ordering};
 use std::sync::Arc;
 
 use super::alloc::Allocator;
+use super::cycles;
+use super::stats::{ReadAmp, ReadClass};
+use super::table::{Table, WriteStamp, SNAPSHOT_RETRIES};
 use super::tenant::Tenant;
-use super::wireformat::{InvokeRequest, InvokeResponse};
+use super::wireformat::{InvokeRequest, InvokeResponse, RpcStatus};
 
+use sandstorm::arena::Arena;
 use sandstorm::buf::{MultiReadBuf, ReadBuf, WriteBuf};
-use sandstorm::db::DB;
+use sandstorm::db::{UpdateStatus, DB};
+use sandstorm::future::DBFuture;
+use sandstorm::schema::Schema;
 
 use e2d2::common::EmptyMetadata;
 use e2d2::interface::Packet;
 
+use bytes::Bytes;
+
+use rand::{self, Rng};
+
 /// The maximum number of bytes that can be allocated by an instance of an
 /// extension on the table heap.
 const MAX_ALLOC: usize = 10240;
 
+/// The maximum length of an error message that an extension can return to
+/// the tenant that invoked it.
+const MAX_ERROR_MSG: usize = 128;
+
+/// The maximum length of a continuation token that an extension can return
+/// to the tenant that invoked it along with partial results.
+const MAX_TOKEN_LEN: usize = 64;
+
+/// The maximum number of bytes of scratch memory that can be handed out to
+/// an instance of an extension.
+const MAX_SCRATCH: usize = 65536;
+
+/// The number of nanoseconds an extension can run for after it was last
+/// resumed before asynchronous operations ask it to yield.
+const ASYNC_SLICE_NS: u64 = 2000;
+
 /// This type is passed into the init method of every extension. The methods
 /// on this type form the interface allowing extensions to read and write
 /// data from and to the database. The constructors for this type (new() and
@@ -67,6 +96,50 @@ pub struct Context {
     // The total number of bytes allocated by the extension so far
     // (on the table heap).
     allocs: Cell<usize>,
+
+    // True if the extension failed and returned an error to the tenant. Once
+    // set, the response cannot be written to anymore.
+    failed: Cell<bool>,
+
+    // True if the extension stopped early and returned partial results along
+    // with a continuation token. Once set, the response cannot be written to
+    // anymore.
+    partial: Cell<bool>,
+
+    // True if the extension aborted before it could complete. Once set, the
+    // response cannot be written to anymore.
+    aborted: Cell<bool>,
+
+    // The number of objects allocated by the extension that have not been
+    // handed back through put() yet. Objects still outstanding once the
+    // extension is done are discarded along with it.
+    pending: Cell<usize>,
+
+    // Scratch memory handed out to the extension for temporary buffers. It
+    // is released along with the context once the extension is committed.
+    scratch: Arena,
+
+    // The number of objects the extension read off the heap, and the total
+    // length of their values. Added to `reads` once the extension is
+    // committed.
+    objects: Cell<usize>,
+    heap_bytes: Cell<usize>,
+
+    // Counters of data moved by invocations, shared by all cores.
+    reads: Arc<ReadAmp>,
+
+    // True if writes issued by the extension must be discarded instead of being applied to the
+    // database. Refer to `set_dry_run()`.
+    dry_run: Cell<bool>,
+
+    // The value of the cycle counter when the extension was last resumed. Refer to `resumed()`.
+    resumed: Cell<u64>,
+
+    // The number of cycles corresponding to `ASYNC_SLICE_NS`.
+    slice: u64,
+    // If set, writes issued by the extension are checked against tombstones, and deletes leave
+    // one behind, ordered by this stamp. Refer to `set_stamped()`.
+    stamped: Cell<Option<WriteStamp>>,
 }
 
 // Methods on Context.
@@ -86,6 +159,8 @@ impl Context {
     /// * `tenant`:   An `Arc` to the tenant that issued the invoke() request.
     /// * `alloc`:    An `Arc` to the memory allocator. Required to allow the
     ///               extension to issue writes to the database.
+    /// * `reads`:    Counters that the data read and returned by the extension
+    ///               is accounted to once it is committed.
     ///
     /// # Result
     /// A context that can be used to invoke an extension.
@@ -96,6 +171,7 @@ impl Context {
         res: Packet<InvokeResponse, EmptyMetadata>,
         tenant: Arc<Tenant>,
         alloc: Arc<Allocator>,
+        reads: Arc<ReadAmp>,
     ) -> Context {
         Context {
             request: req,
@@ -105,13 +181,170 @@ impl Context {
             tenant: tenant,
             heap: alloc,
             allocs: Cell::new(0),
+            failed: Cell::new(false),
+            partial: Cell::new(false),
+            aborted: Cell::new(false),
+            pending: Cell::new(0),
+            scratch: Arena::new(MAX_SCRATCH),
+            objects: Cell::new(0),
+            heap_bytes: Cell::new(0),
+            reads: reads,
+            dry_run: Cell::new(false),
+            resumed: Cell::new(cycles::rdtsc()),
+            slice: (cycles::cycles_per_second() * ASYNC_SLICE_NS) / 1000000000,
+            stamped: Cell::new(None),
+        }
+    }
+
+    /// This method makes the context discard every write issued by the extension, while
+    /// reporting it as successful. Used to run a shadow version of an extension against live
+    /// requests without it affecting the database; such a version does not observe it's own
+    /// writes. Data read by a dry run is not accounted either.
+    pub fn set_dry_run(&self) {
+        self.dry_run.set(true);
+    }
+
+    /// This method orders the extension's writes against deletes of the same keys as if they
+    /// were issued by the invoke() request. Deletes issued by the extension leave a tombstone
+    /// behind, and puts issued by it are rejected if they precede a deletion of their key. Refer
+    /// to `Table::put_stamped()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`: The stamp of the invoke() request.
+    pub fn set_stamped(&self, stamp: WriteStamp) {
+        self.stamped.set(Some(stamp));
+    }
+
+    // Returns an object read out of a table, or None if it has expired and must be treated as
+    // missing.
+    fn live(&self, object: Bytes) -> Option<Bytes> {
+        if self.heap.is_expired(&object) {
+            return None;
+        }
+
+        Some(object)
+    }
+
+    // Applies an update to a copy of an object, and writes the copy into the table in the
+    // object's place through the allocator's commit path, so that it is logged like a put().
+    // The copy keeps the object's expiration time. Returns `Busy` if another write replaced the
+    // object in the meantime, so that the extension retries the update on the new object.
+    fn update_copy(
+        &self,
+        table: &Table,
+        table_id: u64,
+        object: &Bytes,
+        value: &[u8],
+        offset: usize,
+        data: &[u8],
+    ) -> UpdateStatus {
+        let mut val = value.to_vec();
+        val[offset..offset + data.len()].copy_from_slice(data);
+
+        let expires = self.heap.expires(object);
+        let key = match self.heap.resolve(object.clone()) {
+            Some((k, _v)) => k,
+            None => return UpdateStatus::Failed,
+        };
+
+        let (k, copy) = match self.heap.object(self.tenant.id(), table_id, &key, &val) {
+            Some(alloc) => alloc,
+            None => return UpdateStatus::Failed,
+        };
+
+        if expires != 0 {
+            self.heap.set_expires(&copy, expires);
+        }
+
+        // The copy only replaces the object it was made off, and is committed with the key's
+        // bucket locked so that it is logged in the order it enters the table.
+        let replaced = Cell::new(false);
+        let written = table.put_if(&key, |current| {
+            if current.map_or(true, |current| current.as_ptr() != object.as_ptr()) {
+                replaced.set(true);
+                return None;
+            }
+
+            self.heap.commit(k, copy)
+        });
+
+        match (written, replaced.get()) {
+            (true, _) => UpdateStatus::Updated,
+            (false, true) => UpdateStatus::Busy,
+            (false, false) => UpdateStatus::Failed,
         }
     }
 
+    /// This method records that the extension is about to be resumed. Asynchronous operations
+    /// issued by the extension more than `ASYNC_SLICE_NS` after this ask it to yield before
+    /// they hand back their results.
+    pub fn resumed(&self) {
+        self.resumed.set(cycles::rdtsc());
+    }
+
+    // Returns true if the extension has not run for long enough since it was last resumed to be
+    // asked to yield.
+    fn within_slice(&self) -> bool {
+        cycles::rdtsc() - self.resumed.get() < self.slice
+    }
+
+    // Accounts for an object of a given value length read by the extension.
+    fn account(&self, len: usize) {
+        self.objects.set(self.objects.get() + 1);
+        self.heap_bytes.set(self.heap_bytes.get() + len);
+    }
+
+    /// This method marks the extension as having aborted before it could
+    /// complete (ex: it panicked). Anything written to the response so far is
+    /// discarded, and the response is given a status of
+    /// `StatusExtensionAborted`, so that the tenant can tell the invocation
+    /// apart from one that succeeded with a short response.
+    pub fn abort(&self) {
+        if self.aborted.get() {
+            return;
+        }
+        self.aborted.set(true);
+
+        let mut response = self.response.borrow_mut();
+
+        let len = response.get_payload().len();
+        if len > 0 {
+            response.remove_from_payload_tail(len).unwrap();
+        }
+
+        response.get_mut_header().common_header.status = RpcStatus::StatusExtensionAborted;
+    }
+
+    // Fails the invocation once the extension writes more than the response packet can hold,
+    // i.e, once the response grows past `common::MAX_RESPONSE_LEN` bytes.
+    // Whatever was written so far is discarded instead of being returned truncated, and the
+    // response is given a status of `StatusInternalError`.
+    fn overflow(&self, response: &mut Packet<InvokeResponse, EmptyMetadata>) {
+        self.failed.set(true);
+
+        let len = response.get_payload().len();
+        if len > 0 {
+            response.remove_from_payload_tail(len).unwrap();
+        }
+
+        response.get_mut_header().common_header.status = RpcStatus::StatusInternalError;
+    }
+
+    /// This method returns true if the extension was marked as aborted.
+    pub fn aborted(&self) -> bool {
+        self.aborted.get()
+    }
+
     /// This method commits any changes made by an extension to the database.
     /// It consumes the context, and returns the request and response
     /// packets/buffers to the caller.
     ///
+    /// Objects allocated by the extension only become part of the database
+    /// once they are put(), so there is nothing to undo for those still
+    /// outstanding; they were released along with the extension's generator.
+    /// The caller must drop the generator before committing.
+    ///
     /// # Return
     /// A tupule whose first member is the request packet/buffer for the
     /// extension, and whose second member is the response packet/buffer
@@ -122,6 +355,25 @@ impl Context {
         Packet<InvokeRequest, EmptyMetadata>,
         Packet<InvokeResponse, EmptyMetadata>,
     ) {
+        let pending = self.pending.get();
+        if pending > 0 {
+            debug!(
+                "Tenant {}: discarded {} objects allocated but never put by an extension{}",
+                self.tenant.id(),
+                pending,
+                if self.aborted.get() { " that aborted" } else { "" }
+            );
+        }
+
+        if !self.dry_run.get() {
+            self.reads.record(
+                ReadClass::INVOKE,
+                self.objects.get(),
+                self.heap_bytes.get(),
+                self.response.borrow().get_payload().len(),
+            );
+        }
+
         return (self.request, self.response.into_inner());
     }
 }
@@ -134,11 +386,16 @@ impl DB for Context {
         // the read set and return the value.
         self.tenant.get_table(table_id)
                     .and_then(| table | { table.get(key) })
+                    // Objects that have expired do not exist anymore.
+                    .and_then(| object | { self.live(object) })
                     // The object exists in the database. Get a handle to it's
                     // key and value.
                     .and_then(| object | { self.heap.resolve(object) })
                     // Return the value wrapped up inside a safe type.
-                    .and_then(| (_k, v) | { unsafe { Some(ReadBuf::new(v)) } })
+                    .and_then(| (_k, v) | {
+                        self.account(v.len());
+                        unsafe { Some(ReadBuf::new(v)) }
+                    })
     }
 
     /// Lookup the `DB` trait for documentation on this method.
@@ -148,16 +405,26 @@ impl DB for Context {
         if let Some(table) = self.tenant.get_table(table_id) {
             let mut objs = Vec::new();
 
-            // Iterate through the list of keys. Lookup each one of them at the database.
-            for key in keys.chunks(key_len as usize) {
-                if key.len() != key_len as usize {
-                    break;
-                }
+            // Read every key as of a single point in time, so that the extension never observes
+            // a concurrent write to several of them half way through. Gives up if the keys keep
+            // being written to.
+            let keys: Vec<&[u8]> = keys
+                .chunks(key_len as usize)
+                .take_while(|key| key.len() == key_len as usize)
+                .collect();
+            let heap = &self.heap;
+            let objects = match table.snapshot(&keys, SNAPSHOT_RETRIES, |obj| heap.version(obj)) {
+                Some(objects) => objects,
+                None => return None,
+            };
 
-                let r = table
-                    .get(key)
+            // Iterate through the objects read. Each one of them must exist.
+            for object in objects.into_iter() {
+                let r = object
+                    .and_then(|obj| self.live(obj))
                     .and_then(|obj| self.heap.resolve(obj))
                     .and_then(|(_k, v)| {
+                        self.account(v.len());
                         objs.push(v);
                         Some(())
                     });
@@ -175,10 +442,65 @@ impl DB for Context {
         return None;
     }
 
+    /// Lookup the `DB` trait for documentation on this method.
+    fn async_get(&self, table_id: u64, key: &[u8]) -> DBFuture<Option<ReadBuf>> {
+        unsafe { DBFuture::new(self.get(table_id, key), self.within_slice()) }
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn async_multiget(&self, table_id: u64, key_len: u16, keys: &[u8])
+        -> DBFuture<Option<MultiReadBuf>>
+    {
+        unsafe { DBFuture::new(self.multiget(table_id, key_len, keys), self.within_slice()) }
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn multitable_get(&self, key_len: u16, keys: &[u8]) -> Option<MultiReadBuf> {
+        let mut objs = Vec::new();
+
+        // The most recently looked up table. Consecutive keys usually belong to the same table.
+        let mut table: Option<(u64, Arc<Table>)> = None;
+
+        // Iterate through the list of (table, key) pairs. Lookup each one of them at the database.
+        let entry_len = size_of::<u64>() + key_len as usize;
+        for entry in keys.chunks(entry_len) {
+            if entry.len() != entry_len {
+                break;
+            }
+
+            let mut id: [u8; 8] = [0; 8];
+            id.copy_from_slice(&entry[0..8]);
+            let table_id = u64::from_le(unsafe { transmute(id) });
+
+            if !table.as_ref().map_or(false, |&(t, _)| t == table_id) {
+                table = self.tenant.get_table(table_id).map(|t| (table_id, t));
+            }
+
+            let r = table
+                .as_ref()
+                .and_then(|&(_, ref t)| t.get(&entry[8..]))
+                .and_then(|obj| self.live(obj))
+                .and_then(|obj| self.heap.resolve(obj))
+                .and_then(|(_k, v)| {
+                    self.account(v.len());
+                    objs.push(v);
+                    Some(())
+                });
+
+            if r.is_none() {
+                return None;
+            }
+        }
+
+        unsafe {
+            return Some(MultiReadBuf::new(objs));
+        }
+    }
+
     /// Lookup the `DB` trait for documentation on this method.
     fn alloc(&self, table_id: u64, key: &[u8], val_len: u64) -> Option<WriteBuf> {
-        // If the extension has exceeded it's quota, do not allow any more allocs.
-        if self.allocs.get() >= MAX_ALLOC {
+        // If the extension has exceeded it's quota, or aborted, do not allow any more allocs.
+        if self.allocs.get() >= MAX_ALLOC || self.aborted.get() {
             return None;
         }
 
@@ -189,20 +511,41 @@ impl DB for Context {
             .and_then(|_table| self.heap.raw(self.tenant.id(), table_id, key, val_len))
             .and_then(|buf| {
                 self.allocs.set(self.allocs.get() + buf.len());
+                self.pending.set(self.pending.get() + 1);
                 unsafe { Some(WriteBuf::new(table_id, buf)) }
             })
     }
 
     /// Lookup the `DB` trait for documentation on this method.
     fn put(&self, buf: WriteBuf) -> bool {
-        // Convert the passed in Writebuf to read only.
+        // Convert the passed in Writebuf to read only. Whether or not the put succeeds, the
+        // allocation is no longer outstanding.
         let (table_id, buf) = unsafe { buf.freeze() };
+        self.pending.set(self.pending.get().saturating_sub(1));
+
+        // An aborted extension cannot write to the database anymore.
+        if self.aborted.get() {
+            return false;
+        }
+
+        // Checksum the value now that the extension is done writing it.
+        let buf = self.heap.seal(buf);
 
         // If the table exists, write to the database.
         if let Some(table) = self.tenant.get_table(table_id) {
+            if self.dry_run.get() {
+                return true;
+            }
+
+            // The object is committed with the key's bucket locked, so that it is logged in the
+            // order it enters the table. The put fails if the log is full.
             return self.heap.resolve(buf.clone()).map_or(false, |(k, _v)| {
-                table.put(k, buf);
-                true
+                let key = k.clone();
+                if let Some(stamp) = self.stamped.get() {
+                    return table.put_stamped(&key, &stamp, || self.heap.commit(k, buf));
+                }
+
+                table.put_if(&key, |_| self.heap.commit(k, buf))
             });
         }
 
@@ -211,12 +554,92 @@ impl DB for Context {
 
     /// Lookup the `DB` trait for documentation on this method.
     fn del(&self, table_id: u64, key: &[u8]) {
-        // Delete the key-value pair from the database
+        if self.dry_run.get() {
+            return;
+        }
+
+        // Delete the key-value pair from the database. The deletion is logged with the key's
+        // bucket locked, and is dropped if the log is full.
         if let Some(table) = self.tenant.get_table(table_id) {
-            table.delete(key);
+            let stamp = self.stamped.get();
+            let _ = table.delete_with(key, stamp.as_ref(), || {
+                self.heap.commit_delete(self.tenant.id(), table_id, key)
+            });
         }
     }
 
+    /// Lookup the `DB` trait for documentation on this method.
+    fn update(&self, table_id: u64, key: &[u8], offset: usize, data: &[u8]) -> UpdateStatus {
+        let table = match self.tenant.get_table(table_id) {
+            Some(table) => table,
+            None => return UpdateStatus::Failed,
+        };
+
+        // Lookup the object, and make sure the range lies within it's value.
+        let object = match table.get(key) {
+            Some(object) => object,
+            None => return UpdateStatus::Failed,
+        };
+
+        let value = match self.heap.resolve(object.clone()) {
+            Some((_k, value)) => value,
+            None => return UpdateStatus::Failed,
+        };
+
+        if offset + data.len() > value.len() {
+            return UpdateStatus::Failed;
+        }
+
+        if self.dry_run.get() {
+            return UpdateStatus::Updated;
+        }
+
+        // Writes in place bypass the write-ahead log. Tables that are logged get the update
+        // applied to a copy of the object instead, which is logged on its way into the table.
+        if self.heap.is_logged(self.tenant.id(), table_id) {
+            return self.update_copy(&table, table_id, &object, &value, offset, data);
+        }
+
+        // Latch the range being written to. If an overlapping update is in progress, the
+        // extension has to yield and retry, so that there is never a wait on the latch.
+        let start = (value.as_ptr() as usize - object.as_ptr() as usize) + offset;
+        let end = start + data.len();
+        if !table.try_latch(&object, start, end) {
+            return UpdateStatus::Busy;
+        }
+
+        // The value is about to change under it's checksum, so the checksum can no longer be
+        // trusted. The object also needs a new version, so that conditional writes notice it.
+        self.heap.invalidate_checksum(&object);
+        self.heap.bump_version(&object);
+
+        // Write the data directly into the object. The range is latched, so no other update can
+        // write to it concurrently.
+        unsafe {
+            let dst = (object.as_ptr() as *mut u8).offset(start as isize);
+            ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+        }
+
+        table.unlatch(&object, start, end);
+        table.mark_changed(key);
+
+        // If the value is small enough to be kept inline in the table, update the copy too.
+        table.refresh(key);
+
+        return UpdateStatus::Updated;
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn move_key(&self, src_table: u64, dst_table: u64, key: &[u8]) -> bool {
+        if self.dry_run.get() {
+            let dst = self.tenant.get_table(dst_table);
+            let src = self.tenant.get_table(src_table).and_then(|table| table.get(key));
+            return dst.is_some() && src.is_some();
+        }
+
+        self.tenant.move_key(&self.heap, src_table, dst_table, key) == RpcStatus::StatusOk
+    }
+
     /// Lookup the `DB` trait for documentation on this method.
     fn args(&self) -> &[u8] {
         // Return a slice to the arguments off the request packet/buffer's
@@ -231,11 +654,95 @@ impl DB for Context {
 
     /// Lookup the `DB` trait for documentation on this method.
     fn resp(&self, data: &[u8]) {
-        // Write the passed in data to the response packet/buffer.
-        self.response
-            .borrow_mut()
-            .add_to_payload_tail(data.len(), data)
-            .unwrap();
+        // If the extension already returned an error or a continuation token,
+        // or aborted, then ignore any writes.
+        if self.failed.get() || self.partial.get() || self.aborted.get() {
+            return;
+        }
+
+        // Write the passed in data to the response packet/buffer. Responses larger than the MTU
+        // are fragmented on their way out, but must still fit in a single packet buffer, so no
+        // more than `common::MAX_RESPONSE_LEN` bytes can be returned by an invocation.
+        let mut response = self.response.borrow_mut();
+        if response.add_to_payload_tail(data.len(), data).is_err() {
+            self.overflow(&mut response);
+        }
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn resp_error(&self, code: u32, msg: &str) {
+        // Only the first error is returned to the tenant, and none at all once aborted.
+        if self.failed.get() || self.aborted.get() {
+            return;
+        }
+        self.failed.set(true);
+
+        let mut response = self.response.borrow_mut();
+
+        // Discard anything the extension wrote to the response so far.
+        let len = response.get_payload().len();
+        if len > 0 {
+            response.remove_from_payload_tail(len).unwrap();
+        }
+
+        // Mark the response as failed, and write in the error code and message.
+        response.get_mut_header().common_header.status = RpcStatus::StatusExtensionError;
+
+        let code: [u8; 4] = unsafe { transmute(code.to_le()) };
+        let msg = msg.as_bytes();
+        let msg = &msg[..msg.len().min(MAX_ERROR_MSG)];
+        response.add_to_payload_tail(code.len(), &code).unwrap();
+        response.add_to_payload_tail(msg.len(), msg).unwrap();
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn resp_partial(&self, token: &[u8]) {
+        // An error or abort takes precedence over partial results, and only
+        // the first token is returned to the tenant.
+        if self.failed.get() || self.partial.get() || self.aborted.get() {
+            return;
+        }
+        self.partial.set(true);
+
+        let mut response = self.response.borrow_mut();
+        response.get_mut_header().common_header.status = RpcStatus::StatusPartialResult;
+
+        // The token is written after the results, and it's length at the very end so that
+        // the client can find it without knowing the length of the results.
+        let token = &token[..token.len().min(MAX_TOKEN_LEN)];
+        let len: [u8; 4] = unsafe { transmute((token.len() as u32).to_le()) };
+        if response.add_to_payload_tail(token.len(), token).is_err()
+            || response.add_to_payload_tail(len.len(), &len).is_err()
+        {
+            self.overflow(&mut response);
+        }
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn scratch(&self, len: usize) -> &mut [u8] {
+        self.scratch.alloc(len)
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn now_cycles(&self) -> u64 {
+        cycles::rdtsc()
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn random_bytes(&self, buf: &mut [u8]) {
+        rand::thread_rng().fill_bytes(buf);
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn schema(&self, table: u64) -> Option<Arc<Schema>> {
+        self.tenant.schema(table)
+    }
+
+    /// Lookup the `DB` trait for documentation on this method.
+    fn fence(&self) {
+        // Puts and deletes are published by releasing the bucket lock of their table, but in place
+        // updates are plain stores. A full fence orders them against every later write.
+        atomic::fence(Ordering::SeqCst);
     }
 
     /// Lookup the `DB` trait for documentation on this method.
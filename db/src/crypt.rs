@@ -0,0 +1,561 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::fs::{rename, File};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::mem::transmute;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::task::{CostClass, Task, TaskPriority, TaskState};
+use super::trace::RequestId;
+
+use e2d2::common::EmptyMetadata;
+use e2d2::headers::UdpHeader;
+use e2d2::interface::Packet;
+
+use ring::aead::{self, OpeningKey, SealingKey, AES_128_GCM, AES_256_GCM};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// The length of the nonce prepended to every sealed payload.
+pub const NONCE_LEN: usize = 12;
+
+/// The length of the authentication tag appended to every sealed payload.
+pub const TAG_LEN: usize = 16;
+
+/// The number of bytes sealing adds to a payload.
+pub const OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+
+// Set on the salt of every server's nonces, and clear on the salt of every client's, so that a
+// client never reuses a server's nonce. Refer to `next_salt()`.
+const SERVER_SALT: u32 = 0x8000_0000;
+
+/// Per-tenant keys, indexed by tenant identifier.
+pub type Keys = HashMap<u32, Arc<Key>>;
+
+/// This type represents a tenant's symmetric key, used to seal and open the payloads on RPCs
+/// with AES-GCM. A sealed payload has the following layout:
+///      _________________________________________________
+///     |            |                       |            |
+///     |   Nonce    |      Ciphertext       |    Tag     |
+///     |____________|_______________________|____________|
+///       12 Bytes          Var Length          16 Bytes
+///
+/// The RPC header preceding the payload is sent in the clear, but is authenticated along with
+/// the payload so that it cannot be tampered with either.
+///
+/// Nonces consist of a four byte salt, followed by an eight byte counter that starts at zero
+/// whenever the key is created. The salt keeps the client and server, which share the key, from
+/// reusing each other's nonces, and keeps a restarted server from reusing the nonces of an
+/// earlier run. Servers persist it across restarts (refer to `next_salt()`), while clients pick
+/// it at random.
+pub struct Key {
+    // The key used to seal payloads.
+    sealing: SealingKey,
+
+    // The key used to open payloads.
+    opening: OpeningKey,
+
+    // The salt on every nonce generated with this key.
+    salt: [u8; 4],
+
+    // The counter on the next nonce generated with this key.
+    counter: AtomicUsize,
+}
+
+// Implementation of methods on Key.
+impl Key {
+    /// Creates a Key for a client, salting it's nonces at random.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes`: The raw key. Must be 16 bytes long for AES-128, or 32 for AES-256.
+    ///
+    /// # Return
+    ///
+    /// A `Key`, or None if the raw key had an invalid length.
+    pub fn new(bytes: &[u8]) -> Option<Key> {
+        let mut salt = [0; 4];
+        if SystemRandom::new().fill(&mut salt).is_err() {
+            return None;
+        }
+
+        let salt: u32 = u32::from_le(unsafe { transmute(salt) });
+        Key::with_salt(bytes, salt & !SERVER_SALT)
+    }
+
+    /// Creates a Key whose nonces carry a given salt.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes`: The raw key. Must be 16 bytes long for AES-128, or 32 for AES-256.
+    /// * `salt`:  The salt on every nonce generated with the key (ex: `next_salt()`). Must
+    ///            not have been used with the same key before.
+    ///
+    /// # Return
+    ///
+    /// A `Key`, or None if the raw key had an invalid length.
+    pub fn with_salt(bytes: &[u8], salt: u32) -> Option<Key> {
+        let alg = match bytes.len() {
+            16 => &AES_128_GCM,
+            32 => &AES_256_GCM,
+            _ => return None,
+        };
+        let salt: [u8; 4] = unsafe { transmute(salt.to_le()) };
+
+        match (SealingKey::new(alg, bytes), OpeningKey::new(alg, bytes)) {
+            (Ok(sealing), Ok(opening)) => Some(Key {
+                sealing: sealing,
+                opening: opening,
+                salt: salt,
+                counter: AtomicUsize::new(0),
+            }),
+
+            _ => None,
+        }
+    }
+
+    /// Seals a payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `ad`:      Data that is authenticated but not encrypted (ex: the RPC header).
+    /// * `payload`: The payload to be sealed.
+    ///
+    /// # Return
+    ///
+    /// The sealed payload, consisting of the nonce, ciphertext, and tag.
+    pub fn seal(&self, ad: &[u8], payload: &[u8]) -> Vec<u8> {
+        let count = self.counter.fetch_add(1, Ordering::Relaxed) as u64;
+        let count: [u8; 8] = unsafe { transmute(count.to_le()) };
+
+        let mut nonce = [0; NONCE_LEN];
+        nonce[0..4].copy_from_slice(&self.salt);
+        nonce[4..12].copy_from_slice(&count);
+
+        let mut sealed = Vec::with_capacity(payload.len() + OVERHEAD);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(payload);
+        sealed.extend_from_slice(&[0; TAG_LEN]);
+
+        aead::seal_in_place(&self.sealing, &nonce, ad, &mut sealed[NONCE_LEN..], TAG_LEN)
+            .expect("Failed to seal payload.");
+
+        sealed
+    }
+
+    /// Opens a sealed payload in place.
+    ///
+    /// # Arguments
+    ///
+    /// * `ad`:     The data that was authenticated along with the payload.
+    /// * `sealed`: The sealed payload, consisting of the nonce, ciphertext, and tag. Overwritten
+    ///             with the opened payload, starting at the first byte.
+    ///
+    /// # Return
+    ///
+    /// The length of the opened payload, or None if the payload could not be authenticated.
+    pub fn open(&self, ad: &[u8], sealed: &mut [u8]) -> Option<usize> {
+        if sealed.len() < OVERHEAD {
+            return None;
+        }
+
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&sealed[0..NONCE_LEN]);
+
+        aead::open_in_place(&self.opening, &nonce, ad, NONCE_LEN, sealed)
+            .map(|payload| payload.len())
+            .ok()
+    }
+}
+
+/// Loads per-tenant keys from a key file. Each line in the file consists of a tenant identifier
+/// followed by it's hex encoded key, separated by whitespace. Empty lines and lines starting
+/// with '#' are ignored.
+///
+/// # Arguments
+///
+/// * `path`: The path of the key file. If empty, no keys are loaded.
+/// * `salt`: The salt on the nonces of every key (refer to `Key::with_salt()`). Picked at random
+///           for every key if None, like clients do.
+///
+/// # Return
+///
+/// The keys in the file, or an error if the file could not be read or was malformed.
+pub fn load_keys(path: &str, salt: Option<u32>) -> Result<Keys> {
+    let mut keys = HashMap::new();
+    if path.is_empty() {
+        return Ok(keys);
+    }
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (tenant, key) = parse_key(line, salt)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Malformed key file entry."))?;
+        keys.insert(tenant, Arc::new(key));
+    }
+
+    Ok(keys)
+}
+
+/// Picks the salt on a server's nonces (refer to `Key::with_salt()`). The salt is persisted in a
+/// file: it is picked at random when the server is first started, and bumped on every restart
+/// from there on, so that a restarted server never reuses the nonces of an earlier run, while
+/// servers sharing keys are unlikely to use the same salts. Salts repeat only once a server was
+/// restarted 2^31 times. The file is replaced atomically, so a crash leaves either the old or the
+/// new salt behind, never neither.
+///
+/// # Arguments
+///
+/// * `path`: The path of the file. Created if it does not exist.
+///
+/// # Return
+///
+/// A salt that no client and no earlier run of the server uses, or an error if the file could
+/// not be read or written.
+pub fn next_salt(path: &str) -> Result<u32> {
+    let salt = match File::open(path) {
+        Ok(mut file) => {
+            let mut salt = String::new();
+            file.read_to_string(&mut salt)?;
+            let salt = salt
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Malformed salt file."))?;
+            salt.wrapping_add(1)
+        }
+
+        Err(ref err) if err.kind() == ErrorKind::NotFound => {
+            let mut salt = [0; 4];
+            SystemRandom::new()
+                .fill(&mut salt)
+                .map_err(|_| Error::new(ErrorKind::Other, "Failed to pick a salt."))?;
+            u32::from_le(unsafe { transmute(salt) })
+        }
+
+        Err(err) => return Err(err),
+    };
+    let salt = SERVER_SALT | (salt & !SERVER_SALT);
+
+    // The salt must reach the disk before any nonce carrying it is used.
+    let tmp = format!("{}.tmp", path);
+    {
+        let mut file = File::create(&tmp)?;
+        write!(file, "{}\n", salt)?;
+        file.sync_all()?;
+    }
+    rename(&tmp, path)?;
+
+    Ok(salt)
+}
+
+// Parses a line in the key file into a tenant identifier and it's key, salted if required.
+fn parse_key(line: &str, salt: Option<u32>) -> Option<(u32, Key)> {
+    let mut fields = line.split_whitespace();
+    let tenant = fields.next().and_then(|t| t.parse::<u32>().ok())?;
+    let hex = fields.next()?;
+    if !hex.is_ascii() || hex.len() % 2 != 0 || fields.next().is_some() {
+        return None;
+    }
+
+    let mut raw = Vec::with_capacity(hex.len() / 2);
+    for i in 0..(hex.len() / 2) {
+        raw.push(u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?);
+    }
+
+    let key = match salt {
+        Some(salt) => Key::with_salt(&raw, salt),
+        None => Key::new(&raw),
+    };
+    key.map(|key| (tenant, key))
+}
+
+/// Seals the payload following an RPC header on a packet in place.
+///
+/// # Arguments
+///
+/// * `packet`:  The packet, parsed upto it's UDP header.
+/// * `hdr_len`: The length of the RPC header at the start of the UDP payload.
+/// * `key`:     The key to seal the payload with.
+///
+/// # Return
+///
+/// True if the payload was sealed. The caller is responsible for fixing up length fields on the
+/// packet's UDP and IP headers.
+pub fn seal_payload(
+    packet: &mut Packet<UdpHeader, EmptyMetadata>,
+    hdr_len: usize,
+    key: &Key,
+) -> bool {
+    let len = packet.get_payload().len();
+    if len < hdr_len {
+        return false;
+    }
+
+    let sealed = {
+        let (hdr, payload) = packet.get_payload().split_at(hdr_len);
+        key.seal(hdr, payload)
+    };
+
+    if len > hdr_len && packet.remove_from_payload_tail(len - hdr_len).is_err() {
+        return false;
+    }
+
+    packet.add_to_payload_tail(sealed.len(), &sealed).is_ok()
+}
+
+/// Opens the sealed payload following an RPC header on a packet in place.
+///
+/// # Arguments
+///
+/// * `packet`:  The packet, parsed upto it's UDP header.
+/// * `hdr_len`: The length of the RPC header at the start of the UDP payload.
+/// * `key`:     The key the payload was sealed with.
+///
+/// # Return
+///
+/// True if the payload was authenticated and opened. The packet is left in an unspecified state
+/// otherwise, and should be dropped.
+pub fn open_payload(
+    packet: &mut Packet<UdpHeader, EmptyMetadata>,
+    hdr_len: usize,
+    key: &Key,
+) -> bool {
+    let len = packet.get_payload().len();
+    if len < hdr_len + OVERHEAD {
+        return false;
+    }
+
+    let opened = {
+        let (hdr, sealed) = packet.get_mut_payload().split_at_mut(hdr_len);
+        key.open(hdr, sealed)
+    };
+
+    match opened {
+        Some(opened) => packet.remove_from_payload_tail(len - hdr_len - opened).is_ok(),
+        None => false,
+    }
+}
+
+/// This type wraps a task running on behalf of a tenant with a key, and seals the payload on
+/// it's response once it completes.
+pub struct SealedTask {
+    // The wrapped task.
+    task: Box<Task>,
+
+    // The tenant's key.
+    key: Arc<Key>,
+
+    // The length of the RPC header on the task's response.
+    hdr_len: usize,
+}
+
+// Implementation of methods on SealedTask.
+impl SealedTask {
+    /// Creates a SealedTask.
+    ///
+    /// # Arguments
+    ///
+    /// * `task`:    The task to be wrapped.
+    /// * `key`:     The key of the tenant the task is running on behalf of.
+    /// * `hdr_len`: The length of the RPC header on the task's response.
+    ///
+    /// # Return
+    ///
+    /// A task that behaves exactly like `task`, except that it's response is sealed.
+    pub fn new(task: Box<Task>, key: Arc<Key>, hdr_len: usize) -> SealedTask {
+        SealedTask {
+            task: task,
+            key: key,
+            hdr_len: hdr_len,
+        }
+    }
+
+    // Seals the payload of a response. A response that cannot be sealed must not go out in the
+    // clear, so just the header is sent back instead.
+    fn seal(&self, res: &mut Packet<UdpHeader, EmptyMetadata>) {
+        if !seal_payload(res, self.hdr_len, &self.key) {
+            warn!("Failed to seal response payload.");
+            let len = res.get_payload().len();
+            if len > self.hdr_len {
+                let _ = res.remove_from_payload_tail(len - self.hdr_len);
+            }
+        }
+    }
+}
+
+// Implementation of the Task trait for SealedTask. Everything except tear() and tear_more() is
+// passed through.
+impl Task for SealedTask {
+    /// Refer to the `Task` trait for Documentation.
+    fn run(&mut self) -> (TaskState, u64) {
+        self.task.run()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn state(&self) -> TaskState {
+        self.task.state()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn time(&self) -> u64 {
+        self.task.time()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn priority(&self) -> TaskPriority {
+        self.task.priority()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn cost(&self) -> CostClass {
+        self.task.cost()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn affinity(&self) -> Option<usize> {
+        self.task.affinity()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn deadline(&self) -> Option<u64> {
+        self.task.deadline()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn set_deadline(&mut self, deadline: u64) {
+        self.task.set_deadline(deadline)
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    unsafe fn tear(
+        &mut self,
+    ) -> Option<(
+        Packet<UdpHeader, EmptyMetadata>,
+        Packet<UdpHeader, EmptyMetadata>,
+    )> {
+        let (req, mut res) = self.task.tear()?;
+        self.seal(&mut res);
+
+        Some((req, res))
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    unsafe fn tear_more(&mut self) -> Vec<Packet<UdpHeader, EmptyMetadata>> {
+        let mut more = self.task.tear_more();
+        for res in more.iter_mut() {
+            self.seal(res);
+        }
+
+        more
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn id(&self) -> Option<RequestId> {
+        self.task.id()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn wake(&self) -> Option<u64> {
+        self.task.wake()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn successor(&mut self) -> Option<Box<Task>> {
+        self.task.successor()
+    }
+}
+
+// This module contains simple unit tests for Key.
+#[cfg(test)]
+mod tests {
+    use super::{next_salt, parse_key, Key, OVERHEAD};
+    use std::fs::remove_file;
+
+    // This test verifies that a sealed payload opens back up to the original.
+    #[test]
+    fn test_seal_open() {
+        let key = Key::new(&[7; 16]).expect("Failed to create key.");
+
+        let mut sealed = key.seal(&[1, 2, 3], &[9; 40]);
+        assert_eq!(40 + OVERHEAD, sealed.len());
+        assert!(&sealed[12..52] != &[9; 40][..]);
+
+        assert_eq!(Some(40), key.open(&[1, 2, 3], &mut sealed));
+        assert_eq!(&[9; 40][..], &sealed[0..40]);
+    }
+
+    // This test verifies that tampering with the payload or header is detected.
+    #[test]
+    fn test_seal_tamper() {
+        let key = Key::new(&[7; 32]).expect("Failed to create key.");
+
+        let mut sealed = key.seal(&[1, 2, 3], &[9; 40]);
+        let mut tampered = sealed.clone();
+        tampered[20] ^= 0x1;
+        assert_eq!(None, key.open(&[1, 2, 3], &mut tampered));
+        assert_eq!(None, key.open(&[1, 2, 4], &mut sealed));
+    }
+
+    // This test verifies that every payload is sealed under a different nonce.
+    #[test]
+    fn test_seal_nonce() {
+        let key = Key::new(&[7; 16]).expect("Failed to create key.");
+        assert!(key.seal(&[], &[0; 4])[0..12] != key.seal(&[], &[0; 4])[0..12]);
+    }
+
+    // This test verifies that key file entries are parsed correctly.
+    #[test]
+    fn test_parse_key() {
+        let entry = parse_key("12 000102030405060708090a0b0c0d0e0f", None);
+        let (tenant, _) = entry.expect("Bad entry.");
+        assert_eq!(12, tenant);
+
+        assert!(parse_key("12 0001", None).is_none());
+        assert!(parse_key("x 000102030405060708090a0b0c0d0e0f", None).is_none());
+        assert!(parse_key("12 000102030405060708090a0b0c0d0e0", None).is_none());
+    }
+
+    // This test verifies that server and client keys carry their salt on every nonce, and that
+    // server salts never collide with client salts.
+    #[test]
+    fn test_seal_salt() {
+        let server = Key::with_salt(&[7; 16], 0x80000003).expect("Failed to create key.");
+        let client = Key::new(&[7; 16]).expect("Failed to create key.");
+
+        let (s, c) = (server.seal(&[], &[0; 4]), client.seal(&[], &[0; 4]));
+        assert_eq!(&[3, 0, 0, 0x80], &s[0..4]);
+        assert_eq!(0, c[3] & 0x80);
+    }
+
+    // This test verifies that a server's salt is persisted, and bumped every time it is picked.
+    #[test]
+    fn test_next_salt() {
+        let path = "/tmp/sandstorm_salt.test";
+        let _ = remove_file(path);
+
+        let first = next_salt(path).expect("Failed to pick salt.");
+        let second = next_salt(path).expect("Failed to pick salt.");
+        assert!(first & second & 0x80000000 != 0);
+        assert_eq!(first & 0x7fffffff, second.wrapping_sub(1) & 0x7fffffff);
+        let _ = remove_file(path);
+    }
+}
@@ -16,6 +16,8 @@
 use time::PreciseTime;
 use std::sync::{Once, ONCE_INIT};
 
+use e2d2::interface::dpdk;
+
 static mut CYCLES_PER_SECOND: u64 = 0;
 static INIT: Once = ONCE_INIT;
 
@@ -77,6 +79,87 @@ pub fn to_seconds(cycles: u64) -> f64 {
     cycles as f64 / cycles_per_second() as f64
 }
 
+/// The interval in milliseconds after which a NicClock recalibrates against rdtsc(). Keeps drift
+/// between the two clocks well under a microsecond.
+const NIC_CLOCK_CALIBRATE_MS: u64 = 10;
+
+/// Converts hardware timestamps taken by a NIC into rdtsc() cycles, so that they can be compared
+/// against software time stamps, such as the ones on requests. A timestamp taken by the NIC when
+/// a packet arrives excludes the time the packet then waited in the receive queue before it was
+/// polled, which is noise when comparing latencies under a microsecond.
+pub struct NicClock {
+    // The port whose clock timestamps packets.
+    port: i32,
+
+    // The NIC's clock in nanoseconds at the last calibration.
+    nic: u64,
+
+    // rdtsc() at the last calibration.
+    tsc: u64,
+
+    // The interval in cycles after which the clock is recalibrated.
+    interval: u64,
+}
+
+// Implementation of methods on NicClock.
+impl NicClock {
+    /// Returns a NicClock for a port.
+    ///
+    /// # Arguments
+    ///
+    /// * `port`: The port whose hardware timestamps will be converted.
+    ///
+    /// # Return
+    ///
+    /// A NicClock, or None if the port's clock cannot be read.
+    pub fn new(port: i32) -> Option<NicClock> {
+        let mut clock = NicClock {
+            port: port,
+            nic: 0,
+            tsc: 0,
+            interval: (NIC_CLOCK_CALIBRATE_MS * cycles_per_second()) / 1000,
+        };
+
+        if clock.calibrate() {
+            Some(clock)
+        } else {
+            None
+        }
+    }
+
+    /// Converts a hardware timestamp into rdtsc() cycles.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`: The hardware timestamp in nanoseconds of the NIC's clock.
+    pub fn to_cycles(&mut self, stamp: u64) -> u64 {
+        if rdtsc() - self.tsc > self.interval {
+            self.calibrate();
+        }
+
+        let delta = stamp as i64 - self.nic as i64;
+        (self.tsc as i64 + (delta * cycles_per_second() as i64) / 1000000000) as u64
+    }
+
+    // Takes a parallel reading of the NIC's clock and rdtsc(). Returns false if the NIC's clock
+    // could not be read.
+    fn calibrate(&mut self) -> bool {
+        let before = rdtsc();
+        let nic = dpdk::read_nic_clock(self.port);
+        let after = rdtsc();
+
+        match nic {
+            Some(nic) => {
+                self.nic = nic;
+                self.tsc = before + (after - before) / 2;
+                true
+            }
+
+            None => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -13,43 +13,1786 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt::Display;
-use std::net::Ipv4Addr;
+use std::mem::{size_of, transmute};
 use std::option::Option;
-use std::str::FromStr;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use super::common;
 use super::config;
 use super::cycles;
+use super::cycles::NicClock;
 use super::master::Master;
 use super::rpc::*;
-use super::sched::RoundRobin;
+use super::sampler::Sampler;
+use super::sched::{ExpressTx, RoundRobin};
 use super::service::Service;
-use super::task::{Task, TaskPriority, TaskState};
+use super::stats::utilization;
+use super::statspage::{CoreStats, StatsSlot};
+use super::task::{CostClass, Task, TaskPriority, TaskState};
+use super::trace::{RequestId, Traced};
+use super::transport::Transport;
 use super::wireformat;
 
 use super::e2d2::common::EmptyMetadata;
 use super::e2d2::headers::*;
 use super::e2d2::interface::*;
+use super::e2d2::native::zcsi::MBuf;
 
 use cyclecounter::CycleCounter;
+use spin::{Mutex, RwLock};
+
+/// The maximum number of polls for which a dispatcher will skip stealing from it's sibling after
+/// a run of failed steal attempts.
+const MAX_STEAL_BACKOFF: u64 = 1024;
+
+/// The number of polls between successive updates by a dispatcher to it's slot on the server's
+/// stats page. Reading the scheduler's queue depths takes locks, so this is not done every poll.
+const STATS_PAGE_POLLS: u64 = 1024;
+
+/// This type implements exponential backoff on steal attempts from a sibling's receive queue.
+/// When all dispatchers are idle, stealing on every poll just bounces the sibling queue's cache
+/// lines between cores. Every failed attempt doubles the number of polls skipped before the next
+/// one (upto `MAX_STEAL_BACKOFF`), and a successful attempt resets the backoff immediately.
+struct StealBackoff {
+    // The number of polls to skip after the most recent failed attempt.
+    backoff: u64,
+
+    // The number of polls left to skip before the next attempt.
+    skip: u64,
+
+    // The number of steal attempts made so far.
+    attempts: u64,
+
+    // The number of steal attempts that returned packets.
+    successes: u64,
+
+    // The number of steal attempts made, and the number that returned packets, since the
+    // dispatcher started. Unlike the above, these are never reset.
+    totals: (u64, u64),
+}
+
+// Implementation of methods on StealBackoff.
+impl StealBackoff {
+    /// Returns a StealBackoff that allows an attempt on the very first poll.
+    fn new() -> StealBackoff {
+        StealBackoff {
+            backoff: 0,
+            skip: 0,
+            attempts: 0,
+            successes: 0,
+            totals: (0, 0),
+        }
+    }
+
+    /// Returns true if a steal must be attempted on this poll. Must be followed by a call to
+    /// `record()` if true.
+    #[inline]
+    fn should_attempt(&mut self) -> bool {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return false;
+        }
+
+        true
+    }
+
+    /// Records the outcome of a steal attempt, and updates the backoff accordingly.
+    ///
+    /// # Arguments
+    ///
+    /// * `success`: True if the attempt returned packets.
+    #[inline]
+    fn record(&mut self, success: bool) {
+        self.attempts += 1;
+        self.totals.0 += 1;
+
+        if success {
+            self.successes += 1;
+            self.totals.1 += 1;
+            self.backoff = 0;
+        } else {
+            self.backoff = if self.backoff == 0 {
+                1
+            } else {
+                (self.backoff * 2).min(MAX_STEAL_BACKOFF)
+            };
+        }
+
+        self.skip = self.backoff;
+    }
+
+    /// Returns the number of attempts and successes since the last call, and resets them.
+    fn take_stats(&mut self) -> (u64, u64) {
+        let stats = (self.attempts, self.successes);
+        self.attempts = 0;
+        self.successes = 0;
+        stats
+    }
+
+    /// Returns the number of attempts and successes since the dispatcher started.
+    fn totals(&self) -> (u64, u64) {
+        self.totals
+    }
+}
+
+/// The number of distinct opcodes whose request rates are tracked by a `RateMonitor`. Opcodes
+/// are used directly as indices, so this covers every valid opcode as well as InvalidOperation.
+const NUM_OPCODES: usize = wireformat::OpCode::InvalidOperation as usize + 1;
+
+/// The weight given to the most recent interval when updating a `RateMonitor`'s baselines.
+const RATE_EWMA_ALPHA: f64 = 0.2;
+
+/// The number of intervals a `RateMonitor` waits for it's baselines to settle before raising
+/// any alerts.
+const RATE_WARMUP_INTERVALS: u64 = 5;
+
+/// Rates (in requests per second) below which an opcode is never considered anomalous. Keeps
+/// opcodes that are only rarely issued (ex: install()) from raising alerts on every request.
+const RATE_ALERT_FLOOR: f64 = 100.0;
+
+/// This type tracks the rate at which each opcode is received by a dispatcher over fixed
+/// intervals, and maintains an exponentially weighted moving average of these rates as a
+/// baseline. An opcode whose rate over an interval exceeds, or falls below it's baseline by more
+/// than a configured factor is flagged as anomalous, catching runaway clients or misconfigured
+/// benchmarks early in long runs.
+struct RateMonitor {
+    // Rates deviating from their baseline by more than this factor are anomalous. A factor of
+    // zero disables the monitor.
+    factor: f64,
+
+    // The length of an interval in cycles.
+    interval: u64,
+
+    // The time stamp in cycles at which the current interval started.
+    start: u64,
+
+    // The number of requests received for each opcode in the current interval.
+    counts: [u64; NUM_OPCODES],
+
+    // The baseline rate for each opcode in requests per second.
+    baselines: [f64; NUM_OPCODES],
+
+    // The number of intervals completed so far.
+    intervals: u64,
+
+    // True if any opcode was found to be anomalous in the most recent interval.
+    anomalous: bool,
+}
+
+// Implementation of methods on RateMonitor.
+impl RateMonitor {
+    /// Returns a RateMonitor.
+    ///
+    /// # Arguments
+    ///
+    /// * `factor`:   Rates deviating from their baseline by more than this factor are anomalous.
+    ///               Zero disables the monitor.
+    /// * `interval`: The length of an interval in cycles over which rates are measured.
+    fn new(factor: f64, interval: u64) -> RateMonitor {
+        RateMonitor {
+            factor: factor,
+            interval: interval,
+            start: 0,
+            counts: [0; NUM_OPCODES],
+            baselines: [0.0; NUM_OPCODES],
+            intervals: 0,
+            anomalous: false,
+        }
+    }
+
+    /// Returns true if the monitor has been configured to track rates.
+    #[inline]
+    fn enabled(&self) -> bool {
+        self.factor > 0.0
+    }
+
+    /// Records a request received by the dispatcher.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode`: The opcode on the received request.
+    #[inline]
+    fn record(&mut self, opcode: &wireformat::OpCode) {
+        let idx = match *opcode {
+            wireformat::OpCode::SandstormGetRpc => 1,
+            wireformat::OpCode::SandstormPutRpc => 2,
+            wireformat::OpCode::SandstormInvokeRpc => 3,
+            wireformat::OpCode::SandstormInstallRpc => 4,
+            wireformat::OpCode::SandstormMultiGetRpc => 5,
+            wireformat::OpCode::SandstormMultiTableGetRpc => 6,
+            wireformat::OpCode::SandstormListTablesRpc => 7,
+            wireformat::OpCode::SandstormAssocAddRpc => 8,
+            wireformat::OpCode::SandstormAssocDelRpc => 9,
+            wireformat::OpCode::SandstormAssocRangeRpc => 10,
+            wireformat::OpCode::SandstormAssocCountRpc => 11,
+            wireformat::OpCode::SandstormServerInfoRpc => 12,
+            wireformat::OpCode::SandstormMoveKeyRpc => 13,
+            wireformat::OpCode::SandstormBulkPutRpc => 14,
+            wireformat::OpCode::SandstormMigrateRpc => 15,
+            wireformat::OpCode::SandstormScanRpc => 16,
+            wireformat::OpCode::SandstormShadowRpc => 17,
+            wireformat::OpCode::SandstormCasRpc => 18,
+            wireformat::OpCode::SandstormFragment => 19,
+            wireformat::OpCode::SandstormSchemaRpc => 20,
+            wireformat::OpCode::SandstormSnapshotRpc => 21,
+            wireformat::OpCode::SandstormRestoreRpc => 22,
+            wireformat::OpCode::SandstormDeleteRpc => 23,
+            wireformat::OpCode::SandstormGetStatisticsRpc => 24,
+            wireformat::OpCode::SandstormTenantAdminRpc => 25,
+            wireformat::OpCode::InvalidOperation => 26,
+        };
+
+        self.counts[idx] += 1;
+    }
+
+    /// Closes the current interval if it has run it's course, and updates baselines.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: The current time stamp in cycles.
+    ///
+    /// # Return
+    ///
+    /// The opcode, rate and baseline (in requests per second) of every anomalous opcode if an
+    /// interval was closed. An empty vector otherwise.
+    fn tick(&mut self, now: u64) -> Vec<(usize, f64, f64)> {
+        let mut alerts = Vec::new();
+
+        if self.start == 0 {
+            self.start = now;
+        }
+        if now - self.start < self.interval {
+            return alerts;
+        }
+
+        let secs = cycles::to_seconds(now - self.start);
+        let warm = self.intervals >= RATE_WARMUP_INTERVALS;
+
+        for op in 0..NUM_OPCODES {
+            let rate = self.counts[op] as f64 / secs;
+            let base = self.baselines[op];
+
+            if warm && rate.max(base) >= RATE_ALERT_FLOOR
+                && (rate > base * self.factor || rate * self.factor < base)
+            {
+                alerts.push((op, rate, base));
+            }
+
+            // The very first interval seeds the baseline.
+            self.baselines[op] = if self.intervals == 0 {
+                rate
+            } else {
+                RATE_EWMA_ALPHA * rate + (1.0 - RATE_EWMA_ALPHA) * base
+            };
+            self.counts[op] = 0;
+        }
+
+        self.intervals += 1;
+        self.anomalous = !alerts.is_empty();
+        self.start = now;
+
+        alerts
+    }
+}
+
+/// This type accounts for the bytes a dispatcher sends out in responses to each tenant, and
+/// periodically reports the bandwidth each tenant used over the last interval. Byte counts
+/// include the MAC and IP headers on every packet, so that they add up to the dispatcher's share
+/// of the link. Totals since the dispatcher started are kept as well, for billing.
+struct EgressMeter {
+    // The length of an interval in cycles. Zero disables the meter.
+    interval: u64,
+
+    // The time stamp in cycles at which the current interval started.
+    start: u64,
+
+    // The number of response packets and bytes sent out to each tenant in the current interval.
+    counts: HashMap<u32, (u64, u64)>,
+
+    // The number of bytes sent out to each tenant since the dispatcher started.
+    totals: HashMap<u32, u64>,
+}
+
+// Implementation of methods on EgressMeter.
+impl EgressMeter {
+    /// Returns an EgressMeter.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval`: The length of an interval in cycles over which bandwidth is reported. Zero
+    ///               disables the meter.
+    fn new(interval: u64) -> EgressMeter {
+        EgressMeter {
+            interval: interval,
+            start: 0,
+            counts: HashMap::new(),
+            totals: HashMap::new(),
+        }
+    }
+
+    /// Returns true if the meter has been configured to account for responses.
+    #[inline]
+    fn enabled(&self) -> bool {
+        self.interval > 0
+    }
+
+    /// Records a response packet sent out by the dispatcher.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant the packet was sent out to.
+    /// * `bytes`:  The size of the packet on the wire in bytes.
+    #[inline]
+    fn record(&mut self, tenant: u32, bytes: u64) {
+        let count = self.counts.entry(tenant).or_insert((0, 0));
+        count.0 += 1;
+        count.1 += bytes;
+    }
+
+    /// Closes the current interval if it has run it's course, and folds it into the totals.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: The current time stamp in cycles.
+    ///
+    /// # Return
+    ///
+    /// The tenant, packets and bytes sent out in the interval, bandwidth in Gbps, and total bytes
+    /// sent out of every tenant that was sent anything, ordered by tenant, if an interval was
+    /// closed. An empty vector otherwise.
+    fn tick(&mut self, now: u64) -> Vec<(u32, u64, u64, f64, u64)> {
+        let mut report = Vec::new();
+
+        if self.start == 0 {
+            self.start = now;
+        }
+        if now - self.start < self.interval {
+            return report;
+        }
+
+        let secs = cycles::to_seconds(now - self.start);
+        for (tenant, (packets, bytes)) in self.counts.drain() {
+            let total = self.totals.entry(tenant).or_insert(0);
+            *total += bytes;
+            report.push((tenant, packets, bytes, (bytes * 8) as f64 / secs / 1e9, *total));
+        }
+        report.sort_by_key(|entry| entry.0);
+
+        self.start = now;
+        report
+    }
+}
+
+/// This type detects a persistent backlog at a dispatcher's receive queue. A receive that fills
+/// up an entire batch indicates that there were more packets waiting than could be picked up; a
+/// run of such receives means that the dispatcher is falling behind the network.
+struct Backlog {
+    // The number of consecutive full receives after which the backlog is considered persistent.
+    // Zero if detection is disabled.
+    threshold: u64,
+
+    // The number of consecutive full receives seen so far.
+    run: u64,
+}
+
+// Implementation of methods on Backlog.
+impl Backlog {
+    /// Returns a Backlog that flags a backlog after `threshold` consecutive full receives. A
+    /// threshold of zero disables detection.
+    fn new(threshold: u64) -> Backlog {
+        Backlog {
+            threshold: threshold,
+            run: 0,
+        }
+    }
+
+    /// Records the outcome of a receive.
+    ///
+    /// # Arguments
+    ///
+    /// * `full`: True if the receive filled up an entire batch.
+    ///
+    /// # Return
+    ///
+    /// True if the receive queue has a persistent backlog, and the received batch should be
+    /// handed off to the scheduler as a `BulkDispatch` task.
+    #[inline]
+    fn record(&mut self, full: bool) -> bool {
+        if !full {
+            self.run = 0;
+            return false;
+        }
+
+        self.run += 1;
+        self.threshold > 0 && self.run >= self.threshold
+    }
+}
+
+/// This type decides whether a dispatcher groups each received batch by opcode and tenant
+/// before handing it off to Master, so that requests taking the same code paths and touching the
+/// same tenant run back to back with warm caches. When configured with a period, the switch
+/// alternates between grouped and ungrouped batches and tracks the cost of dispatching a request
+/// under each, so that the benefit of grouping can be measured on a live workload.
+struct GroupSwitch {
+    // True if batches are grouped when the switch is not alternating.
+    grouped: bool,
+
+    // The number of batches dispatched one way before switching to the other. Zero if the switch
+    // does not alternate.
+    period: u64,
+
+    // The number of batches dispatched so far.
+    batches: u64,
+
+    // The total time in cycles spent dispatching ungrouped and grouped batches respectively, in
+    // the current measurement interval.
+    cycles: [u64; 2],
+
+    // The number of requests in ungrouped and grouped batches respectively, in the current
+    // measurement interval.
+    requests: [u64; 2],
+}
+
+// Implementation of methods on GroupSwitch.
+impl GroupSwitch {
+    /// Returns a GroupSwitch that always groups batches if `grouped` is true, unless `period` is
+    /// non-zero, in which case it alternates every `period` batches.
+    fn new(grouped: bool, period: u64) -> GroupSwitch {
+        GroupSwitch {
+            grouped: grouped,
+            period: period,
+            batches: 0,
+            cycles: [0; 2],
+            requests: [0; 2],
+        }
+    }
+
+    /// Returns true if the next batch should be grouped.
+    #[inline]
+    fn grouped(&self) -> bool {
+        if self.period == 0 {
+            self.grouped
+        } else {
+            (self.batches / self.period) % 2 == 1
+        }
+    }
+
+    /// Records the cost of dispatching a batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `grouped`:  True if the batch was grouped.
+    /// * `requests`: The number of requests in the batch.
+    /// * `cycles`:   The time in cycles it took to dispatch the batch.
+    #[inline]
+    fn record(&mut self, grouped: bool, requests: u64, cycles: u64) {
+        let arm = grouped as usize;
+        self.cycles[arm] += cycles;
+        self.requests[arm] += requests;
+        self.batches += 1;
+    }
+
+    /// Returns the average time in cycles it took to dispatch a request in ungrouped and grouped
+    /// batches since the last call, and resets them.
+    fn take_stats(&mut self) -> (f64, f64) {
+        let stats = {
+            let avg = |arm: usize| self.cycles[arm] as f64 / self.requests[arm].max(1) as f64;
+            (avg(0), avg(1))
+        };
+        self.cycles = [0; 2];
+        self.requests = [0; 2];
+        stats
+    }
+}
+
+/// Returns the key a request is grouped on within a batch: it's service, opcode and tenant.
+/// Requests too short to carry these sort to the end.
+#[inline]
+fn group_key(request: &Packet<UdpHeader, EmptyMetadata>) -> (u8, u8, u32) {
+    let payload = request.get_payload();
+    if payload.len() < 6 {
+        return (u8::max_value(), u8::max_value(), u32::max_value());
+    }
+
+    (payload[0], payload[1], parse_rpc_tenant(request))
+}
+
+/// The length of an ARP packet for IPv4 over ethernet, following the MAC header.
+const ARP_LEN: usize = 28;
+
+/// Turns an ARP request for an IPv4 address into the reply to it, in place.
+///
+/// # Arguments
+///
+/// * `arp`: The ARP packet, following the MAC header.
+/// * `mac`: The MAC address to answer the request with.
+/// * `ip`:  The IPv4 address whose requests are answered.
+///
+/// # Return
+///
+/// True if `arp` was a request for `ip` and now holds the reply. False if it was left untouched.
+fn reply_arp(arp: &mut [u8], mac: &[u8; 6], ip: u32) -> bool {
+    let ip: [u8; 4] = [(ip >> 24) as u8, (ip >> 16) as u8, (ip >> 8) as u8, ip as u8];
+
+    // Only requests (operation 1) for IPv4 (0x0800) over ethernet (1) are answered.
+    if arp.len() < ARP_LEN || arp[..8] != [0, 1, 8, 0, 6, 4, 0, 1] || arp[24..28] != ip {
+        return false;
+    }
+
+    // The sender of the request becomes the target of the reply.
+    let mut sender = [0; 10];
+    sender.copy_from_slice(&arp[8..18]);
+    arp[18..28].copy_from_slice(&sender);
+
+    arp[7] = 2;
+    arp[8..14].copy_from_slice(mac);
+    arp[14..18].copy_from_slice(&ip);
+    true
+}
+
+/// The length of a MAC header.
+const MAC_LEN: usize = 14;
+
+/// The length of an IPv6 header without extension headers.
+const IPV6_HEADER_LEN: usize = (common::PACKET_IPV6_LEN - common::PACKET_UDP_LEN) as usize;
+
+/// The number of bytes by which an IPv6 header is longer than an IPv4 one without options.
+const IPV6_GROWTH: usize = IPV6_HEADER_LEN - size_of::<IpHeader>();
+
+/// The maximum number of clients that can talk to the server over IPv6 at a time. Requests from
+/// any more clients are dropped until one of them goes idle. Must fit in the lower 24 bits of an
+/// IPv4 address.
+const MAX_IPV6_CLIENTS: usize = 1 << 16;
+
+/// The time in seconds after which an IPv6 client that has not sent a request can be replaced
+/// by a new one. Far longer than any request runs for, so that a response is never sent to a
+/// client that replaced the one that issued its request.
+const IPV6_CLIENT_IDLE_SECS: u64 = 60;
+
+/// The number of slots looked at for an idle IPv6 client when a new one arrives at a full table.
+const IPV6_EVICT_SCAN: usize = 64;
+
+/// The IPv6 addresses of the server and of every client that sent it a request over IPv6.
+/// Requests received over IPv6 are rewritten into IPv4 ones whose source address is the client's
+/// slot on this table, which falls in 0.0.0.0/8 and cannot be the source of an IPv4 request.
+/// Their responses carry the slot as their destination, and are rewritten back into IPv6 ones
+/// for the client it identifies. Refer to `ipv6_to_ipv4()` and `to_ipv6()`.
+///
+/// Requests are translated rather than parsed as IPv6 because everything past the dispatcher
+/// (RPC parsing, tasks, the schedulers' response queues, and the construction of responses off
+/// their request's headers) works on e2d2's IPv4 `IpHeader`. Translating at the edge leaves a
+/// single code path behind it, at the cost of rewriting the headers of every IPv6 request and
+/// response in place.
+///
+/// Tasks and their responses move between cores, so the table is shared by every dispatcher
+/// and scheduler on the server. Once the table is full, a new client replaces one that has been
+/// idle for `IPV6_CLIENT_IDLE_SECS`, so that a flood of spoofed addresses locks clients out for
+/// at most that long. Known clients are looked up under a read lock.
+pub struct Ipv6Clients {
+    // The server's IPv6 address.
+    server: [u8; 16],
+
+    // The address of the client in every slot, the slot of every client, and the slot the next
+    // search for an idle client starts at.
+    clients: RwLock<(Vec<[u8; 16]>, HashMap<[u8; 16], u32>, usize)>,
+
+    // The time stamp in cycles at which the client in every slot last sent a request.
+    seen: Vec<AtomicUsize>,
+
+    // The time stamp in cycles before which requests from new clients are dropped without
+    // searching for an idle slot, set when a search on a full table comes up empty.
+    retry: AtomicUsize,
+
+    // The number of slots, and the time in cycles after which a client is idle.
+    max: usize,
+    idle: u64,
+
+    // Set once the table fills up, and requests from new clients start being dropped.
+    full: AtomicBool,
+}
+
+// Implementation of methods on Ipv6Clients.
+impl Ipv6Clients {
+    /// Creates an empty table of clients for a server reachable at an IPv6 address.
+    pub fn new(server: [u8; 16]) -> Ipv6Clients {
+        let idle = IPV6_CLIENT_IDLE_SECS * cycles::cycles_per_second();
+        Ipv6Clients::with_limits(server, MAX_IPV6_CLIENTS, idle)
+    }
+
+    // Creates an empty table with `max` slots, whose clients are idle after `idle` cycles.
+    fn with_limits(server: [u8; 16], max: usize, idle: u64) -> Ipv6Clients {
+        Ipv6Clients {
+            server: server,
+            clients: RwLock::new((Vec::new(), HashMap::new(), 0)),
+            seen: (0..max).map(|_| AtomicUsize::new(0)).collect(),
+            retry: AtomicUsize::new(0),
+            max: max,
+            idle: idle,
+            full: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the slot of a client on the table, adding it if required. None if the table is
+    /// full, and no client on it is idle.
+    fn index(&self, client: &[u8; 16]) -> Option<u32> {
+        self.index_at(client, cycles::rdtsc())
+    }
+
+    // Refer to `index()`. `now` is the current time stamp in cycles.
+    fn index_at(&self, client: &[u8; 16], now: u64) -> Option<u32> {
+        if let Some(idx) = self.clients.read().1.get(client).cloned() {
+            self.seen[idx as usize].store(now as usize, Ordering::Relaxed);
+            return Some(idx);
+        }
+
+        if (now as usize) < self.retry.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let mut clients = self.clients.write();
+        if let Some(idx) = clients.1.get(client).cloned() {
+            self.seen[idx as usize].store(now as usize, Ordering::Relaxed);
+            return Some(idx);
+        }
+
+        let idx = if clients.0.len() < self.max {
+            clients.0.push(*client);
+            clients.0.len() - 1
+        } else {
+            match self.evict(&mut *clients, now) {
+                Some(idx) => {
+                    clients.0[idx] = *client;
+                    idx
+                }
+
+                None => {
+                    if !self.full.swap(true, Ordering::Relaxed) {
+                        warn!("Too many IPv6 clients, dropping requests from new ones");
+                    }
+
+                    // Look again in a millisecond; a flood of new clients must not take the
+                    // write lock on every request in the meantime.
+                    let retry = now + cycles::cycles_per_second() / 1000;
+                    self.retry.store(retry as usize, Ordering::Relaxed);
+                    return None;
+                }
+            }
+        };
+
+        clients.1.insert(*client, idx as u32);
+        self.seen[idx].store(now as usize, Ordering::Relaxed);
+        Some(idx as u32)
+    }
+
+    // Looks for an idle client on a full table, starting where the last search left off and
+    // looking at no more than `IPV6_EVICT_SCAN` slots. Returns its slot after removing it.
+    fn evict(
+        &self,
+        clients: &mut (Vec<[u8; 16]>, HashMap<[u8; 16], u32>, usize),
+        now: u64,
+    ) -> Option<usize> {
+        for _ in 0..IPV6_EVICT_SCAN.min(self.max) {
+            let idx = clients.2;
+            clients.2 = (idx + 1) % self.max;
+
+            let seen = self.seen[idx].load(Ordering::Relaxed) as u64;
+            if now.saturating_sub(seen) >= self.idle {
+                let old = clients.0[idx];
+                clients.1.remove(&old);
+                return Some(idx);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the address of the client in a slot on the table.
+    fn client(&self, idx: u32) -> Option<[u8; 16]> {
+        self.clients.read().0.get(idx as usize).cloned()
+    }
+}
+
+/// Rewrites a UDP over IPv6 request into the UDP over IPv4 request the rest of the server
+/// expects, in place. The IPv4 request starts `IPV6_GROWTH` bytes into the frame, and carries a
+/// source address in 0.0.0.0/8 identifying the client, which marks it as received over IPv6.
+/// Refer to `Ipv6Clients`.
+///
+/// # Arguments
+///
+/// * `frame`:  The request, starting at it's MAC header.
+/// * `addr`:   The IPv6 address of the server.
+/// * `ip`:     The destination address to set on the IPv4 request.
+/// * `client`: Returns the index identifying the client at an IPv6 address, or None if the
+///             request should be dropped. Only called for valid requests.
+///
+/// # Return
+///
+/// True if `frame` was a valid request for `addr`, and now holds the IPv4 request. A request is
+/// valid if it carries no extension headers, it's hop limit is greater than zero, and it's UDP
+/// header and payload are long enough. False if it was left untouched.
+fn ipv6_to_ipv4(
+    frame: &mut [u8],
+    addr: &[u8; 16],
+    ip: u32,
+    client: &Fn(&[u8; 16]) -> Option<u32>,
+) -> bool {
+    const MIN_LENGTH_UDP: usize = common::PACKET_UDP_LEN as usize + 2;
+    let udp = MAC_LEN + IPV6_HEADER_LEN;
+    if frame.len() < udp + MIN_LENGTH_UDP {
+        return false;
+    }
+
+    let length = ((frame[MAC_LEN + 4] as usize) << 8) | frame[MAC_LEN + 5] as usize;
+    let hop = frame[MAC_LEN + 7];
+    {
+        let ipv6 = &frame[MAC_LEN..udp];
+        if (ipv6[0] >> 4) != 6 || ipv6[6] != 0x11 || hop == 0 || ipv6[24..40] != addr[..]
+            || length < MIN_LENGTH_UDP || length > frame.len() - udp
+        {
+            return false;
+        }
+    }
+
+    let mut src = [0; 16];
+    src.copy_from_slice(&frame[MAC_LEN + 8..MAC_LEN + 24]);
+    let idx = match client(&src) {
+        Some(idx) => idx,
+        None => return false,
+    };
+
+    // The IPv4 header overwrites the tail end of the IPv6 one.
+    let ip: [u8; 4] = [(ip >> 24) as u8, (ip >> 16) as u8, (ip >> 8) as u8, ip as u8];
+    let idx: [u8; 4] = [0, (idx >> 16) as u8, (idx >> 8) as u8, idx as u8];
+    let total = length + size_of::<IpHeader>();
+    {
+        let ipv4 = &mut frame[udp - size_of::<IpHeader>()..udp];
+        for byte in ipv4.iter_mut() {
+            *byte = 0;
+        }
+        ipv4[0] = (common::PACKET_IP_VER << 4) | common::PACKET_IP_IHL;
+        ipv4[2] = (total >> 8) as u8;
+        ipv4[3] = total as u8;
+        ipv4[8] = hop;
+        ipv4[9] = 0x11;
+        ipv4[12..16].copy_from_slice(&idx);
+        ipv4[16..20].copy_from_slice(&ip);
+    }
+
+    // The MAC addresses move up to just before the IPv4 header.
+    let mut addrs = [0; 12];
+    addrs.copy_from_slice(&frame[..12]);
+    frame[IPV6_GROWTH..IPV6_GROWTH + 12].copy_from_slice(&addrs);
+    frame[IPV6_GROWTH + 12] = (common::PACKET_ETYPE >> 8) as u8;
+    frame[IPV6_GROWTH + 13] = common::PACKET_ETYPE as u8;
+    true
+}
+
+/// Returns the MAC and IP headers of a request, which it's response is sent back according to.
+fn request_headers(request: &Packet<UdpHeader, EmptyMetadata>) -> (&MacHeader, &IpHeader) {
+    // Headers are laid out back to back in the request's mbuf, and requests never carry IP
+    // options, so it's IP header is right before it's UDP header.
+    unsafe {
+        let ip = (request.get_header() as *const UdpHeader as *const u8)
+            .offset(-(size_of::<IpHeader>() as isize));
+        let mac = ip.offset(-(size_of::<MacHeader>() as isize));
+        (&*(mac as *const MacHeader), &*(ip as *const IpHeader))
+    }
+}
+
+/// Returns true if a response is to be sent out over IPv6. Responses to requests received over
+/// IPv6 carry the client's index on `Ipv6Clients` as their destination until they are sent out.
+fn to_ipv6(response: &Packet<IpHeader, EmptyMetadata>) -> bool {
+    (response.get_header().dst() >> 24) == 0
+}
+
+/// How the checksums on the IP and UDP headers of responses sent out over IPv4 are filled in.
+/// Responses sent out over IPv6 always carry a UDP checksum computed in software.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Checksums {
+    /// Both are left as zero. Cheapest, but the IP header is invalid, and responses are dropped
+    /// by anything on the way to the client that validates it.
+    Off,
+
+    /// The IP header is checksummed in software. The UDP checksum is left as zero, which UDP
+    /// over IPv4 allows.
+    Ip,
+
+    /// Both are checksummed in software.
+    Full,
+
+    /// Both are checksummed by the NIC on transmit. The port must be configured with checksum
+    /// offload.
+    Offload,
+}
+
+// Implementation of methods on Checksums.
+impl Checksums {
+    /// Parses `response_checksums` off the server's config. Empty defaults to `Ip`.
+    pub fn parse(mode: &str) -> Checksums {
+        match mode {
+            "off" => Checksums::Off,
+            "full" => Checksums::Full,
+            "offload" => Checksums::Offload,
+            "" | "ip" => Checksums::Ip,
+
+            _ => {
+                warn!("Unknown response_checksums {:?}, checksumming IP headers", mode);
+                Checksums::Ip
+            }
+        }
+    }
+}
+
+/// Adds a buffer as a sequence of big endian 16 bit words to a running checksum. An odd byte at
+/// the end is padded with zero, so only the last buffer added can be of odd length.
+fn checksum_add(sum: u32, bytes: &[u8]) -> u32 {
+    bytes.chunks(2).fold(sum, |sum, word| {
+        sum + (((word[0] as u32) << 8) | word.get(1).map_or(0, |b| *b as u32))
+    })
+}
+
+/// Folds the carries of a running checksum back into it's lower 16 bits.
+fn checksum_fold(mut sum: u32) -> u16 {
+    while (sum >> 16) != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// Completes a UDP checksum. A checksum that works out to zero is sent as all ones, since zero
+/// means that there isn't one.
+fn udp_checksum(sum: u32) -> u16 {
+    match !checksum_fold(sum) {
+        0 => 0xffff,
+        sum => sum,
+    }
+}
+
+/// Computes the checksum on a UDP over IPv6 packet. IPv6 does not allow it to be left out.
+///
+/// # Arguments
+///
+/// * `src`: The source address on the packet.
+/// * `dst`: The destination address on the packet.
+/// * `udp`: The UDP header and payload. The checksum on the header must be zero.
+fn udp6_checksum(src: &[u8; 16], dst: &[u8; 16], udp: &[u8]) -> u16 {
+    // The pseudo header consists of the addresses, the UDP length and the next header.
+    let sum = checksum_add(checksum_add(udp.len() as u32 + 0x11, src), dst);
+    udp_checksum(checksum_add(sum, udp))
+}
+
+/// Fills in the checksums on a UDP over IPv4 response, in place.
+///
+/// # Arguments
+///
+/// * `frame`:     The response, starting at it's MAC header. Must not carry IP options.
+/// * `checksums`: Which checksums to fill in. With `Offload`, the IP checksum is zeroed and the
+///                UDP checksum set to that of the pseudo header, as the NIC expects.
+fn ipv4_checksums(frame: &mut [u8], checksums: Checksums) {
+    if checksums == Checksums::Off {
+        return;
+    }
+
+    let udp = MAC_LEN + size_of::<IpHeader>();
+    let total = ((frame[MAC_LEN + 2] as usize) << 8) | frame[MAC_LEN + 3] as usize;
+    let length = total - size_of::<IpHeader>();
+
+    frame[MAC_LEN + 10] = 0;
+    frame[MAC_LEN + 11] = 0;
+    if checksums != Checksums::Offload {
+        let checksum = !checksum_fold(checksum_add(0, &frame[MAC_LEN..udp]));
+        frame[MAC_LEN + 10] = (checksum >> 8) as u8;
+        frame[MAC_LEN + 11] = checksum as u8;
+    }
+
+    if checksums == Checksums::Ip {
+        return;
+    }
+
+    // The pseudo header consists of the addresses, the protocol and the UDP length.
+    frame[udp + 6] = 0;
+    frame[udp + 7] = 0;
+    let sum = checksum_add(length as u32 + 0x11, &frame[MAC_LEN + 12..udp]);
+    let checksum = if checksums == Checksums::Offload {
+        checksum_fold(sum)
+    } else {
+        udp_checksum(checksum_add(sum, &frame[udp..udp + length]))
+    };
+    frame[udp + 6] = (checksum >> 8) as u8;
+    frame[udp + 7] = checksum as u8;
+}
+
+/// Rewrites a UDP over IPv4 response into a UDP over IPv6 one, in place.
+///
+/// # Arguments
+///
+/// * `frame`: The response, whose MAC header starts `IPV6_GROWTH` bytes into the frame. Once
+///            rewritten, the response starts at the beginning of the frame.
+/// * `src`:   The source address to set on the IPv6 response.
+/// * `dst`:   The destination address to set on the IPv6 response.
+fn ipv4_to_ipv6(frame: &mut [u8], src: &[u8; 16], dst: &[u8; 16]) {
+    let ipv4 = IPV6_GROWTH + MAC_LEN;
+    let total = ((frame[ipv4 + 2] as usize) << 8) | frame[ipv4 + 3] as usize;
+    let length = total - size_of::<IpHeader>();
+    let ttl = frame[ipv4 + 8];
+
+    // The MAC addresses move down to the beginning of the frame.
+    let mut addrs = [0; 12];
+    addrs.copy_from_slice(&frame[IPV6_GROWTH..IPV6_GROWTH + 12]);
+    frame[..12].copy_from_slice(&addrs);
+    frame[12] = (common::PACKET_IPV6_ETYPE >> 8) as u8;
+    frame[13] = common::PACKET_IPV6_ETYPE as u8;
+
+    let udp = MAC_LEN + IPV6_HEADER_LEN;
+    {
+        let ipv6 = &mut frame[MAC_LEN..udp];
+        ipv6[..4].copy_from_slice(&[0x60, 0, 0, 0]);
+        ipv6[4] = (length >> 8) as u8;
+        ipv6[5] = length as u8;
+        ipv6[6] = 0x11;
+        ipv6[7] = ttl;
+        ipv6[8..24].copy_from_slice(src);
+        ipv6[24..40].copy_from_slice(dst);
+    }
+
+    frame[udp + 6] = 0;
+    frame[udp + 7] = 0;
+    let checksum = udp6_checksum(src, dst, &frame[udp..udp + length]);
+    frame[udp + 6] = (checksum >> 8) as u8;
+    frame[udp + 7] = checksum as u8;
+}
+
+/// Hands over the mbuf of a response to be sent out, rewriting the response into an IPv6 one
+/// first if required, and filling in it's checksums. Refer to `to_ipv6()`.
+///
+/// # Arguments
+///
+/// * `response`:  The response, parsed upto it's IP header.
+/// * `ipv6`:      The IPv6 clients of the server, if the server is reachable over IPv6.
+/// * `checksums`: How the checksums on a response sent out over IPv4 are filled in.
+///
+/// # Return
+///
+/// The mbuf. None if there was no room in the mbuf for the IPv6 header, or the response was for
+/// an unknown IPv6 client, in which case the response is freed.
+unsafe fn response_mbuf(
+    response: Packet<IpHeader, EmptyMetadata>,
+    ipv6: &Option<Arc<Ipv6Clients>>,
+    checksums: Checksums,
+) -> Option<*mut MBuf> {
+    let dst = match *ipv6 {
+        Some(ref clients) if to_ipv6(&response) => {
+            Some((clients.server, clients.client(response.get_header().dst())))
+        }
+
+        _ => None,
+    };
+
+    let mbuf = response.get_mbuf();
+    let (src, dst) = match dst {
+        Some((src, Some(dst))) => (src, dst),
+
+        Some((_, None)) => {
+            packet_from_mbuf_no_increment::<NullHeader>(mbuf, 0).free_packet();
+            return None;
+        }
+
+        _ => {
+            let frame = slice::from_raw_parts_mut((*mbuf).data_address(0), (*mbuf).data_len());
+            ipv4_checksums(frame, checksums);
+            if checksums == Checksums::Offload {
+                (*mbuf).offload_udp_checksums(MAC_LEN, size_of::<IpHeader>());
+            }
+            return Some(mbuf);
+        }
+    };
+
+    if (*mbuf).add_data_beginning(IPV6_GROWTH) == 0 {
+        packet_from_mbuf_no_increment::<NullHeader>(mbuf, 0).free_packet();
+        return None;
+    }
+
+    ipv4_to_ipv6(
+        slice::from_raw_parts_mut((*mbuf).data_address(0), (*mbuf).data_len()),
+        &src,
+        &dst,
+    );
+    Some(mbuf)
+}
+
+/// Returns a function that sends responses out on a transmit queue one at a time, for a scheduler
+/// to send latency-critical responses out on as soon as their tasks complete. Refer to
+/// `RoundRobin::set_express()`.
+///
+/// # Arguments
+///
+/// * `port`:      The transmit queue. Must only be used from the thread the scheduler runs on.
+/// * `ipv6`:      The IPv6 clients of the server, if the server is reachable over IPv6. Must be
+///                shared with the dispatchers.
+/// * `checksums`: How the checksums on responses sent out over IPv4 are filled in.
+pub fn express_tx<T>(
+    port: T,
+    ipv6: Option<Arc<Ipv6Clients>>,
+    checksums: Checksums,
+) -> ExpressTx
+where
+    T: Transport + 'static,
+{
+    Box::new(move |response: Packet<IpHeader, EmptyMetadata>| unsafe {
+        let ipv6_bound = to_ipv6(&response);
+        let mut mbufs = match response_mbuf(response, &ipv6, checksums) {
+            Some(mbuf) => [mbuf],
+            None => return None,
+        };
+
+        match port.send_burst(&mut mbufs) {
+            Ok(1) => None,
+
+            // The response was not sent out, and has already been rewritten into an IPv6 one.
+            // It cannot be queued up behind other responses, so drop it.
+            _ if ipv6_bound && ipv6.is_some() => {
+                packet_from_mbuf_no_increment::<NullHeader>(mbufs[0], 0).free_packet();
+                None
+            }
+
+            // The response was not sent out. Hand it back parsed upto it's IP header.
+            _ => {
+                let response = packet_from_mbuf_no_increment::<MacHeader>(mbufs[0], 0);
+                Some(response.parse_header::<IpHeader>())
+            }
+        }
+    })
+}
+
+/// The maximum number of `BulkDispatch` tasks a dispatcher can have outstanding on it's peers.
+/// Once reached, received batches are processed by the dispatcher itself, which slows down it's
+/// receives and leaves the backlog on the receive queue instead of in software.
+const MAX_BUNDLES: usize = 4;
+
+/// The part of request processing that does not depend on the network port a request was
+/// received on: validating network headers, allocating responses and handing requests off to
+/// Master. Shared by a dispatcher and the `BulkDispatch` tasks it creates.
+struct Ingress {
+    /// A ref counted pointer to a master service. The master service
+    /// implements the primary interface to the database.
+    master: Arc<Master>,
+
+    /// A ref counted pointer to the scheduler on which to enqueue tasks,
+    /// and from which to receive response packets to be sent back to clients.
+    scheduler: Arc<RoundRobin>,
+
+    /// The IP address of the server. This is required to ensure that the
+    /// server does not process packets that were destined to a different
+    /// machine. Zero if the server is not reachable over IPv4.
+    network_ip_addr: u32,
+
+    /// True if the server is reachable over IPv4. IPv4 packets are dropped otherwise.
+    ipv4: bool,
+
+    /// The IPv6 clients of the server, if the server is reachable over IPv6. Requests received
+    /// over IPv6 are rewritten into IPv4 ones on arrival, and their responses rewritten back into
+    /// IPv6 ones when they are sent out. Refer to `Ipv6Clients`.
+    network_ipv6: Option<Arc<Ipv6Clients>>,
+
+    /// How the checksums on responses sent out over IPv4 are filled in. Set through
+    /// `response_checksums` in the server's config.
+    checksums: Checksums,
+
+    /// The UDP header that will be appended to every response packet (cached
+    /// here to avoid wasting time creating a new one for every response
+    /// packet).
+    resp_udp_header: UdpHeader,
+
+    /// The IP header that will be appended to every response packet (cached
+    /// here to avoid creating a new one for every response packet). It's
+    /// destination is that of the request the response is for.
+    resp_ip_header: IpHeader,
+
+    /// The MAC header that will be appended to every response packet (cached
+    /// here to avoid creating a new one for every response packet). It's
+    /// destination is that of the request the response is for.
+    resp_mac_header: MacHeader,
+
+    /// If true, every request for Master is tagged with it's correlation id, and events in it's
+    /// lifetime are logged at the trace level. Set through `trace_requests` in the server's
+    /// config.
+    trace: bool,
+
+    /// If true, received batches are grouped by opcode and tenant before they are handed off to
+    /// Master. Set through `group_requests` in the server's config.
+    group: bool,
+
+    /// If true, the deadline on every request for Master is attached to it's task, so that a
+    /// scheduler that orders tasks by deadline can run it in time. Set when `scheduler` in the
+    /// server's config is "edf".
+    deadlines: bool,
+
+    /// The cycle cap within which tasks of extensions declared SHORT are run inline instead of
+    /// being enqueued on the scheduler. Refer to `RoundRobin::run_inline()`. Zero disables
+    /// running them inline. Set through `inline_short_ns` in the server's config.
+    inline_cap: u64,
+
+    /// The number of requests with an unknown service or opcode received from each source,
+    /// keyed by UDP source port. Refer to `reject_unknown()`.
+    unknown: Mutex<HashMap<u16, u64>>,
+
+    /// Replies to ARP requests for the server's IP address, waiting to be sent out by the
+    /// dispatcher. Refer to `arp_replies()`.
+    arp: Mutex<Vec<Packet<MacHeader, EmptyMetadata>>>,
+
+    /// The number of `BulkDispatch` tasks created off this ingress that have not run yet. Capped
+    /// at `MAX_BUNDLES`.
+    bundles: AtomicUsize,
+}
+
+// Implementation of methods on Ingress.
+impl Ingress {
+    /// This function frees a set of packets that were received from DPDK.
+    ///
+    /// # Arguments
+    ///
+    /// * `packets`: A vector of packets wrapped in Netbrick's Packet<> type.
+    #[inline]
+    fn free_packets<S: EndOffset>(&self, mut packets: Vec<Packet<S, EmptyMetadata>>) {
+        while let Some(packet) = packets.pop() {
+            packet.free_packet();
+        }
+    }
+
+    /// Returns the number of requests with an unknown service or opcode received so far, and the
+    /// number of distinct sources they were received from.
+    fn unknown_opcodes(&self) -> (u64, usize) {
+        let unknown = self.unknown.lock();
+        (unknown.values().sum(), unknown.len())
+    }
+
+    /// Responds to a request carrying a service or opcode that is not known to this server with
+    /// `StatusBadOpcode`, so that a protocol mismatch shows up at the client instead of as a
+    /// request that silently timed out. The request is counted against it's source, and the
+    /// first one from every source is logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `request`:  The request. Freed by this method.
+    /// * `response`: The response pre-allocated for the request, with it's network headers.
+    fn reject_unknown(
+        &self,
+        request: Packet<UdpHeader, EmptyMetadata>,
+        response: Packet<UdpHeader, EmptyMetadata>,
+    ) {
+        let port = request.get_header().src_port();
+        let (service, opcode) = {
+            let payload = request.get_payload();
+            (payload[0], payload[1])
+        };
+
+        {
+            let mut unknown = self.unknown.lock();
+            let count = unknown.entry(port).or_insert(0);
+            if *count == 0 {
+                warn!(
+                    "Request with unknown service {:#x} or opcode {:#x} from port {}",
+                    service, opcode, port
+                );
+            }
+            *count += 1;
+        }
+
+        let opcode = wireformat::OpCode::InvalidOperation;
+        self.reply_status(request, response, opcode, wireformat::RpcStatus::StatusBadOpcode);
+    }
+
+    /// Answers a request with just a response header carrying a status, without dispatching it
+    /// to Master. The request packet is freed, and the response is queued up to be sent out.
+    ///
+    /// # Arguments
+    ///
+    /// * `request`:  The request. Freed by this method.
+    /// * `response`: The response pre-allocated for the request, with it's network headers.
+    /// * `opcode`:   The opcode the response is marked with.
+    /// * `status`:   The status the response is marked with.
+    fn reply_status(
+        &self,
+        request: Packet<UdpHeader, EmptyMetadata>,
+        mut response: Packet<UdpHeader, EmptyMetadata>,
+        opcode: wireformat::OpCode,
+        status: wireformat::RpcStatus,
+    ) {
+        // The tenant and stamp are echoed back only if the request is long enough to carry them.
+        let min = size_of::<wireformat::RpcRequestHeader>();
+        let (tenant, stamp) = if request.get_payload().len() >= min {
+            (parse_rpc_tenant(&request), parse_rpc_stamp(&request))
+        } else {
+            (0, 0)
+        };
+        request.free_packet();
+
+        let mut hdr = wireformat::RpcResponseHeader::new(stamp, opcode, tenant);
+        hdr.status = status;
+        let hdr: [u8; size_of::<wireformat::RpcResponseHeader>()] = unsafe { transmute(hdr) };
+        if response.add_to_payload_tail(hdr.len(), &hdr).is_err() {
+            response.free_packet();
+            return;
+        }
+
+        let mut responses = vec![fixup_header_length_fields(response)];
+        self.scheduler.append_resps(&mut responses);
+    }
+
+    /// Returns the replies to ARP requests that are waiting to be sent out.
+    fn arp_replies(&self) -> Vec<Packet<MacHeader, EmptyMetadata>> {
+        let mut arp = self.arp.lock();
+        arp.drain(..).collect()
+    }
+
+    /// Answers an ARP request for the server's IP address. The request is turned into the reply
+    /// in place, and queued up to be sent out by the dispatcher.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet`: The ARP packet, parsed upto it's MAC header.
+    ///
+    /// # Return
+    ///
+    /// The packet if it was not a request for the server's IP address, so that it can be freed.
+    fn answer_arp(
+        &self,
+        mut packet: Packet<MacHeader, EmptyMetadata>,
+    ) -> Option<Packet<MacHeader, EmptyMetadata>> {
+        let mac = self.resp_mac_header.src.addr;
+        if !reply_arp(packet.get_mut_payload(), &mac, self.network_ip_addr) {
+            return Some(packet);
+        }
+
+        {
+            let hdr = packet.get_mut_header();
+            let requester = hdr.src.addr;
+            hdr.dst.addr.copy_from_slice(&requester);
+            hdr.src.addr.copy_from_slice(&mac);
+        }
+
+        self.arp.lock().push(packet);
+        None
+    }
+
+    /// Rewrites a request received over IPv6 into an IPv4 one. Refer to `ipv6_to_ipv4()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet`: The request, parsed upto it's MAC header.
+    ///
+    /// # Return
+    ///
+    /// The rewritten request, parsed upto it's MAC header. The request as an error if it was not
+    /// a valid request for the server's IPv6 address, in which case it should be dropped.
+    fn rewrite_ipv6_request(
+        &self,
+        packet: Packet<MacHeader, EmptyMetadata>,
+    ) -> Result<Packet<MacHeader, EmptyMetadata>, Packet<MacHeader, EmptyMetadata>> {
+        let clients = match self.network_ipv6 {
+            Some(ref clients) => clients,
+            None => return Err(packet),
+        };
+
+        unsafe {
+            let mbuf = packet.get_mbuf();
+            let valid = ipv6_to_ipv4(
+                slice::from_raw_parts_mut((*mbuf).data_address(0), (*mbuf).data_len()),
+                &clients.server,
+                self.network_ip_addr,
+                &|client: &[u8; 16]| clients.index(client),
+            );
+            if valid {
+                (*mbuf).remove_data_beginning(IPV6_GROWTH);
+            }
+
+            let packet = packet_from_mbuf_no_increment::<MacHeader>(mbuf, 0);
+            if valid {
+                Ok(packet)
+            } else {
+                Err(packet)
+            }
+        }
+    }
+
+    /// This method parses the MAC headers on a vector of input packets.
+    ///
+    /// This method takes in a vector of packets that were received from
+    /// DPDK and wrapped up in Netbrick's Packet<> type, and parses the MAC
+    /// headers on the underlying MBufs, effectively rewrapping the packets
+    /// into a new type (Packet<MacHeader, EmptyMetadata>).
+    ///
+    /// ARP requests for the server's IP address are answered. IPv6 packets
+    /// are rewritten into IPv4 ones (refer to `ipv6_to_ipv4()`). Any other
+    /// packets with an unexpected ethertype on the parsed header, or for an
+    /// address family the server is not reachable over, are dropped by this
+    /// method.
+    ///
+    /// # Arguments
+    ///
+    /// * `packets`: A vector of packets that were received from DPDK and
+    ///              wrapped up in Netbrick's Packet<NullHeader, EmptyMetadata>
+    ///              type.
+    ///
+    /// # Return
+    ///
+    /// A vector of valid packets with their MAC headers parsed. The packets are of type
+    /// `Packet<MacHeader, EmptyMetadata>`.
+    #[allow(unused_assignments)]
+    fn parse_mac_headers(
+        &self,
+        mut packets: Vec<Packet<NullHeader, EmptyMetadata>>,
+    ) -> Vec<Packet<MacHeader, EmptyMetadata>> {
+        // This vector will hold the set of *valid* parsed packets.
+        let mut parsed_packets = Vec::with_capacity(packets.len());
+        // This vector will hold the set of invalid parsed packets.
+        let mut ignore_packets = Vec::new();
+
+        // Parse the MacHeader on each packet, and check if it is valid.
+        while let Some(packet) = packets.pop() {
+            let mut valid: bool = true;
+            let packet = packet.parse_header::<MacHeader>();
+
+            // The following block borrows the MAC header from the parsed
+            // packet, and checks if the ethertype on it matches what the
+            // server expects.
+            let mut arp: bool = false;
+            let mut ipv6: bool = false;
+            {
+                let mac_header: &MacHeader = packet.get_header();
+                valid = self.ipv4 && common::PACKET_ETYPE.eq(&mac_header.etype());
+                arp = self.ipv4 && common::ARP_ETYPE.eq(&mac_header.etype());
+                ipv6 = self.network_ipv6.is_some()
+                    && common::PACKET_IPV6_ETYPE.eq(&mac_header.etype());
+            }
+
+            match (valid, arp, ipv6) {
+                (true, _, _) => {
+                    parsed_packets.push(packet);
+                }
+
+                (false, true, _) => {
+                    if let Some(packet) = self.answer_arp(packet) {
+                        ignore_packets.push(packet);
+                    }
+                }
+
+                (false, false, true) => match self.rewrite_ipv6_request(packet) {
+                    Ok(packet) => parsed_packets.push(packet),
+                    Err(packet) => ignore_packets.push(packet),
+                },
+
+                (false, false, false) => {
+                    ignore_packets.push(packet);
+                }
+            }
+        }
+
+        // Drop any invalid packets.
+        self.free_packets(ignore_packets);
+
+        return parsed_packets;
+    }
+
+    /// This method parses the IP header on a vector of packets that have
+    /// already had their MAC headers parsed. A vector of valid packets with
+    /// their IP headers parsed is returned.
+    ///
+    /// This method drops a packet if:
+    ///     - It is not an IPv4 packet,
+    ///     - The TTL field on it is 0,
+    ///     - It's destination IP address does not match that of the server,
+    ///     - It's IP header and payload are not long enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `packets`: A vector of packets with their MAC headers parsed off
+    ///              (type Packet<MacHeader, EmptyMetadata>).
+    ///
+    /// # Return
+    ///
+    /// A vector of packets with their IP headers parsed, and wrapped up in Netbrick's
+    /// `Packet<MacHeader, EmptyMetadata>` type.
+    #[allow(unused_assignments)]
+    fn parse_ip_headers(
+        &self,
+        mut packets: Vec<Packet<MacHeader, EmptyMetadata>>,
+    ) -> Vec<Packet<IpHeader, EmptyMetadata>> {
+        // This vector will hold the set of *valid* parsed packets.
+        let mut parsed_packets = Vec::with_capacity(packets.len());
+        // This vector will hold the set of invalid parsed packets.
+        let mut ignore_packets = Vec::new();
+
+        // Parse the IpHeader on each packet, and check if it is valid.
+        while let Some(packet) = packets.pop() {
+            let mut valid: bool = true;
+            let packet = packet.parse_header::<IpHeader>();
+
+            // The following block borrows the Ip header from the parsed
+            // packet, and checks if it is valid. A packet is considered
+            // valid if:
+            //      - It is an IPv4 packet,
+            //      - It's TTL (time to live) is greater than zero,
+            //      - It is not long enough,
+            //      - It's destination Ip address matches that of the server,
+            //      - It does not carry any options,
+            //      - It's source is not in 0.0.0.0/8, which identifies clients over IPv6.
+            {
+                const MIN_LENGTH_IP: u16 = common::PACKET_IP_LEN + 2;
+                let ip_header: &IpHeader = packet.get_header();
+                valid = (ip_header.version() == 4) && (ip_header.ttl() > 0)
+                    && (ip_header.length() >= MIN_LENGTH_IP)
+                    && (ip_header.dst() == self.network_ip_addr)
+                    && (ip_header.ihl() == common::PACKET_IP_IHL)
+                    && (ip_header.src() >> 24) != 0;
+            }
+
+            match valid {
+                true => {
+                    parsed_packets.push(packet);
+                }
+
+                false => {
+                    ignore_packets.push(packet);
+                }
+            }
+        }
+
+        // Drop any invalid packets.
+        self.free_packets(ignore_packets);
+
+        return parsed_packets;
+    }
+
+    /// This function parses the UDP headers on a vector of packets that have
+    /// had their IP headers parsed. A vector of valid packets with their UDP
+    /// headers parsed is returned.
+    ///
+    /// A packet is dropped by this method if:
+    ///     - It's destination UDP port does not match that of the server,
+    ///     - It's UDP header plus payload is not long enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `packets`: A vector of packets with their IP headers parsed.
+    ///
+    /// # Return
+    ///
+    /// A vector of packets with their UDP headers parsed. These packets are wrapped in Netbrick's
+    /// `Packet<UdpHeader, EmptyMetadata>` type.
+    #[allow(unused_assignments)]
+    fn parse_udp_headers(
+        &self,
+        mut packets: Vec<Packet<IpHeader, EmptyMetadata>>,
+    ) -> Vec<Packet<UdpHeader, EmptyMetadata>> {
+        // This vector will hold the set of *valid* parsed packets.
+        let mut parsed_packets = Vec::with_capacity(packets.len());
+        // This vector will hold the set of invalid parsed packets.
+        let mut ignore_packets = Vec::new();
+
+        // Parse the UdpHeader on each packet, and check if it is valid.
+        while let Some(packet) = packets.pop() {
+            let mut valid: bool = true;
+            let packet = packet.parse_header::<UdpHeader>();
+
+            // This block borrows the UDP header from the parsed packet, and
+            // checks if it is valid. A packet is considered valid if:
+            //      - It is not long enough,
+            {
+                const MIN_LENGTH_UDP: u16 = common::PACKET_UDP_LEN + 2;
+                let udp_header: &UdpHeader = packet.get_header();
+                valid = udp_header.length() >= MIN_LENGTH_UDP;
+            }
+
+            match valid {
+                true => {
+                    parsed_packets.push(packet);
+                }
+
+                false => {
+                    ignore_packets.push(packet);
+                }
+            }
+        }
+
+        // Drop any invalid packets.
+        self.free_packets(ignore_packets);
+
+        return parsed_packets;
+    }
+
+    /// This method dispatches requests to the appropriate service. A response
+    /// packet is pre-allocated by this method and handed in along with the
+    /// request. Once the service returns, this method frees the request packet.
+    /// Requests for an unknown service or opcode are responded to with
+    /// `StatusBadOpcode`, and those that would overflow the scheduler's run-queues with
+    /// `StatusOverloaded` (refer to `RoundRobin::admit()`).
+    ///
+    /// # Arguments
+    ///
+    /// * `requests`: A vector of packets parsed upto and including their UDP
+    ///               headers that will be dispatched to the appropriate
+    ///               service.
+    /// * `group`:    If true, requests are grouped by service, opcode and tenant, and handed off
+    ///               one group at a time.
+    /// * `observe`:  Closure invoked with the opcode and payload of every request for Master
+    ///               before it is handed off.
+    fn dispatch_requests<F>(
+        &self,
+        mut requests: Vec<Packet<UdpHeader, EmptyMetadata>>,
+        group: bool,
+        mut observe: F,
+    ) where
+        F: FnMut(&wireformat::OpCode, &[u8]),
+    {
+        // This vector will hold the set of packets for requests that were rejected by Master.
+        let mut ignore_packets = Vec::new();
+
+        // The sort is stable, so requests from a tenant with the same opcode retain their
+        // relative order.
+        if group {
+            requests.sort_by_key(group_key);
+        }
+
+        // Deadlines are relative to the time the batch was received at.
+        let arrival = if self.deadlines { cycles::rdtsc() } else { 0 };
+
+        while let Some(request) = requests.pop() {
+            // Allocate a packet for the response upfront, and add in MAC, IP, and UDP headers.
+            // The response is sent back to the MAC and IP addresses the request came from, so
+            // any number of clients can talk to the server. Requests received over IPv6 are
+            // answered over IPv6, since their source identifies the client on `Ipv6Clients`.
+            let mut response = {
+                let (mac, ip) = request_headers(&request);
+                let mut response = new_packet()
+                    .expect("ERROR: Failed to allocate packet for response!")
+                    .push_header(&self.resp_mac_header)
+                    .expect("ERROR: Failed to add response MAC header");
+                response.get_mut_header().dst.copy_address(&mac.src);
+
+                let mut response = response
+                    .push_header(&self.resp_ip_header)
+                    .expect("ERROR: Failed to add response IP header");
+                response.get_mut_header().set_dst(ip.src());
+
+                response
+                    .push_header(&self.resp_udp_header)
+                    .expect("ERROR: Failed to add response UDP header")
+            };
+
+            // Set the destination port on the response UDP header.
+            response
+                .get_mut_header()
+                .set_dst_port(request.get_header().src_port());
+
+            // Requests too short to carry an RPC header are dropped before anything is read off
+            // it. Every field parsed below, and by Master, lies within the header.
+            if request.get_payload().len() < size_of::<wireformat::RpcRequestHeader>() {
+                ignore_packets.push(request);
+                ignore_packets.push(response);
+                continue;
+            }
+
+            let (service, opcode) = {
+                let payload = request.get_payload();
+                (
+                    wireformat::Service::try_from(payload[0]),
+                    wireformat::OpCode::try_from(payload[1]),
+                )
+            };
+
+            // Requests for a service or operation this server does not know of are rejected.
+            let opcode = match (service, opcode) {
+                (Ok(wireformat::Service::MasterService), Ok(opcode)) => opcode,
+
+                _ => {
+                    self.reject_unknown(request, response);
+                    continue;
+                }
+            };
+
+            // The request is for Master, get it's tenant, and call into Master.
+            let tenant = parse_rpc_tenant(&request);
+
+            observe(&opcode, request.get_payload());
+            self.scheduler
+                .counters()
+                .record_request(request.get_payload()[1], tenant);
+
+            let deadline = if self.deadlines {
+                parse_rpc_deadline(&request) as u64
+            } else {
+                0
+            };
+
+            // Requests with the trace flag set have the time they spend at the server stamped on
+            // their responses, timed from the batch's arrival if deadlines are enabled.
+            let traced = if parse_rpc_flags(&request) & wireformat::FLAG_TRACE != 0 {
+                Some(if arrival > 0 { arrival } else { cycles::rdtsc() })
+            } else {
+                None
+            };
+
+            let id = if self.trace {
+                let id = RequestId {
+                    tenant: tenant,
+                    stamp: parse_rpc_stamp(&request),
+                };
+                trace!("{} received, opcode {}", id, request.get_payload()[1]);
+                Some(id)
+            } else {
+                None
+            };
+
+            // Requests that would overflow the run-queues are turned away, letting the tenant
+            // know to back off.
+            if !self.scheduler.admit() {
+                if let Some(id) = id {
+                    trace!("{} rejected, run-queues full", id);
+                }
+                let status = wireformat::RpcStatus::StatusOverloaded;
+                self.reply_status(request, response, opcode, status);
+                continue;
+            }
+
+            match self.master.dispatch(opcode, request, response) {
+                Ok(mut task) => {
+                    if deadline > 0 {
+                        let deadline = (cycles::cycles_per_second() * deadline) / 1000000;
+                        task.set_deadline(arrival + deadline);
+                    }
+
+                    if let Some(id) = id {
+                        trace!("{} task created by master", id);
+                    }
+
+                    let task: Box<Task> = if id.is_some() || traced.is_some() {
+                        Box::new(Traced::new(task, id, traced))
+                    } else {
+                        task
+                    };
+
+                    if self.inline_cap > 0 && task.cost() == CostClass::SHORT {
+                        self.scheduler.run_inline(tenant, task, self.inline_cap);
+                    } else {
+                        self.scheduler.enqueue(tenant, task);
+                    }
+                }
+
+                Err((req, res)) => {
+                    if let Some(id) = id {
+                        trace!("{} rejected by master", id);
+                    }
+
+                    // Master returned an error. The allocated request and response packets
+                    // need to be freed up.
+                    ignore_packets.push(req);
+                    ignore_packets.push(res);
+                }
+            }
+        }
+
+        // Free the set of ignored packets.
+        self.free_packets(ignore_packets);
+    }
+}
+
+/// A task that performs network processing on a batch of received packets, and dispatches them
+/// to Master. Created by a dispatcher with a persistent receive backlog, and enqueued on the peer
+/// scheduler with the fewest waiting tasks, so that the work of parsing and dispatching a batch is
+/// moved off the dispatcher's core instead of holding up it's next receive. Tasks created for the
+/// requests in the batch are still enqueued on the dispatcher's scheduler.
+///
+/// Requests dispatched by this task are neither sampled nor counted towards request rates.
+struct BulkDispatch {
+    // Request processing state shared with the dispatcher that created this task.
+    ingress: Arc<Ingress>,
+
+    // The batch of received packets. Consumed once the task runs.
+    packets: Vec<Packet<NullHeader, EmptyMetadata>>,
+
+    // The current execution state of the task.
+    state: TaskState,
+
+    // The total time for which the task has executed on the CPU in cycles.
+    time: u64,
+}
+
+// Implementation of the Task trait for BulkDispatch.
+impl Task for BulkDispatch {
+    /// Refer to the `Task` trait for Documentation.
+    fn run(&mut self) -> (TaskState, u64) {
+        let start = cycles::rdtsc();
+        self.state = TaskState::RUNNING;
+
+        let packets = self.packets.drain(..).collect();
+        let packets = self.ingress.parse_mac_headers(packets);
+        let packets = self.ingress.parse_ip_headers(packets);
+        let packets = self.ingress.parse_udp_headers(packets);
+        let group = self.ingress.group;
+        self.ingress.dispatch_requests(packets, group, |_, _| {});
+
+        self.state = TaskState::COMPLETED;
+        let exec = cycles::rdtsc() - start;
+        self.time += exec;
+
+        (self.state.clone(), exec)
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn state(&self) -> TaskState {
+        self.state.clone()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Refer to the `Task` trait for Documentation. Runs as a system task, since the batch can
+    /// contain requests from any tenant.
+    fn priority(&self) -> TaskPriority {
+        TaskPriority::DISPATCH
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    unsafe fn tear(
+        &mut self,
+    ) -> Option<(
+        Packet<UdpHeader, EmptyMetadata>,
+        Packet<UdpHeader, EmptyMetadata>,
+    )> {
+        // Every packet is handed off to Master or freed when the task runs.
+        None
+    }
+}
+
+// Frees up a slot for another bundle once this one has run, or was dropped without running.
+impl Drop for BulkDispatch {
+    fn drop(&mut self) {
+        self.ingress.bundles.fetch_sub(1, Ordering::Relaxed);
+        while let Some(packet) = self.packets.pop() {
+            packet.free_packet();
+        }
+    }
+}
 
 /// This type represents a requests-dispatcher in Sandstorm. When added to a
 /// Netbricks scheduler, this dispatcher polls a network port for RPCs,
 /// dispatches them to a service, and sends out responses on the same network
-/// port.
+/// port. The port can be any `Transport`, ex: a NIC's queue pair or a stand-in
+/// over a kernel socket (refer to `transport::Port`).
 pub struct Dispatch<T>
 where
-    T: PacketRx + PacketTx + Display + Clone + 'static,
+    T: Transport + Display + Clone + 'static,
 {
-    /// A ref counted pointer to a master service. The master service
-    /// implements the primary interface to the database.
-    master_service: Arc<Master>,
-
-    /// A ref counted pointer to the scheduler on which to enqueue tasks,
-    /// and from which to receive response packets to be sent back to clients.
-    scheduler: Arc<RoundRobin>,
+    /// Validates and dispatches received requests. Refer to `Ingress`.
+    ingress: Arc<Ingress>,
 
     /// The network port/interface on which this dispatcher receives and
     /// transmits RPC requests and responses on.
@@ -58,28 +1801,10 @@ where
     /// The receive queue over which this dispatcher steals RPC requests from.
     sibling_port: T,
 
-    /// The IP address of the server. This is required to ensure that the
-    /// server does not process packets that were destined to a different
-    /// machine.
-    network_ip_addr: u32,
-
     /// The maximum number of packets that the dispatcher can receive from the
     /// network interface in a single burst.
     max_rx_packets: u8,
 
-    /// The UDP header that will be appended to every response packet (cached
-    /// here to avoid wasting time creating a new one for every response
-    /// packet).
-    resp_udp_header: UdpHeader,
-
-    /// The IP header that will be appended to every response packet (cached
-    /// here to avoid creating a new one for every response packet).
-    resp_ip_header: IpHeader,
-
-    /// The MAC header that will be appended to every response packet (cached
-    /// here to avoid creating a new one for every response packet).
-    resp_mac_header: MacHeader,
-
     /// The number of response packets that were sent out by the dispatcher in
     /// the last measurement interval.
     responses_sent: u64,
@@ -104,11 +1829,61 @@ where
     id: i32,
 
     cycle_counter: CycleCounter,
+
+    /// Mirrors 1-in-N received requests into a capture file for workload characterization.
+    /// Disabled unless `sample_rate` is set in the server's config.
+    sampler: Sampler,
+
+    /// Backs off steal attempts from the sibling's receive queue while it is empty.
+    steal: StealBackoff,
+
+    /// Tracks per-opcode request rates, and flags rates that deviate from their baselines.
+    /// Disabled unless `rate_alert_factor` is set in the server's config.
+    rates: RateMonitor,
+
+    /// Accounts for the bytes sent out in responses to each tenant. Disabled unless
+    /// `egress_report_secs` is set in the server's config.
+    egress: EgressMeter,
+
+    /// Detects a persistent backlog at the receive queue, during which received batches are
+    /// handed off to the scheduler as `BulkDispatch` tasks. Disabled unless `bulk_dispatch_polls`
+    /// is set in the server's config.
+    backlog: Backlog,
+
+    /// The number of batches handed off as `BulkDispatch` tasks in the last measurement interval.
+    bulk_batches: u64,
+
+    /// Decides whether received batches are grouped by opcode and tenant, and measures the cost
+    /// of dispatching requests with and without grouping when alternating between the two.
+    grouping: GroupSwitch,
+
+    /// Converts hardware timestamps on received requests into cycles. None unless the server is
+    /// configured with `timestamp_source = "nic"`.
+    clock: Option<NicClock>,
+
+    /// The total time in cycles that requests timestamped by the NIC in the last measurement
+    /// interval spent waiting in the receive queue, and the number of such requests.
+    rx_delay: (u64, u64),
+
+    /// The time in cycles spent on polls that received requests or sent out responses, and on
+    /// polls that found nothing to do, in the last measurement interval.
+    poll_cycles: (u64, u64),
+
+    /// The slot on the server's stats page that this dispatcher publishes it's core's counters
+    /// into. None unless `stats_page` is set in the server's config.
+    stats_slot: Option<StatsSlot>,
+
+    /// The number of polls left before counters are next published into `stats_slot`.
+    stats_polls: u64,
+
+    /// The number of responses this dispatcher had to split into fragments. Used to assign
+    /// identifiers to fragmented responses.
+    fragmented: u32,
 }
 
 impl<T> Dispatch<T>
 where
-    T: PacketRx + PacketTx + Display + Clone + 'static,
+    T: Transport + Display + Clone + 'static,
 {
     /// This function creates and returns a requests-dispatcher which can be
     /// added to a Netbricks scheduler.
@@ -123,6 +1898,9 @@ where
     ///               packets.
     /// * `sched`:    A reference to a scheduler on which tasks will be enqueued.
     /// * `id`:       The identifier of the dispatcher.
+    /// * `clock`:    Converts hardware timestamps taken by the NIC on `net_port` into cycles.
+    /// * `ipv6`:     The IPv6 clients of the server, if it is reachable over IPv6. Must be
+    ///               shared by every dispatcher on the server.
     ///
     /// # Return
     ///
@@ -134,6 +1912,8 @@ where
         master: Arc<Master>,
         sched: Arc<RoundRobin>,
         id: i32,
+        clock: Option<NicClock>,
+        ipv6: Option<Arc<Ipv6Clients>>,
     ) -> Dispatch<T> {
         let rx_batch_size: u8 = 32;
         let measurement_count: u64 = 100;
@@ -150,13 +1930,14 @@ where
         udp_header.set_length(udp_length);
         udp_header.set_checksum(udp_checksum);
 
-        // Create a common ip header for response packets.
-        let ip_src_addr: u32 = u32::from(
-            Ipv4Addr::from_str(&config.ip_address).expect("Failed to create server IP address."),
-        );
-        let ip_dst_addr: u32 = u32::from(
-            Ipv4Addr::from_str(&config.client_ip).expect("Failed to create client IP address."),
-        );
+        // Create a common ip header for response packets. The server can be reachable over
+        // IPv4, IPv6 or both. The destination is filled in from each request.
+        let ipv4 = config.parse_ipv4();
+        if ipv4.is_none() && ipv6.is_none() {
+            panic!("Either ip_address or ipv6_address must be set in the server's config.");
+        }
+
+        let ip_src_addr: u32 = ipv4.unwrap_or(0);
         let ip_ttl: u8 = common::PACKET_IP_TTL;
         let ip_version: u8 = common::PACKET_IP_VER;
         let ip_ihl: u8 = common::PACKET_IP_IHL;
@@ -164,33 +1945,43 @@ where
 
         let mut ip_header: IpHeader = IpHeader::new();
         ip_header.set_src(ip_src_addr);
-        ip_header.set_dst(ip_dst_addr);
         ip_header.set_ttl(ip_ttl);
         ip_header.set_version(ip_version);
         ip_header.set_ihl(ip_ihl);
         ip_header.set_length(ip_length);
         ip_header.set_protocol(0x11);
 
-        // Create a common mac header for response packets.
+        // Create a common mac header for response packets. The destination is filled in from
+        // each request.
         let mac_src_addr: MacAddress = config.parse_mac();
-        let mac_dst_addr: MacAddress = config.parse_client_mac();
         let mac_etype: u16 = common::PACKET_ETYPE;
 
         let mut mac_header: MacHeader = MacHeader::new();
         mac_header.src = mac_src_addr;
-        mac_header.dst = mac_dst_addr;
         mac_header.set_etype(mac_etype);
 
         Dispatch {
-            master_service: master,
-            scheduler: sched,
+            ingress: Arc::new(Ingress {
+                master: master,
+                scheduler: sched,
+                network_ip_addr: ip_src_addr,
+                ipv4: ipv4.is_some(),
+                network_ipv6: ipv6,
+                checksums: Checksums::parse(&config.response_checksums),
+                resp_udp_header: udp_header,
+                resp_ip_header: ip_header,
+                resp_mac_header: mac_header,
+                trace: config.trace_requests,
+                group: config.group_requests,
+                deadlines: config.edf_scheduler(),
+                inline_cap: (cycles::cycles_per_second() * config.inline_short_ns) / 1000000000,
+                unknown: Mutex::new(HashMap::new()),
+                arp: Mutex::new(Vec::new()),
+                bundles: AtomicUsize::new(0),
+            }),
             network_port: net_port.clone(),
             sibling_port: sib_port.clone(),
-            network_ip_addr: ip_src_addr,
             max_rx_packets: rx_batch_size,
-            resp_udp_header: udp_header,
-            resp_ip_header: ip_header,
-            resp_mac_header: mac_header,
             responses_sent: 0,
             measurement_start: cycles::rdtsc(),
             measurement_stop: 0,
@@ -199,6 +1990,68 @@ where
             priority: TaskPriority::DISPATCH,
             id: id,
             cycle_counter: CycleCounter::new(measurement_count),
+            sampler: Sampler::new(
+                config.sample_rate,
+                config.sample_headers_only,
+                &format!("{}.{}", config.sample_file, id),
+            ),
+            steal: StealBackoff::new(),
+            rates: RateMonitor::new(
+                config.rate_alert_factor,
+                config.rate_alert_secs.max(1) * cycles::cycles_per_second(),
+            ),
+            egress: EgressMeter::new(config.egress_report_secs * cycles::cycles_per_second()),
+            backlog: Backlog::new(config.bulk_dispatch_polls),
+            bulk_batches: 0,
+            grouping: GroupSwitch::new(config.group_requests, config.group_requests_ab),
+            clock: clock,
+            rx_delay: (0, 0),
+            poll_cycles: (0, 0),
+            stats_slot: None,
+            stats_polls: 0,
+            fragmented: 0,
+        }
+    }
+
+    /// Makes the dispatcher periodically publish it's core's queue depths and steal counters
+    /// into a slot on the server's stats page.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot`: The slot on the page reserved for the core the dispatcher runs on.
+    pub fn set_stats_slot(&mut self, slot: StatsSlot) {
+        self.stats_slot = Some(slot);
+        self.stats_polls = 0;
+    }
+
+    // Publishes the core's counters into the stats page once every `STATS_PAGE_POLLS` polls.
+    #[inline]
+    fn publish_stats(&mut self) {
+        if self.stats_slot.is_none() {
+            return;
+        }
+
+        if self.stats_polls > 0 {
+            self.stats_polls -= 1;
+            return;
+        }
+        self.stats_polls = STATS_PAGE_POLLS;
+
+        let sched = &self.ingress.scheduler;
+        let (attempts, successes) = self.steal.totals();
+        let stats = CoreStats {
+            core: sched.core() as u64,
+            heartbeat: cycles::rdtsc(),
+            pending: sched.pending() as u64,
+            parked: sched.parked() as u64,
+            completed: sched.completed(),
+            stolen: sched.stolen(),
+            steal_attempts: attempts,
+            steal_successes: successes,
+        };
+
+        if let Some(ref slot) = self.stats_slot {
+            slot.publish(&stats);
         }
     }
 
@@ -221,7 +2074,7 @@ where
             mbuf_vector.set_len(self.max_rx_packets as usize);
 
             // Try to receive packets from the network port.
-            match self.network_port.recv(&mut mbuf_vector[..]) {
+            match self.network_port.recv_burst(&mut mbuf_vector[..]) {
                 // The receive call returned successfully.
                 Ok(num_received) => {
                     if num_received == 0 {
@@ -268,6 +2121,12 @@ where
     /// A vector of packets wrapped up in Netbrick's Packet<NullHeader, EmptyMetadata> type if
     /// there was anything received at the network port.
     fn try_steal_packets(&self) -> Option<Vec<Packet<NullHeader, EmptyMetadata>>> {
+        // Stealing off an empty queue still bounces it's cache lines. Skip it if the sibling's
+        // transport can tell that there is nothing to steal.
+        if self.sibling_port.queue_depth() == Some(0) {
+            return None;
+        }
+
         // Allocate a vector of mutable MBuf pointers into which packets will
         // be received.
         let mut mbuf_vector = Vec::with_capacity(self.max_rx_packets as usize);
@@ -279,7 +2138,7 @@ where
             mbuf_vector.set_len(self.max_rx_packets as usize);
 
             // Try to receive packets from the sibling.
-            match self.sibling_port.recv(&mut mbuf_vector[..]) {
+            match self.sibling_port.recv_burst(&mut mbuf_vector[..]) {
                 // The receive call returned successfully.
                 Ok(num_received) => {
                     if num_received == 0 {
@@ -317,346 +2176,353 @@ where
         }
     }
 
-    /// This method takes as input a vector of packets and tries to send them
-    /// out a network interface.
-    ///
-    /// # Arguments
-    ///
-    /// * `packets`: A vector of packets to be sent out the network, parsed upto their UDP headers.
-    fn try_send_packets(&mut self, mut packets: Vec<Packet<IpHeader, EmptyMetadata>>) {
-        // This unsafe block is required to extract the underlying Mbuf's from
-        // the passed in batch of packets, and send them out the network port.
-        unsafe {
-            let mut mbufs = vec![];
-            let num_packets = packets.len();
-
-            // Extract Mbuf's from the batch of packets.
-            while let Some(packet) = packets.pop() {
-                mbufs.push(packet.get_mbuf());
-            }
-
-            // Send out the above MBuf's.
-            match self.network_port.send(&mut mbufs) {
-                Ok(sent) => {
-                    if sent < num_packets as u32 {
-                        warn!("Was able to send only {} of {} packets.", sent, num_packets);
-                    }
-
-                    self.responses_sent += mbufs.len() as u64;
-                }
-
-                Err(ref err) => {
-                    error!("Error on packet send: {}", err);
-                }
-            }
-        }
-
-        // For every million packets sent out by the dispatcher, print out the
-        // amount of time in nano seconds it took to do so.
-        let every = 1000000;
-        if self.responses_sent >= every {
-            self.measurement_stop = cycles::rdtsc();
-
-            debug!(
-                "Dispatcher {}: {:.0} K/packets/s",
-                self.id,
-                (self.responses_sent as f64 / 1e3)
-                    / ((self.measurement_stop - self.measurement_start) as f64
-                        / (cycles::cycles_per_second() as f64))
-            );
-
-            self.measurement_start = self.measurement_stop;
-            self.responses_sent = 0;
-        }
-    }
-
-    /// This function frees a set of packets that were received from DPDK.
-    ///
-    /// # Arguments
-    ///
-    /// * `packets`: A vector of packets wrapped in Netbrick's Packet<> type.
-    #[inline]
-    fn free_packets<S: EndOffset>(&self, mut packets: Vec<Packet<S, EmptyMetadata>>) {
-        while let Some(packet) = packets.pop() {
-            packet.free_packet();
-        }
-    }
-
-    /// This method parses the MAC headers on a vector of input packets.
-    ///
-    /// This method takes in a vector of packets that were received from
-    /// DPDK and wrapped up in Netbrick's Packet<> type, and parses the MAC
-    /// headers on the underlying MBufs, effectively rewrapping the packets
-    /// into a new type (Packet<MacHeader, EmptyMetadata>).
-    ///
-    /// Any packets with an unexpected ethertype on the parsed header are
-    /// dropped by this method.
-    ///
-    /// # Arguments
-    ///
-    /// * `packets`: A vector of packets that were received from DPDK and
-    ///              wrapped up in Netbrick's Packet<NullHeader, EmptyMetadata>
-    ///              type.
-    ///
-    /// # Return
-    ///
-    /// A vector of valid packets with their MAC headers parsed. The packets are of type
-    /// `Packet<MacHeader, EmptyMetadata>`.
-    #[allow(unused_assignments)]
-    fn parse_mac_headers(
-        &self,
-        mut packets: Vec<Packet<NullHeader, EmptyMetadata>>,
-    ) -> Vec<Packet<MacHeader, EmptyMetadata>> {
-        // This vector will hold the set of *valid* parsed packets.
-        let mut parsed_packets = Vec::with_capacity(self.max_rx_packets as usize);
-        // This vector will hold the set of invalid parsed packets.
-        let mut ignore_packets = Vec::with_capacity(self.max_rx_packets as usize);
-
-        // Parse the MacHeader on each packet, and check if it is valid.
-        while let Some(packet) = packets.pop() {
-            let mut valid: bool = true;
-            let packet = packet.parse_header::<MacHeader>();
+    // Splits responses too large to be sent out in a single packet into fragments that the
+    // client reassembles. Refer to `rpc::fragment_response()`.
+    fn fragment(
+        &mut self,
+        packets: Vec<Packet<IpHeader, EmptyMetadata>>,
+    ) -> Vec<Packet<IpHeader, EmptyMetadata>> {
+        // Responses sent out over IPv6 have less room for their payload.
+        let fits = |packet: &Packet<IpHeader, EmptyMetadata>| {
+            let ip_len = if to_ipv6(packet) {
+                IPV6_HEADER_LEN
+            } else {
+                size_of::<IpHeader>()
+            };
+            packet.get_payload().len() + ip_len <= common::PACKET_MTU as usize
+        };
 
-            // The following block borrows the MAC header from the parsed
-            // packet, and checks if the ethertype on it matches what the
-            // server expects.
-            {
-                let mac_header: &MacHeader = packet.get_header();
-                valid = common::PACKET_ETYPE.eq(&mac_header.etype());
+        if packets.iter().all(|packet| fits(packet)) {
+            return packets;
+        }
+
+        let mut fragments = Vec::with_capacity(packets.len());
+        for packet in packets.into_iter() {
+            if fits(&packet) {
+                fragments.push(packet);
+                continue;
             }
 
-            match valid {
-                true => {
-                    parsed_packets.push(packet);
-                }
+            // Fragment ids are unique across dispatchers, so that the client can tell apart
+            // responses from different cores.
+            let id = ((self.id as u32) << 24) | (self.fragmented & 0xffffff);
+            self.fragmented = self.fragmented.wrapping_add(1);
 
-                false => {
-                    ignore_packets.push(packet);
-                }
+            let ipv6 = to_ipv6(&packet);
+            let parts = rpc::fragment_response(packet, id, ipv6);
+            if parts.is_empty() {
+                warn!("Dispatcher {}: Dropping response that could not be fragmented", self.id);
             }
+            fragments.extend(parts);
         }
 
-        // Drop any invalid packets.
-        self.free_packets(ignore_packets);
-
-        return parsed_packets;
+        fragments
     }
 
-    /// This method parses the IP header on a vector of packets that have
-    /// already had their MAC headers parsed. A vector of valid packets with
-    /// their IP headers parsed is returned.
-    ///
-    /// This method drops a packet if:
-    ///     - It is not an IPv4 packet,
-    ///     - The TTL field on it is 0,
-    ///     - It's destination IP address does not match that of the server,
-    ///     - It's IP header and payload are not long enough.
+    /// This method takes as input a vector of packets and tries to send them
+    /// out a network interface.
     ///
     /// # Arguments
     ///
-    /// * `packets`: A vector of packets with their MAC headers parsed off
-    ///              (type Packet<MacHeader, EmptyMetadata>).
-    ///
-    /// # Return
-    ///
-    /// A vector of packets with their IP headers parsed, and wrapped up in Netbrick's
-    /// `Packet<MacHeader, EmptyMetadata>` type.
-    #[allow(unused_assignments)]
-    fn parse_ip_headers(
-        &self,
-        mut packets: Vec<Packet<MacHeader, EmptyMetadata>>,
-    ) -> Vec<Packet<IpHeader, EmptyMetadata>> {
-        // This vector will hold the set of *valid* parsed packets.
-        let mut parsed_packets = Vec::with_capacity(self.max_rx_packets as usize);
-        // This vector will hold the set of invalid parsed packets.
-        let mut ignore_packets = Vec::with_capacity(self.max_rx_packets as usize);
+    /// * `packets`: A vector of packets to be sent out the network, parsed upto their UDP headers.
+    fn try_send_packets(&mut self, packets: Vec<Packet<IpHeader, EmptyMetadata>>) {
+        // Responses larger than the MTU would be dropped on the way to the client.
+        let mut packets = self.fragment(packets);
 
-        // Parse the IpHeader on each packet, and check if it is valid.
-        while let Some(packet) = packets.pop() {
-            let mut valid: bool = true;
-            let packet = packet.parse_header::<IpHeader>();
+        // This unsafe block is required to extract the underlying Mbuf's from
+        // the passed in batch of packets, and send them out the network port.
+        unsafe {
+            let mut mbufs = vec![];
+            let mut sizes = vec![];
 
-            // The following block borrows the Ip header from the parsed
-            // packet, and checks if it is valid. A packet is considered
-            // valid if:
-            //      - It is an IPv4 packet,
-            //      - It's TTL (time to live) is greater than zero,
-            //      - It is not long enough,
-            //      - It's destination Ip address matches that of the server.
-            {
-                const MIN_LENGTH_IP: u16 = common::PACKET_IP_LEN + 2;
-                let ip_header: &IpHeader = packet.get_header();
-                valid = (ip_header.version() == 4) && (ip_header.ttl() > 0)
-                    && (ip_header.length() >= MIN_LENGTH_IP)
-                    && (ip_header.dst() == self.network_ip_addr);
+            // Extract Mbuf's from the batch of packets, rewriting responses to be sent out over
+            // IPv6 on the way. If responses are being accounted for, note down the tenant and
+            // size of each packet too.
+            while let Some(packet) = packets.pop() {
+                let size = if self.egress.enabled() {
+                    let ip_len = if to_ipv6(&packet) {
+                        IPV6_HEADER_LEN
+                    } else {
+                        size_of::<IpHeader>()
+                    };
+                    let bytes = size_of::<MacHeader>() + ip_len + packet.get_payload().len();
+                    Some((parse_response_tenant(&packet), bytes as u64))
+                } else {
+                    None
+                };
+
+                let ipv6 = &self.ingress.network_ipv6;
+                match response_mbuf(packet, ipv6, self.ingress.checksums) {
+                    Some(mbuf) => {
+                        mbufs.push(mbuf);
+                        sizes.extend(size);
+                    }
+
+                    None => {
+                        warn!("Dispatcher {}: Dropping response with no room for IPv6", self.id);
+                    }
+                }
             }
+            let num_packets = mbufs.len();
 
-            match valid {
-                true => {
-                    parsed_packets.push(packet);
+            // Send out the above MBuf's.
+            match self.network_port.send_burst(&mut mbufs) {
+                Ok(sent) => {
+                    if sent < num_packets as u32 {
+                        warn!("Was able to send only {} of {} packets.", sent, num_packets);
+                    }
+
+                    self.responses_sent += mbufs.len() as u64;
+                    self.ingress
+                        .scheduler
+                        .counters()
+                        .record_packets(0, sent as usize);
+
+                    // Only the packets that made it out are accounted for. These are the ones at
+                    // the front of the batch.
+                    for &(tenant, bytes) in sizes.iter().take(sent as usize) {
+                        self.egress.record(tenant, bytes);
+                    }
                 }
 
-                false => {
-                    ignore_packets.push(packet);
+                Err(ref err) => {
+                    error!("Error on packet send: {}", err);
                 }
             }
         }
 
-        // Drop any invalid packets.
-        self.free_packets(ignore_packets);
+        // For every million packets sent out by the dispatcher, print out the
+        // amount of time in nano seconds it took to do so.
+        let every = 1000000;
+        if self.responses_sent >= every {
+            self.measurement_stop = cycles::rdtsc();
+            let (attempts, successes) = self.steal.take_stats();
+            let (delay, timed) = self.rx_delay;
+            let (ungrouped, grouped) = self.grouping.take_stats();
+            let (busy, idle) = self.poll_cycles;
+            let (unknown, sources) = self.ingress.unknown_opcodes();
+            let (express, queued) = self.ingress.scheduler.take_express_stats().unwrap_or((0, 0));
+            let (rejected, evicted) = self.ingress.scheduler.overflows();
 
-        return parsed_packets;
+            debug!(
+                "Dispatcher {}: {:.0} K/packets/s, {} of {} steals succeeded, rates anomalous: {}, \
+                 {} bulk batches, {:.0} ns avg rx queueing, {:.0}/{:.0} cycles/req dispatch \
+                 ungrouped/grouped, {:.1}% busy, {} unknown opcodes from {} sources, \
+                 {:.0}/{:.0} ns p99 express/queued response delay, {} rejected/{} dropped \
+                 on full run-queues",
+                self.id,
+                (self.responses_sent as f64 / 1e3)
+                    / ((self.measurement_stop - self.measurement_start) as f64
+                        / (cycles::cycles_per_second() as f64)),
+                successes,
+                attempts,
+                self.rates.anomalous,
+                self.bulk_batches,
+                cycles::to_seconds(delay) * 1e9 / timed.max(1) as f64,
+                ungrouped,
+                grouped,
+                utilization(busy, idle),
+                unknown,
+                sources,
+                cycles::to_seconds(express) * 1e9,
+                cycles::to_seconds(queued) * 1e9,
+                rejected,
+                evicted
+            );
+
+            self.measurement_start = self.measurement_stop;
+            self.responses_sent = 0;
+            self.bulk_batches = 0;
+            self.rx_delay = (0, 0);
+            self.poll_cycles = (0, 0);
+        }
     }
 
-    /// This function parses the UDP headers on a vector of packets that have
-    /// had their IP headers parsed. A vector of valid packets with their UDP
-    /// headers parsed is returned.
-    ///
-    /// A packet is dropped by this method if:
-    ///     - It's destination UDP port does not match that of the server,
-    ///     - It's UDP header plus payload is not long enough.
+    /// This method sends out replies to ARP requests over the network interface. Replies that
+    /// could not be sent out are freed; the requester will ask again.
     ///
     /// # Arguments
     ///
-    /// * `packets`: A vector of packets with their IP headers parsed.
-    ///
-    /// # Return
-    ///
-    /// A vector of packets with their UDP headers parsed. These packets are wrapped in Netbrick's
-    /// `Packet<UdpHeader, EmptyMetadata>` type.
-    #[allow(unused_assignments)]
-    fn parse_udp_headers(
-        &self,
-        mut packets: Vec<Packet<IpHeader, EmptyMetadata>>,
-    ) -> Vec<Packet<UdpHeader, EmptyMetadata>> {
-        // This vector will hold the set of *valid* parsed packets.
-        let mut parsed_packets = Vec::with_capacity(self.max_rx_packets as usize);
-        // This vector will hold the set of invalid parsed packets.
-        let mut ignore_packets = Vec::with_capacity(self.max_rx_packets as usize);
-
-        // Parse the UdpHeader on each packet, and check if it is valid.
-        while let Some(packet) = packets.pop() {
-            let mut valid: bool = true;
-            let packet = packet.parse_header::<UdpHeader>();
-
-            // This block borrows the UDP header from the parsed packet, and
-            // checks if it is valid. A packet is considered valid if:
-            //      - It is not long enough,
-            {
-                const MIN_LENGTH_UDP: u16 = common::PACKET_UDP_LEN + 2;
-                let udp_header: &UdpHeader = packet.get_header();
-                valid = udp_header.length() >= MIN_LENGTH_UDP;
+    /// * `replies`: A vector of ARP replies, parsed upto their MAC headers.
+    fn try_send_arp_replies(&mut self, mut replies: Vec<Packet<MacHeader, EmptyMetadata>>) {
+        unsafe {
+            let mut mbufs = vec![];
+            while let Some(reply) = replies.pop() {
+                mbufs.push(reply.get_mbuf());
             }
 
-            match valid {
-                true => {
-                    parsed_packets.push(packet);
+            match self.network_port.send_burst(&mut mbufs) {
+                Ok(sent) => {
+                    for mbuf in mbufs.into_iter().skip(sent as usize) {
+                        packet_from_mbuf_no_increment::<NullHeader>(mbuf, 0).free_packet();
+                    }
                 }
 
-                false => {
-                    ignore_packets.push(packet);
+                Err(ref err) => {
+                    error!("Error on ARP reply send: {}", err);
                 }
             }
         }
-
-        // Drop any invalid packets.
-        self.free_packets(ignore_packets);
-
-        return parsed_packets;
     }
 
-    /// This method dispatches requests to the appropriate service. A response
-    /// packet is pre-allocated by this method and handed in along with the
-    /// request. Once the service returns, this method frees the request packet.
+    /// This method dispatches requests to the appropriate service, recording their request rates
+    /// and sampling them along the way if required.
     ///
     /// # Arguments
     ///
-    /// * `requests`: A vector of packets parsed upto and including their UDP
-    ///               headers that will be dispatched to the appropriate
-    ///               service.
-    fn dispatch_requests(&self, mut requests: Vec<Packet<UdpHeader, EmptyMetadata>>) {
-        // This vector will hold the set of packets that were for either an invalid service or
-        // operation.
-        let mut ignore_packets = Vec::with_capacity(self.max_rx_packets as usize);
+    /// * `requests`: A vector of packets parsed upto and including their UDP headers.
+    fn dispatch_requests(&mut self, requests: Vec<Packet<UdpHeader, EmptyMetadata>>) {
+        let rates = &mut self.rates;
+        let sampler = &mut self.sampler;
 
-        while let Some(request) = requests.pop() {
-            // Allocate a packet for the response upfront, and add in MAC, IP, and UDP headers.
-            let mut response = new_packet()
-                .expect("ERROR: Failed to allocate packet for response!")
-                .push_header(&self.resp_mac_header)
-                .expect("ERROR: Failed to add response MAC header")
-                .push_header(&self.resp_ip_header)
-                .expect("ERROR: Failed to add response IP header")
-                .push_header(&self.resp_udp_header)
-                .expect("ERROR: Failed to add response UDP header");
+        let group = self.grouping.grouped();
+        let count = requests.len() as u64;
+        let start = cycles::rdtsc();
 
-            // Set the destination port on the response UDP header.
-            response
-                .get_mut_header()
-                .set_dst_port(request.get_header().src_port());
+        self.ingress.dispatch_requests(requests, group, |opcode, payload| {
+            if rates.enabled() {
+                rates.record(opcode);
+            }
 
-            if parse_rpc_service(&request) == wireformat::Service::MasterService {
-                // The request is for Master, get it's opcode, and call into Master.
-                let opcode = parse_rpc_opcode(&request);
-                match self.master_service.dispatch(opcode, request, response) {
-                    Ok(task) => {
-                        self.scheduler.enqueue(task);
-                    }
+            // If required, mirror the request into the capture file before it is handed off.
+            if sampler.enabled() {
+                sampler.sample(opcode, payload, cycles::rdtsc());
+            }
+        });
 
-                    Err((req, res)) => {
-                        // Master returned an error. The allocated request and response packets
-                        // need to be freed up.
-                        ignore_packets.push(req);
-                        ignore_packets.push(res);
+        if count > 0 {
+            self.grouping.record(group, count, cycles::rdtsc() - start);
+        }
+    }
+
+    /// Performs network processing on a batch of received packets, and dispatches them to the
+    /// appropriate service.
+    ///
+    /// # Arguments
+    ///
+    /// * `packets`: A batch of packets received from the network.
+    fn process_packets(&mut self, packets: Vec<Packet<NullHeader, EmptyMetadata>>) {
+        // If the NIC timestamped these packets, measure how long they waited to be polled.
+        if let Some(ref mut clock) = self.clock {
+            let now = cycles::rdtsc();
+            for packet in packets.iter() {
+                if let Some(stamp) = packet.rx_timestamp() {
+                    let arrival = clock.to_cycles(stamp);
+                    if arrival < now {
+                        self.rx_delay.0 += now - arrival;
                     }
+                    self.rx_delay.1 += 1;
                 }
-            } else {
-                // The request is not for Master. The allocated request and response packets need
-                // to be freed up.
-                ignore_packets.push(request);
-                ignore_packets.push(response);
             }
         }
 
-        // Free the set of ignored packets.
-        self.free_packets(ignore_packets);
+        let packets = self.ingress.parse_mac_headers(packets);
+        let packets = self.ingress.parse_ip_headers(packets);
+        let packets = self.ingress.parse_udp_headers(packets);
+
+        self.dispatch_requests(packets);
     }
 
     /// This method polls the dispatchers network port for any received packets,
     /// dispatches them to the appropriate service, and sends out responses over
     /// the network port.
+    ///
+    /// # Return
+    ///
+    /// True if the poll received requests or sent out responses, false if it was idle.
     #[inline]
-    fn poll(&mut self) {
+    fn poll(&mut self) -> bool {
         // First, send any pending response packets out.
-        let responses = self.scheduler.responses();
-        if responses.len() > 0 {
+        let responses = self.ingress.scheduler.responses();
+        let sent = responses.len() > 0;
+        if sent {
             self.try_send_packets(responses);
         }
 
+        // Replies to ARP requests are rare, and are sent out on their own.
+        let replies = self.ingress.arp_replies();
+        if replies.len() > 0 {
+            self.try_send_arp_replies(replies);
+        }
+
+        // Check whether request rates over the last interval were out of the ordinary.
+        if self.rates.enabled() {
+            for (op, rate, base) in self.rates.tick(cycles::rdtsc()) {
+                warn!(
+                    "Dispatcher {}: Rate of opcode {} is {:.0} req/s, baseline is {:.0} req/s",
+                    self.id, op, rate, base
+                );
+            }
+        }
+
+        // Report the bandwidth each tenant's responses used over the last interval.
+        if self.egress.enabled() {
+            for (tenant, packets, bytes, gbps, total) in self.egress.tick(cycles::rdtsc()) {
+                info!(
+                    "Dispatcher {}: Tenant {} was sent {} packets, {} bytes ({:.3} Gbps), \
+                     {} bytes total",
+                    self.id, tenant, packets, bytes, gbps, total
+                );
+            }
+        }
+
         self.cycle_counter.start();
 
         // Next, try to receive packets from the network.
         if let Some(packets) = self.try_receive_packets() {
             self.cycle_counter.stop();
+            self.ingress
+                .scheduler
+                .counters()
+                .record_packets(packets.len(), 0);
+
+            // If the dispatcher has been falling behind the network, hand the batch off to a
+            // peer scheduler so that it can get back to receiving packets right away. Otherwise,
+            // or if enough batches are already waiting on peers, process and dispatch the batch
+            // here.
+            let peer = if self.backlog.record(packets.len() == self.max_rx_packets as usize) {
+                self.ingress.scheduler.idlest_peer()
+            } else {
+                None
+            };
+
+            match peer {
+                Some(ref peer) if self.ingress.bundles.load(Ordering::Relaxed) < MAX_BUNDLES => {
+                    self.ingress.bundles.fetch_add(1, Ordering::Relaxed);
+                    let bulk = BulkDispatch {
+                        ingress: Arc::clone(&self.ingress),
+                        packets: packets,
+                        state: TaskState::INITIALIZED,
+                        time: 0,
+                    };
+                    peer.enqueue(0, Box::new(bulk));
+                    self.bulk_batches += 1;
+                }
 
-            // Perform basic network processing on the received packets.
-            let mut packets = self.parse_mac_headers(packets);
-            let mut packets = self.parse_ip_headers(packets);
-            let mut packets = self.parse_udp_headers(packets);
+                _ => self.process_packets(packets),
+            }
 
-            // Dispatch these packets to the appropriate service.
-            self.dispatch_requests(packets);
+            true
         } else {
-            // There were no packets at the receive queue. Try to steal some from the sibling.
-            if let Some(stolen) = self.try_steal_packets() {
-                // Perform basic network processing on the stolen packets.
-                let mut stolen = self.parse_mac_headers(stolen);
-                let mut stolen = self.parse_ip_headers(stolen);
-                let mut stolen = self.parse_udp_headers(stolen);
+            // There were no packets at the receive queue. Try to steal some from the sibling,
+            // unless recent attempts have been failing.
+            if !self.steal.should_attempt() {
+                return sent;
+            }
+
+            let stolen = self.try_steal_packets();
+            self.steal.record(stolen.is_some());
 
-                // Dispatch these packets to the appropriate service.
-                self.dispatch_requests(stolen);
+            if let Some(stolen) = stolen {
+                self.ingress
+                    .scheduler
+                    .counters()
+                    .record_packets(stolen.len(), 0);
+                self.process_packets(stolen);
+                return true;
             }
+
+            sent
         }
     }
 }
@@ -665,7 +2531,7 @@ where
 // database.
 impl<T> Task for Dispatch<T>
 where
-    T: PacketRx + PacketTx + Display + Clone + 'static,
+    T: Transport + Display + Clone + 'static,
 {
     /// Refer to the `Task` trait for Documentation.
     fn run(&mut self) -> (TaskState, u64) {
@@ -673,7 +2539,7 @@ where
 
         // Run the dispatch task, polling for received packets and sending out pending responses.
         self.state = TaskState::RUNNING;
-        self.poll();
+        let busy = self.poll();
         self.state = TaskState::YIELDED;
 
         // Update the time the task spent executing and return.
@@ -681,6 +2547,16 @@ where
 
         self.time += exec;
 
+        // Account the poll as busy or idle, so that the dispatcher's utilization can be compared
+        // against that of the scheduler it runs on.
+        if busy {
+            self.poll_cycles.0 += exec;
+        } else {
+            self.poll_cycles.1 += exec;
+        }
+        self.ingress.scheduler.record_dispatch(busy, exec);
+        self.publish_stats();
+
         return (self.state.clone(), exec);
     }
 
@@ -710,3 +2586,399 @@ where
         None
     }
 }
+
+// This module contains simple unit tests for StealBackoff, RateMonitor, EgressMeter, Backlog
+// and ARP replies.
+#[cfg(test)]
+mod tests {
+    use super::{
+        ipv4_checksums, ipv4_to_ipv6, ipv6_to_ipv4, reply_arp, udp6_checksum, Backlog, Checksums,
+        EgressMeter, GroupSwitch, Ipv6Clients, RateMonitor, StealBackoff, IPV6_GROWTH,
+        MAX_STEAL_BACKOFF, RATE_WARMUP_INTERVALS,
+    };
+    use super::Dispatch;
+    use config::ServerConfig;
+    use cycles;
+    use master::Master;
+    use sched::RoundRobin;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use transport::MockTransport;
+    use wireformat::OpCode;
+
+    // Returns a dispatcher that receives off `net`, and steals off `sib`.
+    fn mock_dispatch(net: &MockTransport, sib: &MockTransport) -> Dispatch<MockTransport> {
+        let mut config = ServerConfig::default();
+        config.ip_address = String::from("10.0.0.1");
+        config.mac_address = String::from("02:00:00:00:00:01");
+
+        Dispatch::new(
+            &config,
+            net.clone(),
+            sib.clone(),
+            Arc::new(Master::new()),
+            Arc::new(RoundRobin::new(0, 0, HashMap::new())),
+            0,
+            None,
+            None,
+        )
+    }
+
+    // Returns the number of polls skipped before the next attempt is allowed.
+    fn skipped(steal: &mut StealBackoff) -> u64 {
+        let mut n = 0;
+        while !steal.should_attempt() {
+            n += 1;
+        }
+        n
+    }
+
+    // This test verifies that consecutive failures double the backoff upto a maximum.
+    #[test]
+    fn test_backoff_grows() {
+        let mut steal = StealBackoff::new();
+
+        let mut expected = 1;
+        assert!(steal.should_attempt());
+        for _ in 0..16 {
+            steal.record(false);
+            assert_eq!(expected, skipped(&mut steal));
+            expected = (expected * 2).min(MAX_STEAL_BACKOFF);
+        }
+    }
+
+    // This test verifies that a successful steal resets the backoff, and that stats are counted.
+    #[test]
+    fn test_backoff_resets() {
+        let mut steal = StealBackoff::new();
+
+        for _ in 0..4 {
+            steal.record(false);
+            skipped(&mut steal);
+        }
+
+        steal.record(true);
+        assert!(steal.should_attempt());
+        assert_eq!((5, 1), steal.take_stats());
+        assert_eq!((0, 0), steal.take_stats());
+        assert_eq!((5, 1), steal.totals());
+    }
+
+    // This test verifies that a rate far above it's baseline raises an alert only once the
+    // baselines have warmed up, and that steady rates do not.
+    #[test]
+    fn test_rate_alerts() {
+        let interval = cycles::cycles_per_second();
+        let mut rates = RateMonitor::new(4.0, interval);
+        let mut now = 1;
+        rates.tick(now);
+
+        // Steady state of 1000 gets per second.
+        for _ in 0..(RATE_WARMUP_INTERVALS + 2) {
+            for _ in 0..1000 {
+                rates.record(&OpCode::SandstormGetRpc);
+            }
+            now += interval;
+            assert!(rates.tick(now).is_empty());
+            assert!(!rates.anomalous);
+        }
+
+        // A sudden burst of gets is flagged.
+        for _ in 0..10000 {
+            rates.record(&OpCode::SandstormGetRpc);
+        }
+        now += interval;
+        let alerts = rates.tick(now);
+        assert_eq!(1, alerts.len());
+        assert_eq!(1, alerts[0].0);
+        assert!(rates.anomalous);
+
+        // So is a sudden stop.
+        now += interval;
+        assert_eq!(1, rates.tick(now).len());
+    }
+
+    // This test verifies that rates are not updated before an interval has run it's course.
+    #[test]
+    fn test_rate_interval() {
+        let mut rates = RateMonitor::new(2.0, 1000);
+        rates.tick(1);
+
+        rates.record(&OpCode::SandstormPutRpc);
+        assert!(rates.tick(500).is_empty());
+        assert_eq!(1, rates.counts[2]);
+        assert_eq!(0, rates.intervals);
+
+        rates.tick(1001);
+        assert_eq!(0, rates.counts[2]);
+        assert_eq!(1, rates.intervals);
+    }
+
+    // This test verifies that bytes sent out to each tenant are reported once an interval has
+    // run it's course, and that totals carry over across intervals.
+    #[test]
+    fn test_egress_report() {
+        let interval = cycles::cycles_per_second();
+        let mut egress = EgressMeter::new(interval);
+        assert!(egress.enabled());
+        assert!(!EgressMeter::new(0).enabled());
+
+        let mut now = 1;
+        egress.tick(now);
+        egress.record(2, 1000);
+        egress.record(1, 500);
+        egress.record(2, 250);
+        assert!(egress.tick(now + interval / 2).is_empty());
+
+        now += interval;
+        let report = egress.tick(now);
+        assert_eq!(2, report.len());
+        assert_eq!((1, 1, 500, 500), (report[0].0, report[0].1, report[0].2, report[0].4));
+        assert_eq!((2, 2, 1250, 1250), (report[1].0, report[1].1, report[1].2, report[1].4));
+        assert!((report[1].3 - 1250.0 * 8.0 / 1e9).abs() < 1e-9);
+
+        // Tenants that were not sent anything are left out, but keep their totals.
+        egress.record(2, 750);
+        now += interval;
+        let report = egress.tick(now);
+        assert_eq!(1, report.len());
+        assert_eq!((2, 1, 750, 2000), (report[0].0, report[0].1, report[0].2, report[0].4));
+        assert_eq!(Some(&500), egress.totals.get(&1));
+    }
+
+    // This test verifies that an ARP request for the server's address is turned into a reply
+    // carrying the server's MAC address, and that other ARP packets are left untouched.
+    #[test]
+    fn test_arp_reply() {
+        let mac = [0x02, 0, 0, 0, 0, 0x01];
+        let ip = 0x0a000001;
+
+        // Who has 10.0.0.1? Tell 10.0.0.2 at 02:00:00:00:00:02.
+        let mut arp = vec![0, 1, 8, 0, 6, 4, 0, 1, 2, 0, 0, 0, 0, 2, 10, 0, 0, 2];
+        arp.extend_from_slice(&[0, 0, 0, 0, 0, 0, 10, 0, 0, 1]);
+        let request = arp.clone();
+
+        assert!(reply_arp(&mut arp, &mac, ip));
+        assert_eq!(&[0, 1, 8, 0, 6, 4, 0, 2], &arp[..8]);
+        assert_eq!(&mac, &arp[8..14]);
+        assert_eq!(&[10, 0, 0, 1], &arp[14..18]);
+        assert_eq!(&request[8..18], &arp[18..28]);
+
+        // Neither requests for other addresses, nor replies are answered.
+        let mut other = request.clone();
+        assert!(!reply_arp(&mut other, &mac, 0x0a000003));
+        assert_eq!(request, other);
+        assert!(!reply_arp(&mut arp.clone(), &mac, ip));
+        assert!(!reply_arp(&mut request.clone()[..27], &mac, ip));
+    }
+
+    // This test verifies the checksum on UDP over IPv6 packets, including ones of odd length.
+    #[test]
+    fn test_udp6_checksum() {
+        let mut src = [0; 16];
+        let mut dst = [0; 16];
+        src[0] = 0xfd;
+        src[15] = 1;
+        dst[0] = 0xfd;
+        dst[15] = 2;
+
+        let even = [0x1f, 0x90, 0, 0, 0, 10, 0, 0, 1, 2];
+        assert_eq!(0xe543, udp6_checksum(&src, &dst, &even));
+        let odd = [0x1f, 0x90, 0, 0, 0, 11, 0, 0, 1, 2, 3];
+        assert_eq!(0xe241, udp6_checksum(&src, &dst, &odd));
+    }
+
+    // This test verifies that a UDP over IPv6 request for the server's address is rewritten into
+    // a UDP over IPv4 one, and that the response to it is rewritten back into an IPv6 one.
+    #[test]
+    fn test_ipv6_rewrite() {
+        let mut server = [0; 16];
+        let mut client = [0; 16];
+        server[0] = 0xfd;
+        server[15] = 1;
+        client[0] = 0xfd;
+        client[15] = 2;
+
+        // MAC header, followed by the IPv6 header, the UDP header and a 2 byte payload.
+        let mut frame = vec![2, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 2, 0x86, 0xdd];
+        frame.extend_from_slice(&[0x60, 0, 0, 0, 0, 10, 0x11, 64]);
+        frame.extend_from_slice(&client);
+        frame.extend_from_slice(&server);
+        frame.extend_from_slice(&[0, 1, 0, 2, 0, 10, 0, 0, 7, 8]);
+        let request = frame.clone();
+
+        // Neither requests for other addresses, nor ones that are too short, nor ones from
+        // clients that were turned away are rewritten.
+        let known = |addr: &[u8; 16]| if addr[15] == 2 { Some(0x010203) } else { None };
+        assert!(!ipv6_to_ipv4(&mut frame, &client, 0x0a000001, &known));
+        assert!(!ipv6_to_ipv4(&mut frame[..63], &server, 0x0a000001, &known));
+        assert!(!ipv6_to_ipv4(&mut frame, &server, 0x0a000001, &|_: &[u8; 16]| None));
+        assert_eq!(request, frame);
+
+        assert!(ipv6_to_ipv4(&mut frame, &server, 0x0a000001, &known));
+        let ipv4 = &frame[IPV6_GROWTH..];
+        assert_eq!(&request[..12], &ipv4[..12]);
+        assert_eq!(&[8, 0, 0x45, 0, 0, 30], &ipv4[12..18]);
+        assert_eq!(&[64, 0x11], &ipv4[22..24]);
+        assert_eq!(&[0, 1, 2, 3, 10, 0, 0, 1], &ipv4[26..34]);
+        assert_eq!(&request[54..], &ipv4[34..]);
+
+        // Rewriting the request back as a response restores it, but with a checksum.
+        ipv4_to_ipv6(&mut frame, &client, &server);
+        assert_eq!(&request[..60], &frame[..60]);
+        assert_eq!(0xffff, udp6_checksum(&client, &server, &frame[54..]));
+        assert_eq!(&request[62..], &frame[62..]);
+    }
+
+    // This test verifies that IPv6 clients keep the slot they were first given.
+    #[test]
+    fn test_ipv6_clients() {
+        let clients = Ipv6Clients::new([1; 16]);
+        assert_eq!(Some(0), clients.index(&[2; 16]));
+        assert_eq!(Some(1), clients.index(&[3; 16]));
+        assert_eq!(Some(0), clients.index(&[2; 16]));
+
+        assert_eq!(Some([3; 16]), clients.client(1));
+        assert_eq!(None, clients.client(2));
+    }
+
+    // This test verifies that a new IPv6 client replaces an idle one once the table is full,
+    // and that busy clients are never replaced.
+    #[test]
+    fn test_ipv6_clients_evict() {
+        let clients = Ipv6Clients::with_limits([1; 16], 2, 100);
+        assert_eq!(Some(0), clients.index_at(&[2; 16], 10));
+        assert_eq!(Some(1), clients.index_at(&[3; 16], 10));
+        assert_eq!(Some(0), clients.index_at(&[2; 16], 60));
+
+        // Neither client is idle yet.
+        assert_eq!(None, clients.index_at(&[4; 16], 100));
+
+        // The first client is still busy, but the second one went idle and is replaced.
+        let now = 111 + cycles::cycles_per_second() / 1000;
+        assert_eq!(Some(0), clients.index_at(&[2; 16], now));
+        assert_eq!(Some(1), clients.index_at(&[4; 16], now));
+        assert_eq!(Some([4; 16]), clients.client(1));
+        assert_eq!(None, clients.index_at(&[3; 16], now + 1));
+    }
+
+    // This test verifies the checksums filled in on a UDP over IPv4 response in each mode.
+    #[test]
+    fn test_ipv4_checksums() {
+        // MAC header, followed by the IPv4 header, the UDP header and a 2 byte payload.
+        let mut frame = vec![2, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 2, 8, 0];
+        frame.extend_from_slice(&[0x45, 0, 0, 30, 0, 0, 0, 0, 64, 0x11, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        frame.extend_from_slice(&[0, 1, 0, 2, 0, 10, 0, 0, 7, 8]);
+        let response = frame.clone();
+
+        ipv4_checksums(&mut frame, Checksums::Off);
+        assert_eq!(response, frame);
+
+        ipv4_checksums(&mut frame, Checksums::Ip);
+        assert_eq!(&[0x66, 0xcd], &frame[24..26]);
+        assert_eq!(&[0, 0], &frame[40..42]);
+
+        ipv4_checksums(&mut frame, Checksums::Full);
+        assert_eq!(&[0x66, 0xcd], &frame[24..26]);
+        assert_eq!(&[0xe4, 0xcc], &frame[40..42]);
+
+        // The NIC expects a zero IP checksum, and the checksum of just the pseudo header.
+        ipv4_checksums(&mut frame, Checksums::Offload);
+        assert_eq!(&[0, 0], &frame[24..26]);
+        assert_eq!(&[0x14, 0x1e], &frame[40..42]);
+        assert_eq!(&response[..24], &frame[..24]);
+        assert_eq!(&response[26..40], &frame[26..40]);
+    }
+
+    // This test verifies that a backlog is only flagged after a run of full receives.
+    #[test]
+    fn test_backlog_persistent() {
+        let mut backlog = Backlog::new(3);
+
+        assert!(!backlog.record(true));
+        assert!(!backlog.record(true));
+        assert!(backlog.record(true));
+        assert!(backlog.record(true));
+
+        // A receive that does not fill up a batch ends the run.
+        assert!(!backlog.record(false));
+        assert!(!backlog.record(true));
+    }
+
+    // This test verifies that a threshold of zero disables backlog detection.
+    #[test]
+    fn test_backlog_disabled() {
+        let mut backlog = Backlog::new(0);
+        for _ in 0..100 {
+            assert!(!backlog.record(true));
+        }
+    }
+
+    // This test verifies that an alternating switch flips every period, and that the cost of
+    // dispatching is averaged separately for either arm.
+    #[test]
+    fn test_group_switch_ab() {
+        let mut grouping = GroupSwitch::new(false, 2);
+
+        let mut arms = Vec::new();
+        for _ in 0..6 {
+            let group = grouping.grouped();
+            arms.push(group);
+            grouping.record(group, 10, if group { 100 } else { 300 });
+        }
+
+        assert_eq!(vec![false, false, true, true, false, false], arms);
+        assert_eq!((30.0, 10.0), grouping.take_stats());
+        assert_eq!((0.0, 0.0), grouping.take_stats());
+    }
+
+    // This test verifies that a switch without a period sticks to it's configured arm.
+    #[test]
+    fn test_group_switch_fixed() {
+        let mut on = GroupSwitch::new(true, 0);
+        let mut off = GroupSwitch::new(false, 0);
+        for _ in 0..10 {
+            on.record(true, 1, 1);
+            off.record(false, 1, 1);
+            assert!(on.grouped());
+            assert!(!off.grouped());
+        }
+    }
+
+    // This test verifies that an idle poll receives off the network port, sends nothing out,
+    // and does not steal off a sibling that reports an empty queue.
+    #[test]
+    fn test_dispatch_idle_poll() {
+        let net = MockTransport::new(None);
+        let sib = MockTransport::new(Some(0));
+        let mut dispatch = mock_dispatch(&net, &sib);
+
+        for polls in 1..5 {
+            assert!(!dispatch.poll());
+            assert_eq!(polls, net.recvs());
+        }
+        assert_eq!(0, sib.recvs());
+        assert_eq!(0, net.sent());
+        assert_eq!(0, sib.sent());
+    }
+
+    // This test verifies that a dispatcher steals off a sibling that cannot tell its queue
+    // depth, and backs off once the steal comes back empty.
+    #[test]
+    fn test_dispatch_steal_backoff() {
+        let net = MockTransport::new(None);
+        let sib = MockTransport::new(None);
+        let mut dispatch = mock_dispatch(&net, &sib);
+
+        let mut steals = Vec::new();
+        for _ in 0..3 {
+            assert!(!dispatch.poll());
+            steals.push(sib.recvs());
+        }
+
+        assert_eq!(vec![1, 1, 2], steals);
+        assert_eq!(3, net.recvs());
+        assert_eq!(0, sib.sent());
+    }
+}
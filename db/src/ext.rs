@@ -17,8 +17,11 @@ use std::rc::Rc;
 use std::sync::Arc;
 use std::ops::Generator;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use super::common::TenantId;
+use super::common::{TableId, TenantId};
+use super::shadow::Shadow;
+use super::task::CostClass;
 
 use spin::RwLock;
 use sandstorm::db::DB;
@@ -31,6 +34,48 @@ const EXT_BUCKETS: usize = 32;
 // The type signature of the function that will be searched for inside an so.
 type Proc = unsafe extern "C" fn(Rc<DB>) -> Box<Generator<Yield = u64, Return = u64>>;
 
+/// The behavior an extension declares when it is installed. Master relies on this instead of
+/// discovering an extension's behavior while it runs.
+#[derive(Clone, Debug)]
+pub struct Manifest {
+    /// How long the extension is expected to run for between yields. Refer to `CostClass`.
+    pub cost: CostClass,
+
+    /// True if the extension never writes to the database. Only read-only extensions can be
+    /// invoked in read-only mode, and only they can have their results cached.
+    pub read_only: bool,
+
+    /// The tables the extension reads from.
+    pub tables: Vec<TableId>,
+}
+
+// Implementation of methods on Manifest.
+impl Manifest {
+    /// Returns the manifest of an extension that did not declare anything about itself. Such an
+    /// extension is assumed to write to the database, and to have an unknown cost.
+    pub fn undeclared() -> Manifest {
+        Manifest {
+            cost: CostClass::UNKNOWN,
+            read_only: false,
+            tables: Vec::new(),
+        }
+    }
+
+    /// Returns a manifest declaring a cost class and whether the extension is read-only.
+    ///
+    /// # Arguments
+    ///
+    /// * `cost`:      The cost class of the extension.
+    /// * `read_only`: True if the extension never writes to the database.
+    pub fn new(cost: CostClass, read_only: bool) -> Manifest {
+        Manifest {
+            cost: cost,
+            read_only: read_only,
+            tables: Vec::new(),
+        }
+    }
+}
+
 /// This type represents an extension that has been successfully loaded into
 /// the database. As long as this type is not dropped, the extension will exist
 /// inside the database's address space, and can be called into.
@@ -45,6 +90,13 @@ pub struct Extension {
     // The actual symbol inside the dynamically loaded library that will be
     // used by the database during an "invoke".
     procedure: Symbol<Proc>,
+
+    // The manifest the extension was installed with.
+    manifest: Manifest,
+
+    // The number of invocations of the extension that are currently running or waiting to run.
+    // Only tracked for extensions declared LONG. Refer to `Container`.
+    inflight: AtomicUsize,
 }
 
 // Implementation of methods on Extension.
@@ -70,6 +122,22 @@ impl Extension {
     /// An `Extension` if the .so file was found, and contains a symbol called
     /// "init". This handle can then be used to call into the so.
     pub fn load(name: &str) -> Option<Extension> {
+        Extension::load_with_manifest(name, Manifest::undeclared())
+    }
+
+    /// This function loads an .so file into the database along with the
+    /// manifest it was installed with. Refer to `load()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`:     The path (absolute or relative) of the .so file to be loaded.
+    /// * `manifest`: The behavior declared by the extension.
+    ///
+    /// # Return
+    ///
+    /// An `Extension` if the .so file was found, and contains a symbol called
+    /// "init".
+    pub fn load_with_manifest(name: &str, manifest: Manifest) -> Option<Extension> {
         // First, try to dynamically load the .so file into the database.
         if let Ok(lib) = Library::new(name) {
             // If the load was successfull, try to find a function called
@@ -87,6 +155,8 @@ impl Extension {
                 return Some(Extension {
                     library: lib,
                     procedure: procedure,
+                    manifest: manifest,
+                    inflight: AtomicUsize::new(0),
                 });
             }
         }
@@ -110,6 +180,30 @@ impl Extension {
         // Call into the procedure, and return the generator.
         unsafe { (self.procedure)(db) }
     }
+
+    /// Returns the manifest the extension was installed with.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Returns the number of invocations of the extension that are in flight. Always zero unless
+    /// the extension was declared LONG.
+    pub fn inflight(&self) -> usize {
+        self.inflight.load(Ordering::Relaxed)
+    }
+
+    /// Marks an invocation of the extension as started or finished. Refer to `inflight()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `started`: True if an invocation was started, false if one finished.
+    pub fn track(&self, started: bool) {
+        if started {
+            self.inflight.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.inflight.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
 }
 
 /// This type represents an extension manager which keeps track of extensions
@@ -117,6 +211,9 @@ impl Extension {
 pub struct ExtensionManager {
     // A simple map from tenants and extension names to extensions.
     extensions: [RwLock<HashMap<(TenantId, String), Arc<Extension>>>; EXT_BUCKETS],
+
+    // Shadow versions of extensions, by the tenant and name of the extension they shadow.
+    shadows: RwLock<HashMap<(TenantId, String), Arc<Shadow>>>,
 }
 
 // Implementation of methods on ExtensionManager.
@@ -164,6 +261,7 @@ impl ExtensionManager {
                 RwLock::new(HashMap::new()),
                 RwLock::new(HashMap::new()),
             ],
+            shadows: RwLock::new(HashMap::new()),
         }
     }
 
@@ -182,8 +280,32 @@ impl ExtensionManager {
     ///
     /// True if the extension was successfully loaded. False otherwise.
     pub fn load(&self, path: &str, tenant: TenantId, name: &str) -> bool {
+        self.load_with_manifest(path, tenant, name, Manifest::undeclared())
+    }
+
+    /// This method loads an extension for a particular tenant into the
+    /// database along with the manifest it was installed with.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`:     The path (absolute or relative) of the .so file containing
+    ///               the extension.
+    /// * `tenant`:   The tenant owning the extension.
+    /// * `name`:     The name of the extension.
+    /// * `manifest`: The behavior declared by the extension.
+    ///
+    /// # Return
+    ///
+    /// True if the extension was successfully loaded. False otherwise.
+    pub fn load_with_manifest(
+        &self,
+        path: &str,
+        tenant: TenantId,
+        name: &str,
+        manifest: Manifest,
+    ) -> bool {
         // Try to load the extension from the supplied path.
-        Extension::load(path)
+        Extension::load_with_manifest(path, manifest)
                     // If the extension was loaded successfully, write it into
                     // the extension manager. The bucket is determined by the
                     // least significant byte of the tenant id.
@@ -242,16 +364,101 @@ impl ExtensionManager {
             })
             .is_some()
     }
+
+    /// This method loads a shadow version of an extension that was previously loaded for a
+    /// tenant, replacing any earlier shadow version. The shadow version is declared with the
+    /// manifest of the loaded version. Refer to `Shadow`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`:   The path (absolute or relative) of the .so file containing the shadow
+    ///             version.
+    /// * `tenant`: The tenant owning the extension.
+    /// * `name`:   The name of the extension.
+    /// * `sample`: The shadow version is run on one in every these many invocations.
+    ///
+    /// # Return
+    ///
+    /// True if the shadow version was successfully loaded. False if it could not be, or if the
+    /// tenant does not have an extension by that name.
+    pub fn load_shadow(&self, path: &str, tenant: TenantId, name: &str, sample: u32) -> bool {
+        self.get(tenant, name)
+            .and_then(|ext| Extension::load_with_manifest(path, ext.manifest().clone()))
+            .and_then(|shadow| {
+                self.shadows
+                    .write()
+                    .insert((tenant, String::from(name)), Arc::new(Shadow::new(shadow, sample)));
+                Some(())
+            })
+            .is_some()
+    }
+
+    /// This method retrieves the shadow version of an extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant owning the extension.
+    /// * `name`:   The name of the extension.
+    ///
+    /// # Return
+    ///
+    /// A ref-counted handle to the shadow version if there is one.
+    pub fn shadow(&self, tenant: TenantId, name: &str) -> Option<Arc<Shadow>> {
+        let shadows = self.shadows.read();
+        if shadows.is_empty() {
+            return None;
+        }
+
+        shadows.get(&(tenant, String::from(name))).cloned()
+    }
+
+    /// This method removes the shadow version of an extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant owning the extension.
+    /// * `name`:   The name of the extension.
+    ///
+    /// # Return
+    ///
+    /// The shadow version that was removed, if there was one. Invocations already running it
+    /// complete normally.
+    pub fn remove_shadow(&self, tenant: TenantId, name: &str) -> Option<Arc<Shadow>> {
+        self.shadows.write().remove(&(tenant, String::from(name)))
+    }
+
+    /// This method replaces an extension with it's shadow version, which stops being run as a
+    /// shadow. Invocations already running the replaced version complete normally.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant owning the extension.
+    /// * `name`:   The name of the extension.
+    ///
+    /// # Return
+    ///
+    /// The shadow version that was promoted, if there was one.
+    pub fn promote(&self, tenant: TenantId, name: &str) -> Option<Arc<Shadow>> {
+        self.remove_shadow(tenant, name).map(|shadow| {
+            let bucket = (tenant & 0xff) as usize & (EXT_BUCKETS - 1);
+            self.extensions[bucket]
+                .write()
+                .insert((tenant, String::from(name)), shadow.extension());
+            shadow
+        })
+    }
 }
 
 // This module contains simple tests for Extension and ExtensionManager.
 #[cfg(test)]
 mod tests {
     use std::rc::Rc;
+    use std::sync::Arc;
     use std::ops::GeneratorState;
 
     use sandstorm::null::NullDB;
-    use super::{Extension, ExtensionManager};
+    use super::{Extension, ExtensionManager, Manifest};
+    use super::super::task::CostClass;
 
     // This function attempts to load and run a test extension, and asserts
     // that both operations were successfull.
@@ -322,6 +529,60 @@ mod tests {
         unsafe { assert_eq!(GeneratorState::Complete(0), gen.resume()) };
     }
 
+    // This function tests that an extension retrieved from the extension
+    // manager carries the manifest it was loaded with.
+    #[test]
+    fn test_man_get_manifest() {
+        let man = ExtensionManager::new();
+        let manifest = Manifest::new(CostClass::LONG, true);
+        assert!(man.load_with_manifest("../ext/test/target/release/libtest.so", 0, "t", manifest));
+        assert!(man.load("../ext/test/target/release/libtest.so", 0, "u"));
+
+        let ext = man.get(0, "t").unwrap();
+        assert_eq!(CostClass::LONG, ext.manifest().cost);
+        assert!(ext.manifest().read_only);
+
+        let ext = man.get(0, "u").unwrap();
+        assert_eq!(CostClass::UNKNOWN, ext.manifest().cost);
+        assert!(!ext.manifest().read_only);
+    }
+
+    // This function tests that a shadow version can only be loaded for an
+    // extension that exists, and that it inherits the extension's manifest.
+    #[test]
+    fn test_man_load_shadow() {
+        let man = ExtensionManager::new();
+        assert!(!man.load_shadow("../ext/test/target/release/libtest.so", 0, "t", 1));
+
+        let manifest = Manifest::new(CostClass::LONG, true);
+        assert!(man.load_with_manifest("../ext/test/target/release/libtest.so", 0, "t", manifest));
+        assert!(man.load_shadow("../ext/test/target/release/libtest.so", 0, "t", 1));
+        assert!(man.shadow(1, "t").is_none());
+
+        let shadow = man.shadow(0, "t").unwrap().extension();
+        assert_eq!(CostClass::LONG, shadow.manifest().cost);
+        assert!(shadow.manifest().read_only);
+
+        assert!(man.remove_shadow(0, "t").is_some());
+        assert!(man.shadow(0, "t").is_none());
+    }
+
+    // This function tests that promoting a shadow version replaces the
+    // extension with it.
+    #[test]
+    fn test_man_promote() {
+        let man = ExtensionManager::new();
+        assert!(man.load("../ext/test/target/release/libtest.so", 0, "t"));
+        assert!(man.promote(0, "t").is_none());
+
+        assert!(man.load_shadow("../ext/test/target/release/libtest.so", 0, "t", 1));
+        let shadow = man.shadow(0, "t").unwrap().extension();
+        assert!(man.promote(0, "t").is_some());
+
+        assert!(man.shadow(0, "t").is_none());
+        assert!(Arc::ptr_eq(&shadow, &man.get(0, "t").unwrap()));
+    }
+
     // This function tests that a non-existent extension cannot be retrieved
     // from the extension manager.
     #[test]
@@ -0,0 +1,903 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+// Wire compatibility tests. Every request and response is built with fixed field values, and
+// compared byte for byte against a golden copy checked in under db/golden, so that a change to
+// the wire format shows up as a failing test instead of as clients and servers that silently
+// disagree. Only the RPC (i.e, everything after the UDP header) is compared. If a change to the
+// format is intended, regenerate the corpus by running the ignored `regenerate_golden` test, and
+// check in the result along with the change:
+//
+//     cargo test --no-default-features golden -- --ignored
+//
+// The parsers in the rpc and unpack modules are also fed random packets, and must never panic.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Write;
+use std::mem::{size_of, transmute};
+use std::slice;
+
+use e2d2::common::EmptyMetadata;
+use e2d2::headers::{IpHeader, MacHeader, UdpHeader};
+use e2d2::interface::*;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use sandstorm::schema::{Field, FieldType, Schema};
+
+use common::PACKET_MTU;
+use migrate;
+use rpc;
+use rpc::{AssocInfo, ScanRecord, TableInfo};
+use unpack;
+use wireformat::*;
+
+// Field values shared by every vector. Every byte of a multi-byte value is distinct, so that
+// fields written with the wrong width or byte order don't go unnoticed.
+const TENANT: u32 = 0x0a0b0c0d;
+const STAMP: u64 = 0x1122334455667788;
+const TABLE: u64 = 0x0102030405060708;
+const KEY: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+const VALUE: &[u8] = b"value";
+const REDIRECT: &[u8] = b"10.0.0.2:9000";
+
+// The destination port on requests. Not part of the compared bytes.
+const PORT: u16 = 0x8001;
+
+// Returns the bytes of a header as laid out on the wire.
+fn raw<T>(hdr: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(hdr as *const T as *const u8, size_of::<T>()) }
+}
+
+// Returns a little-endian table identifier.
+fn table(id: u64) -> [u8; 8] {
+    unsafe { transmute(id.to_le()) }
+}
+
+// Returns the bytes following the UDP header on a request built by the rpc module.
+fn rpc_bytes(request: Packet<IpHeader, EmptyMetadata>) -> Vec<u8> {
+    request.parse_header::<UdpHeader>().get_payload().to_vec()
+}
+
+// Returns a packet parsed upto it's UDP header, whose payload is `rpc`.
+fn udp_packet(rpc: &[u8]) -> Packet<UdpHeader, EmptyMetadata> {
+    let mut packet = new_packet()
+        .expect("Failed to allocate packet!")
+        .push_header(&MacHeader::new())
+        .expect("Failed to push MAC header!")
+        .push_header(&IpHeader::new())
+        .expect("Failed to push IP header!")
+        .push_header(&UdpHeader::new())
+        .expect("Failed to push UDP header!");
+
+    packet
+        .add_to_payload_tail(rpc.len(), rpc)
+        .expect("Failed to write RPC into packet!");
+    packet
+}
+
+// Builds every request in the corpus.
+fn requests() -> Vec<(&'static str, Vec<u8>)> {
+    let mac = MacHeader::new();
+    let ip = IpHeader::new();
+    let udp = UdpHeader::new();
+
+    let mut install = raw(&InstallRequest::new(TENANT, 3, 4, 60, 1, 2, true, STAMP)).to_vec();
+    install.extend_from_slice(b"get");
+    install.extend_from_slice(b"\x7fELF");
+    install.extend_from_slice(&table(TABLE));
+
+    // A put of KEY followed by a delete of it.
+    let mut records = Vec::new();
+    migrate::encode(&mut records, TABLE, &KEY, Some(VALUE));
+    migrate::encode(&mut records, TABLE, &KEY, None);
+
+    let mut migration = raw(&MigrateRequest::new(TENANT, 13, 13, STAMP)).to_vec();
+    migration.extend_from_slice(b"10.0.0.2:5000");
+    migration.extend_from_slice(REDIRECT);
+
+    let mut shadow = raw(&ShadowRequest::new(TENANT, 3, 4, 8, false, STAMP)).to_vec();
+    shadow.extend_from_slice(b"get");
+    shadow.extend_from_slice(b"\x7fELF");
+
+    // A schema with a single 8 byte field at offset 16.
+    let fields = vec![Field {
+        name: "id".to_string(),
+        kind: FieldType::U64,
+        offset: 0x10,
+        len: 8,
+    }];
+    let fields = Schema::new(fields).expect("Failed to create schema.").encode();
+    let mut schema = raw(&SchemaRequest::new(TENANT, TABLE, fields.len() as u32, STAMP)).to_vec();
+    schema.extend_from_slice(&fields);
+
+    let path = b"/tmp/table.snap";
+    let mut table_snapshot = raw(&SnapshotRequest::new(TENANT, TABLE, 15, STAMP)).to_vec();
+    table_snapshot.extend_from_slice(path);
+
+    let mut restore = raw(&RestoreRequest::new(TENANT, TABLE, 15, STAMP)).to_vec();
+    restore.extend_from_slice(path);
+
+    let mut pairs = table(TABLE).to_vec();
+    pairs.extend_from_slice(&KEY);
+
+    let assoc = |opcode: OpCode, data: &[u8]| {
+        rpc_bytes(rpc::create_assoc_rpc(
+            &mac, &ip, &udp, TENANT, opcode, 0x10, 0x0203, 0x20, 0x30, 0x40, data, STAMP, PORT,
+        ))
+    };
+
+    vec![
+        (
+            "get_request",
+            rpc_bytes(rpc::create_get_rpc(
+                &mac, &ip, &udp, TENANT, TABLE, &KEY, STAMP, PORT,
+            )),
+        ),
+        (
+            "put_request",
+            rpc_bytes(rpc::create_put_rpc(
+                &mac, &ip, &udp, TENANT, TABLE, &KEY, VALUE, STAMP, PORT,
+            )),
+        ),
+        (
+            "invoke_request",
+            rpc_bytes(rpc::create_invoke_rpc(
+                &mac, &ip, &udp, TENANT, 3, b"getargs", STAMP, PORT,
+            )),
+        ),
+        ("install_request", install),
+        (
+            "multiget_request",
+            rpc_bytes(rpc::create_multiget_rpc(
+                &mac,
+                &ip,
+                &udp,
+                TENANT,
+                TABLE,
+                4,
+                2,
+                &[1, 0, 0, 0, 2, 0, 0, 0],
+                STAMP,
+                PORT,
+            )),
+        ),
+        (
+            "multitable_get_request",
+            rpc_bytes(rpc::create_multitable_get_rpc(
+                &mac, &ip, &udp, TENANT, 4, 1, &pairs, STAMP, PORT,
+            )),
+        ),
+        (
+            "list_tables_request",
+            rpc_bytes(rpc::create_list_tables_rpc(
+                &mac, &ip, &udp, TENANT, STAMP, PORT,
+            )),
+        ),
+        (
+            "assoc_add_request",
+            assoc(OpCode::SandstormAssocAddRpc, b"data"),
+        ),
+        ("assoc_del_request", assoc(OpCode::SandstormAssocDelRpc, &[])),
+        (
+            "assoc_range_request",
+            assoc(OpCode::SandstormAssocRangeRpc, &[]),
+        ),
+        (
+            "assoc_count_request",
+            assoc(OpCode::SandstormAssocCountRpc, &[]),
+        ),
+        (
+            "server_info_request",
+            rpc_bytes(rpc::create_server_info_rpc(
+                &mac, &ip, &udp, TENANT, STAMP, PORT,
+            )),
+        ),
+        (
+            "move_key_request",
+            rpc_bytes(rpc::create_move_key_rpc(
+                &mac,
+                &ip,
+                &udp,
+                TENANT,
+                TABLE,
+                0x0807060504030201,
+                &KEY,
+                STAMP,
+                PORT,
+            )),
+        ),
+        (
+            "bulk_put_request",
+            rpc_bytes(rpc::create_bulk_put_rpc(
+                &mac, &ip, &udp, TENANT, 2, &records, STAMP, PORT,
+            )),
+        ),
+        ("migrate_request", migration),
+        (
+            "scan_request",
+            rpc_bytes(rpc::create_scan_rpc(
+                &mac, &ip, &udp, TENANT, TABLE, &KEY, &[0xdf], 16, STAMP, PORT,
+            )),
+        ),
+        ("shadow_request", shadow),
+        (
+            "cas_request",
+            rpc_bytes(rpc::create_cas_rpc(
+                &mac, &ip, &udp, TENANT, TABLE, &KEY, VALUE, 0x3132333435363738, STAMP, PORT,
+            )),
+        ),
+        ("schema_request", schema),
+        ("snapshot_request", table_snapshot),
+        ("restore_request", restore),
+        (
+            "delete_request",
+            rpc_bytes(rpc::create_delete_rpc(
+                &mac, &ip, &udp, TENANT, TABLE, &KEY, STAMP, PORT,
+            )),
+        ),
+        (
+            "get_statistics_request",
+            rpc_bytes(rpc::create_get_statistics_rpc(
+                &mac, &ip, &udp, TENANT, STAMP, PORT,
+            )),
+        ),
+        (
+            "tenant_admin_request",
+            rpc_bytes(rpc::create_tenant_admin_rpc(
+                &mac,
+                &ip,
+                &udp,
+                TENANT,
+                0x21222324,
+                TenantAction::CreateTable,
+                TABLE,
+                STAMP,
+                PORT,
+            )),
+        ),
+    ]
+}
+
+// Builds every response in the corpus.
+fn responses() -> Vec<(&'static str, Vec<u8>)> {
+    let mut get = GetResponse::new(STAMP, OpCode::SandstormGetRpc, TENANT);
+    get.value_length = VALUE.len() as u32;
+    get.version = 0x2122232425262728;
+    let mut get = raw(&get).to_vec();
+    get.extend_from_slice(VALUE);
+
+    let mut invoke = raw(&InvokeResponse::new(STAMP, OpCode::SandstormInvokeRpc, TENANT)).to_vec();
+    invoke.extend_from_slice(b"result");
+
+    // A single table with 42 objects, created at 0x5b000000 seconds since the epoch.
+    let mut list = raw(&ListTablesResponse::new(
+        STAMP,
+        OpCode::SandstormListTablesRpc,
+        TENANT,
+        1,
+    )).to_vec();
+    list.extend_from_slice(&table(TABLE));
+    list.push(TableKind::Hash as u8);
+    list.extend_from_slice(&table(42));
+    list.extend_from_slice(&table(0x5b000000));
+
+    let assoc = |opcode: OpCode, count: u32| {
+        let mut res = AssocResponse::new(STAMP, opcode, TENANT);
+        res.count = count;
+        raw(&res).to_vec()
+    };
+
+    // A single association pointing to object 0x20 at time 0x30.
+    let mut range = assoc(OpCode::SandstormAssocRangeRpc, 1);
+    range.extend_from_slice(&table(0x20));
+    range.extend_from_slice(&table(0x30));
+    range.extend_from_slice(&[4, 0, 0, 0]);
+    range.extend_from_slice(b"data");
+
+    let mut throttled = PutResponse::new(STAMP, OpCode::SandstormPutRpc, TENANT);
+    throttled.common_header.status = RpcStatus::StatusThrottled;
+
+    let mut statistics =
+        GetStatisticsResponse::new(STAMP, OpCode::SandstormGetStatisticsRpc, TENANT);
+    statistics.length = 0x2f;
+
+    let mut tenant_admin =
+        TenantAdminResponse::new(STAMP, OpCode::SandstormTenantAdminRpc, TENANT);
+    tenant_admin.objects = 0x1020304050607080;
+
+    let mut info = ServerInfoResponse::new(STAMP, OpCode::SandstormServerInfoRpc, TENANT, 8);
+    info.config_version = 0x0102030405060708;
+    info.git_hash = 0x1112131415161718;
+    info.features = 3;
+
+    let mut bulk_put = BulkPutResponse::new(STAMP, OpCode::SandstormBulkPutRpc, TENANT);
+    bulk_put.num_records = 2;
+
+    let mut migration = MigrateResponse::new(STAMP, OpCode::SandstormMigrateRpc, TENANT);
+    migration.num_records = 42;
+
+    // The second of two packets making up a scan() response, carrying a single object.
+    let mut scan = raw(&ScanResponse::new(
+        STAMP,
+        OpCode::SandstormScanRpc,
+        TENANT,
+        1,
+        1,
+        2,
+    )).to_vec();
+    scan.extend_from_slice(&[4, 0, 5, 0, 0, 0]);
+    scan.extend_from_slice(&KEY);
+    scan.extend_from_slice(VALUE);
+
+    let mut shadow = ShadowResponse::new(STAMP, OpCode::SandstormShadowRpc, TENANT);
+    shadow.runs = 0x10;
+    shadow.mismatches = 1;
+    shadow.primary_cycles = 0x1000;
+    shadow.shadow_cycles = 0x1200;
+
+    // A cas() that found the object under the key at a different version than expected.
+    let mut cas = CasResponse::new(STAMP, OpCode::SandstormCasRpc, TENANT);
+    cas.common_header.status = RpcStatus::StatusVersionMismatch;
+    cas.version = 0x4142434445464748;
+
+    // A schema() for a table the tenant does not have.
+    let mut schema = SchemaResponse::new(STAMP, OpCode::SandstormSchemaRpc, TENANT);
+    schema.common_header.status = RpcStatus::StatusTableDoesNotExist;
+
+    let mut table_snapshot = SnapshotResponse::new(STAMP, OpCode::SandstormSnapshotRpc, TENANT);
+    table_snapshot.num_records = 42;
+
+    let mut restore = RestoreResponse::new(STAMP, OpCode::SandstormRestoreRpc, TENANT);
+    restore.num_records = 42;
+
+    // The second of two fragments of a response too large for a single packet.
+    let mut fragment = raw(&FragmentResponse::new(STAMP, TENANT, 0x31323334, 1, 2)).to_vec();
+    fragment.extend_from_slice(VALUE);
+
+    // A get() redirected to the server the tenant was migrated to. The header is as long as a
+    // get() response's, and is zeroed beyond the common header.
+    let mut hdr = RpcResponseHeader::new(STAMP, OpCode::SandstormGetRpc, TENANT);
+    hdr.status = RpcStatus::StatusMoved;
+    let mut moved = raw(&hdr).to_vec();
+    moved.resize(size_of::<GetResponse>(), 0);
+    moved.extend_from_slice(REDIRECT);
+
+    vec![
+        ("get_response", get),
+        (
+            "put_response",
+            raw(&PutResponse::new(STAMP, OpCode::SandstormPutRpc, TENANT)).to_vec(),
+        ),
+        ("invoke_response", invoke),
+        (
+            "install_response",
+            raw(&InstallResponse::new(STAMP, OpCode::SandstormInstallRpc, TENANT)).to_vec(),
+        ),
+        (
+            "multiget_response",
+            raw(&MultiGetResponse::new(STAMP, OpCode::SandstormMultiGetRpc, TENANT, 2)).to_vec(),
+        ),
+        (
+            "multitable_get_response",
+            raw(&MultiGetResponse::new(
+                STAMP,
+                OpCode::SandstormMultiTableGetRpc,
+                TENANT,
+                1,
+            )).to_vec(),
+        ),
+        ("list_tables_response", list),
+        (
+            "assoc_add_response",
+            assoc(OpCode::SandstormAssocAddRpc, 1),
+        ),
+        (
+            "assoc_del_response",
+            assoc(OpCode::SandstormAssocDelRpc, 1),
+        ),
+        ("assoc_range_response", range),
+        (
+            "assoc_count_response",
+            assoc(OpCode::SandstormAssocCountRpc, 7),
+        ),
+        ("server_info_response", raw(&info).to_vec()),
+        (
+            "move_key_response",
+            raw(&MoveKeyResponse::new(STAMP, OpCode::SandstormMoveKeyRpc, TENANT)).to_vec(),
+        ),
+        ("throttled_response", raw(&throttled).to_vec()),
+        ("bulk_put_response", raw(&bulk_put).to_vec()),
+        ("migrate_response", raw(&migration).to_vec()),
+        ("moved_response", moved),
+        ("scan_response", scan),
+        ("shadow_response", raw(&shadow).to_vec()),
+        ("cas_response", raw(&cas).to_vec()),
+        ("fragment_response", fragment),
+        ("schema_response", raw(&schema).to_vec()),
+        ("snapshot_response", raw(&table_snapshot).to_vec()),
+        ("restore_response", raw(&restore).to_vec()),
+        (
+            "delete_response",
+            raw(&DeleteResponse::new(STAMP, OpCode::SandstormDeleteRpc, TENANT)).to_vec(),
+        ),
+        ("get_statistics_response", raw(&statistics).to_vec()),
+        ("tenant_admin_response", raw(&tenant_admin).to_vec()),
+    ]
+}
+
+// Returns the checked in copy of a vector.
+fn golden(name: &str) -> &'static [u8] {
+    match name {
+        "get_request" => &include_bytes!("../golden/get_request.bin")[..],
+        "put_request" => &include_bytes!("../golden/put_request.bin")[..],
+        "invoke_request" => &include_bytes!("../golden/invoke_request.bin")[..],
+        "install_request" => &include_bytes!("../golden/install_request.bin")[..],
+        "multiget_request" => &include_bytes!("../golden/multiget_request.bin")[..],
+        "multitable_get_request" => &include_bytes!("../golden/multitable_get_request.bin")[..],
+        "list_tables_request" => &include_bytes!("../golden/list_tables_request.bin")[..],
+        "assoc_add_request" => &include_bytes!("../golden/assoc_add_request.bin")[..],
+        "assoc_del_request" => &include_bytes!("../golden/assoc_del_request.bin")[..],
+        "assoc_range_request" => &include_bytes!("../golden/assoc_range_request.bin")[..],
+        "assoc_count_request" => &include_bytes!("../golden/assoc_count_request.bin")[..],
+        "server_info_request" => &include_bytes!("../golden/server_info_request.bin")[..],
+        "move_key_request" => &include_bytes!("../golden/move_key_request.bin")[..],
+        "bulk_put_request" => &include_bytes!("../golden/bulk_put_request.bin")[..],
+        "migrate_request" => &include_bytes!("../golden/migrate_request.bin")[..],
+        "scan_request" => &include_bytes!("../golden/scan_request.bin")[..],
+        "shadow_request" => &include_bytes!("../golden/shadow_request.bin")[..],
+        "cas_request" => &include_bytes!("../golden/cas_request.bin")[..],
+        "schema_request" => &include_bytes!("../golden/schema_request.bin")[..],
+        "snapshot_request" => &include_bytes!("../golden/snapshot_request.bin")[..],
+        "restore_request" => &include_bytes!("../golden/restore_request.bin")[..],
+        "delete_request" => &include_bytes!("../golden/delete_request.bin")[..],
+        "get_statistics_request" => &include_bytes!("../golden/get_statistics_request.bin")[..],
+        "tenant_admin_request" => &include_bytes!("../golden/tenant_admin_request.bin")[..],
+        "get_response" => &include_bytes!("../golden/get_response.bin")[..],
+        "put_response" => &include_bytes!("../golden/put_response.bin")[..],
+        "invoke_response" => &include_bytes!("../golden/invoke_response.bin")[..],
+        "install_response" => &include_bytes!("../golden/install_response.bin")[..],
+        "multiget_response" => &include_bytes!("../golden/multiget_response.bin")[..],
+        "multitable_get_response" => &include_bytes!("../golden/multitable_get_response.bin")[..],
+        "list_tables_response" => &include_bytes!("../golden/list_tables_response.bin")[..],
+        "assoc_add_response" => &include_bytes!("../golden/assoc_add_response.bin")[..],
+        "assoc_del_response" => &include_bytes!("../golden/assoc_del_response.bin")[..],
+        "assoc_range_response" => &include_bytes!("../golden/assoc_range_response.bin")[..],
+        "assoc_count_response" => &include_bytes!("../golden/assoc_count_response.bin")[..],
+        "server_info_response" => &include_bytes!("../golden/server_info_response.bin")[..],
+        "move_key_response" => &include_bytes!("../golden/move_key_response.bin")[..],
+        "throttled_response" => &include_bytes!("../golden/throttled_response.bin")[..],
+        "bulk_put_response" => &include_bytes!("../golden/bulk_put_response.bin")[..],
+        "migrate_response" => &include_bytes!("../golden/migrate_response.bin")[..],
+        "moved_response" => &include_bytes!("../golden/moved_response.bin")[..],
+        "scan_response" => &include_bytes!("../golden/scan_response.bin")[..],
+        "shadow_response" => &include_bytes!("../golden/shadow_response.bin")[..],
+        "cas_response" => &include_bytes!("../golden/cas_response.bin")[..],
+        "fragment_response" => &include_bytes!("../golden/fragment_response.bin")[..],
+        "schema_response" => &include_bytes!("../golden/schema_response.bin")[..],
+        "snapshot_response" => &include_bytes!("../golden/snapshot_response.bin")[..],
+        "restore_response" => &include_bytes!("../golden/restore_response.bin")[..],
+        "delete_response" => &include_bytes!("../golden/delete_response.bin")[..],
+        "get_statistics_response" => &include_bytes!("../golden/get_statistics_response.bin")[..],
+        "tenant_admin_response" => &include_bytes!("../golden/tenant_admin_response.bin")[..],
+        _ => panic!("No golden copy of {}", name),
+    }
+}
+
+// Returns `len` random bytes.
+fn random_bytes(rng: &mut XorShiftRng, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+// This test verifies that every opcode and status keeps the value it is encoded with on the wire.
+#[test]
+fn test_golden_codes() {
+    assert_eq!(0x01, Service::MasterService as u8);
+
+    let opcodes = [
+        OpCode::SandstormGetRpc as u8,
+        OpCode::SandstormPutRpc as u8,
+        OpCode::SandstormInvokeRpc as u8,
+        OpCode::SandstormInstallRpc as u8,
+        OpCode::SandstormMultiGetRpc as u8,
+        OpCode::SandstormMultiTableGetRpc as u8,
+        OpCode::SandstormListTablesRpc as u8,
+        OpCode::SandstormAssocAddRpc as u8,
+        OpCode::SandstormAssocDelRpc as u8,
+        OpCode::SandstormAssocRangeRpc as u8,
+        OpCode::SandstormAssocCountRpc as u8,
+        OpCode::SandstormServerInfoRpc as u8,
+        OpCode::SandstormMoveKeyRpc as u8,
+        OpCode::SandstormBulkPutRpc as u8,
+        OpCode::SandstormMigrateRpc as u8,
+        OpCode::SandstormScanRpc as u8,
+        OpCode::SandstormShadowRpc as u8,
+        OpCode::SandstormCasRpc as u8,
+        OpCode::SandstormFragment as u8,
+        OpCode::SandstormSchemaRpc as u8,
+        OpCode::SandstormSnapshotRpc as u8,
+        OpCode::SandstormRestoreRpc as u8,
+        OpCode::SandstormDeleteRpc as u8,
+        OpCode::SandstormGetStatisticsRpc as u8,
+        OpCode::SandstormTenantAdminRpc as u8,
+        OpCode::InvalidOperation as u8,
+    ];
+    for (i, opcode) in opcodes.iter().enumerate() {
+        assert_eq!(i as u8 + 1, *opcode);
+    }
+
+    let statuses = [
+        RpcStatus::StatusOk as u8,
+        RpcStatus::StatusTenantDoesNotExist as u8,
+        RpcStatus::StatusTableDoesNotExist as u8,
+        RpcStatus::StatusObjectDoesNotExist as u8,
+        RpcStatus::StatusMalformedRequest as u8,
+        RpcStatus::StatusInternalError as u8,
+        RpcStatus::StatusInvalidExtension as u8,
+        RpcStatus::StatusInvalidOperation as u8,
+        RpcStatus::StatusExtensionError as u8,
+        RpcStatus::StatusPartialResult as u8,
+        RpcStatus::StatusReadOnly as u8,
+        RpcStatus::StatusThrottled as u8,
+        RpcStatus::StatusCorruptObject as u8,
+        RpcStatus::StatusExtensionAborted as u8,
+        RpcStatus::StatusMoved as u8,
+        RpcStatus::StatusVersionMismatch as u8,
+        RpcStatus::StatusStaleWrite as u8,
+        RpcStatus::StatusBadOpcode as u8,
+        RpcStatus::StatusOverloaded as u8,
+        RpcStatus::StatusUnauthorized as u8,
+        RpcStatus::StatusQuotaExceeded as u8,
+        RpcStatus::StatusSnapshotAborted as u8,
+        RpcStatus::StatusLogFull as u8,
+    ];
+    for (i, status) in statuses.iter().enumerate() {
+        assert_eq!(i as u8 + 1, *status);
+    }
+
+    let actions = [
+        TenantAction::CreateTenant as u8,
+        TenantAction::DeleteTenant as u8,
+        TenantAction::CreateTable as u8,
+        TenantAction::DropTable as u8,
+    ];
+    for (i, action) in actions.iter().enumerate() {
+        assert_eq!(i as u8 + 1, *action);
+        assert_eq!(Ok(*action), TenantAction::try_from(*action).map(|a| a as u8));
+    }
+    assert_eq!(Err(0), TenantAction::try_from(0).map(|a| a as u8));
+}
+
+// This test verifies that every byte parses into the opcode, service or status it encodes, and
+// that the bytes beyond the last known value (including the invalid markers) fail to parse.
+#[test]
+fn test_golden_try_from() {
+    for byte in 0..256 {
+        let byte = byte as u8;
+        let known = byte >= 1 && byte < OpCode::InvalidOperation as u8;
+        match OpCode::try_from(byte) {
+            Ok(opcode) => assert!(known && opcode as u8 == byte),
+            Err(b) => assert!(!known && b == byte),
+        }
+
+        let known = byte >= 1 && byte <= RpcStatus::StatusLogFull as u8;
+        match RpcStatus::try_from(byte) {
+            Ok(status) => assert!(known && status as u8 == byte),
+            Err(b) => assert!(!known && b == byte),
+        }
+
+        let known = byte >= 1 && byte < Service::InvalidService as u8;
+        match Service::try_from(byte) {
+            Ok(service) => assert!(known && service as u8 == byte),
+            Err(b) => assert!(!known && b == byte),
+        }
+    }
+}
+
+// This test verifies that every request is built exactly like it's golden copy.
+#[test]
+fn test_golden_requests() {
+    for (name, bytes) in requests() {
+        assert_eq!(golden(name), &bytes[..], "{} differs from it's golden copy", name);
+    }
+}
+
+// This test verifies that every response is built exactly like it's golden copy.
+#[test]
+fn test_golden_responses() {
+    for (name, bytes) in responses() {
+        assert_eq!(golden(name), &bytes[..], "{} differs from it's golden copy", name);
+    }
+}
+
+// This test verifies that golden requests and responses parse back to the values they were built
+// with.
+#[test]
+fn test_golden_parse() {
+    let req = udp_packet(golden("get_request"));
+    assert!(rpc::header_len_ok(&req, size_of::<GetRequest>()));
+    assert!(rpc::parse_rpc_service(&req) == Service::MasterService);
+    assert!(rpc::parse_rpc_opcode(&req) == OpCode::SandstormGetRpc);
+    assert_eq!(TENANT, rpc::parse_rpc_tenant(&req));
+    assert_eq!(STAMP, rpc::parse_rpc_stamp(&req));
+    assert_eq!(size_of::<GetRequest>(), rpc::parse_rpc_header_len(&req));
+    assert_eq!(&KEY, req.parse_header::<GetRequest>().get_payload());
+
+    let res = udp_packet(golden("list_tables_response")).parse_header::<ListTablesResponse>();
+    assert_eq!(
+        vec![TableInfo {
+            id: TABLE,
+            kind: TableKind::Hash as u8,
+            num_objects: 42,
+            created: 0x5b000000,
+        }],
+        rpc::parse_list_tables(&res)
+    );
+
+    let res = udp_packet(golden("assoc_range_response")).parse_header::<AssocResponse>();
+    assert_eq!(
+        vec![AssocInfo {
+            id2: 0x20,
+            time: 0x30,
+            data: b"data".to_vec(),
+        }],
+        rpc::parse_assoc_range(&res)
+    );
+
+    let res = udp_packet(golden("scan_response")).parse_header::<ScanResponse>();
+    assert_eq!((1, 2), (res.get_header().seq, res.get_header().num_packets));
+    assert_eq!(
+        vec![ScanRecord {
+            key: KEY.to_vec(),
+            value: VALUE.to_vec(),
+        }],
+        rpc::parse_scan(&res)
+    );
+}
+
+// This test verifies that the request parsers never panic on random packets that pass the
+// header length check, and that the check rejects packets too short to carry a header length.
+#[test]
+fn test_fuzz_request_parsers() {
+    let mut rng = XorShiftRng::from_seed([0x5eed, 0x1, 0x2, 0x3]);
+
+    for _ in 0..10000 {
+        let len = rng.gen_range(0, 128);
+        let req = udp_packet(&random_bytes(&mut rng, len));
+        if !rpc::header_len_ok(&req, size_of::<RpcRequestHeader>()) {
+            continue;
+        }
+
+        assert!(len >= size_of::<RpcRequestHeader>());
+        assert!(rpc::parse_rpc_header_len(&req) <= len);
+        let _ = rpc::parse_rpc_service(&req);
+        let _ = rpc::parse_rpc_opcode(&req);
+        let _ = rpc::parse_rpc_tenant(&req);
+        let _ = rpc::parse_rpc_stamp(&req);
+    }
+
+    for len in 0..(HEADER_LEN_OFFSET + 2) {
+        assert!(!rpc::header_len_ok(&udp_packet(&vec![0xff; len]), 0));
+    }
+}
+
+// This test verifies that the response parsers never panic on random responses that pass the
+// header length check, and never return more entries than the response claims or can hold.
+// Statuses are drawn from the valid ones, since they are read as an enum.
+#[test]
+fn test_fuzz_response_parsers() {
+    let mut rng = XorShiftRng::from_seed([0x5eed, 0x4, 0x5, 0x6]);
+    let last = RpcStatus::StatusOverloaded as u8;
+
+    for _ in 0..10000 {
+        let len = rng.gen_range(size_of::<RpcResponseHeader>(), 512);
+        let mut res = random_bytes(&mut rng, len);
+        res[0] = if rng.gen() { RpcStatus::StatusOk as u8 } else { rng.gen_range(1, last + 1) };
+        res[1] = rng.gen_range(1, OpCode::InvalidOperation as u8);
+
+        // Most random header lengths fail the check, so use the length of one of the parsed
+        // headers half the time.
+        if rng.gen() {
+            let hdr_len = *rng
+                .choose(&[
+                    size_of::<InvokeResponse>(),
+                    size_of::<AssocResponse>(),
+                    size_of::<ScanResponse>(),
+                ])
+                .unwrap() as u16;
+            let hdr_len: [u8; 2] = unsafe { transmute(hdr_len.to_le()) };
+            res[HEADER_LEN_OFFSET..HEADER_LEN_OFFSET + 2].copy_from_slice(&hdr_len);
+        }
+
+        let packet = udp_packet(&res);
+        if rpc::header_len_ok(&packet, size_of::<ListTablesResponse>()) {
+            let num = packet.get_payload().len() / LIST_TABLES_ENTRY_LEN;
+            let res = packet.parse_header::<ListTablesResponse>();
+            let n_tables = res.get_header().num_tables as usize;
+            let tables = rpc::parse_list_tables(&res);
+            assert!(tables.len() <= n_tables && tables.len() <= num);
+        }
+
+        let packet = udp_packet(&res);
+        if rpc::header_len_ok(&packet, size_of::<AssocResponse>()) {
+            let num = packet.get_payload().len() / ASSOC_ENTRY_HEADER_LEN;
+            let res = packet.parse_header::<AssocResponse>();
+            let count = res.get_header().count as usize;
+            let assocs = rpc::parse_assoc_range(&res);
+            assert!(assocs.len() <= count && assocs.len() <= num);
+        }
+
+        let packet = udp_packet(&res);
+        if rpc::header_len_ok(&packet, size_of::<ScanResponse>()) {
+            let num = packet.get_payload().len() / SCAN_RECORD_HEADER_LEN;
+            let res = packet.parse_header::<ScanResponse>();
+            let count = res.get_header().num_records as usize;
+            let records = rpc::parse_scan(&res);
+            assert!(records.len() <= count && records.len() <= num);
+        }
+
+        let packet = udp_packet(&res);
+        if rpc::header_len_ok(&packet, size_of::<InvokeResponse>()) {
+            let res = packet.parse_header::<InvokeResponse>();
+            let _ = rpc::parse_extension_error(&res);
+            let _ = rpc::parse_partial_result(&res);
+        }
+
+        let packet = udp_packet(&res);
+        if rpc::header_len_ok(&packet, size_of::<GetStatisticsResponse>()) {
+            let _ = rpc::parse_statistics(&packet.parse_header::<GetStatisticsResponse>());
+        }
+
+        let _ = rpc::parse_redirect(&udp_packet(&res));
+    }
+}
+
+// This test verifies that a response too large for a single packet is split into fragments that
+// each fit in one, and that the fragments reassemble back into the response in any order.
+#[test]
+fn test_golden_fragments() {
+    let mut get = GetResponse::new(STAMP, OpCode::SandstormGetRpc, TENANT);
+    get.value_length = 1800;
+    let mut bytes = raw(&get).to_vec();
+    bytes.extend((0..1800).map(|i| i as u8));
+
+    // Responses that fit in a single packet go out as they are.
+    let small = rpc::fixup_header_length_fields(udp_packet(golden("get_response")));
+    let small = rpc::fragment_response(small, 7, false);
+    assert_eq!(1, small.len());
+
+    // Responses that fit in a single packet over IPv4 might not once the IPv6 header is added.
+    let mut edge = bytes[..size_of::<GetResponse>()].to_vec();
+    edge.extend((0..1460 - size_of::<GetResponse>()).map(|i| i as u8));
+    let v4 = rpc::fragment_response(rpc::fixup_header_length_fields(udp_packet(&edge)), 7, false);
+    assert_eq!(1, v4.len());
+    let v6 = rpc::fragment_response(rpc::fixup_header_length_fields(udp_packet(&edge)), 7, true);
+    assert_eq!(2, v6.len());
+
+    let response = rpc::fixup_header_length_fields(udp_packet(&bytes));
+    let fragments = rpc::fragment_response(response, 7, false);
+    assert_eq!(2, fragments.len());
+
+    let mut fragments: Vec<_> = fragments
+        .into_iter()
+        .map(|fragment| {
+            assert!(fragment.get_payload().len() + size_of::<IpHeader>() <= PACKET_MTU as usize);
+            let fragment = fragment.parse_header::<UdpHeader>();
+            assert!(rpc::parse_rpc_opcode(&fragment) == OpCode::SandstormFragment);
+            assert!(rpc::header_len_ok(&fragment, size_of::<FragmentResponse>()));
+            assert_eq!(STAMP, rpc::parse_rpc_stamp(&fragment));
+            fragment
+        }).collect();
+
+    let mut reassembler = rpc::Reassembler::new(4);
+    assert!(reassembler.add(fragments.pop().unwrap()).is_none());
+    assert_eq!(1, reassembler.pending());
+
+    let response = reassembler.add(fragments.pop().unwrap()).expect("Failed to reassemble!");
+    assert_eq!(&bytes[..], response.get_payload());
+    assert_eq!(0, reassembler.pending());
+}
+
+// This test verifies that a reassembler holding on to as many incomplete responses as it can
+// drops the oldest of them to make room for a new one.
+#[test]
+fn test_golden_fragments_evict() {
+    let fragment = |id: u32, index: u16| {
+        let mut bytes = raw(&FragmentResponse::new(STAMP, TENANT, id, index, 2)).to_vec();
+        bytes.extend_from_slice(VALUE);
+        udp_packet(&bytes)
+    };
+
+    let mut reassembler = rpc::Reassembler::new(1);
+    assert!(reassembler.add(fragment(1, 0)).is_none());
+    assert!(reassembler.add(fragment(2, 0)).is_none());
+    assert_eq!(1, reassembler.pending());
+
+    // The first response was dropped, so it's second fragment starts reassembly over.
+    assert!(reassembler.add(fragment(1, 1)).is_none());
+    assert!(reassembler.add(fragment(1, 0)).is_some());
+
+    // Fragments that claim to lie beyond the end of their response are dropped.
+    assert!(reassembler.add(fragment(3, 2)).is_none());
+    assert_eq!(0, reassembler.pending());
+}
+
+// This test verifies that the address a redirected request should be reissued at is parsed off
+// it's response, and that responses with other statuses carry no address.
+#[test]
+fn test_golden_redirect() {
+    let moved = udp_packet(golden("moved_response"));
+    assert_eq!(Some("10.0.0.2:9000".to_string()), rpc::parse_redirect(&moved));
+
+    let get = udp_packet(golden("get_response"));
+    assert_eq!(None, rpc::parse_redirect(&get));
+}
+
+// This test verifies that the trace flag and stage times land on the header fields carrying
+// them, and that golden vectors carry neither.
+#[test]
+fn test_golden_stage_times() {
+    let req = udp_packet(golden("get_request"));
+    assert_eq!(0, rpc::parse_rpc_flags(&req));
+
+    let mut hdr = GetRequest::new(TENANT, TABLE, KEY.len() as u16, STAMP);
+    hdr.common_header.flags = FLAG_TRACE;
+    assert_eq!(FLAG_TRACE, rpc::parse_rpc_flags(&udp_packet(raw(&hdr))));
+
+    let mut res = udp_packet(golden("get_response"));
+    assert_eq!((0, 0), rpc::parse_rpc_stage_times(&res));
+    rpc::set_rpc_stage_times(&mut res, 0x01020304, 0x05060708);
+    assert_eq!((0x01020304, 0x05060708), rpc::parse_rpc_stage_times(&res));
+
+    let res = res.parse_header::<GetResponse>();
+    let queued = res.get_header().common_header.queued;
+    let executed = res.get_header().common_header.executed;
+    assert_eq!((0x01020304, 0x05060708), (queued, executed));
+}
+
+// This test verifies that the helpers that decode invoke() payloads never panic on random
+// payloads.
+#[test]
+fn test_fuzz_unpack() {
+    let mut rng = XorShiftRng::from_seed([0x5eed, 0x7, 0x8, 0x9]);
+
+    for _ in 0..10000 {
+        let len = rng.gen_range(0, 64);
+        let payload = random_bytes(&mut rng, len);
+
+        assert_eq!(len >= 8, unpack::unpack::<u64>(&payload).is_some());
+        assert_eq!(len % 4 == 0, unpack::unpack_list::<u32>(&payload).is_some());
+        let _ = unpack::unpack_str(&payload);
+        if let Some((_, rest)) = unpack::consume_three::<u64, u32, u8>(&payload) {
+            assert!(rest.len() < len);
+        }
+    }
+}
+
+// Writes the corpus out to db/golden. Only run by hand, when the wire format changes on purpose.
+#[test]
+#[ignore]
+fn regenerate_golden() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/golden");
+
+    for (name, bytes) in requests().into_iter().chain(responses().into_iter()) {
+        let path = format!("{}/{}.bin", dir, name);
+        let mut file = File::create(&path).expect("Failed to create golden file!");
+        file.write_all(&bytes).expect("Failed to write golden file!");
+    }
+}
@@ -18,9 +18,12 @@ use std::net::{Shutdown, TcpListener};
 use std::sync::Arc;
 
 use super::master::Master;
+use super::wireformat::OpCode;
 
-/// This type is responsible for servicing the install() RPC in Sandstorm. It listens for incoming
-/// RPCs on a TCP socket, and hands them off the Master.
+/// This type is responsible for servicing the install(), shadow() and schema() RPCs, along
+/// with the bulk_put() and migrate() RPCs used to move tenants between servers, and the
+/// snapshot() and restore() RPCs used to save and reload tables. It listens for incoming RPCs
+/// on a TCP socket, and hands them off the Master.
 pub struct Installer {
     /// Master service that the install() RPC is handed off to.
     master: Arc<Master>,
@@ -58,10 +61,34 @@ impl Installer {
                     continue;
                 }
 
-                // Handoff to Master.
-                // TODO: Check Service and OpCode in RPC header.
+                // Handoff to Master based on the opcode in the RPC header. The admin RPCs that
+                // migrate tenants between servers are received here too.
+                // TODO: Check Service in RPC header.
                 req.truncate(num);
-                let res = self.master.install(req);
+                let opcode = if req.len() > 1 { req[1] } else { 0 };
+
+                // Snapshots can be streamed back, and are written straight onto the connection.
+                if opcode == OpCode::SandstormSnapshotRpc as u8 {
+                    if let Err(e) = self.master.snapshot_table(req, &mut stream) {
+                        warn!("Failed to respond to snapshot(): {}", e);
+                    }
+                    let _ = stream.shutdown(Shutdown::Both);
+                    continue;
+                }
+
+                let res = if opcode == OpCode::SandstormBulkPutRpc as u8 {
+                    self.master.bulk_put(req)
+                } else if opcode == OpCode::SandstormMigrateRpc as u8 {
+                    self.master.migrate(req)
+                } else if opcode == OpCode::SandstormShadowRpc as u8 {
+                    self.master.shadow(req)
+                } else if opcode == OpCode::SandstormSchemaRpc as u8 {
+                    self.master.schema(req)
+                } else if opcode == OpCode::SandstormRestoreRpc as u8 {
+                    self.master.restore_table(req)
+                } else {
+                    self.master.install(req)
+                };
 
                 // Return a response to the client.
                 stream.write_all(&res).unwrap();
@@ -13,9 +13,12 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-#![feature(generators, generator_trait, asm)]
+#![feature(generators, generator_trait, asm, try_from)]
 
+extern crate libc;
 extern crate libloading;
+extern crate ring;
+extern crate rand;
 extern crate sandstorm;
 extern crate serde;
 #[macro_use]
@@ -25,25 +28,45 @@ extern crate toml;
 extern crate time;
 
 pub extern crate bytes;
+#[cfg(feature = "dpdk")]
 pub extern crate e2d2;
 pub extern crate env_logger;
 #[macro_use]
 pub extern crate log;
 
 mod alloc;
+mod assoc;
 mod common;
 mod container;
 mod context;
 mod service;
 mod tenant;
 mod native;
+mod memo;
+mod shadow;
+mod sampler;
+mod pmem;
+mod shm;
+mod throttle;
+
+// Wire compatibility tests against the stand-ins for Netbricks.
+#[cfg(all(test, not(feature = "dpdk")))]
+mod golden;
+
+// Stands in for Netbricks when building without DPDK.
+#[cfg(not(feature = "dpdk"))]
+#[path = "mock.rs"]
+pub mod e2d2;
 
 // Public modules for binaries.
 pub mod rpc;
 pub mod cycles;
 pub mod cyclecounter;
 pub mod config;
+#[cfg(feature = "dpdk")]
 pub mod dispatch;
+#[cfg(feature = "dpdk")]
+pub mod transport;
 pub mod ext;
 pub mod table;
 pub mod wireformat;
@@ -51,3 +74,14 @@ pub mod master;
 pub mod sched;
 pub mod task;
 pub mod install;
+pub mod snapshot;
+pub mod backup;
+pub mod migrate;
+pub mod crypt;
+pub mod trace;
+pub mod timer;
+pub mod stats;
+pub mod statspage;
+pub mod unpack;
+pub mod logsink;
+pub mod wal;
@@ -0,0 +1,338 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Once, ONCE_INIT};
+use std::thread;
+use std::time::Duration;
+
+use env_logger::{LogBuilder, Logger};
+use log::{set_logger, Log, LogMetadata, LogRecord, SetLoggerError};
+use spin::Mutex;
+
+use super::cycles;
+
+/// Records are written straight to stderr by the core that logged them, as env_logger does.
+const SINK_CONSOLE: usize = 0;
+
+/// Records are appended to a buffer private to the core that logged them, and written to stderr
+/// by a background thread.
+const SINK_BUFFERED: usize = 1;
+
+/// Records are appended to a buffer private to the core that logged them, and written to a file
+/// private to that core by a background thread.
+const SINK_FILES: usize = 2;
+
+/// The number of bytes a core's buffer can grow to between flushes. Records logged once a buffer
+/// is full are dropped and counted, so that a core never waits on the background thread.
+const BUFFER_CAP: usize = 4 * 1024 * 1024;
+
+// A buffer that a single core appends records to, and the background thread drains.
+struct CoreBuffer {
+    // The records appended since the last flush.
+    lines: Mutex<Vec<u8>>,
+
+    // The number of records dropped since the last flush because the buffer was full.
+    dropped: AtomicUsize,
+
+    // The name of the thread that owns this buffer, if it has one.
+    name: String,
+}
+
+// State shared by the logger and the background thread that flushes buffers.
+struct Shared {
+    // One of SINK_CONSOLE, SINK_BUFFERED, or SINK_FILES.
+    sink: AtomicUsize,
+
+    // Buffers of every thread that has logged something since the sink was switched from
+    // SINK_CONSOLE. A buffer's index in this list is it's identifier.
+    buffers: Mutex<Vec<Arc<CoreBuffer>>>,
+}
+
+static mut SHARED: *const Shared = 0 as *const Shared;
+static INIT: Once = ONCE_INIT;
+
+thread_local!(static BUFFER: RefCell<Option<Arc<CoreBuffer>>> = RefCell::new(None));
+
+/// A logger that filters records like env_logger (i.e, according to RUST_LOG), but can write
+/// them out through per-core buffers instead of a stream shared by all cores.
+struct CoreLogger {
+    // Decides which records are logged.
+    filter: Logger,
+
+    // The sink records are written to, along with every core's buffer.
+    shared: &'static Shared,
+}
+
+// Implementation of the Log trait for CoreLogger.
+impl Log for CoreLogger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &LogRecord) {
+        if !self.filter.matches(record) {
+            return;
+        }
+
+        if self.shared.sink.load(Ordering::Relaxed) == SINK_CONSOLE {
+            let _ = writeln!(
+                &mut io::stderr(),
+                "{}:{}: {}",
+                record.level(),
+                record.location().module_path(),
+                record.args()
+            );
+            return;
+        }
+
+        // Records are prefixed with the cycle counter so that records from different cores can
+        // be merged back into a single timeline.
+        BUFFER.with(|buffer| {
+            let mut buffer = buffer.borrow_mut();
+            if buffer.is_none() {
+                *buffer = Some(self.shared.register());
+            }
+
+            if let Some(ref buffer) = *buffer {
+                let appended = append(
+                    &mut buffer.lines.lock(),
+                    format_args!(
+                        "{} {}:{}: {}",
+                        cycles::rdtsc(),
+                        record.level(),
+                        record.location().module_path(),
+                        record.args()
+                    ),
+                    BUFFER_CAP,
+                );
+                if !appended {
+                    buffer.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+    }
+}
+
+// Implementation of methods on Shared.
+impl Shared {
+    // Allocates a buffer for the calling thread, and adds it to the list of buffers drained by
+    // the background thread.
+    fn register(&self) -> Arc<CoreBuffer> {
+        let buffer = Arc::new(CoreBuffer {
+            lines: Mutex::new(Vec::with_capacity(64 * 1024)),
+            dropped: AtomicUsize::new(0),
+            name: thread::current().name().unwrap_or("unnamed").to_string(),
+        });
+
+        self.buffers.lock().push(Arc::clone(&buffer));
+        buffer
+    }
+}
+
+/// Appends a record to a buffer if doing so will not grow it beyond a limit.
+///
+/// # Arguments
+///
+/// * `buffer`: The buffer to append to.
+/// * `line`:   The record. A newline is appended after it.
+/// * `cap`:    The maximum length of the buffer in bytes.
+///
+/// # Return
+///
+/// True if the record was appended. False if the buffer was full, in which case it is unchanged.
+fn append(buffer: &mut Vec<u8>, line: fmt::Arguments, cap: usize) -> bool {
+    if buffer.len() >= cap {
+        return false;
+    }
+
+    let len = buffer.len();
+    let written = buffer.write_fmt(line).is_ok() && buffer.write_all(b"\n").is_ok();
+    if !written || buffer.len() > cap {
+        buffer.truncate(len);
+        return false;
+    }
+
+    true
+}
+
+/// Installs the logger, filtering records according to RUST_LOG exactly like env_logger::init().
+/// Records go to stderr until `configure()` is called.
+pub fn init() -> Result<(), SetLoggerError> {
+    let mut builder = LogBuilder::new();
+    if let Ok(filter) = env::var("RUST_LOG") {
+        builder.parse(&filter);
+    }
+
+    set_logger(|max_level| {
+        let filter = builder.build();
+        max_level.set(filter.filter());
+
+        unsafe {
+            INIT.call_once(|| {
+                SHARED = Box::into_raw(Box::new(Shared {
+                    sink: AtomicUsize::new(SINK_CONSOLE),
+                    buffers: Mutex::new(Vec::new()),
+                }));
+            });
+
+            Box::new(CoreLogger {
+                filter: filter,
+                shared: &*SHARED,
+            })
+        }
+    })
+}
+
+/// Switches the sink that records are written to, starting up the background thread that
+/// flushes per-core buffers if required. Must be called after `init()`, and at most once.
+///
+/// # Arguments
+///
+/// * `sink`:     "console" (or empty) writes records to stderr as they are logged. "buffered"
+///               appends them to per-core buffers that are written to stderr by a background
+///               thread. "files" instead writes every core's buffer to it's own file.
+/// * `dir`:      The directory that per-core files are created in when `sink` is "files". A
+///               core's file is named after the order in which it first logged a record, i.e,
+///               "core.0.log", "core.1.log" etc.
+/// * `flush_ms`: The interval in milliseconds at which buffers are flushed. Zero defaults to
+///               ten milliseconds.
+///
+/// # Return
+///
+/// An error if `sink` is not recognized, or if `init()` was not called.
+pub fn configure(sink: &str, dir: &str, flush_ms: u64) -> io::Result<()> {
+    let sink = match sink {
+        "" | "console" => return Ok(()),
+        "buffered" => SINK_BUFFERED,
+        "files" => SINK_FILES,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown log sink {}", sink),
+            ))
+        }
+    };
+
+    let shared: &'static Shared = unsafe {
+        if SHARED.is_null() {
+            return Err(io::Error::new(io::ErrorKind::Other, "logger not initialized"));
+        }
+        &*SHARED
+    };
+
+    let dir = if dir.is_empty() { "." } else { dir }.to_string();
+    let interval = Duration::from_millis(if flush_ms == 0 { 10 } else { flush_ms });
+
+    thread::Builder::new()
+        .name("log-flush".to_string())
+        .spawn(move || flush(shared, sink, &dir, interval))?;
+
+    shared.sink.store(sink, Ordering::Relaxed);
+    Ok(())
+}
+
+// Runs on the background thread, periodically writing out every core's buffer.
+fn flush(shared: &'static Shared, sink: usize, dir: &str, interval: Duration) {
+    let mut files: Vec<Option<BufWriter<File>>> = Vec::new();
+    let mut spare: Vec<u8> = Vec::with_capacity(64 * 1024);
+
+    loop {
+        thread::sleep(interval);
+
+        let buffers: Vec<Arc<CoreBuffer>> = shared.buffers.lock().clone();
+        for (id, buffer) in buffers.iter().enumerate() {
+            // Swap the buffer out so that it's core can keep appending while it is written.
+            spare.clear();
+            mem::swap(&mut *buffer.lines.lock(), &mut spare);
+            let dropped = buffer.dropped.swap(0, Ordering::Relaxed);
+            if spare.is_empty() && dropped == 0 {
+                continue;
+            }
+
+            if sink == SINK_BUFFERED {
+                let stderr = io::stderr();
+                let mut out = stderr.lock();
+                let _ = out.write_all(&spare);
+                if dropped > 0 {
+                    let _ = writeln!(out, "log: dropped {} records on {}", dropped, buffer.name);
+                }
+                continue;
+            }
+
+            // Open a core's file the first time it has something to write.
+            while files.len() <= id {
+                files.push(None);
+            }
+            if files[id].is_none() {
+                let path = format!("{}/core.{}.log", dir, id);
+                files[id] = match File::create(&path) {
+                    Ok(file) => {
+                        let mut file = BufWriter::new(file);
+                        let _ = writeln!(file, "log: records from thread {}", buffer.name);
+                        Some(file)
+                    }
+
+                    Err(err) => {
+                        let _ = writeln!(&mut io::stderr(), "log: can't create {}: {}", path, err);
+                        None
+                    }
+                };
+            }
+
+            if let Some(ref mut file) = files[id] {
+                let _ = file.write_all(&spare);
+                if dropped > 0 {
+                    let _ = writeln!(file, "log: dropped {} records", dropped);
+                }
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+// This module contains simple unit tests for the per-core log buffers.
+#[cfg(test)]
+mod tests {
+    use super::append;
+
+    // This test verifies that records are appended upto the buffer's capacity, and that a record
+    // that doesn't fit leaves the buffer untouched.
+    #[test]
+    fn test_append() {
+        let mut buffer = Vec::new();
+
+        assert!(append(&mut buffer, format_args!("{} {}", "get", 1), 16));
+        assert_eq!(b"get 1\n", &buffer[..]);
+
+        assert!(!append(&mut buffer, format_args!("{}", "put 2 with a value"), 16));
+        assert_eq!(b"get 1\n", &buffer[..]);
+
+        assert!(append(&mut buffer, format_args!("{}", "put 2"), 16));
+        assert_eq!(b"get 1\nput 2\n", &buffer[..]);
+
+        // A full buffer refuses everything.
+        buffer.resize(16, b'x');
+        assert!(!append(&mut buffer, format_args!(""), 16));
+        assert_eq!(16, buffer.len());
+    }
+}
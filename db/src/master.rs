@@ -13,34 +13,143 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write};
 use std::mem::{size_of, transmute};
 use std::rc::Rc;
 use std::str::from_utf8;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use super::alloc::Allocator;
-use super::common::{TableId, TenantId, PACKET_UDP_LEN};
+use super::alloc::{now_ms, Allocator};
+use super::common::{TableId, TenantId, PACKET_IP_LEN, PACKET_MTU, PACKET_UDP_LEN};
+use super::config;
 use super::container::Container;
 use super::context::Context;
+use super::crypt::{self, Keys, SealedTask};
+use super::cycles;
 use super::ext::*;
-use super::native::Native;
+use super::memo::{Memoize, ResultCache};
+use super::migrate::{self, Batch, BulkRecord};
+use super::native::{Native, Responses};
+use super::rpc::{
+    self, header_len_ok, parse_rpc_header_len, parse_rpc_opcode, parse_rpc_stamp, parse_rpc_tenant,
+};
+use super::sched::Peers;
 use super::service::Service;
-use super::task::{Task, TaskPriority};
+use super::shadow::{ShadowStats, Shadowed};
+use super::snapshot::{self, ManifestEntry, Record, TableReader, TableWriter, Target};
+use super::stats::{CoreStatistics, ReadAmp, ReadClass, ReadStats, ServerStatistics};
+use super::table::{partition, Found, Table, WriteStamp, SNAPSHOT_RETRIES};
+use super::task::{CostClass, Task, TaskPriority};
 use super::tenant::Tenant;
+use super::throttle::TokenBucket;
+use super::wal::Wal;
 use super::wireformat::*;
 
 use e2d2::common::EmptyMetadata;
-use e2d2::headers::UdpHeader;
+use e2d2::headers::{EndOffset, IpHeader, UdpHeader};
 use e2d2::interface::Packet;
 
+use bytes::Bytes;
+
+use sandstorm::schema::Schema;
+
 use spin::RwLock;
 
 // The number of buckets in the `tenants` hashtable inside of Master.
 const TENANT_BUCKETS: usize = 32;
 
+// The maximum number of tenants whose request counts are returned by a get_statistics() RPC.
+// Keeps the response to a few packets.
+const MAX_STATISTICS_TENANTS: usize = 64;
+
+// The number of tenants every thread caches handles to. Must be a power of two. Refer to
+// `Master::get_tenant()`.
+const TENANT_CACHE_SLOTS: usize = 64;
+
+// The number of objects a tenant_admin() task frees between yields, so that tearing down a large
+// tenant or table does not hold up the requests queued behind it.
+const ADMIN_FREE_BATCH: usize = 1024;
+
+// Identifies every Master created by the process, so that a thread's cache of tenants is never
+// consulted on behalf of a Master other than the one that filled it.
+static NEXT_MASTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// A direct-mapped cache of recently looked up tenants, private to a thread. Entries are valid
+// only for the Master and generation of it's tenant map they were filled for.
+struct TenantCache {
+    master: usize,
+    generation: usize,
+    slots: Vec<Option<Arc<Tenant>>>,
+}
+
+thread_local!(static TENANT_CACHE: RefCell<TenantCache> = RefCell::new(TenantCache {
+    master: usize::max_value(),
+    generation: 0,
+    slots: vec![None; TENANT_CACHE_SLOTS],
+}));
+
+/// The scratch table that the bench() extension is meant to be invoked against. Created for every
+/// tenant the test extensions are loaded for, and kept clear of the tables workloads populate.
+pub const BENCH_TABLE: u64 = 0xbe7c;
+
+// The maximum number of catch-up rounds a migration runs before it starts redirecting the
+// tenant, and the number of dirty keys below which it stops running them early.
+const MIGRATE_ROUNDS: usize = 8;
+const MIGRATE_SETTLED: usize = 64;
+
+// The time in milliseconds a migration waits after it starts redirecting a tenant, so that
+// requests already handed to schedulers complete before the final catch-up round.
+const MIGRATE_DRAIN_MS: u64 = 10;
+
+// The number of bytes of records that fit on a single packet of a scan() response.
+const SCAN_PAYLOAD: usize = (PACKET_MTU - PACKET_IP_LEN) as usize - size_of::<ScanResponse>();
+
+/// Statistics gathered by a single pass of `Master::migrate_hot()` over the database.
+pub struct HotStats {
+    /// The number of sampled reads examined by the pass.
+    pub sampled: usize,
+
+    /// The number of sampled reads that were served from a hot arena.
+    pub hot: usize,
+
+    /// The number of distinct 4 KB pages touched by the sampled reads. Fewer pages per read
+    /// indicates better cache and TLB locality.
+    pub pages: usize,
+
+    /// The number of objects promoted into hot arenas by this pass.
+    pub promoted: usize,
+
+    /// The number of bytes copied into hot arenas by this pass.
+    pub bytes: usize,
+
+    /// The number of objects that were not promoted because the pass ran out of it's budget.
+    /// These are promoted by a later pass if they are still being read.
+    pub deferred: usize,
+
+    /// The number of tables that were left alone because enough of their reads were already
+    /// served from hot arenas.
+    pub settled: usize,
+}
+
+/// Statistics gathered by a single pass of `Master::self_check()` over the database.
+pub struct CheckStats {
+    /// The number of tables whose objects were sampled.
+    pub tables: usize,
+
+    /// The number of objects whose metadata was verified.
+    pub sampled: usize,
+
+    /// The number of objects and allocator invariants found to be inconsistent.
+    pub violations: usize,
+}
+
 /// The primary service in Sandstorm. Master is responsible managing tenants, extensions, and
 /// the database. It implements the Service trait, allowing it to generate schedulable tasks
 /// for data and extension related RPC requests.
@@ -49,6 +158,12 @@ pub struct Master {
     // will require a lookup on this map.
     tenants: [RwLock<HashMap<TenantId, Arc<Tenant>>>; TENANT_BUCKETS],
 
+    // Identifies this Master to the per-thread caches of tenants, and the generation of the
+    // tenant map. The generation is bumped whenever a tenant is added or removed, invalidating
+    // every cache. Refer to `get_tenant()`.
+    id: usize,
+    generation: AtomicUsize,
+
     // An extension manager maintaining state concerning extensions loaded into the system.
     // Required to retrieve and determine if an extension belongs to a particular tenant while
     // handling an invocation request.
@@ -56,6 +171,80 @@ pub struct Master {
 
     // Manager of the table heap. Required to allow writes to the database.
     heap: Arc<Allocator>,
+
+    // Keys of tenants whose RPC payloads are sealed. Requests from these tenants are opened
+    // before they are parsed, and responses to them are sealed before they are sent out.
+    keys: Keys,
+
+    // Results of read-only extensions that were declared cacheable when they were installed.
+    // Identical invocations of these extensions are serviced from here instead of re-running them.
+    results: Arc<ResultCache>,
+
+    // Set when the database is in read-only mode. While set, requests that would modify the
+    // database are rejected with `StatusReadOnly`.
+    read_only: AtomicBool,
+
+    // Token buckets limiting the rate at which put() requests can write to a tenant's table.
+    // Writes to tables without a bucket are never throttled.
+    throttles: HashMap<(TenantId, TableId), TokenBucket>,
+
+    // The maximum number of bytes a single pass of `migrate_hot()` copies into hot arenas, and
+    // the percentage of a table's sampled reads that must already be hot for the pass to leave
+    // the table alone. Zero disables either limit. Refer to `set_migrate_pacing()`.
+    migrate_budget: AtomicUsize,
+    migrate_target: AtomicUsize,
+
+    // The number of cores the server is dispatching and servicing requests on. Advertised to
+    // clients through the server_info() RPC.
+    num_cores: u32,
+
+    // Identifies the configuration the server is running with. Advertised to clients through
+    // the server_info() RPC. Refer to `ServerConfig::version()`.
+    config_version: u64,
+
+    // If true, extensions must declare a cost class in their manifest to be installed.
+    require_manifest: bool,
+
+    // The maximum number of invocations of an extension declared LONG that can be in flight at
+    // once. Zero removes the limit.
+    long_limit: usize,
+
+    // If true, get() and put() tasks carry an affinity hint derived from their key's partition.
+    key_affinity: bool,
+
+    // The objects touched, bytes read off the heap, and bytes returned to clients by get(),
+    // multiget(), and invoke() requests. Shared with the tasks servicing them.
+    reads: Arc<ReadAmp>,
+
+    // Tenants that were migrated to another server, along with the address (IPv4:Port) their
+    // requests are redirected to, and the number of them. Requests are only checked against the
+    // map while the count is non-zero.
+    moved: RwLock<HashMap<TenantId, Vec<u8>>>,
+    num_moved: AtomicUsize,
+
+    // The maximum number of objects a single scan() request can return. Zero removes the limit.
+    scan_limit: usize,
+
+    // The time in cycles that the tombstones of deleted keys are kept for. Zero disables
+    // tombstones. Refer to `set_tombstone_window()`.
+    tombstone_window: u64,
+
+    // Tables whose objects have a time-to-live, and a flag that is set while a task reclaiming
+    // their expired objects is in flight. Refer to `reclaim_expired()`.
+    expiring: Vec<(TenantId, TableId)>,
+    reclaiming: Arc<AtomicBool>,
+
+    // Every scheduler on the server, whose counters are summed up by the get_statistics() RPC.
+    // None until `set_schedulers()` is called.
+    schedulers: Option<Peers>,
+
+    // Values of at most this many bytes are kept inline in the tables of tenants created from
+    // here on. Zero keeps no value inline. Refer to `set_inline_values()`.
+    inline_values: usize,
+
+    // The tenant allowed to issue tenant_admin() requests. Zero rejects every such request.
+    // Refer to `set_admin_tenant()`.
+    admin_tenant: TenantId,
 }
 
 // Implementation of methods on Master.
@@ -66,6 +255,34 @@ impl Master {
     ///
     /// A Master service capable of creating schedulable tasks out of RPC requests.
     pub fn new() -> Master {
+        Master::with_heap(Allocator::new())
+    }
+
+    /// Creates and returns a new Master service whose table heap is backed by a persistent
+    /// segment. Objects committed by an earlier run can be added back with `recover()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the shared-memory or DAX file backing the heap segment.
+    /// * `size`: The size of the heap segment in bytes.
+    /// * `pmem`: If true, the file is on persistent memory, and every put() is flushed out to it
+    ///           before it is acknowledged.
+    ///
+    /// # Return
+    ///
+    /// A Master service, or an error if the heap segment could not be mapped.
+    pub fn persistent(path: &str, size: usize, pmem: bool) -> io::Result<Master> {
+        let heap = if pmem {
+            Allocator::pmem(path, size)?
+        } else {
+            Allocator::persistent(path, size)?
+        };
+
+        Ok(Master::with_heap(heap))
+    }
+
+    // Creates and returns a new Master service that allocates objects off a given heap.
+    fn with_heap(heap: Allocator) -> Master {
         Master {
             // Cannot use copy constructor because of the Arc<Tenant>.
             tenants: [
@@ -102,9 +319,416 @@ impl Master {
                 RwLock::new(HashMap::new()),
                 RwLock::new(HashMap::new()),
             ],
+            id: NEXT_MASTER.fetch_add(1, Ordering::Relaxed),
+            generation: AtomicUsize::new(0),
             extensions: ExtensionManager::new(),
-            heap: Arc::new(Allocator::new()),
+            heap: Arc::new(heap),
+            keys: HashMap::new(),
+            results: Arc::new(ResultCache::new()),
+            read_only: AtomicBool::new(false),
+            throttles: HashMap::new(),
+            migrate_budget: AtomicUsize::new(0),
+            migrate_target: AtomicUsize::new(0),
+            num_cores: 0,
+            config_version: 0,
+            require_manifest: false,
+            long_limit: 0,
+            key_affinity: false,
+            reads: Arc::new(ReadAmp::new()),
+            moved: RwLock::new(HashMap::new()),
+            num_moved: AtomicUsize::new(0),
+            scan_limit: 0,
+            tombstone_window: 0,
+            expiring: Vec::new(),
+            reclaiming: Arc::new(AtomicBool::new(false)),
+            schedulers: None,
+            inline_values: 0,
+            admin_tenant: 0,
+        }
+    }
+
+    /// Turns read-only mode on or off. While on, put() and install() requests, and invocations
+    /// of extensions that were not declared read-only (cacheable) at install, are rejected with
+    /// `StatusReadOnly`. Reads are serviced as usual. Meant for use during snapshots, migrations,
+    /// and while mitigating incidents.
+    ///
+    /// # Arguments
+    ///
+    /// * `read_only`: True if the database should stop accepting modifications.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Release);
+    }
+
+    /// Returns true if the database is in read-only mode.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Acquire)
+    }
+
+    /// Sets the keys of tenants whose RPC payloads are sealed. Must be called before Master
+    /// starts servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys`: A map from tenant identifier to the tenant's key.
+    pub fn set_keys(&mut self, keys: Keys) {
+        self.keys = keys;
+    }
+
+    /// Stores a checksum of the value with every object written from here on, and verifies it
+    /// when the object is read by get() and multiget() requests. Objects whose value does not
+    /// match are not served; the request fails with `StatusCorruptObject`. Must be called before
+    /// any object is added or recovered, and before Master is shared.
+    ///
+    /// # Arguments
+    ///
+    /// * `verify_every`: Checksums are verified on one in every this many reads. Zero or one
+    ///                   verifies every read.
+    pub fn set_checksums(&mut self, verify_every: u64) {
+        Arc::get_mut(&mut self.heap)
+            .expect("Checksums must be enabled before the heap is shared.")
+            .set_checksums(verify_every);
+    }
+
+    /// Keeps values of at most a given size inline in the buckets of tables, so that get()
+    /// requests on them are serviced without touching the object (refer to
+    /// `Table::with_inline()`). Applies to tenants created from here on, so it must be called
+    /// before any object is added or recovered. Values kept inline are not verified against
+    /// their checksums.
+    ///
+    /// # Arguments
+    ///
+    /// * `max`: The size in bytes of the largest value kept inline, capped at
+    ///          `INLINE_VALUE_MAX`. Zero keeps no value inline.
+    pub fn set_inline_values(&mut self, max: usize) {
+        self.inline_values = max;
+    }
+
+    /// Sets the tenant allowed to create and delete tenants, and to create and drop tables under
+    /// them, through the tenant_admin() RPC. Such requests from any other tenant are rejected
+    /// with `StatusUnauthorized`. If the admin tenant's payloads are sealed (refer to
+    /// `set_keys()`), it's key authenticates these requests. Must be called before Master starts
+    /// servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The admin tenant. Zero disables the tenant_admin() RPC.
+    pub fn set_admin_tenant(&mut self, tenant: TenantId) {
+        self.admin_tenant = tenant;
+    }
+
+    /// Appends every object committed to a table from here on, and every deletion, to a
+    /// write-ahead log. Must be called before Master starts servicing requests, and after the
+    /// log has been replayed through `replay_wal()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `wal`: The write-ahead log.
+    pub fn set_wal(&mut self, wal: Arc<Wal>) {
+        Arc::get_mut(&mut self.heap)
+            .expect("The write-ahead log must be set before the heap is shared.")
+            .set_wal(wal);
+    }
+
+    /// Restricts the persistent heap segment to the objects of a set of tables. Objects of other
+    /// tables are not persisted, and do not survive a restart. Must be called before Master
+    /// starts servicing requests, and before `recover()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tables`: The (tenant, table) pairs whose objects are persisted. Empty persists the
+    ///             objects of every table.
+    pub fn set_durable_tables(&mut self, tables: Vec<(TenantId, TableId)>) {
+        Arc::get_mut(&mut self.heap)
+            .expect("Durable tables must be set before the heap is shared.")
+            .set_durable_tables(tables);
+    }
+
+    /// This method returns the number of reads whose values were verified against their
+    /// checksums, and the number of those that were found to be corrupted.
+    pub fn checksum_stats(&self) -> (usize, usize) {
+        self.heap.checksum_stats()
+    }
+
+    /// This method returns the data moved by every type of request that reads off the heap,
+    /// from which the read amplification of extensions can be compared against that of plain
+    /// get() and multiget() requests.
+    pub fn read_stats(&self) -> Vec<ReadStats> {
+        self.reads.stats()
+    }
+
+    /// Sets the number of cores advertised to clients through the server_info() RPC. Must be
+    /// called before Master starts servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_cores`: The number of cores the server is dispatching requests on.
+    pub fn set_num_cores(&mut self, num_cores: u32) {
+        self.num_cores = num_cores;
+    }
+
+    /// Sets the version of the server's configuration advertised to clients through the
+    /// server_info() RPC, along with the commit and features the server was built with. Must be
+    /// called before Master starts servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `version`: The version of the configuration. Refer to `ServerConfig::version()`.
+    pub fn set_config_version(&mut self, version: u64) {
+        self.config_version = version;
+    }
+
+    /// Hands Master the schedulers requests are serviced on, whose counters are summed up and
+    /// returned by the get_statistics() RPC. Schedulers added to the list later on are picked up
+    /// too. Must be called before Master starts servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `schedulers`: Every scheduler on the server.
+    pub fn set_schedulers(&mut self, schedulers: Peers) {
+        self.schedulers = Some(schedulers);
+    }
+
+    /// Decides how the manifests extensions are installed with are enforced. Must be called before
+    /// Master starts servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `required`:   If true, installs that do not declare a cost class are rejected with
+    ///                 `StatusMalformedRequest`.
+    /// * `long_limit`: The maximum number of invocations of an extension declared LONG that can
+    ///                 be in flight at once. Invocations beyond it are rejected with
+    ///                 `StatusThrottled`. Zero removes the limit.
+    pub fn set_manifest_policy(&mut self, required: bool, long_limit: usize) {
+        self.require_manifest = required;
+        self.long_limit = long_limit;
+    }
+
+    /// Attaches an affinity hint derived from the partition of the key being looked up or written
+    /// to the tasks created for get() and put() requests, so that schedulers stealing work keep
+    /// requests on a partition on the same core where they can. Must be called before Master
+    /// starts servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: True if tasks should carry affinity hints.
+    pub fn set_key_affinity(&mut self, enabled: bool) {
+        self.key_affinity = enabled;
+    }
+
+    /// Caps the number of objects a single scan() request can return, irrespective of the limit
+    /// on the request. Must be called before Master starts servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit`: The maximum number of objects returned per scan(). Zero removes the cap.
+    pub fn set_scan_limit(&mut self, limit: usize) {
+        self.scan_limit = limit;
+    }
+
+    /// Makes deletes leave a tombstone behind, so that put() requests and writes by extensions
+    /// that were issued before a key was deleted, but were delayed or replayed until after it,
+    /// are rejected with `StatusStaleWrite` instead of resurrecting the key. Writes are ordered
+    /// against deletes by the time the server received the requests issuing them, and the stamps
+    /// on requests are only compared if they were issued by the same client (refer to
+    /// `WriteStamp::precedes()`). Tombstones are kept for a window after the server received the
+    /// delete, and are removed by `purge_tombstones()` once it has passed. Must be called before
+    /// Master starts servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `window_ms`: The time in milliseconds tombstones are kept for. Zero disables tombstones.
+    pub fn set_tombstone_window(&mut self, window_ms: u64) {
+        self.tombstone_window = (cycles::cycles_per_second() * window_ms) / 1000;
+    }
+
+    /// Removes the tombstones of keys that were deleted longer ago than the window set through
+    /// `set_tombstone_window()`, across every tenant's tables. Meant to be called periodically.
+    ///
+    /// # Return
+    ///
+    /// The number of tombstones that were removed, and the number that are still kept.
+    pub fn purge_tombstones(&self) -> (usize, usize) {
+        let now = cycles::rdtsc();
+        let before = if now > self.tombstone_window {
+            now - self.tombstone_window
+        } else {
+            0
+        };
+
+        let mut purged = 0;
+        let mut kept = 0;
+        for bucket in self.tenants.iter() {
+            // Clone out the tenants so that the bucket isn't locked while tables are purged.
+            let tenants: Vec<Arc<Tenant>> = bucket.read().values().cloned().collect();
+
+            for (_, table) in tenants.iter().flat_map(|tenant| tenant.tables()) {
+                purged += table.purge_tombstones(before);
+                kept += table.tombstones();
+            }
+        }
+
+        (purged, kept)
+    }
+
+    // Returns the stamp that orders a write or delete received just now against deletes of the
+    // same key, or None if tombstones are not kept.
+    fn write_stamp(&self, client: u32, rpc_stamp: u64) -> Option<WriteStamp> {
+        if self.tombstone_window == 0 {
+            return None;
+        }
+
+        Some(WriteStamp {
+            arrival: cycles::rdtsc(),
+            client: client,
+            stamp: rpc_stamp,
+        })
+    }
+
+    /// Gives objects written to a table from here on a time-to-live. Once it elapses, get(),
+    /// multiget(), and scan() requests and extensions treat the object as missing, and it is
+    /// removed by the task returned by `reclaim_expired()`. Changes the layout of every object,
+    /// so it must be called before any object is added or recovered, and before Master is shared.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant_id`: The tenant the table belongs to.
+    /// * `table_id`:  The table whose objects expire.
+    /// * `ttl_ms`:    The time in milliseconds after being written that objects expire.
+    pub fn set_table_ttl(&mut self, tenant_id: TenantId, table_id: TableId, ttl_ms: u64) {
+        Arc::get_mut(&mut self.heap)
+            .expect("Expiration must be enabled before the heap is shared.")
+            .set_ttl(tenant_id, table_id, ttl_ms);
+        self.expiring.push((tenant_id, table_id));
+    }
+
+    /// Limits the memory the objects of a group of tenants can occupy together. Once the group is
+    /// over it's quota, put(), cas() and move_key() requests from it's tenants are rejected with
+    /// `StatusQuotaExceeded`, and extensions invoked by them fail to allocate. Deletes are still
+    /// allowed, and free up room right away. Objects already in the tenants' tables, such as
+    /// recovered ones, count against the quota. Must be called before Master is shared.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenants`: The tenants in the group. A tenant belongs to at most one group; tenants
+    ///              already in one are left there.
+    /// * `limit`:   The number of bytes the objects of the group's tenants can occupy.
+    pub fn set_group_quota(&mut self, tenants: Vec<TenantId>, limit: usize) {
+        let quota = Arc::get_mut(&mut self.heap)
+            .expect("Quotas must be set before the heap is shared.")
+            .set_group_quota(&tenants, limit);
+
+        // Tenants created from here on are handed their group's quota by `new_tenant()`. Those
+        // already in another group keep that group's quota.
+        for tenant_id in tenants.iter() {
+            let joined = self
+                .heap
+                .group_quota(*tenant_id)
+                .map_or(false, |group| Arc::ptr_eq(&group, &quota));
+            if !joined {
+                continue;
+            }
+
+            if let Some(tenant) = self.get_tenant(*tenant_id) {
+                tenant.set_quota(Arc::clone(&quota));
+            }
+        }
+    }
+
+    /// Returns the number of bytes used by every group of tenants set through
+    /// `set_group_quota()`, it's quota, and the number of allocations that were refused to it
+    /// so far, indexed by group.
+    pub fn group_stats(&self) -> Vec<(usize, usize, usize)> {
+        self.heap.group_stats()
+    }
+
+    /// Returns a background task that sweeps the tables set through `set_table_ttl()` one bucket
+    /// at a time, removing the objects that have expired. The task yields after every bucket, and
+    /// only runs while it's core has no requests to run. Meant to be enqueued periodically.
+    ///
+    /// # Return
+    ///
+    /// The task, or None if no table has a time-to-live, or if the task returned by an earlier
+    /// call has not completed yet.
+    pub fn reclaim_expired(&self) -> Option<Box<Task>> {
+        if self.expiring.is_empty() || self.reclaiming.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+
+        let tables: Vec<Arc<Table>> = self
+            .expiring
+            .iter()
+            .filter_map(|&(tenant_id, table_id)| {
+                self.get_tenant(tenant_id)
+                    .and_then(|tenant| tenant.get_table(table_id))
+            }).collect();
+        let buckets = tables.first().map_or(0, |table| table.buckets());
+        let alloc = self.heap.clone();
+        let reclaiming = Arc::clone(&self.reclaiming);
+
+        let gen = Box::new(move || {
+            let mut removed = 0;
+            for idx in 0..tables.len() * buckets {
+                removed += tables[idx / buckets].expire(idx % buckets, |object| {
+                    alloc.is_expired(object)
+                });
+                yield 0;
+            }
+
+            if removed > 0 {
+                debug!("Reclaimed {} expired objects", removed);
+            }
+            reclaiming.store(false, Ordering::Relaxed);
+
+            return None;
+        });
+
+        Some(Box::new(Native::new(TaskPriority::BACKGROUND, gen)))
+    }
+
+    /// Maintains an ordered index over a table, so that it can be scanned with the scan() RPC.
+    /// The tenant and table are created if they do not exist yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant_id`: The tenant the table belongs to.
+    /// * `table_id`:  The table to be indexed.
+    pub fn order_table(&self, tenant_id: TenantId, table_id: TableId) {
+        self.get_or_create_table(tenant_id, table_id).order();
+    }
+
+    // Returns the affinity hint for a task on a key, or None if hints are disabled.
+    fn key_affinity(&self, key: &[u8]) -> Option<usize> {
+        if self.key_affinity && !key.is_empty() {
+            Some(partition(key))
+        } else {
+            None
+        }
+    }
+
+    /// Limits the rate at which put() requests can write to a table. Writes above the limit are
+    /// rejected with `StatusThrottled` before a task is created for them. Must be called before
+    /// Master starts servicing requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant_id`: Identifier of the tenant owning the table.
+    /// * `table_id`:  Identifier of the table.
+    /// * `rate`:      The sustained number of writes admitted every second. Zero removes the limit.
+    /// * `burst`:     The number of writes that can be admitted back to back.
+    pub fn set_write_limit(
+        &mut self,
+        tenant_id: TenantId,
+        table_id: TableId,
+        rate: u64,
+        burst: u64,
+    ) {
+        if rate == 0 {
+            self.throttles.remove(&(tenant_id, table_id));
+            return;
         }
+
+        let bucket = TokenBucket::new(rate, burst, cycles::cycles_per_second());
+        self.throttles.insert((tenant_id, table_id), bucket);
     }
 
     /// Adds a tenant and a table full of objects.
@@ -118,7 +742,7 @@ impl Master {
     /// * `num`:       The number of objects to be added to the data table.
     pub fn fill_test(&self, tenant_id: TenantId, table_id: TableId, num: u32) {
         // Create a tenant containing the table.
-        let tenant = Tenant::new(tenant_id);
+        let tenant = self.new_tenant(tenant_id);
         tenant.create_table(table_id);
 
         let table = tenant
@@ -138,6 +762,7 @@ impl Master {
             let obj = self.heap
                 .object(tenant_id, table_id, &key, &val)
                 .expect("Failed to create test object.");
+            let obj = self.heap.commit(obj.0, obj.1).expect("Failed to commit test object.");
             table.put(obj.0, obj.1);
         }
 
@@ -145,7 +770,8 @@ impl Master {
         self.insert_tenant(tenant);
     }
 
-    /// Populates the TAO dataset.
+    /// Populates the TAO dataset. Every tenant gets it's own graph, whose object ids are scoped
+    /// to the tenant; the upper four bytes of an id are the tenant's identifier.
     ///
     /// # Arguments
     ///
@@ -155,7 +781,7 @@ impl Master {
     pub fn fill_tao(&self, tenant_id: TenantId, num: u32) {
         // Create a tenant containing two tables, one for objects, and one for
         // associations.
-        let tenant = Tenant::new(tenant_id);
+        let tenant = self.new_tenant(tenant_id);
         tenant.create_table(1); // Holds tao objects.
         tenant.create_table(2); // Holds tao assocs.
 
@@ -163,7 +789,9 @@ impl Master {
         let table = tenant.get_table(1).expect("Failed to init test table.");
 
         // Objects are identified by an 8 byte key.
+        let scope: [u8; 4] = unsafe { transmute(tenant_id.to_le()) };
         let mut key = vec![0; 8];
+        &key[4..8].copy_from_slice(&scope);
         // Objects contain a 4 byte otype, 8 byte version, 4 byte update time, and
         // 16 byte payload, all of which are zero.
         let val = vec![0; 32];
@@ -176,6 +804,7 @@ impl Master {
             let obj = self.heap
                 .object(tenant_id, 1, &key, &val)
                 .expect("Failed to create test object.");
+            let obj = self.heap.commit(obj.0, obj.1).expect("Failed to commit test object.");
             table.put(obj.0, obj.1);
         }
 
@@ -185,6 +814,8 @@ impl Master {
         // Assocs are identified by an 8 byte object 1 id, 2 byte association
         // type (always zero), and 8 byte object 2 id.
         let mut key = vec![0; 18];
+        &key[4..8].copy_from_slice(&scope);
+        &key[14..18].copy_from_slice(&scope);
         // Assocs have a 22 byte value (all zeros).
         let val = vec![0; 22];
 
@@ -200,13 +831,16 @@ impl Master {
             for a in 1u32..5u32 {
                 let temp: [u8; 4] = unsafe { transmute(((i + a) % num).to_le()) };
                 &key[10..14].copy_from_slice(&temp);
-                list.extend_from_slice(&temp);
-                list.extend_from_slice(&[0; 12]);
+                list.extend_from_slice(&key[10..18]);
+                list.extend_from_slice(&[0; 8]);
 
                 // Add this assoc to the assoc table.
                 let obj = self.heap
                     .object(tenant_id, 2, &key, &val)
                     .expect("Failed to create test object.");
+                let obj = self.heap
+                    .commit(obj.0, obj.1)
+                    .expect("Failed to commit test object.");
                 table.put(obj.0, obj.1);
             }
 
@@ -214,6 +848,7 @@ impl Master {
             let obj = self.heap
                 .object(tenant_id, 2, &key[0..10], &list)
                 .expect("Failed to create test object.");
+            let obj = self.heap.commit(obj.0, obj.1).expect("Failed to commit test object.");
             table.put(obj.0, obj.1);
         }
 
@@ -233,7 +868,7 @@ impl Master {
     pub fn fill_aggregate(&self, tenant_id: TenantId, table_id: TableId, num: u32) {
         // One table for the tenant. Both, objects and indirection lists will be
         // stored in here.
-        let tenant = Tenant::new(tenant_id);
+        let tenant = self.new_tenant(tenant_id);
         tenant.create_table(table_id);
 
         let table = tenant
@@ -270,6 +905,7 @@ impl Master {
             let obj = self.heap
                 .object(tenant_id, table_id, &key, &val)
                 .expect("Failed to create test object.");
+            let obj = self.heap.commit(obj.0, obj.1).expect("Failed to commit test object.");
             table.put(obj.0, obj.1);
         }
 
@@ -285,13 +921,16 @@ impl Master {
             let obj = self.heap
                 .object(tenant_id, table_id, &key, &val)
                 .expect("Failed to create test object.");
+            let obj = self.heap.commit(obj.0, obj.1).expect("Failed to commit test object.");
             table.put(obj.0, obj.1);
         }
 
         self.insert_tenant(tenant);
     }
 
-    /// Loads the get(), put(), tao(), and bad() extensions.
+    /// Loads the get(), put(), tao(), bad(), long(), aggregate(), and bench() extensions. All but
+    /// bad() are loaded with a manifest declaring their cost class and read-only-ness. Also
+    /// creates the `BENCH_TABLE` scratch table bench() is meant to be invoked against.
     ///
     /// # Arguments
     ///
@@ -299,19 +938,22 @@ impl Master {
     pub fn load_test(&self, tenant: TenantId) {
         // Load the get() extension.
         let name = "../ext/get/target/release/libget.so";
-        if self.extensions.load(name, tenant, "get") == false {
+        let manifest = Manifest::new(CostClass::SHORT, true);
+        if self.extensions.load_with_manifest(name, tenant, "get", manifest) == false {
             panic!("Failed to load get() extension.");
         }
 
         // Load the put() extension.
         let name = "../ext/put/target/release/libput.so";
-        if self.extensions.load(name, tenant, "put") == false {
+        let manifest = Manifest::new(CostClass::SHORT, false);
+        if self.extensions.load_with_manifest(name, tenant, "put", manifest) == false {
             panic!("Failed to load put() extension.");
         }
 
         // Load the tao() extension.
         let name = "../ext/tao/target/release/libtao.so";
-        if self.extensions.load(name, tenant, "tao") == false {
+        let manifest = Manifest::new(CostClass::MEDIUM, false);
+        if self.extensions.load_with_manifest(name, tenant, "tao", manifest) == false {
             panic!("Failed to load tao() extension.");
         }
 
@@ -323,15 +965,25 @@ impl Master {
 
         // Load the long() extension.
         let name = "../ext/long/target/release/liblong.so";
-        if self.extensions.load(name, tenant, "long") == false {
+        let manifest = Manifest::new(CostClass::LONG, true);
+        if self.extensions.load_with_manifest(name, tenant, "long", manifest) == false {
             panic!("Failed to load long() extension.");
         }
 
         // Load the aggregate() extension.
         let name = "../ext/aggregate/target/release/libaggregate.so";
-        if self.extensions.load(name, tenant, "aggregate") == false {
+        let manifest = Manifest::new(CostClass::MEDIUM, true);
+        if self.extensions.load_with_manifest(name, tenant, "aggregate", manifest) == false {
             panic!("Failed to load aggregate() extension.");
         }
+
+        // Load the bench() extension, along with the scratch table it measures against.
+        self.get_or_create_table(tenant, BENCH_TABLE);
+        let name = "../ext/bench/target/release/libbench.so";
+        let manifest = Manifest::new(CostClass::MEDIUM, false);
+        if self.extensions.load_with_manifest(name, tenant, "bench", manifest) == false {
+            panic!("Failed to load bench() extension.");
+        }
     }
 
     /// Loads the get(), put(), and tao() extensions once, and shares them across multiple tenants.
@@ -364,6 +1016,11 @@ impl Master {
 
     /// This method returns a handle to a tenant if it exists.
     ///
+    /// Tenants are looked up on practically every request, so every thread keeps a small cache
+    /// of handles to the tenants it looked up recently, and only falls back to the shared map
+    /// (and it's locks) on a miss. Caches are invalidated whenever a tenant is added or removed;
+    /// a removed tenant's handle is dropped by a thread's cache on the thread's next lookup.
+    ///
     /// # Arguments
     ///
     /// * `tenant_id`: The identifier for the tenant to be returned.
@@ -372,49 +1029,508 @@ impl Master {
     ///
     /// An atomic reference counted handle to the tenant if it exists.
     fn get_tenant(&self, tenant_id: TenantId) -> Option<Arc<Tenant>> {
-        // Acquire a read lock. The bucket is determined by the least significant byte of the
-        // tenant id.
-        let bucket = (tenant_id & 0xff) as usize & (TENANT_BUCKETS - 1);
-        let map = self.tenants[bucket].read();
+        let generation = self.generation.load(Ordering::Acquire);
+        let slot = tenant_id as usize & (TENANT_CACHE_SLOTS - 1);
+
+        TENANT_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            if cache.master != self.id || cache.generation != generation {
+                cache.master = self.id;
+                cache.generation = generation;
+                for entry in cache.slots.iter_mut() {
+                    *entry = None;
+                }
+            }
+
+            if let Some(ref tenant) = cache.slots[slot] {
+                if tenant.id() == tenant_id {
+                    return Some(Arc::clone(tenant));
+                }
+            }
 
-        // Lookup, and return the tenant if it exists.
-        map.get(&tenant_id)
-            .and_then(|tenant| Some(Arc::clone(tenant)))
+            // Acquire a read lock. The bucket is determined by the least significant byte of
+            // the tenant id.
+            let bucket = (tenant_id & 0xff) as usize & (TENANT_BUCKETS - 1);
+            let tenant = self.tenants[bucket].read().get(&tenant_id).cloned();
+            if tenant.is_some() {
+                cache.slots[slot] = tenant.clone();
+            }
+
+            tenant
+        })
     }
 
-    /// This method adds a tenant to Master.
+    /// Paces hot object migration, so that it does not interfere with request processing. Can be
+    /// called at any time; takes effect from the next pass of `migrate_hot()`.
     ///
     /// # Arguments
     ///
-    /// * `tenant`: The tenant to be added.
-    fn insert_tenant(&self, tenant: Tenant) {
-        // Acquire a write lock. The bucket is determined by the least significant byte of the
-        // tenant id.
-        let bucket = (tenant.id() & 0xff) as usize & (TENANT_BUCKETS - 1);
-        let mut map = self.tenants[bucket].write();
+    /// * `max_bytes`:  The maximum number of bytes a pass copies into hot arenas. Zero removes
+    ///                 the limit.
+    /// * `target_pct`: A table is left alone by a pass if at least this percentage of it's
+    ///                 sampled reads were served from hot arenas. Zero removes the target.
+    pub fn set_migrate_pacing(&self, max_bytes: usize, target_pct: usize) {
+        self.migrate_budget.store(max_bytes, Ordering::Relaxed);
+        self.migrate_target.store(target_pct.min(100), Ordering::Relaxed);
+    }
 
-        // Insert the tenant and return.
-        map.insert(tenant.id(), Arc::new(tenant));
+    /// This method migrates objects that were recently read into dense hot arenas, so that
+    /// frequently read objects share cache and TLB pages. Recently read objects are identified
+    /// from the reads sampled by each table since the previous call to this method; tables only
+    /// start sampling reads once this method is first called on them. The amount of work done
+    /// by a pass is limited by `set_migrate_pacing()`.
+    ///
+    /// # Return
+    ///
+    /// Statistics on the sampled reads and the number of objects that were promoted.
+    pub fn migrate_hot(&self) -> HotStats {
+        let mut stats = HotStats {
+            sampled: 0,
+            hot: 0,
+            pages: 0,
+            promoted: 0,
+            bytes: 0,
+            deferred: 0,
+            settled: 0,
+        };
+
+        let budget = self.migrate_budget.load(Ordering::Relaxed);
+        let target = self.migrate_target.load(Ordering::Relaxed);
+
+        let mut pages = HashSet::new();
+
+        // Arenas whose objects were all dropped since the last pass must not be mistaken for
+        // hot once their memory is reused.
+        self.heap.reclaim_hot();
+
+        for bucket in self.tenants.iter() {
+            // Clone out the tenants so that the bucket isn't locked during migration.
+            let tenants: Vec<Arc<Tenant>> = bucket.read().values().cloned().collect();
+
+            for (_, table) in tenants.iter().flat_map(|tenant| tenant.tables()) {
+                // An object can be sampled multiple times. Promote it only once.
+                let mut seen = HashSet::new();
+
+                // Tables only sample reads once they are migrated, so a table's first pass finds
+                // nothing to do.
+                table.set_sampling(true);
+                let samples = table.take_samples();
+                let hot = samples.iter().filter(|object| self.heap.is_hot(object)).count();
+                stats.sampled += samples.len();
+                stats.hot += hot;
+
+                // Leave the table alone if enough of it's reads are already served hot.
+                let settled = target > 0 && hot * 100 >= samples.len() * target;
+                if settled && !samples.is_empty() {
+                    stats.settled += 1;
+                }
+
+                for object in samples.into_iter() {
+                    let addr = object.as_ptr() as usize;
+                    pages.insert(addr >> 12);
+
+                    if settled || self.heap.is_hot(&object) {
+                        continue;
+                    }
+
+                    if !seen.insert(addr) {
+                        continue;
+                    }
+
+                    // Stop copying once the pass has used up it's budget.
+                    if budget > 0 && stats.bytes + object.len() > budget {
+                        stats.deferred += 1;
+                        continue;
+                    }
+
+                    // Copy the object into a hot arena, and replace the original in the table
+                    // unless it was concurrently updated.
+                    let swapped = self.heap
+                        .promote(&object)
+                        .and_then(|copy| self.heap.resolve(copy.clone()).map(|(k, _)| (k, copy)))
+                        .map_or(false, |(key, copy)| table.swap(key, &object, copy));
+
+                    if swapped {
+                        stats.promoted += 1;
+                        stats.bytes += object.len();
+                    }
+                }
+            }
+        }
+
+        stats.pages = pages.len();
+        return stats;
     }
 
-    /// Handles the Get() RPC request.
+    /// This method samples objects from every table and verifies that their metadata agrees
+    /// with the tenant, table, and key they were found under, followed by the allocator's own
+    /// invariants. Every violation is logged, so that memory corrupted by a buggy extension is
+    /// noticed before it propagates any further.
     ///
-    /// A hash table lookup is performed on a supplied tenant id, table id, and key. If successfull,
-    /// the result of the lookup is written into a response packet, and the response header is
-    /// updated. In the case of a failure, the response header is updated with a status indicating
-    /// the reason for the failure.
+    /// # Arguments
+    ///
+    /// * `samples`: The maximum number of objects to verify per table.
+    ///
+    /// # Return
+    ///
+    /// Statistics on the number of objects verified and violations found.
+    pub fn self_check(&self, samples: usize) -> CheckStats {
+        let mut stats = CheckStats {
+            tables: 0,
+            sampled: 0,
+            violations: 0,
+        };
+
+        // Start sampling at a different bucket on every pass.
+        let seed = cycles::rdtsc() as usize;
+
+        for bucket in self.tenants.iter() {
+            // Clone out the tenants so that the bucket isn't locked during the check.
+            let tenants: Vec<Arc<Tenant>> = bucket.read().values().cloned().collect();
+
+            for tenant in tenants.iter() {
+                for (table_id, table) in tenant.tables() {
+                    stats.tables += 1;
+
+                    for (key, object) in table.sample(samples, seed) {
+                        stats.sampled += 1;
+
+                        if let Err(violation) =
+                            self.heap.verify(tenant.id(), table_id, &key, &object)
+                        {
+                            stats.violations += 1;
+                            warn!(
+                                "Self-check: tenant {} table {} object at {:p}: {}",
+                                tenant.id(),
+                                table_id,
+                                object.as_ptr(),
+                                violation
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        for violation in self.heap.check() {
+            stats.violations += 1;
+            warn!("Self-check: allocator: {}", violation);
+        }
+
+        return stats;
+    }
+
+    /// This method returns the total number of objects promoted into hot arenas, and the number
+    /// of bytes that they occupy.
+    pub fn hot_stats(&self) -> (usize, usize) {
+        self.heap.hot_stats()
+    }
+
+    /// This method writes out a snapshot of the database. A full snapshot contains every object
+    /// in the database. An incremental snapshot contains only objects that were written or
+    /// deleted since the previous snapshot, with tombstones for deleted objects. Tables that were
+    /// created after the previous snapshot are written out in full in both cases. Snapshots are
+    /// fuzzy; objects concurrently written while a snapshot is in progress are picked up by the
+    /// next one.
     ///
     /// # Arguments
     ///
-    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
-    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    /// * `dir`:   The directory to write the snapshot into. Must already exist.
+    /// * `epoch`: The epoch of the snapshot. Must be larger than that of previous snapshots in
+    ///            the same directory.
+    /// * `full`:  True if the snapshot should contain every object.
     ///
     /// # Return
     ///
-    /// A Native task that can be scheduled by the database. In the case of an error, the passed
-    /// in request and response packets are returned with the response status appropriately set.
-    #[allow(unreachable_code)]
-    #[allow(unused_assignments)]
+    /// The entry for the snapshot on the directory's manifest.
+    pub fn snapshot(&self, dir: &str, epoch: u64, full: bool) -> io::Result<ManifestEntry> {
+        self.snapshot_to(snapshot::Writer::new(dir, epoch, full)?)
+    }
+
+    /// This method writes out a snapshot of the database to an arbitrary target, ex: a stream to
+    /// a backup (refer to `backup::Stream`). Refer to `snapshot()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: The writer for the snapshot, with it's header already written out.
+    ///
+    /// # Return
+    ///
+    /// The entry for the snapshot on the target's manifest.
+    pub fn snapshot_to<T>(&self, mut writer: snapshot::Writer<T>) -> io::Result<ManifestEntry>
+    where
+        T: Target,
+    {
+        let full = writer.full();
+
+        for bucket in self.tenants.iter() {
+            // Clone out the tenants so that the bucket isn't locked during the snapshot.
+            let tenants: Vec<Arc<Tenant>> = bucket.read().values().cloned().collect();
+
+            for tenant in tenants.iter() {
+                for (table_id, table) in tenant.tables() {
+                    // Changes are recorded from here on. Anything recorded before this point is
+                    // covered by the snapshot being written out now.
+                    let tracked = table.track_changes();
+                    let changed = table.take_changed();
+
+                    if full || !tracked {
+                        let mut res = Ok(());
+                        table.scan(|object| {
+                            if res.is_ok() {
+                                res = writer.object(&object);
+                            }
+                        });
+                        res?;
+                        continue;
+                    }
+
+                    for key in changed.iter() {
+                        match table.get(key) {
+                            Some(object) => writer.object(&object)?,
+                            None => writer.tombstone(tenant.id(), table_id, key)?,
+                        }
+                    }
+                }
+            }
+        }
+
+        writer.finish()
+    }
+
+    /// This method restores the database from a snapshot directory, reading the most recent
+    /// full snapshot and every incremental snapshot taken after it. Every record is verified
+    /// against it's checksum. Tenants and tables are created as required.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir`: The snapshot directory.
+    ///
+    /// # Return
+    ///
+    /// The epoch of the most recent snapshot that was restored, or None if the directory did
+    /// not contain any snapshots.
+    pub fn restore(&self, dir: &str) -> io::Result<Option<u64>> {
+        let chain = snapshot::restore_chain(&snapshot::read_manifest(dir)?);
+
+        for entry in chain.iter() {
+            let mut reader = snapshot::Reader::new(dir, entry)?;
+
+            while let Some(record) = reader.next()? {
+                match record {
+                    Record::Object(object) => self.restore_object(object)?,
+
+                    Record::Tombstone(tenant, table, key) => {
+                        if let Some(t) = self.get_tenant(tenant).and_then(|t| t.get_table(table)) {
+                            t.delete(&key);
+                            self.heap.commit_delete(tenant, table, &key);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(chain.last().map(|entry| entry.epoch))
+    }
+
+    /// This method adds an object read out of a snapshot to the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: The object, laid out exactly as it was by the allocator.
+    fn restore_object(&self, object: Vec<u8>) -> io::Result<()> {
+        // Read the tenant, table, and key length off the object's metadata.
+        let meta = self.heap.meta_size();
+        if object.len() < meta {
+            return Err(Error::new(ErrorKind::InvalidData, "Snapshot object too short."));
+        }
+
+        let mut t: [u8; 4] = [0; 4];
+        let mut id: [u8; 8] = [0; 8];
+        let mut k: [u8; 2] = [0; 2];
+        t.copy_from_slice(&object[0..4]);
+        id.copy_from_slice(&object[4..12]);
+        k.copy_from_slice(&object[12..14]);
+        let tenant_id: TenantId = u32::from_le(unsafe { transmute(t) });
+        let table_id: TableId = u64::from_le(unsafe { transmute(id) });
+        let key_len = u16::from_le(unsafe { transmute(k) }) as usize;
+
+        if object.len() < meta + key_len {
+            return Err(Error::new(ErrorKind::InvalidData, "Snapshot object too short."));
+        }
+
+        // The object already has the layout expected by the table. Add it as is, making sure
+        // that objects written from here on are at a newer version.
+        self.heap.observe(&object);
+        let table = self.get_or_create_table(tenant_id, table_id);
+        let object = Bytes::from(object);
+        let (key, object) = self.heap
+            .commit(object.slice(meta, meta + key_len), object)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "Write-ahead log is full."))?;
+        table.put(key, object);
+
+        Ok(())
+    }
+
+    /// This method rebuilds tables from the objects in the persistent heap segment, allowing a
+    /// restarted server to pick up where an earlier run left off without re-loading data.
+    /// Objects are added back in the order they were committed, so the most recently committed
+    /// version of every key wins. Tenants and tables are created as required.
+    ///
+    /// # Return
+    ///
+    /// The number of objects that were added back, or an error if the segment was malformed.
+    /// Zero if the table heap is not backed by a persistent segment.
+    pub fn recover(&self) -> io::Result<usize> {
+        let mut objects = 0;
+
+        self.heap.recover(|tenant_id, table_id, key, object| {
+            let table = self.get_or_create_table(tenant_id, table_id);
+            match object {
+                Some(object) => {
+                    table.put(key, object);
+                    objects += 1;
+                }
+
+                None => {
+                    table.delete(&key);
+                }
+            }
+        })?;
+
+        Ok(objects)
+    }
+
+    /// This method rebuilds tables from the objects and deletions in a write-ahead log written by
+    /// an earlier run. Records are applied in the order they were originally committed, so the
+    /// last write to a key wins. Tenants and tables are created as required. Objects are copied
+    /// out of the log into the table heap.
+    ///
+    /// # Arguments
+    ///
+    /// * `wal`: The write-ahead log.
+    ///
+    /// # Return
+    ///
+    /// The number of objects written into tables, or an error if the log was malformed.
+    pub fn replay_wal(&self, wal: &Wal) -> io::Result<usize> {
+        let mut objects = 0;
+        let mut failed = false;
+
+        let now = now_ms();
+        self.heap.replay(wal, |tenant_id, table_id, key, val, expires| {
+            let table = self.get_or_create_table(tenant_id, table_id);
+            match val {
+                // Objects that expired while the server was down are left out. The rest keep the
+                // expiration time they were written with.
+                Some(_) if expires != 0 && expires <= now => {
+                    table.delete(key);
+                }
+
+                Some(val) => match self.heap.object(tenant_id, table_id, key, val) {
+                    Some((key, obj)) => {
+                        if expires != 0 {
+                            self.heap.set_expires(&obj, expires);
+                        }
+                        match self.heap.commit(key, obj) {
+                            Some((key, obj)) => {
+                                table.put(key, obj);
+                                objects += 1;
+                            }
+
+                            None => failed = true,
+                        }
+                    }
+
+                    None => failed = true,
+                },
+
+                None => {
+                    table.delete(key);
+                    self.heap.commit_delete(tenant_id, table_id, key);
+                }
+            }
+        })?;
+
+        if failed {
+            return Err(Error::new(ErrorKind::Other, "Failed to allocate or log an object."));
+        }
+
+        Ok(objects)
+    }
+
+    // Returns a table belonging to a tenant, creating the tenant and table if required.
+    fn get_or_create_table(&self, tenant_id: TenantId, table_id: TableId) -> Arc<Table> {
+        if self.get_tenant(tenant_id).is_none() {
+            self.insert_tenant(self.new_tenant(tenant_id));
+        }
+        let tenant = self.get_tenant(tenant_id).expect("Failed to create tenant.");
+
+        if let Some(table) = tenant.get_table(table_id) {
+            return table;
+        }
+
+        tenant.create_table(table_id);
+        tenant.get_table(table_id).expect("Failed to create table.")
+    }
+
+    // Returns a new tenant whose tables keep values inline if `set_inline_values()` was called,
+    // and charge their objects to the tenant's group if `set_group_quota()` was.
+    fn new_tenant(&self, tenant_id: TenantId) -> Tenant {
+        let tenant = Tenant::with_inline(tenant_id, self.inline_values, self.heap.layout());
+        if let Some(quota) = self.heap.group_quota(tenant_id) {
+            tenant.set_quota(quota);
+        }
+        tenant
+    }
+
+    /// This method adds a tenant to Master.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant to be added.
+    fn insert_tenant(&self, tenant: Tenant) {
+        // Acquire a write lock. The bucket is determined by the least significant byte of the
+        // tenant id.
+        let bucket = (tenant.id() & 0xff) as usize & (TENANT_BUCKETS - 1);
+        let mut map = self.tenants[bucket].write();
+
+        // Insert the tenant, and invalidate every thread's cache of tenants in case it replaced
+        // one.
+        map.insert(tenant.id(), Arc::new(tenant));
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// This method removes a tenant from Master. The tenant's objects are freed once requests
+    /// still holding on to it complete.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant_id`: An identifier for the tenant to be removed.
+    fn remove_tenant(&self, tenant_id: TenantId) {
+        let bucket = (tenant_id & 0xff) as usize & (TENANT_BUCKETS - 1);
+        self.tenants[bucket].write().remove(&tenant_id);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Handles the Get() RPC request.
+    ///
+    /// A hash table lookup is performed on a supplied tenant id, table id, and key. If successfull,
+    /// the result of the lookup is written into a response packet, and the response header is
+    /// updated. In the case of a failure, the response header is updated with a status indicating
+    /// the reason for the failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unreachable_code)]
+    #[allow(unused_assignments)]
     fn get(
         &self,
         req: Packet<UdpHeader, EmptyMetadata>,
@@ -463,10 +1579,16 @@ impl Master {
         // reference to Master in the generator below.
         let tenant = self.get_tenant(tenant_id);
         let alloc = self.heap.clone();
+        let reads = Arc::clone(&self.reads);
+
+        // Hint that the task run on the core handling the key's partition.
+        let affinity = self.key_affinity(&req.get_payload()[..key_length as usize]);
 
         // Create a generator for this request.
         let gen = Box::new(move || {
             let mut status: RpcStatus = RpcStatus::StatusTenantDoesNotExist;
+            let mut heap_bytes = None;
+            let mut version = 0;
 
             let outcome =
                 // Check if the tenant exists. If it does, then check if the
@@ -480,18 +1602,45 @@ impl Master {
                 .and_then(| table | {
                                 status = RpcStatus::StatusObjectDoesNotExist;
                                 let (key, _) = req.get_payload().split_at(key_length as usize);
-                                table.get(key)
-                            })
-                // If the lookup succeeded, obtain the value, and update the
-                // status of the rpc.
-                .and_then(| object | {
-                                status = RpcStatus::StatusInternalError;
-                                alloc.resolve(object)
+                                table.lookup(key)
                             })
-                // If the value was obtained, then write to the response packet
-                // and update the status of the rpc.
-                .and_then(| (_k, value) | {
+                // If the lookup succeeded, obtain the value, write it to the
+                // response packet, and update the status of the rpc. Values
+                // kept inline are written without touching the object.
+                .and_then(| found | {
+                                let object = match found {
+                                    Found::Inline(inline) => {
+                                        if inline.expires != 0 && inline.expires <= now_ms() {
+                                            return None;
+                                        }
+
+                                        status = RpcStatus::StatusInternalError;
+                                        version = inline.version;
+                                        let value = inline.value();
+                                        heap_bytes = Some(value.len());
+                                        return res.add_to_payload_tail(value.len(), value).ok();
+                                    }
+
+                                    Found::Object(object) => object,
+                                };
+
+                                if alloc.is_expired(&object) {
+                                    return None;
+                                }
+
+                                if !alloc.checksum_ok(&object) {
+                                    status = RpcStatus::StatusCorruptObject;
+                                    return None;
+                                }
+
                                 status = RpcStatus::StatusInternalError;
+                                version = alloc.version(&object);
+                                let (_k, value) = match alloc.resolve(object) {
+                                    Some(resolved) => resolved,
+                                    None => return None,
+                                };
+
+                                heap_bytes = Some(value.len());
                                 res.add_to_payload_tail(value.len(), &value[..]).ok()
                             })
                 // If the value was written to the response payload,
@@ -501,6 +1650,12 @@ impl Master {
                                 Some(())
                             });
 
+            let resp_bytes = res.get_payload().len();
+            match heap_bytes {
+                Some(len) => reads.record(ReadClass::GET, 1, len, resp_bytes),
+                None => reads.record(ReadClass::GET, 0, 0, resp_bytes),
+            }
+
             match outcome {
                 // The RPC completed successfully. Update the response header with
                 // the status and value length.
@@ -509,6 +1664,7 @@ impl Master {
 
                     let hdr: &mut GetResponse = res.get_mut_header();
                     hdr.value_length = val_len;
+                    hdr.version = version;
                     hdr.common_header.status = status;
                 }
 
@@ -530,7 +1686,11 @@ impl Master {
         });
 
         // Return a native task.
-        return Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)));
+        let mut task = Native::new(TaskPriority::REQUEST, gen);
+        if let Some(affinity) = affinity {
+            task.set_affinity(affinity);
+        }
+        return Ok(Box::new(task));
     }
 
     /// Handles the put() RPC request.
@@ -560,7 +1720,8 @@ impl Master {
             Packet<UdpHeader, EmptyMetadata>,
         ),
     > {
-        // First, parse the request packet.
+        // First, note the client that sent the request, and parse the request packet.
+        let (client, req) = client_of(req);
         let req = req.parse_header::<PutRequest>();
 
         // Read fields off the request header.
@@ -593,11 +1754,37 @@ impl Master {
             ));
         }
 
+        // Writes are not allowed in read-only mode.
+        if self.is_read_only() {
+            res.get_mut_header().common_header.status = RpcStatus::StatusReadOnly;
+            return Ok(self.respond(req, res));
+        }
+
+        // Writes to a throttled table are only allowed if it's bucket has a token left.
+        if let Some(bucket) = self.throttles.get(&(tenant_id, table_id)) {
+            if !bucket.admit(cycles::rdtsc()) {
+                res.get_mut_header().common_header.status = RpcStatus::StatusThrottled;
+                return Ok(self.respond(req, res));
+            }
+        }
+
+        // Writes by a tenant whose group has used up it's memory quota are not allowed.
+        if self.heap.over_quota(tenant_id) {
+            res.get_mut_header().common_header.status = RpcStatus::StatusQuotaExceeded;
+            return Ok(self.respond(req, res));
+        }
+
         // Lookup the tenant, and get a handle to the allocator. Required to avoid capturing a
         // reference to Master in the generator below.
         let tenant = self.get_tenant(tenant_id);
         let alloc = self.heap.clone();
 
+        // While tombstones are kept, the write is checked against the key's tombstone.
+        let stamp = self.write_stamp(client, rpc_stamp);
+
+        // Hint that the task run on the core handling the key's partition.
+        let affinity = self.key_affinity(&req.get_payload()[..key_length as usize]);
+
         // Create a generator for this request.
         let gen = Box::new(move || {
             let mut status: RpcStatus = RpcStatus::StatusTenantDoesNotExist;
@@ -623,9 +1810,31 @@ impl Master {
                                     // If the allocation succeeds, update the
                                     // status of the rpc, and insert the object
                                     // into the table.
-                                    .and_then(| (key, obj) | {
-                                        status = RpcStatus::StatusOk;
-                                        table.put(key, obj);
+                                    .and_then(| (k, obj) | {
+                                        // The object is committed with the key's bucket
+                                        // locked, so that it is logged in the order that it
+                                        // enters the table.
+                                        let refused = Cell::new(false);
+                                        let commit = || {
+                                            let committed = alloc.commit(k, obj);
+                                            refused.set(committed.is_none());
+                                            committed
+                                        };
+
+                                        // Stamped writes are rejected if the key was deleted
+                                        // after they were issued.
+                                        let written = match stamp {
+                                            Some(ref stamp) => {
+                                                table.put_stamped(key, stamp, commit)
+                                            }
+                                            None => table.put_if(key, |_| commit()),
+                                        };
+
+                                        status = match (written, refused.get()) {
+                                            (true, _) => RpcStatus::StatusOk,
+                                            (false, true) => RpcStatus::StatusLogFull,
+                                            (false, false) => RpcStatus::StatusStaleWrite,
+                                        };
                                         Some(())
                                     });
                 }
@@ -646,13 +1855,18 @@ impl Master {
         });
 
         // Create and return a native task.
-        return Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)));
+        let mut task = Native::new(TaskPriority::REQUEST, gen);
+        if let Some(affinity) = affinity {
+            task.set_affinity(affinity);
+        }
+        return Ok(Box::new(task));
     }
 
-    /// Handles the multiget() RPC request.
+    /// Handles the delete() RPC request.
     ///
-    /// If issued by a valid tenant for a valid table, lookups up a list of keys and returns
-    /// their values.
+    /// If the issuing tenant is valid, and has a table with the requested id, the key-value pair
+    /// is removed from the table. The response's status indicates whether there was anything
+    /// to remove.
     ///
     /// # Arguments
     ///
@@ -665,7 +1879,7 @@ impl Master {
     /// in request and response packets are returned with the response status appropriately set.
     #[allow(unreachable_code)]
     #[allow(unused_assignments)]
-    fn multiget(
+    fn delete(
         &self,
         req: Packet<UdpHeader, EmptyMetadata>,
         res: Packet<UdpHeader, EmptyMetadata>,
@@ -676,35 +1890,33 @@ impl Master {
             Packet<UdpHeader, EmptyMetadata>,
         ),
     > {
-        // First, parse the request packet.
-        let req = req.parse_header::<MultiGetRequest>();
+        // First, note the client that sent the request, and parse the request packet.
+        let (client, req) = client_of(req);
+        let req = req.parse_header::<DeleteRequest>();
 
         // Read fields off the request header.
         let mut tenant_id: TenantId = 0;
         let mut table_id: TableId = 0;
         let mut key_length = 0;
-        let mut num_keys = 0;
         let mut rpc_stamp = 0;
 
         {
             let hdr = req.get_header();
             tenant_id = hdr.common_header.tenant as TenantId;
             table_id = hdr.table_id as TableId;
-            key_length = hdr.key_len;
-            num_keys = hdr.num_keys;
+            key_length = hdr.key_length;
             rpc_stamp = hdr.common_header.stamp;
         }
 
-        // Next, add a header to the response packet.
-        let mut res = res.push_header(&MultiGetResponse::new(
+        // Next, write a header into the response packet.
+        let mut res = res.push_header(&DeleteResponse::new(
             rpc_stamp,
-            OpCode::SandstormMultiGetRpc,
+            OpCode::SandstormDeleteRpc,
             tenant_id,
-            0,
-        )).expect("Failed to setup MultiGetResponse");
+        )).expect("Failed to push DeleteResponse");
 
-        // If the payload size is less than the key length, return an error.
-        if req.get_payload().len() < ((key_length as u32) * num_keys) as usize {
+        // If there is no key, or the payload size is less than the key length, return an error.
+        if key_length == 0 || req.get_payload().len() < key_length as usize {
             res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
             return Err((
                 req.deparse_header(PACKET_UDP_LEN as usize),
@@ -712,69 +1924,61 @@ impl Master {
             ));
         }
 
+        // Writes are not allowed in read-only mode.
+        if self.is_read_only() {
+            res.get_mut_header().common_header.status = RpcStatus::StatusReadOnly;
+            return Ok(self.respond(req, res));
+        }
+
+        // Writes to a throttled table are only allowed if it's bucket has a token left.
+        if let Some(bucket) = self.throttles.get(&(tenant_id, table_id)) {
+            if !bucket.admit(cycles::rdtsc()) {
+                res.get_mut_header().common_header.status = RpcStatus::StatusThrottled;
+                return Ok(self.respond(req, res));
+            }
+        }
+
         // Lookup the tenant, and get a handle to the allocator. Required to avoid capturing a
         // reference to Master in the generator below.
         let tenant = self.get_tenant(tenant_id);
         let alloc = self.heap.clone();
 
+        // While tombstones are kept, the deletion leaves one behind.
+        let stamp = self.write_stamp(client, rpc_stamp);
+
+        // Hint that the task run on the core handling the key's partition.
+        let affinity = self.key_affinity(&req.get_payload()[..key_length as usize]);
+
         // Create a generator for this request.
         let gen = Box::new(move || {
-            let mut n_recs: u32 = 0;
             let mut status: RpcStatus = RpcStatus::StatusTenantDoesNotExist;
 
-            let outcome =
-                // Check if the tenant exists. If it does, then check if the
-                // table exists, and update the status of the rpc.
-                tenant.and_then(| tenant | {
-                                status = RpcStatus::StatusTableDoesNotExist;
-                                tenant.get_table(table_id)
-                            });
+            // If the tenant exists, check if it has a table with the given id,
+            // and update the status of the rpc.
+            let outcome = tenant.and_then(|tenant| {
+                status = RpcStatus::StatusTableDoesNotExist;
+                tenant.get_table(table_id)
+            });
 
-            // If the table exists, then lookup the keys in the database.
+            // If the table exists, remove the key from it. An object that had already expired
+            // is reported as not existing, just like it would be to a get().
             if let Some(table) = outcome {
-                status = RpcStatus::StatusObjectDoesNotExist;
-
-                // Iterate across keys in the request payload. There are `num_keys` keys, each
-                // of length `key_length`.
-                let mut n = 0;
-                for key in req.get_payload().chunks(key_length as usize) {
-                    n += 1;
-                    // Corner case: We've either already seen `num_keys` keys or the current key
-                    // is not `key_length` bytes long.
-                    if n > num_keys || key.len() != key_length as usize {
-                        break;
-                    }
-
-                    // Lookup the key, and add it to the response payload.
-                    let res = table
-                        .get(key)
-                        .and_then(|object| alloc.resolve(object))
-                        .and_then(|(_k, value)| {
-                            res.add_to_payload_tail(value.len(), &value[..]).ok()
-                        });
-
-                    // If the current lookup failed, then stop all lookups.
-                    match res {
-                        Some(_) => n_recs += 1,
-
-                        None => break,
-                    }
-                }
-
-                // Success if all keys could be looked up at the database.
-                if n_recs == num_keys {
-                    status = RpcStatus::StatusOk;
-                }
+                // The deletion is logged with the key's bucket locked, so that it is ordered
+                // against writes to the key.
+                let key = &req.get_payload()[..key_length as usize];
+                let removed = table.delete_with(key, stamp.as_ref(), || {
+                    alloc.commit_delete(tenant_id, table_id, key)
+                });
+
+                status = match removed {
+                    Ok(Some(ref obj)) if !alloc.is_expired(obj) => RpcStatus::StatusOk,
+                    Ok(_) => RpcStatus::StatusObjectDoesNotExist,
+                    Err(()) => RpcStatus::StatusLogFull,
+                };
             }
 
-            // Write the status into the RPC response header.
-            res.get_mut_header().common_header.status = status.clone();
-
-            // If the RPC was handled successfully, then update the response header with the number
-            // of records that were read from the database.
-            if status == RpcStatus::StatusOk {
-                res.get_mut_header().num_records = n_recs;
-            }
+            // Update the response header.
+            res.get_mut_header().common_header.status = status;
 
             // Deparse request and response packets to UDP, and return from the generator.
             return Some((
@@ -788,12 +1992,22 @@ impl Master {
         });
 
         // Create and return a native task.
-        return Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)));
+        let mut task = Native::new(TaskPriority::REQUEST, gen);
+        if let Some(affinity) = affinity {
+            task.set_affinity(affinity);
+        }
+        return Ok(Box::new(task));
     }
 
-    /// Handles the invoke RPC request.
+    /// Handles the bulk_put() RPC request, issued by tenants over the data path (ex: to load
+    /// records at line rate).
     ///
-    /// If issued by a valid tenant for a valid extension, invokes the extension.
+    /// Applies the records on the request in order, just like a put() or delete() would, to the
+    /// issuing tenant's tables. Unlike bulk_put() requests issued by servers migrating a tenant
+    /// (refer to `bulk_put()`), the request is authenticated like any other request of the
+    /// tenant, and neither the tenant nor it's tables are created if they do not exist. The
+    /// response carries the number of records that were applied; on a failure, the records after
+    /// these were not.
     ///
     /// # Arguments
     ///
@@ -802,10 +2016,11 @@ impl Master {
     ///
     /// # Return
     ///
-    /// A Container task that can be scheduled by the database. In the case of an error, the passed
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
     /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unreachable_code)]
     #[allow(unused_assignments)]
-    fn invoke(
+    fn tenant_bulk_put(
         &self,
         req: Packet<UdpHeader, EmptyMetadata>,
         res: Packet<UdpHeader, EmptyMetadata>,
@@ -816,46 +2031,1509 @@ impl Master {
             Packet<UdpHeader, EmptyMetadata>,
         ),
     > {
-        // First, parse the request packet.
-        let req = req.parse_header::<InvokeRequest>();
+        // First, note the client that sent the request, and parse the request packet.
+        let (client, req) = client_of(req);
+        let req = req.parse_header::<BulkPutRequest>();
 
-        // Read fields of the request header.
+        // Read fields off the request header.
         let mut tenant_id: TenantId = 0;
-        let mut name_length: usize = 0;
-        let mut args_length: usize = 0;
+        let mut num_records: usize = 0;
         let mut rpc_stamp = 0;
 
         {
             let hdr = req.get_header();
             tenant_id = hdr.common_header.tenant as TenantId;
-            name_length = hdr.name_length as usize;
-            args_length = hdr.args_length as usize;
+            num_records = hdr.num_records as usize;
             rpc_stamp = hdr.common_header.stamp;
         }
 
-        // Next, add a header to the response packet.
-        let mut res = res.push_header(&InvokeResponse::new(
+        // Next, write a header into the response packet.
+        let mut res = res.push_header(&BulkPutResponse::new(
             rpc_stamp,
-            OpCode::SandstormInvokeRpc,
+            OpCode::SandstormBulkPutRpc,
             tenant_id,
-        )).expect("Failed to push InvokeResponse");
+        )).expect("Failed to push BulkPutResponse");
+
+        // Collect the tables written to, returning an error if the records are malformed.
+        let tables = {
+            let mut tables: Vec<TableId> = Vec::new();
+            let valid = migrate::decode(req.get_payload(), num_records).map_or(false, |records| {
+                for record in records.iter() {
+                    if !tables.contains(&record.table) {
+                        tables.push(record.table);
+                    }
+                }
+                records.iter().all(|record| {
+                    record.key.len() > 0 && record.value.map_or(true, |val| val.len() > 0)
+                })
+            });
 
-        // If the payload size is less than the sum of the name and args
-        // length, return an error.
-        if req.get_payload().len() < name_length + args_length {
-            res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
-            return Err((
-                req.deparse_header(PACKET_UDP_LEN as usize),
-                res.deparse_header(PACKET_UDP_LEN as usize),
-            ));
-        }
+            if valid {
+                Some(tables)
+            } else {
+                None
+            }
+        };
+
+        let tables = match tables {
+            Some(tables) => tables,
+            None => {
+                res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
+                return Err((
+                    req.deparse_header(PACKET_UDP_LEN as usize),
+                    res.deparse_header(PACKET_UDP_LEN as usize),
+                ));
+            }
+        };
 
-        // Read the extension's name from the request payload.
-        let mut name = Vec::new();
-        name.extend_from_slice(req.get_payload().split_at(name_length).0);
-        let name: String = String::from_utf8(name).expect("ERROR: Failed to get ext name.");
+        // Writes are not allowed in read-only mode.
+        if self.is_read_only() {
+            res.get_mut_header().common_header.status = RpcStatus::StatusReadOnly;
+            return Ok(self.respond(req, res));
+        }
 
-        let mut status = RpcStatus::StatusTenantDoesNotExist;
+        // Writes to a throttled table are only allowed if it's bucket has a token left. Every
+        // table written to takes a token, and none of the records are applied otherwise.
+        for table_id in tables.iter() {
+            if let Some(bucket) = self.throttles.get(&(tenant_id, *table_id)) {
+                if !bucket.admit(cycles::rdtsc()) {
+                    res.get_mut_header().common_header.status = RpcStatus::StatusThrottled;
+                    return Ok(self.respond(req, res));
+                }
+            }
+        }
+
+        // Writes by a tenant whose group has used up it's memory quota are not allowed.
+        if self.heap.over_quota(tenant_id) {
+            res.get_mut_header().common_header.status = RpcStatus::StatusQuotaExceeded;
+            return Ok(self.respond(req, res));
+        }
+
+        // Lookup the tenant, and get a handle to the allocator. Required to avoid capturing a
+        // reference to Master in the generator below.
+        let tenant = self.get_tenant(tenant_id);
+        let alloc = self.heap.clone();
+
+        // While tombstones are kept, writes are checked against tombstones, and deletions leave
+        // one behind, just like they would on a put() or delete().
+        let stamp = self.write_stamp(client, rpc_stamp);
+
+        // Create a generator for this request.
+        let gen = Box::new(move || {
+            let mut status: RpcStatus = RpcStatus::StatusTenantDoesNotExist;
+            let mut applied: u32 = 0;
+
+            if let Some(tenant) = tenant {
+                status = RpcStatus::StatusOk;
+
+                // The records were checked above. They borrow the request's payload, and are
+                // dropped before the request is handed back.
+                {
+                    let records = migrate::decode(req.get_payload(), num_records).unwrap_or(vec![]);
+                    for record in records.iter() {
+                        let table = match tenant.get_table(record.table) {
+                            Some(table) => table,
+                            None => {
+                                status = RpcStatus::StatusTableDoesNotExist;
+                                break;
+                            }
+                        };
+
+                        status = write_record(&alloc, tenant_id, &table, record, stamp.as_ref());
+                        if status != RpcStatus::StatusOk {
+                            break;
+                        }
+                        applied += 1;
+                    }
+                }
+            }
+
+            // Update the response header.
+            {
+                let hdr = res.get_mut_header();
+                hdr.common_header.status = status;
+                hdr.num_records = applied;
+            }
+
+            // Deparse request and response packets to UDP, and return from the generator.
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        // Create and return a native task.
+        return Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)));
+    }
+
+    /// Handles the cas() RPC request.
+    ///
+    /// If the issuing tenant is valid, a new key-value pair is allocated and inserted into a
+    /// table if it exists, but only if the object currently under the key is at the version
+    /// expected by the request. The version is checked and the object inserted atomically.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unreachable_code)]
+    #[allow(unused_assignments)]
+    fn cas(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        // First, parse the request packet.
+        let req = req.parse_header::<CasRequest>();
+
+        // Read fields off the request header.
+        let mut tenant_id: TenantId = 0;
+        let mut table_id: TableId = 0;
+        let mut key_length = 0;
+        let mut expected = 0;
+        let mut rpc_stamp = 0;
+
+        {
+            let hdr = req.get_header();
+            tenant_id = hdr.common_header.tenant as TenantId;
+            table_id = hdr.table_id as TableId;
+            key_length = hdr.key_length;
+            expected = hdr.version;
+            rpc_stamp = hdr.common_header.stamp;
+        }
+
+        // Next, write a header into the response packet.
+        let mut res = res.push_header(&CasResponse::new(
+            rpc_stamp,
+            OpCode::SandstormCasRpc,
+            tenant_id,
+        )).expect("Failed to push CasResponse");
+
+        // The payload must consist of a key followed by a value.
+        if req.get_payload().len() <= key_length as usize || key_length == 0 {
+            res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
+            return Err((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+        }
+
+        // Writes are not allowed in read-only mode.
+        if self.is_read_only() {
+            res.get_mut_header().common_header.status = RpcStatus::StatusReadOnly;
+            return Ok(self.respond(req, res));
+        }
+
+        // Writes to a throttled table are only allowed if it's bucket has a token left.
+        if let Some(bucket) = self.throttles.get(&(tenant_id, table_id)) {
+            if !bucket.admit(cycles::rdtsc()) {
+                res.get_mut_header().common_header.status = RpcStatus::StatusThrottled;
+                return Ok(self.respond(req, res));
+            }
+        }
+
+        // Writes by a tenant whose group has used up it's memory quota are not allowed.
+        if self.heap.over_quota(tenant_id) {
+            res.get_mut_header().common_header.status = RpcStatus::StatusQuotaExceeded;
+            return Ok(self.respond(req, res));
+        }
+
+        // Lookup the tenant, and get a handle to the allocator. Required to avoid capturing a
+        // reference to Master in the generator below.
+        let tenant = self.get_tenant(tenant_id);
+        let alloc = self.heap.clone();
+
+        // Hint that the task run on the core handling the key's partition.
+        let affinity = self.key_affinity(&req.get_payload()[..key_length as usize]);
+
+        // Create a generator for this request.
+        let gen = Box::new(move || {
+            let mut status: RpcStatus = RpcStatus::StatusTenantDoesNotExist;
+            let mut version = 0;
+
+            let outcome = tenant.and_then(|tenant| {
+                status = RpcStatus::StatusTableDoesNotExist;
+                tenant.get_table(table_id)
+            });
+
+            if let Some(table) = outcome {
+                let (key, val) = req.get_payload().split_at(key_length as usize);
+
+                // Allocate and commit the object only once the version has been checked, so that
+                // a failed cas() never leaves an object behind in the persistent segment.
+                let written = table.put_if(key, |current| {
+                    version = current.map_or(0, |object| alloc.version(object));
+                    if version != expected {
+                        status = RpcStatus::StatusVersionMismatch;
+                        return None;
+                    }
+
+                    status = RpcStatus::StatusInternalError;
+                    alloc.object(tenant_id, table_id, key, val).and_then(|(key, obj)| {
+                        version = alloc.version(&obj);
+                        status = RpcStatus::StatusLogFull;
+                        alloc.commit(key, obj)
+                    })
+                });
+
+                if written {
+                    status = RpcStatus::StatusOk;
+                }
+            }
+
+            // Update the response header.
+            {
+                let hdr = res.get_mut_header();
+                hdr.common_header.status = status;
+                hdr.version = version;
+            }
+
+            // Deparse request and response packets to UDP, and return from the generator.
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        // Create and return a native task.
+        let mut task = Native::new(TaskPriority::REQUEST, gen);
+        if let Some(affinity) = affinity {
+            task.set_affinity(affinity);
+        }
+        return Ok(Box::new(task));
+    }
+
+    /// Handles the multiget() RPC request.
+    ///
+    /// If issued by a valid tenant for a valid table, lookups up a list of keys and returns
+    /// their values. Every key is read as of a single point in time (refer to `Table::snapshot()`).
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unreachable_code)]
+    #[allow(unused_assignments)]
+    fn multiget(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        // First, parse the request packet.
+        let req = req.parse_header::<MultiGetRequest>();
+
+        // Read fields off the request header.
+        let mut tenant_id: TenantId = 0;
+        let mut table_id: TableId = 0;
+        let mut key_length = 0;
+        let mut num_keys = 0;
+        let mut rpc_stamp = 0;
+
+        {
+            let hdr = req.get_header();
+            tenant_id = hdr.common_header.tenant as TenantId;
+            table_id = hdr.table_id as TableId;
+            key_length = hdr.key_len;
+            num_keys = hdr.num_keys;
+            rpc_stamp = hdr.common_header.stamp;
+        }
+
+        // Next, add a header to the response packet.
+        let mut res = res.push_header(&MultiGetResponse::new(
+            rpc_stamp,
+            OpCode::SandstormMultiGetRpc,
+            tenant_id,
+            0,
+        )).expect("Failed to setup MultiGetResponse");
+
+        // If the payload size is less than the key length, return an error.
+        if req.get_payload().len() < ((key_length as u32) * num_keys) as usize {
+            res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
+            return Err((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+        }
+
+        // Lookup the tenant, and get a handle to the allocator. Required to avoid capturing a
+        // reference to Master in the generator below.
+        let tenant = self.get_tenant(tenant_id);
+        let alloc = self.heap.clone();
+        let reads = Arc::clone(&self.reads);
+
+        // Create a generator for this request.
+        let gen = Box::new(move || {
+            let mut n_recs: u32 = 0;
+            let mut status: RpcStatus = RpcStatus::StatusTenantDoesNotExist;
+
+            let outcome =
+                // Check if the tenant exists. If it does, then check if the
+                // table exists, and update the status of the rpc.
+                tenant.and_then(| tenant | {
+                                status = RpcStatus::StatusTableDoesNotExist;
+                                tenant.get_table(table_id)
+                            });
+
+            // If the table exists, then lookup the keys in the database.
+            if let Some(table) = outcome {
+                status = RpcStatus::StatusObjectDoesNotExist;
+
+                // Read the keys in the request payload as of a single point in time, so that a
+                // concurrent write to several of them is never observed half way through. There
+                // are `num_keys` keys, each of length `key_length`; the payload is cut short at
+                // a key that is not `key_length` bytes long.
+                let objects = {
+                    let keys: Vec<&[u8]> = req
+                        .get_payload()
+                        .chunks(key_length as usize)
+                        .take(num_keys as usize)
+                        .take_while(|key| key.len() == key_length as usize)
+                        .collect();
+                    table.snapshot(&keys, SNAPSHOT_RETRIES, |object| alloc.version(object))
+                };
+
+                // Add the objects read to the response payload, unless the keys kept being
+                // written to.
+                let mut corrupt = false;
+                let objects = objects.unwrap_or_else(|| {
+                    status = RpcStatus::StatusSnapshotAborted;
+                    Vec::new()
+                });
+                for object in objects.into_iter() {
+                    let res = object
+                        .and_then(|object| {
+                            if alloc.is_expired(&object) {
+                                None
+                            } else if alloc.checksum_ok(&object) {
+                                alloc.resolve(object)
+                            } else {
+                                corrupt = true;
+                                None
+                            }
+                        })
+                        .and_then(|(_k, value)| {
+                            res.add_to_payload_tail(value.len(), &value[..]).ok()
+                        });
+
+                    // If the current lookup failed, then stop all lookups.
+                    match res {
+                        Some(_) => n_recs += 1,
+
+                        None => break,
+                    }
+                }
+
+                // Success if all keys could be looked up at the database.
+                if n_recs == num_keys {
+                    status = RpcStatus::StatusOk;
+                } else if corrupt {
+                    status = RpcStatus::StatusCorruptObject;
+                }
+            }
+
+            // Values are copied into the response as is, so every byte read is returned.
+            let len = res.get_payload().len();
+            reads.record(ReadClass::MULTIGET, n_recs as usize, len, len);
+
+            // Write the status into the RPC response header.
+            res.get_mut_header().common_header.status = status.clone();
+
+            // If the RPC was handled successfully, then update the response header with the number
+            // of records that were read from the database.
+            if status == RpcStatus::StatusOk {
+                res.get_mut_header().num_records = n_recs;
+            }
+
+            // Deparse request and response packets to UDP, and return from the generator.
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        // Create and return a native task.
+        return Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)));
+    }
+
+    /// Handles the multi-table multiget() RPC request.
+    ///
+    /// If issued by a valid tenant, looks up a list of (table, key) pairs and returns their
+    /// values. Every key on the request can belong to a different table.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unreachable_code)]
+    #[allow(unused_assignments)]
+    fn multitable_get(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        // First, parse the request packet.
+        let req = req.parse_header::<MultiTableGetRequest>();
+
+        // Read fields off the request header.
+        let mut tenant_id: TenantId = 0;
+        let mut key_length = 0;
+        let mut num_keys = 0;
+        let mut rpc_stamp = 0;
+
+        {
+            let hdr = req.get_header();
+            tenant_id = hdr.common_header.tenant as TenantId;
+            key_length = hdr.key_len;
+            num_keys = hdr.num_keys;
+            rpc_stamp = hdr.common_header.stamp;
+        }
+
+        // Next, add a header to the response packet.
+        let mut res = res.push_header(&MultiGetResponse::new(
+            rpc_stamp,
+            OpCode::SandstormMultiTableGetRpc,
+            tenant_id,
+            0,
+        )).expect("Failed to setup MultiGetResponse");
+
+        // Every entry on the payload is an eight byte table identifier followed by a key. If the
+        // payload is not large enough to hold `num_keys` entries, return an error.
+        let entry_len = size_of::<u64>() + key_length as usize;
+        if req.get_payload().len() < entry_len * num_keys as usize {
+            res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
+            return Err((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+        }
+
+        // Lookup the tenant, and get a handle to the allocator. Required to avoid capturing a
+        // reference to Master in the generator below.
+        let tenant = self.get_tenant(tenant_id);
+        let alloc = self.heap.clone();
+        let reads = Arc::clone(&self.reads);
+
+        // Create a generator for this request.
+        let gen = Box::new(move || {
+            let mut n_recs: u32 = 0;
+            let mut status: RpcStatus = RpcStatus::StatusTenantDoesNotExist;
+
+            if let Some(tenant) = tenant {
+                status = RpcStatus::StatusObjectDoesNotExist;
+
+                // The most recently looked up table. Consecutive keys usually belong to the same
+                // table, so this avoids a lookup on the tenant's table map for every key.
+                let mut table: Option<(TableId, Arc<Table>)> = None;
+
+                // Iterate across entries in the request payload. There are `num_keys` entries,
+                // each consisting of a table identifier and a key of length `key_length`.
+                let mut n = 0;
+                let mut corrupt = false;
+                for entry in req.get_payload().chunks(entry_len) {
+                    n += 1;
+                    // Corner case: We've either already seen `num_keys` entries or the current
+                    // entry is not `entry_len` bytes long.
+                    if n > num_keys || entry.len() != entry_len {
+                        break;
+                    }
+
+                    let mut id: [u8; 8] = [0; 8];
+                    id.copy_from_slice(&entry[0..8]);
+                    let table_id: TableId = u64::from_le(unsafe { transmute(id) });
+                    let key = &entry[8..];
+
+                    // Lookup the table if it differs from the one looked up previously.
+                    let cached = table.as_ref().map_or(false, |&(id, _)| id == table_id);
+                    if !cached {
+                        table = tenant.get_table(table_id).map(|table| (table_id, table));
+                    }
+
+                    if table.is_none() {
+                        status = RpcStatus::StatusTableDoesNotExist;
+                        break;
+                    }
+
+                    // Lookup the key, and add it to the response payload.
+                    let res = table
+                        .as_ref()
+                        .and_then(|&(_, ref t)| t.get(key))
+                        .and_then(|object| {
+                            if alloc.is_expired(&object) {
+                                None
+                            } else if alloc.checksum_ok(&object) {
+                                alloc.resolve(object)
+                            } else {
+                                corrupt = true;
+                                None
+                            }
+                        })
+                        .and_then(|(_k, value)| {
+                            res.add_to_payload_tail(value.len(), &value[..]).ok()
+                        });
+
+                    // If the current lookup failed, then stop all lookups.
+                    match res {
+                        Some(_) => n_recs += 1,
+
+                        None => break,
+                    }
+                }
+
+                // Success if all keys could be looked up at the database.
+                if n_recs == num_keys {
+                    status = RpcStatus::StatusOk;
+                } else if corrupt {
+                    status = RpcStatus::StatusCorruptObject;
+                }
+            }
+
+            // Values are copied into the response as is, so every byte read is returned.
+            let len = res.get_payload().len();
+            reads.record(ReadClass::MULTIGET, n_recs as usize, len, len);
+
+            // Write the status into the RPC response header.
+            res.get_mut_header().common_header.status = status.clone();
+
+            // If the RPC was handled successfully, then update the response header with the number
+            // of records that were read from the database.
+            if status == RpcStatus::StatusOk {
+                res.get_mut_header().num_records = n_recs;
+            }
+
+            // Deparse request and response packets to UDP, and return from the generator.
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        // Create and return a native task.
+        return Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)));
+    }
+
+    /// Handles the list_tables() RPC request.
+    ///
+    /// If issued by a valid tenant, returns the identifier, kind, number of objects, and creation
+    /// time of every table owned by the tenant.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unreachable_code)]
+    #[allow(unused_assignments)]
+    fn list_tables(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        // First, parse the request packet.
+        let req = req.parse_header::<ListTablesRequest>();
+
+        // Read fields off the request header.
+        let mut tenant_id: TenantId = 0;
+        let mut rpc_stamp = 0;
+
+        {
+            let hdr = req.get_header();
+            tenant_id = hdr.common_header.tenant as TenantId;
+            rpc_stamp = hdr.common_header.stamp;
+        }
+
+        // Next, add a header to the response packet.
+        let mut res = res.push_header(&ListTablesResponse::new(
+            rpc_stamp,
+            OpCode::SandstormListTablesRpc,
+            tenant_id,
+            0,
+        )).expect("Failed to setup ListTablesResponse");
+
+        // Lookup the tenant. Required to avoid capturing a reference to Master in the generator.
+        let tenant = self.get_tenant(tenant_id);
+
+        // Create a generator for this request.
+        let gen = Box::new(move || {
+            let mut n_tables: u32 = 0;
+            let mut status: RpcStatus = RpcStatus::StatusTenantDoesNotExist;
+
+            if let Some(tenant) = tenant {
+                status = RpcStatus::StatusOk;
+
+                // Sort tables by identifier so that listings are stable across calls.
+                let mut tables = tenant.tables();
+                tables.sort_by_key(|&(id, _)| id);
+
+                // Add an entry for every table to the response payload.
+                for (id, table) in tables.into_iter() {
+                    let id: [u8; 8] = unsafe { transmute(id.to_le()) };
+                    let len: [u8; 8] = unsafe { transmute((table.len() as u64).to_le()) };
+                    let created: [u8; 8] = unsafe { transmute(table.created().to_le()) };
+
+                    let mut entry: Vec<u8> = Vec::with_capacity(LIST_TABLES_ENTRY_LEN);
+                    entry.extend_from_slice(&id);
+                    entry.push(table.kind() as u8);
+                    entry.extend_from_slice(&len);
+                    entry.extend_from_slice(&created);
+
+                    // If the response packet is full, then stop adding entries.
+                    if res.add_to_payload_tail(entry.len(), &entry[..]).is_err() {
+                        status = RpcStatus::StatusInternalError;
+                        break;
+                    }
+
+                    n_tables += 1;
+                }
+            }
+
+            // Write the status and number of tables into the RPC response header.
+            {
+                let hdr = res.get_mut_header();
+                hdr.common_header.status = status;
+                hdr.num_tables = n_tables;
+            }
+
+            // Deparse request and response packets to UDP, and return from the generator.
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        // Create and return a native task.
+        return Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)));
+    }
+
+    /// Handles the server_info() RPC request. Responds immediately with the number of cores the
+    /// server is running on, the version of it's configuration, and the commit and features it
+    /// was built with, which lets clients annotate their measurements with the server's
+    /// configuration. Does not require the tenant to exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database.
+    fn server_info(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        let req = req.parse_header::<ServerInfoRequest>();
+
+        let (tenant_id, rpc_stamp) = {
+            let hdr = req.get_header();
+            (hdr.common_header.tenant, hdr.common_header.stamp)
+        };
+
+        let mut res = res.push_header(&ServerInfoResponse::new(
+            rpc_stamp,
+            OpCode::SandstormServerInfoRpc,
+            tenant_id,
+            self.num_cores,
+        )).expect("Failed to setup ServerInfoResponse");
+
+        {
+            let hdr = res.get_mut_header();
+            hdr.config_version = self.config_version;
+            hdr.git_hash = config::git_hash_prefix();
+            hdr.features = config::features();
+            hdr.common_header.status = RpcStatus::StatusOk;
+        }
+        Ok(self.respond(req, res))
+    }
+
+    /// Handles the get_statistics() RPC request. Responds immediately with the counters collected
+    /// across the server's cores: the packets received and sent out by every dispatcher, the
+    /// depth of every scheduler's run-queues, the tasks that completed on them, the requests
+    /// dispatched with every opcode and from the busiest tenants, and the heap's usage. Does not
+    /// require the tenant to exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database.
+    fn get_statistics(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        let req = req.parse_header::<GetStatisticsRequest>();
+
+        let (tenant_id, rpc_stamp) = {
+            let hdr = req.get_header();
+            (hdr.common_header.tenant, hdr.common_header.stamp)
+        };
+
+        let mut stats = match self.schedulers {
+            Some(ref schedulers) => {
+                let schedulers = schedulers.read();
+                let cores = schedulers
+                    .iter()
+                    .map(|sched| {
+                        let (rx, tx) = sched.counters().packets();
+                        CoreStatistics {
+                            core: sched.core(),
+                            rx_packets: rx,
+                            tx_packets: tx,
+                            pending: sched.pending() as u32,
+                            parked: sched.parked() as u32,
+                            completed: sched.completed(),
+                        }
+                    }).collect();
+                let counters: Vec<_> = schedulers.iter().map(|sched| sched.counters()).collect();
+                ServerStatistics::collect(cores, &counters, MAX_STATISTICS_TENANTS)
+            }
+
+            None => ServerStatistics::default(),
+        };
+
+        stats.heap_allocated = self.heap.allocated() as u64;
+        stats.hot_bytes = self.heap.hot_stats().1 as u64;
+        if let Some((used, size)) = self.heap.segment_stats() {
+            stats.segment_used = used as u64;
+            stats.segment_size = size as u64;
+        }
+        let stats = stats.encode();
+
+        let mut res = res.push_header(&GetStatisticsResponse::new(
+            rpc_stamp,
+            OpCode::SandstormGetStatisticsRpc,
+            tenant_id,
+        )).expect("Failed to setup GetStatisticsResponse");
+
+        match res.add_to_payload_tail(stats.len(), &stats) {
+            Ok(()) => {
+                let hdr = res.get_mut_header();
+                hdr.length = stats.len() as u32;
+                hdr.common_header.status = RpcStatus::StatusOk;
+            }
+
+            Err(_) => {
+                res.get_mut_header().common_header.status = RpcStatus::StatusInternalError;
+            }
+        }
+        Ok(self.respond(req, res))
+    }
+
+    /// Handles the tenant_admin() RPC request.
+    ///
+    /// Creates or deletes a tenant, or creates or drops a table under one, on behalf of the admin
+    /// tenant set through `set_admin_tenant()`. Requests from any other tenant are rejected with
+    /// `StatusUnauthorized`. Tenants and tables are added or removed right away, so that requests
+    /// received after this one observe the change. The objects on a deleted tenant or dropped
+    /// table are then freed by a task that runs ahead of tenant requests, yielding after every
+    /// `ADMIN_FREE_BATCH` objects.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    fn tenant_admin(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        let req = req.parse_header::<TenantAdminRequest>();
+
+        let (tenant_id, target, action, table_id, rpc_stamp) = {
+            let hdr = req.get_header();
+            (
+                hdr.common_header.tenant as TenantId,
+                hdr.target as TenantId,
+                hdr.action,
+                hdr.table_id as TableId,
+                hdr.common_header.stamp,
+            )
+        };
+
+        let mut res = res.push_header(&TenantAdminResponse::new(
+            rpc_stamp,
+            OpCode::SandstormTenantAdminRpc,
+            tenant_id,
+        )).expect("Failed to setup TenantAdminResponse");
+
+        // Only the admin tenant can create or delete tenants and tables.
+        if self.admin_tenant == 0 || tenant_id != self.admin_tenant {
+            res.get_mut_header().common_header.status = RpcStatus::StatusUnauthorized;
+            return Ok(self.respond(req, res));
+        }
+
+        let action = match TenantAction::try_from(action) {
+            Ok(action) => action,
+            Err(_) => {
+                res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
+                return Ok(self.respond(req, res));
+            }
+        };
+
+        // Modifications are not allowed in read-only mode.
+        if self.is_read_only() {
+            res.get_mut_header().common_header.status = RpcStatus::StatusReadOnly;
+            return Ok(self.respond(req, res));
+        }
+
+        // Perform the action, collecting the tables whose objects must be freed.
+        let mut status = RpcStatus::StatusOk;
+        let dropped: Vec<(TableId, Arc<Table>)> = match (action, self.get_tenant(target)) {
+            (TenantAction::CreateTenant, None) => {
+                self.insert_tenant(self.new_tenant(target));
+                Vec::new()
+            }
+
+            (TenantAction::CreateTenant, Some(_)) => Vec::new(),
+
+            (TenantAction::DeleteTenant, Some(tenant)) => {
+                self.remove_tenant(target);
+                tenant.tables()
+            }
+
+            (TenantAction::CreateTable, Some(tenant)) => {
+                if tenant.get_table(table_id).is_none() {
+                    tenant.create_table(table_id);
+                }
+                Vec::new()
+            }
+
+            (TenantAction::DropTable, Some(tenant)) => match tenant.drop_table(table_id) {
+                Some(table) => vec![(table_id, table)],
+                None => {
+                    status = RpcStatus::StatusTableDoesNotExist;
+                    Vec::new()
+                }
+            },
+
+            (_, None) => {
+                status = RpcStatus::StatusTenantDoesNotExist;
+                Vec::new()
+            }
+        };
+        res.get_mut_header().common_header.status = status;
+
+        // Get a handle to the allocator. Required to avoid capturing a reference to Master in the
+        // generator below.
+        let alloc = self.heap.clone();
+
+        let gen = Box::new(move || {
+            let mut freed: u64 = 0;
+            for idx in 0..dropped.len() {
+                let table_id = dropped[idx].0;
+
+                // Collect the table's keys first, so that no bucket stays locked while it's
+                // objects are freed.
+                let mut keys = Vec::with_capacity(dropped[idx].1.len());
+                dropped[idx].1.scan(|object| {
+                    if let Some((key, _)) = alloc.resolve(object) {
+                        keys.push(key);
+                    }
+                });
+
+                for num in 0..keys.len() {
+                    let removed = dropped[idx].1.delete_with(&keys[num], None, || {
+                        alloc.commit_delete(target, table_id, &keys[num])
+                    });
+                    if let Ok(Some(_)) = removed {
+                        freed += 1;
+                    }
+
+                    if (num + 1) % ADMIN_FREE_BATCH == 0 {
+                        yield 0;
+                    }
+                }
+            }
+
+            if freed > 0 {
+                info!("Freed {} objects of tenant {}", freed, target);
+            }
+            res.get_mut_header().objects = freed;
+
+            // Deparse request and response packets to UDP, and return from the generator.
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+        });
+
+        Ok(Box::new(Native::new(TaskPriority::ADMIN, gen)))
+    }
+
+    /// Handles the scan() RPC request.
+    ///
+    /// Objects in an ordered table whose keys fall between a start and end key are looked up in
+    /// key order, and written into the response. If they do not fit in a single packet, they are
+    /// spread across as many packets as required, each with it's own ScanResponse header, and
+    /// handed to the scheduler through the task's `tear_more()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unreachable_code)]
+    fn scan(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        let req = req.parse_header::<ScanRequest>();
+
+        let (tenant_id, table_id, start_length, end_length, limit, rpc_stamp) = {
+            let hdr = req.get_header();
+            (
+                hdr.common_header.tenant as TenantId,
+                hdr.table_id as TableId,
+                hdr.start_length as usize,
+                hdr.end_length as usize,
+                hdr.limit as usize,
+                hdr.common_header.stamp,
+            )
+        };
+
+        // The payload must consist of exactly the start and end key.
+        if req.get_payload().len() != start_length + end_length {
+            let mut res = res.push_header(&ScanResponse::new(
+                rpc_stamp,
+                OpCode::SandstormScanRpc,
+                tenant_id,
+                0,
+                0,
+                1,
+            )).expect("Failed to setup ScanResponse");
+            res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
+            return Err((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+        }
+
+        let limit = if self.scan_limit > 0 {
+            limit.min(self.scan_limit)
+        } else {
+            limit
+        };
+
+        let tenant = self.get_tenant(tenant_id);
+        let alloc = self.heap.clone();
+
+        // Packets beyond the first are handed to the task through this.
+        let more: Responses = Rc::new(RefCell::new(Vec::new()));
+        let extra = Rc::clone(&more);
+
+        let gen = Box::new(move || {
+            let mut status = RpcStatus::StatusTenantDoesNotExist;
+            let mut records = Vec::new();
+
+            let table = tenant.and_then(|tenant| {
+                status = RpcStatus::StatusTableDoesNotExist;
+                tenant.get_table(table_id)
+            });
+
+            // Only tables with an ordered index can be scanned.
+            if let Some(table) = table {
+                status = RpcStatus::StatusInvalidOperation;
+                if table.kind() == TableKind::Ordered {
+                    let (start, end) = req.get_payload().split_at(start_length);
+                    let end = if end.is_empty() { None } else { Some(end) };
+
+                    records = table
+                        .range(start, end, limit)
+                        .into_iter()
+                        .filter(|&(_, ref object)| !alloc.is_expired(object))
+                        .filter_map(|(_, object)| alloc.resolve(object))
+                        .collect();
+                    status = RpcStatus::StatusOk;
+                }
+            }
+
+            // Allocate a packet for every part of the response beyond the first. If any of them
+            // cannot be allocated, respond with just an error.
+            let mut counts = scan_packets(&records);
+            let mut res = res;
+            let mut copies = Vec::with_capacity(counts.len());
+            for _ in 1..counts.len() {
+                let (orig, copy) = rpc::copy_response_headers(res);
+                res = orig;
+                match copy {
+                    Some(copy) => copies.push(copy),
+                    None => break,
+                }
+            }
+
+            if copies.len() + 1 < counts.len() {
+                status = RpcStatus::StatusInternalError;
+                counts.clear();
+                for copy in copies.drain(..) {
+                    copy.free_packet();
+                }
+            }
+
+            let num_packets = counts.len().max(1) as u16;
+            let mut records = records.iter();
+
+            let mut res = res.push_header(&ScanResponse::new(
+                rpc_stamp,
+                OpCode::SandstormScanRpc,
+                tenant_id,
+                0,
+                0,
+                num_packets,
+            )).expect("Failed to setup ScanResponse");
+            let first = counts.first().cloned().unwrap_or(0);
+            if !add_scan_records(&mut res, records.by_ref().take(first)) {
+                status = RpcStatus::StatusInternalError;
+            }
+
+            let mut parts = Vec::with_capacity(copies.len());
+            let rest = copies.into_iter().zip(counts.into_iter().skip(1));
+            for (seq, (copy, count)) in rest.enumerate() {
+                let mut copy = copy.push_header(&ScanResponse::new(
+                    rpc_stamp,
+                    OpCode::SandstormScanRpc,
+                    tenant_id,
+                    0,
+                    seq as u16 + 1,
+                    num_packets,
+                )).expect("Failed to setup ScanResponse");
+                if !add_scan_records(&mut copy, records.by_ref().take(count)) {
+                    status = RpcStatus::StatusInternalError;
+                }
+                parts.push(copy);
+            }
+
+            // Every packet carries the status of the scan as a whole.
+            res.get_mut_header().common_header.status = status;
+            for mut part in parts.into_iter() {
+                part.get_mut_header().common_header.status = status;
+                extra.borrow_mut().push(part.deparse_header(PACKET_UDP_LEN as usize));
+            }
+
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        let mut task = Native::new(TaskPriority::REQUEST, gen);
+        task.set_more(more);
+        Ok(Box::new(task))
+    }
+
+    /// Handles the move_key() RPC request.
+    ///
+    /// If issued by a valid tenant for two valid tables, atomically moves an object from one of
+    /// them into the other. Refer to `Tenant::move_key()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unreachable_code)]
+    fn move_key(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        let req = req.parse_header::<MoveKeyRequest>();
+
+        let (tenant_id, src_table, dst_table, key_length, rpc_stamp) = {
+            let hdr = req.get_header();
+            (
+                hdr.common_header.tenant as TenantId,
+                hdr.src_table as TableId,
+                hdr.dst_table as TableId,
+                hdr.key_length as usize,
+                hdr.common_header.stamp,
+            )
+        };
+
+        let mut res = res.push_header(&MoveKeyResponse::new(
+            rpc_stamp,
+            OpCode::SandstormMoveKeyRpc,
+            tenant_id,
+        )).expect("Failed to setup MoveKeyResponse");
+
+        // The payload must consist of exactly the key.
+        if req.get_payload().len() != key_length {
+            res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
+            return Err((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+        }
+
+        // Moves write to both tables, and are not allowed in read-only mode.
+        if self.is_read_only() {
+            res.get_mut_header().common_header.status = RpcStatus::StatusReadOnly;
+            return Ok(self.respond(req, res));
+        }
+
+        // A move counts as a write to the destination table.
+        if let Some(bucket) = self.throttles.get(&(tenant_id, dst_table)) {
+            if !bucket.admit(cycles::rdtsc()) {
+                res.get_mut_header().common_header.status = RpcStatus::StatusThrottled;
+                return Ok(self.respond(req, res));
+            }
+        }
+
+        // Writes by a tenant whose group has used up it's memory quota are not allowed.
+        if self.heap.over_quota(tenant_id) {
+            res.get_mut_header().common_header.status = RpcStatus::StatusQuotaExceeded;
+            return Ok(self.respond(req, res));
+        }
+
+        let tenant = self.get_tenant(tenant_id);
+        let alloc = self.heap.clone();
+
+        let gen = Box::new(move || {
+            let status = match tenant {
+                Some(tenant) => tenant.move_key(&alloc, src_table, dst_table, req.get_payload()),
+                None => RpcStatus::StatusTenantDoesNotExist,
+            };
+
+            res.get_mut_header().common_header.status = status;
+
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)))
+    }
+
+    /// Handles the association RPC requests (add, del, range, and count). These are native
+    /// versions of TAO's primitive association operations, and save TAO workloads the overhead
+    /// of an invoke() on each of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`:    The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`:    The RPC response packet, with pre-allocated headers upto UDP.
+    /// * `opcode`: The opcode on the request. Must be one of the association opcodes.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unreachable_code)]
+    #[allow(unused_assignments)]
+    fn assoc(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+        opcode: OpCode,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        // First, parse the request packet.
+        let req = req.parse_header::<AssocRequest>();
+
+        // Read fields off the request header.
+        let mut tenant_id: TenantId = 0;
+        let mut id1: u64 = 0;
+        let mut atype: u16 = 0;
+        let mut id2: u64 = 0;
+        let mut time: u64 = 0;
+        let mut limit: usize = 0;
+        let mut rpc_stamp = 0;
+
+        {
+            let hdr = req.get_header();
+            tenant_id = hdr.common_header.tenant as TenantId;
+            id1 = hdr.id1;
+            atype = hdr.atype;
+            id2 = hdr.id2;
+            time = hdr.time;
+            limit = hdr.limit as usize;
+            rpc_stamp = hdr.common_header.stamp;
+        }
+
+        // The response carries the same opcode as the request.
+        let mutates = opcode == OpCode::SandstormAssocAddRpc
+            || opcode == OpCode::SandstormAssocDelRpc;
+        let ret = match opcode {
+            OpCode::SandstormAssocAddRpc => OpCode::SandstormAssocAddRpc,
+            OpCode::SandstormAssocDelRpc => OpCode::SandstormAssocDelRpc,
+            OpCode::SandstormAssocRangeRpc => OpCode::SandstormAssocRangeRpc,
+            _ => OpCode::SandstormAssocCountRpc,
+        };
+
+        // Next, add a header to the response packet.
+        let mut res = res.push_header(&AssocResponse::new(rpc_stamp, ret, tenant_id))
+            .expect("Failed to setup AssocResponse");
+
+        // Association lists cannot be modified in read-only mode.
+        if mutates && self.is_read_only() {
+            res.get_mut_header().common_header.status = RpcStatus::StatusReadOnly;
+            return Ok(self.respond(req, res));
+        }
+
+        // Lookup the tenant. Required to avoid capturing a reference to Master in the generator.
+        let tenant = self.get_tenant(tenant_id);
+
+        // Create a generator for this request.
+        let gen = Box::new(move || {
+            let mut count: usize = 0;
+            let mut status: RpcStatus = RpcStatus::StatusTenantDoesNotExist;
+
+            if let Some(tenant) = tenant {
+                status = RpcStatus::StatusOk;
+
+                match opcode {
+                    OpCode::SandstormAssocAddRpc => {
+                        let data = Bytes::from(req.get_payload());
+                        count = tenant.assoc_add(id1, atype, id2, time, data);
+                    }
+
+                    OpCode::SandstormAssocDelRpc => {
+                        count = tenant.assoc_del(id1, atype, id2) as usize;
+                    }
+
+                    OpCode::SandstormAssocRangeRpc => {
+                        // Add every association to the response payload. If the response packet
+                        // fills up, then return the ones that fit. The tenant can fetch the rest
+                        // with another range starting at the oldest one returned.
+                        for assoc in tenant.assoc_range(id1, atype, time, limit).iter() {
+                            let id2: [u8; 8] = unsafe { transmute(assoc.id2.to_le()) };
+                            let time: [u8; 8] = unsafe { transmute(assoc.time.to_le()) };
+                            let len: [u8; 4] =
+                                unsafe { transmute((assoc.data.len() as u32).to_le()) };
+
+                            let mut entry: Vec<u8> =
+                                Vec::with_capacity(ASSOC_ENTRY_HEADER_LEN + assoc.data.len());
+                            entry.extend_from_slice(&id2);
+                            entry.extend_from_slice(&time);
+                            entry.extend_from_slice(&len);
+                            entry.extend_from_slice(&assoc.data);
+
+                            if res.add_to_payload_tail(entry.len(), &entry[..]).is_err() {
+                                break;
+                            }
+
+                            count += 1;
+                        }
+                    }
+
+                    _ => {
+                        count = tenant.assoc_count(id1, atype);
+                    }
+                }
+            }
+
+            // Write the status and count into the RPC response header.
+            {
+                let hdr = res.get_mut_header();
+                hdr.common_header.status = status;
+                hdr.count = count as u32;
+            }
+
+            // Deparse request and response packets to UDP, and return from the generator.
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        // Create and return a native task.
+        return Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)));
+    }
+
+    /// Handles the invoke RPC request.
+    ///
+    /// If issued by a valid tenant for a valid extension, invokes the extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`: The RPC response packet, with pre-allocated headers upto UDP.
+    ///
+    /// # Return
+    ///
+    /// A Container task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned with the response status appropriately set.
+    #[allow(unused_assignments)]
+    fn invoke(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        res: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        // First, note the client that sent the request, and parse the request packet.
+        let (client, req) = client_of(req);
+        let req = req.parse_header::<InvokeRequest>();
+
+        // Read fields of the request header.
+        let mut tenant_id: TenantId = 0;
+        let mut name_length: usize = 0;
+        let mut args_length: usize = 0;
+        let mut rpc_stamp = 0;
+
+        {
+            let hdr = req.get_header();
+            tenant_id = hdr.common_header.tenant as TenantId;
+            name_length = hdr.name_length as usize;
+            args_length = hdr.args_length as usize;
+            rpc_stamp = hdr.common_header.stamp;
+        }
+
+        // Next, add a header to the response packet.
+        let mut res = res.push_header(&InvokeResponse::new(
+            rpc_stamp,
+            OpCode::SandstormInvokeRpc,
+            tenant_id,
+        )).expect("Failed to push InvokeResponse");
+
+        // If the payload size is less than the sum of the name and args
+        // length, return an error.
+        if req.get_payload().len() < name_length + args_length {
+            res.get_mut_header().common_header.status = RpcStatus::StatusMalformedRequest;
+            return Err((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+        }
+
+        // Read the extension's name from the request payload.
+        let mut name = Vec::new();
+        name.extend_from_slice(req.get_payload().split_at(name_length).0);
+        let name: String = String::from_utf8(name).expect("ERROR: Failed to get ext name.");
+
+        let mut status = RpcStatus::StatusTenantDoesNotExist;
 
         // Check if the request was issued by a valid tenant.
         if let Some(tenant) = self.get_tenant(tenant_id) {
@@ -863,6 +3541,59 @@ impl Master {
             // setting the RPC status appropriately.
             status = RpcStatus::StatusInvalidExtension;
             if let Some(ext) = self.extensions.get(tenant_id, &name) {
+                // In read-only mode, only extensions declared read-only can be invoked.
+                if self.is_read_only() && !ext.manifest().read_only {
+                    res.get_mut_header().common_header.status = RpcStatus::StatusReadOnly;
+                    return Ok(self.respond(req, res));
+                }
+
+                // Only so many invocations of a long running extension are admitted at once.
+                let long = ext.manifest().cost == CostClass::LONG;
+                if long && self.long_limit > 0 && ext.inflight() >= self.long_limit {
+                    res.get_mut_header().common_header.status = RpcStatus::StatusThrottled;
+                    return Ok(self.respond(req, res));
+                }
+
+                // If the extension is cacheable, try to service the invocation from the cache. Only
+                // extensions that declared themselves read-only are eligible.
+                let policy = if ext.manifest().read_only {
+                    self.results.policy(tenant_id, &name)
+                } else {
+                    None
+                };
+                let memo = policy.map(|policy| {
+                    let versions = policy.versions(&tenant);
+                    let args = req.get_payload()[name_length..name_length + args_length].to_vec();
+                    (policy, args, versions)
+                });
+
+                if let Some((_, ref args, ref versions)) = memo {
+                    let now = cycles::rdtsc();
+                    let hit = self.results.lookup(tenant_id, &name, args, versions, now);
+                    if let Some(result) = hit {
+                        return Ok(self.cached_invoke(req, res, result));
+                    }
+                }
+
+                // On a sample of invocations, also run the shadow version of the extension (if
+                // any) once this one completes. It runs on copies of the packets.
+                let shadow = self.extensions.shadow(tenant_id, &name).and_then(|shadow| {
+                    if !shadow.sampled() {
+                        return None;
+                    }
+
+                    shadow
+                        .invocation(
+                            &req,
+                            name_length,
+                            args_length,
+                            Arc::clone(&tenant),
+                            Arc::clone(&self.heap),
+                            Arc::clone(&self.reads),
+                        )
+                        .map(|run| (shadow, run))
+                });
+
                 let db = Rc::new(Context::new(
                     req,
                     name_length,
@@ -870,19 +3601,182 @@ impl Master {
                     res,
                     tenant,
                     Arc::clone(&self.heap),
+                    Arc::clone(&self.reads),
                 ));
+                if let Some(stamp) = self.write_stamp(client, rpc_stamp) {
+                    db.set_stamped(stamp);
+                }
 
-                return Ok(Box::new(Container::new(TaskPriority::REQUEST, db, ext)));
+                let task = Box::new(Container::new(TaskPriority::REQUEST, db, ext));
+
+                // Cache the result of the invocation once it completes.
+                let task: Box<Task> = match memo {
+                    Some((policy, args, versions)) => Box::new(Memoize::new(
+                        task,
+                        Arc::clone(&self.results),
+                        &policy,
+                        tenant_id,
+                        name,
+                        args,
+                        versions,
+                    )),
+
+                    None => task,
+                };
+
+                return Ok(match shadow {
+                    Some((shadow, run)) => Box::new(Shadowed::new(task, shadow, run)),
+                    None => task,
+                });
             }
         }
 
-        // A Task could not be created. Set the status of the RPC and return.
-        res.get_mut_header().common_header.status = status;
+        // A Task could not be created. Set the status of the RPC and return.
+        res.get_mut_header().common_header.status = status;
+
+        return Err((
+            req.deparse_header(PACKET_UDP_LEN as usize),
+            res.deparse_header(PACKET_UDP_LEN as usize),
+        ));
+    }
+
+    /// Creates a task that responds to an invocation with a cached result.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`:    The RPC request packet sent by the client, parsed upto it's invoke header.
+    /// * `res`:    The RPC response packet, with pre-allocated headers upto invoke.
+    /// * `result`: The cached result that the response payload should consist of.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database.
+    #[allow(unreachable_code)]
+    fn cached_invoke(
+        &self,
+        req: Packet<InvokeRequest, EmptyMetadata>,
+        mut res: Packet<InvokeResponse, EmptyMetadata>,
+        result: Vec<u8>,
+    ) -> Box<Task> {
+        let gen = Box::new(move || {
+            res.get_mut_header().common_header.status =
+                match res.add_to_payload_tail(result.len(), &result) {
+                    Ok(()) => RpcStatus::StatusOk,
+                    Err(_) => RpcStatus::StatusInternalError,
+                };
+
+            // Deparse request and response packets down to UDP, and return from the generator.
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        Box::new(Native::new(TaskPriority::REQUEST, gen))
+    }
+
+    /// Creates a task that responds to a request without doing anything else. Used to reply to
+    /// requests that were rejected where a task is otherwise created (ex: a put() in read-only
+    /// mode), so that the tenant still receives the status set on the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`: The RPC request packet sent by the client, parsed upto it's RPC header.
+    /// * `res`: The RPC response packet, with it's status already set.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database.
+    #[allow(unreachable_code)]
+    fn respond<T, U>(
+        &self,
+        req: Packet<T, EmptyMetadata>,
+        res: Packet<U, EmptyMetadata>,
+    ) -> Box<Task>
+    where
+        T: EndOffset<PreviousHeader = UdpHeader> + 'static,
+        U: EndOffset<PreviousHeader = UdpHeader> + 'static,
+    {
+        let gen = Box::new(move || {
+            // Deparse request and response packets down to UDP, and return from the generator.
+            return Some((
+                req.deparse_header(PACKET_UDP_LEN as usize),
+                res.deparse_header(PACKET_UDP_LEN as usize),
+            ));
+
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        Box::new(Native::new(TaskPriority::REQUEST, gen))
+    }
+
+    // Returns the address requests from a tenant should be redirected to if it was migrated to
+    // another server. The tenant is only looked up if some tenant was migrated away.
+    #[inline]
+    fn moved_to(&self, req: &Packet<UdpHeader, EmptyMetadata>) -> Option<Vec<u8>> {
+        if self.num_moved.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+
+        self.moved.read().get(&parse_rpc_tenant(req)).cloned()
+    }
+
+    /// Responds to a request from a tenant that was migrated to another server with
+    /// `StatusMoved`. The response carries a header as long as the one for the request's opcode,
+    /// zeroed beyond the common header, followed by the address the tenant now lives at.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`:  The RPC request packet sent by the client, parsed upto it's UDP header.
+    /// * `res`:  The RPC response packet, with pre-allocated headers upto UDP.
+    /// * `addr`: The address (IPv4:Port) the request should be reissued at.
+    ///
+    /// # Return
+    ///
+    /// A Native task that can be scheduled by the database. In the case of an error, the passed
+    /// in request and response packets are returned.
+    #[allow(unreachable_code)]
+    fn redirect(
+        &self,
+        req: Packet<UdpHeader, EmptyMetadata>,
+        mut res: Packet<UdpHeader, EmptyMetadata>,
+        addr: Vec<u8>,
+    ) -> Result<
+        Box<Task>,
+        (
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        ),
+    > {
+        let opcode = parse_rpc_opcode(&req);
+        let res_len = response_header_len(&opcode);
+
+        let mut hdr = RpcResponseHeader::new(parse_rpc_stamp(&req), opcode, parse_rpc_tenant(&req));
+        hdr.status = RpcStatus::StatusMoved;
+        let hdr: [u8; size_of::<RpcResponseHeader>()] = unsafe { transmute(hdr) };
+
+        let mut payload = hdr.to_vec();
+        payload.resize(res_len, 0);
+        payload.extend_from_slice(&addr);
+        if res.add_to_payload_tail(payload.len(), &payload).is_err() {
+            return Err((req, res));
+        }
+
+        let gen = Box::new(move || {
+            return Some((req, res));
 
-        return Err((
-            req.deparse_header(PACKET_UDP_LEN as usize),
-            res.deparse_header(PACKET_UDP_LEN as usize),
-        ));
+            // XXX: This yield is required to get the compiler to compile this closure into a
+            // generator. It is unreachable and benign.
+            yield 0;
+        });
+
+        Ok(Box::new(Native::new(TaskPriority::REQUEST, gen)))
     }
 
     /// Handles the install() RPC request.
@@ -903,21 +3797,42 @@ impl Master {
         let tenant: TenantId;
         let name_l: usize;
         let extn_l: usize;
+        let cache_ttl: u64;
+        let tables_l: usize;
         let tstamp: u64;
+        let hdr_l: usize;
+        let cost: Option<CostClass>;
+        let read_only: bool;
 
         unsafe {
             tenant = (*hdr).common_header.tenant as TenantId;
+            hdr_l = (*hdr).common_header.header_len as usize;
             name_l = (*hdr).name_length as usize;
             extn_l = (*hdr).extn_length as usize;
+            cache_ttl = (*hdr).cache_ttl as u64;
+            tables_l = (*hdr).tables_length as usize;
             tstamp = (*hdr).common_header.stamp;
+            cost = CostClass::from_u8((*hdr).cost_class);
+            read_only = (*hdr).read_only != 0 || cache_ttl > 0;
         }
 
         // Create a response for the tenant.
         let mut res = InstallResponse::new(tstamp, OpCode::SandstormInstallRpc, tenant as u32);
         res.common_header.status = RpcStatus::StatusTenantDoesNotExist;
 
-        // Check if the tenant provided lengths match the actual request length.
-        if buf.len() != size_of::<InstallRequest>() + name_l + extn_l {
+        // Check if the tenant provided lengths match the actual request length. The header may
+        // be longer than the one known to this build, but never shorter.
+        let tables_b = tables_l * size_of::<TableId>();
+        // Extensions must declare a valid cost class, and a known one if manifests are required.
+        let undeclared = match cost {
+            None => true,
+            Some(CostClass::UNKNOWN) => self.require_manifest,
+            Some(_) => false,
+        };
+        if hdr_l < size_of::<InstallRequest>()
+            || buf.len() != hdr_l + name_l + extn_l + tables_b
+            || undeclared
+        {
             res.common_header.status = RpcStatus::StatusMalformedRequest;
             let res: [u8; size_of::<InstallResponse>()] = unsafe { transmute(res) };
             let mut ret: Vec<u8> = Vec::new();
@@ -925,13 +3840,32 @@ impl Master {
             return ret;
         }
 
+        // Extensions cannot be installed in read-only mode.
+        if self.is_read_only() {
+            res.common_header.status = RpcStatus::StatusReadOnly;
+            let res: [u8; size_of::<InstallResponse>()] = unsafe { transmute(res) };
+            let mut ret: Vec<u8> = Vec::new();
+            ret.extend_from_slice(&res);
+            return ret;
+        }
+
         // Save the extension to a .so file. If all goes well, load it into the server.
         if let Some(_) = self.get_tenant(tenant) {
             res.common_header.status = RpcStatus::StatusInternalError;
 
-            let (_, payload) = buf.split_at(size_of::<InstallRequest>());
+            let (_, payload) = buf.split_at(hdr_l);
             let (name, payload) = payload.split_at(name_l);
-            let (extn, _) = payload.split_at(extn_l);
+            let (extn, payload) = payload.split_at(extn_l);
+
+            // Read the identifiers of the tables the extension reads from.
+            let tables: Vec<TableId> = payload
+                .chunks(size_of::<TableId>())
+                .map(|id| {
+                    let mut t: [u8; 8] = [0; 8];
+                    t.copy_from_slice(id);
+                    u64::from_le(unsafe { transmute(t) })
+                })
+                .collect();
 
             if let Ok(name) = from_utf8(name) {
                 let mut path = String::new();
@@ -945,7 +3879,14 @@ impl Master {
                 let _ = file.write_all(extn).unwrap();
                 let _ = file.sync_all().unwrap();
 
-                if self.extensions.load(&path, tenant, name) {
+                let manifest = Manifest {
+                    cost: cost.unwrap_or(CostClass::UNKNOWN),
+                    read_only: read_only,
+                    tables: tables.clone(),
+                };
+
+                if self.extensions.load_with_manifest(&path, tenant, name, manifest) {
+                    self.results.declare(tenant, name, cache_ttl, tables);
                     res.common_header.status = RpcStatus::StatusOk;
                 }
             }
@@ -956,6 +3897,783 @@ impl Master {
         ret.extend_from_slice(&res);
         return ret;
     }
+
+    /// Handles the shadow() RPC request, issued by tenants validating a new version of one of
+    /// their extensions against live invocations before it replaces the installed version.
+    ///
+    /// Depending on the request, a shadow version is installed, it's sampling rate is changed, it
+    /// is promoted to replace the installed version, or it is removed. Refer to `ShadowRequest`.
+    /// Installing and promoting a shadow version is not allowed in read-only mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf`: The RPC buffer consisting of the request header followed by the payload.
+    ///
+    /// # Return
+    ///
+    /// A response buffer that can be sent back to the tenant. It carries how the shadow version
+    /// compared against the installed version up until the request was received.
+    pub fn shadow(&self, buf: Vec<u8>) -> Vec<u8> {
+        let (tenant, name_l, extn_l, sample, promote, tstamp, hdr_l) =
+            if buf.len() < size_of::<ShadowRequest>() {
+                (0, 0, 0, 0, false, 0, 0)
+            } else {
+                let hdr = buf.as_ptr() as *const ShadowRequest;
+                unsafe {
+                    (
+                        (*hdr).common_header.tenant as TenantId,
+                        (*hdr).name_length as usize,
+                        (*hdr).extn_length as usize,
+                        (*hdr).sample,
+                        (*hdr).promote != 0,
+                        (*hdr).common_header.stamp,
+                        (*hdr).common_header.header_len as usize,
+                    )
+                }
+            };
+
+        let mut res = ShadowResponse::new(tstamp, OpCode::SandstormShadowRpc, tenant as u32);
+        res.common_header.status = RpcStatus::StatusMalformedRequest;
+
+        // A shadow version cannot be installed and promoted at once, and must be run on some
+        // invocations when installed.
+        let valid = hdr_l >= size_of::<ShadowRequest>()
+            && buf.len() == hdr_l + name_l + extn_l
+            && !(promote && extn_l > 0)
+            && (extn_l == 0 || sample > 0);
+        let name = if valid {
+            from_utf8(&buf[hdr_l..hdr_l + name_l]).ok()
+        } else {
+            None
+        };
+
+        if let Some(name) = name {
+            let extn = &buf[hdr_l + name_l..];
+            let (status, stats) = self.update_shadow(tenant, name, extn, sample, promote);
+
+            res.common_header.status = status;
+            res.runs = stats.runs;
+            res.mismatches = stats.mismatches;
+            res.primary_cycles = stats.primary_cycles;
+            res.shadow_cycles = stats.shadow_cycles;
+        }
+
+        let res: [u8; size_of::<ShadowResponse>()] = unsafe { transmute(res) };
+        res.to_vec()
+    }
+
+    // Carries out a validated shadow() request. Returns the status of the request, and how the
+    // shadow version compared against the installed version before the request.
+    fn update_shadow(
+        &self,
+        tenant: TenantId,
+        name: &str,
+        extn: &[u8],
+        sample: u32,
+        promote: bool,
+    ) -> (RpcStatus, ShadowStats) {
+        if self.get_tenant(tenant).is_none() {
+            return (RpcStatus::StatusTenantDoesNotExist, ShadowStats::default());
+        }
+
+        if self.extensions.get(tenant, name).is_none() {
+            return (RpcStatus::StatusInvalidExtension, ShadowStats::default());
+        }
+
+        let shadow = self.extensions.shadow(tenant, name);
+        let stats = shadow
+            .as_ref()
+            .map_or(ShadowStats::default(), |shadow| shadow.stats());
+
+        // Both change the code that can run against the database.
+        if (promote || !extn.is_empty()) && self.is_read_only() {
+            return (RpcStatus::StatusReadOnly, stats);
+        }
+
+        if !extn.is_empty() {
+            // dlopen() hands back the library already loaded off a path, so every shadow
+            // version is written to a path of it's own.
+            let path = format!("/tmp/{}.{}.{}.so", name, tenant, cycles::rdtsc());
+            let mut file = File::create(path.clone()).unwrap();
+            let _ = file.write_all(extn).unwrap();
+            let _ = file.sync_all().unwrap();
+
+            if !self.extensions.load_shadow(&path, tenant, name, sample) {
+                return (RpcStatus::StatusInternalError, stats);
+            }
+
+            info!("Tenant {}: shadowing extension {} on 1 in {} invocations", tenant, name, sample);
+            return (RpcStatus::StatusOk, stats);
+        }
+
+        let shadow = match shadow {
+            Some(shadow) => shadow,
+            None => return (RpcStatus::StatusInvalidExtension, stats),
+        };
+
+        if promote {
+            if self.extensions.promote(tenant, name).is_none() {
+                return (RpcStatus::StatusInvalidExtension, stats);
+            }
+
+            // Results cached for the replaced version may not be what the new one computes.
+            self.results.forget(tenant, name);
+            info!(
+                "Tenant {}: promoted shadow of extension {} after {} runs, {} mismatches",
+                tenant, name, stats.runs, stats.mismatches
+            );
+        } else if sample > 0 {
+            shadow.set_sample(sample);
+        } else {
+            self.extensions.remove_shadow(tenant, name);
+        }
+
+        (RpcStatus::StatusOk, stats)
+    }
+
+    /// Handles the schema() RPC request, issued by tenants describing the layout of the values
+    /// on one of their tables to their extensions.
+    ///
+    /// Registers the schema on the request for the table, replacing any registered earlier, or
+    /// removes the registered schema if the request does not carry one. Schemas are held in
+    /// memory only, and must be registered again after a restart. Not allowed in read-only mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf`: The RPC buffer consisting of the request header followed by the payload.
+    ///
+    /// # Return
+    ///
+    /// A response buffer that can be sent back to the tenant.
+    pub fn schema(&self, buf: Vec<u8>) -> Vec<u8> {
+        let (tenant, table_id, schema_l, tstamp, hdr_l) =
+            if buf.len() < size_of::<SchemaRequest>() {
+                (0, 0, 0, 0, 0)
+            } else {
+                let hdr = buf.as_ptr() as *const SchemaRequest;
+                unsafe {
+                    (
+                        (*hdr).common_header.tenant as TenantId,
+                        (*hdr).table_id as TableId,
+                        (*hdr).schema_length as usize,
+                        (*hdr).common_header.stamp,
+                        (*hdr).common_header.header_len as usize,
+                    )
+                }
+            };
+
+        let mut res = SchemaResponse::new(tstamp, OpCode::SandstormSchemaRpc, tenant as u32);
+        res.common_header.status = RpcStatus::StatusMalformedRequest;
+
+        // An empty payload removes the registered schema.
+        let schema = if hdr_l < size_of::<SchemaRequest>() || buf.len() != hdr_l + schema_l {
+            None
+        } else if schema_l == 0 {
+            Some(None)
+        } else {
+            Schema::decode(&buf[hdr_l..]).map(|schema| Some(schema))
+        };
+
+        if let Some(schema) = schema {
+            res.common_header.status = if self.is_read_only() {
+                RpcStatus::StatusReadOnly
+            } else {
+                match self.get_tenant(tenant) {
+                    Some(tenant) if tenant.set_schema(table_id, schema) => RpcStatus::StatusOk,
+                    Some(_) => RpcStatus::StatusTableDoesNotExist,
+                    None => RpcStatus::StatusTenantDoesNotExist,
+                }
+            };
+        }
+
+        let res: [u8; size_of::<SchemaResponse>()] = unsafe { transmute(res) };
+        res.to_vec()
+    }
+
+    /// Handles the snapshot() RPC request.
+    ///
+    /// Writes every object in one of a tenant's tables (keys, values and versions) into a
+    /// snapshot laid out by `snapshot::TableWriter`. The snapshot is written into a file on the
+    /// server if the request carries a path, and streamed back right after the response header
+    /// otherwise. Like snapshots of the database, the snapshot is fuzzy; objects written while it
+    /// is being taken may or may not be picked up.
+    ///
+    /// This method blocks until the snapshot is written out, and must not be called on a core
+    /// that requests are dispatched or scheduled on.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf`: The RPC buffer consisting of the request header followed by the payload.
+    /// * `out`: The stream the response is written out to.
+    ///
+    /// # Return
+    ///
+    /// An error if the response could not be written out to the stream.
+    pub fn snapshot_table<W: Write>(&self, buf: Vec<u8>, out: &mut W) -> io::Result<()> {
+        let (tenant_id, table_id, path_l, tstamp, hdr_l) =
+            if buf.len() < size_of::<SnapshotRequest>() {
+                (0, 0, 0, 0, 0)
+            } else {
+                let hdr = buf.as_ptr() as *const SnapshotRequest;
+                unsafe {
+                    (
+                        (*hdr).common_header.tenant as TenantId,
+                        (*hdr).table_id as TableId,
+                        (*hdr).path_length as usize,
+                        (*hdr).common_header.stamp,
+                        (*hdr).common_header.header_len as usize,
+                    )
+                }
+            };
+
+        let mut res = SnapshotResponse::new(tstamp, OpCode::SandstormSnapshotRpc, tenant_id);
+        res.common_header.status = RpcStatus::StatusMalformedRequest;
+
+        let path = if hdr_l < size_of::<SnapshotRequest>() || buf.len() != hdr_l + path_l {
+            None
+        } else {
+            from_utf8(&buf[hdr_l..]).ok()
+        };
+
+        // The table to stream back after the response header, if any.
+        let mut stream = None;
+
+        if let Some(path) = path {
+            res.common_header.status = RpcStatus::StatusTenantDoesNotExist;
+            let table = self.get_tenant(tenant_id).and_then(|tenant| {
+                res.common_header.status = RpcStatus::StatusTableDoesNotExist;
+                tenant.get_table(table_id)
+            });
+
+            if let Some(table) = table {
+                res.common_header.status = RpcStatus::StatusOk;
+                if path.is_empty() {
+                    stream = Some(table);
+                } else {
+                    let written = File::create(path).and_then(|file| {
+                        let writer = TableWriter::new(BufWriter::new(file), tenant_id, table_id)?;
+                        let (records, file) = self.write_table(&table, writer)?;
+                        file.into_inner()?.sync_all()?;
+                        Ok(records)
+                    });
+
+                    match written {
+                        Ok(records) => res.num_records = records,
+
+                        Err(e) => {
+                            warn!("Failed to snapshot table {} to {}: {}", table_id, path, e);
+                            res.common_header.status = RpcStatus::StatusInternalError;
+                        }
+                    }
+                }
+            }
+        }
+
+        let res: [u8; size_of::<SnapshotResponse>()] = unsafe { transmute(res) };
+        out.write_all(&res)?;
+
+        if let Some(table) = stream {
+            let writer = TableWriter::new(BufWriter::new(out), tenant_id, table_id)?;
+            self.write_table(&table, writer)?;
+        }
+
+        Ok(())
+    }
+
+    // Writes every object in a table into a snapshot, and finishes the snapshot. Returns the
+    // number of objects written along with the file or stream they were written to.
+    fn write_table<W: Write>(
+        &self,
+        table: &Table,
+        mut writer: TableWriter<W>,
+    ) -> io::Result<(u64, W)> {
+        let mut res = Ok(());
+        table.scan(|object| {
+            if res.is_ok() {
+                res = writer.object(&object);
+            }
+        });
+        res?;
+
+        let records = writer.records();
+        writer.finish().map(|out| (records, out))
+    }
+
+    /// Handles the restore() RPC request.
+    ///
+    /// Repopulates one of a tenant's tables from a snapshot taken by the snapshot() RPC, read
+    /// off a file on the server if the request carries a path, and off the rest of the request
+    /// otherwise. The tenant and table are created if required. Objects are restored at the
+    /// versions they were snapshotted at, replacing any objects under the same keys. If the
+    /// snapshot turns out to be corrupt, the objects restored before the corruption remain.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf`: The RPC buffer consisting of the request header followed by the payload.
+    ///
+    /// # Return
+    ///
+    /// A response buffer that can be sent back to the client.
+    pub fn restore_table(&self, buf: Vec<u8>) -> Vec<u8> {
+        let (tenant_id, table_id, path_l, tstamp, hdr_l) =
+            if buf.len() < size_of::<RestoreRequest>() {
+                (0, 0, 0, 0, 0)
+            } else {
+                let hdr = buf.as_ptr() as *const RestoreRequest;
+                unsafe {
+                    (
+                        (*hdr).common_header.tenant as TenantId,
+                        (*hdr).table_id as TableId,
+                        (*hdr).path_length as usize,
+                        (*hdr).common_header.stamp,
+                        (*hdr).common_header.header_len as usize,
+                    )
+                }
+            };
+
+        let mut res = RestoreResponse::new(tstamp, OpCode::SandstormRestoreRpc, tenant_id);
+        res.common_header.status = RpcStatus::StatusMalformedRequest;
+
+        let path = if hdr_l < size_of::<RestoreRequest>() || buf.len() < hdr_l + path_l {
+            None
+        } else {
+            from_utf8(&buf[hdr_l..hdr_l + path_l]).ok()
+        };
+
+        if let Some(path) = path {
+            let restored = if self.is_read_only() {
+                res.common_header.status = RpcStatus::StatusReadOnly;
+                None
+            } else if path.is_empty() {
+                Some(self.restore_objects(tenant_id, table_id, &buf[hdr_l..]))
+            } else {
+                Some(File::open(path).and_then(|file| {
+                    self.restore_objects(tenant_id, table_id, BufReader::new(file))
+                }))
+            };
+
+            match restored {
+                Some(Ok(records)) => {
+                    res.common_header.status = RpcStatus::StatusOk;
+                    res.num_records = records;
+                }
+
+                Some(Err(e)) => {
+                    warn!("Failed to restore table {} of tenant {}: {}", table_id, tenant_id, e);
+                    res.common_header.status = if e.kind() == ErrorKind::InvalidData {
+                        RpcStatus::StatusMalformedRequest
+                    } else {
+                        RpcStatus::StatusInternalError
+                    };
+                }
+
+                None => {}
+            }
+        }
+
+        let res: [u8; size_of::<RestoreResponse>()] = unsafe { transmute(res) };
+        res.to_vec()
+    }
+
+    // Restores every object in a table's snapshot. The snapshot, and every object on it, must
+    // belong to the table being restored. Returns the number of objects restored.
+    fn restore_objects<R: Read>(
+        &self,
+        tenant_id: TenantId,
+        table_id: TableId,
+        input: R,
+    ) -> io::Result<u64> {
+        let mut reader = TableReader::new(input)?;
+        if reader.table() != (tenant_id, table_id) {
+            return Err(Error::new(ErrorKind::InvalidData, "Snapshot is of a different table."));
+        }
+
+        // Objects start with the tenant and table they belong to.
+        let t: [u8; 4] = unsafe { transmute(tenant_id.to_le()) };
+        let id: [u8; 8] = unsafe { transmute(table_id.to_le()) };
+
+        let mut records = 0;
+        while let Some(object) = reader.next()? {
+            if object.len() < 12 || object[0..4] != t || object[4..12] != id {
+                return Err(Error::new(ErrorKind::InvalidData, "Object of a different table."));
+            }
+
+            self.restore_object(object)?;
+            records += 1;
+        }
+
+        Ok(records)
+    }
+
+    /// Handles the bulk_put() RPC request, issued by servers migrating a tenant to this one.
+    ///
+    /// Applies every record on the request in order, creating the tenant and it's tables if
+    /// required. Refer to `migrate::decode()` for the layout of the payload. Requests issued by
+    /// tenants over the data path are handled by `tenant_bulk_put()` instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf`: The RPC buffer consisting of the request header followed by the payload.
+    ///
+    /// # Return
+    ///
+    /// A response buffer that can be sent back to the migrating server.
+    pub fn bulk_put(&self, buf: Vec<u8>) -> Vec<u8> {
+        let (tenant, num_records, tstamp, hdr_l) = if buf.len() < size_of::<BulkPutRequest>() {
+            (0, 0, 0, 0)
+        } else {
+            let hdr = buf.as_ptr() as *const BulkPutRequest;
+            unsafe {
+                (
+                    (*hdr).common_header.tenant as TenantId,
+                    (*hdr).num_records as usize,
+                    (*hdr).common_header.stamp,
+                    (*hdr).common_header.header_len as usize,
+                )
+            }
+        };
+
+        let mut res = BulkPutResponse::new(tstamp, OpCode::SandstormBulkPutRpc, tenant as u32);
+        res.common_header.status = RpcStatus::StatusMalformedRequest;
+
+        let records = if hdr_l < size_of::<BulkPutRequest>() || buf.len() < hdr_l {
+            None
+        } else {
+            migrate::decode(&buf[hdr_l..], num_records)
+        };
+
+        if let Some(records) = records {
+            res.common_header.status = if self.is_read_only() {
+                RpcStatus::StatusReadOnly
+            } else {
+                RpcStatus::StatusOk
+            };
+
+            // The tenant is being moved (back) onto this server, so stop redirecting it.
+            // XXX: Requests that were redirected here early are serviced off whatever has been
+            // copied over so far.
+            if res.common_header.status == RpcStatus::StatusOk
+                && self.moved.write().remove(&tenant).is_some()
+            {
+                self.num_moved.fetch_sub(1, Ordering::Relaxed);
+            }
+
+            for record in records.iter() {
+                if res.common_header.status != RpcStatus::StatusOk {
+                    break;
+                }
+
+                let table = self.get_or_create_table(tenant, record.table);
+                match record.value {
+                    Some(val) => match self.heap.object(tenant, record.table, record.key, val) {
+                        Some((key, obj)) => {
+                            if !table.put_if(record.key, |_| self.heap.commit(key, obj)) {
+                                res.common_header.status = RpcStatus::StatusLogFull;
+                                continue;
+                            }
+                        }
+
+                        None => {
+                            res.common_header.status = RpcStatus::StatusInternalError;
+                            continue;
+                        }
+                    },
+
+                    None => {
+                        let removed = table.delete_with(record.key, None, || {
+                            self.heap.commit_delete(tenant, record.table, record.key)
+                        });
+                        if removed.is_err() {
+                            res.common_header.status = RpcStatus::StatusLogFull;
+                            continue;
+                        }
+                    }
+                }
+
+                res.num_records += 1;
+            }
+        }
+
+        let res: [u8; size_of::<BulkPutResponse>()] = unsafe { transmute(res) };
+        res.to_vec()
+    }
+
+    /// Handles the migrate() RPC request.
+    ///
+    /// Copies every table belonging to a tenant over to a destination server through bulk_put()
+    /// requests while the tenant continues to be serviced here. Keys written to while the copy
+    /// is in progress are copied again in catch-up rounds until few enough remain. From then on,
+    /// the tenant's requests are responded to with `StatusMoved` and the address of the
+    /// destination, and a final catch-up round copies over anything written by requests that
+    /// were already in flight. The tenant is then dropped from this server. Extensions are not
+    /// migrated, and must be installed on the destination separately.
+    ///
+    /// This method blocks until the migration completes, and must not be called on a core that
+    /// requests are dispatched or scheduled on.
+    ///
+    /// XXX: Tables created after the migration starts are not copied over. Objects dropped from
+    /// a persistent heap are recovered on a restart.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf`: The RPC buffer consisting of the request header followed by the payload.
+    ///
+    /// # Return
+    ///
+    /// A response buffer that can be sent back to the client that initiated the migration.
+    pub fn migrate(&self, buf: Vec<u8>) -> Vec<u8> {
+        let (tenant_id, dest_l, redirect_l, tstamp, hdr_l) =
+            if buf.len() < size_of::<MigrateRequest>() {
+                (0, 0, 0, 0, 0)
+            } else {
+                let hdr = buf.as_ptr() as *const MigrateRequest;
+                unsafe {
+                    (
+                        (*hdr).common_header.tenant as TenantId,
+                        (*hdr).dest_length as usize,
+                        (*hdr).redirect_length as usize,
+                        (*hdr).common_header.stamp,
+                        (*hdr).common_header.header_len as usize,
+                    )
+                }
+            };
+
+        let mut res = MigrateResponse::new(tstamp, OpCode::SandstormMigrateRpc, tenant_id as u32);
+        res.common_header.status = RpcStatus::StatusMalformedRequest;
+
+        let addrs = if hdr_l < size_of::<MigrateRequest>()
+            || buf.len() != hdr_l + dest_l + redirect_l
+        {
+            None
+        } else {
+            let (dest, redirect) = buf[hdr_l..].split_at(dest_l);
+            from_utf8(dest).ok().map(|dest| (dest, redirect))
+        };
+
+        if let Some((dest, redirect)) = addrs {
+            res.common_header.status = RpcStatus::StatusTenantDoesNotExist;
+
+            if let Some(tenant) = self.get_tenant(tenant_id) {
+                match self.migrate_tenant(&tenant, dest, redirect) {
+                    Ok(records) => {
+                        res.common_header.status = RpcStatus::StatusOk;
+                        res.num_records = records;
+                    }
+
+                    Err(e) => {
+                        warn!("Failed to migrate tenant {} to {}: {}", tenant_id, dest, e);
+                        res.common_header.status = RpcStatus::StatusInternalError;
+                    }
+                }
+            }
+        }
+
+        let res: [u8; size_of::<MigrateResponse>()] = unsafe { transmute(res) };
+        res.to_vec()
+    }
+
+    // Migrates a tenant to a destination server, and starts redirecting it's requests. Refer to
+    // `migrate()`. Returns the number of records sent to the destination. If the migration
+    // fails, the tenant continues to be serviced here.
+    fn migrate_tenant(&self, tenant: &Tenant, dest: &str, redirect: &[u8]) -> io::Result<u64> {
+        let tables = tenant.tables();
+        let mut batch = Batch::new(dest, tenant.id());
+
+        // Keys written to from here on are recorded, and copied over again by a catch-up round.
+        for &(_, ref table) in tables.iter() {
+            table.track_dirty(true);
+        }
+
+        let mut res = self.migrate_copy(&tables, &mut batch);
+        for _ in 0..MIGRATE_ROUNDS {
+            let dirty = match res {
+                Ok(dirty) => dirty,
+                Err(_) => break,
+            };
+            if dirty <= MIGRATE_SETTLED {
+                break;
+            }
+
+            res = self.migrate_catch_up(&tables, &mut batch);
+        }
+
+        if res.is_ok() {
+            // Redirect the tenant, and wait for requests that might still write to it's tables
+            // to drain out before the final round.
+            if self.moved.write().insert(tenant.id(), redirect.to_vec()).is_none() {
+                self.num_moved.fetch_add(1, Ordering::Relaxed);
+            }
+            thread::sleep(Duration::from_millis(MIGRATE_DRAIN_MS));
+
+            res = self.migrate_catch_up(&tables, &mut batch);
+            if res.is_ok() {
+                self.remove_tenant(tenant.id());
+            } else if self.moved.write().remove(&tenant.id()).is_some() {
+                self.num_moved.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        for &(_, ref table) in tables.iter() {
+            table.track_dirty(false);
+        }
+
+        res.map(|_| batch.sent())
+    }
+
+    // Copies every object in a set of tables into a batch, and sends it out. Returns the number
+    // of keys dirtied while the copy was in progress.
+    fn migrate_copy(&self, tables: &Vec<(TableId, Arc<Table>)>, batch: &mut Batch)
+        -> io::Result<usize>
+    {
+        for &(table_id, ref table) in tables.iter() {
+            let mut res = Ok(());
+            table.scan(|object| {
+                if res.is_ok() {
+                    if let Some((key, val)) = self.heap.resolve(object) {
+                        res = batch.add(table_id, &key, Some(&val[..]));
+                    }
+                }
+            });
+            res?;
+        }
+        batch.flush()?;
+
+        Ok(tables.iter().map(|&(_, ref table)| table.dirty_len()).sum())
+    }
+
+    // Copies the current version of every dirty key in a set of tables into a batch, and sends
+    // it out. Keys that no longer exist are deleted at the destination. Returns the number of
+    // keys dirtied while the round was in progress.
+    fn migrate_catch_up(&self, tables: &Vec<(TableId, Arc<Table>)>, batch: &mut Batch)
+        -> io::Result<usize>
+    {
+        for &(table_id, ref table) in tables.iter() {
+            for key in table.take_dirty().iter() {
+                match table.get(key).and_then(|object| self.heap.resolve(object)) {
+                    Some((_, val)) => batch.add(table_id, key, Some(&val[..]))?,
+                    None => batch.add(table_id, key, None)?,
+                }
+            }
+        }
+        batch.flush()?;
+
+        Ok(tables.iter().map(|&(_, ref table)| table.dirty_len()).sum())
+    }
+}
+
+// Returns the IPv4 address of the client that sent a request, along with the request. Requests
+// received over IPv6 carry the address they were translated to (refer to `Ipv6Clients`).
+fn client_of(
+    req: Packet<UdpHeader, EmptyMetadata>,
+) -> (u32, Packet<UdpHeader, EmptyMetadata>) {
+    let ip = req.deparse_header(size_of::<IpHeader>());
+    let client = ip.get_header().src();
+    (client, ip.parse_header::<UdpHeader>())
+}
+
+// Applies a record on a bulk_put() issued by a tenant to the table it belongs to, returning the
+// status to respond with. Objects and deletions are committed with the key's bucket locked, so
+// that they are logged in the order they are applied to the table.
+fn write_record(
+    alloc: &Allocator,
+    tenant: TenantId,
+    table: &Table,
+    record: &BulkRecord,
+    stamp: Option<&WriteStamp>,
+) -> RpcStatus {
+    let val = match record.value {
+        Some(val) => val,
+        None => {
+            let removed = table.delete_with(record.key, stamp, || {
+                alloc.commit_delete(tenant, record.table, record.key)
+            });
+
+            return match removed {
+                Ok(_) => RpcStatus::StatusOk,
+                Err(()) => RpcStatus::StatusLogFull,
+            };
+        }
+    };
+
+    let (k, obj) = match alloc.object(tenant, record.table, record.key, val) {
+        Some(object) => object,
+        None => return RpcStatus::StatusInternalError,
+    };
+
+    let refused = Cell::new(false);
+    let commit = || {
+        let committed = alloc.commit(k, obj);
+        refused.set(committed.is_none());
+        committed
+    };
+
+    let written = match stamp {
+        Some(stamp) => table.put_stamped(record.key, stamp, commit),
+        None => table.put_if(record.key, |_| commit()),
+    };
+
+    match (written, refused.get()) {
+        (true, _) => RpcStatus::StatusOk,
+        (false, true) => RpcStatus::StatusLogFull,
+        (false, false) => RpcStatus::StatusStaleWrite,
+    }
+}
+
+// Splits the objects returned by a scan() into packets. Returns the number of objects on each
+// packet, in order. Objects from the first one that is too large to fit in a packet of it's own
+// onwards are left out of the response.
+fn scan_packets(records: &Vec<(Bytes, Bytes)>) -> Vec<usize> {
+    let mut counts = Vec::new();
+    let (mut count, mut bytes) = (0, 0);
+
+    for &(ref key, ref value) in records.iter() {
+        let len = SCAN_RECORD_HEADER_LEN + key.len() + value.len();
+        if len > SCAN_PAYLOAD {
+            break;
+        }
+
+        if bytes + len > SCAN_PAYLOAD {
+            counts.push(count);
+            count = 0;
+            bytes = 0;
+        }
+
+        count += 1;
+        bytes += len;
+    }
+
+    if count > 0 {
+        counts.push(count);
+    }
+
+    counts
+}
+
+// Writes objects returned by a scan() into a response packet, and updates the number of records
+// on it's header. Returns false if they could not be written.
+fn add_scan_records<'a, I>(res: &mut Packet<ScanResponse, EmptyMetadata>, records: I) -> bool
+where
+    I: Iterator<Item = &'a (Bytes, Bytes)>,
+{
+    let mut payload = Vec::with_capacity(SCAN_PAYLOAD);
+    let mut count: u32 = 0;
+
+    for &(ref key, ref value) in records {
+        let k_len: [u8; 2] = unsafe { transmute((key.len() as u16).to_le()) };
+        let v_len: [u8; 4] = unsafe { transmute((value.len() as u32).to_le()) };
+        payload.extend_from_slice(&k_len);
+        payload.extend_from_slice(&v_len);
+        payload.extend_from_slice(key);
+        payload.extend_from_slice(value);
+        count += 1;
+    }
+
+    if res.add_to_payload_tail(payload.len(), &payload).is_err() {
+        return false;
+    }
+
+    res.get_mut_header().num_records = count;
+    true
 }
 
 /// Implementation of the Service trait for Master, allowing it to service RPC requests.
@@ -964,7 +4682,7 @@ impl Service for Master {
     fn dispatch(
         &self,
         op: OpCode,
-        req: Packet<UdpHeader, EmptyMetadata>,
+        mut req: Packet<UdpHeader, EmptyMetadata>,
         res: Packet<UdpHeader, EmptyMetadata>,
     ) -> Result<
         Box<Task>,
@@ -973,27 +4691,84 @@ impl Service for Master {
             Packet<UdpHeader, EmptyMetadata>,
         ),
     > {
-        // Based on the opcode, call the relevant RPC handler.
-        match op {
-            OpCode::SandstormGetRpc => {
-                return self.get(req, res);
-            }
+        // If the tenant's payloads are sealed, then open the request before it is parsed. A
+        // request that cannot be authenticated is dropped.
+        let key = if self.keys.is_empty() {
+            None
+        } else {
+            self.keys.get(&parse_rpc_tenant(&req)).cloned()
+        };
+
+        // Requests whose header length is shorter than the header for their opcode, or runs past
+        // the end of the packet, are malformed and dropped before they are parsed.
+        if !header_len_ok(&req, request_header_len(&op)) {
+            return Err((req, res));
+        }
 
-            OpCode::SandstormPutRpc => {
-                return self.put(req, res);
+        if let Some(ref key) = key {
+            if !crypt::open_payload(&mut req, parse_rpc_header_len(&req), key) {
+                return Err((req, res));
             }
+        }
+
+        let res_len = response_header_len(&op);
+        let moved = self.moved_to(&req);
+
+        // Based on the opcode, call the relevant RPC handler. Requests from tenants that were
+        // migrated to another server are redirected there instead.
+        let task = match op {
+            _ if moved.is_some() => self.redirect(req, res, moved.unwrap()),
+
+            OpCode::SandstormGetRpc => self.get(req, res),
+
+            OpCode::SandstormPutRpc => self.put(req, res),
+
+            OpCode::SandstormDeleteRpc => self.delete(req, res),
 
-            OpCode::SandstormMultiGetRpc => {
-                return self.multiget(req, res);
+            OpCode::SandstormBulkPutRpc => self.tenant_bulk_put(req, res),
+
+            OpCode::SandstormMultiGetRpc => self.multiget(req, res),
+
+            OpCode::SandstormMultiTableGetRpc => self.multitable_get(req, res),
+
+            OpCode::SandstormListTablesRpc => self.list_tables(req, res),
+
+            OpCode::SandstormAssocAddRpc => self.assoc(req, res, OpCode::SandstormAssocAddRpc),
+
+            OpCode::SandstormAssocDelRpc => self.assoc(req, res, OpCode::SandstormAssocDelRpc),
+
+            OpCode::SandstormAssocRangeRpc => {
+                self.assoc(req, res, OpCode::SandstormAssocRangeRpc)
             }
 
-            OpCode::SandstormInvokeRpc => {
-                return self.invoke(req, res);
+            OpCode::SandstormAssocCountRpc => {
+                self.assoc(req, res, OpCode::SandstormAssocCountRpc)
             }
 
-            _ => {
-                return Err((req, res));
+            OpCode::SandstormServerInfoRpc => self.server_info(req, res),
+
+            OpCode::SandstormGetStatisticsRpc => self.get_statistics(req, res),
+
+            OpCode::SandstormTenantAdminRpc => self.tenant_admin(req, res),
+
+            OpCode::SandstormMoveKeyRpc => self.move_key(req, res),
+
+            OpCode::SandstormCasRpc => self.cas(req, res),
+
+            OpCode::SandstormScanRpc => self.scan(req, res),
+
+            OpCode::SandstormInvokeRpc => self.invoke(req, res),
+
+            _ => Err((req, res)),
+        };
+
+        // Seal the response once the task completes.
+        match key {
+            Some(key) => {
+                task.map(|task| Box::new(SealedTask::new(task, key, res_len)) as Box<Task>)
             }
+
+            None => task,
         }
     }
 }
@@ -0,0 +1,459 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use super::common::{TableId, TenantId, PACKET_UDP_LEN};
+use super::cycles;
+use super::task::{CostClass, Task, TaskPriority, TaskState};
+use super::tenant::Tenant;
+use super::trace::RequestId;
+use super::wireformat::{InvokeResponse, RpcStatus};
+
+use e2d2::common::EmptyMetadata;
+use e2d2::headers::UdpHeader;
+use e2d2::interface::Packet;
+
+use spin::RwLock;
+
+// The maximum number of results held by a cache. Once full, expired results are evicted, and new
+// results are not cached until there is room for them.
+const MAX_ENTRIES: usize = 4096;
+
+// The version recorded for a declared table that did not exist when an extension was invoked.
+const MISSING_TABLE: u64 = u64::max_value();
+
+/// The caching policy of a read-only extension, declared when the extension is installed.
+pub struct Policy {
+    // The time in cycles for which a cached result remains valid.
+    ttl: u64,
+
+    // The tables the extension reads from. A write to any of these invalidates cached results.
+    tables: Vec<TableId>,
+}
+
+// Implementation of methods on Policy.
+impl Policy {
+    /// Returns the current versions of the tables declared by this policy. Results are only
+    /// reused while the versions they were computed at are still current.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant invoking the extension.
+    pub fn versions(&self, tenant: &Tenant) -> Vec<u64> {
+        self.tables
+            .iter()
+            .map(|id| {
+                tenant
+                    .get_table(*id)
+                    .map_or(MISSING_TABLE, |table| table.version())
+            })
+            .collect()
+    }
+}
+
+// A result cached for one set of arguments to an extension.
+struct Entry {
+    // The arguments the extension was invoked with. Compared on lookup so that a hash collision
+    // never returns another invocation's result.
+    args: Vec<u8>,
+
+    // The response payload written by the extension.
+    result: Vec<u8>,
+
+    // The versions of the declared tables at the time the extension was invoked.
+    versions: Vec<u64>,
+
+    // The time-stamp in cycles after which the result is stale.
+    expires: u64,
+}
+
+/// A cache of results produced by read-only extensions. Results are keyed by the invoking tenant,
+/// the name of the extension, and a hash of the arguments it was invoked with. A result is reused
+/// until it's time-to-live expires, or any table declared by the extension is written to.
+pub struct ResultCache {
+    // The caching policy of every extension that was declared cacheable.
+    policies: RwLock<HashMap<(TenantId, String), Arc<Policy>>>,
+
+    // Cached results.
+    entries: RwLock<HashMap<(TenantId, String, u64), Entry>>,
+}
+
+// Implementation of methods on ResultCache.
+impl ResultCache {
+    /// Returns an empty cache. No extension is cacheable until it is declared.
+    pub fn new() -> ResultCache {
+        ResultCache {
+            policies: RwLock::new(HashMap::new()),
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Declares an extension cacheable, or not. Any results cached under an earlier
+    /// declaration are dropped, since re-installing an extension can change what it computes.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant the extension was installed for.
+    /// * `name`:   The name of the extension.
+    /// * `ttl_ms`: The time in milliseconds for which results remain valid. Zero if the extension
+    ///             is not cacheable.
+    /// * `tables`: The tables the extension reads from.
+    pub fn declare(&self, tenant: TenantId, name: &str, ttl_ms: u64, tables: Vec<TableId>) {
+        {
+            let mut policies = self.policies.write();
+            if ttl_ms == 0 {
+                policies.remove(&(tenant, String::from(name)));
+            } else {
+                let policy = Policy {
+                    ttl: (ttl_ms * cycles::cycles_per_second()) / 1000,
+                    tables: tables,
+                };
+                policies.insert((tenant, String::from(name)), Arc::new(policy));
+            }
+        }
+
+        self.forget(tenant, name);
+    }
+
+    /// Drops every result cached for an extension (ex: because it was replaced).
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant the extension was installed for.
+    /// * `name`:   The name of the extension.
+    pub fn forget(&self, tenant: TenantId, name: &str) {
+        self.entries
+            .write()
+            .retain(|&(t, ref n, _), _| t != tenant || n != name);
+    }
+
+    /// Returns the caching policy of an extension, if it was declared cacheable.
+    pub fn policy(&self, tenant: TenantId, name: &str) -> Option<Arc<Policy>> {
+        self.policies
+            .read()
+            .get(&(tenant, String::from(name)))
+            .cloned()
+    }
+
+    /// Looks up a cached result.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:   The tenant invoking the extension.
+    /// * `name`:     The name of the extension.
+    /// * `args`:     The arguments the extension is being invoked with.
+    /// * `versions`: The current versions of the declared tables. Refer to `Policy::versions()`.
+    /// * `now`:      The current time-stamp in cycles.
+    ///
+    /// # Return
+    ///
+    /// A copy of the cached result if there is one that is still valid.
+    pub fn lookup(
+        &self,
+        tenant: TenantId,
+        name: &str,
+        args: &[u8],
+        versions: &[u64],
+        now: u64,
+    ) -> Option<Vec<u8>> {
+        let key = (tenant, String::from(name), hash(args));
+        self.entries.read().get(&key).and_then(|entry| {
+            if entry.args == args && entry.versions == versions && now < entry.expires {
+                Some(entry.result.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Caches the result of an invocation.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:   The tenant that invoked the extension.
+    /// * `name`:     The name of the extension.
+    /// * `args`:     The arguments the extension was invoked with.
+    /// * `versions`: The versions of the declared tables when the extension was invoked. If a
+    ///               table was written to while the extension ran, the result is never reused.
+    /// * `result`:   The response payload written by the extension.
+    /// * `expires`:  The time-stamp in cycles after which the result is stale.
+    pub fn insert(
+        &self,
+        tenant: TenantId,
+        name: &str,
+        args: Vec<u8>,
+        versions: Vec<u64>,
+        result: Vec<u8>,
+        expires: u64,
+    ) {
+        let key = (tenant, String::from(name), hash(&args));
+        let mut entries = self.entries.write();
+
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            let now = cycles::rdtsc();
+            entries.retain(|_, entry| now < entry.expires);
+            if entries.len() >= MAX_ENTRIES {
+                return;
+            }
+        }
+
+        entries.insert(
+            key,
+            Entry {
+                args: args,
+                result: result,
+                versions: versions,
+                expires: expires,
+            },
+        );
+    }
+
+    /// Returns the number of results currently cached.
+    pub fn len(&self) -> usize {
+        self.entries.read().len()
+    }
+}
+
+// Hashes an extension's arguments.
+fn hash(args: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    args.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A task that runs an invocation of a cacheable extension, and caches it's result once it
+/// completes successfully.
+pub struct Memoize {
+    // The task running the extension.
+    task: Box<Task>,
+
+    // The cache the result is written to.
+    cache: Arc<ResultCache>,
+
+    // The tenant that invoked the extension.
+    tenant: TenantId,
+
+    // The name of the extension.
+    name: String,
+
+    // The arguments the extension was invoked with.
+    args: Vec<u8>,
+
+    // The versions of the declared tables when the extension was invoked.
+    versions: Vec<u64>,
+
+    // The time-stamp in cycles after which the result is stale.
+    expires: u64,
+
+    // The response payload of a successful invocation, held by `tear()` until `tear_more()`
+    // shows whether the whole result fit in it.
+    result: Option<Vec<u8>>,
+}
+
+// Implementation of methods on Memoize.
+impl Memoize {
+    /// Wraps a task invoking a cacheable extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `task`:     The task running the extension.
+    /// * `cache`:    The cache the result should be written to.
+    /// * `policy`:   The caching policy of the extension.
+    /// * `tenant`:   The tenant invoking the extension.
+    /// * `name`:     The name of the extension.
+    /// * `args`:     The arguments the extension was invoked with.
+    /// * `versions`: The versions of the declared tables, read before the task first runs.
+    pub fn new(
+        task: Box<Task>,
+        cache: Arc<ResultCache>,
+        policy: &Policy,
+        tenant: TenantId,
+        name: String,
+        args: Vec<u8>,
+        versions: Vec<u64>,
+    ) -> Memoize {
+        Memoize {
+            task: task,
+            cache: cache,
+            tenant: tenant,
+            name: name,
+            args: args,
+            versions: versions,
+            expires: cycles::rdtsc() + policy.ttl,
+            result: None,
+        }
+    }
+}
+
+// Implementation of the Task trait for Memoize. Everything except tear() and tear_more() is
+// delegated to the wrapped task.
+impl Task for Memoize {
+    /// Refer to the `Task` trait for Documentation.
+    fn run(&mut self) -> (TaskState, u64) {
+        self.task.run()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn state(&self) -> TaskState {
+        self.task.state()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn time(&self) -> u64 {
+        self.task.time()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn priority(&self) -> TaskPriority {
+        self.task.priority()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn cost(&self) -> CostClass {
+        self.task.cost()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn affinity(&self) -> Option<usize> {
+        self.task.affinity()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn deadline(&self) -> Option<u64> {
+        self.task.deadline()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn set_deadline(&mut self, deadline: u64) {
+        self.task.set_deadline(deadline)
+    }
+
+    /// Refer to the `Task` trait for Documentation. Holds on to the response payload if the
+    /// extension completed successfully.
+    unsafe fn tear(
+        &mut self,
+    ) -> Option<(
+        Packet<UdpHeader, EmptyMetadata>,
+        Packet<UdpHeader, EmptyMetadata>,
+    )> {
+        self.task.tear().map(|(req, res)| {
+            let res = res.parse_header::<InvokeResponse>();
+            if res.get_header().common_header.status == RpcStatus::StatusOk {
+                self.result = Some(res.get_payload().to_vec());
+            }
+
+            (req, res.deparse_header(PACKET_UDP_LEN as usize))
+        })
+    }
+
+    /// Refer to the `Task` trait for Documentation. Caches the payload held on to by `tear()`,
+    /// unless the result spilled over into more packets.
+    unsafe fn tear_more(&mut self) -> Vec<Packet<UdpHeader, EmptyMetadata>> {
+        let more = self.task.tear_more();
+        if let Some(result) = self.result.take() {
+            if more.is_empty() {
+                self.cache.insert(
+                    self.tenant,
+                    &self.name,
+                    self.args.drain(..).collect(),
+                    self.versions.drain(..).collect(),
+                    result,
+                    self.expires,
+                );
+            }
+        }
+
+        more
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn id(&self) -> Option<RequestId> {
+        self.task.id()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn wake(&self) -> Option<u64> {
+        self.task.wake()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn successor(&mut self) -> Option<Box<Task>> {
+        self.task.successor()
+    }
+}
+
+// This module contains simple unit tests for ResultCache.
+#[cfg(test)]
+mod tests {
+    use super::{ResultCache, MAX_ENTRIES};
+    use std::mem::transmute;
+
+    // This test verifies that a cached result is only returned for a declared extension, the
+    // same arguments and table versions, and before it expires.
+    #[test]
+    fn test_cache_lookup() {
+        let cache = ResultCache::new();
+        assert!(cache.policy(1, "agg").is_none());
+
+        cache.declare(1, "agg", 1000, vec![7]);
+        assert!(cache.policy(1, "agg").is_some());
+        assert!(cache.policy(2, "agg").is_none());
+
+        cache.insert(1, "agg", vec![1, 2], vec![3], vec![9, 9], 100);
+        assert_eq!(Some(vec![9, 9]), cache.lookup(1, "agg", &[1, 2], &[3], 50));
+
+        assert_eq!(None, cache.lookup(1, "agg", &[1, 3], &[3], 50));
+        assert_eq!(None, cache.lookup(1, "agg", &[1, 2], &[4], 50));
+        assert_eq!(None, cache.lookup(1, "agg", &[1, 2], &[3], 100));
+        assert_eq!(None, cache.lookup(2, "agg", &[1, 2], &[3], 50));
+    }
+
+    // This test verifies that re-declaring an extension drops it's cached results.
+    #[test]
+    fn test_cache_redeclare() {
+        let cache = ResultCache::new();
+        cache.declare(1, "agg", 1000, vec![7]);
+        cache.insert(1, "agg", vec![1], vec![0], vec![2], u64::max_value());
+        cache.insert(1, "cnt", vec![1], vec![0], vec![3], u64::max_value());
+
+        cache.declare(1, "agg", 0, vec![]);
+        assert!(cache.policy(1, "agg").is_none());
+        assert_eq!(None, cache.lookup(1, "agg", &[1], &[0], 0));
+        assert_eq!(Some(vec![3]), cache.lookup(1, "cnt", &[1], &[0], 0));
+    }
+
+    // This test verifies that a full cache evicts expired results, and otherwise stops caching.
+    #[test]
+    fn test_cache_full() {
+        let cache = ResultCache::new();
+        for i in 0..MAX_ENTRIES {
+            let expires = if i == 0 { 0 } else { u64::max_value() };
+            let args: [u8; 8] = unsafe { transmute((i as u64).to_le()) };
+            cache.insert(1, "agg", args.to_vec(), vec![], vec![], expires);
+        }
+        assert_eq!(MAX_ENTRIES, cache.len());
+
+        // The first result has expired, and makes room for a new one.
+        cache.insert(1, "agg", vec![0xff; 9], vec![], vec![1], u64::max_value());
+        assert_eq!(Some(vec![1]), cache.lookup(1, "agg", &[0xff; 9], &[], 0));
+
+        cache.insert(1, "agg", vec![0xee; 9], vec![], vec![1], u64::max_value());
+        assert_eq!(None, cache.lookup(1, "agg", &[0xee; 9], &[], 0));
+    }
+}
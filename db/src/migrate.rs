@@ -0,0 +1,317 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+// Tenants are migrated between servers by copying their objects over in batches of bulk_put()
+// requests, sent to the destination's install() endpoint over TCP. This module builds and parses
+// the records on these requests. The migration itself is driven by `Master::migrate()`.
+
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::mem::{size_of, transmute};
+use std::net::{Shutdown, TcpStream};
+
+use super::common::{TableId, TenantId};
+use super::wireformat::*;
+
+/// The number of payload bytes a `Batch` accumulates before it sends them out as a single
+/// bulk_put() request.
+pub const BATCH_BYTES: usize = 1 << 20;
+
+/// A single record on the payload of a bulk_put() request.
+#[derive(Debug, PartialEq)]
+pub struct BulkRecord<'a> {
+    /// The table the record's key belongs to.
+    pub table: TableId,
+
+    /// The key of the object.
+    pub key: &'a [u8],
+
+    /// The object's value, or None if the record deletes the key.
+    pub value: Option<&'a [u8]>,
+}
+
+/// Appends a record to the payload of a bulk_put() request. Refer to
+/// `wireformat::BULK_PUT_RECORD_HEADER_LEN` for the layout.
+///
+/// # Arguments
+///
+/// * `buf`:   The payload the record is appended to.
+/// * `table`: The table the key belongs to.
+/// * `key`:   The key of the object. Must be shorter than 64 KB.
+/// * `value`: The object's value, or None if the key should be deleted.
+pub fn encode(buf: &mut Vec<u8>, table: TableId, key: &[u8], value: Option<&[u8]>) {
+    let t: [u8; 8] = unsafe { transmute(table.to_le()) };
+    let k: [u8; 2] = unsafe { transmute((key.len() as u16).to_le()) };
+    let v_len = value.map_or(BULK_PUT_TOMBSTONE, |v| v.len() as u32);
+    let v: [u8; 4] = unsafe { transmute(v_len.to_le()) };
+
+    buf.extend_from_slice(&t);
+    buf.extend_from_slice(&k);
+    buf.extend_from_slice(&v);
+    buf.extend_from_slice(key);
+    if let Some(value) = value {
+        buf.extend_from_slice(value);
+    }
+}
+
+/// Parses the records off the payload of a bulk_put() request.
+///
+/// # Arguments
+///
+/// * `payload`:     The payload on the request.
+/// * `num_records`: The number of records the request's header claims are on the payload.
+///
+/// # Return
+///
+/// The records in the order they appear on the payload, or None if the payload does not consist
+/// of exactly `num_records` well formed records.
+pub fn decode(payload: &[u8], num_records: usize) -> Option<Vec<BulkRecord>> {
+    let mut records = Vec::with_capacity(num_records.min(payload.len()));
+    let mut rest = payload;
+
+    for _ in 0..num_records {
+        if rest.len() < BULK_PUT_RECORD_HEADER_LEN {
+            return None;
+        }
+
+        let mut t: [u8; 8] = [0; 8];
+        let mut k: [u8; 2] = [0; 2];
+        let mut v: [u8; 4] = [0; 4];
+        t.copy_from_slice(&rest[0..8]);
+        k.copy_from_slice(&rest[8..10]);
+        v.copy_from_slice(&rest[10..14]);
+        let table = u64::from_le(unsafe { transmute(t) });
+        let k_len = u16::from_le(unsafe { transmute(k) }) as usize;
+        let v_len = u32::from_le(unsafe { transmute(v) });
+
+        let body = &rest[BULK_PUT_RECORD_HEADER_LEN..];
+        let len = if v_len == BULK_PUT_TOMBSTONE { k_len } else { k_len + v_len as usize };
+        if body.len() < len {
+            return None;
+        }
+
+        let (key, value) = body[..len].split_at(k_len);
+        records.push(BulkRecord {
+            table: table,
+            key: key,
+            value: if v_len == BULK_PUT_TOMBSTONE { None } else { Some(value) },
+        });
+        rest = &body[len..];
+    }
+
+    if rest.len() > 0 {
+        return None;
+    }
+
+    Some(records)
+}
+
+/// This type batches up a tenant's records, and sends them to a destination server in
+/// bulk_put() requests once enough of them have accumulated.
+pub struct Batch {
+    // The address (IPv4:Port) of the destination's install() endpoint.
+    dest: String,
+
+    // The tenant the records belong to.
+    tenant: TenantId,
+
+    // Records that have not been sent yet, and the number of them.
+    records: Vec<u8>,
+    count: u32,
+
+    // The number of records sent so far.
+    sent: u64,
+}
+
+// Implementation of methods on Batch.
+impl Batch {
+    /// Returns an empty Batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest`:   The address (IPv4:Port) of the destination's install() endpoint.
+    /// * `tenant`: The tenant whose records will be sent.
+    pub fn new(dest: &str, tenant: TenantId) -> Batch {
+        Batch {
+            dest: dest.to_string(),
+            tenant: tenant,
+            records: Vec::new(),
+            count: 0,
+            sent: 0,
+        }
+    }
+
+    /// Adds a record to the batch, sending the batch out if it has grown large enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `table`: The table the key belongs to.
+    /// * `key`:   The key of the object.
+    /// * `value`: The object's value, or None if the key should be deleted at the destination.
+    ///
+    /// # Return
+    ///
+    /// An error if the batch had to be sent out, and the destination could not apply it.
+    pub fn add(&mut self, table: TableId, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        encode(&mut self.records, table, key, value);
+        self.count += 1;
+
+        if self.records.len() >= BATCH_BYTES {
+            return self.flush();
+        }
+
+        Ok(())
+    }
+
+    /// Sends out every record in the batch, and waits for the destination to apply them.
+    ///
+    /// # Return
+    ///
+    /// An error if the destination could not be reached, or did not apply every record.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.count == 0 {
+            return Ok(());
+        }
+
+        send(&self.dest, self.tenant, self.count, &self.records)?;
+
+        self.sent += self.count as u64;
+        self.records.clear();
+        self.count = 0;
+        Ok(())
+    }
+
+    /// Returns the number of records sent out so far.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+}
+
+// Sends a bulk_put() request to a destination server, and waits for it's response.
+fn send(dest: &str, tenant: TenantId, count: u32, records: &[u8]) -> Result<()> {
+    let hdr = BulkPutRequest::new(tenant, count, 0);
+    let hdr: [u8; size_of::<BulkPutRequest>()] = unsafe { transmute(hdr) };
+
+    let mut stream = TcpStream::connect(dest)?;
+    stream.write_all(&hdr)?;
+    stream.write_all(records)?;
+    stream.flush()?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut res: Vec<u8> = Vec::new();
+    stream.read_to_end(&mut res)?;
+
+    // The response is applied in full only if it's status is ok, and it covers every record.
+    if res.len() < size_of::<BulkPutResponse>() || res[0] != RpcStatus::StatusOk as u8 {
+        return Err(Error::new(ErrorKind::Other, "Destination rejected bulk_put()."));
+    }
+
+    let mut n: [u8; 4] = [0; 4];
+    n.copy_from_slice(&res[size_of::<RpcResponseHeader>()..size_of::<BulkPutResponse>()]);
+    if u32::from_le(unsafe { transmute(n) }) != count {
+        return Err(Error::new(ErrorKind::Other, "Destination applied a partial bulk_put()."));
+    }
+
+    Ok(())
+}
+
+// This module contains simple unit tests for bulk_put() records and batches.
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Batch, BulkRecord};
+    use std::io::{Read, Write};
+    use std::mem::{size_of, transmute};
+    use std::net::TcpListener;
+    use std::thread;
+    use wireformat::*;
+
+    // This test verifies that records are parsed back exactly as they were encoded.
+    #[test]
+    fn test_records() {
+        let mut buf = Vec::new();
+        encode(&mut buf, 7, &[1, 2], Some(&[3, 4, 5]));
+        encode(&mut buf, 9, &[6], None);
+        encode(&mut buf, 7, &[8], Some(&[]));
+
+        let records = decode(&buf, 3).expect("Failed to decode records.");
+        assert_eq!(
+            vec![
+                BulkRecord { table: 7, key: &[1, 2], value: Some(&[3, 4, 5]) },
+                BulkRecord { table: 9, key: &[6], value: None },
+                BulkRecord { table: 7, key: &[8], value: Some(&[]) },
+            ],
+            records
+        );
+    }
+
+    // This test verifies that payloads that are truncated, carry trailing bytes, or disagree
+    // with the record count are rejected.
+    #[test]
+    fn test_records_malformed() {
+        let mut buf = Vec::new();
+        encode(&mut buf, 7, &[1, 2], Some(&[3, 4, 5]));
+
+        assert!(decode(&buf[..buf.len() - 1], 1).is_none());
+        assert!(decode(&buf[..10], 1).is_none());
+        assert!(decode(&buf, 2).is_none());
+        assert!(decode(&buf, 0).is_none());
+
+        buf.push(0);
+        assert!(decode(&buf, 1).is_none());
+    }
+
+    // This test verifies that a batch is sent out as a single bulk_put() request once flushed,
+    // and that a failed request is surfaced as an error.
+    #[test]
+    fn test_batch() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind listener.");
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let server = thread::spawn(move || {
+            let mut statuses = vec![RpcStatus::StatusOk, RpcStatus::StatusInternalError];
+            let mut received = Vec::new();
+
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut req = Vec::new();
+                stream.read_to_end(&mut req).unwrap();
+                received.push(req.len() - size_of::<BulkPutRequest>());
+
+                let mut res = BulkPutResponse::new(0, OpCode::SandstormBulkPutRpc, 5);
+                res.common_header.status = statuses.remove(0);
+                res.num_records = 2;
+                let res: [u8; size_of::<BulkPutResponse>()] = unsafe { transmute(res) };
+                stream.write_all(&res).unwrap();
+            }
+
+            received
+        });
+
+        let mut batch = Batch::new(&addr, 5);
+        batch.add(1, &[1], Some(&[2])).unwrap();
+        batch.add(1, &[3], None).unwrap();
+        assert_eq!(0, batch.sent());
+
+        batch.flush().expect("Failed to send batch.");
+        assert_eq!(2, batch.sent());
+        batch.flush().expect("Failed to flush an empty batch.");
+
+        batch.add(1, &[1], Some(&[2])).unwrap();
+        batch.add(1, &[4], Some(&[5])).unwrap();
+        assert!(batch.flush().is_err());
+        assert_eq!(2, batch.sent());
+
+        assert_eq!(vec![14 * 2 + 3, 14 * 2 + 4], server.join().unwrap());
+    }
+}
@@ -0,0 +1,476 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+// Stand-ins for the parts of Netbricks used by the database. Compiled in place of the e2d2 crate
+// when the "dpdk" feature is disabled, so that the library and it's unit tests build and run on
+// machines without DPDK, hugepages, or a NIC:
+//
+//     cargo test --no-default-features
+//
+// Packets are backed by heap buffers no larger than an mbuf's data room, and headers have the
+// same layout as their Netbricks counterparts, so code manipulating them behaves exactly as it
+// would on an mbuf. The requests dispatcher, which polls ports for mbufs, and the binaries are
+// not built without DPDK.
+
+pub mod common {
+    /// Null metadata associated with packets. Refer to e2d2::common::EmptyMetadata.
+    pub struct EmptyMetadata;
+
+    /// The error returned when a packet's buffer cannot hold any more data.
+    #[derive(Debug)]
+    pub struct FailedAllocation;
+
+    /// The result of an operation on a packet.
+    pub type Result<T> = ::std::result::Result<T, FailedAllocation>;
+}
+
+pub mod headers {
+    /// A trait implemented by all headers. Refer to e2d2::headers::EndOffset.
+    pub trait EndOffset: Send {
+        type PreviousHeader: EndOffset;
+
+        /// Returns the number of bytes to skip to get to the next header.
+        fn offset(&self) -> usize;
+
+        /// Returns the size of this header in bytes.
+        fn size() -> usize;
+
+        /// Returns the size of the payload in bytes.
+        fn payload_size(&self, hint: usize) -> usize;
+
+        fn check_correct(&self, prev: &Self::PreviousHeader) -> bool;
+    }
+
+    // Implements EndOffset for a fixed size header.
+    macro_rules! fixed_header {
+        ($header:ty, $previous:ty, $size:expr) => {
+            impl EndOffset for $header {
+                type PreviousHeader = $previous;
+
+                fn offset(&self) -> usize {
+                    $size
+                }
+
+                fn size() -> usize {
+                    $size
+                }
+
+                fn payload_size(&self, hint: usize) -> usize {
+                    hint - $size
+                }
+
+                fn check_correct(&self, _prev: &$previous) -> bool {
+                    true
+                }
+            }
+        };
+    }
+
+    /// The header on a packet that hasn't been parsed yet.
+    #[derive(Debug, Default)]
+    #[repr(C, packed)]
+    pub struct NullHeader;
+
+    fixed_header!(NullHeader, NullHeader, 0);
+
+    /// An ethernet address.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    #[repr(C, packed)]
+    pub struct MacAddress {
+        pub addr: [u8; 6],
+    }
+
+    impl MacAddress {
+        pub fn new(a: u8, b: u8, c: u8, d: u8, e: u8, f: u8) -> MacAddress {
+            MacAddress {
+                addr: [a, b, c, d, e, f],
+            }
+        }
+
+        pub fn new_from_slice(slice: &[u8]) -> MacAddress {
+            MacAddress::new(slice[0], slice[1], slice[2], slice[3], slice[4], slice[5])
+        }
+    }
+
+    /// An ethernet header without VLAN tags.
+    #[derive(Default)]
+    #[repr(C, packed)]
+    pub struct MacHeader {
+        pub dst: MacAddress,
+        pub src: MacAddress,
+        etype: u16,
+    }
+
+    fixed_header!(MacHeader, NullHeader, 14);
+
+    impl MacHeader {
+        pub fn new() -> MacHeader {
+            Default::default()
+        }
+
+        pub fn etype(&self) -> u16 {
+            u16::from_be(self.etype)
+        }
+
+        pub fn set_etype(&mut self, etype: u16) {
+            self.etype = u16::to_be(etype)
+        }
+    }
+
+    /// An IPv4 header without options. Fields without accessors are kept to match the layout of
+    /// the header Netbricks parses.
+    #[allow(dead_code)]
+    #[derive(Default)]
+    #[repr(C, packed)]
+    pub struct IpHeader {
+        version_to_len: u32,
+        id_to_foffset: u32,
+        ttl_to_csum: u32,
+        src_ip: u32,
+        dst_ip: u32,
+    }
+
+    fixed_header!(IpHeader, MacHeader, 20);
+
+    impl IpHeader {
+        pub fn new() -> IpHeader {
+            Default::default()
+        }
+
+        pub fn src(&self) -> u32 {
+            u32::from_be(self.src_ip)
+        }
+
+        pub fn set_src(&mut self, src: u32) {
+            self.src_ip = u32::to_be(src)
+        }
+
+        pub fn dst(&self) -> u32 {
+            u32::from_be(self.dst_ip)
+        }
+
+        pub fn set_dst(&mut self, dst: u32) {
+            self.dst_ip = u32::to_be(dst)
+        }
+
+        pub fn length(&self) -> u16 {
+            u16::from_be(((self.version_to_len & 0xffff0000) >> 16) as u16)
+        }
+
+        pub fn set_length(&mut self, len: u16) {
+            self.version_to_len =
+                (self.version_to_len & !0xffff0000) | ((u16::to_be(len) as u32) << 16);
+        }
+    }
+
+    /// A UDP header.
+    #[derive(Default)]
+    #[repr(C, packed)]
+    pub struct UdpHeader {
+        src_port: u16,
+        dst_port: u16,
+        len: u16,
+        csum: u16,
+    }
+
+    fixed_header!(UdpHeader, IpHeader, 8);
+
+    impl UdpHeader {
+        pub fn new() -> UdpHeader {
+            Default::default()
+        }
+
+        pub fn src_port(&self) -> u16 {
+            u16::from_be(self.src_port)
+        }
+
+        pub fn set_src_port(&mut self, port: u16) {
+            self.src_port = u16::to_be(port)
+        }
+
+        pub fn dst_port(&self) -> u16 {
+            u16::from_be(self.dst_port)
+        }
+
+        pub fn set_dst_port(&mut self, port: u16) {
+            self.dst_port = u16::to_be(port)
+        }
+
+        pub fn length(&self) -> u16 {
+            u16::from_be(self.len)
+        }
+
+        pub fn set_length(&mut self, len: u16) {
+            self.len = u16::to_be(len)
+        }
+
+        pub fn checksum(&self) -> u16 {
+            u16::from_be(self.csum)
+        }
+
+        pub fn set_checksum(&mut self, csum: u16) {
+            self.csum = u16::to_be(csum)
+        }
+    }
+}
+
+pub mod interface {
+    use std::collections::VecDeque;
+    use std::marker::PhantomData;
+    use std::ptr;
+    use std::sync::{Arc, Mutex};
+
+    use super::common::*;
+    use super::headers::{EndOffset, NullHeader};
+
+    /// The number of bytes a packet can hold. Matches the data room on Netbricks' mbufs.
+    pub const PACKET_BUF_LEN: usize = 2048;
+
+    /// A packet whose headers have been parsed upto `T`. Refer to e2d2::interface::Packet.
+    pub struct Packet<T: EndOffset, M: Sized + Send> {
+        // The contents of the packet, starting at the first header. Never grows past
+        // PACKET_BUF_LEN, mirroring an mbuf.
+        buf: Vec<u8>,
+
+        // The offset of the header of type `T` into `buf`.
+        offset: usize,
+
+        _phantom_t: PhantomData<T>,
+        _phantom_m: PhantomData<M>,
+    }
+
+    /// Allocates an empty packet.
+    pub fn new_packet() -> Option<Packet<NullHeader, EmptyMetadata>> {
+        Some(Packet {
+            buf: Vec::with_capacity(PACKET_BUF_LEN),
+            offset: 0,
+            _phantom_t: PhantomData,
+            _phantom_m: PhantomData,
+        })
+    }
+
+    impl<T: EndOffset, M: Sized + Send> Packet<T, M> {
+        // Re-types the packet so that it is parsed upto a header of type `T2` at `offset`.
+        fn cast<T2: EndOffset, M2: Sized + Send>(self, offset: usize) -> Packet<T2, M2> {
+            Packet {
+                buf: self.buf,
+                offset: offset,
+                _phantom_t: PhantomData,
+                _phantom_m: PhantomData,
+            }
+        }
+
+        // The offset at which the payload following the header begins.
+        fn payload_offset(&self) -> usize {
+            self.offset + self.get_header().offset()
+        }
+
+        pub fn get_header(&self) -> &T {
+            unsafe { &*(self.buf.as_ptr().offset(self.offset as isize) as *const T) }
+        }
+
+        pub fn get_mut_header(&mut self) -> &mut T {
+            unsafe { &mut *(self.buf.as_mut_ptr().offset(self.offset as isize) as *mut T) }
+        }
+
+        pub fn get_payload(&self) -> &[u8] {
+            &self.buf[self.payload_offset()..]
+        }
+
+        pub fn get_mut_payload(&mut self) -> &mut [u8] {
+            let offset = self.payload_offset();
+            &mut self.buf[offset..]
+        }
+
+        /// Writes a header at the beginning of the payload, shifting the payload down.
+        pub fn push_header<T2: EndOffset<PreviousHeader = T>>(
+            mut self,
+            header: &T2,
+        ) -> Option<Packet<T2, M>> {
+            let size = header.offset();
+            if self.buf.len() + size > PACKET_BUF_LEN {
+                return None;
+            }
+
+            let at = self.payload_offset();
+            let tail = self.buf.split_off(at);
+            self.buf.resize(at + size, 0);
+            unsafe {
+                let dst = self.buf.as_mut_ptr().offset(at as isize) as *mut T2;
+                ptr::copy_nonoverlapping(header as *const T2, dst, 1);
+            }
+            self.buf.extend_from_slice(&tail);
+
+            Some(self.cast(at))
+        }
+
+        pub fn add_to_payload_tail(&mut self, size: usize, data: &[u8]) -> Result<()> {
+            if self.buf.len() + size > PACKET_BUF_LEN {
+                return Err(FailedAllocation);
+            }
+
+            self.buf.extend_from_slice(&data[..size]);
+            Ok(())
+        }
+
+        pub fn remove_from_payload_tail(&mut self, size: usize) -> Result<()> {
+            if self.get_payload().len() < size {
+                return Err(FailedAllocation);
+            }
+
+            let len = self.buf.len() - size;
+            self.buf.truncate(len);
+            Ok(())
+        }
+
+        pub fn parse_header<T2: EndOffset<PreviousHeader = T>>(self) -> Packet<T2, M> {
+            let at = self.payload_offset();
+            assert!(self.buf.len() - at >= T2::size());
+            self.cast(at)
+        }
+
+        pub fn deparse_header(self, offset: usize) -> Packet<T::PreviousHeader, M> {
+            let at = self.offset - offset;
+            self.cast(at)
+        }
+
+        /// Returns the packet parsed upto nothing, as it would be when received.
+        pub fn reset(self) -> Packet<NullHeader, EmptyMetadata> {
+            self.cast(0)
+        }
+
+        pub fn free_packet(self) {}
+    }
+
+    /// A loopback stand-in for a Netbricks receive and transmit queue pair. Packets sent out on
+    /// the queue are received back off it in order, so that code sending requests can be tested
+    /// against code receiving them.
+    #[derive(Clone)]
+    pub struct PortQueue {
+        port: i32,
+        rxq: i32,
+        txq: i32,
+        queue: Arc<Mutex<VecDeque<Packet<NullHeader, EmptyMetadata>>>>,
+    }
+
+    impl PortQueue {
+        pub fn new(port: i32, rxq: i32, txq: i32) -> PortQueue {
+            PortQueue {
+                port: port,
+                rxq: rxq,
+                txq: txq,
+                queue: Arc::new(Mutex::new(VecDeque::new())),
+            }
+        }
+
+        /// Queues up packets to be received. Returns the number queued.
+        pub fn send<T: EndOffset>(&self, packets: Vec<Packet<T, EmptyMetadata>>) -> u32 {
+            let mut queue = self.queue.lock().unwrap();
+            let sent = packets.len() as u32;
+            for packet in packets.into_iter() {
+                queue.push_back(packet.reset());
+            }
+            sent
+        }
+
+        /// Receives upto `max` packets.
+        pub fn recv(&self, max: usize) -> Vec<Packet<NullHeader, EmptyMetadata>> {
+            let mut queue = self.queue.lock().unwrap();
+            let count = max.min(queue.len());
+            queue.drain(..count).collect()
+        }
+
+        pub fn port_id(&self) -> i32 {
+            self.port
+        }
+
+        pub fn rxq(&self) -> i32 {
+            self.rxq
+        }
+
+        pub fn txq(&self) -> i32 {
+            self.txq
+        }
+    }
+
+    pub mod dpdk {
+        /// There is no NIC whose clock can be read. Refer to e2d2::interface::dpdk.
+        pub fn read_nic_clock(_port: i32) -> Option<u64> {
+            None
+        }
+    }
+}
+
+// This module contains simple unit tests for mock packets.
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use super::headers::*;
+    use super::interface::*;
+
+    use rpc;
+    use wireformat::{GetRequest, OpCode};
+
+    // This test verifies that mock headers are laid out like the ones Netbricks parses, since
+    // offsets into requests are computed off their sizes.
+    #[test]
+    fn test_mock_header_sizes() {
+        assert_eq!(14, size_of::<MacHeader>());
+        assert_eq!(20, size_of::<IpHeader>());
+        assert_eq!(8, size_of::<UdpHeader>());
+    }
+
+    // This test verifies that a request built by the rpc module survives a trip over a loopback
+    // queue, and that it's headers and payload parse back out as expected.
+    #[test]
+    fn test_mock_request_loopback() {
+        let port = PortQueue::new(0, 1, 1);
+
+        let req = rpc::create_get_rpc(
+            &MacHeader::new(),
+            &IpHeader::new(),
+            &UdpHeader::new(),
+            7,
+            1,
+            &[1, 2, 3, 4],
+            0xbeef,
+            0x8001,
+        );
+        assert_eq!(1, port.send(vec![req]));
+
+        let mut recvd = port.recv(32);
+        assert_eq!(1, recvd.len());
+        assert!(port.recv(32).is_empty());
+
+        let req = recvd
+            .pop()
+            .unwrap()
+            .parse_header::<MacHeader>()
+            .parse_header::<IpHeader>();
+        let total = (size_of::<IpHeader>() + size_of::<UdpHeader>() + size_of::<GetRequest>() + 4)
+            as u16;
+        assert_eq!(total, req.get_header().length());
+
+        let req = req.parse_header::<UdpHeader>();
+        assert_eq!(0x8001, req.get_header().dst_port());
+        assert_eq!(7, rpc::parse_rpc_tenant(&req));
+        assert_eq!(0xbeef, rpc::parse_rpc_stamp(&req));
+        assert!(rpc::parse_rpc_opcode(&req) == OpCode::SandstormGetRpc);
+
+        let req = req.parse_header::<GetRequest>();
+        assert_eq!(&[1, 2, 3, 4], req.get_payload());
+    }
+}
@@ -13,8 +13,9 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::ops::{Generator, GeneratorState};
+use std::rc::Rc;
 
 use super::cycles;
 use super::task::TaskState::*;
@@ -38,6 +39,10 @@ type NativeGenerator = Box<
     >,
 >;
 
+/// A handle through which the generator of a native operation can hand back response packets
+/// beyond the one it returns. Refer to `Task::tear_more()`.
+pub type Responses = Rc<RefCell<Vec<Packet<UdpHeader, EmptyMetadata>>>>;
+
 /// A task corresponding to a native operation (like get() and put() requests).
 pub struct Native {
     // The current execution state of the task. Required to determine if the task has completed
@@ -53,6 +58,15 @@ pub struct Native {
     // The underlying generator for the task. Running the task effectively runs this generator.
     gen: NativeGenerator,
 
+    // A hint identifying the core the task would prefer to run on. Refer to `Task::affinity()`.
+    affinity: Option<usize>,
+
+    // The time stamp in cycles by which the task should complete. Refer to `Task::deadline()`.
+    deadline: Option<u64>,
+
+    // Response packets handed back by the generator beyond the one it returned, if any.
+    more: Option<Responses>,
+
     // The result (if any) returned by the generator once it completes execution.
     res: Cell<
         Option<(
@@ -82,9 +96,31 @@ impl Native {
             time: 0,
             priority: prio,
             gen: generator,
+            affinity: None,
+            deadline: None,
+            more: None,
             res: Cell::new(None),
         }
     }
+
+    /// Attaches an affinity hint to the task. Refer to `Task::affinity()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `affinity`: A hint identifying the core the task would prefer to run on.
+    pub fn set_affinity(&mut self, affinity: usize) {
+        self.affinity = Some(affinity);
+    }
+
+    /// Attaches a handle through which the task's generator hands back response packets beyond
+    /// the one it returns. Refer to `Task::tear_more()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `more`: A handle shared with the generator.
+    pub fn set_more(&mut self, more: Responses) {
+        self.more = Some(more);
+    }
 }
 
 // Implementation of the Task trait on Native.
@@ -145,4 +181,27 @@ impl Task for Native {
     )> {
         self.res.replace(None)
     }
+
+    /// Refer to the Task trait for documentation.
+    unsafe fn tear_more(&mut self) -> Vec<Packet<UdpHeader, EmptyMetadata>> {
+        match self.more {
+            Some(ref more) => more.borrow_mut().drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Refer to the Task trait for documentation.
+    fn affinity(&self) -> Option<usize> {
+        self.affinity
+    }
+
+    /// Refer to the Task trait for documentation.
+    fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Refer to the Task trait for documentation.
+    fn set_deadline(&mut self, deadline: u64) {
+        self.deadline = Some(deadline);
+    }
 }
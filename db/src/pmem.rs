@@ -0,0 +1,236 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+#[cfg(feature = "pmem")]
+use std::ffi::CString;
+#[cfg(feature = "pmem")]
+use std::fs::metadata;
+#[cfg(feature = "pmem")]
+use std::io::{Error, ErrorKind};
+use std::io::Result;
+
+use libc;
+
+use super::shm::{HeapStore, Segment};
+
+// The page size assumed if it cannot be read off the system.
+const DEFAULT_PAGE: usize = 4096;
+
+// Bindings to the parts of libpmem used to map and flush persistent memory.
+#[cfg(feature = "pmem")]
+mod ffi {
+    use libc::{c_char, c_int, c_void, mode_t, size_t};
+
+    // Create the file if it does not exist.
+    pub const PMEM_FILE_CREATE: c_int = 1 << 0;
+
+    #[link(name = "pmem")]
+    extern "C" {
+        pub fn pmem_map_file(
+            path: *const c_char,
+            len: size_t,
+            flags: c_int,
+            mode: mode_t,
+            mapped_lenp: *mut size_t,
+            is_pmemp: *mut c_int,
+        ) -> *mut c_void;
+
+        pub fn pmem_persist(addr: *const c_void, len: size_t);
+    }
+}
+
+/// This type represents a heap segment on persistent memory, ex: an Optane DIMM exposed through
+/// a DAX filesystem. Records are laid out exactly as they are on a `Segment`, so a heap file can
+/// be switched between the two backends across restarts. Unlike a `Segment`, every record is
+/// flushed out of the CPU's caches and fenced before the header publishing it is written, and
+/// the header is flushed and fenced before the append returns. A put() acknowledged to a client
+/// therefore survives a crash of the machine, and not only of the server.
+///
+/// When built with the "pmem" feature, the file is mapped through libpmem, which flushes with
+/// user-space cache flush instructions if the mapping is real persistent memory, and falls back
+/// to msync() otherwise. Without the feature, every flush is an msync().
+pub struct PmemSegment {
+    // The segment laid out over the mapping.
+    segment: Segment,
+
+    // True if the mapping is real persistent memory that can be flushed from user-space.
+    is_pmem: bool,
+
+    // The size of a page in bytes. msync() only works on whole pages.
+    page: usize,
+}
+
+// Implementation of methods on PmemSegment.
+impl PmemSegment {
+    /// Maps a persistent memory heap segment, creating it if required.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the DAX file backing the segment.
+    /// * `size`: The size of the segment in bytes. Ignored if the file already exists and is
+    ///           larger than this.
+    ///
+    /// # Return
+    ///
+    /// The mapped segment. Objects appended to it by an earlier run can be walked through the
+    /// `HeapStore` trait's `recover()`.
+    pub fn open(path: &str, size: usize) -> Result<PmemSegment> {
+        let (segment, is_pmem) = map(path, size)?;
+
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        let pmem = PmemSegment {
+            segment: segment,
+            is_pmem: is_pmem,
+            page: if page > 0 { page as usize } else { DEFAULT_PAGE },
+        };
+
+        // The header of a fresh segment was written out, but not flushed.
+        let (base, len) = pmem.segment.header();
+        pmem.persist(base, len);
+
+        if !pmem.is_pmem {
+            info!("Heap file {} is not persistent memory, flushing with msync()", path);
+        }
+
+        Ok(pmem)
+    }
+
+    /// Returns true if the segment is mapped on real persistent memory, and can be flushed
+    /// without entering the kernel.
+    pub fn is_pmem(&self) -> bool {
+        self.is_pmem
+    }
+
+    // Flushes a range of the segment out to the persistent media, and waits for it to get there.
+    fn persist(&self, addr: *const u8, len: usize) {
+        #[cfg(feature = "pmem")]
+        {
+            if self.is_pmem {
+                unsafe { ffi::pmem_persist(addr as *const libc::c_void, len) };
+                return;
+            }
+        }
+
+        let (start, len) = page_range(addr as usize, len, self.page);
+        unsafe { libc::msync(start as *mut libc::c_void, len, libc::MS_SYNC) };
+    }
+}
+
+// Implementation of the HeapStore trait for PmemSegment.
+impl HeapStore for PmemSegment {
+    fn append(&self, object: &[u8], tombstone: bool) -> Option<&'static [u8]> {
+        self.segment
+            .append_with(object, tombstone, |addr, len| self.persist(addr, len))
+    }
+
+    fn recover(&self, f: &mut FnMut(bool, &'static [u8])) -> Result<()> {
+        self.segment.recover(|tombstone, object| f(tombstone, object))
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        self.segment.contains(addr)
+    }
+
+    fn stats(&self) -> (usize, usize) {
+        self.segment.stats()
+    }
+}
+
+// Maps the file backing a segment through libpmem. Returns the segment, and true if the mapping
+// is real persistent memory.
+#[cfg(feature = "pmem")]
+fn map(path: &str, size: usize) -> Result<(Segment, bool)> {
+    // Like Segment::open(), never shrink an existing file.
+    let size = size.max(metadata(path).map(|m| m.len() as usize).unwrap_or(0));
+
+    let cpath =
+        CString::new(path).map_err(|_| Error::new(ErrorKind::InvalidInput, "Bad heap file path."))?;
+    let mut mapped: libc::size_t = 0;
+    let mut is_pmem: libc::c_int = 0;
+
+    let base = unsafe {
+        ffi::pmem_map_file(
+            cpath.as_ptr(),
+            size,
+            ffi::PMEM_FILE_CREATE,
+            0o666,
+            &mut mapped,
+            &mut is_pmem,
+        )
+    };
+    if base.is_null() {
+        return Err(Error::last_os_error());
+    }
+
+    let segment = unsafe { Segment::attach(base as *mut u8, mapped)? };
+    Ok((segment, is_pmem != 0))
+}
+
+// Maps the file backing a segment with mmap(). Such a mapping is never treated as real
+// persistent memory.
+#[cfg(not(feature = "pmem"))]
+fn map(path: &str, size: usize) -> Result<(Segment, bool)> {
+    Ok((Segment::open(path, size)?, false))
+}
+
+// Returns the start address and length of the smallest run of whole pages covering a range.
+fn page_range(addr: usize, len: usize, page: usize) -> (usize, usize) {
+    let start = addr & !(page - 1);
+    let end = (addr + len + page - 1) & !(page - 1);
+    (start, end - start)
+}
+
+// This module contains simple unit tests for PmemSegment.
+#[cfg(test)]
+mod tests {
+    use super::{page_range, PmemSegment};
+    use shm::HeapStore;
+    use std::fs::remove_file;
+
+    // This test verifies that flushed ranges are rounded out to whole pages.
+    #[test]
+    fn test_page_range() {
+        assert_eq!((4096, 4096), page_range(4096, 4096, 4096));
+        assert_eq!((4096, 4096), page_range(4100, 8, 4096));
+        assert_eq!((4096, 8192), page_range(8188, 8, 4096));
+        assert_eq!((0, 0), page_range(0, 0, 4096));
+    }
+
+    // This test verifies that objects appended to a segment are recovered, in order, once the
+    // segment is re-attached.
+    #[test]
+    fn test_pmem_recover() {
+        let path = "/tmp/sandstorm_pmem_recover.test";
+        let _ = remove_file(path);
+
+        {
+            let segment = PmemSegment::open(path, 4096).expect("Failed to create segment.");
+            let copy = segment.append(&[1, 2, 3], false).expect("Failed to append.");
+            assert_eq!(&[1, 2, 3], copy);
+            assert!(segment.contains(copy.as_ptr() as usize));
+            segment.append(&[4; 9], true).expect("Failed to append.");
+        }
+
+        let segment = PmemSegment::open(path, 4096).expect("Failed to re-attach segment.");
+        let mut records = Vec::new();
+        segment
+            .recover(&mut |tombstone, object| records.push((tombstone, object.to_vec())))
+            .expect("Failed to recover segment.");
+
+        assert_eq!(vec![(false, vec![1, 2, 3]), (true, vec![4; 9])], records);
+        assert_eq!((64 + 16 + 24, 4096), segment.stats());
+        let _ = remove_file(path);
+    }
+}
@@ -13,8 +13,13 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::mem::{size_of, transmute};
+use std::str::from_utf8;
 
+use super::common::{PACKET_IPV6_LEN, PACKET_IP_LEN, PACKET_MTU};
+use super::stats::ServerStatistics;
 use super::wireformat::*;
 
 use e2d2::common::EmptyMetadata;
@@ -36,17 +41,7 @@ use e2d2::interface::*;
 /// code corresponding to an invalid service (InvalidService).
 pub fn parse_rpc_service(request: &Packet<UdpHeader, EmptyMetadata>) -> Service {
     // Read the service off the first byte on the payload.
-    let service: u8 = request.get_payload()[0];
-    match service.lt(&(Service::InvalidService as u8)) {
-        true => unsafe {
-            let service: Service = transmute(service);
-            return service;
-        },
-
-        false => {
-            return Service::InvalidService;
-        }
-    }
+    Service::try_from(request.get_payload()[0]).unwrap_or(Service::InvalidService)
 }
 
 /// This function looks into a packet corresponding to an RPC request, and
@@ -64,17 +59,301 @@ pub fn parse_rpc_service(request: &Packet<UdpHeader, EmptyMetadata>) -> Service
 /// to an invalid operation (InvalidOperation) will be returned.
 pub fn parse_rpc_opcode(request: &Packet<UdpHeader, EmptyMetadata>) -> OpCode {
     // Read the opcode off the second byte on the payload.
-    let opcode: u8 = request.get_payload()[1];
-    match opcode.lt(&(OpCode::InvalidOperation as u8)) {
-        true => unsafe {
-            let opcode: OpCode = transmute(opcode);
-            return opcode;
-        },
-
-        false => {
-            return OpCode::InvalidOperation;
-        }
+    OpCode::try_from(request.get_payload()[1]).unwrap_or(OpCode::InvalidOperation)
+}
+
+/// This function looks into a packet corresponding to an RPC request, and
+/// reads the identifier of the tenant that issued it (assumed to be the four
+/// bytes following the opcode).
+///
+/// # Arguments
+///
+/// * `request`: A reference to a packet corresponding to an RPC request.
+///              The packet should have been parsed upto it's UDP header.
+///
+/// # Return
+///
+/// The identifier of the tenant on the RPC request.
+pub fn parse_rpc_tenant(request: &Packet<UdpHeader, EmptyMetadata>) -> u32 {
+    // Read the tenant off the third to sixth bytes on the payload.
+    let mut tenant: [u8; 4] = [0; 4];
+    tenant.copy_from_slice(&request.get_payload()[2..6]);
+    u32::from_le(unsafe { transmute(tenant) })
+}
+
+/// This function looks into a packet corresponding to an RPC request, and
+/// reads the stamp identifying it (assumed to be the eight bytes following
+/// the tenant).
+///
+/// # Arguments
+///
+/// * `request`: A reference to a packet corresponding to an RPC request.
+///              The packet should have been parsed upto it's UDP header.
+///
+/// # Return
+///
+/// The stamp on the RPC request.
+pub fn parse_rpc_stamp(request: &Packet<UdpHeader, EmptyMetadata>) -> u64 {
+    // Read the stamp off the seventh to fourteenth bytes on the payload.
+    let mut stamp: [u8; 8] = [0; 8];
+    stamp.copy_from_slice(&request.get_payload()[6..14]);
+    u64::from_le(unsafe { transmute(stamp) })
+}
+
+/// This function looks into a packet corresponding to an RPC request or
+/// response, and reads the length of the header on it (assumed to be the two
+/// bytes following the stamp).
+///
+/// # Arguments
+///
+/// * `packet`: A reference to a packet corresponding to an RPC request or
+///             response. The packet should have been parsed upto it's UDP
+///             header, and validated with `header_len_ok()`.
+///
+/// # Return
+///
+/// The length of the header on the RPC.
+pub fn parse_rpc_header_len(packet: &Packet<UdpHeader, EmptyMetadata>) -> usize {
+    // Read the header length off the fifteenth and sixteenth bytes on the payload.
+    let mut len: [u8; 2] = [0; 2];
+    len.copy_from_slice(&packet.get_payload()[HEADER_LEN_OFFSET..HEADER_LEN_OFFSET + 2]);
+    u16::from_le(unsafe { transmute(len) }) as usize
+}
+
+/// This function looks into a packet corresponding to an RPC request, and
+/// reads the priority on it (assumed to be the byte following the header
+/// length).
+///
+/// # Arguments
+///
+/// * `request`: A reference to a packet corresponding to an RPC request.
+///              The packet should have been parsed upto it's UDP header.
+///
+/// # Return
+///
+/// The priority of the request. Zero if the request has the default priority,
+/// or is too short to carry one.
+pub fn parse_rpc_priority(request: &Packet<UdpHeader, EmptyMetadata>) -> u8 {
+    let offset = HEADER_LEN_OFFSET + size_of::<u16>();
+    match request.get_payload().get(offset) {
+        Some(priority) => *priority,
+        None => 0,
+    }
+}
+
+/// This function looks into a packet corresponding to an RPC request, and
+/// reads the deadline on it (assumed to be the four bytes following the header
+/// length and priority).
+///
+/// # Arguments
+///
+/// * `request`: A reference to a packet corresponding to an RPC request.
+///              The packet should have been parsed upto it's UDP header.
+///
+/// # Return
+///
+/// The deadline in microseconds after the request's arrival at the server.
+/// Zero if the request has no deadline, or is too short to carry one.
+pub fn parse_rpc_deadline(request: &Packet<UdpHeader, EmptyMetadata>) -> u32 {
+    let offset = HEADER_LEN_OFFSET + size_of::<u16>() + 1;
+    let payload = request.get_payload();
+    if payload.len() < offset + 4 {
+        return 0;
+    }
+
+    let mut deadline: [u8; 4] = [0; 4];
+    deadline.copy_from_slice(&payload[offset..offset + 4]);
+    u32::from_le(unsafe { transmute(deadline) })
+}
+
+/// This function checks that the header length on an RPC request or response
+/// covers atleast the header this build knows of for it's opcode, and does not
+/// run past the end of the packet. Headers longer than the known one are
+/// accepted; the fields beyond it are skipped over when the RPC is parsed.
+///
+/// # Arguments
+///
+/// * `packet`: A reference to a packet corresponding to an RPC request or
+///             response, parsed upto it's UDP header.
+/// * `known`:  The length of the header known to this build for the RPC's
+///             opcode. Refer to `request_header_len()`.
+///
+/// # Return
+///
+/// True if the RPC can be safely parsed.
+pub fn header_len_ok(packet: &Packet<UdpHeader, EmptyMetadata>, known: usize) -> bool {
+    let len = packet.get_payload().len();
+    if len < HEADER_LEN_OFFSET + 2 {
+        return false;
+    }
+
+    let hdr = parse_rpc_header_len(packet);
+    hdr >= known && hdr <= len
+}
+
+/// This function sets the priority and deadline on an RPC request that has
+/// already been populated.
+///
+/// # Arguments
+///
+/// * `request`:  The RPC request packet, parsed upto it's IP header.
+/// * `priority`: The priority of the request. Larger values are more urgent.
+/// * `deadline`: The deadline of the request in microseconds after it's
+///               arrival at the server. Zero indicates no deadline.
+pub fn set_rpc_class(request: &mut Packet<IpHeader, EmptyMetadata>, priority: u8, deadline: u32) {
+    // The priority and deadline follow the service, opcode, tenant, stamp, and
+    // header length on the RPC header, which in turn follows the UDP header.
+    let offset = size_of::<UdpHeader>() + HEADER_LEN_OFFSET + size_of::<u16>();
+
+    let d: [u8; 4] = unsafe { transmute(deadline.to_le()) };
+    let payload = request.get_mut_payload();
+    payload[offset] = priority;
+    payload[offset + 1..offset + 5].copy_from_slice(&d);
+}
+
+/// This function reads the stamp off an RPC request that has already been
+/// populated.
+///
+/// # Arguments
+///
+/// * `request`: The RPC request packet, parsed upto it's IP header.
+///
+/// # Return
+///
+/// The stamp on the RPC request.
+pub fn parse_request_stamp(request: &Packet<IpHeader, EmptyMetadata>) -> u64 {
+    // The stamp follows the service, opcode, and tenant on the RPC header.
+    let offset = size_of::<UdpHeader>() + 6;
+
+    let mut stamp: [u8; 8] = [0; 8];
+    stamp.copy_from_slice(&request.get_payload()[offset..offset + 8]);
+    u64::from_le(unsafe { transmute(stamp) })
+}
+
+/// This function overwrites the stamp on an RPC request that has already
+/// been populated.
+///
+/// # Arguments
+///
+/// * `request`: The RPC request packet, parsed upto it's IP header.
+/// * `stamp`:   The new stamp on the request.
+pub fn set_rpc_stamp(request: &mut Packet<IpHeader, EmptyMetadata>, stamp: u64) {
+    // The stamp follows the service, opcode, and tenant on the RPC header.
+    let offset = size_of::<UdpHeader>() + 6;
+
+    let s: [u8; 8] = unsafe { transmute(stamp.to_le()) };
+    request.get_mut_payload()[offset..offset + 8].copy_from_slice(&s);
+}
+
+/// This function reads the tenant off an RPC response that has already been
+/// populated.
+///
+/// # Arguments
+///
+/// * `response`: The RPC response packet, parsed upto it's IP header.
+///
+/// # Return
+///
+/// The tenant the response is destined for. Zero if the packet is too short
+/// to carry an RPC header.
+pub fn parse_response_tenant(response: &Packet<IpHeader, EmptyMetadata>) -> u32 {
+    // The tenant follows the status and opcode on the RPC header.
+    let offset = size_of::<UdpHeader>() + 2;
+
+    let payload = response.get_payload();
+    if payload.len() < offset + 4 {
+        return 0;
+    }
+
+    let mut tenant: [u8; 4] = [0; 4];
+    tenant.copy_from_slice(&payload[offset..offset + 4]);
+    u32::from_le(unsafe { transmute(tenant) })
+}
+
+/// This function sets the flags on an RPC request that has already been
+/// populated.
+///
+/// # Arguments
+///
+/// * `request`: The RPC request packet, parsed upto it's IP header.
+/// * `flags`:   The flags on the request (ex: `FLAG_TRACE`).
+pub fn set_rpc_flags(request: &mut Packet<IpHeader, EmptyMetadata>, flags: u8) {
+    // The flags follow the header length, priority and deadline on the RPC
+    // header, which in turn follows the UDP header.
+    let offset = size_of::<UdpHeader>() + HEADER_LEN_OFFSET + size_of::<u16>() + 5;
+    request.get_mut_payload()[offset] = flags;
+}
+
+/// This function looks into a packet corresponding to an RPC request, and
+/// reads the flags on it (assumed to be the byte following the deadline).
+///
+/// # Arguments
+///
+/// * `request`: A reference to a packet corresponding to an RPC request.
+///              The packet should have been parsed upto it's UDP header.
+///
+/// # Return
+///
+/// The flags on the request. Zero if the request is too short to carry any.
+pub fn parse_rpc_flags(request: &Packet<UdpHeader, EmptyMetadata>) -> u8 {
+    let offset = HEADER_LEN_OFFSET + size_of::<u16>() + 5;
+    match request.get_payload().get(offset) {
+        Some(flags) => *flags,
+        None => 0,
+    }
+}
+
+/// This function sets the time a request spent waiting and running at the
+/// server on it's response. Refer to `RpcResponseHeader`.
+///
+/// # Arguments
+///
+/// * `response`: The RPC response packet, parsed upto it's UDP header.
+/// * `queued`:   The time in nanoseconds the request spent waiting to run.
+/// * `executed`: The time in nanoseconds the request spent running.
+pub fn set_rpc_stage_times(
+    response: &mut Packet<UdpHeader, EmptyMetadata>,
+    queued: u32,
+    executed: u32,
+) {
+    // The times follow the header length on the RPC header.
+    let offset = HEADER_LEN_OFFSET + size_of::<u16>();
+    let payload = response.get_mut_payload();
+    if payload.len() < offset + 8 {
+        return;
+    }
+
+    let q: [u8; 4] = unsafe { transmute(queued.to_le()) };
+    let e: [u8; 4] = unsafe { transmute(executed.to_le()) };
+    payload[offset..offset + 4].copy_from_slice(&q);
+    payload[offset + 4..offset + 8].copy_from_slice(&e);
+}
+
+/// This function looks into a packet corresponding to an RPC response, and
+/// reads the time it's request spent waiting and running at the server.
+///
+/// # Arguments
+///
+/// * `response`: A reference to a packet corresponding to an RPC response.
+///               The packet should have been parsed upto it's UDP header.
+///
+/// # Return
+///
+/// The time in nanoseconds the request spent waiting to run, and running, in
+/// that order. Both are zero if the request did not have `FLAG_TRACE` set, or
+/// the response is too short to carry them.
+pub fn parse_rpc_stage_times(response: &Packet<UdpHeader, EmptyMetadata>) -> (u32, u32) {
+    let offset = HEADER_LEN_OFFSET + size_of::<u16>();
+    let payload = response.get_payload();
+    if payload.len() < offset + 8 {
+        return (0, 0);
     }
+
+    let mut q: [u8; 4] = [0; 4];
+    let mut e: [u8; 4] = [0; 4];
+    q.copy_from_slice(&payload[offset..offset + 4]);
+    e.copy_from_slice(&payload[offset + 4..offset + 8]);
+    unsafe { (u32::from_le(transmute(q)), u32::from_le(transmute(e))) }
 }
 
 /// Allocate a packet with MAC, IP, and UDP headers for an RPC request.
@@ -115,6 +394,37 @@ fn create_request(
     return packet;
 }
 
+/// Allocates a packet with the same MAC, IP, and UDP headers as a response. Required to send
+/// responses that do not fit in a single packet (ex: scan()).
+///
+/// # Arguments
+///
+/// * `response`: The response packet, parsed upto it's UDP header.
+///
+/// # Return
+///
+/// The response packet, parsed upto it's UDP header again, and the copy parsed upto it's UDP
+/// header. The copy is None if a packet could not be allocated.
+pub fn copy_response_headers(
+    response: Packet<UdpHeader, EmptyMetadata>,
+) -> (
+    Packet<UdpHeader, EmptyMetadata>,
+    Option<Packet<UdpHeader, EmptyMetadata>>,
+) {
+    let mac = response
+        .deparse_header(size_of::<IpHeader>())
+        .deparse_header(size_of::<MacHeader>());
+    let copy = new_packet().and_then(|packet| packet.push_header(mac.get_header()));
+
+    let ip = mac.parse_header::<IpHeader>();
+    let copy = copy.and_then(|packet| packet.push_header(ip.get_header()));
+
+    let udp = ip.parse_header::<UdpHeader>();
+    let copy = copy.and_then(|packet| packet.push_header(udp.get_header()));
+
+    (udp, copy)
+}
+
 /// Sets the length fields on the UDP and IP headers of a packet.
 ///
 /// # Arguments
@@ -141,6 +451,232 @@ pub fn fixup_header_length_fields(
     return request;
 }
 
+/// Splits a response too large to be sent out in a single packet into fragments, each of which
+/// fits in one. Refer to `FragmentResponse` for the layout of a fragment.
+///
+/// # Arguments
+///
+/// * `response`: The response, parsed upto it's IP header, with it's length fields set.
+/// * `id`:       Identifies the response among those sent out by the server. Must not be reused
+///               until the client is done reassembling earlier responses with the same id.
+/// * `ipv6`:     True if the response is to be sent out over IPv6, whose header is longer.
+///
+/// # Return
+///
+/// The packets to be sent out in place of the response, each parsed upto it's IP header. Just
+/// the response if it already fits in a single packet. Empty if packets for the fragments could
+/// not be allocated, in which case the response is freed.
+pub fn fragment_response(
+    response: Packet<IpHeader, EmptyMetadata>,
+    id: u32,
+    ipv6: bool,
+) -> Vec<Packet<IpHeader, EmptyMetadata>> {
+    let mut response = response.parse_header::<UdpHeader>();
+    let len = response.get_payload().len();
+    let ip_len = if ipv6 { PACKET_IPV6_LEN } else { PACKET_IP_LEN };
+    if len + ip_len as usize <= PACKET_MTU as usize {
+        return vec![response.deparse_header(size_of::<IpHeader>())];
+    }
+
+    let stamp = parse_rpc_stamp(&response);
+    let tenant = parse_rpc_tenant(&response);
+    let payload = response.get_payload().to_vec();
+    let total = (len + FRAGMENT_PAYLOAD - 1) / FRAGMENT_PAYLOAD;
+
+    // The response itself carries the first fragment. Allocate a packet for every other one.
+    let mut copies = Vec::with_capacity(total - 1);
+    for _ in 1..total {
+        let (orig, copy) = copy_response_headers(response);
+        response = orig;
+        match copy {
+            Some(copy) => copies.push(copy),
+            None => break,
+        }
+    }
+
+    if copies.len() + 1 < total {
+        response.free_packet();
+        for copy in copies.drain(..) {
+            copy.free_packet();
+        }
+        return Vec::new();
+    }
+
+    let _ = response.remove_from_payload_tail(len);
+
+    let mut fragments = Vec::with_capacity(total);
+    let packets = Some(response).into_iter().chain(copies.into_iter());
+    for (index, (packet, chunk)) in packets.zip(payload.chunks(FRAGMENT_PAYLOAD)).enumerate() {
+        let mut packet = packet
+            .push_header(&FragmentResponse::new(
+                stamp,
+                tenant,
+                id,
+                index as u16,
+                total as u16,
+            )).expect("Failed to setup FragmentResponse");
+        packet
+            .add_to_payload_tail(chunk.len(), chunk)
+            .expect("Failed to write fragment into packet");
+
+        let packet = packet.deparse_header(size_of::<UdpHeader>());
+        fragments.push(fixup_header_length_fields(packet));
+    }
+
+    fragments
+}
+
+// A response that has had some, but not all of it's fragments received.
+struct Partial {
+    // The number of fragments making up the response.
+    total: u16,
+
+    // The number of fragments received so far.
+    received: u16,
+
+    // The payload of every fragment, indexed by it's position. None until received.
+    chunks: Vec<Option<Vec<u8>>>,
+
+    // The order in which the first of the response's fragments was received. Required to evict
+    // the oldest response when too many are outstanding.
+    seq: u64,
+}
+
+/// Reassembles responses that were split into fragments by `fragment_response()`. Responses
+/// whose fragments are lost are never completed, so only a bounded number of them are held on
+/// to, beyond which the one that started reassembly the earliest is dropped.
+pub struct Reassembler {
+    // Responses being reassembled, keyed by the address of the server that sent them out, and
+    // the identifier on their fragments.
+    partial: HashMap<(u32, u32), Partial>,
+
+    // The largest number of responses that can be reassembled at once.
+    max: usize,
+
+    // The number of responses that have started reassembly so far.
+    seq: u64,
+}
+
+// Implementation of methods on Reassembler.
+impl Reassembler {
+    /// Constructs a Reassembler.
+    ///
+    /// # Arguments
+    ///
+    /// * `max`: The largest number of responses that can be reassembled at once. At least one.
+    ///
+    /// # Return
+    ///
+    /// A Reassembler with no responses outstanding.
+    pub fn new(max: usize) -> Reassembler {
+        Reassembler {
+            partial: HashMap::new(),
+            max: max.max(1),
+            seq: 0,
+        }
+    }
+
+    /// Returns the number of responses that have had some, but not all of their fragments
+    /// received.
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Adds a fragment of a response.
+    ///
+    /// # Arguments
+    ///
+    /// * `fragment`: A packet parsed upto it's UDP header, whose header length has been checked
+    ///               to fit a `FragmentResponse`.
+    ///
+    /// # Return
+    ///
+    /// The response, parsed upto it's UDP header, if this was the last of it's fragments to be
+    /// received. The response is rebuilt on the packet of this fragment. None otherwise, in which
+    /// case the fragment is freed.
+    pub fn add(
+        &mut self,
+        fragment: Packet<UdpHeader, EmptyMetadata>,
+    ) -> Option<Packet<UdpHeader, EmptyMetadata>> {
+        let ip = fragment.deparse_header(size_of::<IpHeader>());
+        let src = ip.get_header().src();
+
+        let fragment = ip
+            .parse_header::<UdpHeader>()
+            .parse_header::<FragmentResponse>();
+        let (id, index, total) = {
+            let hdr = fragment.get_header();
+            (hdr.id, hdr.index, hdr.total)
+        };
+        let chunk = fragment.get_payload().to_vec();
+        let mut fragment = fragment.deparse_header(size_of::<UdpHeader>());
+
+        if index >= total {
+            fragment.free_packet();
+            return None;
+        }
+
+        // Make room for this response if it is the first of it's fragments to be received.
+        let key = (src, id);
+        let fresh = !self.partial.contains_key(&key);
+        if fresh && self.partial.len() >= self.max {
+            let oldest = self
+                .partial
+                .iter()
+                .min_by_key(|&(_, partial)| partial.seq)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.partial.remove(&oldest);
+            }
+        }
+
+        if fresh {
+            self.seq += 1;
+        }
+
+        let complete = {
+            let seq = self.seq;
+            let partial = self.partial.entry(key).or_insert_with(|| Partial {
+                total: total,
+                received: 0,
+                chunks: vec![None; total as usize],
+                seq: seq,
+            });
+
+            // Fragments that disagree with the ones received earlier, and duplicates are
+            // dropped.
+            if partial.total == total && partial.chunks[index as usize].is_none() {
+                partial.chunks[index as usize] = Some(chunk);
+                partial.received += 1;
+            }
+
+            partial.received == partial.total
+        };
+
+        if !complete {
+            fragment.free_packet();
+            return None;
+        }
+
+        let partial = self.partial.remove(&key)?;
+        let mut payload = Vec::new();
+        for chunk in partial.chunks.into_iter() {
+            if let Some(chunk) = chunk {
+                payload.extend_from_slice(&chunk);
+            }
+        }
+
+        let len = fragment.get_payload().len();
+        let _ = fragment.remove_from_payload_tail(len);
+        if fragment.add_to_payload_tail(payload.len(), &payload).is_err() {
+            fragment.free_packet();
+            return None;
+        }
+
+        Some(fragment)
+    }
+}
+
 /// Allocate and populate a packet that requests a server "get" operation.
 ///
 /// # Panic
@@ -247,51 +783,912 @@ pub fn create_put_rpc(
     fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
 }
 
-/// Allocate and populate a packet that requests a server "multiget" operation.
+/// Allocate and populate a packet that requests a server "bulk_put" operation, applying a
+/// batch of records to the tenant's tables in order.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
 ///
 /// # Arguments
 ///
-/// * `mac`:      Reference to the MAC header to be added to the request.
-/// * `ip` :      Reference to the IP header to be added to the request.
-/// * `udp`:      Reference to the UDP header to be added to the request.
-/// * `tenant`:   Id of the tenant requesting the item.
-/// * `table_id`: Id of the table from which the key is looked up.
-/// * `key_len`:  The length of each key to be looked up at the server. All keys are
-///               assumed to be of equal length.
-/// * `num_keys`: The number of keys to be looked up at the server.
-/// * `keys`:     Byte string of key whose values are to be fetched.
-/// * `id`:       RPC identifier.
-/// * `dst`:      The UDP port on the server the RPC is destined for.
+/// * `mac`:         Reference to the MAC header to be added to the request.
+/// * `ip` :         Reference to the IP header to be added to the request.
+/// * `udp`:         Reference to the UDP header to be added to the request.
+/// * `tenant`:      Id of the tenant requesting the insertion.
+/// * `num_records`: The number of records on `records`.
+/// * `records`:     Records encoded with `migrate::encode()`. Limit `BULK_PUT_PAYLOAD` bytes.
+/// * `id`:          RPC identifier.
+/// * `dst`:         The UDP port on the server the RPC is destined for.
 ///
 /// # Return
 ///
 /// Packet populated with the request parameters.
 #[inline]
-pub fn create_multiget_rpc(
+pub fn create_bulk_put_rpc(
     mac: &MacHeader,
     ip: &IpHeader,
     udp: &UdpHeader,
     tenant: u32,
-    table_id: u64,
-    key_len: u16,
-    num_keys: u32,
-    keys: &[u8],
+    num_records: u32,
+    records: &[u8],
     id: u64,
     dst: u16,
 ) -> Packet<IpHeader, EmptyMetadata> {
+    // Requests are never fragmented. Required for the request to fit in a single packet.
+    if records.len() > BULK_PUT_PAYLOAD {
+        panic!("Records too long ({} bytes).", records.len());
+    }
+
     // Allocate a packet, write the header and payload into it, and set fields on it's UDP and IP
     // header.
     let mut request = create_request(mac, ip, udp, dst)
-        .push_header(&MultiGetRequest::new(tenant, table_id, key_len, num_keys, id))
+        .push_header(&BulkPutRequest::new(tenant, num_records, id))
         .expect("Failed to push RPC header into request!");
 
     request
-        .add_to_payload_tail(keys.len(), &keys)
-        .expect("Failed to write key into multiget() request!");
+        .add_to_payload_tail(records.len(), records)
+        .expect("Failed to write records into bulk_put() request!");
 
     fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
 }
 
+/// Allocate and populate a packet that requests a server "cas" operation.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:      Reference to the MAC header to be added to the request.
+/// * `ip` :      Reference to the IP header to be added to the request.
+/// * `udp`:      Reference to the UDP header to be added to the request.
+/// * `tenant`:   Id of the tenant requesting the insertion.
+/// * `table_id`: Id of the table into which the key-value pair is to be inserted.
+/// * `key`:      Byte string of key whose value is to be inserted. Limit 64 KB.
+/// * `val`:      Byte string of the value to be inserted.
+/// * `version`:  The version the object under the key is expected to be at, as returned by a
+///               get(). Zero if the key is expected to not exist.
+/// * `id`:       RPC identifier.
+/// * `dst`:      The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_cas_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    table_id: u64,
+    key: &[u8],
+    val: &[u8],
+    version: u64,
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Key length cannot be more than 16 bits. Required to construct the RPC header.
+    if key.len() > u16::max_value() as usize {
+        panic!("Key too long ({} bytes).", key.len());
+    }
+
+    let mut request = create_request(mac, ip, udp, dst)
+        .push_header(&CasRequest::new(tenant, table_id, key.len() as u16, version, id))
+        .expect("Failed to push RPC header into request!");
+
+    let mut payload = Vec::with_capacity(key.len() + val.len());
+    payload.extend_from_slice(key);
+    payload.extend_from_slice(val);
+
+    request
+        .add_to_payload_tail(payload.len(), &payload)
+        .expect("Failed to write key into cas() request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// Allocate and populate a packet that requests a server "multiget" operation.
+///
+/// # Arguments
+///
+/// * `mac`:      Reference to the MAC header to be added to the request.
+/// * `ip` :      Reference to the IP header to be added to the request.
+/// * `udp`:      Reference to the UDP header to be added to the request.
+/// * `tenant`:   Id of the tenant requesting the item.
+/// * `table_id`: Id of the table from which the key is looked up.
+/// * `key_len`:  The length of each key to be looked up at the server. All keys are
+///               assumed to be of equal length.
+/// * `num_keys`: The number of keys to be looked up at the server.
+/// * `keys`:     Byte string of key whose values are to be fetched.
+/// * `id`:       RPC identifier.
+/// * `dst`:      The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_multiget_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    table_id: u64,
+    key_len: u16,
+    num_keys: u32,
+    keys: &[u8],
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Allocate a packet, write the header and payload into it, and set fields on it's UDP and IP
+    // header.
+    let mut request = create_request(mac, ip, udp, dst)
+        .push_header(&MultiGetRequest::new(tenant, table_id, key_len, num_keys, id))
+        .expect("Failed to push RPC header into request!");
+
+    request
+        .add_to_payload_tail(keys.len(), &keys)
+        .expect("Failed to write key into multiget() request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// Allocate and populate a packet that requests a server "multiget" operation across multiple
+/// tables.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:      Reference to the MAC header to be added to the request.
+/// * `ip` :      Reference to the IP header to be added to the request.
+/// * `udp`:      Reference to the UDP header to be added to the request.
+/// * `tenant`:   Id of the tenant requesting the items.
+/// * `key_len`:  The length of each key to be looked up at the server. All keys are
+///               assumed to be of equal length.
+/// * `num_keys`: The number of (table, key) pairs to be looked up at the server.
+/// * `keys`:     Byte string of (table, key) pairs whose values are to be fetched. Each table
+///               identifier is eight bytes long and little-endian.
+/// * `id`:       RPC identifier.
+/// * `dst`:      The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_multitable_get_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    key_len: u16,
+    num_keys: u32,
+    keys: &[u8],
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Allocate a packet, write the header and payload into it, and set fields on it's UDP and IP
+    // header.
+    let mut request = create_request(mac, ip, udp, dst)
+        .push_header(&MultiTableGetRequest::new(tenant, key_len, num_keys, id))
+        .expect("Failed to push RPC header into request!");
+
+    request
+        .add_to_payload_tail(keys.len(), &keys)
+        .expect("Failed to write keys into multiget() request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// Allocate and populate a packet that requests a server "list_tables" operation.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:    Reference to the MAC header to be added to the request.
+/// * `ip` :    Reference to the IP header to be added to the request.
+/// * `udp`:    Reference to the UDP header to be added to the request.
+/// * `tenant`: Id of the tenant whose tables should be listed.
+/// * `id`:     RPC identifier.
+/// * `dst`:    The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_list_tables_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Allocate a packet, write the header into it, and set fields on it's UDP and IP header.
+    let request = create_request(mac, ip, udp, dst)
+        .push_header(&ListTablesRequest::new(tenant, id))
+        .expect("Failed to push RPC header into request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// Allocate and populate a packet that requests a server "scan" operation.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:    Reference to the MAC header to be added to the request.
+/// * `ip` :    Reference to the IP header to be added to the request.
+/// * `udp`:    Reference to the UDP header to be added to the request.
+/// * `tenant`: Id of the tenant issuing the request.
+/// * `table`:  Id of the ordered table to be scanned.
+/// * `start`:  The first key in the range. Limit 64 KB.
+/// * `end`:    The key after the last one in the range, or an empty slice if the range extends
+///             to the end of the table. Limit 64 KB.
+/// * `limit`:  The maximum number of objects to be returned.
+/// * `id`:     RPC identifier.
+/// * `dst`:    The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_scan_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    table: u64,
+    start: &[u8],
+    end: &[u8],
+    limit: u32,
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Key lengths cannot be more than 16 bits. Required to construct the RPC header.
+    if start.len() > u16::max_value() as usize || end.len() > u16::max_value() as usize {
+        panic!("Key too long ({} and {} bytes).", start.len(), end.len());
+    }
+
+    // Allocate a packet, write the header and payload into it, and set fields on it's UDP and IP
+    // header.
+    let hdr = ScanRequest::new(tenant, table, start.len() as u16, end.len() as u16, limit, id);
+    let mut request = create_request(mac, ip, udp, dst)
+        .push_header(&hdr)
+        .expect("Failed to push RPC header into request!");
+
+    request
+        .add_to_payload_tail(start.len(), start)
+        .expect("Failed to write start key into scan() request!");
+    request
+        .add_to_payload_tail(end.len(), end)
+        .expect("Failed to write end key into scan() request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// This type represents a single object returned by a scan() RPC.
+#[derive(Debug, PartialEq)]
+pub struct ScanRecord {
+    /// The key of the object.
+    pub key: Vec<u8>,
+
+    /// The value of the object.
+    pub value: Vec<u8>,
+}
+
+/// Parses the objects off a single packet of the response to a scan() RPC. The objects returned
+/// by the scan are in key order once the packets are ordered by their `seq`.
+///
+/// # Arguments
+///
+/// * `response`: A packet of the response to a scan() RPC, parsed upto it's ScanResponse
+///               header.
+///
+/// # Return
+///
+/// The objects on the packet if the response has a status of `StatusOk`. An empty vector
+/// otherwise.
+pub fn parse_scan(response: &Packet<ScanResponse, EmptyMetadata>) -> Vec<ScanRecord> {
+    let count = {
+        let hdr = response.get_header();
+        if hdr.common_header.status != RpcStatus::StatusOk {
+            return Vec::new();
+        }
+
+        hdr.num_records as usize
+    };
+
+    // The count is untrusted, so don't reserve more entries than the payload can hold.
+    let mut payload = response.get_payload();
+    let mut records = Vec::with_capacity(count.min(payload.len() / SCAN_RECORD_HEADER_LEN));
+
+    while records.len() < count && payload.len() >= SCAN_RECORD_HEADER_LEN {
+        let mut k_len: [u8; 2] = [0; 2];
+        let mut v_len: [u8; 4] = [0; 4];
+        k_len.copy_from_slice(&payload[0..2]);
+        v_len.copy_from_slice(&payload[2..6]);
+
+        let k_len = u16::from_le(unsafe { transmute(k_len) }) as usize;
+        let v_len = u32::from_le(unsafe { transmute(v_len) }) as usize;
+        if payload.len() - SCAN_RECORD_HEADER_LEN < k_len + v_len {
+            break;
+        }
+
+        let (entry, rest) = payload.split_at(SCAN_RECORD_HEADER_LEN + k_len + v_len);
+        let (key, value) = entry[SCAN_RECORD_HEADER_LEN..].split_at(k_len);
+        records.push(ScanRecord {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        payload = rest;
+    }
+
+    records
+}
+
+/// Allocate and populate a packet that requests a server "move_key" operation.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:       Reference to the MAC header to be added to the request.
+/// * `ip` :       Reference to the IP header to be added to the request.
+/// * `udp`:       Reference to the UDP header to be added to the request.
+/// * `tenant`:    Id of the tenant issuing the request.
+/// * `src_table`: Id of the table the object is currently in.
+/// * `dst_table`: Id of the table the object should be moved into.
+/// * `key`:       Byte string of the key of the object to be moved. Limit 64 KB.
+/// * `id`:        RPC identifier.
+/// * `dst`:       The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_move_key_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    src_table: u64,
+    dst_table: u64,
+    key: &[u8],
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Key length cannot be more than 16 bits. Required to construct the RPC header.
+    if key.len() > u16::max_value() as usize {
+        panic!("Key too long ({} bytes).", key.len());
+    }
+
+    // Allocate a packet, write the header and payload into it, and set fields on it's UDP and IP
+    // header.
+    let mut request = create_request(mac, ip, udp, dst)
+        .push_header(&MoveKeyRequest::new(tenant, src_table, dst_table, key.len() as u16, id))
+        .expect("Failed to push RPC header into request!");
+
+    request
+        .add_to_payload_tail(key.len(), &key)
+        .expect("Failed to write key into move_key() request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// Allocate and populate a packet that requests a server "delete" operation.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:    Reference to the MAC header to be added to the request.
+/// * `ip` :    Reference to the IP header to be added to the request.
+/// * `udp`:    Reference to the UDP header to be added to the request.
+/// * `tenant`: Id of the tenant issuing the request.
+/// * `table`:  Id of the table to delete the key from.
+/// * `key`:    Byte string of the key to be deleted. Limit 64 KB.
+/// * `id`:     RPC identifier.
+/// * `dst`:    The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_delete_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    table: u64,
+    key: &[u8],
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Key length cannot be more than 16 bits. Required to construct the RPC header.
+    if key.len() > u16::max_value() as usize {
+        panic!("Key too long ({} bytes).", key.len());
+    }
+
+    // Allocate a packet, write the header and payload into it, and set fields on it's UDP and IP
+    // header.
+    let mut request = create_request(mac, ip, udp, dst)
+        .push_header(&DeleteRequest::new(tenant, table, key.len() as u16, id))
+        .expect("Failed to push RPC header into request!");
+
+    request
+        .add_to_payload_tail(key.len(), &key)
+        .expect("Failed to write key into delete() request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// Allocate and populate a packet that requests a server "server_info" operation.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:    Reference to the MAC header to be added to the request.
+/// * `ip` :    Reference to the IP header to be added to the request.
+/// * `udp`:    Reference to the UDP header to be added to the request.
+/// * `tenant`: Id of the tenant issuing the request.
+/// * `id`:     RPC identifier.
+/// * `dst`:    The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_server_info_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Allocate a packet, write the header into it, and set fields on it's UDP and IP header.
+    let request = create_request(mac, ip, udp, dst)
+        .push_header(&ServerInfoRequest::new(tenant, id))
+        .expect("Failed to push RPC header into request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// Allocate and populate a packet that requests a server "get_statistics" operation.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:    Reference to the MAC header to be added to the request.
+/// * `ip` :    Reference to the IP header to be added to the request.
+/// * `udp`:    Reference to the UDP header to be added to the request.
+/// * `tenant`: Id of the tenant issuing the request.
+/// * `id`:     RPC identifier.
+/// * `dst`:    The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_get_statistics_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Allocate a packet, write the header into it, and set fields on it's UDP and IP header.
+    let request = create_request(mac, ip, udp, dst)
+        .push_header(&GetStatisticsRequest::new(tenant, id))
+        .expect("Failed to push RPC header into request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// Allocate and populate a packet that requests a server "tenant_admin" operation.
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:      Reference to the MAC header to be added to the request.
+/// * `ip` :      Reference to the IP header to be added to the request.
+/// * `udp`:      Reference to the UDP header to be added to the request.
+/// * `tenant`:   Id of the tenant issuing the request. Must be the server's admin tenant.
+/// * `target`:   Id of the tenant the operation should be performed on.
+/// * `action`:   The operation to be performed on the target tenant.
+/// * `table_id`: Id of the table to be created or dropped. Ignored by operations on the tenant.
+/// * `id`:       RPC identifier.
+/// * `dst`:      The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_tenant_admin_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    target: u32,
+    action: TenantAction,
+    table_id: u64,
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Allocate a packet, write the header into it, and set fields on it's UDP and IP header.
+    let request = create_request(mac, ip, udp, dst)
+        .push_header(&TenantAdminRequest::new(tenant, target, action, table_id, id))
+        .expect("Failed to push RPC header into request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// Parses the statistics on the response to a get_statistics() RPC.
+///
+/// # Arguments
+///
+/// * `response`: The response to a get_statistics() RPC, parsed upto it's GetStatisticsResponse
+///               header.
+///
+/// # Return
+///
+/// The server's statistics. None if the RPC did not succeed, or if they were malformed.
+pub fn parse_statistics(
+    response: &Packet<GetStatisticsResponse, EmptyMetadata>,
+) -> Option<ServerStatistics> {
+    let length = {
+        let hdr = response.get_header();
+        if hdr.common_header.status != RpcStatus::StatusOk {
+            return None;
+        }
+
+        hdr.length as usize
+    };
+
+    let payload = response.get_payload();
+    if payload.len() < length {
+        return None;
+    }
+
+    ServerStatistics::decode(&payload[..length])
+}
+
+/// Metadata describing a table, as returned by a list_tables() RPC.
+#[derive(Debug, PartialEq)]
+pub struct TableInfo {
+    /// The identifier of the table.
+    pub id: u64,
+
+    /// The kind of the table. Refer to `TableKind`.
+    pub kind: u8,
+
+    /// The number of objects in the table.
+    pub num_objects: u64,
+
+    /// The time at which the table was created in seconds since the unix epoch.
+    pub created: u64,
+}
+
+/// Parses the tables listed on the response to a list_tables() RPC.
+///
+/// # Arguments
+///
+/// * `response`: The response to a list_tables() RPC, parsed upto it's ListTablesResponse header.
+///
+/// # Return
+///
+/// The tables listed on the response. Empty if the RPC did not succeed.
+pub fn parse_list_tables(response: &Packet<ListTablesResponse, EmptyMetadata>) -> Vec<TableInfo> {
+    let num_tables = {
+        let hdr = response.get_header();
+        if hdr.common_header.status != RpcStatus::StatusOk {
+            return Vec::new();
+        }
+
+        hdr.num_tables as usize
+    };
+
+    // Reads a little-endian u64 starting at a given offset into an entry.
+    let read = |entry: &[u8], offset: usize| -> u64 {
+        let mut v: [u8; 8] = [0; 8];
+        v.copy_from_slice(&entry[offset..offset + 8]);
+        u64::from_le(unsafe { transmute(v) })
+    };
+
+    response
+        .get_payload()
+        .chunks(LIST_TABLES_ENTRY_LEN)
+        .filter(|entry| entry.len() == LIST_TABLES_ENTRY_LEN)
+        .take(num_tables)
+        .map(|entry| TableInfo {
+            id: read(entry, 0),
+            kind: entry[8],
+            num_objects: read(entry, 9),
+            created: read(entry, 17),
+        })
+        .collect()
+}
+
+/// Allocate and populate a packet that requests one of the server's association operations
+/// (add, del, range, or count).
+///
+/// # Panic
+///
+/// May panic if there is a problem allocating the packet or constructing
+/// headers.
+///
+/// # Arguments
+///
+/// * `mac`:    Reference to the MAC header to be added to the request.
+/// * `ip` :    Reference to the IP header to be added to the request.
+/// * `udp`:    Reference to the UDP header to be added to the request.
+/// * `tenant`: Id of the tenant owning the association list.
+/// * `opcode`: One of the association opcodes.
+/// * `id1`:    The object the association list originates at.
+/// * `atype`:  The type of the association list.
+/// * `id2`:    The object the association points to. Ignored by range and count.
+/// * `time`:   The time of the association on an add, and the time of the newest association
+///             that can be returned on a range. Ignored by del and count.
+/// * `limit`:  The maximum number of associations returned by a range.
+/// * `data`:   The data attached to the association on an add. Empty otherwise.
+/// * `id`:     RPC identifier.
+/// * `dst`:    The UDP port on the server the RPC is destined for.
+///
+/// # Return
+///
+/// Packet populated with the request parameters.
+#[inline]
+pub fn create_assoc_rpc(
+    mac: &MacHeader,
+    ip: &IpHeader,
+    udp: &UdpHeader,
+    tenant: u32,
+    opcode: OpCode,
+    id1: u64,
+    atype: u16,
+    id2: u64,
+    time: u64,
+    limit: u32,
+    data: &[u8],
+    id: u64,
+    dst: u16,
+) -> Packet<IpHeader, EmptyMetadata> {
+    // Allocate a packet, write the header and payload into it, and set fields on it's UDP and IP
+    // header.
+    let mut request = create_request(mac, ip, udp, dst)
+        .push_header(&AssocRequest::new(
+            tenant, opcode, id1, atype, id2, time, limit, id,
+        ))
+        .expect("Failed to push RPC header into request!");
+
+    request
+        .add_to_payload_tail(data.len(), data)
+        .expect("Failed to write data into assoc() request!");
+
+    fixup_header_length_fields(request.deparse_header(size_of::<UdpHeader>()))
+}
+
+/// An association, as returned by an assoc_range() RPC.
+#[derive(Debug, PartialEq)]
+pub struct AssocInfo {
+    /// The object the association points to.
+    pub id2: u64,
+
+    /// The time of the association.
+    pub time: u64,
+
+    /// The data attached to the association.
+    pub data: Vec<u8>,
+}
+
+/// Parses the associations on the response to an assoc_range() RPC.
+///
+/// # Arguments
+///
+/// * `response`: The response to an assoc_range() RPC, parsed upto it's AssocResponse header.
+///
+/// # Return
+///
+/// The associations on the response, newest first. Empty if the RPC did not succeed.
+pub fn parse_assoc_range(response: &Packet<AssocResponse, EmptyMetadata>) -> Vec<AssocInfo> {
+    let count = {
+        let hdr = response.get_header();
+        if hdr.common_header.status != RpcStatus::StatusOk {
+            return Vec::new();
+        }
+
+        hdr.count as usize
+    };
+
+    // The count is untrusted, so don't reserve more entries than the payload can hold.
+    let mut payload = response.get_payload();
+    let mut assocs = Vec::with_capacity(count.min(payload.len() / ASSOC_ENTRY_HEADER_LEN));
+
+    while assocs.len() < count && payload.len() >= ASSOC_ENTRY_HEADER_LEN {
+        let mut id2: [u8; 8] = [0; 8];
+        let mut time: [u8; 8] = [0; 8];
+        let mut len: [u8; 4] = [0; 4];
+        id2.copy_from_slice(&payload[0..8]);
+        time.copy_from_slice(&payload[8..16]);
+        len.copy_from_slice(&payload[16..20]);
+
+        let len = u32::from_le(unsafe { transmute(len) }) as usize;
+        if payload.len() < ASSOC_ENTRY_HEADER_LEN + len {
+            break;
+        }
+
+        let (entry, rest) = payload.split_at(ASSOC_ENTRY_HEADER_LEN + len);
+        assocs.push(AssocInfo {
+            id2: u64::from_le(unsafe { transmute(id2) }),
+            time: u64::from_le(unsafe { transmute(time) }),
+            data: entry[ASSOC_ENTRY_HEADER_LEN..].to_vec(),
+        });
+        payload = rest;
+    }
+
+    assocs
+}
+
+/// An error returned to a tenant by an extension that failed. Refer to `DB::resp_error()`.
+#[derive(Debug, PartialEq)]
+pub struct ExtensionError {
+    /// The error code defined by the extension.
+    pub code: u32,
+
+    /// A short description of the error.
+    pub msg: String,
+}
+
+/// Parses the error returned by an extension from the response to an invoke() RPC.
+///
+/// # Arguments
+///
+/// * `response`: The response to an invoke() RPC, parsed upto it's InvokeResponse header.
+///
+/// # Return
+///
+/// The error returned by the extension if the response has a status of `StatusExtensionError`.
+/// None otherwise.
+pub fn parse_extension_error(
+    response: &Packet<InvokeResponse, EmptyMetadata>,
+) -> Option<ExtensionError> {
+    if response.get_header().common_header.status != RpcStatus::StatusExtensionError {
+        return None;
+    }
+
+    // The payload consists of a four byte error code followed by the message.
+    let payload = response.get_payload();
+    if payload.len() < 4 {
+        return None;
+    }
+
+    let mut code: [u8; 4] = [0; 4];
+    code.copy_from_slice(&payload[0..4]);
+
+    Some(ExtensionError {
+        code: u32::from_le(unsafe { transmute(code) }),
+        msg: String::from_utf8_lossy(&payload[4..]).into_owned(),
+    })
+}
+
+/// Parses the address a request should be reissued at off the response to it, if the tenant
+/// that sent the request was migrated to another server. Works on the response to any RPC.
+///
+/// # Arguments
+///
+/// * `response`: The response to an RPC, parsed upto it's UDP header.
+///
+/// # Return
+///
+/// The address (IPv4:Port) of the server the tenant was migrated to if the response has a
+/// status of `StatusMoved`. None otherwise.
+pub fn parse_redirect(response: &Packet<UdpHeader, EmptyMetadata>) -> Option<String> {
+    if !header_len_ok(response, size_of::<RpcResponseHeader>())
+        || response.get_payload()[0] != RpcStatus::StatusMoved as u8
+    {
+        return None;
+    }
+
+    let addr = &response.get_payload()[parse_rpc_header_len(response)..];
+    from_utf8(addr).ok().map(|addr| addr.to_string())
+}
+
+/// Partial results returned to a tenant by an extension that stopped early. Refer to
+/// `DB::resp_partial()`.
+#[derive(Debug, PartialEq)]
+pub struct PartialResult {
+    /// The results produced by the extension before it stopped.
+    pub results: Vec<u8>,
+
+    /// The continuation token to be passed back to the extension to resume it.
+    pub token: Vec<u8>,
+}
+
+/// Splits the payload of a response with partial results into the results and the token.
+///
+/// # Arguments
+///
+/// * `payload`: The payload on an invoke() response with a status of `StatusPartialResult`.
+///
+/// # Return
+///
+/// The results and continuation token if the payload was well formed. None otherwise.
+fn split_partial_result(payload: &[u8]) -> Option<PartialResult> {
+    // The payload ends with the four byte length of the continuation token.
+    if payload.len() < 4 {
+        return None;
+    }
+
+    let (rest, len) = payload.split_at(payload.len() - 4);
+    let mut l: [u8; 4] = [0; 4];
+    l.copy_from_slice(len);
+    let len = u32::from_le(unsafe { transmute(l) }) as usize;
+    if rest.len() < len {
+        return None;
+    }
+
+    let (results, token) = rest.split_at(rest.len() - len);
+    Some(PartialResult {
+        results: results.to_vec(),
+        token: token.to_vec(),
+    })
+}
+
+/// Parses the partial results returned by an extension from the response to an invoke() RPC.
+/// The tenant can resume the extension by reissuing the invoke() with the returned token.
+///
+/// # Arguments
+///
+/// * `response`: The response to an invoke() RPC, parsed upto it's InvokeResponse header.
+///
+/// # Return
+///
+/// The partial results and continuation token returned by the extension if the response has a
+/// status of `StatusPartialResult`. None otherwise.
+pub fn parse_partial_result(
+    response: &Packet<InvokeResponse, EmptyMetadata>,
+) -> Option<PartialResult> {
+    if response.get_header().common_header.status != RpcStatus::StatusPartialResult {
+        return None;
+    }
+
+    split_partial_result(response.get_payload())
+}
+
 /// Allocate and populate a packet that requests a server "invoke" operation.
 ///
 /// # Panic
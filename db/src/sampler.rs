@@ -0,0 +1,192 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::fs::File;
+use std::io::Write;
+use std::mem::transmute;
+
+use super::wireformat::*;
+
+// The number of bytes of sampled requests buffered in memory before they are written out to the
+// capture file. Keeps file writes off the common path of the dispatcher.
+const FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// This type mirrors 1-in-N requests received by a dispatcher into a capture file so that the
+/// workload (key popularity, op mix etc.) can be characterized live without modifying clients.
+///
+/// Each sampled request is recorded in the capture file with the following layout (little-endian):
+///      _________________________________________________
+///     |            |            |                       |
+///     |  Cycles    |  Length    |   RPC header/payload  |
+///     |____________|____________|_______________________|
+///        8 Bytes      4 Bytes          Var Length
+pub struct Sampler {
+    // One out of every `rate` requests is sampled. A rate of zero disables sampling.
+    rate: u64,
+
+    // The number of requests seen by the sampler since the last sample was taken.
+    seen: u64,
+
+    // If true, only the RPC header on the request is recorded. Otherwise, the entire request
+    // (header and payload) is recorded.
+    headers_only: bool,
+
+    // Sampled requests that have not been written out to the capture file yet.
+    buffer: Vec<u8>,
+
+    // The capture file sampled requests are written out to.
+    file: Option<File>,
+}
+
+// Implementation of methods on Sampler.
+impl Sampler {
+    /// Creates a Sampler.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate`:         One out of every `rate` requests will be sampled. Zero disables sampling.
+    /// * `headers_only`: If true, only RPC headers are recorded.
+    /// * `path`:         Path of the capture file sampled requests are written to.
+    ///
+    /// # Return
+    ///
+    /// A `Sampler`. If the capture file could not be created, sampling is disabled.
+    pub fn new(rate: u64, headers_only: bool, path: &str) -> Sampler {
+        let mut file = None;
+
+        if rate > 0 {
+            match File::create(path) {
+                Ok(f) => file = Some(f),
+
+                Err(ref err) => {
+                    warn!("Failed to create sample file {}: {}. Disabling sampling.", path, err);
+                }
+            }
+        }
+
+        Sampler {
+            rate: if file.is_some() { rate } else { 0 },
+            seen: 0,
+            headers_only: headers_only,
+            buffer: Vec::with_capacity(FLUSH_THRESHOLD * 2),
+            file: file,
+        }
+    }
+
+    /// Returns true if the sampler has been configured to sample requests.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.rate > 0
+    }
+
+    /// Decides whether a request must be sampled, and if so, records it.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode`:  The opcode on the request. Determines the length of the RPC header.
+    /// * `request`: The RPC request (header followed by payload), starting at the end of the
+    ///              UDP header.
+    /// * `stamp`:   Timestamp in cycles to be recorded along with the request.
+    pub fn sample(&mut self, opcode: &OpCode, request: &[u8], stamp: u64) {
+        if !self.enabled() {
+            return;
+        }
+
+        self.seen += 1;
+        if self.seen < self.rate {
+            return;
+        }
+        self.seen = 0;
+
+        // Determine how much of the request needs to be recorded.
+        let mut len = request.len();
+        if self.headers_only {
+            len = request_header_len(opcode).min(len);
+        }
+
+        let stamp: [u8; 8] = unsafe { transmute(stamp.to_le()) };
+        let l: [u8; 4] = unsafe { transmute((len as u32).to_le()) };
+        self.buffer.extend_from_slice(&stamp);
+        self.buffer.extend_from_slice(&l);
+        self.buffer.extend_from_slice(&request[0..len]);
+
+        if self.buffer.len() >= FLUSH_THRESHOLD {
+            self.flush();
+        }
+    }
+
+    /// Writes out all buffered samples to the capture file.
+    pub fn flush(&mut self) {
+        if let Some(ref mut file) = self.file {
+            if let Err(ref err) = file.write_all(&self.buffer) {
+                warn!("Failed to write to sample file: {}", err);
+            }
+        }
+
+        self.buffer.clear();
+    }
+}
+
+// Implementation of the Drop trait for Sampler. Makes sure that buffered samples make it to disk.
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+// This module contains simple unit tests for Sampler.
+#[cfg(test)]
+mod tests {
+    use super::Sampler;
+    use wireformat::{request_header_len, OpCode};
+
+    // This test verifies that exactly one out of every `rate` requests is recorded.
+    #[test]
+    fn test_sample_rate() {
+        let mut sampler = Sampler::new(4, false, "/tmp/sandstorm_sampler_rate.test");
+        let request = [1u8; 10];
+
+        for _ in 0..8 {
+            sampler.sample(&OpCode::SandstormGetRpc, &request, 0);
+        }
+
+        // Two samples of 10 bytes each along with a 12 byte record header.
+        assert_eq!(2 * (12 + 10), sampler.buffer.len());
+    }
+
+    // This test verifies that only the RPC header is recorded in headers only mode.
+    #[test]
+    fn test_sample_headers_only() {
+        let mut sampler = Sampler::new(1, true, "/tmp/sandstorm_sampler_hdr.test");
+        let request = [1u8; 100];
+
+        sampler.sample(&OpCode::SandstormGetRpc, &request, 7);
+
+        let hdr = request_header_len(&OpCode::SandstormGetRpc);
+        assert_eq!(12 + hdr, sampler.buffer.len());
+        assert_eq!(&[7, 0, 0, 0, 0, 0, 0, 0], &sampler.buffer[0..8]);
+        assert_eq!(hdr as u8, sampler.buffer[8]);
+    }
+
+    // This test verifies that a sampler with a zero rate never records anything.
+    #[test]
+    fn test_sample_disabled() {
+        let mut sampler = Sampler::new(0, false, "");
+        assert!(!sampler.enabled());
+
+        sampler.sample(&OpCode::SandstormGetRpc, &[1u8; 10], 0);
+        assert_eq!(0, sampler.buffer.len());
+    }
+}
@@ -13,13 +13,21 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-use std::collections::VecDeque;
+use std::cmp;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::mem;
 use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+use std::sync::Arc;
 
+use super::common::{TenantId, PACKET_IPV6_LEN, PACKET_MTU, PACKET_UDP_LEN};
 use super::cycles;
 use super::rpc;
+use super::stats::CoreCounters;
+use super::task::CostClass;
 use super::task::Task;
+use super::task::TaskPriority;
 use super::task::TaskState::*;
+use super::timer::TimerWheel;
 
 use e2d2::common::EmptyMetadata;
 use e2d2::headers::IpHeader;
@@ -27,84 +35,1515 @@ use e2d2::interface::Packet;
 
 use spin::RwLock;
 
-/// A simple round robin scheduler for Tasks in Sandstorm.
+/// The number of nanoseconds of CPU time a tenant of unit weight is entitled to every round.
+const QUANTUM_NS: u64 = 2000;
+
+/// The maximum number of quanta a tenant can be in debt by. Bounds the time a tenant is denied
+/// the CPU after one of it's tasks runs for a long time (ex: before it gets pre-empted).
+const MAX_DEBT_QUANTA: i64 = 64;
+
+/// The default length in milliseconds of the interval over which tenants' CPU budgets are
+/// enforced. Refer to `RoundRobin::set_budget()`.
+const BUDGET_INTERVAL_MS: u64 = 10;
+
+/// The default time in microseconds after being queued by which a task without a deadline should
+/// complete, on schedulers that order tasks by deadline. Refer to `RoundRobin::with_deadlines()`.
+const DEFAULT_SLACK_US: u64 = 1000;
+
+/// The granularity of the timer wheel on which parked tasks wait, in nanoseconds.
+const TIMER_TICK_NS: u64 = 1000;
+
+/// The interval in nanoseconds at which a scheduler without tenant tasks tries to steal one from
+/// a peer. Bounds how often idle schedulers contend on the run-queues of busy ones.
+const STEAL_INTERVAL_NS: u64 = 1000;
+
+/// The maximum number of response delays an `ExpressStats` holds on to for each path between
+/// reports. Bounds it's memory when reports are far apart.
+const MAX_EXPRESS_SAMPLES: usize = 1 << 16;
+
+/// Handles to every scheduler on the server. Refer to `RoundRobin::set_peers()`.
+pub type Peers = Arc<RwLock<Vec<Arc<RoundRobin>>>>;
+
+/// Sends a response, parsed upto it's IP header, out on a transmit queue right away. Returns the
+/// response if it could not be sent out. Refer to `RoundRobin::set_express()`.
+pub type ExpressTx =
+    Box<Fn(Packet<IpHeader, EmptyMetadata>) -> Option<Packet<IpHeader, EmptyMetadata>> + Send>;
+
+/// What a scheduler does with a new request once the number of tenant tasks waiting on it's
+/// run-queues has reached the bound set through `RoundRobin::set_queue_limit()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    /// The request is turned away, and the tenant told to back off and retry.
+    Reject,
+
+    /// A task that hasn't run yet is dropped to make room, picked to be the one that would have
+    /// run last. It's request is never answered. Requests are rejected if no task can be dropped.
+    DropLowest,
+}
+
+/// The run-queues of a scheduler, deciding the order in which the tasks waiting on it run.
+trait Queues {
+    /// Adds a task to the run-queues on behalf of a tenant. Tasks with DISPATCH and ADMIN
+    /// priority are system tasks, and belong to no tenant. So are tasks with BACKGROUND
+    /// priority, which only run while there aren't any tenant tasks, taking turns with system
+    /// tasks.
+    fn push(&mut self, tenant: TenantId, task: Box<Task>);
+
+    /// Picks the next task to run.
+    ///
+    /// # Return
+    ///
+    /// The task along with an index identifying it's tenant (None for system tasks), or None if
+    /// there isn't anything to run.
+    fn pop(&mut self) -> Option<(Option<usize>, Box<Task>)>;
+
+    /// Charges the tenant of a task that was picked off the run-queues for the time it ran for,
+    /// and re-queues the task if it has to run again.
+    ///
+    /// # Arguments
+    ///
+    /// * `idx`:  The index returned along with the task by `pop()`.
+    /// * `exec`: The time in cycles that the task ran for.
+    /// * `task`: The task, if it has to run again.
+    fn charge(&mut self, idx: Option<usize>, exec: u64, task: Option<Box<Task>>);
+
+    /// Charges a tenant for a task that ran without being picked off the run-queues. Refer to
+    /// `RoundRobin::run_inline()`.
+    fn account(&mut self, tenant: TenantId, exec: u64);
+
+    /// Returns the tenant identified by an index returned by `pop()`. Zero for system tasks.
+    fn tenant(&self, idx: Option<usize>) -> TenantId;
+
+    /// Returns the total CPU time in cycles that every tenant's tasks have run for.
+    fn cycles(&self) -> Vec<(TenantId, u64)>;
+
+    /// Removes a tenant task accepted by a filter, if any. System tasks are never removed.
+    fn steal(&mut self, accept: &Fn(&Task) -> bool) -> Option<(TenantId, Box<Task>)>;
+
+    /// Removes the tenant task accepted by a filter that would otherwise run last, if any, so
+    /// that it can be dropped to make room for new tasks. System tasks are never removed.
+    fn evict(&mut self, accept: &Fn(&Task) -> bool) -> Option<(TenantId, Box<Task>)>;
+
+    /// Removes all tasks from the run-queues.
+    fn drain(&mut self) -> VecDeque<(TenantId, Box<Task>)>;
+
+    /// Returns the number of tenant tasks waiting on the run-queues.
+    fn pending(&self) -> usize;
+
+    /// Limits the CPU time tenants can consume every interval. Ignored by run-queues that do not
+    /// support budgets. Refer to `RoundRobin::set_budget()`.
+    fn set_budget(&mut self, _budget: u64, _interval: u64, _now: u64) {}
+
+    /// Limits the CPU time a group of tenants can consume together every interval, on top of the
+    /// budget of each tenant. Ignored by run-queues that do not support budgets. Refer to
+    /// `RoundRobin::set_group_budget()`.
+    fn set_group_budget(&mut self, _tenants: &[TenantId], _budget: u64) {}
+
+    /// Lets the run-queues know the current time. Called before every `pop()`.
+    fn roll(&mut self, _now: u64) {}
+
+    /// Returns the number of times a tenant used up it's budget for an interval.
+    fn throttled(&self) -> u64 {
+        0
+    }
+}
+
+/// A run-queue of tasks belonging to a single tenant.
+struct TenantQueue {
+    // The tenant whose tasks are on this queue.
+    tenant: TenantId,
+
+    // The CPU time in cycles that the tenant is entitled to every round. Proportional to the
+    // tenant's weight.
+    quantum: i64,
+
+    // The CPU time in cycles that the tenant can still consume. Can be negative if a task ran for
+    // longer than what the tenant was entitled to.
+    deficit: i64,
+
+    // Tasks waiting to execute. Tasks on this queue have either yielded, or have been recently
+    // enqueued and never run before.
+    tasks: VecDeque<Box<Task>>,
+
+    // The total CPU time in cycles that the tenant's tasks have run for on this scheduler.
+    total: u64,
+
+    // The CPU time in cycles that the tenant's tasks have run for in the current interval, and
+    // how much they can run for before they are deferred. A budget of zero is unlimited.
+    used: u64,
+    budget: u64,
+
+    // The index of the group the tenant belongs to, if any. Refer to `RunQueues::groups`.
+    group: Option<usize>,
+}
+
+// Implementation of methods on TenantQueue.
+impl TenantQueue {
+    // Returns true if the tenant has used up it's budget for the current interval.
+    fn over_budget(&self) -> bool {
+        self.budget > 0 && self.used >= self.budget
+    }
+}
+
+/// The maximum number of tenant tasks `RunQueues` runs in between two system tasks. Bounds the
+/// time for which network processing is held up when there are many tenants in a round.
+const SYSTEM_INTERVAL: usize = 32;
+
+/// The run-queues of a scheduler. Tasks belonging to a tenant are queued up on that tenant's
+/// queue, and queues are serviced by weighted deficit round robin. System tasks (ex: Dispatch)
+/// are run at the start of every round, and after every `SYSTEM_INTERVAL` tenant tasks.
+struct RunQueues {
+    // Tasks that do not belong to any tenant.
+    system: VecDeque<Box<Task>>,
+
+    // Background tasks, and whether one is up next while there aren't any tenant tasks.
+    background: VecDeque<Box<Task>>,
+    background_turn: bool,
+
+    // Per-tenant queues in the order they are serviced in. Queues are never removed.
+    tenants: Vec<TenantQueue>,
+
+    // The index into `tenants` of every tenant's queue.
+    index: HashMap<TenantId, usize>,
+
+    // The weights of tenants. Tenants that don't have a weight are assigned a weight of one.
+    weights: HashMap<TenantId, u64>,
+
+    // The number of cycles corresponding to a quantum of unit weight.
+    unit: i64,
+
+    // The index into `tenants` of the queue currently being serviced.
+    next: usize,
+
+    // True if the queue currently being serviced was credited it's quantum for this round.
+    credited: bool,
+
+    // True if a system task was run in this round, and the number of tenant tasks run since the
+    // last system task.
+    system_ran: bool,
+    since_system: usize,
+
+    // The total number of tasks across all tenant queues.
+    pending: usize,
+
+    // The CPU time in cycles that a tenant of unit weight can consume every interval before it's
+    // tasks are deferred. Zero disables budgets.
+    budget: u64,
+
+    // The length of an interval in cycles, and the time stamp at which the current one started.
+    interval: u64,
+    epoch: u64,
+
+    // The number of times a tenant used up it's budget for an interval.
+    throttled: u64,
+
+    // The group every grouped tenant belongs to, and the budget in cycles and CPU time consumed
+    // in the current interval of every group, indexed by group. Tenants in a group are deferred
+    // once the group uses up it's budget, even if they have budget of their own left.
+    groups: HashMap<TenantId, usize>,
+    group_budgets: Vec<u64>,
+    group_used: Vec<u64>,
+}
+
+// Implementation of methods on RunQueues.
+impl RunQueues {
+    /// Creates an empty set of run-queues.
+    ///
+    /// # Arguments
+    ///
+    /// * `weights`: The weight of every tenant. Tenants missing from here get a weight of one.
+    /// * `unit`:    The quantum in cycles of a tenant with unit weight.
+    fn new(weights: HashMap<TenantId, u64>, unit: u64) -> RunQueues {
+        RunQueues {
+            system: VecDeque::new(),
+            background: VecDeque::new(),
+            background_turn: false,
+            tenants: Vec::new(),
+            index: HashMap::new(),
+            weights: weights,
+            unit: unit as i64,
+            next: 0,
+            credited: false,
+            system_ran: false,
+            since_system: 0,
+            pending: 0,
+            budget: 0,
+            interval: 0,
+            epoch: 0,
+            throttled: 0,
+            groups: HashMap::new(),
+            group_budgets: Vec::new(),
+            group_used: Vec::new(),
+        }
+    }
+
+    /// Returns true if a tenant, or the group it belongs to, has used up it's budget for the
+    /// current interval.
+    fn over_budget(&self, queue: &TenantQueue) -> bool {
+        queue.over_budget()
+            || queue
+                .group
+                .map_or(false, |group| self.group_used[group] >= self.group_budgets[group])
+    }
+
+    /// Returns true if tenants or groups have budgets.
+    fn budgeted(&self) -> bool {
+        self.budget > 0 || !self.group_budgets.is_empty()
+    }
+
+    /// Returns the index of a tenant's queue, creating the queue if required.
+    fn queue(&mut self, tenant: TenantId) -> usize {
+        if let Some(idx) = self.index.get(&tenant) {
+            return *idx;
+        }
+
+        let weight = (*self.weights.get(&tenant).unwrap_or(&1)).max(1);
+        self.tenants.push(TenantQueue {
+            tenant: tenant,
+            quantum: self.unit * weight as i64,
+            deficit: 0,
+            tasks: VecDeque::new(),
+            total: 0,
+            used: 0,
+            budget: self.budget * weight,
+            group: self.groups.get(&tenant).cloned(),
+        });
+
+        let idx = self.tenants.len() - 1;
+        self.index.insert(tenant, idx);
+        idx
+    }
+}
+
+// Implementation of the Queues trait on RunQueues.
+impl Queues for RunQueues {
+    /// Limits the CPU time tenants can consume every interval. Tenants that use up their budget
+    /// are passed over until the next interval for as long as some other tenant has tasks and
+    /// budget left.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget`:   The budget in cycles of a tenant with unit weight. Tenants get a budget in
+    ///               proportion to their weight. Zero disables budgets.
+    /// * `interval`: The length of an interval in cycles.
+    /// * `now`:      The current time stamp in cycles. Starts the first interval.
+    fn set_budget(&mut self, budget: u64, interval: u64, now: u64) {
+        self.budget = budget;
+        self.interval = interval;
+        self.epoch = now;
+
+        for queue in self.tenants.iter_mut() {
+            let weight = *self.weights.get(&queue.tenant).unwrap_or(&1);
+            queue.budget = budget * weight.max(1);
+            queue.used = 0;
+        }
+    }
+
+    /// Limits the CPU time a group of tenants can consume together every interval. Once the
+    /// group uses up it's budget, the tasks of every tenant in it are deferred just like those of
+    /// a tenant over it's own budget. A tenant belongs to at most one group; adding it to another
+    /// moves it there. Must be called after `set_budget()`, which sets the interval.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenants`: The tenants in the group.
+    /// * `budget`:  The budget in cycles of the group. Zero is ignored.
+    fn set_group_budget(&mut self, tenants: &[TenantId], budget: u64) {
+        if budget == 0 {
+            return;
+        }
+
+        let group = self.group_budgets.len();
+        self.group_budgets.push(budget);
+        self.group_used.push(0);
+        for tenant in tenants.iter() {
+            self.groups.insert(*tenant, group);
+        }
+
+        for queue in self.tenants.iter_mut() {
+            queue.group = self.groups.get(&queue.tenant).cloned();
+        }
+    }
+
+    /// Starts a new interval if the current one has elapsed, restoring every tenant's budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: The current time stamp in cycles.
+    fn roll(&mut self, now: u64) {
+        if !self.budgeted() || now < self.epoch + self.interval {
+            return;
+        }
+
+        self.epoch = now;
+        for queue in self.tenants.iter_mut() {
+            queue.used = 0;
+        }
+        for used in self.group_used.iter_mut() {
+            *used = 0;
+        }
+    }
+
+    /// Adds a task to the end of it's tenant's queue. Tasks with DISPATCH, ADMIN and BACKGROUND
+    /// priority are run as system tasks irrespective of the tenant.
+    fn push(&mut self, tenant: TenantId, task: Box<Task>) {
+        match task.priority() {
+            TaskPriority::DISPATCH | TaskPriority::ADMIN => return self.system.push_back(task),
+            TaskPriority::BACKGROUND => return self.background.push_back(task),
+            TaskPriority::REQUEST => {}
+        }
+
+        let idx = self.queue(tenant);
+        self.tenants[idx].tasks.push_back(task);
+        self.pending += 1;
+    }
+
+    /// Picks the next task to run.
+    ///
+    /// # Return
+    ///
+    /// The task along with the index of the queue it was picked from (None for system tasks),
+    /// or None if there isn't anything to run.
+    fn pop(&mut self) -> Option<(Option<usize>, Box<Task>)> {
+        // Tenants over budget are only deferred if some other tenant can run instead, so that the
+        // core never sits idle while there are tasks waiting.
+        let defer = self.budgeted()
+            && self
+                .tenants
+                .iter()
+                .any(|queue| !queue.tasks.is_empty() && !self.over_budget(queue));
+
+        loop {
+            // If there aren't any tenant tasks, just run system tasks one after the other, taking
+            // turns with background tasks.
+            if self.pending == 0 {
+                self.next = 0;
+                self.credited = false;
+                self.system_ran = false;
+
+                self.background_turn = !self.background_turn;
+                if self.background_turn || self.system.is_empty() {
+                    if let Some(task) = self.background.pop_front() {
+                        return Some((None, task));
+                    }
+                }
+
+                return self.system.pop_front().map(|task| (None, task));
+            }
+
+            // Run a system task at the start of every round, and once enough tenant tasks ran
+            // since the last one.
+            if (self.next == 0 && !self.system_ran) || self.since_system >= SYSTEM_INTERVAL {
+                self.system_ran = true;
+                self.since_system = 0;
+                if let Some(task) = self.system.pop_front() {
+                    return Some((None, task));
+                }
+            }
+
+            let idx = self.next;
+            let deferred = defer && self.over_budget(&self.tenants[idx]);
+            {
+                let queue = &mut self.tenants[idx];
+                if queue.tasks.is_empty() {
+                    // Idle tenants don't accumulate credit, but do retain any debt.
+                    queue.deficit = queue.deficit.min(0);
+                } else if deferred {
+                    // Deferred tenants don't accumulate credit either.
+                } else {
+                    if !self.credited {
+                        queue.deficit += queue.quantum;
+                        self.credited = true;
+                    }
+
+                    if queue.deficit > 0 {
+                        self.pending -= 1;
+                        self.since_system += 1;
+                        return queue.tasks.pop_front().map(|task| (Some(idx), task));
+                    }
+                }
+            }
+
+            // Move on to the next queue, starting a new round if required.
+            self.credited = false;
+            self.next += 1;
+            if self.next == self.tenants.len() {
+                self.next = 0;
+                self.system_ran = false;
+            }
+        }
+    }
+
+    /// Charges a queue for a task that was picked off it, and re-queues the task if required.
+    ///
+    /// # Arguments
+    ///
+    /// * `idx`:  The index of the queue the task was picked from. None for system tasks.
+    /// * `exec`: The time in cycles that the task ran for.
+    /// * `task`: The task, if it has to run again.
+    fn charge(&mut self, idx: Option<usize>, exec: u64, task: Option<Box<Task>>) {
+        match idx {
+            None => {
+                if let Some(task) = task {
+                    if task.priority() == TaskPriority::BACKGROUND {
+                        self.background.push_back(task);
+                    } else {
+                        self.system.push_back(task);
+                    }
+                }
+            }
+
+            Some(idx) => {
+                let over = self.over_budget(&self.tenants[idx]);
+                let group = {
+                    let queue = &mut self.tenants[idx];
+                    queue.deficit =
+                        (queue.deficit - exec as i64).max(-MAX_DEBT_QUANTA * queue.quantum);
+
+                    queue.total += exec;
+                    queue.used += exec;
+                    queue.group
+                };
+
+                if let Some(group) = group {
+                    self.group_used[group] += exec;
+                }
+
+                if !over && self.over_budget(&self.tenants[idx]) {
+                    self.throttled += 1;
+                }
+
+                if let Some(task) = task {
+                    self.tenants[idx].tasks.push_back(task);
+                    self.pending += 1;
+                }
+            }
+        }
+    }
+
+    /// Charges a tenant's queue for a task that ran without being picked off it.
+    fn account(&mut self, tenant: TenantId, exec: u64) {
+        let idx = self.queue(tenant);
+        self.charge(Some(idx), exec, None);
+    }
+
+    /// Returns the tenant whose queue a task was picked off. Zero for system tasks.
+    fn tenant(&self, idx: Option<usize>) -> TenantId {
+        idx.map_or(0, |idx| self.tenants[idx].tenant)
+    }
+
+    /// Returns the total CPU time in cycles that every tenant's tasks have run for.
+    fn cycles(&self) -> Vec<(TenantId, u64)> {
+        self.tenants
+            .iter()
+            .map(|queue| (queue.tenant, queue.total))
+            .collect()
+    }
+
+    /// Removes the most recently queued tenant task accepted by a filter. Tenants are searched in
+    /// the order they are serviced in. System tasks are never removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `accept`: Returns true if a task can be removed.
+    ///
+    /// # Return
+    ///
+    /// The removed task along with the tenant it belongs to, or None if no task was accepted.
+    fn steal(&mut self, accept: &Fn(&Task) -> bool) -> Option<(TenantId, Box<Task>)> {
+        let mut stolen = None;
+        for queue in self.tenants.iter_mut() {
+            let pos = queue.tasks.iter().rposition(|task| accept(&**task));
+            if let Some(pos) = pos {
+                stolen = queue.tasks.remove(pos).map(|task| (queue.tenant, task));
+                break;
+            }
+        }
+
+        if stolen.is_some() {
+            self.pending -= 1;
+        }
+        stolen
+    }
+
+    /// Removes the last task accepted by a filter off the queue of the tenant with the most
+    /// tasks waiting relative to it's weight, since that tenant would otherwise wait the longest
+    /// to get to it.
+    fn evict(&mut self, accept: &Fn(&Task) -> bool) -> Option<(TenantId, Box<Task>)> {
+        let mut victim: Option<(usize, usize)> = None;
+        for (i, queue) in self.tenants.iter().enumerate() {
+            let pos = match queue.tasks.iter().rposition(|task| accept(&**task)) {
+                Some(pos) => pos,
+                None => continue,
+            };
+
+            // Tasks per quantum are compared by cross-multiplying, avoiding a division.
+            let longer = victim.map_or(true, |(v, _)| {
+                let other = &self.tenants[v];
+                queue.tasks.len() as i64 * other.quantum > other.tasks.len() as i64 * queue.quantum
+            });
+            if longer {
+                victim = Some((i, pos));
+            }
+        }
+
+        let (i, pos) = match victim {
+            Some(victim) => victim,
+            None => return None,
+        };
+
+        let queue = &mut self.tenants[i];
+        let evicted = queue.tasks.remove(pos).map(|task| (queue.tenant, task));
+        if evicted.is_some() {
+            self.pending -= 1;
+        }
+        evicted
+    }
+
+    /// Removes all tasks from the run-queues.
+    fn drain(&mut self) -> VecDeque<(TenantId, Box<Task>)> {
+        let mut tasks: VecDeque<(TenantId, Box<Task>)> = VecDeque::new();
+
+        for task in self.system.drain(..).chain(self.background.drain(..)) {
+            tasks.push_back((0, task));
+        }
+
+        for queue in self.tenants.iter_mut() {
+            let tenant = queue.tenant;
+            for task in queue.tasks.drain(..) {
+                tasks.push_back((tenant, task));
+            }
+        }
+
+        self.pending = 0;
+        tasks
+    }
+
+    /// Returns the total number of tasks across all tenant queues.
+    fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Returns the number of times a tenant used up it's budget for an interval.
+    fn throttled(&self) -> u64 {
+        self.throttled
+    }
+}
+
+/// A tenant task waiting on run-queues ordered by deadline.
+struct Due {
+    // The time stamp in cycles by which the task should complete.
+    deadline: u64,
+
+    // The order in which the task was queued. Breaks ties between tasks with the same deadline.
+    seq: u64,
+
+    // The index of the task's tenant. Refer to `EarliestDeadlineFirst::tenants`.
+    idx: usize,
+
+    // The task itself.
+    task: Box<Task>,
+}
+
+// Tasks are ordered by deadline and then by the order they were queued in. The order is reversed
+// so that the max-heap in EarliestDeadlineFirst pops the most urgent task first.
+impl Ord for Due {
+    fn cmp(&self, other: &Due) -> cmp::Ordering {
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+
+impl PartialOrd for Due {
+    fn partial_cmp(&self, other: &Due) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Due {
+    fn eq(&self, other: &Due) -> bool {
+        (self.deadline, self.seq) == (other.deadline, other.seq)
+    }
+}
+
+impl Eq for Due {}
+
+/// The run-queues of a scheduler that runs tenant tasks in the order of their deadlines,
+/// irrespective of the tenant they belong to. A task's deadline is the one attached to it by
+/// the dispatcher (refer to `Task::deadline()`). Tasks without a deadline are given one `slack`
+/// cycles after they were queued, so that they are not starved by a steady stream of tasks with
+/// deadlines. A task that yields keeps it's deadline. System tasks (ex: Dispatch) are run after
+/// every tenant task, so that newly arrived requests with tight deadlines are seen early.
+struct EarliestDeadlineFirst {
+    // Tasks that do not belong to any tenant.
+    system: VecDeque<Box<Task>>,
+
+    // Background tasks, and whether one is up next while there aren't any tenant tasks.
+    background: VecDeque<Box<Task>>,
+    background_turn: bool,
+
+    // Tenant tasks, the most urgent first.
+    heap: BinaryHeap<Due>,
+
+    // Every tenant that queued a task on here, along with the total time in cycles it's tasks
+    // ran for, and the index into `tenants` of every tenant.
+    tenants: Vec<(TenantId, u64)>,
+    index: HashMap<TenantId, usize>,
+
+    // The deadline in cycles relative to the time they are queued of tasks without one.
+    slack: u64,
+
+    // The current time stamp in cycles, as of the last call to `roll()`.
+    now: u64,
+
+    // The number of tasks queued so far. Refer to `Due::seq`.
+    seq: u64,
+
+    // True if a system task was run since the last tenant task.
+    system_ran: bool,
+
+    // The deadline of the tenant task that was picked last.
+    current: u64,
+}
+
+// Implementation of methods on EarliestDeadlineFirst.
+impl EarliestDeadlineFirst {
+    /// Creates an empty set of run-queues.
+    ///
+    /// # Arguments
+    ///
+    /// * `slack`: The deadline in cycles after they are queued of tasks without a deadline.
+    fn new(slack: u64) -> EarliestDeadlineFirst {
+        EarliestDeadlineFirst {
+            system: VecDeque::new(),
+            background: VecDeque::new(),
+            background_turn: false,
+            heap: BinaryHeap::new(),
+            tenants: Vec::new(),
+            index: HashMap::new(),
+            slack: slack,
+            now: 0,
+            seq: 0,
+            system_ran: false,
+            current: 0,
+        }
+    }
+
+    /// Returns the index of a tenant, adding the tenant if required.
+    fn queue(&mut self, tenant: TenantId) -> usize {
+        if let Some(idx) = self.index.get(&tenant) {
+            return *idx;
+        }
+
+        self.tenants.push((tenant, 0));
+        let idx = self.tenants.len() - 1;
+        self.index.insert(tenant, idx);
+        idx
+    }
+
+    /// Queues a tenant task that should complete by a deadline.
+    fn insert(&mut self, deadline: u64, idx: usize, task: Box<Task>) {
+        self.seq += 1;
+        self.heap.push(Due {
+            deadline: deadline,
+            seq: self.seq,
+            idx: idx,
+            task: task,
+        });
+    }
+}
+
+// Implementation of the Queues trait on EarliestDeadlineFirst.
+impl Queues for EarliestDeadlineFirst {
+    /// Adds a task to the run-queues in the order of it's deadline. Tasks with DISPATCH, ADMIN
+    /// and BACKGROUND priority are run as system tasks irrespective of the tenant.
+    fn push(&mut self, tenant: TenantId, task: Box<Task>) {
+        match task.priority() {
+            TaskPriority::DISPATCH | TaskPriority::ADMIN => return self.system.push_back(task),
+            TaskPriority::BACKGROUND => return self.background.push_back(task),
+            TaskPriority::REQUEST => {}
+        }
+
+        let idx = self.queue(tenant);
+        let deadline = task.deadline().unwrap_or(self.now + self.slack);
+        self.insert(deadline, idx, task);
+    }
+
+    /// Picks the most urgent tenant task, alternating with system tasks. Background tasks take
+    /// turns with system tasks while there aren't any tenant tasks.
+    fn pop(&mut self) -> Option<(Option<usize>, Box<Task>)> {
+        if self.heap.is_empty() {
+            self.background_turn = !self.background_turn;
+            if self.background_turn || self.system.is_empty() {
+                if let Some(task) = self.background.pop_front() {
+                    return Some((None, task));
+                }
+            }
+        }
+
+        if !self.system_ran || self.heap.is_empty() {
+            if let Some(task) = self.system.pop_front() {
+                self.system_ran = true;
+                return Some((None, task));
+            }
+        }
+
+        match self.heap.pop() {
+            Some(due) => {
+                self.system_ran = false;
+                self.current = due.deadline;
+                Some((Some(due.idx), due.task))
+            }
+
+            None => None,
+        }
+    }
+
+    /// Charges a tenant for a task that was picked off the run-queues. A task that has to run
+    /// again is re-queued at it's original deadline.
+    fn charge(&mut self, idx: Option<usize>, exec: u64, task: Option<Box<Task>>) {
+        match idx {
+            None => {
+                if let Some(task) = task {
+                    if task.priority() == TaskPriority::BACKGROUND {
+                        self.background.push_back(task);
+                    } else {
+                        self.system.push_back(task);
+                    }
+                }
+            }
+
+            Some(idx) => {
+                self.tenants[idx].1 += exec;
+                if let Some(task) = task {
+                    let deadline = task.deadline().unwrap_or(self.current);
+                    self.insert(deadline, idx, task);
+                }
+            }
+        }
+    }
+
+    /// Charges a tenant for a task that ran without being picked off the run-queues.
+    fn account(&mut self, tenant: TenantId, exec: u64) {
+        let idx = self.queue(tenant);
+        self.tenants[idx].1 += exec;
+    }
+
+    /// Returns the tenant a task was picked off the run-queues for. Zero for system tasks.
+    fn tenant(&self, idx: Option<usize>) -> TenantId {
+        idx.map_or(0, |idx| self.tenants[idx].0)
+    }
+
+    /// Returns the total CPU time in cycles that every tenant's tasks have run for.
+    fn cycles(&self) -> Vec<(TenantId, u64)> {
+        self.tenants.clone()
+    }
+
+    /// Removes the least urgent tenant task accepted by a filter, since it can best afford the
+    /// cold caches of the core it is moved to.
+    fn steal(&mut self, accept: &Fn(&Task) -> bool) -> Option<(TenantId, Box<Task>)> {
+        if !self.heap.iter().any(|due| accept(&*due.task)) {
+            return None;
+        }
+
+        let mut tasks = mem::replace(&mut self.heap, BinaryHeap::new()).into_vec();
+        let mut pos = None;
+        for (i, due) in tasks.iter().enumerate() {
+            if accept(&*due.task) && pos.map_or(true, |p: usize| due < &tasks[p]) {
+                pos = Some(i);
+            }
+        }
+
+        let stolen = pos.map(|pos| tasks.swap_remove(pos));
+        self.heap = BinaryHeap::from(tasks);
+        stolen.map(|due| (self.tenants[due.idx].0, due.task))
+    }
+
+    /// Removes the least urgent tenant task accepted by a filter. Refer to `steal()`.
+    fn evict(&mut self, accept: &Fn(&Task) -> bool) -> Option<(TenantId, Box<Task>)> {
+        self.steal(accept)
+    }
+
+    /// Removes all tasks from the run-queues.
+    fn drain(&mut self) -> VecDeque<(TenantId, Box<Task>)> {
+        let mut tasks: VecDeque<(TenantId, Box<Task>)> = VecDeque::new();
+
+        for task in self.system.drain(..).chain(self.background.drain(..)) {
+            tasks.push_back((0, task));
+        }
+
+        for due in self.heap.drain() {
+            tasks.push_back((self.tenants[due.idx].0, due.task));
+        }
+
+        tasks
+    }
+
+    /// Returns the number of tenant tasks waiting on the run-queues.
+    fn pending(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Records the current time, which tasks without a deadline are given one relative to.
+    fn roll(&mut self, now: u64) {
+        self.now = now;
+    }
+}
+
+/// This type measures how long latency-critical responses take to leave a scheduler once their
+/// task completes, both when sent out right away on the express queue and when queued up for the
+/// dispatcher's next poll, so that the two can be compared on a live workload. When configured
+/// with a period, responses alternate between the two paths every `period` responses.
+struct ExpressStats {
+    // The number of responses sent out one way before switching to the other. Zero if every
+    // response is sent out on the express queue.
+    period: u64,
+
+    // The number of latency-critical responses seen so far.
+    responses: u64,
+
+    // The time stamps in cycles at which the latency-critical responses currently waiting for the
+    // dispatcher were queued up.
+    pending: Vec<u64>,
+
+    // The delays in cycles of express and queued responses respectively since the last report.
+    delays: [Vec<u64>; 2],
+}
+
+// Implementation of methods on ExpressStats.
+impl ExpressStats {
+    /// Returns an ExpressStats that alternates paths every `period` responses, or always picks the
+    /// express queue if `period` is zero.
+    fn new(period: u64) -> ExpressStats {
+        ExpressStats {
+            period: period,
+            responses: 0,
+            pending: Vec::new(),
+            delays: [Vec::new(), Vec::new()],
+        }
+    }
+
+    /// Returns true if the next latency-critical response should be sent out on the express queue.
+    #[inline]
+    fn next(&mut self) -> bool {
+        let express = self.period == 0 || (self.responses / self.period) % 2 == 0;
+        self.responses += 1;
+        express
+    }
+
+    /// Records the delay of a response.
+    ///
+    /// # Arguments
+    ///
+    /// * `express`: True if the response was sent out on the express queue.
+    /// * `delay`:   The time in cycles between the task completing and the response being sent.
+    #[inline]
+    fn record(&mut self, express: bool, delay: u64) {
+        let arm = !express as usize;
+        if self.delays[arm].len() < MAX_EXPRESS_SAMPLES {
+            self.delays[arm].push(delay);
+        }
+    }
+
+    /// Records that a latency-critical response was queued up for the dispatcher at `now`.
+    #[inline]
+    fn queued(&mut self, now: u64) {
+        self.pending.push(now);
+    }
+
+    /// Records that the dispatcher picked up every queued up response at `now`.
+    fn drained(&mut self, now: u64) {
+        let pending = mem::replace(&mut self.pending, Vec::new());
+        for queued in pending.into_iter() {
+            self.record(false, now - queued);
+        }
+    }
+
+    /// Returns the 99th percentile delay in cycles of express and queued responses respectively
+    /// since the last call, and resets them. Zero for a path that saw no responses.
+    fn take(&mut self) -> (u64, u64) {
+        let mut tails = [0; 2];
+        for arm in 0..2 {
+            let delays = &mut self.delays[arm];
+            if !delays.is_empty() {
+                delays.sort();
+                tails[arm] = delays[(delays.len() * 99) / 100];
+            }
+            delays.clear();
+        }
+
+        (tails[0], tails[1])
+    }
+}
+
+/// A transmit queue that a scheduler sends latency-critical responses out on as soon as their
+/// tasks complete. Refer to `RoundRobin::set_express()`.
+struct Express {
+    // Sends responses out on the queue.
+    tx: ExpressTx,
+
+    // Responses to requests of atleast this priority are latency-critical.
+    priority: u8,
+
+    // Compares the delays of responses sent out on the queue with those of queued up responses.
+    stats: ExpressStats,
+}
+
+/// A two-level scheduler for Tasks in Sandstorm. Tasks are queued up per-tenant, and tenants
+/// share the CPU in proportion to their configured weights through deficit round robin. Tasks
+/// belonging to the same tenant are run in round robin order. Alternatively, tasks can be run in
+/// the order of their deadlines irrespective of tenant (refer to `with_deadlines()`).
 pub struct RoundRobin {
     // The time-stamp at which the scheduler last ran. Required to identify whether there is an
     // uncooperative task running on the scheduler.
     latest: AtomicUsize,
 
-    // Atomic flag indicating whether there is a malicious/long running procedure on this
-    // scheduler. If true, the scheduler must return down to Netbricks on the next call to poll().
-    compromised: AtomicBool,
+    // Atomic flag indicating whether there is a malicious/long running procedure on this
+    // scheduler. If true, the scheduler must return down to Netbricks on the next call to poll().
+    compromised: AtomicBool,
+
+    // True if the task that was run last was declared long running by it's extension. Lets the
+    // task be given more time before the scheduler is considered compromised.
+    long: AtomicBool,
+
+    // Identifier of the thread this scheduler is running on. Required for pre-emption.
+    thread: AtomicUsize,
+
+    // Identifier of the core this scheduler is running on. Required for pre-emption.
+    core: AtomicIsize,
+
+    // Run-queues of tasks waiting to execute.
+    waiting: RwLock<Box<Queues>>,
+
+    // Tasks parked until a point in time, along with the tenants they belong to. Checked once
+    // every iteration of the scheduling loop, and moved onto the run-queues once due.
+    timers: RwLock<TimerWheel<(TenantId, Box<Task>)>>,
+
+    // The number of non-dispatch tasks that ran to completion on this scheduler.
+    completed: AtomicUsize,
+
+    // The total time in cycles that non-dispatch tasks ran for on this scheduler.
+    busy: AtomicUsize,
+
+    // The total time in cycles that the dispatch task on this scheduler spent on polls that
+    // received requests or sent out responses, and on polls that found nothing to do.
+    dispatch_busy: AtomicUsize,
+    dispatch_idle: AtomicUsize,
+
+    // Response packets returned by completed tasks. Will be picked up and sent out the network by
+    // the Dispatch task.
+    responses: RwLock<Vec<Packet<IpHeader, EmptyMetadata>>>,
+
+    // The queue latency-critical responses are sent out on right away, bypassing `responses`, and
+    // a flag set once there is one. None unless `set_express()` is called.
+    express: RwLock<Option<Express>>,
+    expressing: AtomicBool,
+
+    // Every scheduler on the server, this one included. Tasks are stolen off them when this
+    // scheduler runs out of tenant tasks. None until `set_peers()` is called.
+    peers: RwLock<Option<Peers>>,
+
+    // The number of tasks a peer must have waiting before a task with an affinity for the peer
+    // is stolen off it. Zero disables stealing.
+    imbalance: AtomicUsize,
+
+    // The time stamp in cycles at which this scheduler last tried to steal a task, and the
+    // minimum number of cycles between attempts.
+    last_steal: AtomicUsize,
+    steal_interval: u64,
+
+    // The number of tasks this scheduler stole off it's peers.
+    stolen: AtomicUsize,
+
+    // The number of tasks that ran to completion inline on the dispatch task, and the number that
+    // had to be handed off to the run-queues after all. Refer to `run_inline()`.
+    inlined: AtomicUsize,
+    handed_off: AtomicUsize,
+
+    // The number of tenant tasks that can wait on the run-queues before new requests overflow,
+    // and what is done with them when they do. Zero leaves the run-queues unbounded. Refer to
+    // `set_queue_limit()`.
+    queue_limit: AtomicUsize,
+    overflow: RwLock<Overflow>,
+
+    // The number of requests turned away, and the number of waiting tasks dropped, because the
+    // run-queues were full.
+    rejected: AtomicUsize,
+    evicted: AtomicUsize,
+
+    // The packets and requests handled by the dispatcher on this scheduler's core. Bumped by the
+    // dispatcher, and summed across schedulers by Master's get_statistics() RPC.
+    counters: CoreCounters,
+}
+
+// Implementation of methods on RoundRobin.
+impl RoundRobin {
+    /// Creates and returns a round-robin scheduler that can run tasks implementing the `Task`
+    /// trait.
+    ///
+    /// # Arguments
+    ///
+    /// * `thread`:  Identifier of the thread this scheduler will run on.
+    /// * `core`:    Identifier of the core this scheduler will run on.
+    /// * `weights`: The share of the CPU each tenant is entitled to. Tenants that are not on here
+    ///              are given a weight of one.
+    pub fn new(thread: u64, core: i32, weights: HashMap<TenantId, u64>) -> RoundRobin {
+        let unit = (cycles::cycles_per_second() * QUANTUM_NS) / 1000000000;
+        RoundRobin::with_queues(thread, core, Box::new(RunQueues::new(weights, unit)))
+    }
+
+    /// Creates and returns a scheduler that runs tenant tasks in the order of their deadlines
+    /// (earliest deadline first) instead of sharing the CPU between tenants by weight. Refer to
+    /// `Task::deadline()`. Budgets set through `set_budget()` do not apply to this scheduler.
+    ///
+    /// # Arguments
+    ///
+    /// * `thread`:   Identifier of the thread this scheduler will run on.
+    /// * `core`:     Identifier of the core this scheduler will run on.
+    /// * `slack_us`: The deadline in microseconds after they are queued of tasks that do not have
+    ///               one. Zero defaults to a millisecond.
+    pub fn with_deadlines(thread: u64, core: i32, slack_us: u64) -> RoundRobin {
+        let slack_us = if slack_us == 0 {
+            DEFAULT_SLACK_US
+        } else {
+            slack_us
+        };
+
+        let slack = (cycles::cycles_per_second() * slack_us) / 1000000;
+        RoundRobin::with_queues(thread, core, Box::new(EarliestDeadlineFirst::new(slack)))
+    }
+
+    // Creates a scheduler whose tasks wait on a given set of run-queues.
+    fn with_queues(thread: u64, core: i32, queues: Box<Queues>) -> RoundRobin {
+        RoundRobin {
+            latest: AtomicUsize::new(cycles::rdtsc() as usize),
+            compromised: AtomicBool::new(false),
+            long: AtomicBool::new(false),
+            thread: AtomicUsize::new(thread as usize),
+            core: AtomicIsize::new(core as isize),
+            waiting: RwLock::new(queues),
+            timers: RwLock::new(TimerWheel::new(
+                (cycles::cycles_per_second() * TIMER_TICK_NS) / 1000000000,
+                cycles::rdtsc(),
+            )),
+            completed: AtomicUsize::new(0),
+            busy: AtomicUsize::new(0),
+            dispatch_busy: AtomicUsize::new(0),
+            dispatch_idle: AtomicUsize::new(0),
+            responses: RwLock::new(Vec::new()),
+            express: RwLock::new(None),
+            expressing: AtomicBool::new(false),
+            peers: RwLock::new(None),
+            imbalance: AtomicUsize::new(0),
+            last_steal: AtomicUsize::new(0),
+            steal_interval: (cycles::cycles_per_second() * STEAL_INTERVAL_NS) / 1000000000,
+            stolen: AtomicUsize::new(0),
+            inlined: AtomicUsize::new(0),
+            handed_off: AtomicUsize::new(0),
+            queue_limit: AtomicUsize::new(0),
+            overflow: RwLock::new(Overflow::Reject),
+            rejected: AtomicUsize::new(0),
+            evicted: AtomicUsize::new(0),
+            counters: CoreCounters::new(),
+        }
+    }
+
+    /// Makes the scheduler send responses to latency-critical requests out on a transmit queue of
+    /// their own as soon as their tasks complete, instead of queueing them up for the dispatcher's
+    /// next poll. Only responses that fit in a single packet are sent out this way, and they are
+    /// not accounted for by the dispatcher. The queue is only ever used from the thread the
+    /// scheduler runs on.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx`:       Sends responses out on the transmit queue.
+    /// * `priority`: Requests with atleast this priority are latency-critical. Must be non-zero.
+    /// * `period`:   If non-zero, latency-critical responses alternate between the queue and the
+    ///               dispatcher every `period` responses, so that their delays can be compared.
+    ///               Refer to `take_express_stats()`.
+    pub fn set_express(&self, tx: ExpressTx, priority: u8, period: u64) {
+        *self.express.write() = Some(Express {
+            tx: tx,
+            priority: cmp::max(priority, 1),
+            stats: ExpressStats::new(period),
+        });
+        self.expressing.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the 99th percentile delay in cycles between a latency-critical request's task
+    /// completing and it's response being sent out, for responses sent out on the express queue
+    /// and for those queued up for the dispatcher respectively, since the last call. None unless
+    /// `set_express()` was called.
+    pub fn take_express_stats(&self) -> Option<(u64, u64)> {
+        if !self.expressing.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        self.express.write().as_mut().map(|express| express.stats.take())
+    }
+
+    /// Lets the scheduler steal tasks off it's peers whenever it runs out of tenant tasks. Only
+    /// tasks that have not run yet are stolen. Tasks with an affinity for this scheduler's core
+    /// are stolen first, followed by those without an affinity for the peer's core. Tasks with
+    /// an affinity for the peer's core stay put unless the peer has more than `imbalance` tasks
+    /// waiting, so that partitioned data tends to stay in one core's caches without cores being
+    /// hard partitioned. A task's affinity hint is mapped onto the scheduler at that index (modulo
+    /// the number of schedulers) in `peers`.
+    ///
+    /// # Arguments
+    ///
+    /// * `peers`:     Every scheduler on the server, including this one. Must be in the same
+    ///                order on every scheduler.
+    /// * `imbalance`: The number of tasks a peer must have waiting before tasks with an affinity
+    ///                for it are stolen. Zero disables stealing.
+    pub fn set_peers(&self, peers: Peers, imbalance: usize) {
+        *self.peers.write() = Some(peers);
+        self.imbalance.store(imbalance, Ordering::Relaxed);
+    }
 
-    // Identifier of the thread this scheduler is running on. Required for pre-emption.
-    thread: AtomicUsize,
+    /// Returns the peer other than this scheduler with the fewest waiting tasks, so that work can
+    /// be handed off to it. None if there isn't any peer, or if the list of peers is being
+    /// modified.
+    pub fn idlest_peer(&self) -> Option<Arc<RoundRobin>> {
+        let guard = match self.peers.try_read() {
+            Some(guard) => guard,
+            None => return None,
+        };
+        let peers = match *guard {
+            Some(ref peers) => peers,
+            None => return None,
+        };
+        let peers = match peers.try_read() {
+            Some(peers) => peers,
+            None => return None,
+        };
 
-    // Identifier of the core this scheduler is running on. Required for pre-emption.
-    core: AtomicIsize,
+        let me = self as *const RoundRobin;
+        let idlest = peers
+            .iter()
+            .filter(|peer| &***peer as *const RoundRobin != me)
+            .min_by_key(|peer| peer.pending())
+            .map(|peer| Arc::clone(peer));
+        idlest
+    }
 
-    // Run-queue of tasks waiting to execute. Tasks on this queue have either yielded, or have been
-    // recently enqueued and never run before.
-    waiting: RwLock<VecDeque<Box<Task>>>,
+    /// Returns the number of tasks this scheduler stole off it's peers.
+    pub fn stolen(&self) -> u64 {
+        self.stolen.load(Ordering::Relaxed) as u64
+    }
 
-    // Response packets returned by completed tasks. Will be picked up and sent out the network by
-    // the Dispatch task.
-    responses: RwLock<Vec<Packet<IpHeader, EmptyMetadata>>>,
-}
+    /// Returns the number of tasks that completed inline on the dispatch task, and the number that
+    /// were handed off to the run-queues after exceeding their cycle cap. Refer to `run_inline()`.
+    pub fn inlined(&self) -> (u64, u64) {
+        (
+            self.inlined.load(Ordering::Relaxed) as u64,
+            self.handed_off.load(Ordering::Relaxed) as u64,
+        )
+    }
 
-// Implementation of methods on RoundRobin.
-impl RoundRobin {
-    /// Creates and returns a round-robin scheduler that can run tasks implementing the `Task`
-    /// trait.
+    /// Limits the CPU time each tenant's tasks can run for on this scheduler every interval. Once
+    /// a tenant uses up it's budget, it's tasks are deferred until the next interval for as long
+    /// as some other tenant has tasks and budget left. If every tenant with tasks waiting is over
+    /// budget, they keep sharing the CPU by weight.
     ///
     /// # Arguments
     ///
-    /// * `thread`: Identifier of the thread this scheduler will run on.
-    /// * `core`:   Identifier of the core this scheduler will run on.
-    pub fn new(thread: u64, core: i32) -> RoundRobin {
-        RoundRobin {
-            latest: AtomicUsize::new(cycles::rdtsc() as usize),
-            compromised: AtomicBool::new(false),
-            thread: AtomicUsize::new(thread as usize),
-            core: AtomicIsize::new(core as isize),
-            waiting: RwLock::new(VecDeque::new()),
-            responses: RwLock::new(Vec::new()),
+    /// * `budget_us`:   The budget in microseconds of a tenant with unit weight. Tenants get a
+    ///                  budget in proportion to their weight. Zero disables budgets.
+    /// * `interval_ms`: The length of an interval in milliseconds. Zero defaults to ten.
+    pub fn set_budget(&self, budget_us: u64, interval_ms: u64) {
+        let interval_ms = if interval_ms == 0 {
+            BUDGET_INTERVAL_MS
+        } else {
+            interval_ms
+        };
+
+        let budget = (cycles::cycles_per_second() * budget_us) / 1000000;
+        let interval = (cycles::cycles_per_second() * interval_ms) / 1000;
+        self.waiting
+            .write()
+            .set_budget(budget, interval, cycles::rdtsc());
+    }
+
+    /// Limits the CPU time a group of tenants can run for together on this scheduler every
+    /// interval, on top of the budget of each tenant set through `set_budget()`, which must be
+    /// called first. Once the group uses up it's budget, the tasks of every tenant in it are
+    /// deferred until the next interval for as long as some tenant outside it has tasks and budget
+    /// left.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenants`:   The tenants in the group. A tenant belongs to at most one group.
+    /// * `budget_us`: The budget of the group in microseconds. Zero leaves the group unlimited.
+    pub fn set_group_budget(&self, tenants: &[TenantId], budget_us: u64) {
+        let budget = (cycles::cycles_per_second() * budget_us) / 1000000;
+        self.waiting.write().set_group_budget(tenants, budget);
+    }
+
+    /// Returns the total CPU time in cycles that every tenant's tasks have run for on this
+    /// scheduler. Tasks are charged to the tenant whose queue they were picked off.
+    pub fn tenant_cycles(&self) -> Vec<(TenantId, u64)> {
+        self.waiting.read().cycles()
+    }
+
+    /// Returns the number of times a tenant used up it's budget for an interval on this
+    /// scheduler. Refer to `set_budget()`.
+    pub fn throttled(&self) -> u64 {
+        self.waiting.read().throttled()
+    }
+
+    /// Bounds the number of tenant tasks that can wait on this scheduler's run-queues, so that
+    /// an overloaded server turns work away instead of queueing it up until it runs out of
+    /// memory. Requests are checked against the bound before being dispatched (refer to
+    /// `admit()`). Tasks that are already waiting, re-queued, or stolen are never turned away.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit`:    The number of tenant tasks that can wait. Zero leaves the run-queues
+    ///               unbounded.
+    /// * `overflow`: What is done with a new request once the bound is reached.
+    pub fn set_queue_limit(&self, limit: usize, overflow: Overflow) {
+        *self.overflow.write() = overflow;
+        self.queue_limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Decides whether a new request can be dispatched onto this scheduler, given the bound set
+    /// through `set_queue_limit()`. If the run-queues are full, either a waiting task is dropped
+    /// to make room, or the request should be turned away with `StatusOverloaded`.
+    ///
+    /// # Return
+    ///
+    /// True if the request can be dispatched. False if it should be turned away.
+    pub fn admit(&self) -> bool {
+        let limit = self.queue_limit.load(Ordering::Relaxed);
+        if limit == 0 || self.waiting.read().pending() < limit {
+            return true;
+        }
+
+        let evicted = if *self.overflow.read() == Overflow::DropLowest {
+            self.waiting
+                .write()
+                .evict(&|task: &Task| task.time() == 0)
+        } else {
+            None
+        };
+
+        match evicted {
+            Some((tenant, mut task)) => {
+                if self.evicted.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("Run-queues on core {} full, dropping waiting tasks", self.core());
+                }
+                if let Some(id) = task.id() {
+                    trace!("{} of tenant {} dropped off full run-queues", id, tenant);
+                }
+
+                // The task never ran, so any packets it holds can be freed right away.
+                if let Some((req, res)) = unsafe { task.tear() } {
+                    req.free_packet();
+                    res.free_packet();
+                }
+                true
+            }
+
+            None => {
+                if self.rejected.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("Run-queues on core {} full, rejecting requests", self.core());
+                }
+                false
+            }
+        }
+    }
+
+    /// Returns the number of requests turned away, and the number of waiting tasks dropped, because
+    /// this scheduler's run-queues were full. Refer to `set_queue_limit()`.
+    pub fn overflows(&self) -> (u64, u64) {
+        (
+            self.rejected.load(Ordering::Relaxed) as u64,
+            self.evicted.load(Ordering::Relaxed) as u64,
+        )
+    }
+
+    // Tries to move a task that hasn't run yet off the peer with the most waiting tasks onto this
+    // scheduler. Gives up instead of waiting if the list of peers is being modified. Returns true
+    // if a task was stolen.
+    fn steal(&self) -> bool {
+        let imbalance = self.imbalance.load(Ordering::Relaxed);
+        if imbalance == 0 {
+            return false;
+        }
+
+        let guard = match self.peers.try_read() {
+            Some(guard) => guard,
+            None => return false,
+        };
+        let peers = match *guard {
+            Some(ref peers) => peers,
+            None => return false,
+        };
+        let peers = match peers.try_read() {
+            Some(peers) => peers,
+            None => return false,
+        };
+
+        // Find this scheduler, and the peer with the most waiting tasks. A peer with a single
+        // waiting task is about to run it, so it is left alone.
+        let me = self as *const RoundRobin;
+        let mut this = None;
+        let mut victim: Option<(usize, usize)> = None;
+        for (idx, peer) in peers.iter().enumerate() {
+            if &**peer as *const RoundRobin == me {
+                this = Some(idx);
+                continue;
+            }
+
+            let pending = peer.pending();
+            if pending > 1 && victim.map_or(true, |(_, most)| pending > most) {
+                victim = Some((idx, pending));
+            }
+        }
+
+        let (this, (idx, pending)) = match (this, victim) {
+            (Some(this), Some(victim)) => (this, victim),
+            _ => return false,
+        };
+
+        let n = peers.len();
+        let imbalanced = pending > imbalance;
+        let home = |task: &Task| task.affinity().map(|hint| hint % n);
+
+        let stolen = {
+            let mut waiting = peers[idx].waiting.write();
+            let mut stolen =
+                waiting.steal(&|task: &Task| task.time() == 0 && home(task) == Some(this));
+            if stolen.is_none() {
+                stolen = waiting.steal(&|task: &Task| {
+                    task.time() == 0 && (imbalanced || home(task) != Some(idx))
+                });
+            }
+            stolen
+        };
+
+        match stolen {
+            Some((tenant, task)) => {
+                self.stolen.fetch_add(1, Ordering::Relaxed);
+                self.waiting.write().push(tenant, task);
+                true
+            }
+
+            None => false,
         }
     }
 
-    /// Enqueues a task onto the scheduler. The task is enqueued at the end of the schedulers
+    /// Enqueues a task onto the scheduler. The task is enqueued at the end of it's tenant's
     /// queue.
     ///
     /// # Arguments
     ///
-    /// * `task`: The task to be added to the scheduler. Must implement the `Task` trait.
+    /// * `tenant`: The tenant the task is running on behalf of. Ignored for tasks with DISPATCH
+    ///             priority, which are run as system tasks.
+    /// * `task`:   The task to be added to the scheduler. Must implement the `Task` trait.
     #[inline]
-    pub fn enqueue(&self, task: Box<Task>) {
-        self.waiting.write().push_back(task);
+    pub fn enqueue(&self, tenant: TenantId, task: Box<Task>) {
+        self.waiting.write().push(tenant, task);
+    }
+
+    /// Parks a task on the scheduler until a point in time, after which it is enqueued at the
+    /// end of it's tenant's queue. Avoids having to repeatedly run a task that cannot make
+    /// progress until then (ex: sleeps, retries, lease expiry, and TTL sweeps).
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant the task is running on behalf of.
+    /// * `task`:   The task to be added to the scheduler. Must implement the `Task` trait.
+    /// * `wake`:   The time stamp in cycles before which the task should not be run.
+    pub fn enqueue_at(&self, tenant: TenantId, task: Box<Task>, wake: u64) {
+        self.timers.write().insert(wake, (tenant, task));
+    }
+
+    /// Runs a task right away on the calling thread instead of enqueueing it, so that requests
+    /// that are cheaper to run than to hand off to the run-queues (ex: extensions declared SHORT
+    /// that issue a single get()) do not pay for the handoff. Meant to be called by the dispatch
+    /// task. The task is resumed for as long as it keeps yielding within a cycle cap. A task that
+    /// is still running once it has used up the cap, or that asks to be woken up later, falls back
+    /// to being scheduled like any other, and it's tenant is charged for the time it ran either
+    /// way. The cap cannot pre-empt a task that does not yield; that is still left to the
+    /// watchdog.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant the task is running on behalf of.
+    /// * `task`:   The task to be run. Must not have DISPATCH priority.
+    /// * `cap`:    The time in cycles after which the task stops being resumed inline.
+    ///
+    /// # Return
+    ///
+    /// True if the task ran to completion inline.
+    pub fn run_inline(&self, tenant: TenantId, mut task: Box<Task>, cap: u64) -> bool {
+        let mut used = 0;
+        loop {
+            let (state, exec) = task.run();
+            used += exec;
+            self.busy.fetch_add(exec as usize, Ordering::Relaxed);
+
+            if state == COMPLETED {
+                self.completed.fetch_add(1, Ordering::Relaxed);
+                self.inlined.fetch_add(1, Ordering::Relaxed);
+                self.respond(&mut *task);
+
+                let next = task.successor();
+                let mut waiting = self.waiting.write();
+                waiting.account(tenant, used);
+                if let Some(next) = next {
+                    waiting.push(tenant, next);
+                }
+                return true;
+            }
+
+            let wake = task.wake();
+            if used >= cap || wake.is_some() {
+                self.handed_off.fetch_add(1, Ordering::Relaxed);
+                if let Some(id) = task.id() {
+                    trace!("{} handed off after {} cycles inline", id, used);
+                }
+
+                self.waiting.write().account(tenant, used);
+                match wake {
+                    Some(wake) if wake > cycles::rdtsc() => self.enqueue_at(tenant, task, wake),
+                    _ => self.enqueue(tenant, task),
+                }
+                return false;
+            }
+        }
     }
 
     /// Enqueues multiple tasks onto the scheduler.
     ///
     /// # Arguments
     ///
-    /// * `tasks`: A deque of tasks to be added to the scheduler. These tasks will be run in the
-    ///            order that they are provided in, and must implement the `Task` trait.
+    /// * `tasks`: A deque of tasks and the tenants they belong to, to be added to the scheduler.
+    ///            Tasks belonging to a tenant will be run in the order that they are provided in,
+    ///            and must implement the `Task` trait.
     #[inline]
-    pub fn enqueue_many(&self, mut tasks: VecDeque<Box<Task>>) {
-        self.waiting.write().append(&mut tasks);
+    pub fn enqueue_many(&self, mut tasks: VecDeque<(TenantId, Box<Task>)>) {
+        let mut waiting = self.waiting.write();
+        for (tenant, task) in tasks.drain(..) {
+            waiting.push(tenant, task);
+        }
     }
 
     /// Dequeues all waiting tasks from the scheduler.
     ///
     /// # Return
     ///
-    /// A deque of all waiting tasks in the scheduler. This tasks might be in various stages of
-    /// execution. Some might have run for a while and yielded, and some might have never run
-    /// before. If there are no tasks waiting to run, then an empty vector is returned.
+    /// A deque of all waiting tasks in the scheduler along with the tenants they belong to. This
+    /// tasks might be in various stages of execution. Some might have run for a while and
+    /// yielded, and some might have never run before. Tasks parked until a point in time are
+    /// returned too, irrespective of whether they are due. If there are no tasks waiting to run,
+    /// then an empty vector is returned.
     #[inline]
-    pub fn dequeue_all(&self) -> VecDeque<Box<Task>> {
-        let mut tasks = self.waiting.write();
-        return tasks.drain(..).collect();
+    pub fn dequeue_all(&self) -> VecDeque<(TenantId, Box<Task>)> {
+        let mut tasks = self.waiting.write().drain();
+        tasks.extend(self.timers.write().drain());
+        tasks
     }
 
     /// Returns a list of pending response packets.
@@ -116,8 +1555,19 @@ impl RoundRobin {
     /// vector is returned.
     #[inline]
     pub fn responses(&self) -> Vec<Packet<IpHeader, EmptyMetadata>> {
-        let mut responses = self.responses.write();
-        return responses.drain(..).collect();
+        let responses: Vec<Packet<IpHeader, EmptyMetadata>> = {
+            let mut responses = self.responses.write();
+            responses.drain(..).collect()
+        };
+
+        // Latency-critical responses that were queued up go out with this batch.
+        if self.expressing.load(Ordering::Relaxed) {
+            if let Some(ref mut express) = *self.express.write() {
+                express.stats.drained(cycles::rdtsc());
+            }
+        }
+
+        return responses;
     }
 
     /// Appends a list of responses to the scheduler.
@@ -135,6 +1585,13 @@ impl RoundRobin {
         self.latest.load(Ordering::Relaxed) as u64
     }
 
+    /// Returns true if the task that is running (or ran last) on the scheduler was declared long
+    /// running by it's extension.
+    #[inline]
+    pub fn running_long(&self) -> bool {
+        self.long.load(Ordering::Relaxed)
+    }
+
     /// Sets the compromised flag on the scheduler.
     #[inline]
     pub fn compromised(&self) {
@@ -153,36 +1610,216 @@ impl RoundRobin {
         self.core.load(Ordering::Relaxed) as i32
     }
 
-    /// Picks up a task from the waiting queue, and runs it until it either yields or completes.
+    /// Returns the number of non-dispatch tasks that have completed on this scheduler.
+    pub fn completed(&self) -> u64 {
+        self.completed.load(Ordering::Relaxed) as u64
+    }
+
+    /// Returns the total time in cycles non-dispatch tasks have run for on this scheduler.
+    pub fn busy(&self) -> u64 {
+        self.busy.load(Ordering::Relaxed) as u64
+    }
+
+    /// Accounts for a single poll by the dispatch task running on this scheduler.
+    ///
+    /// # Arguments
+    ///
+    /// * `busy`:   True if the poll received requests, or sent out responses.
+    /// * `cycles`: The time in cycles the poll took.
+    #[inline]
+    pub fn record_dispatch(&self, busy: bool, cycles: u64) {
+        if busy {
+            self.dispatch_busy.fetch_add(cycles as usize, Ordering::Relaxed);
+        } else {
+            self.dispatch_idle.fetch_add(cycles as usize, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the total time in cycles the dispatch task on this scheduler has spent on busy and
+    /// idle polls, in that order.
+    pub fn dispatch_cycles(&self) -> (u64, u64) {
+        (
+            self.dispatch_busy.load(Ordering::Relaxed) as u64,
+            self.dispatch_idle.load(Ordering::Relaxed) as u64,
+        )
+    }
+
+    /// Returns the counters of the packets and requests handled by the dispatcher on this
+    /// scheduler's core.
+    pub fn counters(&self) -> &CoreCounters {
+        &self.counters
+    }
+
+    /// Returns the number of tenant tasks waiting on the run-queues.
+    pub fn pending(&self) -> usize {
+        self.waiting.read().pending()
+    }
+
+    /// Returns the number of tasks parked until a point in time.
+    pub fn parked(&self) -> usize {
+        self.timers.read().len()
+    }
+
+    // Checks a task that finished execution for request and response packets. If they exist, then
+    // the request packet is freed, and the response packet is queued up to be sent out.
+    fn respond(&self, task: &mut Task) {
+        if let Some((req, res)) = unsafe { task.tear() } {
+            let priority = rpc::parse_rpc_priority(&req);
+            req.free_packet();
+            let res = rpc::fixup_header_length_fields(res);
+            if let Some(id) = task.id() {
+                trace!("{} response of {} bytes queued", id, res.get_payload().len());
+            }
+
+            // Responses too large for a single packet follow the first one out.
+            let more = unsafe { task.tear_more() };
+
+            // Latency-critical responses that fit in a single packet can skip the queue.
+            let res = if more.is_empty() && self.expressing.load(Ordering::Relaxed) {
+                self.express(priority, res)
+            } else {
+                Some(res)
+            };
+            if let Some(res) = res {
+                self.responses.write().push(res);
+            }
+
+            for more in more.into_iter() {
+                let more = rpc::fixup_header_length_fields(more);
+                self.responses.write().push(more);
+            }
+        }
+    }
+
+    // Sends a response out on the express queue if it is latency-critical and fits in a single
+    // packet. Returns the response if it should be queued up for the dispatcher instead.
+    fn express(
+        &self,
+        priority: u8,
+        res: Packet<IpHeader, EmptyMetadata>,
+    ) -> Option<Packet<IpHeader, EmptyMetadata>> {
+        let mut guard = self.express.write();
+        let express = match *guard {
+            Some(ref mut express) => express,
+            None => return Some(res),
+        };
+
+        if priority < express.priority {
+            return Some(res);
+        }
+
+        // Responses too large to be sent out in a single packet need to be fragmented, even if
+        // they end up being sent out over IPv6.
+        let max = (PACKET_MTU - PACKET_IPV6_LEN + PACKET_UDP_LEN) as usize;
+        if res.get_payload().len() > max {
+            return Some(res);
+        }
+
+        let now = cycles::rdtsc();
+        if !express.stats.next() {
+            express.stats.queued(now);
+            return Some(res);
+        }
+
+        // If the queue is full, the response goes out with the rest of them after all.
+        let res = (express.tx)(res);
+        if res.is_none() {
+            express.stats.record(true, cycles::rdtsc() - now);
+        }
+        res
+    }
+
+    /// Picks up a task from the waiting queues, and runs it until it either yields or completes.
     pub fn poll(&self) {
         loop {
             // Set the time-stamp of the latest scheduling decision.
-            self.latest
-                .store(cycles::rdtsc() as usize, Ordering::Relaxed);
+            let now = cycles::rdtsc();
+            self.latest.store(now as usize, Ordering::Relaxed);
 
             // If the compromised flag was set, then return.
             if self.compromised.load(Ordering::Relaxed) {
                 return;
             }
 
-            // If there are tasks to run, then pick one from the head of the queue, and run it until it
-            // either completes or yields back.
-            let task = self.waiting.write().pop_front();
+            // Move parked tasks that are due onto the run-queues.
+            let due = {
+                let mut timers = self.timers.write();
+                if timers.len() > 0 {
+                    timers.advance(now)
+                } else {
+                    Vec::new()
+                }
+            };
+            if !due.is_empty() {
+                let mut waiting = self.waiting.write();
+                for (tenant, task) in due.into_iter() {
+                    waiting.push(tenant, task);
+                }
+            }
+
+            // Out of tenant tasks, every so often try to take one off a busier peer.
+            if self.imbalance.load(Ordering::Relaxed) > 0 && self.pending() == 0 {
+                let last = self.last_steal.load(Ordering::Relaxed) as u64;
+                if now - last >= self.steal_interval {
+                    self.last_steal.store(now as usize, Ordering::Relaxed);
+                    self.steal();
+                }
+            }
+
+            // If there are tasks to run, then pick the next one as determined by the run-queues,
+            // and run it until it either completes or yields back.
+            let task = {
+                let mut waiting = self.waiting.write();
+                waiting.roll(now);
+                waiting.pop()
+            };
+
+            if let Some((idx, mut task)) = task {
+                // Traced tasks carry their request's correlation id.
+                let id = task.id();
+                if let Some(id) = id {
+                    trace!("{} running on core {}", id, self.core());
+                }
+
+                self.long.store(task.cost() == CostClass::LONG, Ordering::Relaxed);
+                let (state, exec) = task.run();
+                if idx.is_some() {
+                    self.busy.fetch_add(exec as usize, Ordering::Relaxed);
+                }
+
+                if state == COMPLETED {
+                    if idx.is_some() {
+                        self.completed.fetch_add(1, Ordering::Relaxed);
+                    }
+
+                    self.respond(&mut *task);
 
-            if let Some(mut task) = task {
-                if task.run().0 == COMPLETED {
-                    // The task finished execution, check for request and response packets. If they
-                    // exist, then free the request packet, and enqueue the response packet.
-                    if let Some((req, res)) = unsafe { task.tear() } {
-                        req.free_packet();
-                        self.responses
-                            .write()
-                            .push(rpc::fixup_header_length_fields(res));
+                    // Queue whatever must follow the task on behalf of the same tenant.
+                    let next = task.successor();
+                    let mut waiting = self.waiting.write();
+                    let tenant = waiting.tenant(idx);
+                    waiting.charge(idx, exec, None);
+                    if let Some(next) = next {
+                        waiting.push(tenant, next);
                     }
                 } else {
-                    // The task did not complete execution. Add it back to the waiting list so that it
+                    if let Some(id) = id {
+                        trace!("{} yielded after {} cycles", id, exec);
+                    }
+
+                    // The task did not complete execution. If it asked to be woken up later, park
+                    // it on the timer wheel. Otherwise, add it back to the waiting list so that it
                     // gets to run again.
-                    self.waiting.write().push_back(task);
+                    match task.wake() {
+                        Some(wake) if wake > cycles::rdtsc() => {
+                            let mut waiting = self.waiting.write();
+                            let tenant = waiting.tenant(idx);
+                            waiting.charge(idx, exec, None);
+                            self.timers.write().insert(wake, (tenant, task));
+                        }
+
+                        _ => self.waiting.write().charge(idx, exec, Some(task)),
+                    }
                 }
             }
         }
@@ -195,3 +1832,423 @@ impl RoundRobin {
 // "Task" trait object.
 unsafe impl Send for RoundRobin {}
 unsafe impl Sync for RoundRobin {}
+
+// This module contains simple unit tests for the scheduler's run-queues.
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{EarliestDeadlineFirst, ExpressStats, Queues, RunQueues};
+    use task::{Task, TaskPriority, TaskState};
+
+    use e2d2::common::EmptyMetadata;
+    use e2d2::headers::UdpHeader;
+    use e2d2::interface::Packet;
+
+    // A task that does nothing, and is identified by the tenant it belongs to.
+    struct Dummy {
+        tenant: u32,
+        priority: TaskPriority,
+        affinity: Option<usize>,
+        deadline: Option<u64>,
+    }
+
+    impl Task for Dummy {
+        fn run(&mut self) -> (TaskState, u64) {
+            (TaskState::YIELDED, 0)
+        }
+
+        fn state(&self) -> TaskState {
+            TaskState::YIELDED
+        }
+
+        fn time(&self) -> u64 {
+            self.tenant as u64
+        }
+
+        fn priority(&self) -> TaskPriority {
+            self.priority.clone()
+        }
+
+        fn affinity(&self) -> Option<usize> {
+            self.affinity
+        }
+
+        fn deadline(&self) -> Option<u64> {
+            self.deadline
+        }
+
+        unsafe fn tear(
+            &mut self,
+        ) -> Option<(
+            Packet<UdpHeader, EmptyMetadata>,
+            Packet<UdpHeader, EmptyMetadata>,
+        )> {
+            None
+        }
+    }
+
+    fn dummy(tenant: u32) -> Box<Task> {
+        Box::new(Dummy {
+            tenant: tenant,
+            priority: TaskPriority::REQUEST,
+            affinity: None,
+            deadline: None,
+        })
+    }
+
+    // Runs `n` tasks charging each `exec` cycles, and returns the number run per tenant.
+    fn run(queues: &mut RunQueues, n: usize, exec: u64) -> HashMap<u64, usize> {
+        let mut counts = HashMap::new();
+        for _ in 0..n {
+            let (idx, task) = queues.pop().expect("Expected a task to run.");
+            *counts.entry(task.time()).or_insert(0) += 1;
+            queues.charge(idx, exec, Some(task));
+        }
+        counts
+    }
+
+    // This test verifies that tenants share the CPU in proportion to their weights.
+    #[test]
+    fn test_weighted_share() {
+        let mut weights = HashMap::new();
+        weights.insert(1, 3);
+        let mut queues = RunQueues::new(weights, 100);
+
+        for _ in 0..4 {
+            queues.push(1, dummy(1));
+            queues.push(2, dummy(2));
+        }
+
+        let counts = run(&mut queues, 400, 10);
+        assert_eq!(300, counts[&1]);
+        assert_eq!(100, counts[&2]);
+    }
+
+    // This test verifies that system tasks get to run once every round.
+    #[test]
+    fn test_system_tasks() {
+        let mut queues = RunQueues::new(HashMap::new(), 100);
+
+        queues.push(
+            0,
+            Box::new(Dummy {
+                tenant: 0,
+                priority: TaskPriority::DISPATCH,
+                affinity: None,
+                deadline: None,
+            }),
+        );
+        queues.push(1, dummy(1));
+        queues.push(2, dummy(2));
+
+        // Every round runs the system task once, and each tenant's task ten times.
+        let counts = run(&mut queues, 63, 10);
+        assert_eq!(3, counts[&0]);
+        assert_eq!(30, counts[&1]);
+        assert_eq!(30, counts[&2]);
+
+        // Draining returns all tasks, including the system task.
+        assert_eq!(3, queues.drain().len());
+        assert!(queues.pop().is_none());
+    }
+
+    // This test verifies that system tasks still run every `SYSTEM_INTERVAL` tenant tasks when
+    // a round is much longer than that.
+    #[test]
+    fn test_system_interval() {
+        let mut queues = RunQueues::new(HashMap::new(), 100);
+
+        queues.push(
+            0,
+            Box::new(Dummy {
+                tenant: 0,
+                priority: TaskPriority::DISPATCH,
+                affinity: None,
+                deadline: None,
+            }),
+        );
+        for tenant in 1..41 {
+            queues.push(tenant, dummy(tenant));
+        }
+
+        // A round runs 400 tenant tasks, but the system task runs every 32 of them.
+        let counts = run(&mut queues, 330, 10);
+        assert_eq!(10, counts[&0]);
+        assert_eq!(320, counts.iter().filter(|&(t, _)| *t != 0).map(|(_, c)| *c).sum::<usize>());
+    }
+
+    // This test verifies that tenants that use up their budget are deferred while another tenant
+    // can run, that they run anyway once every tenant is over budget, and that budgets are
+    // restored every interval.
+    #[test]
+    fn test_budget() {
+        let mut queues = RunQueues::new(HashMap::new(), 100);
+        queues.set_budget(50, 1000, 0);
+        queues.push(1, dummy(1));
+        queues.push(2, dummy(2));
+
+        // Without a budget, tenant 1 would have used it's entire quantum first.
+        let counts = run(&mut queues, 10, 10);
+        assert_eq!(5, counts[&1]);
+        assert_eq!(5, counts[&2]);
+        assert_eq!(2, queues.throttled);
+
+        // Both tenants are over budget, and share the CPU by weight.
+        let counts = run(&mut queues, 10, 10);
+        assert_eq!(5, counts[&1]);
+        assert_eq!(5, counts[&2]);
+        assert_eq!(2, queues.throttled);
+        assert_eq!(vec![(1, 100), (2, 100)], queues.cycles());
+
+        // A new interval restores both budgets.
+        queues.roll(999);
+        assert!(queues.tenants.iter().all(|queue| queue.over_budget()));
+        queues.roll(1000);
+        let counts = run(&mut queues, 10, 10);
+        assert_eq!(5, counts[&1]);
+        assert_eq!(5, counts[&2]);
+        assert_eq!(4, queues.throttled);
+    }
+
+    // This test verifies that the tenants of a group are deferred once the group uses up it's
+    // budget, even if they have budget of their own left, and that the group's budget is
+    // restored every interval.
+    #[test]
+    fn test_group_budget() {
+        let mut queues = RunQueues::new(HashMap::new(), 100);
+        queues.set_budget(0, 1000, 0);
+        queues.set_group_budget(&[1, 2], 50);
+        queues.push(1, dummy(1));
+        queues.push(2, dummy(2));
+        queues.push(3, dummy(3));
+
+        // Tenant 1 uses up the group's budget, after which only tenant 3 runs.
+        let counts = run(&mut queues, 20, 10);
+        assert_eq!(5, counts[&1]);
+        assert!(counts.get(&2).is_none());
+        assert_eq!(15, counts[&3]);
+        assert_eq!(1, queues.throttled);
+
+        // A new interval restores the group's budget.
+        queues.roll(999);
+        assert!(queues.over_budget(&queues.tenants[1]));
+        queues.roll(1000);
+        assert!(!queues.over_budget(&queues.tenants[1]));
+        assert!(!queues.over_budget(&queues.tenants[2]));
+    }
+
+    // Pops `n` tasks, re-queueing system tasks and yielded tasks as required, and returns the
+    // tenant of every task in the order they were picked.
+    fn order(queues: &mut Queues, n: usize, yields: bool) -> Vec<u64> {
+        let mut tenants = Vec::new();
+        for _ in 0..n {
+            let (idx, task) = queues.pop().expect("Expected a task to run.");
+            tenants.push(task.time());
+            let task = if idx.is_none() || yields { Some(task) } else { None };
+            queues.charge(idx, 10, task);
+        }
+        tenants
+    }
+
+    // Returns a task for a tenant that should complete by a deadline.
+    fn due(tenant: u32, deadline: Option<u64>) -> Box<Task> {
+        Box::new(Dummy {
+            tenant: tenant,
+            priority: TaskPriority::REQUEST,
+            affinity: None,
+            deadline: deadline,
+        })
+    }
+
+    // This test verifies that deadline ordered run-queues run tenant tasks earliest deadline
+    // first alternating with system tasks, that tasks without a deadline get one relative to when
+    // they were queued, and that tasks that yield keep their deadline.
+    #[test]
+    fn test_deadline_order() {
+        let mut queues = EarliestDeadlineFirst::new(100);
+        queues.roll(1000);
+
+        queues.push(
+            0,
+            Box::new(Dummy {
+                tenant: 0,
+                priority: TaskPriority::DISPATCH,
+                affinity: None,
+                deadline: None,
+            }),
+        );
+        queues.push(1, due(1, Some(5000)));
+        queues.push(2, due(2, Some(2000)));
+        queues.push(3, due(3, None));
+        queues.push(4, due(4, Some(1100)));
+        assert_eq!(4, queues.pending());
+
+        // Tenant 3 has a deadline of 1100 too, and was queued first.
+        assert_eq!(vec![0, 3, 0, 4, 0, 2], order(&mut queues, 6, false));
+        assert_eq!(1, queues.pending());
+
+        // A task that yields goes back ahead of a later deadline.
+        queues.push(5, due(5, Some(4000)));
+        assert_eq!(vec![0, 5, 0, 5], order(&mut queues, 4, true));
+
+        // Stealing takes the least urgent task.
+        let (tenant, _) = queues
+            .steal(&|task: &Task| task.time() > 0)
+            .expect("Expected a task to steal.");
+        assert_eq!(1, tenant);
+        assert_eq!(1, queues.pending());
+
+        assert_eq!(
+            vec![(1, 0), (2, 10), (3, 10), (4, 10), (5, 20)],
+            queues.cycles()
+        );
+        assert_eq!(2, queues.drain().len());
+        assert_eq!(0, queues.pending());
+    }
+
+    // This test verifies that background tasks only run while there aren't any tenant tasks, and
+    // then take turns with system tasks.
+    #[test]
+    fn test_background_tasks() {
+        let mut queues = RunQueues::new(HashMap::new(), 100);
+
+        let system = [(0, TaskPriority::DISPATCH), (9, TaskPriority::BACKGROUND)];
+        for (tenant, priority) in system.iter().cloned() {
+            queues.push(
+                0,
+                Box::new(Dummy {
+                    tenant: tenant,
+                    priority: priority,
+                    affinity: None,
+                    deadline: None,
+                }),
+            );
+        }
+        queues.push(1, dummy(1));
+        assert_eq!(1, queues.pending());
+
+        let counts = run(&mut queues, 20, 10);
+        assert!(!counts.contains_key(&9));
+
+        let _ = queues
+            .steal(&|task: &Task| task.time() == 1)
+            .expect("Expected a task to steal.");
+        let counts = run(&mut queues, 10, 10);
+        assert_eq!(5, counts[&0]);
+        assert_eq!(5, counts[&9]);
+
+        // Draining returns the background task along with the system task.
+        assert_eq!(2, queues.drain().len());
+    }
+
+    // This test verifies that stealing removes the most recently queued task accepted by the
+    // filter, and never touches system tasks.
+    #[test]
+    fn test_steal() {
+        let mut queues = RunQueues::new(HashMap::new(), 100);
+
+        queues.push(
+            0,
+            Box::new(Dummy {
+                tenant: 0,
+                priority: TaskPriority::DISPATCH,
+                affinity: Some(1),
+                deadline: None,
+            }),
+        );
+        for affinity in vec![Some(1), None, Some(1), Some(2)] {
+            queues.push(
+                1,
+                Box::new(Dummy {
+                    tenant: 1,
+                    priority: TaskPriority::REQUEST,
+                    affinity: affinity,
+                    deadline: None,
+                }),
+            );
+        }
+        assert_eq!(4, queues.pending);
+
+        let (tenant, task) = queues
+            .steal(&|task: &Task| task.affinity() == Some(1))
+            .expect("Expected a task to steal.");
+        assert_eq!(1, tenant);
+        assert_eq!(Some(1), task.affinity());
+        assert_eq!(3, queues.pending);
+
+        assert!(queues.steal(&|task: &Task| task.affinity() == Some(3)).is_none());
+        assert_eq!(3, queues.pending);
+
+        // The other task with an affinity for core 1 goes next. The system task stays put.
+        assert!(queues.steal(&|task: &Task| task.affinity() == Some(1)).is_some());
+        assert!(queues.steal(&|task: &Task| task.affinity() == Some(1)).is_none());
+        assert_eq!(2, queues.pending);
+        assert_eq!(3, queues.drain().len());
+    }
+
+    // This test verifies that eviction takes the last task off the queue of the tenant with the
+    // most tasks relative to it's weight, and that deadline ordered run-queues evict the least
+    // urgent task.
+    #[test]
+    fn test_evict() {
+        let mut weights = HashMap::new();
+        weights.insert(2, 4);
+        let mut queues = RunQueues::new(weights, 100);
+
+        for _ in 0..3 {
+            queues.push(1, dummy(1));
+        }
+        for _ in 0..8 {
+            queues.push(2, dummy(2));
+        }
+        assert_eq!(11, queues.pending);
+
+        // Tenant 2 has more tasks, but fewer relative to it's weight.
+        let (tenant, _) = queues
+            .evict(&|_: &Task| true)
+            .expect("Expected a task to evict.");
+        assert_eq!(1, tenant);
+        assert_eq!(10, queues.pending);
+
+        let (tenant, _) = queues
+            .evict(&|task: &Task| task.time() == 2)
+            .expect("Expected a task to evict.");
+        assert_eq!(2, tenant);
+        assert!(queues.evict(&|task: &Task| task.time() == 3).is_none());
+        assert_eq!(9, queues.pending);
+
+        let mut queues = EarliestDeadlineFirst::new(100);
+        queues.push(1, due(1, Some(5000)));
+        queues.push(2, due(2, Some(9000)));
+        queues.push(3, due(3, Some(2000)));
+        let (tenant, _) = queues
+            .evict(&|_: &Task| true)
+            .expect("Expected a task to evict.");
+        assert_eq!(2, tenant);
+        assert_eq!(2, queues.pending());
+    }
+
+    // This test verifies that latency-critical responses alternate between the express queue and
+    // the dispatcher when configured with a period, and that delays are reported per path.
+    #[test]
+    fn test_express_stats() {
+        let mut always = ExpressStats::new(0);
+        assert!((0..10).all(|_| always.next()));
+
+        let mut stats = ExpressStats::new(2);
+        let paths: Vec<bool> = (0..6).map(|_| stats.next()).collect();
+        assert_eq!(vec![true, true, false, false, true, true], paths);
+
+        // Queued up responses are delayed until the dispatcher picks them up.
+        for delay in 1..101 {
+            stats.record(true, delay);
+        }
+        stats.queued(100);
+        stats.queued(400);
+        stats.drained(1000);
+        assert!(stats.pending.is_empty());
+        assert_eq!((100, 900), stats.take());
+        assert_eq!((0, 0), stats.take());
+    }
+}
@@ -0,0 +1,490 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::mem::size_of;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::alloc::Allocator;
+use super::container::Container;
+use super::context::Context;
+use super::ext::Extension;
+use super::snapshot::Crc32;
+use super::stats::ReadAmp;
+use super::task::{CostClass, Task, TaskPriority, TaskState};
+use super::tenant::Tenant;
+use super::trace::RequestId;
+use super::wireformat::{InvokeRequest, InvokeResponse, OpCode};
+
+use e2d2::common::EmptyMetadata;
+use e2d2::headers::{IpHeader, MacHeader, UdpHeader};
+use e2d2::interface::*;
+
+/// How a shadow version of an extension compared against the installed version.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShadowStats {
+    /// The number of invocations the shadow version was run on.
+    pub runs: u64,
+
+    /// The number of those on which the two versions responded differently.
+    pub mismatches: u64,
+
+    /// The total number of cycles the installed version ran for on those invocations.
+    pub primary_cycles: u64,
+
+    /// The total number of cycles the shadow version ran for on those invocations.
+    pub shadow_cycles: u64,
+}
+
+/// A shadow version of an extension, installed alongside the version that services a tenant's
+/// invocations so that it can be validated against live requests before it replaces it. On a
+/// sample of invocations the shadow version is run once the installed one completes, with the
+/// same arguments. It's writes are discarded (refer to `Context::set_dry_run()`), it's response
+/// is dropped, and a digest of it's response along with the cycles it took is compared against
+/// the installed version's.
+pub struct Shadow {
+    // The shadow version. Declared with the manifest of the installed version.
+    ext: Arc<Extension>,
+
+    // The shadow version is run on one in every these many invocations. Zero if never.
+    sample: AtomicUsize,
+
+    // The number of invocations seen so far. Used to sample invocations.
+    seen: AtomicUsize,
+
+    // Counters making up ShadowStats.
+    runs: AtomicUsize,
+    mismatches: AtomicUsize,
+    primary_cycles: AtomicUsize,
+    shadow_cycles: AtomicUsize,
+
+    // Used to digest responses.
+    crc: Crc32,
+}
+
+// Implementation of methods on Shadow.
+impl Shadow {
+    /// Creates a shadow version of an extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `ext`:    The shadow version of the extension.
+    /// * `sample`: The shadow version is run on one in every these many invocations.
+    pub fn new(ext: Extension, sample: u32) -> Shadow {
+        Shadow {
+            ext: Arc::new(ext),
+            sample: AtomicUsize::new(sample as usize),
+            seen: AtomicUsize::new(0),
+            runs: AtomicUsize::new(0),
+            mismatches: AtomicUsize::new(0),
+            primary_cycles: AtomicUsize::new(0),
+            shadow_cycles: AtomicUsize::new(0),
+            crc: Crc32::new(),
+        }
+    }
+
+    /// Returns the shadow version of the extension.
+    pub fn extension(&self) -> Arc<Extension> {
+        Arc::clone(&self.ext)
+    }
+
+    /// Changes the fraction of invocations the shadow version is run on.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample`: The shadow version is run on one in every these many invocations.
+    pub fn set_sample(&self, sample: u32) {
+        self.sample.store(sample as usize, Ordering::Relaxed);
+    }
+
+    /// Returns true if the shadow version should be run on the invocation being serviced.
+    pub fn sampled(&self) -> bool {
+        let sample = self.sample.load(Ordering::Relaxed);
+        sample > 0 && self.seen.fetch_add(1, Ordering::Relaxed) % sample == 0
+    }
+
+    /// Returns how the shadow version compared against the installed version so far.
+    pub fn stats(&self) -> ShadowStats {
+        ShadowStats {
+            runs: self.runs.load(Ordering::Relaxed) as u64,
+            mismatches: self.mismatches.load(Ordering::Relaxed) as u64,
+            primary_cycles: self.primary_cycles.load(Ordering::Relaxed) as u64,
+            shadow_cycles: self.shadow_cycles.load(Ordering::Relaxed) as u64,
+        }
+    }
+
+    /// Creates a task that runs the shadow version on an invocation. The task runs on copies of
+    /// the invocation's packets, so it must be created before the installed version takes them
+    /// over.
+    ///
+    /// # Arguments
+    ///
+    /// * `req`:         The invoke() request, parsed upto it's InvokeRequest header.
+    /// * `name_length`: The length of the extension's name on the request's payload.
+    /// * `args_length`: The length of the arguments following the name.
+    /// * `tenant`:      The tenant that issued the invocation.
+    /// * `heap`:        The allocator the extension reads objects through.
+    /// * `reads`:       The counters reads are accounted to. Dry runs are not accounted.
+    ///
+    /// # Return
+    ///
+    /// A container running the shadow version, or None if packets could not be allocated.
+    pub fn invocation(
+        &self,
+        req: &Packet<InvokeRequest, EmptyMetadata>,
+        name_length: usize,
+        args_length: usize,
+        tenant: Arc<Tenant>,
+        heap: Arc<Allocator>,
+        reads: Arc<ReadAmp>,
+    ) -> Option<Container> {
+        let (copy, res) = match (udp_packet(), udp_packet()) {
+            (Some(copy), Some(res)) => (copy, res),
+
+            (copy, res) => {
+                for packet in copy.into_iter().chain(res.into_iter()) {
+                    packet.free_packet();
+                }
+                return None;
+            }
+        };
+
+        // Any fields on the header unknown to this build are not copied over.
+        let mut copy = copy
+            .push_header(req.get_header())
+            .expect("Failed to push InvokeRequest");
+        copy.get_mut_header().common_header.header_len = size_of::<InvokeRequest>() as u16;
+        copy.add_to_payload_tail(req.get_payload().len(), req.get_payload())
+            .expect("Failed to copy invoke() payload");
+
+        let (stamp, tenant_id) = {
+            let hdr = req.get_header();
+            (hdr.common_header.stamp, hdr.common_header.tenant)
+        };
+        let res = res
+            .push_header(&InvokeResponse::new(
+                stamp,
+                OpCode::SandstormInvokeRpc,
+                tenant_id,
+            ))
+            .expect("Failed to push InvokeResponse");
+
+        let db = Context::new(copy, name_length, args_length, res, tenant, heap, reads);
+        db.set_dry_run();
+
+        Some(Container::new(TaskPriority::REQUEST, Rc::new(db), self.extension()))
+    }
+
+    // Returns the digest of a response.
+    fn digest(&self, res: &Packet<UdpHeader, EmptyMetadata>) -> u32 {
+        self.crc.checksum(res.get_payload())
+    }
+
+    // Accounts for a run of the shadow version.
+    fn record(&self, matched: bool, primary: u64, shadow: u64) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        if !matched {
+            self.mismatches.fetch_add(1, Ordering::Relaxed);
+        }
+        self.primary_cycles.fetch_add(primary as usize, Ordering::Relaxed);
+        self.shadow_cycles.fetch_add(shadow as usize, Ordering::Relaxed);
+    }
+}
+
+// Allocates a packet with empty headers upto UDP. Shadow invocations never go out on the wire.
+fn udp_packet() -> Option<Packet<UdpHeader, EmptyMetadata>> {
+    new_packet()?
+        .push_header(&MacHeader::new())?
+        .push_header(&IpHeader::new())?
+        .push_header(&UdpHeader::new())
+}
+
+/// A task servicing an invocation of an extension that has a shadow version, and was sampled.
+/// Behaves exactly like the task it wraps, except that it remembers the digest of the response,
+/// and is succeeded by a task running the shadow version.
+pub struct Shadowed {
+    // The task servicing the invocation with the installed version.
+    task: Box<Task>,
+
+    // The shadow version, along with the task running it on the invocation.
+    shadow: Arc<Shadow>,
+    run: Option<Container>,
+
+    // The digest of the installed version's response, once it has completed.
+    digest: Option<u32>,
+}
+
+// Implementation of methods on Shadowed.
+impl Shadowed {
+    /// Wraps a task servicing an invocation.
+    ///
+    /// # Arguments
+    ///
+    /// * `task`:   The task running the installed version.
+    /// * `shadow`: The shadow version.
+    /// * `run`:    The task running the shadow version. Refer to `Shadow::invocation()`.
+    pub fn new(task: Box<Task>, shadow: Arc<Shadow>, run: Container) -> Shadowed {
+        Shadowed {
+            task: task,
+            shadow: shadow,
+            run: Some(run),
+            digest: None,
+        }
+    }
+}
+
+// Implementation of the Task trait for Shadowed. Everything except tear() and successor() is
+// passed through; the wrapped task's own successor runs after the shadow version.
+impl Task for Shadowed {
+    /// Refer to the `Task` trait for Documentation.
+    fn run(&mut self) -> (TaskState, u64) {
+        self.task.run()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn state(&self) -> TaskState {
+        self.task.state()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn time(&self) -> u64 {
+        self.task.time()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn priority(&self) -> TaskPriority {
+        self.task.priority()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn cost(&self) -> CostClass {
+        self.task.cost()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn affinity(&self) -> Option<usize> {
+        self.task.affinity()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn deadline(&self) -> Option<u64> {
+        self.task.deadline()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn set_deadline(&mut self, deadline: u64) {
+        self.task.set_deadline(deadline)
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    unsafe fn tear(
+        &mut self,
+    ) -> Option<(
+        Packet<UdpHeader, EmptyMetadata>,
+        Packet<UdpHeader, EmptyMetadata>,
+    )> {
+        let packets = self.task.tear();
+
+        let shadow = &self.shadow;
+        let digest = packets.as_ref().map(|&(_, ref res)| shadow.digest(res));
+        self.digest = digest;
+
+        packets
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    unsafe fn tear_more(&mut self) -> Vec<Packet<UdpHeader, EmptyMetadata>> {
+        self.task.tear_more()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn id(&self) -> Option<RequestId> {
+        self.task.id()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn wake(&self) -> Option<u64> {
+        self.task.wake()
+    }
+
+    /// Refer to the `Task` trait for Documentation. Runs the shadow version, followed by the
+    /// wrapped task's successor, if any.
+    fn successor(&mut self) -> Option<Box<Task>> {
+        let primary = self.digest.map(|digest| (digest, self.task.time()));
+        let shadow = Arc::clone(&self.shadow);
+        let next = self.task.successor();
+
+        match self.run.take() {
+            Some(run) => Some(Box::new(ShadowRun {
+                task: run,
+                shadow: shadow,
+                primary: primary,
+                next: next,
+            }) as Box<Task>),
+
+            None => next,
+        }
+    }
+}
+
+// A task running the shadow version of an extension on an invocation. Compares the response
+// against the installed version's once it completes, and then drops it.
+struct ShadowRun {
+    // The task running the shadow version.
+    task: Container,
+
+    // The shadow version, whose counters are updated once the task completes.
+    shadow: Arc<Shadow>,
+
+    // The digest of the installed version's response, and the cycles it ran for. None if the
+    // installed version did not respond, in which case there is nothing to compare against.
+    primary: Option<(u32, u64)>,
+
+    // The successor of the task running the installed version, run once this one is done.
+    next: Option<Box<Task>>,
+}
+
+// Implementation of the Task trait for ShadowRun. Everything except the methods handing out
+// packets and successor() is passed through.
+impl Task for ShadowRun {
+    /// Refer to the `Task` trait for Documentation.
+    fn run(&mut self) -> (TaskState, u64) {
+        self.task.run()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn state(&self) -> TaskState {
+        self.task.state()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn time(&self) -> u64 {
+        self.task.time()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn priority(&self) -> TaskPriority {
+        self.task.priority()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn cost(&self) -> CostClass {
+        self.task.cost()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn affinity(&self) -> Option<usize> {
+        self.task.affinity()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn deadline(&self) -> Option<u64> {
+        self.task.deadline()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn set_deadline(&mut self, deadline: u64) {
+        self.task.set_deadline(deadline)
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn id(&self) -> Option<RequestId> {
+        self.task.id()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn wake(&self) -> Option<u64> {
+        self.task.wake()
+    }
+
+    /// Refer to the `Task` trait for Documentation. Never returns packets, since the response of
+    /// the shadow version must not go out to the tenant.
+    unsafe fn tear(
+        &mut self,
+    ) -> Option<(
+        Packet<UdpHeader, EmptyMetadata>,
+        Packet<UdpHeader, EmptyMetadata>,
+    )> {
+        if let Some((req, res)) = self.task.tear() {
+            if let Some((digest, cycles)) = self.primary {
+                let matched = self.shadow.digest(&res) == digest;
+                self.shadow.record(matched, cycles, self.task.time());
+            }
+
+            req.free_packet();
+            res.free_packet();
+        }
+
+        None
+    }
+
+    /// Refer to the `Task` trait for Documentation. Never returns packets, just like `tear()`.
+    unsafe fn tear_more(&mut self) -> Vec<Packet<UdpHeader, EmptyMetadata>> {
+        for res in self.task.tear_more().into_iter() {
+            res.free_packet();
+        }
+
+        Vec::new()
+    }
+
+    /// Refer to the `Task` trait for Documentation. Runs the successor of the installed
+    /// version's task.
+    fn successor(&mut self) -> Option<Box<Task>> {
+        self.next.take()
+    }
+}
+
+// This module contains simple unit tests for Shadow.
+#[cfg(test)]
+mod tests {
+    use super::{Shadow, ShadowStats};
+    use ext::Extension;
+
+    // This test verifies that the shadow version is run on one in every `sample` invocations,
+    // and never once sampling is turned off.
+    #[test]
+    fn test_shadow_sampled() {
+        let ext = Extension::load("../ext/test/target/release/libtest.so").unwrap();
+        let shadow = Shadow::new(ext, 4);
+
+        let runs = (0..16).filter(|_| shadow.sampled()).count();
+        assert_eq!(4, runs);
+
+        shadow.set_sample(0);
+        assert!(!(0..16).any(|_| shadow.sampled()));
+    }
+
+    // This test verifies that runs of the shadow version are accounted, along with those on
+    // which it's response differed.
+    #[test]
+    fn test_shadow_stats() {
+        let ext = Extension::load("../ext/test/target/release/libtest.so").unwrap();
+        let shadow = Shadow::new(ext, 1);
+        assert_eq!(ShadowStats::default(), shadow.stats());
+
+        shadow.record(true, 100, 150);
+        shadow.record(false, 200, 50);
+        assert_eq!(
+            ShadowStats {
+                runs: 2,
+                mismatches: 1,
+                primary_cycles: 300,
+                shadow_cycles: 200,
+            },
+            shadow.stats()
+        );
+    }
+}
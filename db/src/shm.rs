@@ -0,0 +1,457 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::fs::OpenOptions;
+use std::io::{Error, ErrorKind, Result};
+use std::mem::transmute;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use libc;
+use spin::Mutex;
+
+// Identifies a file as a heap segment.
+const MAGIC: &[u8; 8] = b"SPLHEAP1";
+
+// The number of bytes at the head of the segment reserved for it's header. The header consists
+// of the magic followed by the number of bytes of the segment in use (including the header).
+const HEADER_LEN: usize = 64;
+
+// The number of bytes of metadata on every record appended to the segment.
+const RECORD_META: usize = 8;
+
+// Flag on a record indicating that it is a tombstone for a deleted object.
+const FLAG_TOMBSTONE: u32 = 0x1;
+
+/// The store backing an allocator's persistent heap. Objects committed to tables are appended
+/// to the store, and walked back in order by a restarted server.
+pub trait HeapStore: Send + Sync {
+    /// Appends an object to the store. Once this method returns, the object must survive the
+    /// server exiting, and to the extent the store can guarantee it, the machine crashing.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`:    The object, laid out exactly as it was by the allocator.
+    /// * `tombstone`: True if the record marks the deletion of the object's key. The object
+    ///                then consists of only the metadata and key.
+    ///
+    /// # Return
+    ///
+    /// A slice over the copy of the object inside the store. None if the store is full.
+    fn append(&self, object: &[u8], tombstone: bool) -> Option<&'static [u8]>;
+
+    /// Walks every record appended to the store so far in the order they were appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`: Closure invoked with every record. The first argument is true if the record is a
+    ///        tombstone, and the second is a slice over the object inside the store.
+    ///
+    /// # Return
+    ///
+    /// An error if a record in the store was malformed.
+    fn recover(&self, f: &mut FnMut(bool, &'static [u8])) -> Result<()>;
+
+    /// Returns true if an address lies inside the store.
+    fn contains(&self, addr: usize) -> bool;
+
+    /// Returns the number of bytes of the store in use, and the size of the store.
+    fn stats(&self) -> (usize, usize);
+}
+
+/// This type represents a heap segment backed by a named shared-memory file (ex: under
+/// /dev/shm) or a DAX-mapped file. Objects are appended to the segment, and remain in it once
+/// the server exits, allowing a restarted server to re-attach to the segment and rebuild it's
+/// tables from the objects in it instead of re-loading them over the network. Each record in the
+/// segment has the following layout (little-endian), and is padded to a multiple of 8 bytes:
+///      ______________________________________________
+///     |            |            |                    |
+///     |   Length   |   Flags    |       Object       |
+///     |____________|____________|____________________|
+///        4 Bytes      4 Bytes        Var Length
+///
+/// Records are appended in the order that objects were committed. A record only becomes visible
+/// once the header's length has been bumped past it, so a crash mid-append never leaves behind a
+/// partial record.
+///
+/// The segment is mapped for the lifetime of the process, and never unmapped.
+///
+/// XXX: Space held by overwritten or deleted objects is never reclaimed. Once the segment fills
+/// up, objects are no longer persisted.
+pub struct Segment {
+    // The base address of the mapping.
+    base: *mut u8,
+
+    // The size of the mapping in bytes.
+    size: usize,
+
+    // The number of bytes of the segment in use. Mirrors the length in the segment's header.
+    used: AtomicUsize,
+
+    // Serializes appends to the segment.
+    lock: Mutex<()>,
+}
+
+// The mapping is never unmapped, and all writes to it are serialized by the lock.
+unsafe impl Send for Segment {}
+unsafe impl Sync for Segment {}
+
+// Implementation of methods on Segment.
+impl Segment {
+    /// Maps a heap segment, creating it if required.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path of the shared-memory or DAX file backing the segment.
+    /// * `size`: The size of the segment in bytes. Ignored if the file already exists and is
+    ///           larger than this.
+    ///
+    /// # Return
+    ///
+    /// The mapped segment. If the file already existed and contained a segment, any objects
+    /// appended to it by an earlier run can be recovered with `recover()`.
+    pub fn open(path: &str, size: usize) -> Result<Segment> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+
+        let size = size.max(file.metadata()?.len() as usize);
+        if size <= HEADER_LEN {
+            return Err(Error::new(ErrorKind::InvalidInput, "Heap segment too small."));
+        }
+        file.set_len(size as u64)?;
+
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+
+        unsafe { Segment::attach(base as *mut u8, size) }
+    }
+
+    /// Lays out a segment over memory that has already been mapped in.
+    ///
+    /// # Arguments
+    ///
+    /// * `base`: The base address of the mapping. Must remain mapped for the lifetime of the
+    ///           process.
+    /// * `size`: The size of the mapping in bytes.
+    ///
+    /// # Return
+    ///
+    /// The segment. If the mapping already contained a segment, any objects appended to it by an
+    /// earlier run can be recovered with `recover()`. A fresh segment's header is written out,
+    /// but not flushed.
+    pub unsafe fn attach(base: *mut u8, size: usize) -> Result<Segment> {
+        if size <= HEADER_LEN {
+            return Err(Error::new(ErrorKind::InvalidInput, "Heap segment too small."));
+        }
+
+        let segment = Segment {
+            base: base,
+            size: size,
+            used: AtomicUsize::new(HEADER_LEN),
+            lock: Mutex::new(()),
+        };
+
+        // Initialize the header if this is a fresh segment. Otherwise, pick up where the
+        // earlier run left off.
+        {
+            let header = slice::from_raw_parts_mut(segment.base, HEADER_LEN);
+            if &header[0..8] == MAGIC {
+                let used = segment.read_u64(8) as usize;
+                if used < HEADER_LEN || used > size {
+                    return Err(Error::new(ErrorKind::InvalidData, "Corrupt heap segment."));
+                }
+                segment.used.store(used, Ordering::Relaxed);
+            } else {
+                segment.write_u64(8, HEADER_LEN as u64);
+                header[0..8].copy_from_slice(MAGIC);
+            }
+        }
+
+        Ok(segment)
+    }
+
+    /// Appends an object to the segment.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`:    The object, laid out exactly as it was by the allocator.
+    /// * `tombstone`: True if the record marks the deletion of the object's key. The object
+    ///                then consists of only the metadata and key.
+    ///
+    /// # Return
+    ///
+    /// A slice over the copy of the object inside the segment. None if the segment is full.
+    pub fn append(&self, object: &[u8], tombstone: bool) -> Option<&'static [u8]> {
+        self.write(object, tombstone, None, |_, _| {})
+    }
+
+    /// Appends an object to the segment like `append()`, overwriting eight bytes of the copy
+    /// inside the segment with a little endian stamp before the record is published. Lets the
+    /// appender order records by when they were appended rather than by when the object was
+    /// allocated (ex: by stamping the copy's version with a fresh one).
+    ///
+    /// # Arguments
+    ///
+    /// * `object`:    The object, laid out exactly as it was by the allocator.
+    /// * `tombstone`: True if the record marks the deletion of the object's key.
+    /// * `offset`:    The offset within the object that the stamp is written at. The object
+    ///                must hold at least eight bytes from there on.
+    /// * `stamp`:     The stamp.
+    ///
+    /// # Return
+    ///
+    /// A slice over the copy of the object inside the segment. None if the segment is full.
+    pub fn append_stamped(
+        &self,
+        object: &[u8],
+        tombstone: bool,
+        offset: usize,
+        stamp: u64,
+    ) -> Option<&'static [u8]> {
+        self.write(object, tombstone, Some((offset, stamp)), |_, _| {})
+    }
+
+    /// Appends an object to the segment like `append()`, additionally handing every range of the
+    /// segment written to a closure before moving on. The record is handed over before the
+    /// header that publishes it is written, so a closure that flushes the range out to
+    /// persistent memory makes appends crash consistent.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`:    The object, laid out exactly as it was by the allocator.
+    /// * `tombstone`: True if the record marks the deletion of the object's key.
+    /// * `persist`:   Closure invoked with the address and length of every range written to.
+    ///
+    /// # Return
+    ///
+    /// A slice over the copy of the object inside the segment. None if the segment is full.
+    pub fn append_with<F>(
+        &self,
+        object: &[u8],
+        tombstone: bool,
+        persist: F,
+    ) -> Option<&'static [u8]>
+    where
+        F: Fn(*const u8, usize),
+    {
+        self.write(object, tombstone, None, persist)
+    }
+
+    // Appends a record, optionally stamping it's copy of the object. Refer to `append_with()`
+    // and `append_stamped()`.
+    fn write<F>(
+        &self,
+        object: &[u8],
+        tombstone: bool,
+        stamp: Option<(usize, u64)>,
+        persist: F,
+    ) -> Option<&'static [u8]>
+    where
+        F: Fn(*const u8, usize),
+    {
+        let len = RECORD_META + object.len();
+        let padded = (len + 7) & !7;
+
+        let _guard = self.lock.lock();
+        let used = self.used.load(Ordering::Relaxed);
+        if padded > self.size - used {
+            return None;
+        }
+
+        unsafe {
+            let record = self.base.offset(used as isize);
+            let l: [u8; 4] = transmute((object.len() as u32).to_le());
+            let f: [u8; 4] = transmute((if tombstone { FLAG_TOMBSTONE } else { 0 }).to_le());
+            ptr::copy_nonoverlapping(l.as_ptr(), record, 4);
+            ptr::copy_nonoverlapping(f.as_ptr(), record.offset(4), 4);
+
+            let body = record.offset(RECORD_META as isize);
+            ptr::copy_nonoverlapping(object.as_ptr(), body, object.len());
+            if let Some((offset, stamp)) = stamp {
+                assert!(offset + 8 <= object.len());
+                let s: [u8; 8] = transmute(stamp.to_le());
+                ptr::copy_nonoverlapping(s.as_ptr(), body.offset(offset as isize), 8);
+            }
+            persist(record, len);
+
+            // Publish the record only once it has been completely written out.
+            self.write_u64(8, (used + padded) as u64);
+            persist(self.base.offset(8), 8);
+            self.used.store(used + padded, Ordering::Release);
+
+            Some(slice::from_raw_parts(body, object.len()))
+        }
+    }
+
+    /// Walks every record appended to the segment so far in the order they were appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`: Closure invoked with every record. The first argument is true if the record is a
+    ///        tombstone, and the second is a slice over the object inside the segment.
+    ///
+    /// # Return
+    ///
+    /// An error if a record in the segment was malformed.
+    pub fn recover<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(bool, &'static [u8]),
+    {
+        let used = self.used.load(Ordering::Acquire);
+        let mut offset = HEADER_LEN;
+
+        while offset < used {
+            if used - offset < RECORD_META {
+                return Err(Error::new(ErrorKind::InvalidData, "Truncated heap record."));
+            }
+
+            let (len, flags) = unsafe {
+                let record = slice::from_raw_parts(self.base.offset(offset as isize), 8);
+                let mut l: [u8; 4] = [0; 4];
+                let mut fl: [u8; 4] = [0; 4];
+                l.copy_from_slice(&record[0..4]);
+                fl.copy_from_slice(&record[4..8]);
+                (
+                    u32::from_le(transmute(l)) as usize,
+                    u32::from_le(transmute(fl)),
+                )
+            };
+
+            if used - offset - RECORD_META < len {
+                return Err(Error::new(ErrorKind::InvalidData, "Truncated heap record."));
+            }
+
+            let body = unsafe {
+                slice::from_raw_parts(self.base.offset((offset + RECORD_META) as isize), len)
+            };
+            f(flags & FLAG_TOMBSTONE != 0, body);
+
+            offset += (RECORD_META + len + 7) & !7;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if an address lies inside the segment.
+    #[inline]
+    pub fn contains(&self, addr: usize) -> bool {
+        let base = self.base as usize;
+        addr >= base && addr < base + self.size
+    }
+
+    /// Returns the number of bytes of the segment in use, and the size of the segment.
+    pub fn stats(&self) -> (usize, usize) {
+        (self.used.load(Ordering::Relaxed), self.size)
+    }
+
+    /// Returns the base address of the segment's mapping, and the length of it's header.
+    pub fn header(&self) -> (*const u8, usize) {
+        (self.base as *const u8, HEADER_LEN)
+    }
+
+    // Reads a little-endian u64 off the segment's header at a given offset.
+    unsafe fn read_u64(&self, offset: usize) -> u64 {
+        let mut v: [u8; 8] = [0; 8];
+        ptr::copy_nonoverlapping(self.base.offset(offset as isize), v.as_mut_ptr(), 8);
+        u64::from_le(transmute(v))
+    }
+
+    // Writes a little-endian u64 into the segment's header at a given offset.
+    unsafe fn write_u64(&self, offset: usize, value: u64) {
+        let v: [u8; 8] = transmute(value.to_le());
+        ptr::copy_nonoverlapping(v.as_ptr(), self.base.offset(offset as isize), 8);
+    }
+}
+
+// Implementation of the HeapStore trait for Segment. Appends are visible to a restarted server
+// as soon as they return, but are only as durable as the file backing the segment.
+impl HeapStore for Segment {
+    fn append(&self, object: &[u8], tombstone: bool) -> Option<&'static [u8]> {
+        Segment::append(self, object, tombstone)
+    }
+
+    fn recover(&self, f: &mut FnMut(bool, &'static [u8])) -> Result<()> {
+        Segment::recover(self, |tombstone, object| f(tombstone, object))
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        Segment::contains(self, addr)
+    }
+
+    fn stats(&self) -> (usize, usize) {
+        Segment::stats(self)
+    }
+}
+
+// This module contains simple unit tests for Segment.
+#[cfg(test)]
+mod tests {
+    use super::Segment;
+    use std::fs::remove_file;
+
+    // This test verifies that objects appended to a segment are recovered, in order, once the
+    // segment is re-attached.
+    #[test]
+    fn test_segment_recover() {
+        let path = "/tmp/sandstorm_heap_recover.test";
+        let _ = remove_file(path);
+
+        {
+            let segment = Segment::open(path, 4096).expect("Failed to create segment.");
+            let copy = segment.append(&[1, 2, 3], false).expect("Failed to append.");
+            assert_eq!(&[1, 2, 3], copy);
+            assert!(segment.contains(copy.as_ptr() as usize));
+            segment.append(&[4; 9], true).expect("Failed to append.");
+        }
+
+        let segment = Segment::open(path, 4096).expect("Failed to re-attach segment.");
+        let mut records = Vec::new();
+        segment
+            .recover(|tombstone, object| records.push((tombstone, object.to_vec())))
+            .expect("Failed to recover segment.");
+
+        assert_eq!(vec![(false, vec![1, 2, 3]), (true, vec![4; 9])], records);
+        assert_eq!((64 + 16 + 24, 4096), segment.stats());
+        let _ = remove_file(path);
+    }
+
+    // This test verifies that appends fail once the segment fills up.
+    #[test]
+    fn test_segment_full() {
+        let path = "/tmp/sandstorm_heap_full.test";
+        let _ = remove_file(path);
+
+        let segment = Segment::open(path, 128).expect("Failed to create segment.");
+        assert!(segment.append(&[0; 56], false).is_some());
+        assert!(segment.append(&[0; 1], false).is_none());
+        let _ = remove_file(path);
+    }
+}
@@ -0,0 +1,809 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::mem::transmute;
+use std::path::{Path, PathBuf};
+
+// Identifies a snapshot file, and the version of it's format.
+const MAGIC: &[u8; 8] = b"SPLSNAP1";
+
+// The name of the manifest file inside a snapshot directory.
+const MANIFEST: &str = "MANIFEST";
+
+// The record kind of an object. The body of the record is the object as laid out by the
+// allocator (metadata, key, and value).
+const RECORD_OBJECT: u8 = 0x01;
+
+// The record kind of a deleted object. The body of the record is the tenant and table the
+// object belonged to followed by it's key.
+const RECORD_TOMBSTONE: u8 = 0x02;
+
+// Identifies a snapshot of a single table, and the version of it's format. Refer to
+// `TableWriter`.
+const TABLE_MAGIC: &[u8; 8] = b"SPLTABL1";
+
+// The record kind that ends a snapshot of a single table. The body of the record is the number
+// of records before it followed by the checksum over their CRCs.
+const RECORD_END: u8 = 0x03;
+
+/// A table based implementation of CRC-32 (IEEE 802.3), used to checksum snapshot records.
+pub struct Crc32 {
+    table: [u32; 256],
+}
+
+// Implementation of methods on Crc32.
+impl Crc32 {
+    /// Creates a Crc32, precomputing it's lookup table.
+    pub fn new() -> Crc32 {
+        let mut table = [0u32; 256];
+
+        for i in 0..256 {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 == 1 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            }
+            table[i] = c;
+        }
+
+        Crc32 { table: table }
+    }
+
+    /// Extends a running checksum with a slice of bytes. A fresh checksum starts at zero.
+    pub fn update(&self, crc: u32, data: &[u8]) -> u32 {
+        let mut c = !crc;
+        for byte in data.iter() {
+            c = self.table[((c ^ *byte as u32) & 0xff) as usize] ^ (c >> 8);
+        }
+        !c
+    }
+
+    /// Returns the checksum of a slice of bytes.
+    pub fn checksum(&self, data: &[u8]) -> u32 {
+        self.update(0, data)
+    }
+}
+
+/// A record read out of a snapshot.
+#[derive(Debug, PartialEq)]
+pub enum Record {
+    /// An object, laid out exactly as it was by the allocator.
+    Object(Vec<u8>),
+
+    /// An object that was deleted since the previous snapshot.
+    Tombstone(u32, u64, Vec<u8>),
+}
+
+/// An entry in a snapshot directory's manifest. Every snapshot is listed on the manifest once
+/// it has been completely written out and synced to disk.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ManifestEntry {
+    /// The epoch of the snapshot. Epochs increase monotonically within a directory.
+    pub epoch: u64,
+
+    /// True if the snapshot contains every object. False if it only contains objects that
+    /// changed since the previous snapshot.
+    pub full: bool,
+
+    /// The number of records in the snapshot.
+    pub records: u64,
+
+    /// The checksum over all records in the snapshot.
+    pub crc: u32,
+}
+
+/// Returns the path of the snapshot file for a given epoch.
+pub fn snapshot_path(dir: &str, epoch: u64) -> PathBuf {
+    Path::new(dir).join(format!("snapshot.{}", epoch))
+}
+
+/// Lists a snapshot on a directory's manifest. The snapshot must already be synced to disk.
+///
+/// # Arguments
+///
+/// * `dir`:   The snapshot directory.
+/// * `entry`: The snapshot's entry on the manifest.
+pub fn append_manifest(dir: &str, entry: &ManifestEntry) -> Result<()> {
+    let mut manifest = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(Path::new(dir).join(MANIFEST))?;
+    writeln!(
+        manifest,
+        "{} {} {} {:08x}",
+        entry.epoch,
+        if entry.full { "full" } else { "delta" },
+        entry.records,
+        entry.crc
+    )?;
+    manifest.sync_all()
+}
+
+/// Reads the manifest of a snapshot directory.
+///
+/// # Arguments
+///
+/// * `dir`: The snapshot directory.
+///
+/// # Return
+///
+/// The snapshots listed in the manifest in the order they were taken. Empty if the directory
+/// does not have a manifest.
+pub fn read_manifest(dir: &str) -> Result<Vec<ManifestEntry>> {
+    let file = match File::open(Path::new(dir).join(MANIFEST)) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 4 {
+            return Err(Error::new(ErrorKind::InvalidData, "Malformed manifest entry."));
+        }
+
+        let bad = |_| Error::new(ErrorKind::InvalidData, "Malformed manifest entry.");
+        entries.push(ManifestEntry {
+            epoch: fields[0].parse::<u64>().map_err(&bad)?,
+            full: fields[1] == "full",
+            records: fields[2].parse::<u64>().map_err(&bad)?,
+            crc: u32::from_str_radix(fields[3], 16).map_err(&bad)?,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Determines the snapshots that must be read to restore the most recent state in a snapshot
+/// directory: the latest full snapshot followed by every incremental snapshot taken after it.
+///
+/// # Arguments
+///
+/// * `entries`: The entries on the directory's manifest, in the order they were taken.
+pub fn restore_chain(entries: &[ManifestEntry]) -> Vec<ManifestEntry> {
+    match entries.iter().rposition(|entry| entry.full) {
+        Some(base) => entries[base..].to_vec(),
+        None => Vec::new(),
+    }
+}
+
+/// Writes out a single snapshot. A snapshot consists of a header followed by records, each of
+/// which has the following layout (little-endian):
+///      ________________________________________________
+///     |        |            |            |            |
+///     |  Kind  |  Length    |   CRC-32   |    Body    |
+///     |________|____________|____________|____________|
+///      1 Byte     4 Bytes      4 Bytes     Var Length
+///
+/// The CRC covers the body of the record. The manifest records a checksum over the CRCs of all
+/// records in the snapshot, so that missing or reordered records are detected as well.
+///
+/// The snapshot is written out to a `Target`, either a file in a local snapshot directory or a
+/// stream to a backup (refer to `backup::Stream`), and is added to the target's manifest only
+/// once `finish()` is called.
+pub struct Writer<T: Target> {
+    // The epoch of the snapshot.
+    epoch: u64,
+
+    // True if this is a full snapshot.
+    full: bool,
+
+    // The buffered target the snapshot is written out to.
+    file: BufWriter<T>,
+
+    // Checksum generator.
+    crc: Crc32,
+
+    // The running checksum over all records written so far.
+    total: u32,
+
+    // The number of records written so far.
+    records: u64,
+
+    // The number of bytes written so far.
+    bytes: u64,
+}
+
+/// A destination that snapshots are written out to.
+pub trait Target: Write {
+    /// Makes everything written out durable, and lists the snapshot on the target's manifest.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry`: The snapshot's entry on the manifest.
+    fn commit(self, entry: &ManifestEntry) -> Result<()>;
+}
+
+/// A snapshot file inside a local snapshot directory.
+pub struct LocalFile {
+    // The directory the snapshot is being written to.
+    dir: String,
+
+    // The snapshot file.
+    file: File,
+}
+
+// Implementation of the Write trait for LocalFile.
+impl Write for LocalFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()
+    }
+}
+
+// Implementation of the Target trait for LocalFile.
+impl Target for LocalFile {
+    /// Syncs the snapshot to disk, and adds it to the directory's manifest.
+    fn commit(self, entry: &ManifestEntry) -> Result<()> {
+        self.file.sync_all()?;
+        append_manifest(&self.dir, entry)
+    }
+}
+
+// Implementation of methods on Writer for snapshots written to a local directory.
+impl Writer<LocalFile> {
+    /// Creates a snapshot file for an epoch, and writes out it's header.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir`:   The snapshot directory. Must already exist.
+    /// * `epoch`: The epoch of the snapshot.
+    /// * `full`:  True if the snapshot will contain every object.
+    pub fn new(dir: &str, epoch: u64, full: bool) -> Result<Writer<LocalFile>> {
+        let file = LocalFile {
+            dir: dir.to_string(),
+            file: File::create(snapshot_path(dir, epoch))?,
+        };
+        Writer::with_target(file, epoch, full)
+    }
+}
+
+// Implementation of methods on Writer.
+impl<T: Target> Writer<T> {
+    /// Writes out the header of a snapshot for an epoch to a target.
+    ///
+    /// # Arguments
+    ///
+    /// * `target`: The target the snapshot is written out to.
+    /// * `epoch`:  The epoch of the snapshot.
+    /// * `full`:   True if the snapshot will contain every object.
+    pub fn with_target(target: T, epoch: u64, full: bool) -> Result<Writer<T>> {
+        let mut file = BufWriter::new(target);
+
+        let e: [u8; 8] = unsafe { transmute(epoch.to_le()) };
+        file.write_all(MAGIC)?;
+        file.write_all(&e)?;
+        file.write_all(&[full as u8])?;
+
+        Ok(Writer {
+            epoch: epoch,
+            full: full,
+            file: file,
+            crc: Crc32::new(),
+            total: 0,
+            records: 0,
+            bytes: 0,
+        })
+    }
+
+    /// Writes an object into the snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: The object as laid out by the allocator.
+    pub fn object(&mut self, object: &[u8]) -> Result<()> {
+        self.record(RECORD_OBJECT, &[object])
+    }
+
+    /// Records the deletion of an object in the snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: The tenant the object belonged to.
+    /// * `table`:  The table the object belonged to.
+    /// * `key`:    The key of the object.
+    pub fn tombstone(&mut self, tenant: u32, table: u64, key: &[u8]) -> Result<()> {
+        let tenant: [u8; 4] = unsafe { transmute(tenant.to_le()) };
+        let table: [u8; 8] = unsafe { transmute(table.to_le()) };
+        self.record(RECORD_TOMBSTONE, &[&tenant, &table, key])
+    }
+
+    // Writes out a record whose body is the concatenation of a list of slices.
+    fn record(&mut self, kind: u8, body: &[&[u8]]) -> Result<()> {
+        let c = write_record(&mut self.file, &self.crc, kind, body)?;
+
+        self.total = self.crc.update(self.total, &c);
+        self.records += 1;
+        self.bytes += (9 + body.iter().map(|part| part.len()).sum::<usize>()) as u64;
+        Ok(())
+    }
+
+    /// Returns true if this is a full snapshot.
+    pub fn full(&self) -> bool {
+        self.full
+    }
+
+    /// Returns the number of records written out so far.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Returns the number of bytes written out so far, excluding the header.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Makes the snapshot durable on it's target, and adds it to the target's manifest.
+    ///
+    /// # Return
+    ///
+    /// The snapshot's entry on the manifest.
+    pub fn finish(self) -> Result<ManifestEntry> {
+        let entry = ManifestEntry {
+            epoch: self.epoch,
+            full: self.full,
+            records: self.records,
+            crc: self.total,
+        };
+
+        self.file.into_inner()?.commit(&entry)?;
+        Ok(entry)
+    }
+}
+
+/// Reads and verifies the records in a snapshot.
+pub struct Reader {
+    // The buffered snapshot file.
+    file: BufReader<File>,
+
+    // The manifest entry of the snapshot being read.
+    entry: ManifestEntry,
+
+    // Checksum generator.
+    crc: Crc32,
+
+    // The running checksum over all records read so far.
+    total: u32,
+
+    // The number of records read so far.
+    records: u64,
+}
+
+// Implementation of methods on Reader.
+impl Reader {
+    /// Opens a snapshot listed on a directory's manifest, and verifies it's header.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir`:   The snapshot directory.
+    /// * `entry`: The manifest entry for the snapshot.
+    pub fn new(dir: &str, entry: &ManifestEntry) -> Result<Reader> {
+        let mut file = BufReader::new(File::open(snapshot_path(dir, entry.epoch))?);
+
+        let mut header = [0u8; 17];
+        file.read_exact(&mut header)?;
+
+        let mut e: [u8; 8] = [0; 8];
+        e.copy_from_slice(&header[8..16]);
+        let epoch = u64::from_le(unsafe { transmute(e) });
+        if &header[0..8] != MAGIC || epoch != entry.epoch || (header[16] == 1) != entry.full {
+            return Err(Error::new(ErrorKind::InvalidData, "Snapshot header does not match."));
+        }
+
+        Ok(Reader {
+            file: file,
+            entry: entry.clone(),
+            crc: Crc32::new(),
+            total: 0,
+            records: 0,
+        })
+    }
+
+    /// Reads the next record from the snapshot.
+    ///
+    /// # Return
+    ///
+    /// The next record, or None once all records listed on the manifest have been read and the
+    /// snapshot's checksum has been verified. A record that fails it's checksum, or a snapshot
+    /// that is truncated, is returned as an error.
+    pub fn next(&mut self) -> Result<Option<Record>> {
+        if self.records == self.entry.records {
+            if self.total != self.entry.crc {
+                return Err(Error::new(ErrorKind::InvalidData, "Snapshot checksum mismatch."));
+            }
+            return Ok(None);
+        }
+
+        let (kind, body, c) = read_record(&mut self.file, &self.crc)?;
+        let len = body.len();
+
+        self.total = self.crc.update(self.total, &c);
+        self.records += 1;
+
+        match kind {
+            RECORD_OBJECT => Ok(Some(Record::Object(body))),
+
+            RECORD_TOMBSTONE if len >= 12 => {
+                let mut t: [u8; 4] = [0; 4];
+                let mut id: [u8; 8] = [0; 8];
+                t.copy_from_slice(&body[0..4]);
+                id.copy_from_slice(&body[4..12]);
+                Ok(Some(Record::Tombstone(
+                    u32::from_le(unsafe { transmute(t) }),
+                    u64::from_le(unsafe { transmute(id) }),
+                    body[12..].to_vec(),
+                )))
+            }
+
+            _ => Err(Error::new(ErrorKind::InvalidData, "Unknown snapshot record.")),
+        }
+    }
+}
+
+// Writes out a record whose body is the concatenation of a list of slices. Refer to `Writer` for
+// the layout of a record. Returns the record's CRC as laid out on it.
+fn write_record<W: Write>(out: &mut W, crc: &Crc32, kind: u8, body: &[&[u8]]) -> Result<[u8; 4]> {
+    let len: usize = body.iter().map(|part| part.len()).sum();
+    let sum = body.iter().fold(0, |sum, part| crc.update(sum, part));
+
+    let l: [u8; 4] = unsafe { transmute((len as u32).to_le()) };
+    let c: [u8; 4] = unsafe { transmute(sum.to_le()) };
+    out.write_all(&[kind])?;
+    out.write_all(&l)?;
+    out.write_all(&c)?;
+    for part in body.iter() {
+        out.write_all(part)?;
+    }
+
+    Ok(c)
+}
+
+// Reads a record, and verifies it's body against it's CRC. Returns the kind of the record, it's
+// body, and it's CRC as laid out on it.
+fn read_record<R: Read>(input: &mut R, crc: &Crc32) -> Result<(u8, Vec<u8>, [u8; 4])> {
+    let mut header = [0u8; 9];
+    input.read_exact(&mut header)?;
+
+    let mut l: [u8; 4] = [0; 4];
+    let mut c: [u8; 4] = [0; 4];
+    l.copy_from_slice(&header[1..5]);
+    c.copy_from_slice(&header[5..9]);
+    let len = u32::from_le(unsafe { transmute(l) }) as usize;
+
+    let mut body = vec![0u8; len];
+    input.read_exact(&mut body)?;
+    if crc.checksum(&body) != u32::from_le(unsafe { transmute(c) }) {
+        return Err(Error::new(ErrorKind::InvalidData, "Snapshot record checksum mismatch."));
+    }
+
+    Ok((header[0], body, c))
+}
+
+/// Writes out a snapshot of a single table to a file or a stream (ex: a connection to a client),
+/// so that the table can be restored later on, possibly on a different server. The snapshot
+/// consists of a header, followed by one record for every object in the table, followed by an
+/// end record. Records are laid out as in `Writer`. The header has the following layout
+/// (little-endian):
+///      _________________________________
+///     |           |          |          |
+///     |   Magic   |  Tenant  |  Table   |
+///     |___________|__________|__________|
+///        8 Bytes    4 Bytes    8 Bytes
+///
+/// The end record carries the number of records before it and a checksum over their CRCs, so
+/// that a snapshot that was cut short is detected as well.
+pub struct TableWriter<W: Write> {
+    // The file or stream the snapshot is written to.
+    out: W,
+
+    // Checksum generator.
+    crc: Crc32,
+
+    // The running checksum over all records written so far.
+    total: u32,
+
+    // The number of records written so far.
+    records: u64,
+}
+
+// Implementation of methods on TableWriter.
+impl<W: Write> TableWriter<W> {
+    /// Writes out the header of a table's snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `out`:    The file or stream to write the snapshot to. Should be buffered.
+    /// * `tenant`: The tenant owning the table.
+    /// * `table`:  The identifier of the table.
+    pub fn new(mut out: W, tenant: u32, table: u64) -> Result<TableWriter<W>> {
+        let t: [u8; 4] = unsafe { transmute(tenant.to_le()) };
+        let id: [u8; 8] = unsafe { transmute(table.to_le()) };
+        out.write_all(TABLE_MAGIC)?;
+        out.write_all(&t)?;
+        out.write_all(&id)?;
+
+        Ok(TableWriter {
+            out: out,
+            crc: Crc32::new(),
+            total: 0,
+            records: 0,
+        })
+    }
+
+    /// Writes an object into the snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: The object as laid out by the allocator.
+    pub fn object(&mut self, object: &[u8]) -> Result<()> {
+        let c = write_record(&mut self.out, &self.crc, RECORD_OBJECT, &[object])?;
+        self.total = self.crc.update(self.total, &c);
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Returns the number of objects written out so far.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Writes out the end record, and flushes the snapshot.
+    ///
+    /// # Return
+    ///
+    /// The file or stream the snapshot was written to.
+    pub fn finish(mut self) -> Result<W> {
+        let r: [u8; 8] = unsafe { transmute(self.records.to_le()) };
+        let c: [u8; 4] = unsafe { transmute(self.total.to_le()) };
+        write_record(&mut self.out, &self.crc, RECORD_END, &[&r, &c])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads and verifies the objects in a snapshot of a single table written by `TableWriter`.
+pub struct TableReader<R: Read> {
+    // The file or stream the snapshot is read from.
+    input: R,
+
+    // The tenant and table the snapshot was taken of.
+    tenant: u32,
+    table: u64,
+
+    // Checksum generator.
+    crc: Crc32,
+
+    // The running checksum over all records read so far.
+    total: u32,
+
+    // The number of records read so far.
+    records: u64,
+
+    // Set once the end record has been read and verified.
+    done: bool,
+}
+
+// Implementation of methods on TableReader.
+impl<R: Read> TableReader<R> {
+    /// Reads and verifies the header of a table's snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `input`: The file or stream to read the snapshot from. Should be buffered.
+    pub fn new(mut input: R) -> Result<TableReader<R>> {
+        let mut header = [0u8; 20];
+        input.read_exact(&mut header)?;
+        if &header[0..8] != TABLE_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a table snapshot."));
+        }
+
+        let mut t: [u8; 4] = [0; 4];
+        let mut id: [u8; 8] = [0; 8];
+        t.copy_from_slice(&header[8..12]);
+        id.copy_from_slice(&header[12..20]);
+
+        Ok(TableReader {
+            input: input,
+            tenant: u32::from_le(unsafe { transmute(t) }),
+            table: u64::from_le(unsafe { transmute(id) }),
+            crc: Crc32::new(),
+            total: 0,
+            records: 0,
+            done: false,
+        })
+    }
+
+    /// Returns the tenant and table the snapshot was taken of.
+    pub fn table(&self) -> (u32, u64) {
+        (self.tenant, self.table)
+    }
+
+    /// Reads the next object from the snapshot.
+    ///
+    /// # Return
+    ///
+    /// The next object laid out as it was by the allocator, or None once the end record has been
+    /// read and verified. A record that fails it's checksum, or a snapshot that is truncated, is
+    /// returned as an error.
+    pub fn next(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let (kind, body, c) = read_record(&mut self.input, &self.crc)?;
+        match kind {
+            RECORD_OBJECT => {
+                self.total = self.crc.update(self.total, &c);
+                self.records += 1;
+                Ok(Some(body))
+            }
+
+            RECORD_END if body.len() == 12 => {
+                let mut r: [u8; 8] = [0; 8];
+                let mut c: [u8; 4] = [0; 4];
+                r.copy_from_slice(&body[0..8]);
+                c.copy_from_slice(&body[8..12]);
+                if u64::from_le(unsafe { transmute(r) }) != self.records
+                    || u32::from_le(unsafe { transmute(c) }) != self.total
+                {
+                    return Err(Error::new(ErrorKind::InvalidData, "Snapshot checksum mismatch."));
+                }
+
+                self.done = true;
+                Ok(None)
+            }
+
+            _ => Err(Error::new(ErrorKind::InvalidData, "Unknown snapshot record.")),
+        }
+    }
+}
+
+// This module contains simple unit tests for the snapshot format.
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+
+    use super::*;
+
+    // Creates an empty snapshot directory for a test.
+    fn empty_dir(name: &str) -> String {
+        let dir = format!("/tmp/sandstorm_snapshot_{}", name);
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // This test verifies the CRC-32 implementation against the standard check value.
+    #[test]
+    fn test_crc32() {
+        let crc = Crc32::new();
+        assert_eq!(0xcbf43926, crc.checksum(b"123456789"));
+        assert_eq!(crc.checksum(b"123456789"), crc.update(crc.checksum(b"1234"), b"56789"));
+    }
+
+    // This test writes out a full and an incremental snapshot, and reads them back.
+    #[test]
+    fn test_write_read() {
+        let dir = empty_dir("rw");
+
+        let mut writer = Writer::new(&dir, 1, true).unwrap();
+        writer.object(&[1; 20]).unwrap();
+        writer.object(&[2; 30]).unwrap();
+        writer.finish().unwrap();
+
+        let mut writer = Writer::new(&dir, 2, false).unwrap();
+        writer.tombstone(7, 9, &[1; 6]).unwrap();
+        writer.finish().unwrap();
+
+        let chain = restore_chain(&read_manifest(&dir).unwrap());
+        assert_eq!(2, chain.len());
+
+        let mut reader = Reader::new(&dir, &chain[0]).unwrap();
+        assert_eq!(Some(Record::Object(vec![1; 20])), reader.next().unwrap());
+        assert_eq!(Some(Record::Object(vec![2; 30])), reader.next().unwrap());
+        assert_eq!(None, reader.next().unwrap());
+
+        let mut reader = Reader::new(&dir, &chain[1]).unwrap();
+        assert_eq!(Some(Record::Tombstone(7, 9, vec![1; 6])), reader.next().unwrap());
+        assert_eq!(None, reader.next().unwrap());
+    }
+
+    // This test verifies that a corrupted record is detected.
+    #[test]
+    fn test_corrupt_record() {
+        let dir = empty_dir("corrupt");
+
+        let mut writer = Writer::new(&dir, 1, true).unwrap();
+        writer.object(&[1; 20]).unwrap();
+        let entry = writer.finish().unwrap();
+
+        // Flip a byte inside the body of the first record.
+        {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .open(snapshot_path(&dir, 1))
+                .unwrap();
+            file.seek(SeekFrom::Start(17 + 9 + 5)).unwrap();
+            file.write_all(&[0xff]).unwrap();
+        }
+
+        let mut reader = Reader::new(&dir, &entry).unwrap();
+        assert!(reader.next().is_err());
+    }
+
+    // This test verifies that restores start at the most recent full snapshot.
+    #[test]
+    fn test_restore_chain() {
+        let entry = |epoch, full| ManifestEntry {
+            epoch: epoch,
+            full: full,
+            records: 0,
+            crc: 0,
+        };
+
+        let entries = vec![entry(1, true), entry(2, false), entry(3, true), entry(4, false)];
+        let chain: Vec<u64> = restore_chain(&entries).iter().map(|e| e.epoch).collect();
+        assert_eq!(vec![3, 4], chain);
+
+        assert!(restore_chain(&[entry(1, false)]).is_empty());
+    }
+
+    // This test writes out a snapshot of a table into a buffer, and reads it back.
+    #[test]
+    fn test_table_write_read() {
+        let mut writer = TableWriter::new(Vec::new(), 7, 9).unwrap();
+        writer.object(&[1; 20]).unwrap();
+        writer.object(&[2; 30]).unwrap();
+        assert_eq!(2, writer.records());
+        let buf = writer.finish().unwrap();
+
+        let mut reader = TableReader::new(&buf[..]).unwrap();
+        assert_eq!((7, 9), reader.table());
+        assert_eq!(Some(vec![1; 20]), reader.next().unwrap());
+        assert_eq!(Some(vec![2; 30]), reader.next().unwrap());
+        assert_eq!(None, reader.next().unwrap());
+        assert_eq!(None, reader.next().unwrap());
+    }
+
+    // This test verifies that a table's snapshot that was cut short or corrupted is detected.
+    #[test]
+    fn test_table_truncated() {
+        let mut writer = TableWriter::new(Vec::new(), 7, 9).unwrap();
+        writer.object(&[1; 20]).unwrap();
+        let mut buf = writer.finish().unwrap();
+
+        // Cut off the end record.
+        {
+            let len = buf.len();
+            let mut reader = TableReader::new(&buf[..len - 21]).unwrap();
+            assert_eq!(Some(vec![1; 20]), reader.next().unwrap());
+            assert!(reader.next().is_err());
+        }
+
+        // Flip a byte inside the body of the first record.
+        buf[20 + 9 + 5] ^= 0xff;
+        let mut reader = TableReader::new(&buf[..]).unwrap();
+        assert!(reader.next().is_err());
+
+        assert!(TableReader::new(&[0u8; 20][..]).is_err());
+    }
+}
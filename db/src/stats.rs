@@ -0,0 +1,937 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::mem::transmute;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::cycles;
+use super::wireformat::OpCode;
+
+use spin::Mutex;
+
+// Identifies a stats datagram. "SPST" when read as bytes.
+const MAGIC: u32 = 0x54535053;
+
+// The version of the datagram's format.
+const VERSION: u8 = 3;
+
+// Set on the datagram's flags when the server is in read-only mode.
+const FLAG_READ_ONLY: u8 = 0x01;
+
+/// The length of the header on a stats datagram: magic(4) | version(1) | flags(1) | count(2) |
+/// seq(4) | uptime_ms(8).
+pub const STATS_HEADER_LEN: usize = 20;
+
+/// The length of the stats of a single scheduler on a datagram: core(4) | pending(4) |
+/// parked(4) | completed(8) | busy(8) | dispatch_busy(8) | dispatch_idle(8).
+pub const SCHED_STATS_LEN: usize = 44;
+
+/// The length of the read amplification stats of a single request type on a datagram:
+/// class(4) | requests(8) | objects(8) | heap_bytes(8) | resp_bytes(8). These follow the stats
+/// of every scheduler, and are preceded by a count(2).
+pub const READ_STATS_LEN: usize = 36;
+
+/// The request types whose data movement is accounted for by `ReadAmp`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadClass {
+    /// get() requests.
+    GET = 0,
+
+    /// multiget() requests, over a single or multiple tables.
+    MULTIGET = 1,
+
+    /// invoke() requests that ran an extension.
+    INVOKE = 2,
+}
+
+/// The number of variants of ReadClass.
+const READ_CLASSES: usize = 3;
+
+// Implementation of methods on ReadClass.
+impl ReadClass {
+    // Returns the class encoded as `class` on a datagram, if there is one.
+    fn from_u32(class: u32) -> Option<ReadClass> {
+        match class {
+            0 => Some(ReadClass::GET),
+            1 => Some(ReadClass::MULTIGET),
+            2 => Some(ReadClass::INVOKE),
+            _ => None,
+        }
+    }
+}
+
+/// Data moved by requests of a single type. Counters are cumulative since the server started,
+/// so collectors should difference successive reports to get rates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadStats {
+    /// The type of request these stats are for.
+    pub class: ReadClass,
+
+    /// The number of requests of this type that completed.
+    pub requests: u64,
+
+    /// The number of objects the requests looked up and read off the heap.
+    pub objects: u64,
+
+    /// The number of value bytes of those objects.
+    pub heap_bytes: u64,
+
+    /// The number of payload bytes on the responses to the requests.
+    pub resp_bytes: u64,
+}
+
+// Implementation of methods on ReadStats.
+impl ReadStats {
+    /// Returns the read amplification between an earlier report and this one, i.e, the number of
+    /// bytes read off the heap for every byte returned to clients. An extension with an
+    /// amplification of ten saved clients from having to fetch ten times the data it returned.
+    /// Zero if nothing was returned to clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `prev`: The stats of the same request type from an earlier report.
+    pub fn amplification(&self, prev: &ReadStats) -> f64 {
+        let heap = self.heap_bytes.saturating_sub(prev.heap_bytes);
+        let resp = self.resp_bytes.saturating_sub(prev.resp_bytes);
+        if resp == 0 {
+            return 0.0;
+        }
+
+        heap as f64 / resp as f64
+    }
+}
+
+// Counters of data moved by a single type of request.
+struct ReadCounters {
+    requests: AtomicUsize,
+    objects: AtomicUsize,
+    heap_bytes: AtomicUsize,
+    resp_bytes: AtomicUsize,
+}
+
+// Implementation of methods on ReadCounters.
+impl ReadCounters {
+    // Returns counters that are all zero.
+    fn new() -> ReadCounters {
+        ReadCounters {
+            requests: AtomicUsize::new(0),
+            objects: AtomicUsize::new(0),
+            heap_bytes: AtomicUsize::new(0),
+            resp_bytes: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Counts the objects touched, the bytes read off the heap, and the bytes returned to clients by
+/// every type of request, so that the data movement saved by pushing computation to the server
+/// can be quantified. Shared by all cores.
+pub struct ReadAmp {
+    // Counters of every ReadClass, indexed by the class.
+    classes: [ReadCounters; READ_CLASSES],
+}
+
+// Implementation of methods on ReadAmp.
+impl ReadAmp {
+    /// Creates a set of counters that are all zero.
+    pub fn new() -> ReadAmp {
+        ReadAmp {
+            classes: [ReadCounters::new(), ReadCounters::new(), ReadCounters::new()],
+        }
+    }
+
+    /// Accounts for a request that completed.
+    ///
+    /// # Arguments
+    ///
+    /// * `class`:      The type of the request.
+    /// * `objects`:    The number of objects the request read off the heap.
+    /// * `heap_bytes`: The number of value bytes of those objects.
+    /// * `resp_bytes`: The number of payload bytes on the request's response.
+    pub fn record(&self, class: ReadClass, objects: usize, heap_bytes: usize, resp_bytes: usize) {
+        let counters = &self.classes[class as usize];
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        counters.objects.fetch_add(objects, Ordering::Relaxed);
+        counters.heap_bytes.fetch_add(heap_bytes, Ordering::Relaxed);
+        counters.resp_bytes.fetch_add(resp_bytes, Ordering::Relaxed);
+    }
+
+    /// Returns a snapshot of the counters of every request type.
+    pub fn stats(&self) -> Vec<ReadStats> {
+        vec![ReadClass::GET, ReadClass::MULTIGET, ReadClass::INVOKE]
+            .into_iter()
+            .map(|class| {
+                let counters = &self.classes[class as usize];
+                ReadStats {
+                    class: class,
+                    requests: counters.requests.load(Ordering::Relaxed) as u64,
+                    objects: counters.objects.load(Ordering::Relaxed) as u64,
+                    heap_bytes: counters.heap_bytes.load(Ordering::Relaxed) as u64,
+                    resp_bytes: counters.resp_bytes.load(Ordering::Relaxed) as u64,
+                }
+            }).collect()
+    }
+}
+
+/// Metrics of a single scheduler. Counters are cumulative since the scheduler was created, so
+/// collectors should difference successive reports to get rates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchedStats {
+    /// The core the scheduler runs on.
+    pub core: i32,
+
+    /// The number of tasks waiting on the scheduler's run-queues.
+    pub pending: u32,
+
+    /// The number of tasks parked on the scheduler's timer wheel.
+    pub parked: u32,
+
+    /// The number of request tasks that completed on the scheduler.
+    pub completed: u64,
+
+    /// The total time in cycles that request tasks ran for on the scheduler.
+    pub busy: u64,
+
+    /// The total time in cycles that the dispatcher on the scheduler's core spent on polls that
+    /// received requests or sent out responses.
+    pub dispatch_busy: u64,
+
+    /// The total time in cycles that the dispatcher on the scheduler's core spent on polls that
+    /// found nothing to do.
+    pub dispatch_idle: u64,
+}
+
+// Implementation of methods on SchedStats.
+impl SchedStats {
+    /// Returns the percentage of time the dispatcher was busy between an earlier report and this
+    /// one. A dispatcher close to 100% is the bottleneck, whereas one that is mostly idle while
+    /// requests queue up on the scheduler points at request processing instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `prev`: The stats of the same scheduler from an earlier report.
+    pub fn dispatch_utilization(&self, prev: &SchedStats) -> f64 {
+        utilization(
+            self.dispatch_busy.saturating_sub(prev.dispatch_busy),
+            self.dispatch_idle.saturating_sub(prev.dispatch_idle),
+        )
+    }
+}
+
+/// Returns the percentage of time spent busy, given the time spent busy and idle. Zero if no time
+/// was spent at all.
+pub fn utilization(busy: u64, idle: u64) -> f64 {
+    let total = busy + idle;
+    if total == 0 {
+        return 0.0;
+    }
+
+    (busy as f64 * 100.0) / total as f64
+}
+
+/// A report of a server's key metrics, pushed to a collector in a single UDP datagram.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsReport {
+    /// The sequence number of the report. Gaps indicate dropped datagrams.
+    pub seq: u32,
+
+    /// The time in milliseconds since the server started pushing stats.
+    pub uptime_ms: u64,
+
+    /// True if the server is in read-only mode.
+    pub read_only: bool,
+
+    /// Metrics of every scheduler on the server.
+    pub scheds: Vec<SchedStats>,
+
+    /// Data moved by every type of request on the server.
+    pub reads: Vec<ReadStats>,
+}
+
+// Implementation of methods on StatsReport.
+impl StatsReport {
+    /// Encodes the report into a datagram. All fields are little-endian.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(
+            STATS_HEADER_LEN + self.scheds.len() * SCHED_STATS_LEN + 2
+                + self.reads.len() * READ_STATS_LEN,
+        );
+
+        let magic: [u8; 4] = unsafe { transmute(MAGIC.to_le()) };
+        let count: [u8; 2] = unsafe { transmute((self.scheds.len() as u16).to_le()) };
+        let seq: [u8; 4] = unsafe { transmute(self.seq.to_le()) };
+        let uptime: [u8; 8] = unsafe { transmute(self.uptime_ms.to_le()) };
+
+        buf.extend_from_slice(&magic);
+        buf.push(VERSION);
+        buf.push(if self.read_only { FLAG_READ_ONLY } else { 0 });
+        buf.extend_from_slice(&count);
+        buf.extend_from_slice(&seq);
+        buf.extend_from_slice(&uptime);
+
+        for sched in self.scheds.iter() {
+            let core: [u8; 4] = unsafe { transmute(sched.core.to_le()) };
+            let pending: [u8; 4] = unsafe { transmute(sched.pending.to_le()) };
+            let parked: [u8; 4] = unsafe { transmute(sched.parked.to_le()) };
+            let completed: [u8; 8] = unsafe { transmute(sched.completed.to_le()) };
+            let busy: [u8; 8] = unsafe { transmute(sched.busy.to_le()) };
+            let dbusy: [u8; 8] = unsafe { transmute(sched.dispatch_busy.to_le()) };
+            let didle: [u8; 8] = unsafe { transmute(sched.dispatch_idle.to_le()) };
+
+            buf.extend_from_slice(&core);
+            buf.extend_from_slice(&pending);
+            buf.extend_from_slice(&parked);
+            buf.extend_from_slice(&completed);
+            buf.extend_from_slice(&busy);
+            buf.extend_from_slice(&dbusy);
+            buf.extend_from_slice(&didle);
+        }
+
+        let count: [u8; 2] = unsafe { transmute((self.reads.len() as u16).to_le()) };
+        buf.extend_from_slice(&count);
+
+        for read in self.reads.iter() {
+            let class: [u8; 4] = unsafe { transmute((read.class as u32).to_le()) };
+            let requests: [u8; 8] = unsafe { transmute(read.requests.to_le()) };
+            let objects: [u8; 8] = unsafe { transmute(read.objects.to_le()) };
+            let heap: [u8; 8] = unsafe { transmute(read.heap_bytes.to_le()) };
+            let resp: [u8; 8] = unsafe { transmute(read.resp_bytes.to_le()) };
+
+            buf.extend_from_slice(&class);
+            buf.extend_from_slice(&requests);
+            buf.extend_from_slice(&objects);
+            buf.extend_from_slice(&heap);
+            buf.extend_from_slice(&resp);
+        }
+
+        buf
+    }
+
+    /// Decodes a datagram into a report. Meant for collectors.
+    ///
+    /// # Return
+    ///
+    /// The report, or None if the datagram is malformed or of an unknown version.
+    pub fn decode(buf: &[u8]) -> Option<StatsReport> {
+        if buf.len() < STATS_HEADER_LEN || read_u32(&buf[0..4]) != MAGIC || buf[4] != VERSION {
+            return None;
+        }
+
+        let mut c: [u8; 2] = [0; 2];
+        c.copy_from_slice(&buf[6..8]);
+        let count = u16::from_le(unsafe { transmute(c) }) as usize;
+        let reads_off = STATS_HEADER_LEN + count * SCHED_STATS_LEN;
+        if buf.len() < reads_off + 2 {
+            return None;
+        }
+
+        c.copy_from_slice(&buf[reads_off..reads_off + 2]);
+        let n_reads = u16::from_le(unsafe { transmute(c) }) as usize;
+        if buf.len() != reads_off + 2 + n_reads * READ_STATS_LEN {
+            return None;
+        }
+
+        let mut reads = Vec::with_capacity(n_reads);
+        for r in buf[reads_off + 2..].chunks(READ_STATS_LEN) {
+            reads.push(ReadStats {
+                class: ReadClass::from_u32(read_u32(&r[0..4]))?,
+                requests: read_u64(&r[4..12]),
+                objects: read_u64(&r[12..20]),
+                heap_bytes: read_u64(&r[20..28]),
+                resp_bytes: read_u64(&r[28..36]),
+            });
+        }
+
+        let scheds = buf[STATS_HEADER_LEN..reads_off]
+            .chunks(SCHED_STATS_LEN)
+            .map(|s| SchedStats {
+                core: read_u32(&s[0..4]) as i32,
+                pending: read_u32(&s[4..8]),
+                parked: read_u32(&s[8..12]),
+                completed: read_u64(&s[12..20]),
+                busy: read_u64(&s[20..28]),
+                dispatch_busy: read_u64(&s[28..36]),
+                dispatch_idle: read_u64(&s[36..44]),
+            }).collect();
+
+        Some(StatsReport {
+            seq: read_u32(&buf[8..12]),
+            uptime_ms: read_u64(&buf[12..20]),
+            read_only: buf[5] & FLAG_READ_ONLY != 0,
+            scheds: scheds,
+            reads: reads,
+        })
+    }
+}
+
+/// Periodically pushes reports to a collector, so that an experiment's controller can gather
+/// time-series from many servers without having to poll each one of them.
+pub struct StatsPusher {
+    // The socket reports are sent out from.
+    socket: UdpSocket,
+
+    // The address of the collector.
+    collector: SocketAddr,
+
+    // The sequence number of the next report.
+    seq: u32,
+
+    // The time stamp in cycles at which the pusher was created.
+    start: u64,
+}
+
+// Implementation of methods on StatsPusher.
+impl StatsPusher {
+    /// Creates a pusher.
+    ///
+    /// # Arguments
+    ///
+    /// * `collector`: The address of the collector, ex: "10.0.0.1:9000".
+    pub fn new(collector: &str) -> Result<StatsPusher> {
+        let collector = collector
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Bad collector address."))?;
+
+        Ok(StatsPusher {
+            socket: UdpSocket::bind("0.0.0.0:0")?,
+            collector: collector,
+            seq: 0,
+            start: cycles::rdtsc(),
+        })
+    }
+
+    /// Sends a report to the collector.
+    ///
+    /// # Arguments
+    ///
+    /// * `read_only`: True if the server is in read-only mode.
+    /// * `scheds`:    Metrics of every scheduler on the server.
+    /// * `reads`:     Data moved by every type of request on the server.
+    pub fn push(
+        &mut self,
+        read_only: bool,
+        scheds: Vec<SchedStats>,
+        reads: Vec<ReadStats>,
+    ) -> Result<()> {
+        let report = StatsReport {
+            seq: self.seq,
+            uptime_ms: (cycles::to_seconds(cycles::rdtsc() - self.start) * 1e3) as u64,
+            read_only: read_only,
+            scheds: scheds,
+            reads: reads,
+        };
+
+        self.seq = self.seq.wrapping_add(1);
+        self.socket.send_to(&report.encode(), &self.collector)?;
+        Ok(())
+    }
+}
+
+/// The maximum number of tenants whose requests are counted individually on a single core.
+/// Requests from tenants seen after these are counted together. Refer to `CoreCounters`.
+pub const MAX_COUNTED_TENANTS: usize = 1024;
+
+/// The length of the header on encoded server statistics: version(1) | cores(2) | opcodes(2) |
+/// tenants(2) | other_tenants(8) | heap_allocated(8) | hot_bytes(8) | segment_used(8) |
+/// segment_size(8).
+pub const STATISTICS_HEADER_LEN: usize = 47;
+
+/// The length of the statistics of a single core: core(4) | rx_packets(8) | tx_packets(8) |
+/// pending(4) | parked(4) | completed(8). These follow the header.
+pub const CORE_STATISTICS_LEN: usize = 36;
+
+/// The length of the request count of a single opcode: opcode(1) | requests(8). These follow
+/// the statistics of every core.
+pub const OPCODE_COUNT_LEN: usize = 9;
+
+/// The length of the request count of a single tenant: tenant(4) | requests(8). These follow
+/// the request count of every opcode.
+pub const TENANT_COUNT_LEN: usize = 12;
+
+// The version of the format server statistics are encoded in.
+const STATISTICS_VERSION: u8 = 1;
+
+// The number of opcodes requests are counted by. Opcodes are used directly as indices.
+const NUM_OPCODES: usize = OpCode::InvalidOperation as usize + 1;
+
+/// Counters of the packets and requests handled by a single core's dispatcher. Only ever bumped
+/// by that dispatcher, and summed across cores when a client asks for the server's statistics
+/// (refer to `ServerStatistics::collect()`), so they cost little more than an add on the hot path.
+pub struct CoreCounters {
+    // The number of packets received and sent out by the dispatcher.
+    rx_packets: AtomicUsize,
+    tx_packets: AtomicUsize,
+
+    // The number of requests dispatched with every opcode, indexed by the opcode.
+    opcodes: Vec<AtomicUsize>,
+
+    // The number of requests dispatched from each of the first `MAX_COUNTED_TENANTS` tenants
+    // seen, and from every tenant after those.
+    tenants: Mutex<HashMap<u32, u64>>,
+    other_tenants: AtomicUsize,
+}
+
+// Implementation of methods on CoreCounters.
+impl CoreCounters {
+    /// Creates a set of counters that are all zero.
+    pub fn new() -> CoreCounters {
+        CoreCounters {
+            rx_packets: AtomicUsize::new(0),
+            tx_packets: AtomicUsize::new(0),
+            opcodes: (0..NUM_OPCODES).map(|_| AtomicUsize::new(0)).collect(),
+            tenants: Mutex::new(HashMap::new()),
+            other_tenants: AtomicUsize::new(0),
+        }
+    }
+
+    /// Accounts for packets received and sent out by the dispatcher.
+    ///
+    /// # Arguments
+    ///
+    /// * `rx`: The number of packets received.
+    /// * `tx`: The number of packets sent out.
+    #[inline]
+    pub fn record_packets(&self, rx: usize, tx: usize) {
+        if rx > 0 {
+            self.rx_packets.fetch_add(rx, Ordering::Relaxed);
+        }
+
+        if tx > 0 {
+            self.tx_packets.fetch_add(tx, Ordering::Relaxed);
+        }
+    }
+
+    /// Accounts for a request handed to Master.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode`: The opcode on the request, as encoded on the wire.
+    /// * `tenant`: The tenant that issued the request.
+    #[inline]
+    pub fn record_request(&self, opcode: u8, tenant: u32) {
+        if let Some(count) = self.opcodes.get(opcode as usize) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut tenants = self.tenants.lock();
+        if let Some(count) = tenants.get_mut(&tenant) {
+            *count += 1;
+            return;
+        }
+
+        if tenants.len() < MAX_COUNTED_TENANTS {
+            tenants.insert(tenant, 1);
+        } else {
+            self.other_tenants.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the number of packets received and sent out by the dispatcher, in that order.
+    pub fn packets(&self) -> (u64, u64) {
+        (
+            self.rx_packets.load(Ordering::Relaxed) as u64,
+            self.tx_packets.load(Ordering::Relaxed) as u64,
+        )
+    }
+}
+
+/// Statistics of a single core.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoreStatistics {
+    /// The core the statistics are for.
+    pub core: i32,
+
+    /// The number of packets received by the core's dispatcher.
+    pub rx_packets: u64,
+
+    /// The number of packets sent out by the core's dispatcher.
+    pub tx_packets: u64,
+
+    /// The number of tasks waiting on the core's run-queues.
+    pub pending: u32,
+
+    /// The number of tasks parked on the core's timer wheel.
+    pub parked: u32,
+
+    /// The number of request tasks that completed on the core.
+    pub completed: u64,
+}
+
+/// Counters collected across a server's cores, returned to clients by the get_statistics() RPC.
+/// Counters are cumulative since the server started, so clients should difference successive
+/// responses to get rates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerStatistics {
+    /// The statistics of every core.
+    pub cores: Vec<CoreStatistics>,
+
+    /// The number of requests dispatched with every opcode that was seen, ordered by opcode.
+    pub opcodes: Vec<(u8, u64)>,
+
+    /// The number of requests dispatched from the busiest tenants, busiest first.
+    pub tenants: Vec<(u32, u64)>,
+
+    /// The number of requests dispatched from tenants that are not on `tenants`.
+    pub other_tenants: u64,
+
+    /// The total number of bytes allocated for objects. Refer to `Allocator::allocated()`.
+    pub heap_allocated: u64,
+
+    /// The number of bytes occupied by objects promoted into hot arenas.
+    pub hot_bytes: u64,
+
+    /// The number of bytes in use in the persistent heap segment. Zero if the heap is not backed
+    /// by one.
+    pub segment_used: u64,
+
+    /// The size of the persistent heap segment in bytes. Zero if the heap is not backed by one.
+    pub segment_size: u64,
+}
+
+// Implementation of methods on ServerStatistics.
+impl ServerStatistics {
+    /// Sums the request counters of every core. The heap's statistics are left zeroed.
+    ///
+    /// # Arguments
+    ///
+    /// * `cores`:       The statistics of every core.
+    /// * `counters`:    The counters of every core.
+    /// * `max_tenants`: The number of tenants whose counts are returned individually. Counts of
+    ///                  the others are added to `other_tenants`.
+    pub fn collect(
+        cores: Vec<CoreStatistics>,
+        counters: &[&CoreCounters],
+        max_tenants: usize,
+    ) -> ServerStatistics {
+        let mut opcodes = vec![0; NUM_OPCODES];
+        let mut tenants: HashMap<u32, u64> = HashMap::new();
+        let mut other_tenants = 0;
+
+        for counter in counters.iter() {
+            for (sum, count) in opcodes.iter_mut().zip(counter.opcodes.iter()) {
+                *sum += count.load(Ordering::Relaxed) as u64;
+            }
+
+            for (tenant, count) in counter.tenants.lock().iter() {
+                *tenants.entry(*tenant).or_insert(0) += *count;
+            }
+            other_tenants += counter.other_tenants.load(Ordering::Relaxed) as u64;
+        }
+
+        let mut tenants: Vec<(u32, u64)> = tenants.into_iter().collect();
+        tenants.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for &(_, count) in tenants.iter().skip(max_tenants) {
+            other_tenants += count;
+        }
+        tenants.truncate(max_tenants);
+
+        ServerStatistics {
+            cores: cores,
+            opcodes: opcodes
+                .into_iter()
+                .enumerate()
+                .filter(|&(_, count)| count > 0)
+                .map(|(opcode, count)| (opcode as u8, count))
+                .collect(),
+            tenants: tenants,
+            other_tenants: other_tenants,
+            ..Default::default()
+        }
+    }
+
+    /// Encodes the statistics into a byte vector. All fields are little-endian.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(
+            STATISTICS_HEADER_LEN + self.cores.len() * CORE_STATISTICS_LEN
+                + self.opcodes.len() * OPCODE_COUNT_LEN
+                + self.tenants.len() * TENANT_COUNT_LEN,
+        );
+
+        let cores: [u8; 2] = unsafe { transmute((self.cores.len() as u16).to_le()) };
+        let opcodes: [u8; 2] = unsafe { transmute((self.opcodes.len() as u16).to_le()) };
+        let tenants: [u8; 2] = unsafe { transmute((self.tenants.len() as u16).to_le()) };
+        let other: [u8; 8] = unsafe { transmute(self.other_tenants.to_le()) };
+        let allocated: [u8; 8] = unsafe { transmute(self.heap_allocated.to_le()) };
+        let hot: [u8; 8] = unsafe { transmute(self.hot_bytes.to_le()) };
+        let used: [u8; 8] = unsafe { transmute(self.segment_used.to_le()) };
+        let size: [u8; 8] = unsafe { transmute(self.segment_size.to_le()) };
+
+        buf.push(STATISTICS_VERSION);
+        buf.extend_from_slice(&cores);
+        buf.extend_from_slice(&opcodes);
+        buf.extend_from_slice(&tenants);
+        buf.extend_from_slice(&other);
+        buf.extend_from_slice(&allocated);
+        buf.extend_from_slice(&hot);
+        buf.extend_from_slice(&used);
+        buf.extend_from_slice(&size);
+
+        for core in self.cores.iter() {
+            let id: [u8; 4] = unsafe { transmute(core.core.to_le()) };
+            let rx: [u8; 8] = unsafe { transmute(core.rx_packets.to_le()) };
+            let tx: [u8; 8] = unsafe { transmute(core.tx_packets.to_le()) };
+            let pending: [u8; 4] = unsafe { transmute(core.pending.to_le()) };
+            let parked: [u8; 4] = unsafe { transmute(core.parked.to_le()) };
+            let completed: [u8; 8] = unsafe { transmute(core.completed.to_le()) };
+
+            buf.extend_from_slice(&id);
+            buf.extend_from_slice(&rx);
+            buf.extend_from_slice(&tx);
+            buf.extend_from_slice(&pending);
+            buf.extend_from_slice(&parked);
+            buf.extend_from_slice(&completed);
+        }
+
+        for &(opcode, requests) in self.opcodes.iter() {
+            let requests: [u8; 8] = unsafe { transmute(requests.to_le()) };
+            buf.push(opcode);
+            buf.extend_from_slice(&requests);
+        }
+
+        for &(tenant, requests) in self.tenants.iter() {
+            let tenant: [u8; 4] = unsafe { transmute(tenant.to_le()) };
+            let requests: [u8; 8] = unsafe { transmute(requests.to_le()) };
+            buf.extend_from_slice(&tenant);
+            buf.extend_from_slice(&requests);
+        }
+
+        buf
+    }
+
+    /// Decodes statistics encoded by `encode()`. Meant for clients.
+    ///
+    /// # Return
+    ///
+    /// The statistics, or None if the buffer is malformed or of an unknown version.
+    pub fn decode(buf: &[u8]) -> Option<ServerStatistics> {
+        if buf.len() < STATISTICS_HEADER_LEN || buf[0] != STATISTICS_VERSION {
+            return None;
+        }
+
+        let n_cores = read_u16(&buf[1..3]) as usize;
+        let n_opcodes = read_u16(&buf[3..5]) as usize;
+        let n_tenants = read_u16(&buf[5..7]) as usize;
+
+        let opcodes_off = STATISTICS_HEADER_LEN + n_cores * CORE_STATISTICS_LEN;
+        let tenants_off = opcodes_off + n_opcodes * OPCODE_COUNT_LEN;
+        if buf.len() != tenants_off + n_tenants * TENANT_COUNT_LEN {
+            return None;
+        }
+
+        let cores = buf[STATISTICS_HEADER_LEN..opcodes_off]
+            .chunks(CORE_STATISTICS_LEN)
+            .map(|c| CoreStatistics {
+                core: read_u32(&c[0..4]) as i32,
+                rx_packets: read_u64(&c[4..12]),
+                tx_packets: read_u64(&c[12..20]),
+                pending: read_u32(&c[20..24]),
+                parked: read_u32(&c[24..28]),
+                completed: read_u64(&c[28..36]),
+            }).collect();
+
+        let opcodes = buf[opcodes_off..tenants_off]
+            .chunks(OPCODE_COUNT_LEN)
+            .map(|o| (o[0], read_u64(&o[1..9])))
+            .collect();
+
+        let tenants = buf[tenants_off..]
+            .chunks(TENANT_COUNT_LEN)
+            .map(|t| (read_u32(&t[0..4]), read_u64(&t[4..12])))
+            .collect();
+
+        Some(ServerStatistics {
+            cores: cores,
+            opcodes: opcodes,
+            tenants: tenants,
+            other_tenants: read_u64(&buf[7..15]),
+            heap_allocated: read_u64(&buf[15..23]),
+            hot_bytes: read_u64(&buf[23..31]),
+            segment_used: read_u64(&buf[31..39]),
+            segment_size: read_u64(&buf[39..47]),
+        })
+    }
+}
+
+// Reads a little-endian u16 out of a two byte slice.
+fn read_u16(buf: &[u8]) -> u16 {
+    let mut b: [u8; 2] = [0; 2];
+    b.copy_from_slice(buf);
+    u16::from_le(unsafe { transmute(b) })
+}
+
+// Reads a little-endian u32 out of a four byte slice.
+fn read_u32(buf: &[u8]) -> u32 {
+    let mut b: [u8; 4] = [0; 4];
+    b.copy_from_slice(buf);
+    u32::from_le(unsafe { transmute(b) })
+}
+
+// Reads a little-endian u64 out of an eight byte slice.
+fn read_u64(buf: &[u8]) -> u64 {
+    let mut b: [u8; 8] = [0; 8];
+    b.copy_from_slice(buf);
+    u64::from_le(unsafe { transmute(b) })
+}
+
+// This module contains simple unit tests for stats datagrams.
+#[cfg(test)]
+mod tests {
+    use super::{utilization, ReadAmp, ReadClass, ReadStats, SchedStats, StatsReport};
+    use super::{CoreCounters, CoreStatistics, ServerStatistics, MAX_COUNTED_TENANTS};
+    use super::{CORE_STATISTICS_LEN, OPCODE_COUNT_LEN, STATISTICS_HEADER_LEN, TENANT_COUNT_LEN};
+    use super::{READ_STATS_LEN, SCHED_STATS_LEN, STATS_HEADER_LEN};
+
+    // This test verifies that reports decode back to themselves, and that truncated datagrams
+    // are rejected.
+    #[test]
+    fn test_stats_roundtrip() {
+        let report = StatsReport {
+            seq: 42,
+            uptime_ms: 1234567,
+            read_only: true,
+            scheds: vec![
+                SchedStats {
+                    core: 1,
+                    pending: 3,
+                    parked: 0,
+                    completed: 1 << 40,
+                    busy: 99,
+                    dispatch_busy: 300,
+                    dispatch_idle: 700,
+                },
+                SchedStats {
+                    core: 2,
+                    pending: 0,
+                    parked: 7,
+                    completed: 5,
+                    busy: 1 << 50,
+                    dispatch_busy: 1 << 45,
+                    dispatch_idle: 0,
+                },
+            ],
+            reads: ReadAmp::new().stats(),
+        };
+
+        let buf = report.encode();
+        assert_eq!(STATS_HEADER_LEN + 2 * SCHED_STATS_LEN + 2 + 3 * READ_STATS_LEN, buf.len());
+        assert_eq!(&b"SPST"[..], &buf[0..4]);
+        assert_eq!(Some(report), StatsReport::decode(&buf));
+        assert_eq!(None, StatsReport::decode(&buf[..buf.len() - 1]));
+        assert_eq!(None, StatsReport::decode(&buf[1..]));
+    }
+
+    // This test verifies that dispatcher utilization is computed over the interval between two
+    // reports.
+    #[test]
+    fn test_dispatch_utilization() {
+        let prev = SchedStats {
+            core: 1,
+            pending: 0,
+            parked: 0,
+            completed: 0,
+            busy: 0,
+            dispatch_busy: 100,
+            dispatch_idle: 900,
+        };
+
+        let mut next = prev.clone();
+        next.dispatch_busy += 750;
+        next.dispatch_idle += 250;
+
+        assert_eq!(75.0, next.dispatch_utilization(&prev));
+        assert_eq!(0.0, prev.dispatch_utilization(&prev));
+        assert_eq!(10.0, utilization(100, 900));
+    }
+
+    // This test verifies that read amplification is accounted for per request type, and is
+    // computed over the interval between two reports.
+    #[test]
+    fn test_read_amplification() {
+        let amp = ReadAmp::new();
+        amp.record(ReadClass::GET, 1, 100, 100);
+        amp.record(ReadClass::INVOKE, 10, 1000, 8);
+        let prev = amp.stats();
+
+        amp.record(ReadClass::INVOKE, 20, 2000, 10);
+        amp.record(ReadClass::INVOKE, 0, 0, 10);
+        let next = amp.stats();
+
+        assert_eq!(
+            ReadStats {
+                class: ReadClass::INVOKE,
+                requests: 3,
+                objects: 30,
+                heap_bytes: 3000,
+                resp_bytes: 28,
+            },
+            next[2]
+        );
+        assert_eq!(100.0, next[2].amplification(&prev[2]));
+        assert_eq!(1.0, next[0].amplification(&ReadAmp::new().stats()[0]));
+        assert_eq!(0.0, next[1].amplification(&prev[1]));
+    }
+
+    // This test verifies that request counters are summed across cores, that only the busiest
+    // tenants are returned individually, and that the result decodes back to itself.
+    #[test]
+    fn test_server_statistics() {
+        let a = CoreCounters::new();
+        let b = CoreCounters::new();
+        a.record_packets(10, 8);
+        b.record_packets(0, 3);
+
+        a.record_request(1, 7);
+        a.record_request(1, 7);
+        b.record_request(2, 7);
+        b.record_request(1, 9);
+        b.record_request(0xff, 5);
+
+        // Tenants beyond the ones counted individually on a core are counted together.
+        for tenant in 0..MAX_COUNTED_TENANTS as u32 {
+            a.record_request(3, 100 + tenant);
+        }
+        a.record_request(3, 7);
+
+        let cores = vec![CoreStatistics {
+            core: 1,
+            rx_packets: a.packets().0,
+            tx_packets: a.packets().1,
+            pending: 2,
+            parked: 0,
+            completed: 1 << 40,
+        }];
+        let mut stats = ServerStatistics::collect(cores, &[&a, &b], 2);
+        stats.heap_allocated = 1 << 33;
+        stats.segment_size = 4096;
+
+        assert_eq!((10, 8), a.packets());
+        assert_eq!(vec![(1, 3), (2, 1), (3, MAX_COUNTED_TENANTS as u64 + 1)], stats.opcodes);
+        assert_eq!(vec![(7, 4), (5, 1)], stats.tenants);
+        assert_eq!(MAX_COUNTED_TENANTS as u64 + 1, stats.other_tenants);
+
+        let buf = stats.encode();
+        assert_eq!(
+            STATISTICS_HEADER_LEN + CORE_STATISTICS_LEN + 3 * OPCODE_COUNT_LEN
+                + 2 * TENANT_COUNT_LEN,
+            buf.len()
+        );
+        assert_eq!(Some(stats), ServerStatistics::decode(&buf));
+        assert_eq!(None, ServerStatistics::decode(&buf[..buf.len() - 1]));
+        assert_eq!(None, ServerStatistics::decode(&buf[1..]));
+    }
+}
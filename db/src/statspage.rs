@@ -0,0 +1,312 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::fs::OpenOptions;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use libc;
+
+// Identifies a file as a stats page.
+const MAGIC: &[u8; 8] = b"SPLSTAT1";
+
+// The version of the page's layout.
+const VERSION: usize = 1;
+
+/// The number of bytes at the head of the page reserved for it's header. The header consists of
+/// the magic, followed by the layout version, the number of slots, and the rate of the cycle
+/// counter in cycles/second, each a native-endian u64.
+pub const PAGE_HEADER_LEN: usize = 64;
+
+/// The number of bytes of the page each slot occupies. A slot fills a whole cache line, so that
+/// updates to one core's counters never contend with another's.
+pub const PAGE_SLOT_LEN: usize = 64;
+
+// The number of counters in a slot.
+const SLOT_WORDS: usize = PAGE_SLOT_LEN / 8;
+
+/// A snapshot of the counters of a single core on the stats page. Apart from `heartbeat`,
+/// `pending` and `parked`, counters are cumulative since the server started, so readers should
+/// difference successive samples to get rates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CoreStats {
+    /// The identifier of the core.
+    pub core: u64,
+
+    /// The value of the core's cycle counter when the slot was last updated. A heartbeat that
+    /// stops advancing indicates that the dispatcher on the core is no longer being polled.
+    pub heartbeat: u64,
+
+    /// The number of tenant tasks waiting on the core's run-queues.
+    pub pending: u64,
+
+    /// The number of tasks on the core parked until a point in time.
+    pub parked: u64,
+
+    /// The number of non-dispatch tasks that have completed on the core.
+    pub completed: u64,
+
+    /// The number of tasks the core's scheduler stole off it's peers.
+    pub stolen: u64,
+
+    /// The number of attempts the core's dispatcher made to steal packets off it's sibling's
+    /// receive queue.
+    pub steal_attempts: u64,
+
+    /// The number of those attempts that returned packets.
+    pub steal_successes: u64,
+}
+
+/// This type represents a fixed-layout page of per-core counters backed by a named shared-memory
+/// file (ex: under /dev/shm). Every core on the server publishes it's counters into it's own slot
+/// on the page with relaxed atomic stores, allowing a monitoring agent on the same host to map
+/// the file and sample the server's health at a high frequency without issuing RPCs. The page
+/// has the following layout:
+///      ____________________________________________________
+///     |          |          |          |        |          |
+///     |  Header  |  Slot 0  |  Slot 1  |  ....  |  Slot N  |
+///     |__________|__________|__________|________|__________|
+///       64 Bytes   64 Bytes   64 Bytes             64 Bytes
+///
+/// Each slot consists of the fields on `CoreStats` in the order they are declared, each a
+/// native-endian u64. Counters within a slot are not updated atomically with respect to each
+/// other, so a sample might see some of them one update ahead of the others.
+///
+/// The page is mapped for the lifetime of the process, and never unmapped.
+pub struct StatsPage {
+    // The base address of the mapping.
+    base: *mut u8,
+
+    // The number of slots on the page.
+    slots: usize,
+}
+
+// The mapping is never unmapped, and every word on it is only accessed atomically.
+unsafe impl Send for StatsPage {}
+unsafe impl Sync for StatsPage {}
+
+// Implementation of methods on StatsPage.
+impl StatsPage {
+    /// Maps a stats page, creating it if required. Any counters left behind by an earlier run
+    /// are cleared.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`:  The path of the shared-memory file backing the page.
+    /// * `slots`: The number of slots on the page, one per core.
+    /// * `rate`:  The rate of the cycle counter in cycles/second, published on the header so that
+    ///            readers can convert heartbeats into time.
+    ///
+    /// # Return
+    ///
+    /// The mapped page.
+    pub fn open(path: &str, slots: usize, rate: u64) -> Result<StatsPage> {
+        if slots == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Stats page without slots."));
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+
+        let size = PAGE_HEADER_LEN + slots * PAGE_SLOT_LEN;
+        file.set_len(size as u64)?;
+
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+
+        let page = StatsPage {
+            base: base as *mut u8,
+            slots: slots,
+        };
+
+        // Clear out the slots, and write the header. The magic goes in last, so that a reader
+        // that finds it can trust the rest of the header.
+        unsafe {
+            ptr::write_bytes(page.base, 0, size);
+            page.word(1).store(VERSION, Ordering::Relaxed);
+            page.word(2).store(slots, Ordering::Relaxed);
+            page.word(3).store(rate as usize, Ordering::Relaxed);
+            ptr::copy_nonoverlapping(MAGIC.as_ptr(), page.base, MAGIC.len());
+        }
+
+        Ok(page)
+    }
+
+    /// Returns the number of slots on the page.
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Returns a handle to a slot on the page through which a core can publish it's counters.
+    ///
+    /// # Arguments
+    ///
+    /// * `index`: The index of the slot.
+    ///
+    /// # Return
+    ///
+    /// The slot. None if the page does not have that many slots.
+    pub fn slot(&self, index: usize) -> Option<StatsSlot> {
+        if index >= self.slots {
+            return None;
+        }
+
+        Some(StatsSlot {
+            base: unsafe { self.base.offset((PAGE_HEADER_LEN + index * PAGE_SLOT_LEN) as isize) },
+        })
+    }
+
+    /// Reads the counters on a slot the way an external reader of the page would.
+    ///
+    /// # Arguments
+    ///
+    /// * `index`: The index of the slot.
+    ///
+    /// # Return
+    ///
+    /// The counters on the slot. None if the page does not have that many slots.
+    pub fn read(&self, index: usize) -> Option<CoreStats> {
+        self.slot(index).map(|slot| slot.read())
+    }
+
+    // Returns the word at a given index on the page's header.
+    unsafe fn word(&self, index: usize) -> &AtomicUsize {
+        &*(self.base.offset((index * 8) as isize) as *const AtomicUsize)
+    }
+}
+
+/// A handle to a single slot on a `StatsPage`. Each slot must have only one writer.
+pub struct StatsSlot {
+    // The address of the slot on the page's mapping.
+    base: *mut u8,
+}
+
+// The page's mapping is never unmapped, and every word on it is only accessed atomically.
+unsafe impl Send for StatsSlot {}
+
+// Implementation of methods on StatsSlot.
+impl StatsSlot {
+    /// Publishes a core's counters into the slot with relaxed stores.
+    ///
+    /// # Arguments
+    ///
+    /// * `stats`: The counters to publish.
+    pub fn publish(&self, stats: &CoreStats) {
+        let words = [
+            stats.core,
+            stats.heartbeat,
+            stats.pending,
+            stats.parked,
+            stats.completed,
+            stats.stolen,
+            stats.steal_attempts,
+            stats.steal_successes,
+        ];
+
+        for (index, value) in words.iter().enumerate() {
+            self.word(index).store(*value as usize, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the counters last published into the slot.
+    pub fn read(&self) -> CoreStats {
+        let mut words = [0u64; SLOT_WORDS];
+        for (index, value) in words.iter_mut().enumerate() {
+            *value = self.word(index).load(Ordering::Relaxed) as u64;
+        }
+
+        CoreStats {
+            core: words[0],
+            heartbeat: words[1],
+            pending: words[2],
+            parked: words[3],
+            completed: words[4],
+            stolen: words[5],
+            steal_attempts: words[6],
+            steal_successes: words[7],
+        }
+    }
+
+    // Returns the word at a given index on the slot.
+    fn word(&self, index: usize) -> &AtomicUsize {
+        unsafe { &*(self.base.offset((index * 8) as isize) as *const AtomicUsize) }
+    }
+}
+
+// This module contains simple unit tests for StatsPage.
+#[cfg(test)]
+mod tests {
+    use super::{CoreStats, StatsPage, MAGIC, PAGE_HEADER_LEN, PAGE_SLOT_LEN};
+    use std::fs::{remove_file, File};
+    use std::io::Read;
+
+    // This test verifies that counters published into a slot land at fixed offsets on the file
+    // backing the page, and do not spill over into neighbouring slots.
+    #[test]
+    fn test_page_layout() {
+        let path = "/tmp/sandstorm_stats_page.test";
+        let _ = remove_file(path);
+
+        let page = StatsPage::open(path, 2, 2400000000).expect("Failed to create stats page.");
+        assert_eq!(2, page.slots());
+        assert!(page.slot(2).is_none());
+
+        let stats = CoreStats {
+            core: 11,
+            heartbeat: 12345,
+            pending: 3,
+            parked: 1,
+            completed: 1000,
+            stolen: 7,
+            steal_attempts: 20,
+            steal_successes: 5,
+        };
+        page.slot(1).unwrap().publish(&stats);
+        assert_eq!(Some(stats), page.read(1));
+        assert_eq!(Some(CoreStats::default()), page.read(0));
+
+        let mut contents = Vec::new();
+        File::open(path)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(PAGE_HEADER_LEN + 2 * PAGE_SLOT_LEN, contents.len());
+        assert_eq!(&MAGIC[..], &contents[0..8]);
+        assert_eq!(&[1, 0, 0, 0, 0, 0, 0, 0], &contents[8..16]);
+        assert_eq!(&[2, 0, 0, 0, 0, 0, 0, 0], &contents[16..24]);
+
+        let slot = &contents[PAGE_HEADER_LEN + PAGE_SLOT_LEN..];
+        assert_eq!(&[11, 0, 0, 0, 0, 0, 0, 0], &slot[0..8]);
+        assert_eq!(&[7, 0, 0, 0, 0, 0, 0, 0], &slot[40..48]);
+        assert_eq!(&[5, 0, 0, 0, 0, 0, 0, 0], &slot[56..64]);
+        let _ = remove_file(path);
+    }
+}
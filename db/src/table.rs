@@ -13,11 +13,22 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-use std::collections::HashMap;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
-use spin::{RwLock};
+use spin::{Mutex, Once, RwLock};
 use bytes::{Bytes};
 
+use super::alloc::Quota;
+use super::cycles;
+use super::wireformat::TableKind;
+use time;
+
 // The number of buckets in the hash table. Must be a power of two.
 // If you want to change this number, then you will also have to modify
 // the implementation of the Default trait below.
@@ -29,9 +40,268 @@ use bytes::{Bytes};
 //    128 buckets: 18.5 Million ops/s (read-only), 12.3 Million ops/s (50-50)
 const N_BUCKETS : usize = 128;
 
+// Roughly one out of every `SAMPLE_RATE` reads on a table records the object it read, allowing
+// frequently read objects to be identified. Must be a power of two.
+const SAMPLE_RATE : u64 = 1024;
+
+// The maximum number of sampled objects a table holds on to between calls to `take_samples()`.
+const MAX_SAMPLES : usize = 4096;
+
+/// Returns the partition of a table that a key falls into. Keys in the same partition share a
+/// bucket (and therefore a lock and a hash map), so requests on them benefit from running on the
+/// same core.
+///
+/// # Arguments
+///
+/// * `key`: The key. Must not be empty.
+pub fn partition(key: &[u8]) -> usize {
+    key[0] as usize & (N_BUCKETS - 1)
+}
+
+/// The number of times `Table::snapshot()` is retried by multiget() requests before giving up on
+/// keys that keep being written to.
+pub const SNAPSHOT_RETRIES: usize = 8;
+
+/// The largest value, in bytes, that a table can keep inline in it's buckets. Refer to
+/// `Table::with_inline()`.
+pub const INLINE_VALUE_MAX: usize = 32;
+
+/// Describes where the fields a table needs off an object's metadata are, so that it can copy
+/// small values inline without knowing how the allocator laid the object out. Every offset is
+/// from the start of the object, and every field is little endian.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Layout {
+    /// The offset of the key length (two bytes).
+    pub key_len: usize,
+
+    /// The size of the metadata. The key starts right after it, and the value after the key.
+    pub meta: usize,
+
+    /// The offset of the version (eight bytes).
+    pub version: usize,
+
+    /// The offset of the expiration time (eight bytes), if objects carry one.
+    pub expiry: Option<usize>,
+}
+
+/// A copy of a small object's value and the metadata a read needs, kept inline in the table's
+/// bucket along with the object. Reading it does not touch the object itself.
+#[derive(Clone, Copy)]
+pub struct Inline {
+    /// The version of the object.
+    pub version: u64,
+
+    /// The time in milliseconds since the unix epoch at which the object expires. Zero if it
+    /// never expires.
+    pub expires: u64,
+
+    // The number of bytes of `value` that are in use.
+    len: u8,
+    value: [u8; INLINE_VALUE_MAX],
+}
+
+// Implementation of methods on Inline.
+impl Inline {
+    /// Returns the value of the object.
+    #[inline]
+    pub fn value(&self) -> &[u8] {
+        &self.value[..self.len as usize]
+    }
+}
+
+/// Orders a write to a key against deletes of the key that leave a tombstone behind. Refer to
+/// `Table::put_stamped()` and `Table::delete_with()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WriteStamp {
+    /// The time in cycles at which the server received the request issuing the write or delete.
+    /// Writes and deletes are ordered by it, since it is taken off a clock the server controls.
+    pub arrival: u64,
+
+    /// The IPv4 address of the client that issued the request.
+    pub client: u32,
+
+    /// The stamp on the request. Taken off the client's own clock, so it only orders requests
+    /// issued by the same client.
+    pub stamp: u64,
+}
+
+// Implementation of methods on WriteStamp.
+impl WriteStamp {
+    /// Returns true if a write stamped with `self` must not supersede a delete stamped with
+    /// `delete`. That is the case if the server received the write before the delete, and it was
+    /// only applied after it (ex: it ran on another core), or if the client that issued the
+    /// delete had issued the write before it, and the write was delayed or retransmitted until
+    /// after it. The stamps of two different clients are never compared.
+    pub fn precedes(&self, delete: &WriteStamp) -> bool {
+        self.arrival < delete.arrival || (self.client == delete.client && self.stamp < delete.stamp)
+    }
+}
+
+/// The result of a `Table::lookup()`.
+pub enum Found {
+    /// The object's value was kept inline. Refer to `Inline`.
+    Inline(Inline),
+
+    /// The object itself, whose value was too large to be kept inline.
+    Object(Bytes),
+}
+
+// An entry in one of a table's buckets. Holds the object, along with a copy of it's value if the
+// table keeps small values inline.
+struct Entry {
+    object: Bytes,
+    inline: Option<Inline>,
+}
+
+// A key held in one of a table's buckets, along with it's fingerprint (refer to `Table::print()`).
+// The fingerprint is the key's hash in the bucket's map, so a key is hashed once per operation on
+// the table: a write that checks for, removes, and inserts a key reuses it instead of hashing the
+// key on every call into the map. The map already stores hashes next to keys and compares them
+// before dereferencing a key, so lookups gain nothing over hashing keys in the map; refer to the
+// fingerprint benchmark in table_bench for both.
+#[derive(Clone)]
+struct Key {
+    print: u64,
+    key: Bytes,
+}
+
+// A key being looked up in one of a table's buckets, along with it's fingerprint. Borrows the key,
+// so that a lookup does not have to copy it into a Bytes.
+struct Probe<'a> {
+    print: u64,
+    key: &'a [u8],
+}
+
+impl<'a> Probe<'a> {
+    // Returns the probe in the form the buckets can be searched for.
+    fn printed(&self) -> &(Printed + 'a) {
+        self
+    }
+}
+
+// Implemented by Key and Probe, so that buckets holding Keys can be searched for a Probe.
+trait Printed {
+    fn print(&self) -> u64;
+    fn key(&self) -> &[u8];
+}
+
+impl Printed for Key {
+    fn print(&self) -> u64 {
+        self.print
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+impl<'a> Printed for Probe<'a> {
+    fn print(&self) -> u64 {
+        self.print
+    }
+
+    fn key(&self) -> &[u8] {
+        self.key
+    }
+}
+
+impl<'a> Borrow<Printed + 'a> for Key {
+    fn borrow(&self) -> &(Printed + 'a) {
+        self
+    }
+}
+
+// Keys hash to their fingerprint, and have to match on it before they are compared in full.
+impl<'a> Hash for (Printed + 'a) {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.print());
+    }
+}
+
+impl<'a> PartialEq for (Printed + 'a) {
+    fn eq(&self, other: &(Printed + 'a)) -> bool {
+        self.print() == other.print() && self.key() == other.key()
+    }
+}
+
+impl<'a> Eq for (Printed + 'a) {}
+
+// Must agree with the implementations on Printed, since buckets are searched through either.
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.print);
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.print == other.print && self.key == other.key
+    }
+}
+
+impl Eq for Key {}
+
+// Hashes the keys in a table's buckets to the fingerprint they were computed with, so that a key is
+// only hashed once per operation on the table. Keys and probes only ever hash through
+// `write_u64()`.
+#[derive(Default)]
+struct PrintHasher(u64);
+
+impl Hasher for PrintHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {
+        unreachable!("Keys in a table's buckets hash to their fingerprint");
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = i;
+    }
+}
+
+// One of a table's buckets.
+type Bucket = HashMap<Key, Entry, BuildHasherDefault<PrintHasher>>;
+
+// The keys written to or deleted from one of a table's buckets. Writes to a bucket update it with
+// the bucket locked, so it's lock is only ever contended by callers taking the keys out. Refer to
+// `Table::mark_changed()`.
+#[derive(Default)]
+struct Changes {
+    // Keys changed since the last call to `Table::take_changed()`.
+    changed: HashSet<Bytes>,
+
+    // Keys changed since the last call to `Table::take_dirty()`.
+    dirty: HashSet<Bytes>,
+}
+
+// Reads a little endian integer of up to eight bytes.
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |v, b| (v << 8) | *b as u64)
+}
+
 /// This struct represents a single table in Sandstorm. A table is indexed using
-/// an unordered map, which hashes an object's key to it's value. Tables can be
-/// safely accessed concurrently from multiple threads.
+/// an unordered map, which hashes an object's key to it's value. A table can
+/// also keep an ordered index over it's keys, allowing it to be scanned in key
+/// order (refer to `order()`). Tables can be safely accessed concurrently from
+/// multiple threads.
+///
+/// Writes are visible to other cores in the following order:
+///     - A put() or delete() is visible to every get() that starts after it
+///       returns, on any core. The write is made under the bucket's lock, and
+///       releasing the lock publishes both the map entry and the object's
+///       contents.
+///     - Writes issued one after the other from the same core are observed in
+///       that order: a get() that observes a later write also observes every
+///       earlier one, irrespective of the buckets they fall into.
+///     - The table's version is bumped after a write has been published, so a
+///       core that observes a version also observes every write preceding it.
+///       Versions are only kept once they are first read (refer to `version()`).
+///     - In place updates to an object's bytes (refer to `mark_changed()`) are
+///       plain stores, and are only ordered with respect to later writes by a
+///       fence on the writing core. They reach the inline copy of a value
+///       (refer to `with_inline()`) once `refresh()` is called.
 pub struct Table {
     // Each table is effectively an array of hash-maps, each of which is
     // protected by a read-write lock. Each element of this array is effectively
@@ -48,144 +318,1169 @@ pub struct Table {
     //        allowing for multiple threads/procedures to hold references to an
     //        object, without worrying about concurrent updates. An object will
     //        be dropped only when this ref-count goes to zero.
-    maps: [RwLock<HashMap<Bytes, Bytes>>; N_BUCKETS],
+    //
+    // Each key maps to an entry holding the object, and a copy of small values (refer to
+    // `inline`). Keys are held along with their fingerprint (refer to `Key`).
+    maps: [RwLock<Bucket>; N_BUCKETS],
+
+    // Computes the fingerprints of keys. Seeded randomly for every table, so that clients cannot
+    // pick keys that collide.
+    prints: RandomState,
+
+    // If set, values of at most this many bytes are also copied into their entry, and the layout
+    // of objects that the copies are made off. Refer to `with_inline()`.
+    inline: Option<(usize, Layout)>,
+
+    // If true, reads are sampled into `samples`. Off until hot objects are first migrated out
+    // of the table, so that tables which are never migrated do not pay for sampling.
+    sampling: AtomicBool,
+
+    // Objects recently read from this table. Used to determine which objects are hot.
+    samples: Mutex<Vec<Bytes>>,
+
+    // The time at which the table was created in seconds since the unix epoch.
+    created: u64,
+
+    // If true, keys of objects written or deleted are recorded in `changes`. Enabled once the
+    // table has been snapshotted, so that subsequent snapshots can be incremental.
+    tracking: AtomicBool,
+
+    // If true, keys of objects written or deleted are recorded in `changes` as dirty. Enabled
+    // while the table is being migrated to another server, independently of snapshots.
+    migrating: AtomicBool,
+
+    // Keys of objects written or deleted, indexed by bucket.
+    changes: Vec<Mutex<Changes>>,
+
+    // Byte ranges of objects that are currently being updated in place. Each entry consists of
+    // the address of the object, and the start and end offsets of the range. Updates complete
+    // quickly, so this list is only ever as long as the number of concurrent updates.
+    latches: Mutex<Vec<(usize, usize, usize)>>,
+
+    // Bumped on every write to or delete from the table. Allows results computed from the table
+    // to be invalidated once it changes. Only bumped once `watched` is set by the first call to
+    // `version()`, so that tables whose version nobody reads never contend on it.
+    version: AtomicUsize,
+    watched: AtomicBool,
+
+    // If true, the keys of every object in the table are also held in `index` in order, allowing
+    // the table to be scanned. The index is updated with the key's bucket locked, so that the
+    // index and buckets agree on the keys in a bucket whenever it is unlocked.
+    ordered: AtomicBool,
+    index: RwLock<BTreeSet<Bytes>>,
+
+    // Keys deleted by `delete_stamped()`, along with the stamp of the request that deleted them,
+    // indexed by bucket. Entries are added and removed with the key's bucket locked, so that a
+    // write to the key cannot slip in between the check and the write, and so that each lock is
+    // only ever contended by `purge_tombstones()`.
+    tombstones: Vec<Mutex<HashMap<Bytes, WriteStamp>>>,
+
+    // The total size in bytes of the objects in every bucket. Updated with the bucket locked, so
+    // that it never counts an object twice, or misses one, and so that writes to different
+    // buckets never update the same counter.
+    bytes: Vec<AtomicUsize>,
+
+    // The memory quota of the group the table's tenant belongs to, if any. Charged for every
+    // object added to the table, and credited for every object removed from it. Refer to
+    // `set_quota()`.
+    quota: Once<Arc<Quota>>,
+}
+
+// Implementation of the Default trait for Table.
+impl Default for Table {
+    // This method returns a table with 128 buckets. The maps array needs to
+    // be explicitly initialized this way because the Default trait cannot be
+    // derived for arrays with more than 32 elements.
+    fn default() -> Table {
+        Table {
+            maps: [RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                   RwLock::new(HashMap::default()), RwLock::new(HashMap::default()),
+                ],
+            prints: RandomState::new(),
+            inline: None,
+            sampling: AtomicBool::new(false),
+            samples: Mutex::new(Vec::new()),
+            created: time::get_time().sec as u64,
+            tracking: AtomicBool::new(false),
+            migrating: AtomicBool::new(false),
+            changes: (0..N_BUCKETS).map(| _ | { Mutex::new(Changes::default()) }).collect(),
+            latches: Mutex::new(Vec::new()),
+            version: AtomicUsize::new(0),
+            watched: AtomicBool::new(false),
+            ordered: AtomicBool::new(false),
+            index: RwLock::new(BTreeSet::new()),
+            tombstones: (0..N_BUCKETS).map(| _ | { Mutex::new(HashMap::new()) }).collect(),
+            bytes: (0..N_BUCKETS).map(| _ | { AtomicUsize::new(0) }).collect(),
+            quota: Once::new(),
+        }
+    }
+}
+
+// Implementation of the Drop trait for Table.
+impl Drop for Table {
+    // Credits the objects still in the table back to it's quota, if it has one.
+    fn drop(&mut self) {
+        if let Some(quota) = self.quota.try() {
+            quota.credit(self.bytes());
+        }
+    }
 }
 
-// Implementation of the Default trait for Table.
-impl Default for Table {
-    // This method returns a table with 128 buckets. The maps array needs to
-    // be explicitly initialized this way because the Default trait cannot be
-    // derived for arrays with more than 32 elements.
-    fn default() -> Table {
-        Table {
-            maps: [RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                   RwLock::new(HashMap::new()), RwLock::new(HashMap::new()),
-                ]
+// Implementation of Table
+impl Table {
+    /// This function returns a table that keeps a copy of small values inline in it's buckets,
+    /// next to their key. Reading one through `lookup()` then costs no cache miss beyond the one
+    /// on the bucket, which helps workloads dominated by small values (ex: counters). Every
+    /// write of a small value copies it once more, and in place updates to an object must be
+    /// followed by a call to `refresh()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max`:    Values of at most this many bytes are kept inline. Capped at
+    ///             `INLINE_VALUE_MAX`. Zero returns a table that keeps no value inline.
+    /// * `layout`: The layout of objects written to the table.
+    pub fn with_inline(max: usize, layout: Layout) -> Table {
+        let mut table = Table::default();
+        if max > 0 {
+            table.inline = Some((max.min(INLINE_VALUE_MAX), layout));
+        }
+
+        table
+    }
+
+    /// This function reads an object from a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: A slice of bytes corresponding to the object's key.
+    ///
+    /// # Return
+    ///
+    /// The object corresponding to the supplied key if one exists. The
+    /// object is returned wrapped up in a Bytes type. The underlying object
+    /// is guaranteed to exist atleast until the returned Bytes is dropped.
+    /// If the object does not exist in the Table, this method returns None.
+    pub fn get(&self, key: &[u8]) -> Option<Bytes> {
+        // First, identify the bucket the key falls into.
+        let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
+        let map = self.maps[bucket].read();
+
+        // Perform the lookup, and return.
+        let probe = self.probe(key);
+        let object = map.get(probe.printed()).and_then(| entry | { Some(entry.object.clone()) });
+
+        if let Some(ref object) = object {
+            self.sample_read(object);
+        }
+
+        return object;
+    }
+
+    /// This function reads the objects under a list of keys as of a single point in time: every
+    /// object returned was current at one instant, after the first pass completed. Keys are read
+    /// one after the other, and then read again. If none of them maps to a different object, or
+    /// to a different version of the same object, the second time around, nothing was written to
+    /// them in between. Otherwise, the reads are retried. Writes to several keys are not atomic
+    /// (ex: the two puts linking two nodes of a graph), so the snapshot can fall in between them,
+    /// and observe one of the writes without the other. Objects
+    /// read on the first pass are held on to, so their address cannot be reused by a later write
+    /// while they are being checked. Reads are not sampled.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys`:    The keys to be read. None of them may be empty.
+    /// * `retries`: The number of times the reads are retried before giving up.
+    /// * `version`: Closure returning the version of an object (ex: `Allocator::version()`).
+    ///
+    /// # Return
+    ///
+    /// The objects under each key in order, with None in place of keys that do not exist. None
+    /// if the keys were written to during every attempt.
+    pub fn snapshot<F>(&self, keys: &[&[u8]], retries: usize, version: F)
+        -> Option<Vec<Option<Bytes>>>
+    where
+        F: Fn(&Bytes) -> u64,
+    {
+        let read = | key: &[u8] | {
+            let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
+            let map = self.maps[bucket].read();
+            let object = map.get(self.probe(key).printed()).map(| entry | { entry.object.clone() });
+            object
+        };
+
+        for _ in 0..retries + 1 {
+            let objects: Vec<Option<(Bytes, u64)>> = keys
+                .iter()
+                .map(| key | {
+                    read(*key).map(| object | {
+                        let v = version(&object);
+                        (object, v)
+                    })
+                })
+                .collect();
+
+            let unchanged = keys.iter().zip(objects.iter()).all(| (key, first) | {
+                match (read(*key), first) {
+                    (Some(object), &Some((ref old, v))) => {
+                        object.as_ptr() == old.as_ptr() && version(&object) == v
+                    }
+                    (None, &None) => true,
+                    _ => false,
+                }
+            });
+
+            if unchanged {
+                let objects = objects.into_iter().map(| o | { o.map(| (object, _) | object) });
+                return Some(objects.collect());
+            }
+        }
+
+        None
+    }
+
+    /// This function reads an object from a table, returning the copy of it's value if it was
+    /// small enough to be kept inline (refer to `with_inline()`), and the object otherwise.
+    /// Reads served inline are not sampled, since they never touch the object.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: A slice of bytes corresponding to the object's key.
+    ///
+    /// # Return
+    ///
+    /// The inline copy or the object corresponding to the supplied key if one exists, None
+    /// otherwise.
+    pub fn lookup(&self, key: &[u8]) -> Option<Found> {
+        // First, identify the bucket the key falls into.
+        let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
+
+        let object = {
+            let map = self.maps[bucket].read();
+            match map.get(self.probe(key).printed()) {
+                Some(entry) => match entry.inline {
+                    Some(inline) => return Some(Found::Inline(inline)),
+                    None => entry.object.clone(),
+                },
+                None => return None,
+            }
+        };
+
+        self.sample_read(&object);
+        Some(Found::Object(object))
+    }
+
+    /// This function updates the inline copy of an object's value after the object was updated
+    /// in place. Reads that race with the update might observe the old copy until this function
+    /// returns. Does nothing if the table does not keep values inline.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The key of the object that was updated.
+    pub fn refresh(&self, key: &[u8]) {
+        if self.inline.is_none() {
+            return;
+        }
+
+        let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
+        let mut map = self.maps[bucket].write();
+        if let Some(entry) = map.get_mut(self.probe(key).printed()) {
+            let inline = self.inline_copy(&entry.object);
+            entry.inline = inline;
+        }
+    }
+
+    /// This function writes an object into a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`:    A Bytes wrapping the key for the object.
+    /// * `object`: A Bytes wrapping the entire object to be written to
+    ///             the table.
+    pub fn put(&self, key: Bytes, object: Bytes) {
+        // First, identify the bucket the key falls into.
+        let bucket: usize = key.slice(0, 1)[0] as usize & (N_BUCKETS - 1);
+        let mut map = self.maps[bucket].write();
+
+        // Next, remove the key from the hash map if it already exists.
+        let printed = self.key(&key);
+        if map.contains_key(&printed) {
+            let _val = self.release(bucket, map.remove(&printed));
+        }
+
+        // Perform the insert.
+        let _obj = self.release(bucket, map.insert(printed, self.entry(bucket, object)));
+        self.index_insert(&key);
+
+        // Record the change, publishing a new version of the table. This must happen after the
+        // insert so that the new version is never observed without the object.
+        self.mark_changed(&key);
+    }
+
+    /// This function writes an object into a table, unless the key was deleted by a request
+    /// that the write precedes (refer to `delete_stamped()` and `WriteStamp::precedes()`).
+    /// Closes the race between a delete and a put that was issued before it, but was delayed or
+    /// replayed (ex: by a retransmission) until after it. A write that goes through supersedes
+    /// the key's tombstone.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`:   The key of the object.
+    /// * `stamp`: The stamp of the request issuing the write.
+    /// * `f`:     Closure returning the key and object to be written, or None if nothing should
+    ///            be written after all (ex: the write could not be logged). Only invoked if the
+    ///            write goes through, so that the object is committed only then (ex: to a
+    ///            persistent heap). It runs with the bucket locked, and must not access the table.
+    ///
+    /// # Return
+    ///
+    /// True if the object was written. False if the write is older than the key's deletion, or
+    /// if `f` returned None.
+    pub fn put_stamped<F>(&self, key: &[u8], stamp: &WriteStamp, f: F) -> bool
+    where
+        F: FnOnce() -> Option<(Bytes, Bytes)>,
+    {
+        // First, identify the bucket the key falls into.
+        let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
+        let mut map = self.maps[bucket].write();
+
+        // Next, check the write against the key's tombstone, if there is one. The tombstone is
+        // only superseded once the write is known to go through.
+        let mut tombstones = self.tombstones[bucket].lock();
+        let stale = tombstones.get(key).map_or(false, | deleted | { stamp.precedes(deleted) });
+        if stale {
+            return false;
+        }
+
+        let (key, object) = match f() {
+            Some(write) => write,
+            None => return false,
+        };
+
+        let _ = tombstones.remove(&key[..]);
+
+        let printed = self.key(&key);
+        if map.contains_key(&printed) {
+            let _val = self.release(bucket, map.remove(&printed));
+        }
+
+        let _obj = self.release(bucket, map.insert(printed, self.entry(bucket, object)));
+        self.index_insert(&key);
+        self.mark_changed(&key);
+
+        return true;
+    }
+
+    /// This function atomically replaces an object in the table, but only if the key still
+    /// maps to a particular object.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: A Bytes wrapping the key for the object.
+    /// * `old`: The object that the key is expected to currently map to.
+    /// * `new`: A Bytes wrapping the entire object that should replace `old`.
+    ///
+    /// # Return
+    ///
+    /// True if the object was replaced. False if the key was not found or no longer maps to
+    /// `old`, for example because of a concurrent put() or delete().
+    pub fn swap(&self, key: Bytes, old: &Bytes, new: Bytes) -> bool {
+        // First, identify the bucket the key falls into.
+        let bucket: usize = key.slice(0, 1)[0] as usize & (N_BUCKETS - 1);
+        let mut map = self.maps[bucket].write();
+
+        // Objects are compared by address; an updated object is always a new allocation.
+        let printed = self.key(&key);
+        let current = map.get(&printed).map_or(false, | e | { e.object.as_ptr() == old.as_ptr() });
+        if current {
+            let _obj = self.release(bucket, map.insert(printed, self.entry(bucket, new)));
+        }
+
+        return current;
+    }
+
+    /// This function atomically writes an object into the table, but only if the object currently
+    /// under the key passes a check. The key's bucket is locked for the duration of the check
+    /// and write, so no other write to the key can slip in between them.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The key of the object.
+    /// * `f`:   Closure invoked with the object currently under the key, if any. Returns the key
+    ///          and object to be written, or None if nothing should be written. It runs with
+    ///          the bucket locked, and must not access the table.
+    ///
+    /// # Return
+    ///
+    /// True if an object was written.
+    pub fn put_if<F>(&self, key: &[u8], f: F) -> bool
+    where
+        F: FnOnce(Option<&Bytes>) -> Option<(Bytes, Bytes)>,
+    {
+        // First, identify the bucket the key falls into.
+        let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
+        let mut map = self.maps[bucket].write();
+
+        let probe = self.probe(key);
+        let (key, object) = match f(map.get(probe.printed()).map(| entry | { &entry.object })) {
+            Some(write) => write,
+            None => return false,
+        };
+
+        let printed = self.key(&key);
+        if map.contains_key(&printed) {
+            let _val = self.release(bucket, map.remove(&printed));
+        }
+
+        let _obj = self.release(bucket, map.insert(printed, self.entry(bucket, object)));
+        self.index_insert(&key);
+        self.mark_changed(&key);
+
+        return true;
+    }
+
+    /// This function atomically moves an object from this table into another one, but only if
+    /// the key still maps to a particular object. The key's bucket is locked in both tables for
+    /// the duration of the move, so a get() on either table observes the object in exactly one of
+    /// them, never in both or in neither.
+    ///
+    /// # Arguments
+    ///
+    /// * `dst`: The table the object should be moved into. Must not be this table.
+    /// * `key`: The key of the object.
+    /// * `old`: The object that the key is expected to currently map to in this table.
+    /// * `f`:   Closure returning the key and object to be written to `dst`, or None if nothing
+    ///          should be moved after all (ex: the move could not be logged). Only invoked if
+    ///          the key still maps to `old`. It runs with the bucket locked in both tables, and
+    ///          must not access either of them.
+    ///
+    /// # Return
+    ///
+    /// True if the object was moved. False if `dst` is this table, if the key was not found
+    /// or no longer maps to `old`, or if `f` returned None, in which case neither table is
+    /// modified.
+    pub fn move_to<F>(&self, dst: &Table, key: &[u8], old: &Bytes, f: F) -> bool
+    where
+        F: FnOnce() -> Option<(Bytes, Bytes)>,
+    {
+        let src_addr = self as *const Table as usize;
+        let dst_addr = dst as *const Table as usize;
+        if src_addr == dst_addr {
+            return false;
+        }
+
+        // Lock the bucket in both tables, always locking the table at the lower address first so
+        // that two concurrent moves in opposite directions cannot deadlock.
+        let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
+        let (mut src_map, mut dst_map) = if src_addr < dst_addr {
+            let src_map = self.maps[bucket].write();
+            (src_map, dst.maps[bucket].write())
+        } else {
+            let dst_map = dst.maps[bucket].write();
+            (self.maps[bucket].write(), dst_map)
+        };
+
+        // Objects are compared by address, just like swap(). Fingerprints are seeded differently
+        // on every table, so the key is fingerprinted once for each of them.
+        let probe = self.probe(key);
+        let current = src_map
+            .get(probe.printed())
+            .map_or(false, | entry | { entry.object.as_ptr() == old.as_ptr() });
+        if !current {
+            return false;
+        }
+
+        let (key, new) = match f() {
+            Some(write) => write,
+            None => return false,
+        };
+
+        // The copy is made with `dst`'s settings, since the object now belongs to it.
+        let _obj = dst.release(bucket, dst_map.insert(dst.key(&key), dst.entry(bucket, new)));
+        let _val = self.release(bucket, src_map.remove(probe.printed()));
+        dst.index_insert(&key);
+        self.index_remove(&key);
+
+        // Record the change on both tables before releasing the buckets, so that neither new
+        // version is observed without the move.
+        dst.mark_changed(&key);
+        self.mark_changed(&key);
+
+        return true;
+    }
+
+    /// This function starts or stops sampling reads on the table. Reads are not sampled until
+    /// this function is first called. Sampled objects are dropped when sampling is stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `on`: True if reads should be sampled.
+    pub fn set_sampling(&self, on: bool) {
+        self.sampling.store(on, Ordering::Relaxed);
+        if !on {
+            self.samples.lock().clear();
+        }
+    }
+
+    /// This function returns and clears the set of objects sampled on reads since the last call
+    /// to this function. Refer to `set_sampling()`.
+    ///
+    /// # Return
+    ///
+    /// A vector of recently read objects. An object can appear multiple times.
+    pub fn take_samples(&self) -> Vec<Bytes> {
+        let mut samples = self.samples.lock();
+
+        return samples.drain(..).collect();
+    }
+
+    /// This function returns the number of objects in the table.
+    ///
+    /// # Return
+    ///
+    /// The total number of objects across all buckets. Buckets are locked one at a time, so the
+    /// count is not a consistent snapshot if the table is concurrently modified.
+    pub fn len(&self) -> usize {
+        self.maps.iter().map(| map | { map.read().len() }).sum()
+    }
+
+    /// This function returns the total size in bytes of the objects in the table, including
+    /// their metadata. Objects that were removed from the table but are still held by requests
+    /// are not counted.
+    pub fn bytes(&self) -> usize {
+        self.bytes.iter().map(| bytes | { bytes.load(Ordering::Relaxed) }).sum()
+    }
+
+    /// This function charges the objects in the table, and every object added to it from here
+    /// on, to the memory quota of a group of tenants (refer to `Allocator::set_group_quota()`).
+    /// Objects are credited back as soon as they are removed or replaced, and when the table is
+    /// dropped. A table's quota can only be set once. Must be called before the table is shared.
+    ///
+    /// # Arguments
+    ///
+    /// * `quota`: The quota of the group the table's tenant belongs to.
+    ///
+    /// # Return
+    ///
+    /// False if the table already had a quota, in which case it is left unchanged.
+    pub fn set_quota(&self, quota: Arc<Quota>) -> bool {
+        if self.quota.try().is_some() {
+            return false;
+        }
+
+        self.quota.call_once(|| { quota }).charge(self.bytes());
+        true
+    }
+
+    /// This function returns the version of the table. From the first call to this function on,
+    /// the version changes whenever an object is written to or deleted from the table.
+    /// Every write preceding the returned version is visible to the caller.
+    pub fn version(&self) -> u64 {
+        // A write that missed the flag was made under it's bucket's lock before the flag was
+        // set, and is therefore visible to anything the caller reads from the table afterwards.
+        if !self.watched.load(Ordering::Relaxed) {
+            self.watched.store(true, Ordering::SeqCst);
+        }
+
+        self.version.load(Ordering::Acquire) as u64
+    }
+
+    /// This function returns the kind of the table.
+    pub fn kind(&self) -> TableKind {
+        if self.ordered.load(Ordering::Acquire) {
+            return TableKind::Ordered;
+        }
+
+        TableKind::Hash
+    }
+
+    /// This function returns the time at which the table was created in seconds since the unix
+    /// epoch.
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    /// This function deletes an object from a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The key of the object to be deleted, passed in as a slice of bytes.
+    ///
+    /// # Return
+    ///
+    /// The object that was removed, if the key existed.
+    pub fn delete(&self, key: &[u8]) -> Option<Bytes> {
+        self.delete_with(key, None, || { true }).unwrap_or(None)
+    }
+
+    /// This function deletes an object from a table, and leaves a tombstone behind so that
+    /// writes to the key that precede the deletion are rejected by `put_stamped()` (refer to
+    /// `WriteStamp::precedes()`). The tombstone is left behind even if the key did not exist,
+    /// since the write it guards against might not have arrived yet. Tombstones are kept until
+    /// they are purged.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`:   The key of the object to be deleted, passed in as a slice of bytes.
+    /// * `stamp`: The stamp of the request issuing the delete.
+    ///
+    /// # Return
+    ///
+    /// The object that was removed, if the key existed.
+    pub fn delete_stamped(&self, key: &[u8], stamp: &WriteStamp) -> Option<Bytes> {
+        self.delete_with(key, Some(stamp), || { true }).unwrap_or(None)
+    }
+
+    /// This function deletes an object from a table, but only once the deletion has been
+    /// recorded by a closure, for example in a write-ahead log. The closure runs with the key's
+    /// bucket locked, so deletions and writes to a key are recorded in the order in which they
+    /// are applied to the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`:   The key of the object to be deleted, passed in as a slice of bytes.
+    /// * `stamp`: The stamp of the request issuing the delete, if a tombstone should be left
+    ///            behind like `delete_stamped()` does.
+    /// * `log`:   Closure recording the deletion. Returns false if it could not be recorded. It
+    ///            runs with the bucket locked, and must not access the table.
+    ///
+    /// # Return
+    ///
+    /// The object that was removed, if the key existed. An error if `log` returned false, in
+    /// which case the table is not modified.
+    pub fn delete_with<F>(&self, key: &[u8], stamp: Option<&WriteStamp>, log: F)
+        -> Result<Option<Bytes>, ()>
+    where
+        F: FnOnce() -> bool,
+    {
+        // First, identify the bucket the key falls into.
+        let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
+        let mut map = self.maps[bucket].write();
+
+        if !log() {
+            return Err(());
+        }
+
+        // Next, record the tombstone. A key deleted more than once is guarded by the deletion
+        // the server received last.
+        if let Some(stamp) = stamp {
+            let mut tombstones = self.tombstones[bucket].lock();
+            let tombstone = tombstones.entry(Bytes::from(key)).or_insert(*stamp);
+            if tombstone.arrival <= stamp.arrival {
+                *tombstone = *stamp;
+            }
+        }
+
+        // Next, remove the key from the hash map if it already exists.
+        let val = self.release(bucket, map.remove(self.probe(key).printed()));
+        if val.is_some() {
+            self.index_remove(key);
+
+            // Record the change if this table is being snapshotted.
+            self.mark_changed(key);
+        }
+
+        Ok(val)
+    }
+
+    /// This function removes the tombstones of keys whose deletion the server received before a
+    /// point in time. Writes to these keys are accepted irrespective of when they were issued
+    /// from here on. Buckets are purged one at a time, each with it's lock held.
+    ///
+    /// # Arguments
+    ///
+    /// * `before`: The time stamp in cycles before which tombstones are removed.
+    ///
+    /// # Return
+    ///
+    /// The number of tombstones that were removed.
+    pub fn purge_tombstones(&self, before: u64) -> usize {
+        let mut purged = 0;
+        for bucket in 0..N_BUCKETS {
+            if self.tombstones[bucket].lock().is_empty() {
+                continue;
+            }
+
+            let _map = self.maps[bucket].write();
+            let mut tombstones = self.tombstones[bucket].lock();
+            let len = tombstones.len();
+            tombstones.retain(| _, deleted | { deleted.arrival >= before });
+            purged += len - tombstones.len();
+        }
+
+        return purged;
+    }
+
+    /// This function returns the number of tombstones currently held by the table.
+    pub fn tombstones(&self) -> usize {
+        self.tombstones.iter().map(| tombstones | { tombstones.lock().len() }).sum()
+    }
+
+    /// This function returns the number of buckets in the table. Refer to `expire()`.
+    pub fn buckets(&self) -> usize {
+        N_BUCKETS
+    }
+
+    /// This function removes objects that have expired from one of the table's buckets. Buckets
+    /// are swept one at a time, so that a sweep over a large table can be spread out (ex: across
+    /// the runs of a background task), and never holds up writes to the rest of the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket`:  The bucket to sweep, modulo the number of buckets.
+    /// * `expired`: Closure returning true if an object has expired. It runs with the bucket
+    ///              locked, and must not access the table.
+    ///
+    /// # Return
+    ///
+    /// The number of objects that were removed.
+    pub fn expire<F>(&self, bucket: usize, expired: F) -> usize
+    where
+        F: Fn(&Bytes) -> bool,
+    {
+        let bucket = bucket & (N_BUCKETS - 1);
+
+        // Look for expired objects with the bucket read locked first, since most sweeps of a
+        // bucket do not find any.
+        let keys: Vec<Key> = {
+            let map = self.maps[bucket].read();
+            map.iter()
+                .filter(| &(_, entry) | { expired(&entry.object) })
+                .map(| (key, _) | { key.clone() })
+                .collect()
+        };
+        if keys.is_empty() {
+            return 0;
+        }
+
+        // A key could have been written to in the meantime, so check it's object again once the
+        // bucket is write locked.
+        let mut map = self.maps[bucket].write();
+        let mut removed = 0;
+        for key in keys.iter() {
+            if map.get(key).map_or(false, | entry | { expired(&entry.object) }) {
+                let _val = self.release(bucket, map.remove(key));
+                self.index_remove(&key.key);
+                self.mark_changed(&key.key);
+                removed += 1;
+            }
+        }
+
+        return removed;
+    }
+
+    /// This function invokes a closure on every object in the table. Objects are copied out of
+    /// one bucket at a time, so the closure is not invoked with a bucket locked, and does not
+    /// observe a consistent view of the table if it is concurrently modified.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`: The closure to be invoked on every object.
+    pub fn scan<F>(&self, mut f: F)
+    where
+        F: FnMut(Bytes),
+    {
+        for map in self.maps.iter() {
+            let objects: Vec<Bytes> = map.read().values().map(| e | { e.object.clone() }).collect();
+
+            for object in objects.into_iter() {
+                f(object);
+            }
+        }
+    }
+
+    /// This function builds an ordered index over the keys of the table, and keeps it up to date
+    /// from here on, so that the table can be scanned in key order through `range()`. Writes to
+    /// the table get a little slower, since every put() and delete() also updates the index.
+    /// Does nothing if the table already has an index.
+    pub fn order(&self) {
+        if self.ordered.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        // Writes that lock a bucket after this point update the index themselves. Any write
+        // that completed before is picked up here.
+        for map in self.maps.iter() {
+            let map = map.read();
+            let mut index = self.index.write();
+
+            for key in map.keys() {
+                index.replace(key.key.clone());
+            }
+        }
+    }
+
+    /// This function reads the objects whose keys fall in a range, in key order. Keys are read
+    /// off the index first, and their objects are looked up afterwards, so objects written while
+    /// the range is being read might or might not be returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `start`: The first key in the range.
+    /// * `end`:   The key after the last key in the range, or None if the range extends to the
+    ///            end of the table.
+    /// * `limit`: The maximum number of objects to return.
+    ///
+    /// # Return
+    ///
+    /// A vector of at most `limit` key-object pairs. Empty if the table does not have an ordered
+    /// index (refer to `order()`), or if the range is empty.
+    pub fn range(&self, start: &[u8], end: Option<&[u8]>, limit: usize) -> Vec<(Bytes, Bytes)> {
+        if !self.ordered.load(Ordering::Acquire) || end.map_or(false, |end| end <= start) {
+            return Vec::new();
+        }
+
+        let keys: Vec<Bytes> = {
+            let lo = Included(Bytes::from(start));
+            let hi = end.map_or(Unbounded, |end| Excluded(Bytes::from(end)));
+            self.index.read().range((lo, hi)).take(limit).cloned().collect()
+        };
+
+        let mut objects = Vec::with_capacity(keys.len());
+        for key in keys.into_iter() {
+            let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
+            if let Some(entry) = self.maps[bucket].read().get(self.probe(&key).printed()) {
+                objects.push((key.clone(), entry.object.clone()));
+            }
+        }
+
+        return objects;
+    }
+
+    // Returns the fingerprint of a key. Equal keys always have the same fingerprint on a table.
+    fn print(&self, key: &[u8]) -> u64 {
+        let mut hasher = self.prints.build_hasher();
+        hasher.write(key);
+        hasher.finish()
+    }
+
+    // Returns a key along with it's fingerprint, to be looked up in or written to a bucket.
+    fn key(&self, key: &Bytes) -> Key {
+        Key {
+            print: self.print(key),
+            key: key.clone(),
+        }
+    }
+
+    // Returns a probe for a key, to be looked up in or removed from a bucket.
+    fn probe<'a>(&self, key: &'a [u8]) -> Probe<'a> {
+        Probe {
+            print: self.print(key),
+            key: key,
+        }
+    }
+
+    // Returns the entry an object is held in, copying the object's value into it if the table keeps
+    // values inline and the value is small enough.
+    fn entry(&self, bucket: usize, object: Bytes) -> Entry {
+        self.bytes[bucket].fetch_add(object.len(), Ordering::Relaxed);
+        if let Some(quota) = self.quota.try() {
+            quota.charge(object.len());
+        }
+
+        Entry {
+            inline: self.inline_copy(&object),
+            object: object,
+        }
+    }
+
+    // Accounts for an entry that was removed from one of the table's buckets, or replaced on it,
+    // and returns it's object.
+    fn release(&self, bucket: usize, entry: Option<Entry>) -> Option<Bytes> {
+        entry.map(| entry | {
+            self.bytes[bucket].fetch_sub(entry.object.len(), Ordering::Relaxed);
+            if let Some(quota) = self.quota.try() {
+                quota.credit(entry.object.len());
+            }
+
+            entry.object
+        })
+    }
+
+    // Copies the value of an object along with it's version and expiration time, if the table
+    // keeps values inline and the value is small enough.
+    fn inline_copy(&self, object: &[u8]) -> Option<Inline> {
+        let (max, layout) = match self.inline {
+            Some(inline) => inline,
+            None => return None,
+        };
+
+        if object.len() < layout.meta {
+            return None;
+        }
+
+        let key_len = read_le(&object[layout.key_len..layout.key_len + 2]) as usize;
+        let start = layout.meta + key_len;
+        if object.len() < start || object.len() - start > max {
+            return None;
+        }
+
+        let len = object.len() - start;
+        let mut inline = Inline {
+            version: read_le(&object[layout.version..layout.version + 8]),
+            expires: layout.expiry.map_or(0, |offset| read_le(&object[offset..offset + 8])),
+            len: len as u8,
+            value: [0; INLINE_VALUE_MAX],
+        };
+        inline.value[..len].copy_from_slice(&object[start..]);
+
+        Some(inline)
+    }
+
+    // Occasionally records an object that was read, if reads are being sampled. The low bits of
+    // the timestamp counter decide whether to sample, avoiding a shared counter across cores.
+    fn sample_read(&self, object: &Bytes) {
+        if !self.sampling.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if cycles::rdtsc() & (SAMPLE_RATE - 1) == 0 {
+            if let Some(mut samples) = self.samples.try_lock() {
+                if samples.len() < MAX_SAMPLES {
+                    samples.push(object.clone());
+                }
+            }
+        }
+    }
+
+    // Adds a key to the ordered index if the table has one. Must be called with the key's bucket
+    // locked. The index holds on to `key`, which replaces any handle on an earlier object it
+    // held for the same key, so that the earlier object can be freed.
+    fn index_insert(&self, key: &Bytes) {
+        if self.ordered.load(Ordering::Acquire) {
+            self.index.write().replace(key.clone());
+        }
+    }
+
+    // Removes a key from the ordered index if the table has one. Must be called with the key's
+    // bucket locked.
+    fn index_remove(&self, key: &[u8]) {
+        if self.ordered.load(Ordering::Acquire) {
+            self.index.write().remove(key);
         }
     }
-}
 
-// Implementation of Table
-impl Table {
-    /// This function reads an object from a table.
+    /// This function samples objects along with the keys they are stored under. Buckets are
+    /// visited in order starting from `seed`, and an equal number of objects is taken from each
+    /// of them, so that repeated calls with different seeds eventually cover the entire table.
     ///
     /// # Arguments
     ///
-    /// * `key`: A slice of bytes corresponding to the object's key.
+    /// * `n`:    The maximum number of objects to sample.
+    /// * `seed`: Determines the bucket sampling starts at.
     ///
     /// # Return
     ///
-    /// The object corresponding to the supplied key if one exists. The
-    /// object is returned wrapped up in a Bytes type. The underlying object
-    /// is guaranteed to exist atleast until the returned Bytes is dropped.
-    /// If the object does not exist in the Table, this method returns None.
-    pub fn get(&self, key: &[u8]) -> Option<Bytes> {
-        // First, identify the bucket the key falls into.
+    /// A vector of at most `n` key-object pairs.
+    pub fn sample(&self, n: usize, seed: usize) -> Vec<(Bytes, Bytes)> {
+        let per_bucket = (n + N_BUCKETS - 1) / N_BUCKETS;
+        let mut samples = Vec::with_capacity(n);
+
+        for i in 0..N_BUCKETS {
+            if samples.len() >= n {
+                break;
+            }
+
+            let take = per_bucket.min(n - samples.len());
+            let map = self.maps[(seed + i) & (N_BUCKETS - 1)].read();
+            samples.extend(map.iter().take(take).map(|(k, e)| (k.key.clone(), e.object.clone())));
+        }
+
+        return samples;
+    }
+
+    /// This function starts recording the keys of objects written to or deleted from the table.
+    ///
+    /// # Return
+    ///
+    /// True if changes were already being recorded before this call.
+    pub fn track_changes(&self) -> bool {
+        self.tracking.swap(true, Ordering::Relaxed)
+    }
+
+    /// This function records that an object was modified, bumping the table's version, and adding
+    /// the object's key to the set of changed keys if changes are being recorded. Objects written
+    /// through put() and delete() are recorded automatically; objects updated in place must be
+    /// recorded by the caller.
+    ///
+    /// Changes are recorded per bucket, and a key is only copied the first time it changes
+    /// between two calls to `take_changed()` (or `take_dirty()`).
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The key of the object that was modified.
+    pub fn mark_changed(&self, key: &[u8]) {
+        if self.watched.load(Ordering::SeqCst) {
+            self.version.fetch_add(1, Ordering::Release);
+        }
+
+        let tracking = self.tracking.load(Ordering::Relaxed);
+        let migrating = self.migrating.load(Ordering::Relaxed);
+        if !tracking && !migrating {
+            return;
+        }
+
         let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
-        let map = self.maps[bucket].read();
+        let mut changes = self.changes[bucket].lock();
 
-        // Perform the lookup, and return.
-        return map.get(key).and_then(| value | { Some(value.clone()) });
+        if tracking && !changes.changed.contains(key) {
+            changes.changed.insert(Bytes::from(key));
+        }
+
+        if migrating && !changes.dirty.contains(key) {
+            changes.dirty.insert(Bytes::from(key));
+        }
     }
 
-    /// This function writes an object into a table.
+    /// This function tries to latch a byte range of an object so that it can be updated in
+    /// place. Latches on disjoint ranges of an object can be held concurrently. This function
+    /// never blocks; if an overlapping range is latched, the caller should yield and retry later
+    /// instead of waiting, so that two updates can never deadlock.
     ///
     /// # Arguments
     ///
-    /// * `key`:    A Bytes wrapping the key for the object.
-    /// * `object`: A Bytes wrapping the entire object to be written to
-    ///             the table.
-    pub fn put(&self, key: Bytes, object: Bytes) {
-        // First, identify the bucket the key falls into.
-        let bucket: usize = key.slice(0, 1)[0] as usize & (N_BUCKETS - 1);
-        let mut map = self.maps[bucket].write();
+    /// * `object`: The object to be updated.
+    /// * `start`:  Offset of the first byte of the range within the object.
+    /// * `end`:    Offset of the byte after the last byte of the range within the object.
+    ///
+    /// # Return
+    ///
+    /// True if the range was latched. It must be released by a call to `unlatch()`.
+    pub fn try_latch(&self, object: &Bytes, start: usize, end: usize) -> bool {
+        let addr = object.as_ptr() as usize;
+        let mut latches = self.latches.lock();
 
-        // Next, remove the key from the hash map if it already exists.
-        if map.contains_key(&key) {
-            let _val = map.remove(&key);
+        let overlaps = latches
+            .iter()
+            .any(| &(a, s, e) | { a == addr && start < e && s < end });
+        if overlaps {
+            return false;
         }
 
-        // Perform the insert.
-        let _obj = map.insert(key, object);
+        latches.push((addr, start, end));
+        return true;
     }
 
-    /// This function deletes an object from a table.
+    /// This function releases a byte range previously latched through `try_latch()`.
     ///
     /// # Arguments
     ///
-    /// * `key`: The key of the object to be deleted, passed in as a slice of bytes.
-    pub fn delete(&self, key: &[u8]) {
-        // First, identify the bucket the key falls into.
-        let bucket: usize = key[0] as usize & (N_BUCKETS - 1);
-        let mut map = self.maps[bucket].write();
+    /// * `object`: The object that was updated.
+    /// * `start`:  Offset of the first byte of the range within the object.
+    /// * `end`:    Offset of the byte after the last byte of the range within the object.
+    pub fn unlatch(&self, object: &Bytes, start: usize, end: usize) {
+        let addr = object.as_ptr() as usize;
+        let mut latches = self.latches.lock();
 
-        // Next, remove the key from the hash map if it already exists.
-        if map.contains_key(key) {
-            let _val = map.remove(key);
+        if let Some(idx) = latches.iter().position(| l | { *l == (addr, start, end) }) {
+            latches.swap_remove(idx);
         }
     }
+
+    /// This function returns and clears the set of keys written to or deleted from the table
+    /// since the last call to this function.
+    pub fn take_changed(&self) -> HashSet<Bytes> {
+        let mut changed = HashSet::new();
+        for changes in self.changes.iter() {
+            changed.extend(changes.lock().changed.drain());
+        }
+
+        return changed;
+    }
+
+    /// This function starts or stops recording the keys of objects written to or deleted from
+    /// the table while it is being migrated. Stopping discards any keys recorded so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `on`: True if keys should be recorded from here on.
+    pub fn track_dirty(&self, on: bool) {
+        self.migrating.store(on, Ordering::Relaxed);
+
+        if !on {
+            for changes in self.changes.iter() {
+                changes.lock().dirty.clear();
+            }
+        }
+    }
+
+    /// This function returns the number of keys written to or deleted from the table since
+    /// migration began, or since the last call to `take_dirty()`.
+    pub fn dirty_len(&self) -> usize {
+        self.changes.iter().map(| changes | { changes.lock().dirty.len() }).sum()
+    }
+
+    /// This function returns and clears the set of keys written to or deleted from the table
+    /// since migration began, or since the last call to this function.
+    pub fn take_dirty(&self) -> HashSet<Bytes> {
+        let mut dirty = HashSet::new();
+        for changes in self.changes.iter() {
+            dirty.extend(changes.lock().dirty.drain());
+        }
+
+        return dirty;
+    }
 }
 
 // This module contains a few basic unit tests for Table. These tests are
@@ -193,8 +1488,13 @@ impl Table {
 // test basic functionality like reference counting etc.
 #[cfg(test)]
 mod tests {
-    use super::Table;
+    use super::{Found, Layout, Table, WriteStamp};
+    use alloc::Allocator;
     use bytes::{BufMut, Bytes, BytesMut};
+    use wireformat::TableKind;
+    use std::cell::Cell;
+    use std::sync::Arc;
+    use std::thread;
 
     // This unit test inserts a key-value pair into a table, performs a read
     // on the key, and asserts that the value matches. If the key was not found,
@@ -288,6 +1588,107 @@ mod tests {
         }
     }
 
+    // This function tests that swap() replaces an object only if it wasn't updated in between.
+    #[test]
+    fn test_swap() {
+        let table = Table::default();
+
+        let key: &[u8] = &[0; 30];
+
+        // Create two objects with the same key, but different values.
+        let mut objs = Vec::new();
+        for v in 1..3 {
+            let mut obj: BytesMut = BytesMut::with_capacity(key.len() + 30);
+            obj.put_slice(key);
+            obj.put_slice(&[v; 30]);
+            objs.push(obj.freeze());
+        }
+
+        // Add the first object to the table.
+        table.put(objs[0].slice(0, key.len()), objs[0].clone());
+
+        // A swap expecting the second object must fail, and one expecting the first must succeed.
+        assert!(!table.swap(objs[1].slice(0, key.len()), &objs[1], objs[1].clone()));
+        assert!(table.swap(objs[1].slice(0, key.len()), &objs[0], objs[1].clone()));
+        assert_eq!(&objs[1][..], &table.get(key).expect("Key not found.")[..]);
+    }
+
+    // This function tests that put_if() only writes an object if the one currently under the
+    // key passes the supplied check, and that it publishes a new version of the table.
+    #[test]
+    fn test_put_if() {
+        let table = Table::default();
+
+        let key: &[u8] = &[0; 30];
+
+        let mut objs = Vec::new();
+        for v in 1..3 {
+            let mut obj: BytesMut = BytesMut::with_capacity(key.len() + 30);
+            obj.put_slice(key);
+            obj.put_slice(&[v; 30]);
+            objs.push(obj.freeze());
+        }
+
+        // A write expecting the key to not exist succeeds the first time only.
+        let write = |obj: &Bytes| Some((obj.slice(0, key.len()), obj.clone()));
+        assert!(table.put_if(key, |curr| if curr.is_none() { write(&objs[0]) } else { None }));
+        assert!(!table.put_if(key, |curr| if curr.is_none() { write(&objs[1]) } else { None }));
+        assert_eq!(&objs[0][..], &table.get(key).expect("Key not found.")[..]);
+
+        // A write expecting the first object replaces it.
+        let version = table.version();
+        assert!(table.put_if(key, |curr| {
+            if curr.map_or(false, |c| c[..] == objs[0][..]) { write(&objs[1]) } else { None }
+        }));
+        assert_eq!(&objs[1][..], &table.get(key).expect("Key not found.")[..]);
+        assert!(table.version() > version);
+        assert_eq!(1, table.len());
+    }
+
+    // This function tests that move_to() relocates an object between tables only if it wasn't
+    // updated in between, and that an object cannot be moved into the table it is in.
+    #[test]
+    fn test_move_to() {
+        let src = Table::default();
+        let dst = Table::default();
+
+        let key: &[u8] = &[0; 30];
+
+        let mut objs = Vec::new();
+        for v in 1..3 {
+            let mut obj: BytesMut = BytesMut::with_capacity(key.len() + 30);
+            obj.put_slice(key);
+            obj.put_slice(&[v; 30]);
+            objs.push(obj.freeze());
+        }
+
+        src.put(objs[0].slice(0, key.len()), objs[0].clone());
+        let (v1, v2) = (src.version(), dst.version());
+        let write = | i: usize | { Some((objs[i].slice(0, key.len()), objs[i].clone())) };
+
+        // A move expecting the wrong object, or into the same table, must leave both untouched.
+        assert!(!src.move_to(&dst, key, &objs[1], || write(1)));
+        assert!(!src.move_to(&src, key, &objs[0], || write(1)));
+        assert_eq!(&objs[0][..], &src.get(key).expect("Key not found.")[..]);
+        assert_eq!(None, dst.get(key));
+
+        // So must a move that is called off by the closure.
+        assert!(!src.move_to(&dst, key, &objs[0], || None));
+        assert_eq!(&objs[0][..], &src.get(key).expect("Key not found.")[..]);
+        assert_eq!(None, dst.get(key));
+
+        // A move expecting the right object relocates it.
+        assert!(src.move_to(&dst, key, &objs[0], || write(1)));
+        assert_eq!(None, src.get(key));
+        assert_eq!(&objs[1][..], &dst.get(key).expect("Key not found.")[..]);
+        assert!(src.version() > v1 && dst.version() > v2);
+
+        // Moving it back requires the object in the destination.
+        assert!(!dst.move_to(&src, key, &objs[0], || write(0)));
+        assert!(dst.move_to(&src, key, &objs[1], || write(0)));
+        assert_eq!((1, 0), (src.len(), dst.len()));
+    }
+
     // This function tests that once deleted from a table, an object cannot be accessed again.
     #[test]
     fn test_delete() {
@@ -306,10 +1707,465 @@ mod tests {
         let key_ref: Bytes = obj.split_to(key.len());
         table.put(key_ref, obj);
 
-        // Next, delete the key from the table.
-        table.delete(key);
+        // A delete that could not be logged must leave the object in place.
+        assert_eq!(Err(()), table.delete_with(key, None, || { false }));
+        assert!(table.get(key).is_some());
+
+        // Next, delete the key from the table. The removed object is handed back only the first
+        // time around.
+        assert_eq!(Some(Bytes::from(val)), table.delete(key));
+        assert_eq!(None, table.delete(key));
 
         // Assert that the key was deleted.
         assert_eq!(None, table.get(key));
     }
+
+    // This test verifies that len() counts objects across all buckets of the table.
+    #[test]
+    fn test_len() {
+        let table = Table::default();
+        assert_eq!(0, table.len());
+
+        for i in 0..10u8 {
+            let key = Bytes::from(vec![i; 30]);
+            table.put(key.clone(), key);
+        }
+
+        assert_eq!(10, table.len());
+    }
+
+    // This test verifies that bytes() counts the size of every object in the table exactly
+    // once, across writes that replace an object, and deletes.
+    #[test]
+    fn test_bytes() {
+        let table = Table::default();
+        for i in 0..10u8 {
+            let key = Bytes::from(vec![i; 30]);
+            table.put(key.clone(), key);
+        }
+        assert_eq!(300, table.bytes());
+
+        // Replacing an object only counts the new one.
+        let key = Bytes::from(vec![0; 30]);
+        table.put(key.clone(), Bytes::from(vec![0; 40]));
+        assert_eq!(310, table.bytes());
+
+        assert!(table.delete(&key).is_some());
+        assert!(table.delete(&key).is_none());
+        assert_eq!(270, table.bytes());
+    }
+
+    // This test verifies that a table charges it's objects to it's quota once it is set, and
+    // credits them back as soon as they are replaced, deleted, or the table is dropped.
+    #[test]
+    fn test_quota() {
+        let mut heap = Allocator::new();
+        let quota = heap.set_group_quota(&[1], 1000);
+        let table = Table::default();
+        for i in 0..3u8 {
+            let key = Bytes::from(vec![i; 30]);
+            table.put(key.clone(), key);
+        }
+
+        assert!(table.set_quota(quota.clone()));
+        assert!(!table.set_quota(quota.clone()));
+        assert_eq!(vec![(90, 1000, 0)], heap.group_stats());
+
+        let key = Bytes::from(vec![0; 30]);
+        table.put(key.clone(), Bytes::from(vec![0; 40]));
+        assert_eq!(vec![(100, 1000, 0)], heap.group_stats());
+
+        assert!(table.delete(&key).is_some());
+        assert_eq!(vec![(60, 1000, 0)], heap.group_stats());
+
+        drop(table);
+        assert_eq!(vec![(0, 1000, 0)], heap.group_stats());
+    }
+
+    // This test verifies that sample() returns objects along with their keys, and never more
+    // than requested.
+    #[test]
+    fn test_sample() {
+        let table = Table::default();
+        for i in 0..10u8 {
+            let key = Bytes::from(vec![i; 30]);
+            table.put(key.clone(), Bytes::from(vec![i + 1; 40]));
+        }
+
+        let samples = table.sample(4, 0);
+        assert_eq!(4, samples.len());
+        for (key, object) in samples.into_iter() {
+            assert_eq!(vec![key[0] + 1; 40], object.to_vec());
+        }
+        assert_eq!(10, table.sample(1000, 7).len());
+    }
+
+    // This test verifies that reads are sampled only while sampling is enabled on the table.
+    #[test]
+    fn test_sampling() {
+        let table = Table::default();
+        let key: &[u8] = &[1; 30];
+        table.put(Bytes::from(key), Bytes::from(vec![2; 40]));
+
+        for _ in 0..(1 << 16) {
+            let _ = table.get(key);
+        }
+        assert!(table.take_samples().is_empty());
+
+        table.set_sampling(true);
+        for _ in 0..(1 << 16) {
+            let _ = table.get(key);
+        }
+        assert!(!table.take_samples().is_empty());
+
+        table.set_sampling(false);
+        for _ in 0..(1 << 16) {
+            let _ = table.get(key);
+        }
+        assert!(table.take_samples().is_empty());
+    }
+
+    // This test verifies that writes and deletes are recorded only once tracking is enabled.
+    #[test]
+    fn test_changed() {
+        let table = Table::default();
+
+        let first = Bytes::from(vec![1; 30]);
+        let second = Bytes::from(vec![2; 30]);
+
+        table.put(first.clone(), first.clone());
+        assert_eq!(0, table.take_changed().len());
+
+        assert!(!table.track_changes());
+        table.put(second.clone(), second.clone());
+        table.delete(&first);
+
+        let changed = table.take_changed();
+        assert_eq!(2, changed.len());
+        assert!(changed.contains(&first) && changed.contains(&second));
+        assert_eq!(0, table.take_changed().len());
+    }
+
+    // This test verifies that keys dirtied during a migration are recorded independently of
+    // those recorded for snapshots, and are discarded once the migration stops.
+    #[test]
+    fn test_dirty() {
+        let table = Table::default();
+
+        let first = Bytes::from(vec![1; 30]);
+        let second = Bytes::from(vec![2; 30]);
+
+        table.put(first.clone(), first.clone());
+        assert_eq!(0, table.take_dirty().len());
+
+        table.track_dirty(true);
+        table.put(second.clone(), second.clone());
+        assert_eq!(0, table.take_changed().len());
+
+        assert_eq!(1, table.dirty_len());
+        let dirty = table.take_dirty();
+        assert_eq!(1, dirty.len());
+        assert!(dirty.contains(&second));
+
+        table.delete(&first);
+        table.track_dirty(false);
+        assert_eq!(0, table.take_dirty().len());
+    }
+
+    // This test verifies that an ordered table returns the objects in a range in key order,
+    // including those written before the index was built, and that a table without an index
+    // cannot be scanned.
+    #[test]
+    fn test_range() {
+        let table = Table::default();
+        let key = |k: u8| Bytes::from(vec![k, k]);
+
+        table.put(key(4), key(4));
+        table.put(key(2), key(2));
+        assert!(table.range(&[0], None, 10).is_empty());
+        assert_eq!(TableKind::Hash, table.kind());
+
+        table.order();
+        assert_eq!(TableKind::Ordered, table.kind());
+        table.put(key(3), key(3));
+        table.put(key(1), key(1));
+        table.put(key(3), key(30));
+        table.delete(&key(4));
+
+        let objects = table.range(&[0], None, 10);
+        let expected = vec![(key(1), key(1)), (key(2), key(2)), (key(3), key(30))];
+        assert_eq!(expected, objects);
+
+        assert_eq!(vec![(key(2), key(2))], table.range(&key(2), Some(&key(3)[..]), 10));
+        assert_eq!(vec![(key(1), key(1))], table.range(&[0], None, 1));
+        assert!(table.range(&key(3), Some(&key(2)[..]), 10).is_empty());
+        assert!(table.range(&key(3), Some(&key(3)[..]), 10).is_empty());
+    }
+
+    // This test verifies that the version of a table changes on writes and deletes once it has
+    // been read, but not on reads or deletes of keys that do not exist.
+    #[test]
+    fn test_version() {
+        let table = Table::default();
+        let key = Bytes::from(vec![1; 30]);
+        table.put(key.clone(), key.clone());
+        assert_eq!(0, table.version());
+
+        table.put(key.clone(), key.clone());
+        let version = table.version();
+        assert!(version != 0);
+
+        let _ = table.get(&key);
+        table.delete(&[2; 30]);
+        assert_eq!(version, table.version());
+
+        table.delete(&key);
+        assert!(version != table.version());
+    }
+
+    // This test verifies that overlapping byte ranges of an object cannot be latched together,
+    // while disjoint ranges and ranges of other objects can.
+    #[test]
+    fn test_latch() {
+        let table = Table::default();
+        let first = Bytes::from(vec![1; 64]);
+        let second = Bytes::from(vec![2; 64]);
+
+        assert!(table.try_latch(&first, 0, 16));
+        assert!(table.try_latch(&first, 16, 32));
+        assert!(!table.try_latch(&first, 8, 24));
+        assert!(table.try_latch(&second, 8, 24));
+
+        table.unlatch(&first, 0, 16);
+        assert!(table.try_latch(&first, 0, 8));
+        assert!(!table.try_latch(&first, 31, 40));
+    }
+
+    // This test verifies that puts issued in order on one thread are observed in that order on
+    // another: once a key is visible, every key written before it is as well.
+    #[test]
+    fn test_put_ordering() {
+        let table = Arc::new(Table::default());
+        let keys: Vec<Bytes> = (0..1024u32)
+            .map(|i| Bytes::from(vec![(i & 0xff) as u8, (i >> 8) as u8, 1, 2]))
+            .collect();
+
+        let writer = {
+            let table = Arc::clone(&table);
+            let keys = keys.clone();
+            thread::spawn(move || {
+                for key in keys.into_iter() {
+                    table.put(key.clone(), key);
+                }
+            })
+        };
+
+        // Scan from the last key to the first; a key visible to the scan implies all earlier ones.
+        while table.get(&keys[keys.len() - 1]).is_none() {
+            let latest = keys.iter().rposition(|key| table.get(key).is_some());
+            if let Some(latest) = latest {
+                assert!(keys[..latest].iter().all(|key| table.get(key).is_some()));
+            }
+        }
+
+        writer.join().expect("Writer panicked.");
+        assert_eq!(keys.len(), table.len());
+    }
+
+    // This test verifies that puts issued before a key was deleted are rejected, that later ones
+    // go through and supersede the tombstone, and that purged tombstones stop guarding the key.
+    #[test]
+    fn test_tombstones() {
+        let table = Table::default();
+        let key = Bytes::from(vec![3; 30]);
+        let write = || Some((key.clone(), key.clone()));
+
+        // Stamps of requests by client 1, received at a given time, and stamped with a given
+        // value off the client's clock.
+        let by = |arrival, stamp| WriteStamp { arrival: arrival, client: 1, stamp: stamp };
+
+        assert!(table.put_stamped(&key, &by(100, 10), &write));
+        table.delete_stamped(&key, &by(200, 20));
+        assert!(table.get(&key).is_none());
+        assert_eq!(1, table.tombstones());
+
+        // A write received before the delete, or issued before it by the same client, is stale.
+        assert!(!table.put_stamped(&key, &by(150, 30), &write));
+        assert!(!table.put_stamped(&key, &by(300, 15), &write));
+        assert!(table.get(&key).is_none());
+
+        // A write received after the delete goes through, and supersedes the tombstone.
+        assert!(table.put_stamped(&key, &by(300, 25), &write));
+        assert!(table.get(&key).is_some());
+        assert_eq!(0, table.tombstones());
+
+        table.delete_stamped(&key, &by(400, 30));
+        assert_eq!(0, table.purge_tombstones(0));
+        assert_eq!(1, table.purge_tombstones(u64::max_value()));
+        assert_eq!(0, table.tombstones());
+        assert!(table.put_stamped(&key, &by(50, 5), &write));
+    }
+
+    // This test verifies that the stamps of different clients are not compared, so that a write
+    // from a client whose clock lags goes through once the server received it after the delete.
+    #[test]
+    fn test_tombstones_clients() {
+        let table = Table::default();
+        let key = Bytes::from(vec![5; 30]);
+        let write = || Some((key.clone(), key.clone()));
+
+        let ahead = WriteStamp { arrival: 100, client: 1, stamp: 1000000 };
+        let behind = WriteStamp { arrival: 200, client: 2, stamp: 10 };
+        let early = WriteStamp { arrival: 50, client: 2, stamp: 20 };
+
+        table.delete_stamped(&key, &ahead);
+        assert!(!table.put_stamped(&key, &early, &write));
+        assert!(table.put_stamped(&key, &behind, &write));
+        assert!(table.get(&key).is_some());
+    }
+
+    // This test verifies that sweeping a table's buckets removes only the objects that have
+    // expired, and that every bucket gets swept.
+    #[test]
+    fn test_expire() {
+        let table = Table::default();
+        for i in 0..256 {
+            let key = Bytes::from(vec![i as u8; 30]);
+            table.put(key.clone(), key);
+        }
+
+        let expired = | object: &Bytes | { object[0] % 2 == 0 };
+        let removed: usize = (0..table.buckets())
+            .map(| bucket | { table.expire(bucket, &expired) })
+            .sum();
+
+        assert_eq!(128, removed);
+        assert_eq!(128, table.len());
+        assert!(table.get(&[2; 30]).is_none());
+        assert!(table.get(&[3; 30]).is_some());
+        assert_eq!(0, table.expire(2, &expired));
+    }
+
+    // Returns an object laid out the way `test_inline()` expects: a key length at offset zero, a
+    // version at offset two, followed by the key and value.
+    fn inline_object(key: &[u8], val: &[u8], version: u64) -> Bytes {
+        let mut object = BytesMut::with_capacity(10 + key.len() + val.len());
+        object.put_u16_le(key.len() as u16);
+        object.put_u64_le(version);
+        object.put_slice(key);
+        object.put_slice(val);
+        object.freeze()
+    }
+
+    // This test verifies that small values are returned inline along with their version, that
+    // larger ones are returned as objects, and that in place updates reach the inline copy once
+    // the key is refreshed.
+    #[test]
+    fn test_inline() {
+        let layout = Layout {
+            key_len: 0,
+            meta: 10,
+            version: 2,
+            expiry: None,
+        };
+        let table = Table::with_inline(8, layout);
+
+        let small = inline_object(&[1; 4], &[7; 8], 3);
+        let large = inline_object(&[2; 4], &[9; 9], 4);
+        table.put(Bytes::from(&[1u8; 4][..]), small.clone());
+        table.put(Bytes::from(&[2u8; 4][..]), large.clone());
+
+        match table.lookup(&[1; 4]) {
+            Some(Found::Inline(inline)) => {
+                assert_eq!(&[7; 8], inline.value());
+                assert_eq!(3, inline.version);
+                assert_eq!(0, inline.expires);
+            }
+            _ => panic!("Small value was not kept inline."),
+        }
+
+        match table.lookup(&[2; 4]) {
+            Some(Found::Object(object)) => assert_eq!(large, object),
+            _ => panic!("Large value was kept inline."),
+        }
+
+        // Both kinds of objects are still returned by get().
+        assert_eq!(Some(small.clone()), table.get(&[1; 4]));
+        assert!(table.lookup(&[3; 4]).is_none());
+
+        // Update the small value in place, the way extensions do.
+        unsafe { *(small.as_ptr() as *mut u8).offset(14) = 5 };
+        table.refresh(&[1; 4]);
+        match table.lookup(&[1; 4]) {
+            Some(Found::Inline(inline)) => assert_eq!(&[5, 7, 7, 7, 7, 7, 7, 7], inline.value()),
+            _ => panic!("Small value was not kept inline."),
+        }
+
+        // A table without inline values always returns objects.
+        let plain = Table::with_inline(0, layout);
+        plain.put(Bytes::from(&[1u8; 4][..]), small.clone());
+        match plain.lookup(&[1; 4]) {
+            Some(Found::Object(object)) => assert_eq!(small, object),
+            _ => panic!("Value was kept inline on a table without inline values."),
+        }
+    }
+
+    // Tests that keys sharing a bucket and all but their last byte are told apart, and that
+    // every operation finds a key off the fingerprint it was written with.
+    #[test]
+    fn test_fingerprints() {
+        let table = Table::default();
+        let key = |i: u8| {
+            let mut key = [0u8; 30];
+            key[29] = i;
+            Bytes::from(&key[..])
+        };
+
+        for i in 0..200 {
+            table.put(key(i), Bytes::from(vec![i; 10]));
+        }
+
+        assert_eq!(200, table.len());
+        for i in 0..200 {
+            assert_eq!(vec![i; 10], &table.get(&key(i)).expect("Key not found.")[..]);
+        }
+        assert_eq!(None, table.get(&key(200)));
+
+        assert_eq!(false, table.swap(key(1), &Bytes::from(vec![1; 10]), Bytes::from(vec![0; 1])));
+        let old = table.get(&key(1)).expect("Key not found.");
+        assert!(table.swap(key(1), &old, Bytes::from(vec![2; 10])));
+        assert_eq!(&[2; 10], &table.get(&key(1)).expect("Key not found.")[..]);
+
+        assert!(table.delete(&key(2)).is_some());
+        assert_eq!(None, table.get(&key(2)));
+        assert_eq!(199, table.len());
+    }
+
+    // Tests that a snapshot returns the objects under every key along with missing keys, and
+    // gives up on keys whose version changes between every read.
+    #[test]
+    fn test_snapshot() {
+        let table = Table::default();
+        table.put(Bytes::from(&[1u8; 30][..]), Bytes::from(vec![1; 10]));
+        table.put(Bytes::from(&[2u8; 30][..]), Bytes::from(vec![2; 10]));
+
+        let keys: Vec<&[u8]> = vec![&[1u8; 30][..], &[3u8; 30][..], &[2u8; 30][..]];
+        let objects = table.snapshot(&keys, 0, | _ | { 7 }).expect("Snapshot aborted.");
+        assert_eq!(3, objects.len());
+        assert_eq!(Some(Bytes::from(vec![1; 10])), objects[0]);
+        assert_eq!(None, objects[1]);
+        assert_eq!(Some(Bytes::from(vec![2; 10])), objects[2]);
+
+        // A version that changes on every read looks like a concurrent in place update.
+        let versions = Cell::new(0);
+        let bumped = | _: &Bytes | {
+            versions.set(versions.get() + 1);
+            versions.get()
+        };
+        assert!(table.snapshot(&keys, 3, bumped).is_none());
+
+        // Each of the four attempts reads the version of both objects, and stops checking at the
+        // first one.
+        assert_eq!(12, versions.get());
+    }
 }
@@ -17,6 +17,8 @@ use e2d2::interface::Packet;
 use e2d2::headers::UdpHeader;
 use e2d2::common::EmptyMetadata;
 
+use super::trace::RequestId;
+
 /// This enum represents the different states a task can be in.
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq)]
@@ -47,8 +49,48 @@ pub enum TaskPriority {
     /// task is responsible for all network processing.
     DISPATCH = 0x01,
 
+    /// The priority of a task corresponding to an administrative RPC request (ex: creating or
+    /// deleting a tenant). Such tasks are run as system tasks, ahead of tenant requests.
+    ADMIN = 0x02,
+
     /// The priority of a task corresponding to an RPC request.
-    REQUEST = 0x02,
+    REQUEST = 0x03,
+
+    /// The priority of a background task (ex: reclaiming expired objects). Lowest in the system;
+    /// such tasks only run on a core that has no requests to run.
+    BACKGROUND = 0x04,
+}
+
+/// This enum represents the cost class an extension declares in it's manifest at install. It is a
+/// hint to the scheduler about how long the extension runs between yields.
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CostClass {
+    /// The extension did not declare a cost class. Also the class of every native task.
+    UNKNOWN = 0x00,
+
+    /// The extension runs for a few microseconds at most (ex: a handful of gets).
+    SHORT = 0x01,
+
+    /// The extension runs for tens of microseconds (ex: traversals and aggregations).
+    MEDIUM = 0x02,
+
+    /// The extension runs for long stretches, possibly a millisecond or more, between yields.
+    LONG = 0x03,
+}
+
+// Implementation of methods on CostClass.
+impl CostClass {
+    /// Returns the cost class corresponding to a value on an install() request, if any.
+    pub fn from_u8(class: u8) -> Option<CostClass> {
+        match class {
+            0x00 => Some(CostClass::UNKNOWN),
+            0x01 => Some(CostClass::SHORT),
+            0x02 => Some(CostClass::MEDIUM),
+            0x03 => Some(CostClass::LONG),
+            _ => None,
+        }
+    }
 }
 
 /// This trait consists of methods that will allow a type to be run as a task
@@ -98,4 +140,95 @@ pub trait Task {
         Packet<UdpHeader, EmptyMetadata>,
         Packet<UdpHeader, EmptyMetadata>,
     )>;
+
+    /// When called after `tear()`, this method should return any response packets the task
+    /// produced beyond the one returned by `tear()` (ex: the tail of a result too large to fit
+    /// in a single packet), in the order they should be sent out. Every packet is parsed upto
+    /// it's UDP header.
+    ///
+    /// # Return
+    ///
+    /// A vector of response packets. Empty for most tasks.
+    unsafe fn tear_more(&mut self) -> Vec<Packet<UdpHeader, EmptyMetadata>> {
+        Vec::new()
+    }
+
+    /// When called, this method should return the correlation id of the request the task was
+    /// created for, if the request is being traced.
+    ///
+    /// # Return
+    ///
+    /// The correlation id of the task's request. None if the request is not being traced, or
+    /// if the task does not correspond to a request (ex: Dispatch).
+    fn id(&self) -> Option<RequestId> {
+        None
+    }
+
+    /// When called after `run()` returns with the task in the YIELDED state, this method
+    /// should return the time before which the task need not run again, if any. The scheduler
+    /// parks such tasks on it's timer wheel instead of polling them in the meantime (ex: a task
+    /// backing off before a retry).
+    ///
+    /// # Return
+    ///
+    /// The time stamp in cycles at which the task should be woken up. None if the task should be
+    /// run again as soon as possible.
+    fn wake(&self) -> Option<u64> {
+        None
+    }
+
+    /// When called, this method should return the cost class declared for the task's code. The
+    /// scheduler uses it to tell a task that was declared long-running apart from one that is
+    /// misbehaving.
+    ///
+    /// # Return
+    ///
+    /// The cost class of the task. UNKNOWN for tasks that did not declare one.
+    fn cost(&self) -> CostClass {
+        CostClass::UNKNOWN
+    }
+
+    /// When called, this method should return a hint identifying the core whose caches are
+    /// likely to hold the data the task will touch (ex: the partition of the key it looks up).
+    /// A scheduler maps the hint onto one of the server's cores, steals such tasks towards that
+    /// core, and avoids stealing them away from it unless the cores are badly imbalanced.
+    ///
+    /// # Return
+    ///
+    /// The affinity hint of the task. None if the task can run anywhere.
+    fn affinity(&self) -> Option<usize> {
+        None
+    }
+
+    /// When called, this method should return the time by which the task should complete, if it
+    /// has a deadline. Schedulers that order tasks by deadline run tasks with earlier deadlines
+    /// first (refer to `RoundRobin::with_deadlines()`); others ignore it.
+    ///
+    /// # Return
+    ///
+    /// The time stamp in cycles by which the task should complete. None if it has no deadline.
+    fn deadline(&self) -> Option<u64> {
+        None
+    }
+
+    /// When called, this method should attach a deadline to the task. The dispatcher calls it
+    /// on every task created for a request, with the time the request arrived at plus the
+    /// deadline on it's header. Tasks that cannot carry a deadline ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline`: The time stamp in cycles by which the task should complete.
+    fn set_deadline(&mut self, _deadline: u64) {}
+
+    /// When called after `tear()`, this method should return a task that must run once this one
+    /// is done, if any (ex: a shadow invocation of an extension that is compared against the
+    /// one that just completed). The scheduler queues it right away, on behalf of the same
+    /// tenant. Called at most once.
+    ///
+    /// # Return
+    ///
+    /// The task to be run next. None for most tasks.
+    fn successor(&mut self) -> Option<Box<Task>> {
+        None
+    }
 }
@@ -14,13 +14,25 @@
  */
 
 use std::sync::Arc;
+use std::cell::Cell;
 use std::collections::HashMap;
 
-use super::table::Table;
+use super::alloc::{Allocator, Quota};
+use super::assoc::{Assoc, AssocList};
+use super::table::{Layout, Table};
 use super::common::{TableId, TenantId};
+use super::wireformat::RpcStatus;
+
+use bytes::Bytes;
+
+use sandstorm::schema::Schema;
 
 use spin::RwLock;
 
+// The number of times move_key() retries a move that raced with a concurrent write to the key
+// before giving up.
+const MOVE_ATTEMPTS: usize = 4;
+
 /// This type represents a tenant in Sandstorm. It helps uniquely identify
 /// a tenant, and maintains a map of all the data tables belonging to a
 /// particular tenant.
@@ -31,6 +43,23 @@ pub struct Tenant {
     /// A map of all the data tables belonging to a tenant. Each data table
     /// has a unique identifier.
     tables: RwLock<HashMap<TableId, Arc<Table>>>,
+
+    /// The association lists belonging to a tenant, identified by the object
+    /// they originate at and their type. Serviced by the native association
+    /// RPCs. These lists live only in memory.
+    assocs: RwLock<HashMap<(u64, u16), AssocList>>,
+
+    /// The schemas the tenant registered for it's tables, describing the
+    /// layout of the values on them. Extensions look these up by table.
+    schemas: RwLock<HashMap<TableId, Arc<Schema>>>,
+
+    /// The largest value that tables created for the tenant keep inline, and
+    /// the layout of their objects. Refer to `Table::with_inline()`.
+    inline: (usize, Layout),
+
+    /// The memory quota of the group the tenant belongs to, if any. Every
+    /// table of the tenant charges it's objects to it. Refer to `set_quota()`.
+    quota: RwLock<Option<Arc<Quota>>>,
 }
 
 // Implementation of methods on tenant.
@@ -47,9 +76,30 @@ impl Tenant {
     ///
     /// A `Tenant` representing a tenant in the system.
     pub fn new(id: TenantId) -> Tenant {
+        Tenant::with_inline(id, 0, Layout::default())
+    }
+
+    /// This method returns a new tenant whose tables keep small values inline.
+    /// Refer to `Table::with_inline()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`:     A unique identifier for the new tenant.
+    /// * `max`:    Values of at most this many bytes are kept inline. Zero
+    ///             keeps no value inline.
+    /// * `layout`: The layout of objects written to the tenant's tables.
+    ///
+    /// # Return
+    ///
+    /// A `Tenant` representing a tenant in the system.
+    pub fn with_inline(id: TenantId, max: usize, layout: Layout) -> Tenant {
         Tenant {
             id: id,
             tables: RwLock::new(HashMap::new()),
+            assocs: RwLock::new(HashMap::new()),
+            schemas: RwLock::new(HashMap::new()),
+            inline: (max, layout),
+            quota: RwLock::new(None),
         }
     }
 
@@ -74,7 +124,27 @@ impl Tenant {
         let mut map = self.tables.write();
 
         // Insert a new table and return.
-        map.insert(table_id, Arc::new(Table::default()));
+        let (max, layout) = self.inline;
+        let table = Table::with_inline(max, layout);
+        if let Some(ref quota) = *self.quota.read() {
+            table.set_quota(Arc::clone(quota));
+        }
+        map.insert(table_id, Arc::new(table));
+    }
+
+    /// This method charges the objects in the tenant's tables, including tables
+    /// created from here on, to the memory quota of the group the tenant belongs
+    /// to. Refer to `Table::set_quota()`. Must be called before the tenant is
+    /// shared, and at most once.
+    ///
+    /// # Arguments
+    ///
+    /// * `quota`: The quota of the tenant's group.
+    pub fn set_quota(&self, quota: Arc<Quota>) {
+        for table in self.tables.read().values() {
+            table.set_quota(Arc::clone(&quota));
+        }
+        *self.quota.write() = Some(quota);
     }
 
     /// This method returns a table belonging to the tenant if it exists.
@@ -93,4 +163,254 @@ impl Tenant {
         // Lookup on table_id and return.
         map.get(&table_id).and_then(| table | { Some(Arc::clone(&table)) })
     }
+
+    /// This method removes a table from the tenant along with any schema
+    /// registered for it. Requests that have already looked up the table
+    /// continue to hold it.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id`: The identifier for the table to be removed.
+    ///
+    /// # Return
+    ///
+    /// An atomic reference counted handle to the removed table if it existed.
+    pub fn drop_table(&self, table_id: TableId) -> Option<Arc<Table>> {
+        // Remove any schema first, so that it never outlives the table.
+        self.schemas.write().remove(&table_id);
+
+        // Acquire a write lock, and remove the table.
+        self.tables.write().remove(&table_id)
+    }
+
+    /// This method returns all tables belonging to the tenant.
+    ///
+    /// # Return
+    ///
+    /// A vector of table identifiers, and atomic reference counted handles to
+    /// the corresponding tables.
+    pub fn tables(&self) -> Vec<(TableId, Arc<Table>)> {
+        // Acquire a read lock.
+        let map = self.tables.read();
+
+        // Clone out every table and return.
+        map.iter()
+            .map(| (id, table) | { (*id, Arc::clone(table)) })
+            .collect()
+    }
+
+    /// This method registers a schema for one of the tenant's tables,
+    /// replacing any schema registered for it earlier. Extensions that have
+    /// already looked up the earlier schema continue to hold it.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id`: The identifier for the table.
+    /// * `schema`:   The schema to register. If `None`, any registered schema
+    ///               is removed.
+    ///
+    /// # Return
+    ///
+    /// False if the tenant does not have a table with the passed in
+    /// identifier.
+    pub fn set_schema(&self, table_id: TableId, schema: Option<Schema>) -> bool {
+        if self.get_table(table_id).is_none() {
+            return false;
+        }
+
+        // Acquire a write lock.
+        let mut map = self.schemas.write();
+        match schema {
+            Some(schema) => map.insert(table_id, Arc::new(schema)),
+            None => map.remove(&table_id),
+        };
+
+        true
+    }
+
+    /// This method returns the schema registered for one of the tenant's
+    /// tables if there is one.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id`: The identifier for the table.
+    ///
+    /// # Return
+    ///
+    /// An atomic reference counted handle to the schema if it exists.
+    pub fn schema(&self, table_id: TableId) -> Option<Arc<Schema>> {
+        // Acquire a read lock.
+        let map = self.schemas.read();
+
+        map.get(&table_id).map(| schema | { Arc::clone(schema) })
+    }
+
+    /// This method atomically moves an object from one of the tenant's tables
+    /// into another, replacing any object under the same key in the
+    /// destination. Readers observe the object in exactly one of the two
+    /// tables at any point in time. If the move fails, neither table is
+    /// modified.
+    ///
+    /// The move is persisted by committing the object to the destination
+    /// before committing it's deletion from the source, so a restart in
+    /// between recovers the object in both tables rather than in neither.
+    ///
+    /// # Arguments
+    ///
+    /// * `heap`:      The allocator the tenant's objects are allocated from.
+    /// * `src_table`: The table the object is currently in.
+    /// * `dst_table`: The table the object should be moved into.
+    /// * `key`:       The key of the object.
+    ///
+    /// # Return
+    ///
+    /// `StatusOk` if the object was moved. Otherwise, the reason it wasn't.
+    pub fn move_key(&self, heap: &Allocator, src_table: TableId, dst_table: TableId, key: &[u8])
+                    -> RpcStatus
+    {
+        let (src, dst) = match (self.get_table(src_table), self.get_table(dst_table)) {
+            (Some(src), Some(dst)) => (src, dst),
+            _ => return RpcStatus::StatusTableDoesNotExist,
+        };
+
+        if src_table == dst_table || key.len() == 0 {
+            return RpcStatus::StatusMalformedRequest;
+        }
+
+        for _ in 0..MOVE_ATTEMPTS {
+            let object = match src.get(key) {
+                Some(object) => object,
+                None => return RpcStatus::StatusObjectDoesNotExist,
+            };
+
+            if heap.is_expired(&object) {
+                return RpcStatus::StatusObjectDoesNotExist;
+            }
+
+            // Do not launder a corrupted value under a fresh checksum.
+            if !heap.checksum_ok(&object) {
+                return RpcStatus::StatusCorruptObject;
+            }
+
+            // The copy must carry the destination table in it's metadata.
+            let (k, copy) = match heap.relocate(self.id, dst_table, &object) {
+                Some(relocated) => relocated,
+                None => return RpcStatus::StatusInternalError,
+            };
+
+            // Persist the move with the key's bucket locked in both tables, so that it is logged
+            // in the order that it is applied.
+            let refused = Cell::new(false);
+            let moved = src.move_to(&dst, key, &object, || {
+                let committed = heap.commit(k, copy);
+                if committed.is_some() && heap.commit_delete(self.id, src_table, key) {
+                    return committed;
+                }
+
+                refused.set(true);
+                None
+            });
+
+            if refused.get() {
+                return RpcStatus::StatusLogFull;
+            }
+
+            // If the key was written to since the lookup above, retry with the new object.
+            if !moved {
+                continue;
+            }
+
+            return RpcStatus::StatusOk;
+        }
+
+        RpcStatus::StatusInternalError
+    }
+
+    /// This method adds an association to one of the tenant's association
+    /// lists, replacing any existing association to the same object. The
+    /// list is created if it does not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `id1`:   The object the association originates at.
+    /// * `atype`: The type of the association.
+    /// * `id2`:   The object the association points to.
+    /// * `time`:  The time of the association.
+    /// * `data`:  Data attached to the association.
+    ///
+    /// # Return
+    ///
+    /// The number of associations on the list after the add.
+    pub fn assoc_add(&self, id1: u64, atype: u16, id2: u64, time: u64, data: Bytes) -> usize {
+        // Acquire a write lock.
+        let mut map = self.assocs.write();
+
+        let list = map.entry((id1, atype)).or_insert_with(AssocList::new);
+        list.add(id2, time, data);
+        list.len()
+    }
+
+    /// This method deletes an association from one of the tenant's
+    /// association lists. Empty lists are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `id1`:   The object the association originates at.
+    /// * `atype`: The type of the association.
+    /// * `id2`:   The object the association points to.
+    ///
+    /// # Return
+    ///
+    /// True if the association existed.
+    pub fn assoc_del(&self, id1: u64, atype: u16, id2: u64) -> bool {
+        // Acquire a write lock.
+        let mut map = self.assocs.write();
+
+        let (found, empty) = match map.get_mut(&(id1, atype)) {
+            Some(list) => (list.del(id2), list.len() == 0),
+            None => (false, false),
+        };
+
+        if empty {
+            map.remove(&(id1, atype));
+        }
+
+        found
+    }
+
+    /// This method returns associations off one of the tenant's association
+    /// lists that are no newer than a given time, newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `id1`:   The object the associations originate at.
+    /// * `atype`: The type of the associations.
+    /// * `high`:  The time of the newest association that can be returned.
+    /// * `limit`: The maximum number of associations to return.
+    ///
+    /// # Return
+    ///
+    /// Upto `limit` associations. Empty if the list does not exist.
+    pub fn assoc_range(&self, id1: u64, atype: u16, high: u64, limit: usize) -> Vec<Assoc> {
+        // Acquire a read lock.
+        let map = self.assocs.read();
+
+        map.get(&(id1, atype))
+            .map(| list | { list.range(high, limit) })
+            .unwrap_or(Vec::new())
+    }
+
+    /// This method returns the number of associations on one of the tenant's
+    /// association lists.
+    ///
+    /// # Arguments
+    ///
+    /// * `id1`:   The object the associations originate at.
+    /// * `atype`: The type of the associations.
+    pub fn assoc_count(&self, id1: u64, atype: u16) -> usize {
+        // Acquire a read lock.
+        let map = self.assocs.read();
+
+        map.get(&(id1, atype)).map_or(0, | list | { list.len() })
+    }
 }
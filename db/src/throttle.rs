@@ -0,0 +1,105 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A token bucket shared by every core, admitting operations at a sustained rate with bursts of
+/// upto a fixed size. Implemented as a virtual scheduling (GCRA) bucket, so that the entire state
+/// is a single time stamp that can be updated with a compare-and-swap instead of a lock.
+pub struct TokenBucket {
+    // The time in cycles it takes for a single token to be added to the bucket.
+    interval: u64,
+
+    // How far in cycles the bucket's time stamp can run ahead of the current time. Corresponds to
+    // a full bucket less the token being taken.
+    tolerance: u64,
+
+    // The time stamp in cycles at which the bucket would next be full if no more operations were
+    // admitted, i.e, the theoretical arrival time of the next operation.
+    tat: AtomicUsize,
+}
+
+// Implementation of methods on TokenBucket.
+impl TokenBucket {
+    /// Creates a full token bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate`:   The sustained number of operations admitted every second. Must be non-zero.
+    /// * `burst`:  The maximum number of operations admitted back to back. At least one.
+    /// * `cycles`: The number of cycles in a second.
+    pub fn new(rate: u64, burst: u64, cycles: u64) -> TokenBucket {
+        let interval = (cycles / rate).max(1);
+
+        TokenBucket {
+            interval: interval,
+            tolerance: interval * (burst.max(1) - 1),
+            tat: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes a token from the bucket if one is available.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: The current time stamp in cycles.
+    ///
+    /// # Return
+    ///
+    /// True if the operation should be admitted.
+    pub fn admit(&self, now: u64) -> bool {
+        let mut tat = self.tat.load(Ordering::Relaxed);
+
+        loop {
+            let base = (tat as u64).max(now);
+            if base - now > self.tolerance {
+                return false;
+            }
+
+            let next = (base + self.interval) as usize;
+            match self.tat.compare_exchange_weak(tat, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return true,
+                Err(current) => tat = current,
+            }
+        }
+    }
+}
+
+// This module contains simple unit tests for TokenBucket.
+#[cfg(test)]
+mod tests {
+    use super::TokenBucket;
+
+    // This test verifies that a bucket admits a full burst, then admits operations at the
+    // configured rate.
+    #[test]
+    fn test_token_bucket() {
+        // 100 operations per second at a thousand cycles a second, i.e, one every ten cycles.
+        let bucket = TokenBucket::new(100, 5, 1000);
+
+        let start = 1000000;
+        let admitted = (0..10).filter(|_| bucket.admit(start)).count();
+        assert_eq!(5, admitted);
+
+        assert!(!bucket.admit(start + 9));
+        assert!(bucket.admit(start + 10));
+        assert!(!bucket.admit(start + 10));
+
+        // After sitting idle, the bucket refills upto it's burst and no further.
+        let later = start + 100000;
+        let admitted = (0..10).filter(|_| bucket.admit(later)).count();
+        assert_eq!(5, admitted);
+    }
+}
@@ -0,0 +1,228 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::mem;
+
+/// The number of bits of a deadline used to index the slots of a level.
+const LEVEL_BITS: u32 = 6;
+
+/// The number of slots on every level of the wheel.
+const LEVEL_SLOTS: usize = 1 << LEVEL_BITS;
+
+/// The number of levels on the wheel. Deadlines further out than LEVEL_SLOTS^LEVELS ticks are
+/// held on an overflow list until they come within range.
+const LEVELS: usize = 4;
+
+/// A hierarchical timer wheel, holding items until a deadline. Level zero has one slot per tick,
+/// and every slot on a level above covers all the slots of the level below it. Items are added to
+/// the lowest level whose range covers their deadline, and cascade down a level every time the
+/// wheel turns through the slot they are on, so that adding an item and expiring it take constant
+/// time irrespective of how many items are on the wheel.
+pub struct TimerWheel<T> {
+    // The duration of a tick in cycles. Deadlines are rounded up to a tick.
+    tick: u64,
+
+    // The tick upto which the wheel has been advanced. Items with a deadline at or before this
+    // tick have been expired.
+    current: u64,
+
+    // Slots on every level. Each entry holds an item along with it's deadline in ticks.
+    levels: Vec<Vec<Vec<(u64, T)>>>,
+
+    // Items whose deadline is beyond the range of the highest level.
+    overflow: Vec<(u64, T)>,
+
+    // The number of items on the wheel.
+    len: usize,
+}
+
+// Implementation of methods on TimerWheel.
+impl<T> TimerWheel<T> {
+    /// Returns an empty timer wheel.
+    ///
+    /// # Arguments
+    ///
+    /// * `tick`: The granularity of the wheel in cycles. Must be non-zero.
+    /// * `now`:  The current time in cycles.
+    pub fn new(tick: u64, now: u64) -> TimerWheel<T> {
+        TimerWheel {
+            tick: tick,
+            current: now / tick,
+            levels: (0..LEVELS)
+                .map(|_| (0..LEVEL_SLOTS).map(|_| Vec::new()).collect())
+                .collect(),
+            overflow: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of items on the wheel.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Adds an item to the wheel.
+    ///
+    /// # Arguments
+    ///
+    /// * `wake`: The time in cycles at which the item expires. Items with a deadline that has
+    ///           already passed expire on the next tick.
+    /// * `item`: The item.
+    pub fn insert(&mut self, wake: u64, item: T) {
+        let deadline = ((wake + self.tick - 1) / self.tick).max(self.current + 1);
+        self.len += 1;
+        self.place(deadline, item);
+    }
+
+    /// Advances the wheel upto a point in time, expiring items along the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: The current time in cycles.
+    ///
+    /// # Return
+    ///
+    /// Items whose deadline is at or before `now`, in the order they expired.
+    pub fn advance(&mut self, now: u64) -> Vec<T> {
+        let target = now / self.tick;
+        let mut expired = Vec::new();
+
+        // Nothing to turn through. Skip ahead, so that a wheel that was empty for a long time
+        // doesn't have to walk every tick it missed.
+        if self.len == 0 {
+            self.current = self.current.max(target);
+            return expired;
+        }
+
+        while self.current < target && self.len > 0 {
+            self.current += 1;
+            let tick = self.current;
+
+            // Cascade higher levels down whenever the level below them wraps around.
+            for level in 1..LEVELS + 1 {
+                if tick & ((1 << (LEVEL_BITS * level as u32)) - 1) != 0 {
+                    break;
+                }
+
+                let items = if level == LEVELS {
+                    mem::replace(&mut self.overflow, Vec::new())
+                } else {
+                    let slot = Self::slot(tick, level);
+                    mem::replace(&mut self.levels[level][slot], Vec::new())
+                };
+
+                for (deadline, item) in items.into_iter() {
+                    if deadline <= tick {
+                        self.len -= 1;
+                        expired.push(item);
+                    } else {
+                        self.place(deadline, item);
+                    }
+                }
+            }
+
+            let slot = Self::slot(tick, 0);
+            for (_, item) in self.levels[0][slot].drain(..) {
+                self.len -= 1;
+                expired.push(item);
+            }
+        }
+
+        self.current = self.current.max(target);
+        expired
+    }
+
+    /// Removes all items from the wheel, irrespective of their deadline.
+    pub fn drain(&mut self) -> Vec<T> {
+        let mut items: Vec<T> = self.overflow.drain(..).map(|(_, item)| item).collect();
+        for level in self.levels.iter_mut() {
+            for slot in level.iter_mut() {
+                items.extend(slot.drain(..).map(|(_, item)| item));
+            }
+        }
+
+        self.len = 0;
+        items
+    }
+
+    // Adds an item with a deadline in ticks to the lowest level whose range covers it. The
+    // deadline must be after the current tick.
+    fn place(&mut self, deadline: u64, item: T) {
+        let delta = deadline - self.current;
+        for level in 0..LEVELS {
+            if delta < 1 << (LEVEL_BITS * (level as u32 + 1)) {
+                let slot = Self::slot(deadline, level);
+                self.levels[level][slot].push((deadline, item));
+                return;
+            }
+        }
+
+        self.overflow.push((deadline, item));
+    }
+
+    // Returns the slot on a level that a tick falls into.
+    fn slot(tick: u64, level: usize) -> usize {
+        ((tick >> (LEVEL_BITS * level as u32)) as usize) & (LEVEL_SLOTS - 1)
+    }
+}
+
+// This module contains simple unit tests for TimerWheel.
+#[cfg(test)]
+mod tests {
+    use super::{TimerWheel, LEVEL_SLOTS};
+
+    // This test verifies that items expire once their deadline passes and not before, both on
+    // the lowest level and after cascading down from higher ones.
+    #[test]
+    fn test_timer_expiry() {
+        let mut wheel = TimerWheel::new(10, 1000);
+        wheel.insert(1005, 1);
+        wheel.insert(1500, 2);
+        wheel.insert(1000 + 10 * (LEVEL_SLOTS * LEVEL_SLOTS * 3) as u64, 3);
+        wheel.insert(0, 4);
+        assert_eq!(4, wheel.len());
+
+        assert!(wheel.advance(1009).is_empty());
+        assert_eq!(vec![1, 4], wheel.advance(1010));
+        assert!(wheel.advance(1499).is_empty());
+        assert_eq!(vec![2], wheel.advance(1500));
+
+        let far = 1000 + 10 * (LEVEL_SLOTS * LEVEL_SLOTS * 3) as u64;
+        assert!(wheel.advance(far - 1).is_empty());
+        assert_eq!(vec![3], wheel.advance(far));
+        assert_eq!(0, wheel.len());
+    }
+
+    // This test verifies that deadlines beyond the range of the wheel are held until they come
+    // within range, and that draining returns everything.
+    #[test]
+    fn test_timer_overflow_drain() {
+        let range = (LEVEL_SLOTS as u64).pow(4);
+        let mut wheel = TimerWheel::new(1, 0);
+        wheel.insert(range + 7, "far");
+        wheel.insert(5, "near");
+
+        assert_eq!(vec!["near"], wheel.advance(range));
+        assert_eq!(vec!["far"], wheel.advance(range + 7));
+
+        wheel.insert(range * 3, "a");
+        wheel.insert(range * 2 + 100, "b");
+        let mut drained = wheel.drain();
+        drained.sort();
+        assert_eq!(vec!["a", "b"], drained);
+        assert_eq!(0, wheel.len());
+        assert!(wheel.advance(range * 4).is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::fmt;
+
+use super::common::TenantId;
+use super::cycles;
+use super::rpc;
+use super::task::{CostClass, Task, TaskPriority, TaskState};
+
+use e2d2::common::EmptyMetadata;
+use e2d2::headers::UdpHeader;
+use e2d2::interface::Packet;
+
+/// The correlation id of a request. Consists of the tenant that issued the request and the
+/// stamp on it, both of which are also on the response, so that the server's event log can be
+/// lined up against the client's.
+///
+/// Every event logged for a traced request begins with the request's correlation id, ex:
+/// "req 1.5a3f00c2e1". Grepping the log for it yields every event in the request's lifetime, in
+/// order: it's arrival at the dispatcher, the creation of it's task by Master, every time the
+/// scheduler ran the task, and the response that was sent out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RequestId {
+    /// The tenant that issued the request.
+    pub tenant: TenantId,
+
+    /// The stamp on the request.
+    pub stamp: u64,
+}
+
+// Implementation of the Display trait. This is the format logged for every event.
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "req {}.{:x}", self.tenant, self.stamp)
+    }
+}
+
+/// A task that carries the correlation id of the request it was created for, so that the
+/// scheduler can log events for it. Created by the dispatcher for every request when the server
+/// is configured with `trace_requests`, and for every request with `FLAG_TRACE` set on it. The
+/// response to the latter is stamped with the time the request spent waiting and running at the
+/// server, so that clients can break their latencies down.
+pub struct Traced {
+    // The task created by Master for the request.
+    task: Box<Task>,
+
+    // The correlation id of the request, if events are to be logged for it.
+    id: Option<RequestId>,
+
+    // The time stamp in cycles at which the request arrived, if the response is to be stamped
+    // with the time the request spent at the server.
+    arrival: Option<u64>,
+}
+
+// Implementation of methods on Traced.
+impl Traced {
+    /// Wraps a task so that it carries a request's correlation id, and/or stamps it's response
+    /// with the time the request spent at the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `task`:    The task created for the request.
+    /// * `id`:      The correlation id of the request. None if events should not be logged.
+    /// * `arrival`: The time stamp in cycles at which the request arrived. None if the response
+    ///              should not be stamped.
+    ///
+    /// # Return
+    ///
+    /// A task that behaves exactly like `task`, except that it returns `id` from `id()`.
+    pub fn new(task: Box<Task>, id: Option<RequestId>, arrival: Option<u64>) -> Traced {
+        Traced {
+            task: task,
+            id: id,
+            arrival: arrival,
+        }
+    }
+}
+
+// Implementation of the Task trait for Traced. Everything except id() is passed through.
+impl Task for Traced {
+    /// Refer to the `Task` trait for Documentation.
+    fn run(&mut self) -> (TaskState, u64) {
+        self.task.run()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn state(&self) -> TaskState {
+        self.task.state()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn time(&self) -> u64 {
+        self.task.time()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn priority(&self) -> TaskPriority {
+        self.task.priority()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    unsafe fn tear(
+        &mut self,
+    ) -> Option<(
+        Packet<UdpHeader, EmptyMetadata>,
+        Packet<UdpHeader, EmptyMetadata>,
+    )> {
+        let mut packets = self.task.tear();
+        if let Some(id) = self.id {
+            trace!(
+                "{} completed after {} cycles{}",
+                id,
+                self.task.time(),
+                if packets.is_some() { "" } else { " without a response" }
+            );
+        }
+
+        // Everything from the request's arrival upto now that was not spent running the task
+        // was spent waiting; on the run-queues, or parked on a timer.
+        if let (Some(arrival), Some(&mut (_, ref mut res))) = (self.arrival, packets.as_mut()) {
+            let executed = self.task.time();
+            let queued = cycles::rdtsc().saturating_sub(arrival).saturating_sub(executed);
+            rpc::set_rpc_stage_times(res, to_nanos(queued), to_nanos(executed));
+        }
+
+        packets
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    unsafe fn tear_more(&mut self) -> Vec<Packet<UdpHeader, EmptyMetadata>> {
+        self.task.tear_more()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn id(&self) -> Option<RequestId> {
+        self.id
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn wake(&self) -> Option<u64> {
+        self.task.wake()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn cost(&self) -> CostClass {
+        self.task.cost()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn affinity(&self) -> Option<usize> {
+        self.task.affinity()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn deadline(&self) -> Option<u64> {
+        self.task.deadline()
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn set_deadline(&mut self, deadline: u64) {
+        self.task.set_deadline(deadline)
+    }
+
+    /// Refer to the `Task` trait for Documentation.
+    fn successor(&mut self) -> Option<Box<Task>> {
+        self.task.successor()
+    }
+}
+
+// Converts a time in cycles to nanoseconds, saturating at the largest time a response can carry.
+fn to_nanos(cycles: u64) -> u32 {
+    (cycles::to_seconds(cycles) * 1e9).min(u32::max_value() as f64) as u32
+}
+
+// This module contains simple unit tests for RequestId.
+#[cfg(test)]
+mod tests {
+    use super::RequestId;
+
+    // This test verifies the format in which correlation ids are logged.
+    #[test]
+    fn test_request_id_display() {
+        let id = RequestId {
+            tenant: 7,
+            stamp: 0xbeef,
+        };
+        assert_eq!("req 7.beef", format!("{}", id));
+    }
+}
@@ -0,0 +1,476 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[cfg(test)]
+use std::sync::atomic::AtomicUsize;
+
+use super::common;
+
+use super::e2d2::allocators::CacheAligned;
+use super::e2d2::common::Result;
+use super::e2d2::headers::*;
+use super::e2d2::interface::*;
+use super::e2d2::native::zcsi::MBuf;
+
+// The largest frame that is handed out by a kernel port. Matches the data room on an mbuf.
+const MAX_FRAME_LEN: usize = 2048;
+
+// The size of the MAC, IP, and UDP headers written in front of every received datagram.
+const HEADERS_LEN: usize = 14 + 20 + 8;
+
+/// The interface a dispatcher receives requests and sends out responses over (refer to
+/// `dispatch::Dispatch`). Implemented by a queue pair on a NIC driven by DPDK, and by anything
+/// standing in for one, so that dispatchers do not depend on a NIC being present.
+pub trait Transport {
+    /// Receives upto `pkts.len()` packets into `pkts` without blocking.
+    ///
+    /// # Return
+    ///
+    /// The number of packets received, placed at the front of `pkts`.
+    fn recv_burst(&self, pkts: &mut [*mut MBuf]) -> Result<u32>;
+
+    /// Sends out a burst of packets.
+    ///
+    /// # Return
+    ///
+    /// The number of packets taken off the caller, from the front of `pkts`. The caller remains
+    /// responsible for the rest.
+    fn send_burst(&self, pkts: &mut [*mut MBuf]) -> Result<u32>;
+
+    /// Returns the number of packets waiting to be received, or None if the transport cannot
+    /// tell without receiving them.
+    fn queue_depth(&self) -> Option<usize>;
+}
+
+// Implementation of the Transport trait for a queue pair on a NIC.
+impl Transport for CacheAligned<PortQueue> {
+    #[inline]
+    fn recv_burst(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        self.recv(pkts)
+    }
+
+    #[inline]
+    fn send_burst(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        self.send(pkts)
+    }
+
+    // Netbricks does not expose the number of descriptors filled on a receive ring.
+    fn queue_depth(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// A stand-in for a NIC's receive and transmit queue pair, that carries RPC payloads over a UDP
+/// socket of the kernel instead. Allows the server and clients to run on machines without a NIC
+/// DPDK can drive (ex: for development and CI). DPDK still provides the packet buffers, so its
+/// environment is initialized as usual, just without any port.
+///
+/// Received datagrams are handed out as frames with MAC, IP, and UDP headers addressed to this
+/// end, and from the IPv4 address and UDP port the datagram came from, so that responses are
+/// addressed back to the sender just like they would be over a NIC. Only the payload of a frame
+/// that is sent out goes over the socket; the MAC address, and the UDP ports used for steering
+/// are dropped along the way. IPv6 and ARP are not supported.
+#[derive(Clone)]
+pub struct KernelPort {
+    // The socket datagrams are sent and received on. Shared by every clone of the port.
+    socket: Arc<UdpSocket>,
+
+    // The address every frame is sent to, irrespective of its headers. If None, frames are sent
+    // to the IP address and UDP port on their headers.
+    peer: Option<SocketAddrV4>,
+
+    // The MAC and IPv4 addresses received frames are addressed to.
+    mac: MacAddress,
+    ip: u32,
+
+    // The identifier of the queue this port stands in for. Refer to `rxq()`.
+    queue: i32,
+
+    // Set once a send failed, so that failures are only logged once per socket.
+    failed: Arc<AtomicBool>,
+}
+
+// Implementation of methods on KernelPort.
+impl KernelPort {
+    /// Binds a UDP socket, and returns a port over it.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr`:  The address to bind the socket to. A port of zero binds to any free port.
+    /// * `peer`:  The address every frame is sent to. Clients set this to the server's address.
+    ///            If None, frames are sent to the IP address and UDP port on their headers.
+    /// * `mac`:   The MAC address received frames are addressed to.
+    /// * `ip`:    The IPv4 address received frames are addressed to.
+    /// * `queue`: The identifier of the queue this port stands in for.
+    ///
+    /// # Return
+    ///
+    /// The port, or an error if the socket could not be bound.
+    pub fn bind(
+        addr: SocketAddrV4,
+        peer: Option<SocketAddrV4>,
+        mac: MacAddress,
+        ip: u32,
+        queue: i32,
+    ) -> io::Result<KernelPort> {
+        let socket = UdpSocket::bind(SocketAddr::V4(addr))?;
+        socket.set_nonblocking(true)?;
+
+        Ok(KernelPort {
+            socket: Arc::new(socket),
+            peer: peer,
+            mac: mac,
+            ip: ip,
+            queue: queue,
+            failed: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Returns a port over the same socket, standing in for a different queue. Datagrams are
+    /// received by whichever of the ports polls the socket first.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue`: The identifier of the queue the returned port stands in for.
+    pub fn share(&self, queue: i32) -> KernelPort {
+        let mut port = self.clone();
+        port.queue = queue;
+        port
+    }
+
+    /// Returns the identifier of the queue this port stands in for.
+    pub fn rxq(&self) -> i32 {
+        self.queue
+    }
+
+    /// Returns the identifier of the queue this port stands in for. Used as the source UDP port
+    /// on requests, just like a transmit queue's.
+    pub fn txq(&self) -> i32 {
+        self.queue
+    }
+
+    // Writes a datagram received from `peer` into a packet buffer, behind MAC, IP, and UDP
+    // headers addressed from `peer` to this end. Returns None if no buffer was available.
+    fn frame(&self, payload: &[u8], peer: &SocketAddrV4) -> Option<*mut MBuf> {
+        let mut mac = MacHeader::new();
+        mac.dst = self.mac;
+        mac.set_etype(common::PACKET_ETYPE);
+
+        let mut ip = IpHeader::new();
+        ip.set_src(u32::from(*peer.ip()));
+        ip.set_dst(self.ip);
+        ip.set_ttl(common::PACKET_IP_TTL);
+        ip.set_version(common::PACKET_IP_VER);
+        ip.set_ihl(common::PACKET_IP_IHL);
+        ip.set_length((20 + 8 + payload.len()) as u16);
+        ip.set_protocol(0x11);
+
+        let mut udp = UdpHeader::new();
+        udp.set_src_port(peer.port());
+        udp.set_dst_port(self.queue as u16);
+        udp.set_length((8 + payload.len()) as u16);
+        udp.set_checksum(0);
+
+        let mut packet = match new_packet()
+            .and_then(|packet| packet.push_header(&mac))
+            .and_then(|packet| packet.push_header(&ip))
+            .and_then(|packet| packet.push_header(&udp))
+        {
+            Some(packet) => packet,
+            None => return None,
+        };
+
+        if packet.add_to_payload_tail(payload.len(), payload).is_err() {
+            packet.free_packet();
+            return None;
+        }
+
+        unsafe { Some(packet.get_mbuf()) }
+    }
+
+    // Sends out the payload of a frame, and frees it. Frames that are not UDP over IPv4 are
+    // dropped.
+    unsafe fn send_frame(&self, mbuf: *mut MBuf) {
+        let packet = packet_from_mbuf_no_increment::<MacHeader>(mbuf, 0);
+        if packet.get_header().etype() != common::PACKET_ETYPE {
+            packet.free_packet();
+            return;
+        }
+
+        let packet = packet.parse_header::<IpHeader>();
+        let dst_ip = packet.get_header().dst();
+        let packet = packet.parse_header::<UdpHeader>();
+        let dst_port = packet.get_header().dst_port();
+        let dst = self
+            .peer
+            .unwrap_or(SocketAddrV4::new(Ipv4Addr::from(dst_ip), dst_port));
+
+        // A datagram that does not fit in the socket's buffer is dropped, just like a frame that
+        // does not fit in a NIC's transmit ring.
+        if let Err(ref err) = self.socket.send_to(packet.get_payload(), &SocketAddr::V4(dst)) {
+            let blocked = err.kind() == io::ErrorKind::WouldBlock;
+            if !blocked && !self.failed.swap(true, Ordering::Relaxed) {
+                warn!("Failed to send datagram to {}: {}", dst, err);
+            }
+        }
+
+        packet.free_packet();
+    }
+}
+
+// Implementation of the PacketRx trait for KernelPort, allowing it to be polled like a NIC's
+// receive queue.
+impl PacketRx for KernelPort {
+    /// Receives upto `pkts.len()` datagrams without blocking, and returns the number received.
+    fn recv(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        let mut buf = [0u8; MAX_FRAME_LEN - HEADERS_LEN];
+        let mut received = 0;
+
+        while received < pkts.len() {
+            let (len, peer) = match self.socket.recv_from(&mut buf) {
+                Ok((len, SocketAddr::V4(peer))) => (len, peer),
+
+                // Datagrams from IPv6 peers are dropped.
+                Ok(_) => continue,
+
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+
+                Err(ref err) => {
+                    warn!("Failed to receive datagram: {}", err);
+                    break;
+                }
+            };
+
+            match self.frame(&buf[..len], &peer) {
+                Some(mbuf) => {
+                    pkts[received] = mbuf;
+                    received += 1;
+                }
+
+                // Out of packet buffers. The datagram is dropped, like it would be off a NIC.
+                None => break,
+            }
+        }
+
+        Ok(received as u32)
+    }
+}
+
+// Implementation of the PacketTx trait for KernelPort, allowing it to be used like a NIC's
+// transmit queue.
+impl PacketTx for KernelPort {
+    /// Sends out the payload of every frame, and frees it. Every frame is taken off the caller,
+    /// even if it could not be sent out.
+    fn send(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        for mbuf in pkts.iter() {
+            unsafe { self.send_frame(*mbuf) };
+        }
+
+        Ok(pkts.len() as u32)
+    }
+}
+
+// Implementation of the Transport trait for KernelPort.
+impl Transport for KernelPort {
+    fn recv_burst(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        self.recv(pkts)
+    }
+
+    fn send_burst(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        self.send(pkts)
+    }
+
+    // A UDP socket only reports the size of the datagram at it's head.
+    fn queue_depth(&self) -> Option<usize> {
+        None
+    }
+}
+
+// Implementation of the Display trait for KernelPort.
+impl fmt::Display for KernelPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.socket.local_addr() {
+            Ok(addr) => write!(f, "kernel socket {} queue {}", addr, self.queue),
+            Err(_) => write!(f, "kernel socket queue {}", self.queue),
+        }
+    }
+}
+
+/// A receive and transmit queue pair that the server's dispatchers and the clients poll for
+/// packets. Either a queue on a NIC driven by DPDK, or a stand-in over a kernel socket.
+#[derive(Clone)]
+pub enum Port {
+    /// A queue pair on a NIC driven by DPDK.
+    Dpdk(CacheAligned<PortQueue>),
+
+    /// A stand-in for a queue pair, over a kernel socket. Refer to `KernelPort`.
+    Kernel(KernelPort),
+}
+
+// Implementation of methods on Port.
+impl Port {
+    /// Returns the identifier of the NIC the queue pair is on, or None if it is over a kernel
+    /// socket.
+    pub fn nic(&self) -> Option<i32> {
+        match *self {
+            Port::Dpdk(ref port) => Some(port.port_id()),
+            Port::Kernel(_) => None,
+        }
+    }
+
+    /// Returns the identifier of the receive queue.
+    pub fn rxq(&self) -> i32 {
+        match *self {
+            Port::Dpdk(ref port) => port.rxq(),
+            Port::Kernel(ref port) => port.rxq(),
+        }
+    }
+
+    /// Returns the identifier of the transmit queue.
+    pub fn txq(&self) -> i32 {
+        match *self {
+            Port::Dpdk(ref port) => port.txq(),
+            Port::Kernel(ref port) => port.txq(),
+        }
+    }
+}
+
+// Implementation of the PacketRx trait for Port.
+impl PacketRx for Port {
+    #[inline]
+    fn recv(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        match *self {
+            Port::Dpdk(ref port) => port.recv(pkts),
+            Port::Kernel(ref port) => port.recv(pkts),
+        }
+    }
+}
+
+// Implementation of the PacketTx trait for Port.
+impl PacketTx for Port {
+    #[inline]
+    fn send(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        match *self {
+            Port::Dpdk(ref port) => port.send(pkts),
+            Port::Kernel(ref port) => port.send(pkts),
+        }
+    }
+}
+
+// Implementation of the Transport trait for Port.
+impl Transport for Port {
+    #[inline]
+    fn recv_burst(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        match *self {
+            Port::Dpdk(ref port) => port.recv_burst(pkts),
+            Port::Kernel(ref port) => port.recv_burst(pkts),
+        }
+    }
+
+    #[inline]
+    fn send_burst(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        match *self {
+            Port::Dpdk(ref port) => port.send_burst(pkts),
+            Port::Kernel(ref port) => port.send_burst(pkts),
+        }
+    }
+
+    fn queue_depth(&self) -> Option<usize> {
+        match *self {
+            Port::Dpdk(ref port) => port.queue_depth(),
+            Port::Kernel(ref port) => port.queue_depth(),
+        }
+    }
+}
+
+// Implementation of the Display trait for Port.
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Port::Dpdk(ref port) => write!(f, "{}", **port),
+            Port::Kernel(ref port) => write!(f, "{}", port),
+        }
+    }
+}
+
+
+/// A stand-in for a queue pair that never receives a packet, and counts how often it was polled.
+/// Allows dispatchers to be tested without a NIC or a kernel socket. Clones share their counters.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct MockTransport {
+    // The queue depth reported to the caller. Refer to `Transport::queue_depth()`.
+    depth: Option<usize>,
+
+    // The number of calls made to recv_burst(), and the number of packets handed to
+    // send_burst().
+    recvs: Arc<AtomicUsize>,
+    sent: Arc<AtomicUsize>,
+}
+
+// Implementation of methods on MockTransport.
+#[cfg(test)]
+impl MockTransport {
+    /// Returns a transport that reports a given queue depth to the caller.
+    pub fn new(depth: Option<usize>) -> MockTransport {
+        MockTransport {
+            depth: depth,
+            recvs: Arc::new(AtomicUsize::new(0)),
+            sent: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the number of times the transport was polled for packets.
+    pub fn recvs(&self) -> usize {
+        self.recvs.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of packets sent out on the transport.
+    pub fn sent(&self) -> usize {
+        self.sent.load(Ordering::Relaxed)
+    }
+}
+
+// Implementation of the Transport trait for MockTransport.
+#[cfg(test)]
+impl Transport for MockTransport {
+    fn recv_burst(&self, _pkts: &mut [*mut MBuf]) -> Result<u32> {
+        self.recvs.fetch_add(1, Ordering::Relaxed);
+        Ok(0)
+    }
+
+    fn send_burst(&self, pkts: &mut [*mut MBuf]) -> Result<u32> {
+        self.sent.fetch_add(pkts.len(), Ordering::Relaxed);
+        Ok(pkts.len() as u32)
+    }
+
+    fn queue_depth(&self) -> Option<usize> {
+        self.depth
+    }
+}
+
+// Implementation of the Display trait for MockTransport.
+#[cfg(test)]
+impl fmt::Display for MockTransport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mock transport")
+    }
+}
@@ -0,0 +1,185 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+// Helpers for clients to decode the payloads of invoke() responses. Extensions encode their
+// responses with `sandstorm::pack`, so these mirror it's `unpack` and `consume` functions and
+// accept the same `Safe` types. Unlike them, values are copied out of the payload, since a
+// payload sitting in a packet buffer is not guaranteed to be aligned for the type.
+
+use std::mem::size_of;
+use std::ptr;
+use std::str::from_utf8;
+
+pub use sandstorm::pack::{pack, Safe};
+
+/// Copies a value of type `A` out of the head of a payload.
+///
+/// # Arguments
+///
+/// * `payload`: The payload on an invoke() response. May be unaligned.
+///
+/// # Return
+///
+/// The value, or None if the payload is smaller than `A`.
+pub fn unpack<A>(payload: &[u8]) -> Option<A>
+where
+    A: Safe + Copy,
+{
+    read(payload)
+}
+
+/// See `unpack`. Identical except it decodes a two-tuple of the `Safe` types.
+pub fn unpack_two<A, B>(payload: &[u8]) -> Option<(A, B)>
+where
+    A: Safe + Copy,
+    B: Safe + Copy,
+{
+    read(payload)
+}
+
+/// See `unpack_two`.
+pub fn unpack_three<A, B, C>(payload: &[u8]) -> Option<(A, B, C)>
+where
+    A: Safe + Copy,
+    B: Safe + Copy,
+    C: Safe + Copy,
+{
+    read(payload)
+}
+
+/// Copies a value of type `A` out of the head of a payload, and returns it along with the rest of
+/// the payload. Makes it easier to decode responses that an extension wrote with many calls to
+/// `db.resp(pack(..))`.
+///
+/// # Arguments
+///
+/// * `payload`: The payload on an invoke() response. May be unaligned.
+///
+/// # Return
+///
+/// The value and the bytes following it, or None if the payload is smaller than `A`.
+pub fn consume<A>(payload: &[u8]) -> Option<(A, &[u8])>
+where
+    A: Safe + Copy,
+{
+    read(payload).map(|value| (value, &payload[size_of::<A>()..]))
+}
+
+/// See `consume`. Identical except it decodes a two-tuple of the `Safe` types.
+pub fn consume_two<A, B>(payload: &[u8]) -> Option<((A, B), &[u8])>
+where
+    A: Safe + Copy,
+    B: Safe + Copy,
+{
+    read(payload).map(|value| (value, &payload[size_of::<(A, B)>()..]))
+}
+
+/// See `consume_two`.
+pub fn consume_three<A, B, C>(payload: &[u8]) -> Option<((A, B, C), &[u8])>
+where
+    A: Safe + Copy,
+    B: Safe + Copy,
+    C: Safe + Copy,
+{
+    read(payload).map(|value| (value, &payload[size_of::<(A, B, C)>()..]))
+}
+
+/// Decodes a list of values of type `A` packed back to back, ex: the ids in a TAO association
+/// list.
+///
+/// # Arguments
+///
+/// * `payload`: The payload on an invoke() response. May be unaligned.
+///
+/// # Return
+///
+/// The list, or None if the payload does not consist of a whole number of `A`s.
+pub fn unpack_list<A>(payload: &[u8]) -> Option<Vec<A>>
+where
+    A: Safe + Copy,
+{
+    let size = size_of::<A>();
+    if size == 0 || payload.len() % size != 0 {
+        return None;
+    }
+
+    payload.chunks(size).map(|chunk| read(chunk)).collect()
+}
+
+/// Decodes a string, such as the error messages that extensions respond with.
+///
+/// # Arguments
+///
+/// * `payload`: The payload on an invoke() response.
+///
+/// # Return
+///
+/// The string, or None if the payload is not valid UTF-8.
+pub fn unpack_str(payload: &[u8]) -> Option<&str> {
+    from_utf8(payload).ok()
+}
+
+// Copies a `T` out of the head of `payload` if it is large enough to hold one.
+fn read<T>(payload: &[u8]) -> Option<T>
+where
+    T: Copy,
+{
+    if payload.len() < size_of::<T>() {
+        return None;
+    }
+
+    unsafe { Some(ptr::read_unaligned(payload.as_ptr() as *const T)) }
+}
+
+// This module contains simple unit tests for response decoding.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // This test verifies that values packed by an extension decode back to themselves, even when
+    // the payload is not aligned.
+    #[test]
+    fn test_unpack_roundtrip() {
+        let err: u8 = 1;
+        let res: u64 = 0xdeadbeef;
+
+        let mut payload = vec![0u8];
+        payload.extend_from_slice(pack(&err));
+        payload.extend_from_slice(pack(&res));
+
+        let (e, rest): (u8, _) = consume(&payload[1..]).unwrap();
+        assert_eq!(err, e);
+        assert_eq!(Some(res), unpack::<u64>(rest));
+        assert_eq!(None, unpack::<u64>(&rest[1..]));
+    }
+
+    // This test verifies that lists and strings decode, and that partial lists are rejected.
+    #[test]
+    fn test_unpack_list_str() {
+        let mut payload = Vec::new();
+        for id in 1u64..4 {
+            payload.extend_from_slice(pack(&id));
+        }
+
+        assert_eq!(Some(vec![1u64, 2, 3]), unpack_list::<u64>(&payload));
+        assert_eq!(None, unpack_list::<u64>(&payload[1..]));
+
+        let tuple: (u32, u32) = unpack_two(&payload).unwrap();
+        assert_eq!((1, 0), tuple);
+
+        assert_eq!(Some("ERROR"), unpack_str("ERROR".as_bytes()));
+        assert_eq!(None, unpack_str(&[0xff, 0xfe]));
+    }
+}
@@ -0,0 +1,334 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::cell::Cell;
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use libc;
+use spin::Mutex;
+
+use super::shm::Segment;
+
+// Logs are flushed out a page at a time. The start of a flushed range is rounded down to this.
+const PAGE_SIZE: usize = 4096;
+
+// Every thread that appends to a write-ahead log is handed a slot, and always appends to the log
+// at that slot (modulo the number of logs). Scheduler threads are pinned to a core each, so every
+// core ends up with a log of it's own.
+static NEXT_SLOT: AtomicUsize = ATOMIC_USIZE_INIT;
+thread_local!(static SLOT: Cell<Option<usize>> = Cell::new(None));
+
+// Returns the slot of the calling thread, handing it one if required.
+fn slot() -> usize {
+    SLOT.with(|slot| match slot.get() {
+        Some(s) => s,
+        None => {
+            let s = NEXT_SLOT.fetch_add(1, Ordering::Relaxed);
+            slot.set(Some(s));
+            s
+        }
+    })
+}
+
+// Returns the path of the log at an index inside a directory.
+fn log_path(dir: &str, idx: usize) -> String {
+    format!("{}/wal.{}", dir, idx)
+}
+
+// A log appended to by a single core, along with how much of it has been flushed out.
+struct CoreLog {
+    // The memory-mapped file the log's records are appended to.
+    segment: Segment,
+
+    // The number of bytes of the log that are known to have been flushed out to the file.
+    synced: AtomicUsize,
+
+    // Serializes flushes of the log.
+    flush: Mutex<()>,
+}
+
+// Implementation of methods on CoreLog.
+impl CoreLog {
+    // Flushes every record appended to the log so far out to the file. If `wait` is false and
+    // another thread is already flushing the log, returns right away instead; the records are
+    // then flushed by a later call.
+    fn flush(&self, wait: bool) {
+        let _guard = if wait {
+            self.flush.lock()
+        } else {
+            match self.flush.try_lock() {
+                Some(guard) => guard,
+                None => return,
+            }
+        };
+
+        let (used, _) = self.segment.stats();
+        let synced = self.synced.load(Ordering::Relaxed);
+        if used == synced {
+            return;
+        }
+
+        // Flush the records before the header that publishes them, so that a crash in between
+        // never leaves a published record that was not written out.
+        let (base, header) = self.segment.header();
+        let start = synced & !(PAGE_SIZE - 1);
+        unsafe {
+            libc::msync(
+                base.offset(start as isize) as *mut libc::c_void,
+                used - start,
+                libc::MS_SYNC,
+            );
+            libc::msync(base as *mut libc::c_void, header, libc::MS_SYNC);
+        }
+
+        self.synced.store(used, Ordering::Relaxed);
+    }
+}
+
+/// A write-ahead log of the objects written to and the keys deleted from tables, allowing a
+/// restarted server to rebuild it's tables while the tables themselves stay in process memory.
+/// Refer to `Allocator::set_wal()`. The log is split into one memory-mapped file per core, so
+/// that cores never contend on appends. Each file is laid out like a heap segment (refer to
+/// `shm::Segment`), and it's records are objects laid out exactly as they were by the allocator.
+/// Objects carry versions that increase across cores, so the files are merged back into a single
+/// order on recovery.
+///
+/// Appends are written into the mapping, and are flushed out in groups: a core flushes it's log
+/// once a group's worth of bytes has been appended to it since the last flush, and `sync()`
+/// flushes the rest. An acknowledged write survives the server exiting as soon as it is appended,
+/// but can be lost along with the rest of it's group if the machine crashes.
+///
+/// Records are stamped with the order they were appended in by the allocator (refer to
+/// `Allocator::commit()`). Logs are never truncated; once a core's log fills up, writes issued on
+/// that core are refused instead of being applied without being logged.
+///
+/// Only whole objects are logged, so an object that is updated in place would be lost from the
+/// log. Updates to objects of logged tables are therefore applied to a copy of the object, which
+/// is committed like any other write (refer to `Context::update()`).
+pub struct Wal {
+    // The log of every core. Includes logs left behind by earlier runs with more cores.
+    logs: Vec<CoreLog>,
+
+    // The number of bytes appended to a log after which the appending core flushes it.
+    group: usize,
+
+    // Set once a log fills up and a record could not be appended.
+    full: AtomicBool,
+}
+
+// Implementation of methods on Wal.
+impl Wal {
+    /// Opens the write-ahead log inside a directory, creating it if required.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir`:   The directory holding the log's files.
+    /// * `logs`:  The number of files to split the log into. Should be the number of cores.
+    /// * `size`:  The size of each file in bytes.
+    /// * `group`: The number of bytes appended to a file after which it is flushed out by the
+    ///            core appending to it. Zero flushes every append.
+    ///
+    /// # Return
+    ///
+    /// The log. Records appended by an earlier run can be walked with `records()`.
+    pub fn open(dir: &str, logs: usize, size: usize, group: usize) -> Result<Wal> {
+        fs::create_dir_all(dir)?;
+
+        let mut num_logs = logs.max(1);
+        while Path::new(&log_path(dir, num_logs)).exists() {
+            num_logs += 1;
+        }
+
+        let mut opened = Vec::with_capacity(num_logs);
+        for idx in 0..num_logs {
+            let segment = Segment::open(&log_path(dir, idx), size)?;
+            let (used, _) = segment.stats();
+            opened.push(CoreLog {
+                segment: segment,
+                synced: AtomicUsize::new(used),
+                flush: Mutex::new(()),
+            });
+        }
+
+        Ok(Wal {
+            logs: opened,
+            group: group,
+            full: AtomicBool::new(false),
+        })
+    }
+
+    /// Appends a record to the calling core's log, flushing the log out if a group's worth of
+    /// records has built up.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`:    The object, laid out exactly as it was by the allocator.
+    /// * `tombstone`: True if the record marks the deletion of the object's key. The object
+    ///                then consists of only the metadata and key.
+    ///
+    /// # Return
+    ///
+    /// True if the record was appended. False if the log is full.
+    pub fn append(&self, object: &[u8], tombstone: bool) -> bool {
+        self.push(object, tombstone, None)
+    }
+
+    /// Appends a record to the calling core's log like `append()`, stamping the copy of the
+    /// object inside the log. Refer to `Segment::append_stamped()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`:    The object, laid out exactly as it was by the allocator.
+    /// * `tombstone`: True if the record marks the deletion of the object's key.
+    /// * `offset`:    The offset within the object that the stamp is written at.
+    /// * `stamp`:     The stamp.
+    ///
+    /// # Return
+    ///
+    /// True if the record was appended. False if the log is full.
+    pub fn append_stamped(&self, object: &[u8], tombstone: bool, offset: usize, stamp: u64)
+        -> bool
+    {
+        self.push(object, tombstone, Some((offset, stamp)))
+    }
+
+    // Appends a record to the calling core's log, stamping it if required, and flushes the log
+    // out if a group's worth of records has built up.
+    fn push(&self, object: &[u8], tombstone: bool, stamp: Option<(usize, u64)>) -> bool {
+        let log = &self.logs[slot() % self.logs.len()];
+        let appended = match stamp {
+            Some((offset, stamp)) => log.segment.append_stamped(object, tombstone, offset, stamp),
+            None => log.segment.append(object, tombstone),
+        };
+        if appended.is_none() {
+            if !self.full.swap(true, Ordering::Relaxed) {
+                warn!("Write-ahead log is full. Writes on it's core will be refused.");
+            }
+            return false;
+        }
+
+        let (used, _) = log.segment.stats();
+        if used - log.synced.load(Ordering::Relaxed) >= self.group {
+            log.flush(false);
+        }
+
+        true
+    }
+
+    /// Flushes every record appended so far out to the log's files. Meant to be called
+    /// periodically, so that a core that stops writing does not leave a partial group unflushed
+    /// for long.
+    pub fn sync(&self) {
+        for log in self.logs.iter() {
+            log.flush(true);
+        }
+    }
+
+    /// Returns every record in the log, one file after the other. Records in a file are in the
+    /// order they were appended, but records across files are not ordered.
+    ///
+    /// # Return
+    ///
+    /// A vector of records. The first member is true if the record is a tombstone, and the second
+    /// is a slice over the object inside the log. An error if a file was malformed.
+    pub fn records(&self) -> Result<Vec<(bool, &'static [u8])>> {
+        let mut records = Vec::new();
+        for log in self.logs.iter() {
+            log.segment
+                .recover(|tombstone, object| records.push((tombstone, object)))?;
+        }
+
+        Ok(records)
+    }
+
+    /// Returns the total number of bytes in use across the log's files, and their total size.
+    pub fn stats(&self) -> (usize, usize) {
+        self.logs.iter().fold((0, 0), |(used, size), log| {
+            let (u, s) = log.segment.stats();
+            (used + u, size + s)
+        })
+    }
+}
+
+// This module contains simple unit tests for Wal.
+#[cfg(test)]
+mod tests {
+    use super::Wal;
+    use std::fs::remove_dir_all;
+    use std::thread;
+
+    // This test verifies that records appended from multiple threads are returned once the log is
+    // re-opened, including those in files beyond the number asked for.
+    #[test]
+    fn test_wal_records() {
+        let dir = "/tmp/sandstorm_wal_records.test";
+        let _ = remove_dir_all(dir);
+
+        {
+            let wal = Wal::open(dir, 2, 4096, 0).expect("Failed to create log.");
+            assert!(wal.append(&[1, 2, 3], false));
+            let appender = thread::spawn(move || {
+                assert!(wal.append(&[4; 9], true));
+                wal.sync();
+            });
+            appender.join().expect("Appender panicked.");
+        }
+
+        let wal = Wal::open(dir, 1, 4096, 0).expect("Failed to re-open log.");
+        let mut records: Vec<(bool, Vec<u8>)> = wal.records()
+            .expect("Failed to read log.")
+            .into_iter()
+            .map(|(tombstone, object)| (tombstone, object.to_vec()))
+            .collect();
+        records.sort();
+
+        assert_eq!(vec![(false, vec![1, 2, 3]), (true, vec![4; 9])], records);
+        assert_eq!(2 * 4096, wal.stats().1);
+        let _ = remove_dir_all(dir);
+    }
+
+    // This test verifies that appends fail once the calling core's log fills up.
+    #[test]
+    fn test_wal_full() {
+        let dir = "/tmp/sandstorm_wal_full.test";
+        let _ = remove_dir_all(dir);
+
+        let wal = Wal::open(dir, 1, 128, 1024).expect("Failed to create log.");
+        assert!(wal.append(&[0; 56], false));
+        assert!(!wal.append(&[0; 1], false));
+        let _ = remove_dir_all(dir);
+    }
+
+    // This test verifies that stamped appends carry the stamp in place of the bytes it covers,
+    // leaving the object that was appended untouched.
+    #[test]
+    fn test_wal_stamped() {
+        let dir = "/tmp/sandstorm_wal_stamped.test";
+        let _ = remove_dir_all(dir);
+
+        let wal = Wal::open(dir, 1, 4096, 0).expect("Failed to create log.");
+        let object = [9u8; 12];
+        assert!(wal.append_stamped(&object, false, 2, 0x0102030405060708));
+        assert_eq!([9u8; 12], object);
+
+        let records = wal.records().expect("Failed to read log.");
+        assert_eq!(1, records.len());
+        assert_eq!(&[9, 9, 8, 7, 6, 5, 4, 3, 2, 1, 9, 9], records[0].1);
+        let _ = remove_dir_all(dir);
+    }
+}
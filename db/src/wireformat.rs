@@ -13,8 +13,12 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
+use std::convert::TryFrom;
 use std::mem::size_of;
 
+use super::common::{PACKET_IPV6_LEN, PACKET_MTU};
+use super::crypt;
+
 use e2d2::headers::{EndOffset, UdpHeader};
 
 /// This enum represents the different sets of services that a Sandstorm server
@@ -39,6 +43,19 @@ pub enum Service {
     InvalidService = 0x02,
 }
 
+/// Parses a service off the first byte of an RPC request. `InvalidService` is never returned;
+/// the byte itself is returned as the error for anything that is not a known service.
+impl TryFrom<u8> for Service {
+    type Error = u8;
+
+    fn try_from(service: u8) -> Result<Service, u8> {
+        match service {
+            0x01 => Ok(Service::MasterService),
+            _ => Err(service),
+        }
+    }
+}
+
 /// This enum represents the different operations that can be invoked by a
 /// client over a remote procedure call (RPC). Each operation is typically
 /// provided by a service within a Sandstorm server. For example,
@@ -65,8 +82,118 @@ pub enum OpCode {
     /// This operation fetches multiple records in a single round trip.
     SandstormMultiGetRpc = 0x05,
 
+    /// This operation fetches multiple records from different tables in a single round trip.
+    SandstormMultiTableGetRpc = 0x06,
+
+    /// This operation lists the tables owned by a tenant along with their metadata.
+    SandstormListTablesRpc = 0x07,
+
+    /// This operation adds an association to one of a tenant's association lists.
+    SandstormAssocAddRpc = 0x08,
+
+    /// This operation deletes an association from one of a tenant's association lists.
+    SandstormAssocDelRpc = 0x09,
+
+    /// This operation fetches a time ordered range of associations off an association list.
+    SandstormAssocRangeRpc = 0x0a,
+
+    /// This operation counts the associations on an association list.
+    SandstormAssocCountRpc = 0x0b,
+
+    /// This operation describes the server servicing the request, ex: the number of cores it is
+    /// dispatching requests on.
+    SandstormServerInfoRpc = 0x0c,
+
+    /// This operation atomically moves an object from one of a tenant's tables into another.
+    SandstormMoveKeyRpc = 0x0d,
+
+    /// This operation writes and deletes a batch of objects across a tenant's tables, creating
+    /// the tenant and tables if required. Issued by servers migrating a tenant to this one.
+    SandstormBulkPutRpc = 0x0e,
+
+    /// This operation migrates a tenant's tables to another server, after which requests from
+    /// the tenant are redirected to it.
+    SandstormMigrateRpc = 0x0f,
+
+    /// This operation reads the objects whose keys fall in a range off an ordered table, in key
+    /// order. The response can span multiple packets.
+    SandstormScanRpc = 0x10,
+
+    /// This operation installs, promotes, or removes a shadow version of an extension, which is
+    /// invoked alongside the installed version on a sample of requests and compared against it.
+    SandstormShadowRpc = 0x11,
+
+    /// This operation adds a key-value pair to the database, but only if the version of the
+    /// object currently under the key matches the one expected by the client.
+    SandstormCasRpc = 0x12,
+
+    /// Not an operation. Identifies a response packet carrying a fragment of a response too
+    /// large for a single packet. Only ever sent out by servers. Refer to `FragmentResponse`.
+    SandstormFragment = 0x13,
+
+    /// This operation registers a schema describing the layout of the values on one of a
+    /// tenant's tables, or removes a registered one.
+    SandstormSchemaRpc = 0x14,
+
+    /// This operation writes a snapshot of one of a tenant's tables (keys, values and versions)
+    /// out to a file on the server, or streams it back to the client.
+    SandstormSnapshotRpc = 0x15,
+
+    /// This operation repopulates one of a tenant's tables from a snapshot taken by
+    /// `SandstormSnapshotRpc`, read off a file on the server or carried on the request.
+    SandstormRestoreRpc = 0x16,
+
+    /// A simple operation that removes a key-value pair from the database.
+    SandstormDeleteRpc = 0x17,
+
+    /// This operation returns the counters collected across the server's cores, ex: the packets
+    /// received and sent out by every dispatcher, and the requests seen from every tenant.
+    SandstormGetStatisticsRpc = 0x18,
+
+    /// An administrative operation that creates or deletes a tenant, or creates or drops a
+    /// table under one. Only accepted from the server's admin tenant. Refer to `TenantAction`.
+    SandstormTenantAdminRpc = 0x19,
+
     /// Any value beyond this represents an invalid rpc.
-    InvalidOperation = 0x06,
+    InvalidOperation = 0x1a,
+}
+
+/// Parses an opcode off the second byte of an RPC request or response.
+/// `InvalidOperation` is never returned; the byte itself is returned as the error for anything
+/// that is not a known opcode.
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(opcode: u8) -> Result<OpCode, u8> {
+        match opcode {
+            0x01 => Ok(OpCode::SandstormGetRpc),
+            0x02 => Ok(OpCode::SandstormPutRpc),
+            0x03 => Ok(OpCode::SandstormInvokeRpc),
+            0x04 => Ok(OpCode::SandstormInstallRpc),
+            0x05 => Ok(OpCode::SandstormMultiGetRpc),
+            0x06 => Ok(OpCode::SandstormMultiTableGetRpc),
+            0x07 => Ok(OpCode::SandstormListTablesRpc),
+            0x08 => Ok(OpCode::SandstormAssocAddRpc),
+            0x09 => Ok(OpCode::SandstormAssocDelRpc),
+            0x0a => Ok(OpCode::SandstormAssocRangeRpc),
+            0x0b => Ok(OpCode::SandstormAssocCountRpc),
+            0x0c => Ok(OpCode::SandstormServerInfoRpc),
+            0x0d => Ok(OpCode::SandstormMoveKeyRpc),
+            0x0e => Ok(OpCode::SandstormBulkPutRpc),
+            0x0f => Ok(OpCode::SandstormMigrateRpc),
+            0x10 => Ok(OpCode::SandstormScanRpc),
+            0x11 => Ok(OpCode::SandstormShadowRpc),
+            0x12 => Ok(OpCode::SandstormCasRpc),
+            0x13 => Ok(OpCode::SandstormFragment),
+            0x14 => Ok(OpCode::SandstormSchemaRpc),
+            0x15 => Ok(OpCode::SandstormSnapshotRpc),
+            0x16 => Ok(OpCode::SandstormRestoreRpc),
+            0x17 => Ok(OpCode::SandstormDeleteRpc),
+            0x18 => Ok(OpCode::SandstormGetStatisticsRpc),
+            0x19 => Ok(OpCode::SandstormTenantAdminRpc),
+            _ => Err(opcode),
+        }
+    }
 }
 
 /// This enum represents the status of a completed RPC. A status of 'StatusOk'
@@ -106,6 +233,114 @@ pub enum RpcStatus {
     /// The RPC failed at the server because it requested for an
     /// invalid/unsupported operation.
     StatusInvalidOperation = 0x08,
+
+    /// The invoked extension failed and returned an error. The payload on
+    /// the response consists of a four byte little-endian error code defined
+    /// by the extension, followed by a short UTF-8 message.
+    StatusExtensionError = 0x09,
+
+    /// The invoked extension stopped before it could complete (ex: it ran
+    /// past it's budget). The payload on the response consists of the
+    /// results produced so far, followed by an extension defined
+    /// continuation token, followed by the four byte little-endian length
+    /// of the token. The tenant can resume the extension by reissuing the
+    /// invoke() with the token.
+    StatusPartialResult = 0x0a,
+
+    /// The database is in read-only mode, and the request would have
+    /// modified it. Mutating requests (ex: put() and install()) and
+    /// invocations of extensions that were not declared read-only are
+    /// rejected with this status until the mode is turned off again.
+    StatusReadOnly = 0x0b,
+
+    /// The request would have written to a table faster than the rate it
+    /// is allowed to be written at. The request was not executed, and can be
+    /// retried after backing off.
+    StatusThrottled = 0x0c,
+
+    /// The object's value did not match the checksum stored with it when it
+    /// was written, indicating that it was corrupted while in memory. The
+    /// value was not returned.
+    StatusCorruptObject = 0x0d,
+
+    /// The invoked extension aborted before it could complete (ex: it
+    /// panicked). Anything it wrote to the response was discarded, and so
+    /// were objects it had allocated but not yet put. Objects it had already
+    /// put remain in the database. The response has no payload.
+    StatusExtensionAborted = 0x0e,
+
+    /// The tenant was migrated to a different server, and the request was not executed. The
+    /// payload on the response is the address (IPv4:Port) of the server the tenant now lives on.
+    /// The request should be reissued there.
+    StatusMoved = 0x0f,
+
+    /// The version of the object under the key did not match the one expected by a cas() RPC,
+    /// and the object was not replaced. The response carries the current version.
+    StatusVersionMismatch = 0x10,
+
+    /// The key was deleted by a request stamped later than this write, and the write was not
+    /// applied. Returned to writes that were delayed or replayed past a delete of their key.
+    StatusStaleWrite = 0x11,
+
+    /// The request carried a service or opcode that the server does not know of (ex: it was
+    /// issued by a newer client). The request was not executed. The opcode on the response is
+    /// always `InvalidOperation`, and the response has no payload.
+    StatusBadOpcode = 0x12,
+
+    /// The run-queues of the core the request was received on were full, and the request was
+    /// not executed. Can be retried after backing off. The response has no payload.
+    StatusOverloaded = 0x13,
+
+    /// The request performs an administrative operation, and was not issued by the server's
+    /// admin tenant (or admin operations are disabled on the server). Nothing was modified.
+    StatusUnauthorized = 0x14,
+
+    /// The tenant belongs to a group whose objects have used up the group's memory quota, and the
+    /// write was not applied. Deletes are still accepted, and free up room.
+    StatusQuotaExceeded = 0x15,
+
+    /// The keys on a multiget() were written to every time they were read, and could not be read
+    /// as of a single point in time. Nothing was returned; the request can be retried.
+    StatusSnapshotAborted = 0x16,
+
+    /// The write-ahead log of the core that served the request is full, and the write was not
+    /// applied. Reads and writes already in the log are still served.
+    StatusLogFull = 0x17,
+}
+
+/// Parses a status off the first byte of an RPC response. The byte itself is returned as the
+/// error for anything that is not a known status.
+impl TryFrom<u8> for RpcStatus {
+    type Error = u8;
+
+    fn try_from(status: u8) -> Result<RpcStatus, u8> {
+        match status {
+            0x01 => Ok(RpcStatus::StatusOk),
+            0x02 => Ok(RpcStatus::StatusTenantDoesNotExist),
+            0x03 => Ok(RpcStatus::StatusTableDoesNotExist),
+            0x04 => Ok(RpcStatus::StatusObjectDoesNotExist),
+            0x05 => Ok(RpcStatus::StatusMalformedRequest),
+            0x06 => Ok(RpcStatus::StatusInternalError),
+            0x07 => Ok(RpcStatus::StatusInvalidExtension),
+            0x08 => Ok(RpcStatus::StatusInvalidOperation),
+            0x09 => Ok(RpcStatus::StatusExtensionError),
+            0x0a => Ok(RpcStatus::StatusPartialResult),
+            0x0b => Ok(RpcStatus::StatusReadOnly),
+            0x0c => Ok(RpcStatus::StatusThrottled),
+            0x0d => Ok(RpcStatus::StatusCorruptObject),
+            0x0e => Ok(RpcStatus::StatusExtensionAborted),
+            0x0f => Ok(RpcStatus::StatusMoved),
+            0x10 => Ok(RpcStatus::StatusVersionMismatch),
+            0x11 => Ok(RpcStatus::StatusStaleWrite),
+            0x12 => Ok(RpcStatus::StatusBadOpcode),
+            0x13 => Ok(RpcStatus::StatusOverloaded),
+            0x14 => Ok(RpcStatus::StatusUnauthorized),
+            0x15 => Ok(RpcStatus::StatusQuotaExceeded),
+            0x16 => Ok(RpcStatus::StatusSnapshotAborted),
+            0x17 => Ok(RpcStatus::StatusLogFull),
+            _ => Err(status),
+        }
+    }
 }
 
 /// This type represents the request header on a typical remote procedure call
@@ -133,6 +368,27 @@ pub struct RpcRequestHeader {
 
     /// An identifier for the RPC request.
     pub stamp: u64,
+
+    /// The length in bytes of the entire request header, i.e, this header along with the
+    /// fields specific to the request's opcode. The payload begins right after it. Peers skip
+    /// over header fields they do not know about, so new fields can be appended to a header
+    /// without redeploying clients and servers in lockstep.
+    pub header_len: u16,
+
+    /// The priority of the request. Larger values indicate more urgent
+    /// requests. Zero is the default priority.
+    pub priority: u8,
+
+    /// The time in microseconds after arrival at the server within which the
+    /// request should complete. Zero indicates that there is no deadline.
+    pub deadline: u32,
+
+    /// Flags on the request (ex: `FLAG_TRACE`). Flags a server does not know about are ignored.
+    pub flags: u8,
+
+    /// Reserved for future fields (ex: authentication). Must be zero, and is ignored by peers
+    /// that do not know about any such fields.
+    pub reserved: [u8; 10],
 }
 
 impl RpcRequestHeader {
@@ -150,18 +406,26 @@ impl RpcRequestHeader {
     ///
     /// \return
     ///     A header identifying the RPC. This header is of type
-    ///     'RpcRequestHeader'.
+    ///     'RpcRequestHeader'. The request has the default priority and no
+    ///     deadline, and it's header length is that of rpc_opcode's header.
     pub fn new(
         rpc_service: Service,
         rpc_opcode: OpCode,
         rpc_tenant: u32,
         rpc_stamp: u64,
     ) -> RpcRequestHeader {
+        let header_len = request_header_len(&rpc_opcode) as u16;
+
         RpcRequestHeader {
             service: rpc_service,
             opcode: rpc_opcode,
             tenant: rpc_tenant,
             stamp: rpc_stamp,
+            header_len: header_len,
+            priority: 0,
+            deadline: 0,
+            flags: 0,
+            reserved: [0; 10],
         }
     }
 }
@@ -188,6 +452,18 @@ pub struct RpcResponseHeader {
 
     /// Identifier of the RPC request this response is being generated for.
     pub stamp: u64,
+
+    /// The length in bytes of the entire response header. Refer to `RpcRequestHeader`.
+    pub header_len: u16,
+
+    /// The time in nanoseconds the request spent at the server waiting to run, from it's arrival
+    /// until it's response was ready, excluding the time it spent running. Only filled in if
+    /// `FLAG_TRACE` was set on the request, zero otherwise.
+    pub queued: u32,
+
+    /// The time in nanoseconds the request spent running at the server. Only filled in if
+    /// `FLAG_TRACE` was set on the request, zero otherwise.
+    pub executed: u32,
 }
 
 impl RpcResponseHeader {
@@ -201,11 +477,16 @@ impl RpcResponseHeader {
     /// - `return`: A header of type RpcResponseHeader with the status field
     ///             set to RpcStatus::StatusOk.
     pub fn new(req_stamp: u64, opcode: OpCode, tenant: u32) -> RpcResponseHeader {
+        let header_len = response_header_len(&opcode) as u16;
+
         RpcResponseHeader {
             status: RpcStatus::StatusOk,
             opcode: opcode,
             tenant: tenant,
             stamp: req_stamp,
+            header_len: header_len,
+            queued: 0,
+            executed: 0,
         }
     }
 }
@@ -275,7 +556,7 @@ impl EndOffset for GetRequest {
     // \return
     //     The offset of the payload relative to the GetRequest header.
     fn offset(&self) -> usize {
-        size_of::<GetRequest>()
+        payload_offset(self.common_header.header_len, size_of::<GetRequest>())
     }
 
     // This method returns the size of the GetRequest RPC header.
@@ -327,11 +608,15 @@ pub struct GetResponse {
     /// The length of the value returned in the response if the RPC completed
     /// successfully.
     pub value_length: u32,
+
+    /// The version of the object the value was read from. Can be passed into
+    /// a cas() RPC to replace the object only if it has not changed since.
+    pub version: u64,
 }
 
 impl GetResponse {
     /// This method returns a header that can be added to the response to a
-    /// get() RPC request. The value_length field is set to zero.
+    /// get() RPC request. The value_length and version fields are set to zero.
     ///
     /// - `req_stamp`: RPC identifier.
     /// - `opcode`:    The opcode on the original RPC request.
@@ -343,6 +628,7 @@ impl GetResponse {
         GetResponse {
             common_header: RpcResponseHeader::new(req_stamp, opcode, tenant),
             value_length: 0,
+            version: 0,
         }
     }
 }
@@ -353,7 +639,7 @@ impl EndOffset for GetResponse {
     type PreviousHeader = UdpHeader;
 
     fn offset(&self) -> usize {
-        size_of::<GetResponse>()
+        payload_offset(self.common_header.header_len, size_of::<GetResponse>())
     }
 
     fn size() -> usize {
@@ -420,7 +706,7 @@ impl EndOffset for PutRequest {
     type PreviousHeader = UdpHeader;
 
     fn offset(&self) -> usize {
-        size_of::<PutRequest>()
+        payload_offset(self.common_header.header_len, size_of::<PutRequest>())
     }
 
     fn size() -> usize {
@@ -467,7 +753,7 @@ impl EndOffset for PutResponse {
     type PreviousHeader = UdpHeader;
 
     fn offset(&self) -> usize {
-        size_of::<PutResponse>()
+        payload_offset(self.common_header.header_len, size_of::<PutResponse>())
     }
 
     fn size() -> usize {
@@ -483,6 +769,124 @@ impl EndOffset for PutResponse {
     }
 }
 
+/// This type represents the header on a delete() RPC request. The key to be deleted is carried
+/// in the request's payload.
+#[repr(C, packed)]
+pub struct DeleteRequest {
+    /// A generic RPC header identifying the tenant, service, and opcode of the
+    /// request.
+    pub common_header: RpcRequestHeader,
+
+    /// The data table to remove the key-value pair from.
+    pub table_id: u64,
+
+    /// The length of the key within the RPC's payload.
+    pub key_length: u16,
+}
+
+// Implementation of methods on DeleteRequest.
+impl DeleteRequest {
+    /// This method returns an RPC header that can be added to a delete() request.
+    ///
+    /// # Arguments
+    ///
+    /// * `req_tenant`:  An identifier for the tenant issuing the request.
+    /// * `req_table`:   An identifier for the table to remove the key-value pair from.
+    /// * `req_key_len`: The length of the key inside the RPC request's payload.
+    /// * `req_stamp`:   RPC identifier.
+    ///
+    /// # Return
+    ///
+    /// An RPC header that can be appended to a delete() request.
+    pub fn new(req_tenant: u32, req_table: u64, req_key_len: u16, req_stamp: u64)
+               -> DeleteRequest
+    {
+        let common = RpcRequestHeader::new(
+            Service::MasterService,
+            OpCode::SandstormDeleteRpc,
+            req_tenant,
+            req_stamp,
+        );
+
+        DeleteRequest {
+            common_header: common,
+            table_id: req_table,
+            key_length: req_key_len,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for DeleteRequest. Refer to GetRequest's
+// implementation of this trait to understand what the methods and types mean.
+impl EndOffset for DeleteRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<DeleteRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<DeleteRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the header on a response to a delete() RPC request. The status is
+/// `StatusOk` if the key existed and was deleted, and `StatusObjectDoesNotExist` if there was
+/// nothing to delete.
+#[repr(C, packed)]
+pub struct DeleteResponse {
+    /// A generic RPC header indicating whether the RPC request succeeded
+    /// or failed.
+    pub common_header: RpcResponseHeader,
+}
+
+// Implementation of methods on DeleteResponse.
+impl DeleteResponse {
+    /// This method returns a header that can be appended to the response
+    /// to a delete() RPC request.
+    ///
+    /// # Arguments
+    ///
+    /// * `req_stamp`: RPC identifier.
+    /// * `opcode`:    The opcode on the original RPC request.
+    /// * `tenant`:    The tenant this response should be sent to.
+    pub fn new(req_stamp: u64, opcode: OpCode, tenant: u32) -> DeleteResponse {
+        DeleteResponse {
+            common_header: RpcResponseHeader::new(req_stamp, opcode, tenant),
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for DeleteResponse. Refer to GetRequest's
+// implementation of this trait to understand what the methods and types mean.
+impl EndOffset for DeleteResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<DeleteResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<DeleteResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
 /// This type represents the request header corresponding to an invoke() RPC.
 #[repr(C, packed)]
 pub struct InvokeRequest {
@@ -538,7 +942,7 @@ impl EndOffset for InvokeRequest {
     type PreviousHeader = UdpHeader;
 
     fn offset(&self) -> usize {
-        size_of::<InvokeRequest>()
+        payload_offset(self.common_header.header_len, size_of::<InvokeRequest>())
     }
 
     fn size() -> usize {
@@ -584,7 +988,7 @@ impl EndOffset for InvokeResponse {
     type PreviousHeader = UdpHeader;
 
     fn offset(&self) -> usize {
-        size_of::<InvokeResponse>()
+        payload_offset(self.common_header.header_len, size_of::<InvokeResponse>())
     }
 
     fn size() -> usize {
@@ -613,6 +1017,23 @@ pub struct InstallRequest {
     /// Length of the extension in bytes. The extension should follow the name on the RPC's
     /// payload.
     pub extn_length: u32,
+
+    /// The time in milliseconds for which results of the extension can be cached. Zero if the
+    /// extension is not read-only, and it's results must never be cached.
+    pub cache_ttl: u32,
+
+    /// The number of tables the extension reads from. The identifiers of these tables should
+    /// follow the extension on the RPC's payload, each as a little-endian u64. Results cached for
+    /// the extension are invalidated when any of these tables is written to.
+    pub tables_length: u32,
+
+    /// The cost class declared by the extension's manifest. Refer to `task::CostClass`. Zero if
+    /// the extension did not declare one.
+    pub cost_class: u8,
+
+    /// Non-zero if the extension's manifest declares that it never writes to the database.
+    /// Extensions whose results can be cached are always read-only.
+    pub read_only: u8,
 }
 
 // Implementation of methods on InstallRequest.
@@ -626,8 +1047,23 @@ impl InstallRequest {
     ///                  should start with the name of the extension.
     /// * `extn_length`: Length of the extension in bytes. The extension should follow the name on
     ///                  the RPC's payload.
+    /// * `cache_ttl`:   Time in milliseconds for which results of the extension can be cached.
+    ///                  Zero if the extension's results must not be cached.
+    /// * `num_tables`:  Number of tables the extension reads from. Their identifiers should
+    ///                  follow the extension on the RPC's payload.
+    /// * `cost_class`:  The cost class declared by the extension. Zero if undeclared.
+    /// * `read_only`:   True if the extension never writes to the database.
     /// * `req_stamp`:   RPC identifier.
-    pub fn new(tenant: u32, name_length: u32, extn_length: u32, req_stamp: u64) -> InstallRequest {
+    pub fn new(
+        tenant: u32,
+        name_length: u32,
+        extn_length: u32,
+        cache_ttl: u32,
+        num_tables: u32,
+        cost_class: u8,
+        read_only: bool,
+        req_stamp: u64,
+    ) -> InstallRequest {
         InstallRequest {
             common_header: RpcRequestHeader::new(
                 Service::MasterService,
@@ -637,6 +1073,10 @@ impl InstallRequest {
             ),
             name_length: name_length,
             extn_length: extn_length,
+            cache_ttl: cache_ttl,
+            tables_length: num_tables,
+            cost_class: cost_class,
+            read_only: read_only as u8,
         }
     }
 }
@@ -648,7 +1088,7 @@ impl EndOffset for InstallRequest {
     type PreviousHeader = UdpHeader;
 
     fn offset(&self) -> usize {
-        size_of::<InstallRequest>()
+        payload_offset(self.common_header.header_len, size_of::<InstallRequest>())
     }
 
     fn size() -> usize {
@@ -695,7 +1135,7 @@ impl EndOffset for InstallResponse {
     type PreviousHeader = UdpHeader;
 
     fn offset(&self) -> usize {
-        size_of::<InstallResponse>()
+        payload_offset(self.common_header.header_len, size_of::<InstallResponse>())
     }
 
     fn size() -> usize {
@@ -764,7 +1204,7 @@ impl EndOffset for MultiGetRequest {
     type PreviousHeader = UdpHeader;
 
     fn offset(&self) -> usize {
-        size_of::<MultiGetRequest>()
+        payload_offset(self.common_header.header_len, size_of::<MultiGetRequest>())
     }
 
     fn size() -> usize {
@@ -780,6 +1220,72 @@ impl EndOffset for MultiGetRequest {
     }
 }
 
+/// This type represents the request header for a multiget() RPC request that looks up keys
+/// across multiple tables. The payload on the request consists of `num_keys` entries, each of which
+/// is an eight byte little-endian table identifier followed by a key that is `key_len` bytes long.
+/// The response to this request is of type `MultiGetResponse`.
+#[repr(C, packed)]
+pub struct MultiTableGetRequest {
+    /// Generic RPC header consisting of service, opcode, and tenant id.
+    pub common_header: RpcRequestHeader,
+
+    /// The length of every key to be looked up. All keys to be looked up are assumed to be of
+    /// equal length.
+    pub key_len: u16,
+
+    /// The number of (table, key) pairs to be looked up at the database.
+    pub num_keys: u32,
+}
+
+// Implementation of methods on MultiTableGetRequest.
+impl MultiTableGetRequest {
+    /// Constructs an RPC header that can be added to the multi-table multiget() request. The
+    /// header is of type `MultiTableGetRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Identifier of the tenant sending the request.
+    /// * `k_len`:  Length of every key to be looked up. All keys are assumed to be of equal
+    ///             length.
+    /// * `n_keys`: The number of (table, key) pairs to be looked up.
+    /// * `stamp`:  Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(tenant: u32, k_len: u16, n_keys: u32, stamp: u64) -> MultiTableGetRequest {
+        MultiTableGetRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormMultiTableGetRpc,
+                tenant,
+                stamp,
+            ),
+            key_len: k_len,
+            num_keys: n_keys,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for MultiTableGetRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for MultiTableGetRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<MultiTableGetRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<MultiTableGetRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
 /// This type represents the response header for a multiget() RPC request.
 #[repr(C, packed)]
 pub struct MultiGetResponse {
@@ -816,7 +1322,7 @@ impl EndOffset for MultiGetResponse {
     type PreviousHeader = UdpHeader;
 
     fn offset(&self) -> usize {
-        size_of::<MultiGetResponse>()
+        payload_offset(self.common_header.header_len, size_of::<MultiGetResponse>())
     }
 
     fn size() -> usize {
@@ -831,3 +1337,1986 @@ impl EndOffset for MultiGetResponse {
         true
     }
 }
+
+/// This enum represents the different kinds of tables that a tenant can own. The kind of a table
+/// determines the operations that it supports.
+#[repr(u8)]
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TableKind {
+    /// A table indexed by an unordered hash map. Supports point lookups only.
+    Hash = 0x01,
+
+    /// A table indexed by an unordered hash map along with an ordered index over it's keys.
+    /// Supports point lookups and range scans.
+    Ordered = 0x02,
+}
+
+/// The length in bytes of each entry on the payload of a response to a list_tables() RPC.
+/// Each entry has the following layout (little-endian):
+///      ___________________________________________________
+///     |            |        |               |             |
+///     |  Table-ID  |  Kind  |  Num-Objects  |   Created   |
+///     |____________|________|_______________|_____________|
+///        8 Bytes     1 Byte      8 Bytes        8 Bytes
+///
+/// The creation time is in seconds since the unix epoch.
+pub const LIST_TABLES_ENTRY_LEN: usize = 25;
+
+/// This type represents the request header for a list_tables() RPC request. The request lists
+/// all tables owned by the tenant issuing it, and has no payload.
+#[repr(C, packed)]
+pub struct ListTablesRequest {
+    /// Generic RPC header consisting of service, opcode, and tenant id.
+    pub common_header: RpcRequestHeader,
+}
+
+// Implementation of methods on ListTablesRequest.
+impl ListTablesRequest {
+    /// Constructs an RPC header that can be added to the list_tables() request. The header is of
+    /// type `ListTablesRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Identifier of the tenant whose tables should be listed.
+    /// * `stamp`:  Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(tenant: u32, stamp: u64) -> ListTablesRequest {
+        ListTablesRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormListTablesRpc,
+                tenant,
+                stamp,
+            ),
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for ListTablesRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for ListTablesRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<ListTablesRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<ListTablesRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a list_tables() RPC request. The payload on the
+/// response consists of `num_tables` entries, each `LIST_TABLES_ENTRY_LEN` bytes long.
+#[repr(C, packed)]
+pub struct ListTablesResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// Number of tables listed in the response.
+    pub num_tables: u32,
+}
+
+// Implementation of methods on ListTablesResponse.
+impl ListTablesResponse {
+    /// Constructs a response header for the list_tables() RPC. The header is of type
+    /// `ListTablesResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:    RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`:   The opcode on the original RPC request.
+    /// * `tenant`:   The tenant this response should be sent to.
+    /// * `n_tables`: Number of tables being listed in the response.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32, n_tables: u32) -> ListTablesResponse {
+        ListTablesResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            num_tables: n_tables,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for ListTablesResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for ListTablesResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<ListTablesResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<ListTablesResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the request header for a server_info() RPC request. The request has no
+/// payload.
+#[repr(C, packed)]
+pub struct ServerInfoRequest {
+    /// Generic RPC header consisting of service, opcode, and tenant id.
+    pub common_header: RpcRequestHeader,
+}
+
+// Implementation of methods on ServerInfoRequest.
+impl ServerInfoRequest {
+    /// Constructs an RPC header that can be added to the server_info() request. The header is of
+    /// type `ServerInfoRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Identifier of the tenant issuing the request.
+    /// * `stamp`:  Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(tenant: u32, stamp: u64) -> ServerInfoRequest {
+        ServerInfoRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormServerInfoRpc,
+                tenant,
+                stamp,
+            ),
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for ServerInfoRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for ServerInfoRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<ServerInfoRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<ServerInfoRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a server_info() RPC request. The response has no
+/// payload.
+#[repr(C, packed)]
+pub struct ServerInfoResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// Number of cores the server is dispatching and servicing requests on.
+    pub num_cores: u32,
+
+    /// Identifies the configuration the server is running with. Servers running with the same
+    /// configuration report the same version. Refer to `ServerConfig::version()`.
+    pub config_version: u64,
+
+    /// The leading 16 hex digits of the git commit the server was built from, as an integer.
+    /// Zero if unknown. Refer to `config::git_hash()`.
+    pub git_hash: u64,
+
+    /// The cargo features the server was built with. Refer to `config::features()`.
+    pub features: u32,
+}
+
+// Implementation of methods on ServerInfoResponse.
+impl ServerInfoResponse {
+    /// Constructs a response header for the server_info() RPC. The header is of type
+    /// `ServerInfoResponse`. The version and build of the server are left zeroed.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:     RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`:    The opcode on the original RPC request.
+    /// * `tenant`:    The tenant this response should be sent to.
+    /// * `num_cores`: Number of cores the server is running on.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32, num_cores: u32) -> ServerInfoResponse {
+        ServerInfoResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            num_cores: num_cores,
+            config_version: 0,
+            git_hash: 0,
+            features: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for ServerInfoResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for ServerInfoResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<ServerInfoResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<ServerInfoResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the request header for a get_statistics() RPC request. The request has
+/// no payload.
+#[repr(C, packed)]
+pub struct GetStatisticsRequest {
+    /// Generic RPC header consisting of service, opcode, and tenant id.
+    pub common_header: RpcRequestHeader,
+}
+
+// Implementation of methods on GetStatisticsRequest.
+impl GetStatisticsRequest {
+    /// Constructs an RPC header that can be added to the get_statistics() request. The header is
+    /// of type `GetStatisticsRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Identifier of the tenant issuing the request.
+    /// * `stamp`:  Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(tenant: u32, stamp: u64) -> GetStatisticsRequest {
+        GetStatisticsRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormGetStatisticsRpc,
+                tenant,
+                stamp,
+            ),
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for GetStatisticsRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for GetStatisticsRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<GetStatisticsRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<GetStatisticsRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a get_statistics() RPC request. The payload on
+/// the response consists of the server's statistics, encoded as described by
+/// `stats::ServerStatistics::encode()`.
+#[repr(C, packed)]
+pub struct GetStatisticsResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// The length of the encoded statistics on the response's payload.
+    pub length: u32,
+}
+
+// Implementation of methods on GetStatisticsResponse.
+impl GetStatisticsResponse {
+    /// Constructs a response header for the get_statistics() RPC. The header is of type
+    /// `GetStatisticsResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> GetStatisticsResponse {
+        GetStatisticsResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            length: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for GetStatisticsResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for GetStatisticsResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<GetStatisticsResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<GetStatisticsResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This enum represents the operations that can be performed by a tenant_admin() RPC. The
+/// operation is carried in the `action` field of a `TenantAdminRequest`.
+#[repr(u8)]
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TenantAction {
+    /// Creates the target tenant without any tables. Does nothing if the tenant already exists.
+    CreateTenant = 0x01,
+
+    /// Deletes the target tenant along with all of it's tables, freeing every object on them.
+    DeleteTenant = 0x02,
+
+    /// Creates a table with the requested id under the target tenant. Does nothing if the table
+    /// already exists.
+    CreateTable = 0x03,
+
+    /// Drops the table with the requested id from the target tenant, freeing every object on it.
+    DropTable = 0x04,
+}
+
+/// Parses the action off a tenant_admin() request. The byte itself is returned as the error for
+/// anything that is not a known action.
+impl TryFrom<u8> for TenantAction {
+    type Error = u8;
+
+    fn try_from(action: u8) -> Result<TenantAction, u8> {
+        match action {
+            0x01 => Ok(TenantAction::CreateTenant),
+            0x02 => Ok(TenantAction::DeleteTenant),
+            0x03 => Ok(TenantAction::CreateTable),
+            0x04 => Ok(TenantAction::DropTable),
+            _ => Err(action),
+        }
+    }
+}
+
+/// This type represents the request header for a tenant_admin() RPC request. The request must be
+/// issued by the server's admin tenant, and has no payload.
+#[repr(C, packed)]
+pub struct TenantAdminRequest {
+    /// Generic RPC header consisting of service, opcode, and tenant id.
+    pub common_header: RpcRequestHeader,
+
+    /// The tenant to be created or deleted, or whose tables are to be created or dropped.
+    pub target: u32,
+
+    /// The operation to be performed on the target tenant. Refer to `TenantAction`.
+    pub action: u8,
+
+    /// The table to be created or dropped. Ignored by operations on the tenant itself.
+    pub table_id: u64,
+}
+
+// Implementation of methods on TenantAdminRequest.
+impl TenantAdminRequest {
+    /// Constructs an RPC header that can be added to the tenant_admin() request. The header is of
+    /// type `TenantAdminRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:   Identifier of the tenant issuing the request. Must be the admin tenant.
+    /// * `target`:   Identifier of the tenant the operation should be performed on.
+    /// * `action`:   The operation to be performed.
+    /// * `table_id`: Identifier of the table to be created or dropped, if any.
+    /// * `stamp`:    Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(
+        tenant: u32,
+        target: u32,
+        action: TenantAction,
+        table_id: u64,
+        stamp: u64,
+    ) -> TenantAdminRequest {
+        TenantAdminRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormTenantAdminRpc,
+                tenant,
+                stamp,
+            ),
+            target: target,
+            action: action as u8,
+            table_id: table_id,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for TenantAdminRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for TenantAdminRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<TenantAdminRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<TenantAdminRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a tenant_admin() RPC request. The response has
+/// no payload.
+#[repr(C, packed)]
+pub struct TenantAdminResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// The number of objects freed by the operation. Always zero when creating a tenant or table.
+    pub objects: u64,
+}
+
+// Implementation of methods on TenantAdminResponse.
+impl TenantAdminResponse {
+    /// Constructs a response header for the tenant_admin() RPC. The header is of type
+    /// `TenantAdminResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> TenantAdminResponse {
+        TenantAdminResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            objects: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for TenantAdminResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for TenantAdminResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<TenantAdminResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<TenantAdminResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the request header for a move_key() RPC request. The payload on the
+/// request consists of the key of the object to be moved.
+#[repr(C, packed)]
+pub struct MoveKeyRequest {
+    /// Generic RPC header consisting of service, opcode, and tenant id.
+    pub common_header: RpcRequestHeader,
+
+    /// The table the object is currently in.
+    pub src_table: u64,
+
+    /// The table the object should be moved into.
+    pub dst_table: u64,
+
+    /// The length of the key within the RPC's payload.
+    pub key_length: u16,
+}
+
+// Implementation of methods on MoveKeyRequest.
+impl MoveKeyRequest {
+    /// Constructs an RPC header that can be added to the move_key() request. The header is of
+    /// type `MoveKeyRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:     Identifier of the tenant issuing the request.
+    /// * `src_table`:  The table the object is currently in.
+    /// * `dst_table`:  The table the object should be moved into.
+    /// * `key_length`: The length of the key inside the RPC request's payload.
+    /// * `stamp`:      Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(
+        tenant: u32,
+        src_table: u64,
+        dst_table: u64,
+        key_length: u16,
+        stamp: u64,
+    ) -> MoveKeyRequest {
+        MoveKeyRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormMoveKeyRpc,
+                tenant,
+                stamp,
+            ),
+            src_table: src_table,
+            dst_table: dst_table,
+            key_length: key_length,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for MoveKeyRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for MoveKeyRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<MoveKeyRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<MoveKeyRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a move_key() RPC request. The response has no
+/// payload.
+#[repr(C, packed)]
+pub struct MoveKeyResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+}
+
+// Implementation of methods on MoveKeyResponse.
+impl MoveKeyResponse {
+    /// Constructs a response header for the move_key() RPC. The header is of type
+    /// `MoveKeyResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> MoveKeyResponse {
+        MoveKeyResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for MoveKeyResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for MoveKeyResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<MoveKeyResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<MoveKeyResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// The length of the header on every association in the payload of an assoc_range() response.
+/// Each association is laid out as follows, and is followed by `Data-Length` bytes of data:
+///      ____________________________________________
+///     |            |            |                  |
+///     |    ID-2    |    Time    |   Data-Length    |
+///     |____________|____________|__________________|
+///        8 Bytes      8 Bytes        4 Bytes
+pub const ASSOC_ENTRY_HEADER_LEN: usize = 20;
+
+/// This type represents the request header for the association RPCs (add, del, range, and
+/// count), which operate on the association list of type `atype` out of object `id1`. An add
+/// request carries the data attached to the association as it's payload. The others have none.
+#[repr(C, packed)]
+pub struct AssocRequest {
+    /// Generic RPC header consisting of service, opcode, and tenant id.
+    pub common_header: RpcRequestHeader,
+
+    /// The object the association list originates at.
+    pub id1: u64,
+
+    /// The type of the association list.
+    pub atype: u16,
+
+    /// The object the association points to. Used by add and del.
+    pub id2: u64,
+
+    /// The time of the association on an add. On a range, the time of the newest association
+    /// that can be returned.
+    pub time: u64,
+
+    /// The maximum number of associations returned by a range.
+    pub limit: u32,
+}
+
+// Implementation of methods on AssocRequest.
+impl AssocRequest {
+    /// Constructs an RPC header for an association RPC. The header is of type `AssocRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`: Identifier of the tenant owning the association list.
+    /// * `opcode`: One of the association opcodes.
+    /// * `id1`:    The object the association list originates at.
+    /// * `atype`:  The type of the association list.
+    /// * `id2`:    The object the association points to.
+    /// * `time`:   The time of the association, or the upper bound on a range.
+    /// * `limit`:  The maximum number of associations returned by a range.
+    /// * `stamp`:  Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(
+        tenant: u32,
+        opcode: OpCode,
+        id1: u64,
+        atype: u16,
+        id2: u64,
+        time: u64,
+        limit: u32,
+        stamp: u64,
+    ) -> AssocRequest {
+        AssocRequest {
+            common_header: RpcRequestHeader::new(Service::MasterService, opcode, tenant, stamp),
+            id1: id1,
+            atype: atype,
+            id2: id2,
+            time: time,
+            limit: limit,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for AssocRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for AssocRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<AssocRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<AssocRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for the association RPCs. The payload on the
+/// response to a range consists of `count` associations, each laid out as described under
+/// `ASSOC_ENTRY_HEADER_LEN`. The other responses have no payload.
+#[repr(C, packed)]
+pub struct AssocResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// On an add, the length of the list after the add. On a del, one if the association
+    /// existed, zero otherwise. On a range, the number of associations in the payload, and on a
+    /// count, the length of the list.
+    pub count: u32,
+}
+
+// Implementation of methods on AssocResponse.
+impl AssocResponse {
+    /// Constructs a response header for an association RPC. The header is of type
+    /// `AssocResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> AssocResponse {
+        AssocResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            count: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for AssocResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for AssocResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<AssocResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<AssocResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// The length of the header on every record in the payload of a bulk_put() request. Each record
+/// is laid out as follows, and is followed by `Key-Length` bytes of key and `Value-Length` bytes
+/// of value. A record whose value length is `BULK_PUT_TOMBSTONE` deletes the key, and carries
+/// no value:
+///      _________________________________________________
+///     |            |              |                     |
+///     |   Table    |  Key-Length  |    Value-Length     |
+///     |____________|______________|_____________________|
+///        8 Bytes       2 Bytes           4 Bytes
+pub const BULK_PUT_RECORD_HEADER_LEN: usize = 14;
+
+/// The value length on a bulk_put() record that deletes it's key.
+pub const BULK_PUT_TOMBSTONE: u32 = 0xffffffff;
+
+/// The largest payload on a bulk_put() issued by a tenant over the data path. Requests are never
+/// fragmented, so the request, including it's MAC, IP and UDP headers, must fit in one
+/// `PACKET_MTU` sized packet, even if it is sealed and sent out over IPv6.
+pub const BULK_PUT_PAYLOAD: usize =
+    (PACKET_MTU - PACKET_IPV6_LEN) as usize - size_of::<BulkPutRequest>() - crypt::OVERHEAD;
+
+/// This type represents the request header for a bulk_put() RPC request. The payload on the
+/// request consists of `num_records` records laid out as described by
+/// `BULK_PUT_RECORD_HEADER_LEN`. Records are applied in order.
+#[repr(C, packed)]
+pub struct BulkPutRequest {
+    /// Generic RPC header consisting of service, opcode, and tenant id.
+    pub common_header: RpcRequestHeader,
+
+    /// The number of records on the request's payload.
+    pub num_records: u32,
+}
+
+// Implementation of methods on BulkPutRequest.
+impl BulkPutRequest {
+    /// Constructs an RPC header that can be added to the bulk_put() request. The header is of
+    /// type `BulkPutRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:      Identifier of the tenant the records belong to.
+    /// * `num_records`: The number of records on the request's payload.
+    /// * `stamp`:       Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(tenant: u32, num_records: u32, stamp: u64) -> BulkPutRequest {
+        BulkPutRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormBulkPutRpc,
+                tenant,
+                stamp,
+            ),
+            num_records: num_records,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for BulkPutRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for BulkPutRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<BulkPutRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<BulkPutRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a bulk_put() RPC request. The response has no
+/// payload.
+#[repr(C, packed)]
+pub struct BulkPutResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// The number of records that were applied. Records are applied in order, so on a failure,
+    /// every record after these was not.
+    pub num_records: u32,
+}
+
+// Implementation of methods on BulkPutResponse.
+impl BulkPutResponse {
+    /// Constructs a response header for the bulk_put() RPC. The header is of type
+    /// `BulkPutResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> BulkPutResponse {
+        BulkPutResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            num_records: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for BulkPutResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for BulkPutResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<BulkPutResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<BulkPutResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the request header for a migrate() RPC request. The payload on the
+/// request consists of the address (IPv4:Port) bulk_put() requests should be sent to on the
+/// destination server, followed by the address the tenant's requests should be redirected to.
+#[repr(C, packed)]
+pub struct MigrateRequest {
+    /// Generic RPC header consisting of service, opcode, and the tenant to be migrated.
+    pub common_header: RpcRequestHeader,
+
+    /// The length of the destination's bulk_put() address within the RPC's payload.
+    pub dest_length: u32,
+
+    /// The length of the address requests are redirected to within the RPC's payload.
+    pub redirect_length: u32,
+}
+
+// Implementation of methods on MigrateRequest.
+impl MigrateRequest {
+    /// Constructs an RPC header that can be added to the migrate() request. The header is of
+    /// type `MigrateRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:          Identifier of the tenant to be migrated.
+    /// * `dest_length`:     The length of the destination's bulk_put() address in the payload.
+    /// * `redirect_length`: The length of the address requests are redirected to.
+    /// * `stamp`:           Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(tenant: u32, dest_length: u32, redirect_length: u32, stamp: u64) -> MigrateRequest {
+        MigrateRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormMigrateRpc,
+                tenant,
+                stamp,
+            ),
+            dest_length: dest_length,
+            redirect_length: redirect_length,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for MigrateRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for MigrateRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<MigrateRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<MigrateRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a migrate() RPC request. The response has no
+/// payload.
+#[repr(C, packed)]
+pub struct MigrateResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// The number of records sent to the destination, including objects copied again and keys
+    /// deleted because they were written to while the migration was in progress.
+    pub num_records: u64,
+}
+
+// Implementation of methods on MigrateResponse.
+impl MigrateResponse {
+    /// Constructs a response header for the migrate() RPC. The header is of type
+    /// `MigrateResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> MigrateResponse {
+        MigrateResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            num_records: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for MigrateResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for MigrateResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<MigrateResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<MigrateResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// The length of the header on every record in the payload of a scan() response. Each record is
+/// laid out as follows, and is followed by `Key-Length` bytes of key and `Value-Length` bytes of
+/// value:
+///      _________________________________
+///     |              |                  |
+///     |  Key-Length  |   Value-Length   |
+///     |______________|__________________|
+///         2 Bytes          4 Bytes
+pub const SCAN_RECORD_HEADER_LEN: usize = 6;
+
+/// This type represents the request header for a scan() RPC request. The payload on the request
+/// consists of the first key in the range, followed by the key after the last one in it.
+#[repr(C, packed)]
+pub struct ScanRequest {
+    /// Generic RPC header consisting of service, opcode, and tenant id.
+    pub common_header: RpcRequestHeader,
+
+    /// The ordered table to be scanned.
+    pub table_id: u64,
+
+    /// The length of the first key in the range within the RPC's payload.
+    pub start_length: u16,
+
+    /// The length of the key after the last one in the range within the RPC's payload. Zero if
+    /// the range extends to the end of the table.
+    pub end_length: u16,
+
+    /// The maximum number of objects to be returned. The server may return fewer.
+    pub limit: u32,
+}
+
+// Implementation of methods on ScanRequest.
+impl ScanRequest {
+    /// Constructs an RPC header that can be added to the scan() request. The header is of type
+    /// `ScanRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:       Identifier of the tenant issuing the request.
+    /// * `table_id`:     The ordered table to be scanned.
+    /// * `start_length`: The length of the first key in the range inside the payload.
+    /// * `end_length`:   The length of the key after the last one in the range inside the
+    ///                   payload. Zero if the range extends to the end of the table.
+    /// * `limit`:        The maximum number of objects to be returned.
+    /// * `stamp`:        Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(
+        tenant: u32,
+        table_id: u64,
+        start_length: u16,
+        end_length: u16,
+        limit: u32,
+        stamp: u64,
+    ) -> ScanRequest {
+        ScanRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormScanRpc,
+                tenant,
+                stamp,
+            ),
+            table_id: table_id,
+            start_length: start_length,
+            end_length: end_length,
+            limit: limit,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for ScanRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for ScanRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<ScanRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<ScanRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a scan() RPC request. A scan whose results do
+/// not fit in a single packet is responded to with multiple packets, each carrying this header.
+/// The payload on each consists of `num_records` records laid out as described by
+/// `SCAN_RECORD_HEADER_LEN`. Records are in key order across packets, which might arrive out of
+/// order.
+#[repr(C, packed)]
+pub struct ScanResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// The number of records on this packet's payload.
+    pub num_records: u32,
+
+    /// The position of this packet among the packets making up the response, starting at zero.
+    pub seq: u16,
+
+    /// The number of packets making up the response.
+    pub num_packets: u16,
+}
+
+// Implementation of methods on ScanResponse.
+impl ScanResponse {
+    /// Constructs a response header for the scan() RPC. The header is of type `ScanResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:       RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`:      The opcode on the original RPC request.
+    /// * `tenant`:      The tenant this response should be sent to.
+    /// * `num_records`: The number of records on this packet's payload.
+    /// * `seq`:         The position of this packet among the packets making up the response.
+    /// * `num_packets`: The number of packets making up the response.
+    pub fn new(
+        stamp: u64,
+        opcode: OpCode,
+        tenant: u32,
+        num_records: u32,
+        seq: u16,
+        num_packets: u16,
+    ) -> ScanResponse {
+        ScanResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            num_records: num_records,
+            seq: seq,
+            num_packets: num_packets,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for ScanResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for ScanResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<ScanResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<ScanResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the request header for a shadow() RPC request. The payload on the
+/// request consists of the name of the extension, followed by the shadow version of it if one is
+/// being installed. What the request does depends on it's fields:
+///
+/// * `promote` non-zero: the shadow version replaces the installed one. `extn_length` must be
+///   zero.
+/// * `extn_length` non-zero: the payload's extension is installed as the shadow version,
+///   replacing any earlier one. `sample` must be non-zero.
+/// * `sample` non-zero: the shadow version is invoked on one in every `sample` invocations.
+/// * All three zero: the shadow version is removed.
+#[repr(C, packed)]
+pub struct ShadowRequest {
+    /// Generic RPC header consisting of service, opcode, and the tenant owning the extension.
+    pub common_header: RpcRequestHeader,
+
+    /// The length of the name of the extension within the RPC's payload.
+    pub name_length: u32,
+
+    /// The length of the shadow version within the RPC's payload. Zero if none is installed.
+    pub extn_length: u32,
+
+    /// The shadow version is invoked alongside the installed one on one in every these many
+    /// invocations.
+    pub sample: u32,
+
+    /// Non-zero if the shadow version should replace the installed one.
+    pub promote: u8,
+}
+
+// Implementation of methods on ShadowRequest.
+impl ShadowRequest {
+    /// Constructs an RPC header that can be added to the shadow() request. The header is of type
+    /// `ShadowRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:      Identifier of the tenant owning the extension.
+    /// * `name_length`: The length of the name of the extension in the payload.
+    /// * `extn_length`: The length of the shadow version in the payload. Zero if none.
+    /// * `sample`:      The shadow version is invoked on one in every these many invocations.
+    /// * `promote`:     True if the shadow version should replace the installed one.
+    /// * `stamp`:       Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(
+        tenant: u32,
+        name_length: u32,
+        extn_length: u32,
+        sample: u32,
+        promote: bool,
+        stamp: u64,
+    ) -> ShadowRequest {
+        ShadowRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormShadowRpc,
+                tenant,
+                stamp,
+            ),
+            name_length: name_length,
+            extn_length: extn_length,
+            sample: sample,
+            promote: promote as u8,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for ShadowRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for ShadowRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<ShadowRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<ShadowRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a shadow() RPC request. It carries how the
+/// shadow version compared against the installed one until the request was received, so that a
+/// tenant can poll it (ex: with a request that only changes `sample`). The response has no
+/// payload.
+#[repr(C, packed)]
+pub struct ShadowResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// The number of invocations the shadow version was run on.
+    pub runs: u64,
+
+    /// The number of those on which the response of the shadow version differed from that of the
+    /// installed version.
+    pub mismatches: u64,
+
+    /// The total number of cycles the installed version ran for on those invocations.
+    pub primary_cycles: u64,
+
+    /// The total number of cycles the shadow version ran for on those invocations.
+    pub shadow_cycles: u64,
+}
+
+// Implementation of methods on ShadowResponse.
+impl ShadowResponse {
+    /// Constructs a response header for the shadow() RPC. The header is of type
+    /// `ShadowResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier. Can be used to timestamp the RPC.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> ShadowResponse {
+        ShadowResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            runs: 0,
+            mismatches: 0,
+            primary_cycles: 0,
+            shadow_cycles: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for ShadowResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for ShadowResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<ShadowResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<ShadowResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the header on a cas() RPC request. The payload consists of the key
+/// followed by the value to be written under it.
+#[repr(C, packed)]
+pub struct CasRequest {
+    /// A generic RPC header identifying the tenant, service, and opcode of the request.
+    pub common_header: RpcRequestHeader,
+
+    /// The data table to add the key-value pair to.
+    pub table_id: u64,
+
+    /// The length of the key within the RPC's payload.
+    pub key_length: u16,
+
+    /// The version the object under the key is expected to be at. Zero if the key is expected
+    /// to not exist.
+    pub version: u64,
+}
+
+// Implementation of methods on CasRequest.
+impl CasRequest {
+    /// Constructs an RPC header that can be added to a cas() request.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:     Identifier of the tenant issuing the request.
+    /// * `table_id`:   The table to add the key-value pair to.
+    /// * `key_length`: The length of the key inside the RPC request's payload.
+    /// * `version`:    The version the object under the key is expected to be at. Zero if the
+    ///                 key is expected to not exist.
+    /// * `stamp`:      RPC identifier.
+    pub fn new(tenant: u32, table_id: u64, key_length: u16, version: u64, stamp: u64)
+               -> CasRequest
+    {
+        CasRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormCasRpc,
+                tenant,
+                stamp,
+            ),
+            table_id: table_id,
+            key_length: key_length,
+            version: version,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for CasRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for CasRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<CasRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<CasRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a cas() RPC request. The response has no
+/// payload.
+#[repr(C, packed)]
+pub struct CasResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// The version of the written object if the RPC completed successfully. If the status is
+    /// `StatusVersionMismatch`, the version of the object currently under the key instead, or
+    /// zero if the key does not exist.
+    pub version: u64,
+}
+
+// Implementation of methods on CasResponse.
+impl CasResponse {
+    /// Constructs a response header for the cas() RPC. The version is set to zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> CasResponse {
+        CasResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            version: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for CasResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for CasResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<CasResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<CasResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// The largest number of bytes of the original response carried by a single fragment. Every
+/// fragment, including it's MAC, IP and UDP headers, fits in one `PACKET_MTU` sized packet, even
+/// if it is sent out over IPv6.
+pub const FRAGMENT_PAYLOAD: usize =
+    (PACKET_MTU - PACKET_IPV6_LEN) as usize - size_of::<FragmentResponse>();
+
+/// This type represents the header on a fragment of a response too large to be sent out in a
+/// single packet. Such a response is split into `total` fragments, each carrying this header,
+/// and upto `FRAGMENT_PAYLOAD` bytes of the original response following it's UDP header (i.e,
+/// starting with it's RPC header). The original response is the concatenation of the payloads
+/// of all fragments in `index` order; fragments might arrive out of order. The common header
+/// carries the stamp and tenant of the original response, so that a sealed response can be
+/// reassembled before it is opened.
+///
+/// Fragmentation lifts the MTU on responses, not the size of a packet buffer: the original
+/// response is never larger than `common::MAX_RESPONSE_LEN` (2006) bytes, so it is split into
+/// at most two fragments. Larger responses, such as an invocation's, fail on the server.
+#[repr(C, packed)]
+pub struct FragmentResponse {
+    /// Generic response header. The opcode is always `SandstormFragment`.
+    pub common_header: RpcResponseHeader,
+
+    /// Identifies the response this is a fragment of among those sent out by the same server.
+    pub id: u32,
+
+    /// The position of this fragment among the fragments making up the response, starting at
+    /// zero.
+    pub index: u16,
+
+    /// The number of fragments making up the response.
+    pub total: u16,
+}
+
+// Implementation of methods on FragmentResponse.
+impl FragmentResponse {
+    /// Constructs the header on a fragment of a response.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  The stamp on the original response.
+    /// * `tenant`: The tenant the original response is destined for.
+    /// * `id`:     Identifies the original response.
+    /// * `index`:  The position of the fragment.
+    /// * `total`:  The number of fragments making up the response.
+    pub fn new(stamp: u64, tenant: u32, id: u32, index: u16, total: u16) -> FragmentResponse {
+        FragmentResponse {
+            common_header: RpcResponseHeader::new(stamp, OpCode::SandstormFragment, tenant),
+            id: id,
+            index: index,
+            total: total,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for FragmentResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for FragmentResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<FragmentResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<FragmentResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the header on a schema() RPC request. The payload consists of the
+/// schema encoded by `sandstorm::schema::Schema::encode()`. A request without a payload removes
+/// any schema registered for the table.
+#[repr(C, packed)]
+pub struct SchemaRequest {
+    /// Generic RPC header consisting of service, opcode, and the tenant owning the table.
+    pub common_header: RpcRequestHeader,
+
+    /// The identifier of the table the schema describes.
+    pub table_id: u64,
+
+    /// The length of the encoded schema within the RPC's payload.
+    pub schema_length: u32,
+}
+
+// Implementation of methods on SchemaRequest.
+impl SchemaRequest {
+    /// Constructs an RPC header that can be added to the schema() request. The header is of type
+    /// `SchemaRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:        Identifier of the tenant owning the table.
+    /// * `table_id`:      Identifier of the table the schema describes.
+    /// * `schema_length`: The length of the encoded schema in the payload. Zero to remove it.
+    /// * `stamp`:         Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(tenant: u32, table_id: u64, schema_length: u32, stamp: u64) -> SchemaRequest {
+        SchemaRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormSchemaRpc,
+                tenant,
+                stamp,
+            ),
+            table_id: table_id,
+            schema_length: schema_length,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for SchemaRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for SchemaRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<SchemaRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<SchemaRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a schema() RPC request. The response has no
+/// payload.
+#[repr(C, packed)]
+pub struct SchemaResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+}
+
+// Implementation of methods on SchemaResponse.
+impl SchemaResponse {
+    /// Constructs a response header for the schema() RPC. The header is of type
+    /// `SchemaResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> SchemaResponse {
+        SchemaResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for SchemaResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for SchemaResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<SchemaResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<SchemaResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the header on a snapshot() RPC request. The payload consists of the path
+/// of the file on the server the snapshot should be written to. A request without a payload
+/// streams the snapshot back to the client instead. Refer to `snapshot::TableWriter` for the
+/// layout of a snapshot.
+#[repr(C, packed)]
+pub struct SnapshotRequest {
+    /// Generic RPC header consisting of service, opcode, and the tenant owning the table.
+    pub common_header: RpcRequestHeader,
+
+    /// The identifier of the table to snapshot.
+    pub table_id: u64,
+
+    /// The length of the path within the RPC's payload.
+    pub path_length: u32,
+}
+
+// Implementation of methods on SnapshotRequest.
+impl SnapshotRequest {
+    /// Constructs an RPC header that can be added to the snapshot() request. The header is of
+    /// type `SnapshotRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:      Identifier of the tenant owning the table.
+    /// * `table_id`:    Identifier of the table to snapshot.
+    /// * `path_length`: The length of the path in the payload. Zero to stream the snapshot back.
+    /// * `stamp`:       Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(tenant: u32, table_id: u64, path_length: u32, stamp: u64) -> SnapshotRequest {
+        SnapshotRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormSnapshotRpc,
+                tenant,
+                stamp,
+            ),
+            table_id: table_id,
+            path_length: path_length,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for SnapshotRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for SnapshotRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<SnapshotRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<SnapshotRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a snapshot() RPC request. If the snapshot was
+/// streamed back, the payload consists of the snapshot, and continues until the end of the
+/// stream. The response has no payload otherwise.
+#[repr(C, packed)]
+pub struct SnapshotResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// The number of objects written into the snapshot. Zero if the snapshot was streamed back,
+    /// in which case the number is carried at the end of the snapshot.
+    pub num_records: u64,
+}
+
+// Implementation of methods on SnapshotResponse.
+impl SnapshotResponse {
+    /// Constructs a response header for the snapshot() RPC. The header is of type
+    /// `SnapshotResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> SnapshotResponse {
+        SnapshotResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            num_records: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for SnapshotResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for SnapshotResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<SnapshotResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<SnapshotResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the header on a restore() RPC request. The payload consists of the path
+/// of a snapshot file on the server. A request without a path carries the snapshot itself as the
+/// rest of it's payload instead.
+#[repr(C, packed)]
+pub struct RestoreRequest {
+    /// Generic RPC header consisting of service, opcode, and the tenant owning the table.
+    pub common_header: RpcRequestHeader,
+
+    /// The identifier of the table to restore. Must be the table the snapshot was taken of.
+    pub table_id: u64,
+
+    /// The length of the path within the RPC's payload.
+    pub path_length: u32,
+}
+
+// Implementation of methods on RestoreRequest.
+impl RestoreRequest {
+    /// Constructs an RPC header that can be added to the restore() request. The header is of type
+    /// `RestoreRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tenant`:      Identifier of the tenant owning the table.
+    /// * `table_id`:    Identifier of the table to restore.
+    /// * `path_length`: The length of the path in the payload. Zero if the snapshot follows.
+    /// * `stamp`:       Identifier of the RPC. Can be used as a timestamp.
+    pub fn new(tenant: u32, table_id: u64, path_length: u32, stamp: u64) -> RestoreRequest {
+        RestoreRequest {
+            common_header: RpcRequestHeader::new(
+                Service::MasterService,
+                OpCode::SandstormRestoreRpc,
+                tenant,
+                stamp,
+            ),
+            table_id: table_id,
+            path_length: path_length,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for RestoreRequest. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for RestoreRequest {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<RestoreRequest>())
+    }
+
+    fn size() -> usize {
+        size_of::<RestoreRequest>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// This type represents the response header for a restore() RPC request. The response has no
+/// payload.
+#[repr(C, packed)]
+pub struct RestoreResponse {
+    /// Generic response header consisting of RPC status and identifier.
+    pub common_header: RpcResponseHeader,
+
+    /// The number of objects restored into the table.
+    pub num_records: u64,
+}
+
+// Implementation of methods on RestoreResponse.
+impl RestoreResponse {
+    /// Constructs a response header for the restore() RPC. The header is of type
+    /// `RestoreResponse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp`:  RPC identifier.
+    /// * `opcode`: The opcode on the original RPC request.
+    /// * `tenant`: The tenant this response should be sent to.
+    pub fn new(stamp: u64, opcode: OpCode, tenant: u32) -> RestoreResponse {
+        RestoreResponse {
+            common_header: RpcResponseHeader::new(stamp, opcode, tenant),
+            num_records: 0,
+        }
+    }
+}
+
+// Implementation of the EndOffset trait for RestoreResponse. Refer to
+// GetRequest's implementation of this trait to understand what the methods
+// and types mean.
+impl EndOffset for RestoreResponse {
+    type PreviousHeader = UdpHeader;
+
+    fn offset(&self) -> usize {
+        payload_offset(self.common_header.header_len, size_of::<RestoreResponse>())
+    }
+
+    fn size() -> usize {
+        size_of::<RestoreResponse>()
+    }
+
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    fn check_correct(&self, _prev: &Self::PreviousHeader) -> bool {
+        true
+    }
+}
+
+/// The offset on an RPC request or response's payload at which the two byte header length lies.
+pub const HEADER_LEN_OFFSET: usize = 14;
+
+/// Set on the flags of an RPC request to have the server report the time the request spent
+/// waiting and running at it on the response. Refer to `RpcResponseHeader`.
+pub const FLAG_TRACE: u8 = 0x01;
+
+/// Returns the offset of the payload on an RPC given the header length on it. Fields beyond the
+/// header being parsed are skipped over, but the payload never begins before the end of it.
+/// Header lengths must be validated with `rpc::header_len_ok()` before a payload is accessed.
+///
+/// # Arguments
+///
+/// * `header_len`: The header length on the RPC.
+/// * `parsed`:     The length of the header the RPC is being parsed as.
+#[inline]
+fn payload_offset(header_len: u16, parsed: usize) -> usize {
+    (header_len as usize).max(parsed)
+}
+
+/// Returns the length of the header on an RPC request with a particular opcode, as known to this
+/// build. Requests from newer peers may carry longer headers. Refer to `RpcRequestHeader`.
+///
+/// # Arguments
+///
+/// * `opcode`: The opcode on the request.
+pub fn request_header_len(opcode: &OpCode) -> usize {
+    match *opcode {
+        OpCode::SandstormGetRpc => size_of::<GetRequest>(),
+        OpCode::SandstormPutRpc => size_of::<PutRequest>(),
+        OpCode::SandstormInvokeRpc => size_of::<InvokeRequest>(),
+        OpCode::SandstormInstallRpc => size_of::<InstallRequest>(),
+        OpCode::SandstormMultiGetRpc => size_of::<MultiGetRequest>(),
+        OpCode::SandstormMultiTableGetRpc => size_of::<MultiTableGetRequest>(),
+        OpCode::SandstormListTablesRpc => size_of::<ListTablesRequest>(),
+        OpCode::SandstormAssocAddRpc
+        | OpCode::SandstormAssocDelRpc
+        | OpCode::SandstormAssocRangeRpc
+        | OpCode::SandstormAssocCountRpc => size_of::<AssocRequest>(),
+        OpCode::SandstormServerInfoRpc => size_of::<ServerInfoRequest>(),
+        OpCode::SandstormMoveKeyRpc => size_of::<MoveKeyRequest>(),
+        OpCode::SandstormBulkPutRpc => size_of::<BulkPutRequest>(),
+        OpCode::SandstormMigrateRpc => size_of::<MigrateRequest>(),
+        OpCode::SandstormScanRpc => size_of::<ScanRequest>(),
+        OpCode::SandstormShadowRpc => size_of::<ShadowRequest>(),
+        OpCode::SandstormCasRpc => size_of::<CasRequest>(),
+        OpCode::SandstormSchemaRpc => size_of::<SchemaRequest>(),
+        OpCode::SandstormSnapshotRpc => size_of::<SnapshotRequest>(),
+        OpCode::SandstormRestoreRpc => size_of::<RestoreRequest>(),
+        OpCode::SandstormDeleteRpc => size_of::<DeleteRequest>(),
+        OpCode::SandstormGetStatisticsRpc => size_of::<GetStatisticsRequest>(),
+        OpCode::SandstormTenantAdminRpc => size_of::<TenantAdminRequest>(),
+        _ => size_of::<RpcRequestHeader>(),
+    }
+}
+
+/// Returns the length of the header on an RPC response with a particular opcode, as known to
+/// this build. Responses from newer peers may carry longer headers.
+///
+/// # Arguments
+///
+/// * `opcode`: The opcode on the response.
+pub fn response_header_len(opcode: &OpCode) -> usize {
+    match *opcode {
+        OpCode::SandstormGetRpc => size_of::<GetResponse>(),
+        OpCode::SandstormPutRpc => size_of::<PutResponse>(),
+        OpCode::SandstormInvokeRpc => size_of::<InvokeResponse>(),
+        OpCode::SandstormInstallRpc => size_of::<InstallResponse>(),
+        OpCode::SandstormMultiGetRpc => size_of::<MultiGetResponse>(),
+        OpCode::SandstormMultiTableGetRpc => size_of::<MultiGetResponse>(),
+        OpCode::SandstormListTablesRpc => size_of::<ListTablesResponse>(),
+        OpCode::SandstormAssocAddRpc
+        | OpCode::SandstormAssocDelRpc
+        | OpCode::SandstormAssocRangeRpc
+        | OpCode::SandstormAssocCountRpc => size_of::<AssocResponse>(),
+        OpCode::SandstormServerInfoRpc => size_of::<ServerInfoResponse>(),
+        OpCode::SandstormMoveKeyRpc => size_of::<MoveKeyResponse>(),
+        OpCode::SandstormBulkPutRpc => size_of::<BulkPutResponse>(),
+        OpCode::SandstormMigrateRpc => size_of::<MigrateResponse>(),
+        OpCode::SandstormScanRpc => size_of::<ScanResponse>(),
+        OpCode::SandstormShadowRpc => size_of::<ShadowResponse>(),
+        OpCode::SandstormCasRpc => size_of::<CasResponse>(),
+        OpCode::SandstormFragment => size_of::<FragmentResponse>(),
+        OpCode::SandstormSchemaRpc => size_of::<SchemaResponse>(),
+        OpCode::SandstormSnapshotRpc => size_of::<SnapshotResponse>(),
+        OpCode::SandstormRestoreRpc => size_of::<RestoreResponse>(),
+        OpCode::SandstormDeleteRpc => size_of::<DeleteResponse>(),
+        OpCode::SandstormGetStatisticsRpc => size_of::<GetStatisticsResponse>(),
+        OpCode::SandstormTenantAdminRpc => size_of::<TenantAdminResponse>(),
+        _ => size_of::<RpcResponseHeader>(),
+    }
+}
@@ -0,0 +1,160 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+#![crate_type = "dylib"]
+#![feature(no_unsafe)]
+#![feature(generators, generator_trait)]
+
+extern crate sandstorm;
+
+use std::ops::Generator;
+use std::rc::Rc;
+
+use sandstorm::db::DB;
+
+// The length of the arguments to the extension: table(8) | allocs(4) | puts(4) | gets(4) |
+// val_len(2). All fields are little-endian.
+const ARGS_LEN: usize = 22;
+
+// The number of operations performed between yields to the database. Time spent yielded is not
+// counted towards the cost of an operation.
+const YIELD_EVERY: u32 = 128;
+
+// The phases of the benchmark, in the order they are run and reported in.
+const ALLOC: usize = 0;
+const PUT: usize = 1;
+const GET: usize = 2;
+const PHASES: usize = 3;
+
+// Reads a little-endian integer of upto eight bytes out of a slice.
+fn read_le(buf: &[u8]) -> u64 {
+    buf.iter().rev().fold(0, |acc, b| (acc << 8) | *b as u64)
+}
+
+// Appends the lower `n` bytes of an integer to a vector in little-endian order.
+fn write_le(val: u64, n: usize, out: &mut Vec<u8>) {
+    for i in 0..n {
+        out.push((val >> (8 * i)) as u8);
+    }
+}
+
+// Returns the key of the i'th object in the scratch table.
+fn key(i: u32) -> [u8; 8] {
+    let mut key = [0; 8];
+    for b in 0..4 {
+        key[b] = (i >> (8 * b)) as u8;
+    }
+    key
+}
+
+/// This function implements the bench() extension using the sandstorm interface. The extension
+/// measures the server side cost of the database's primitives in situ, free of the noise that
+/// comes with timing requests at a client. It performs a number of allocs (that are never
+/// written to the database), puts, and gets against a scratch table, in that order, and times
+/// each kind with the database's cycle counter.
+///
+/// The arguments consist of the scratch table, the number of allocs, puts, and gets, and the
+/// length of the value on every object allocated or put. Objects are keyed by their eight byte
+/// little-endian index, so gets look up the objects written by the puts, wrapping around if
+/// there are more gets than puts. Allocs and puts beyond the extension's allocation quota fail.
+///
+/// The response consists of one record of 16 bytes for each of allocs, puts, and gets, in that
+/// order: ops(4) | succeeded(4) | cycles(8), all little-endian. `cycles` is the total time spent
+/// on all `ops`, and excludes time spent yielded.
+///
+/// # Arguments
+///
+/// * `db`: An argument whose type implements the `DB` trait which can be used
+///         to interact with the database.
+///
+/// # Return
+///
+/// A coroutine that can be run inside the database.
+#[no_mangle]
+#[allow(unreachable_code)]
+#[allow(unused_assignments)]
+pub fn init(db: Rc<DB>) -> Box<Generator<Yield = u64, Return = u64>> {
+    Box::new(move || {
+        let mut table = 0;
+        let mut ops = [0u32; PHASES];
+        let mut val_len = 0;
+
+        {
+            // First off, retrieve and de-serialize the arguments to the extension.
+            let args = db.args();
+            if args.len() < ARGS_LEN {
+                let error = "Invalid args";
+                db.resp(error.as_bytes());
+                return 1;
+            }
+
+            table = read_le(&args[0..8]);
+            ops[ALLOC] = read_le(&args[8..12]) as u32;
+            ops[PUT] = read_le(&args[12..16]) as u32;
+            ops[GET] = read_le(&args[16..20]) as u32;
+            val_len = read_le(&args[20..22]) as usize;
+        }
+
+        let val = vec![0xab; val_len];
+        let mut succeeded = [0u32; PHASES];
+        let mut cycles = [0u64; PHASES];
+
+        for phase in 0..PHASES {
+            let mut i = 0;
+
+            // Run the phase in chunks, yielding to the database after each one.
+            while i < ops[phase] {
+                let end = i.saturating_add(YIELD_EVERY).min(ops[phase]);
+                let start = db.now_cycles();
+
+                while i < end {
+                    let ok = match phase {
+                        ALLOC => db.alloc(table, &key(i), val_len as u64).is_some(),
+
+                        PUT => db.alloc(table, &key(i), val_len as u64).map_or(false, |mut buf| {
+                            buf.write_slice(&val);
+                            db.put(buf)
+                        }),
+
+                        _ => {
+                            let k = key(i % ops[PUT].max(1));
+                            db.get(table, &k).map_or(false, |obj| obj.read().len() == val_len)
+                        }
+                    };
+
+                    if ok {
+                        succeeded[phase] += 1;
+                    }
+                    i += 1;
+                }
+
+                cycles[phase] += db.now_cycles() - start;
+                yield 0;
+            }
+        }
+
+        // Report the cost of every phase to the tenant.
+        let mut resp = Vec::with_capacity(PHASES * 16);
+        for phase in 0..PHASES {
+            write_le(ops[phase] as u64, 4, &mut resp);
+            write_le(succeeded[phase] as u64, 4, &mut resp);
+            write_le(cycles[phase], 8, &mut resp);
+        }
+        db.resp(&resp);
+
+        // Procedure completed.
+        return 0;
+    })
+}
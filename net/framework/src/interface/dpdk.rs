@@ -52,6 +52,36 @@ pub fn init_system_secondary(name: &str, core: i32) {
     set_numa_domain();
 }
 
+/// Allocate packets from one mempool per core instead of one per NUMA node, so that cores never
+/// contend on a pool. `pool_size` then applies to every core's pool. Must be called before the
+/// system is initialized.
+pub fn set_per_core_pools(enable: bool) {
+    unsafe { zcsi::set_per_core_mempools(enable as i32) }
+}
+
+/// Steer UDP packets destined to `udp_port` to receive queue `rxq` with a flow director rule.
+/// The queue is then dedicated to steered ports: packets it would have received under the default
+/// rules go to the next queue instead. Must be called before the system is initialized.
+pub fn steer_udp_port(udp_port: u16, rxq: i32) -> bool {
+    unsafe { zcsi::steer_udp_port(udp_port, rxq) == 0 }
+}
+
+/// Enable hardware timestamping of received packets on ports that support it. Refer to
+/// `Packet::rx_timestamp()`. Must be called before the system is initialized.
+pub fn set_hw_timestamps(enable: bool) {
+    unsafe { zcsi::set_hw_timestamps(enable as i32) }
+}
+
+/// Read the clock that a port timestamps packets with, in nanoseconds. Returns None if the port
+/// does not support hardware timestamps.
+pub fn read_nic_clock(port: i32) -> Option<u64> {
+    let mut ns: u64 = 0;
+    match unsafe { zcsi::read_nic_clock(port, &mut ns) } {
+        0 => Some(ns),
+        _ => None,
+    }
+}
+
 /// Initialize the system based on the supplied scheduler configuration.
 pub fn init_system(config: &NetbricksConfiguration) {
     if config.name.is_empty() {
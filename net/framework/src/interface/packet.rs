@@ -517,6 +517,13 @@ impl<T: EndOffset, M: Sized + Send> Packet<T, M> {
         unsafe { (*self.mbuf).refcnt() }
     }
 
+    /// The time at which the NIC received this packet in nanoseconds of the NIC's clock, if it was
+    /// timestamped in hardware. Refer to `dpdk::set_hw_timestamps()`.
+    #[inline]
+    pub fn rx_timestamp(&self) -> Option<u64> {
+        unsafe { (*self.mbuf).rx_timestamp() }
+    }
+
     /// Get the mbuf reference by this packet.
     ///
     /// # Safety
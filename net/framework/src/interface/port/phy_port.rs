@@ -90,6 +90,10 @@ impl PortQueue {
     pub fn rxq(&self) -> i32 {
         self.rxq
     }
+
+    pub fn port_id(&self) -> i32 {
+        self.port_id
+    }
 }
 
 impl PacketTx for PortQueue {
@@ -1,3 +1,11 @@
+// Set on ol_flags when the NIC timestamped a received mbuf.
+const PKT_RX_TIMESTAMP: u64 = 1 << 17;
+
+// Set on ol_flags to have the NIC fill in the IPv4 header and UDP checksums of a sent mbuf.
+const PKT_TX_UDP_CKSUM: u64 = 3 << 52;
+const PKT_TX_IP_CKSUM: u64 = 1 << 54;
+const PKT_TX_IPV4: u64 = 1 << 55;
+
 #[repr(C)]
 pub struct MBuf {
     buf_addr: *mut u8,
@@ -62,6 +70,26 @@ impl MBuf {
         }
     }
 
+    /// Returns the time at which the NIC received this mbuf in nanoseconds of the NIC's clock, if
+    /// it was timestamped in hardware.
+    #[inline]
+    pub fn rx_timestamp(&self) -> Option<u64> {
+        if self.ol_flags & PKT_RX_TIMESTAMP != 0 {
+            Some(self.timestamp)
+        } else {
+            None
+        }
+    }
+
+    /// Has the NIC fill in the IPv4 header and UDP checksums of this mbuf when it is sent out.
+    /// The port must be configured with checksum offload, the IP checksum must be zero, and the
+    /// UDP checksum must be that of the pseudo header.
+    #[inline]
+    pub fn offload_udp_checksums(&mut self, l2_len: usize, l3_len: usize) {
+        self.ol_flags |= PKT_TX_IPV4 | PKT_TX_IP_CKSUM | PKT_TX_UDP_CKSUM;
+        self.tx_offload = (l2_len as u64) | ((l3_len as u64) << 7);
+    }
+
     /// Returns the total allocated size of this mbuf segment.
     /// This is a constant.
     #[inline]
@@ -13,9 +13,13 @@ extern "C" {
         cache_size: u32,
         slots: u16,
     ) -> i32;
+    pub fn set_per_core_mempools(enable: i32);
     pub fn init_thread(tid: i32, core: i32) -> i32;
     pub fn init_secondary(name: *const c_char, nlen: i32, core: i32, vdevs: *mut *const c_char, vdev_count: i32)
         -> i32;
+    pub fn steer_udp_port(udp_port: u16, rxq: i32) -> i32;
+    pub fn set_hw_timestamps(enable: i32);
+    pub fn read_nic_clock(port: i32, ns: *mut u64) -> i32;
     pub fn init_pmd_port(
         port: i32,
         rxqs: i32,
@@ -11,6 +11,7 @@ RTE_DECLARE_PER_LCORE(int, _mempool_core);
 
 typedef struct rte_mbuf* restrict* restrict mbuf_array_t;
 /* Called by system initialization */
+void set_per_core_mempools(int enable);
 int init_mempool_core(int core);
 int init_mempool(int master_core, unsigned int mempool_size, unsigned int mcache_size, unsigned short slots);
 int init_secondary_mempool(const char* mempool_name);
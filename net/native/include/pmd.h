@@ -3,6 +3,9 @@
 int num_pmd_ports();
 int get_pmd_ports(struct rte_eth_dev_info* info, int len);
 void enumerate_pmd_ports();
+int steer_udp_port(uint16_t udp_port, int rxq);
+void set_hw_timestamps(int enable);
+int read_nic_clock(int port, uint64_t* ns);
 int init_pmd_port(int port, int rxqs, int txqs, int rxq_core[], int txq_core[], int nrxd, int ntxd,
                   int loopback, int tso, int csumoffload);
 int free_pmd_port(int port);
@@ -1,6 +1,7 @@
 #include <assert.h>
 #include <stdio.h>
 #include <stdlib.h>
+#include <string.h>
 #include <unistd.h>
 
 #include <rte_config.h>
@@ -13,8 +14,6 @@
 #include <mempool.h>
 #include <simd.h>
 
-#define PER_CORE 0
-
 /* Largely taken from SoftNIC (snbuf.c) */
 #define NUM_MEMPOOL_CACHE 32  // Size of per-core object cache.
 #define METADATA_SLOT_SIZE 8  // size in bytes of a metadata slot
@@ -23,8 +22,12 @@ _Static_assert(METADATA_SLOT_SIZE % RTE_MBUF_PRIV_ALIGN == 0,
 
 RTE_DEFINE_PER_LCORE(int, _mempool_core) = 0;
 
-#if PER_CORE
-/* Creating one pool per core. */
+/* If set, one pool is created per core instead of per NUMA node. Cores then never contend on a
+ * pool's ring, at the cost of packets freed on a different core being returned to the pool they
+ * were allocated from. Must be set before init_mempool(). */
+static int per_core = 0;
+
+/* One pool per NUMA node, or one pool per core (indexed by core) if per_core is set. */
 static struct rte_mempool *pframe_pool[RTE_MAX_LCORE];
 /*Needed for bulk allocation */
 struct rte_mbuf mbuf_template[RTE_MAX_LCORE];
@@ -32,34 +35,18 @@ static int mempool_initialized[RTE_MAX_LCORE];
 static unsigned int core_mempool_size;
 static unsigned int core_mempool_cache_size;
 static unsigned short core_metadata_slots;
-#else
-/* Creating one pool per NUMA node. */
-static struct rte_mempool *pframe_pool[RTE_MAX_NUMA_NODES];
-/*Needed for bulk allocation */
-struct rte_mbuf mbuf_template[RTE_MAX_LCORE];
-#endif
 
-#if PER_CORE
-#define MEMPOOL_ID RTE_PER_LCORE(_mempool_core)
-#else
-#define MEMPOOL_ID rte_socket_id()
-#endif
+#define MEMPOOL_ID (per_core ? RTE_PER_LCORE(_mempool_core) : (int)rte_socket_id())
 
-/* Get mempool for calling thread's socket */
-static inline struct rte_mempool *current_pframe_pool() {
-    return pframe_pool[MEMPOOL_ID];
-}
-
-static inline struct rte_mbuf *current_template() {
-    return &mbuf_template[MEMPOOL_ID];
+void set_per_core_mempools(int enable) {
+    per_core = enable;
 }
 
 int init_mempool_core(int core) {
-#if PER_CORE
     int sid;
     struct rte_mbuf *mbuf;
     char name[256];
-    if (mempool_initialized[core]) {
+    if (!per_core || mempool_initialized[core]) {
         return 0;
     }
     sprintf(name, "pframe%d", core);
@@ -74,26 +61,37 @@ int init_mempool_core(int core) {
     mbuf_template[core]       = *mbuf;
     mempool_initialized[core] = 1;
     rte_pktmbuf_free(mbuf);
-#endif
     return 0;
 }
 
 struct rte_mempool *get_pframe_pool(int coreid, int sid) {
-#if PER_CORE
-    if (unlikely(mempool_initialized[coreid] == 0)) {
-        init_mempool_core(coreid);
-        /* If mempool is not initialized it will be NULL */
+    if (per_core) {
+        if (unlikely(mempool_initialized[coreid] == 0)) {
+            init_mempool_core(coreid);
+            /* If mempool is not initialized it will be NULL */
+        }
+        return pframe_pool[coreid];
     }
-    return pframe_pool[coreid];
-#else
     return pframe_pool[sid];
-#endif
 }
 
 struct rte_mempool *get_mempool_for_core(int coreid) {
     return get_pframe_pool(coreid, rte_lcore_to_socket_id(coreid));
 }
 
+/* Get mempool for calling thread's socket, or core if pools are per-core. Per-core pools are
+ * created the first time a core allocates from them. */
+static inline struct rte_mempool *current_pframe_pool() {
+    if (per_core) {
+        return get_pframe_pool(MEMPOOL_ID, 0);
+    }
+    return pframe_pool[MEMPOOL_ID];
+}
+
+static inline struct rte_mbuf *current_template() {
+    return &mbuf_template[MEMPOOL_ID];
+}
+
 static int init_mempool_socket(int sid, unsigned int mempool_size, unsigned int mcache_size, uint16_t metadata_slots) {
     char name[256];
     sprintf(name, "pframe%d", sid);
@@ -103,7 +101,14 @@ static int init_mempool_socket(int sid, unsigned int mempool_size, unsigned int
 }
 
 int init_mempool(int master_core, unsigned int mempool_size, unsigned int mcache_size, unsigned short metadata_slots) {
-#if (!PER_CORE)
+    if (per_core) {
+        core_mempool_size       = mempool_size;
+        core_mempool_cache_size = mcache_size;
+        core_metadata_slots     = metadata_slots;
+        memset(mempool_initialized, 0, sizeof(int) * RTE_MAX_LCORE);
+        return init_mempool_core(master_core);
+    }
+
     int initialized[RTE_MAX_NUMA_NODES];
     for (int i = 0; i < RTE_MAX_NUMA_NODES; i++) {
         initialized[i] = 0;
@@ -130,59 +135,44 @@ fail:
     /* FIXME: Should ideally free up the pools here, but have no way of
      * doing so currently */
     return -ENOMEM;
-#else
-
-    core_mempool_size       = mempool_size;
-    core_mempool_cache_size = mcache_size;
-    core_metadata_slots     = metadata_slots;
-    memset(mempool_initialized, 0, sizeof(int) * RTE_MAX_LCORE);
-    return init_mempool_core(master_core);
-#endif
 }
 
 static void set_mempool(struct rte_mempool *mempool) {
-#if (!PER_CORE)
     int initialized[RTE_MAX_NUMA_NODES];
     for (int i = 0; i < RTE_MAX_NUMA_NODES; i++) {
         initialized[i] = 0;
     }
-#endif
     if (mempool == NULL) {
         rte_panic("Got a NULL mempool");
     }
     /* Loop through all cores, to see if any of them belong to this
-     * socket. */
+     * socket. A secondary process shares the primary's pool on all cores. */
     for (int i = 0; i < RTE_MAX_LCORE; i++) {
-#if (!PER_CORE)
-        int sid = rte_lcore_to_socket_id(i);
-        if (!initialized[sid]) {
-#endif
-            struct rte_mbuf *mbuf = NULL;
-#if (PER_CORE)
+        struct rte_mbuf *mbuf = NULL;
+        if (per_core) {
             pframe_pool[i] = mempool;
-#else
-        pframe_pool[sid] = mempool;
-#endif
-/* Initialize mbuf template */
-#if PER_CORE
+            /* Initialize mbuf template */
             mbuf = rte_pktmbuf_alloc(pframe_pool[i]);
             if (mbuf == NULL) {
                 rte_panic("Bad mbuf");
             }
-            mbuf_template[i] = *mbuf;
+            mbuf_template[i]       = *mbuf;
+            mempool_initialized[i] = 1;
             rte_pktmbuf_free(mbuf);
-#else
-        mbuf             = rte_pktmbuf_alloc(pframe_pool[sid]);
-        if (mbuf == NULL || mbuf->next != NULL || mbuf->pool == NULL) {
-            rte_panic("Bad mbuf");
-        }
-        mbuf_template[sid] = *mbuf;
-        rte_pktmbuf_free(mbuf);
-#endif
-#if (!PER_CORE)
-            initialized[sid] = 1;
+        } else {
+            int sid = rte_lcore_to_socket_id(i);
+            if (!initialized[sid]) {
+                pframe_pool[sid] = mempool;
+                /* Initialize mbuf template */
+                mbuf = rte_pktmbuf_alloc(pframe_pool[sid]);
+                if (mbuf == NULL || mbuf->next != NULL || mbuf->pool == NULL) {
+                    rte_panic("Bad mbuf");
+                }
+                mbuf_template[sid] = *mbuf;
+                rte_pktmbuf_free(mbuf);
+                initialized[sid] = 1;
+            }
         }
-#endif
     }
 }
 
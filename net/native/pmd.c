@@ -50,6 +50,44 @@ static const struct rte_eth_conf default_eth_conf = {
         },
 };
 
+// UDP destination ports steered to dedicated receive queues by flow director
+// rules. Registered through steer_udp_port() before ports are initialized.
+#define MAX_STEERED_PORTS 64
+static struct {
+    uint16_t udp_port;
+    int rxq;
+} steered_ports[MAX_STEERED_PORTS];
+static int num_steered_ports = 0;
+
+// If set, hardware timestamping is enabled on ports when they are initialized.
+static int hw_timestamps = 0;
+
+void set_hw_timestamps(int enable) {
+    hw_timestamps = enable;
+}
+
+int read_nic_clock(int port, uint64_t* ns) {
+    struct timespec ts;
+    int ret = rte_eth_timesync_read_time(port, &ts);
+    if (ret != 0) {
+        return ret;
+    }
+
+    *ns = (uint64_t)ts.tv_sec * 1000000000ULL + (uint64_t)ts.tv_nsec;
+    return 0;
+}
+
+int steer_udp_port(uint16_t udp_port, int rxq) {
+    if (rxq < 0 || rxq >= 16 || num_steered_ports >= MAX_STEERED_PORTS) {
+        return -EINVAL;
+    }
+
+    steered_ports[num_steered_ports].udp_port = udp_port;
+    steered_ports[num_steered_ports].rxq      = rxq;
+    num_steered_ports++;
+    return 0;
+}
+
 int num_pmd_ports() {
     return rte_eth_dev_count();
 }
@@ -192,6 +230,14 @@ int init_pmd_port(int port, int rxqs, int txqs, int rxq_core[], int txq_core[],
         return ret; /* Clean up things */
     }
 
+    /* Hardware timestamps are best effort. Not every NIC supports them. */
+    if (hw_timestamps) {
+        ret = rte_eth_timesync_enable(port);
+        if (ret != 0) {
+            printf("Port %d does not support hardware timestamps: %s\n", port, strerror(-ret));
+        }
+    }
+
     /* Flow director setup */
     int retval = 0;
 
@@ -216,17 +262,41 @@ int init_pmd_port(int port, int rxqs, int txqs, int rxq_core[], int txq_core[],
     }
     */
 
+    /*
+     * Queues that steered ports are directed to are dedicated to them, and do
+     * not receive packets under the default rules below.
+     */
+    int dedicated[16] = {0};
+    int ndedicated    = 0;
+    for (i = 0; i < num_steered_ports; i++) {
+        if (steered_ports[i].rxq >= rxqs) {
+            rte_exit(EXIT_FAILURE, "Cannot steer UDP port %d to rxq %d, port has %d rxqs\n",
+                     steered_ports[i].udp_port, steered_ports[i].rxq, rxqs);
+        }
+
+        if (!dedicated[steered_ports[i].rxq]) {
+            dedicated[steered_ports[i].rxq] = 1;
+            ndedicated++;
+        }
+    }
+
     /*
      * Next, configure a rule for each receive queue. Redirect packets with UDP
-     * destination port 'i' to receive queue 'i'.
+     * destination port 'i' to receive queue 'i', or to the next queue that is
+     * not dedicated if 'i' is.
      */
     for (i = 0; i < rxqs; i++) {
+        q = i;
+        while (ndedicated < rxqs && dedicated[q]) {
+            q = (q + 1) % rxqs;
+        }
+
         struct rte_eth_fdir_filter fdirf;
         memset(&fdirf, 0, sizeof(fdirf));
         fdirf.soft_id = i;
         fdirf.input.flow_type = RTE_ETH_FLOW_NONFRAG_IPV4_UDP;
         fdirf.input.flow.udp4_flow.dst_port = rte_cpu_to_be_16(i);
-        fdirf.action.rx_queue = i;
+        fdirf.action.rx_queue = q;
         fdirf.action.behavior = RTE_ETH_FDIR_ACCEPT;
         fdirf.action.report_status = RTE_ETH_FDIR_NO_REPORT_STATUS;
         retval = rte_eth_dev_filter_ctrl(port, RTE_ETH_FILTER_FDIR,
@@ -237,6 +307,27 @@ int init_pmd_port(int port, int rxqs, int txqs, int rxq_core[], int txq_core[],
         }
     }
 
+    /*
+     * Finally, configure a rule for each steered port, redirecting packets
+     * with it as their UDP destination port to it's dedicated queue.
+     */
+    for (i = 0; i < num_steered_ports; i++) {
+        struct rte_eth_fdir_filter fdirf;
+        memset(&fdirf, 0, sizeof(fdirf));
+        fdirf.soft_id = rxqs + i;
+        fdirf.input.flow_type = RTE_ETH_FLOW_NONFRAG_IPV4_UDP;
+        fdirf.input.flow.udp4_flow.dst_port = rte_cpu_to_be_16(steered_ports[i].udp_port);
+        fdirf.action.rx_queue = steered_ports[i].rxq;
+        fdirf.action.behavior = RTE_ETH_FDIR_ACCEPT;
+        fdirf.action.report_status = RTE_ETH_FDIR_NO_REPORT_STATUS;
+        retval = rte_eth_dev_filter_ctrl(port, RTE_ETH_FILTER_FDIR,
+                        RTE_ETH_FILTER_ADD, &fdirf);
+        if (retval != 0) {
+            rte_exit(EXIT_FAILURE, "Could not add fdir steering filter: %s\n",
+                            strerror(-retval));
+        }
+    }
+
     return 0;
 }
 
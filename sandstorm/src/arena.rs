@@ -0,0 +1,152 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::cell::{Cell, UnsafeCell};
+use std::mem::size_of;
+use std::slice;
+
+/// A bump allocator handing out short-lived scratch buffers to an extension. Memory is only
+/// reclaimed once the arena is reset (or dropped), so every buffer handed out remains valid and
+/// disjoint from every other buffer until then. Buffers are zeroed and aligned to eight bytes,
+/// allowing them to be used with the unpack functions in the `pack` module.
+pub struct Arena {
+    // The backing memory of the arena. Allocated on the first request for a buffer so that tasks
+    // that never ask for scratch memory do not pay for it. The memory is never reallocated, so
+    // buffers handed out earlier are never invalidated by later requests.
+    mem: UnsafeCell<Vec<u64>>,
+
+    // The total number of bytes that can be handed out by the arena.
+    capacity: usize,
+
+    // The number of bytes handed out so far.
+    used: Cell<usize>,
+}
+
+// Implementation of methods on Arena.
+impl Arena {
+    /// Creates an Arena.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity`: The total number of bytes that can be handed out by the arena.
+    ///
+    /// # Return
+    ///
+    /// An empty `Arena`. No memory is allocated until a buffer is first requested.
+    pub fn new(capacity: usize) -> Arena {
+        Arena {
+            mem: UnsafeCell::new(Vec::new()),
+            capacity: capacity,
+            used: Cell::new(0),
+        }
+    }
+
+    /// Hands out a scratch buffer from the arena.
+    ///
+    /// # Arguments
+    ///
+    /// * `len`: The length of the buffer in bytes.
+    ///
+    /// # Return
+    ///
+    /// A zeroed buffer of `len` bytes. If the arena does not have enough space left, an empty
+    /// buffer is returned.
+    #[cfg_attr(feature = "cargo-clippy", allow(mut_from_ref))]
+    pub fn alloc(&self, len: usize) -> &mut [u8] {
+        let start = self.used.get();
+        if len == 0 || len > self.capacity - start {
+            return &mut [];
+        }
+
+        // Round up so that the next buffer is aligned as well.
+        let next = (start + len + size_of::<u64>() - 1) & !(size_of::<u64>() - 1);
+        self.used.set(next.min(self.capacity));
+
+        unsafe {
+            // The arena hands out disjoint ranges of memory that is never reallocated, so no two
+            // mutable references handed out by it will ever alias.
+            let mem = &mut *self.mem.get();
+            if mem.is_empty() {
+                let words = (self.capacity + size_of::<u64>() - 1) / size_of::<u64>();
+                *mem = vec![0; words];
+            }
+
+            let base = mem.as_mut_ptr() as *mut u8;
+            slice::from_raw_parts_mut(base.offset(start as isize), len)
+        }
+    }
+
+    /// Returns the number of bytes handed out by the arena so far.
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    /// Resets the arena, reclaiming every buffer handed out so far. Requires a mutable reference
+    /// so that no previously handed out buffer can outlive this call.
+    pub fn reset(&mut self) {
+        let used = self.used.get();
+        let mem = unsafe { &mut *self.mem.get() };
+        let words = (used + size_of::<u64>() - 1) / size_of::<u64>();
+        for word in mem.iter_mut().take(words) {
+            *word = 0;
+        }
+
+        self.used.set(0);
+    }
+}
+
+// This module contains simple unit tests for Arena.
+#[cfg(test)]
+mod tests {
+    use super::Arena;
+
+    // This test verifies that buffers handed out by the arena are zeroed, aligned and disjoint.
+    #[test]
+    fn test_arena_alloc() {
+        let arena = Arena::new(64);
+
+        let a = arena.alloc(3);
+        let b = arena.alloc(16);
+        assert_eq!(&[0; 3], a);
+        assert_eq!(&[0; 16], b);
+        assert_eq!(0, b.as_ptr() as usize % 8);
+
+        a.copy_from_slice(&[1, 2, 3]);
+        b[0] = 4;
+        assert_eq!(&[1, 2, 3], a);
+        assert_eq!(24, arena.used());
+    }
+
+    // This test verifies that an exhausted arena hands out empty buffers.
+    #[test]
+    fn test_arena_exhausted() {
+        let arena = Arena::new(16);
+
+        assert_eq!(16, arena.alloc(16).len());
+        assert_eq!(0, arena.alloc(1).len());
+        assert_eq!(0, Arena::new(8).alloc(9).len());
+    }
+
+    // This test verifies that resetting the arena reclaims and zeroes it's memory.
+    #[test]
+    fn test_arena_reset() {
+        let mut arena = Arena::new(16);
+        arena.alloc(16)[15] = 7;
+
+        arena.reset();
+        assert_eq!(0, arena.used());
+        assert_eq!(&[0; 16], arena.alloc(16));
+    }
+}
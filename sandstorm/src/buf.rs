@@ -237,6 +237,69 @@ impl WriteBuf {
         }
     }
 
+    /// This method returns the number of bytes that can still be written to
+    /// the end of the `WriteBuf` by the extension.
+    ///
+    /// # Return
+    /// The number of bytes left between the end of what the extension has
+    /// written so far, and the `WriteBuf`'s capacity.
+    pub fn remaining_capacity(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// This method writes a slice of bytes to the end of the `WriteBuf`, only
+    /// if all of it fits. Unlike `write_slice()`, it never aborts the
+    /// extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The slice of bytes to be written into the `WriteBuf`.
+    ///
+    /// # Return
+    /// True if the slice was written. False if there was insufficent space
+    /// left inside the `WriteBuf`, in which case nothing was written.
+    pub fn write_bytes(&mut self, data: &[u8]) -> bool {
+        if data.len() > self.remaining_capacity() {
+            return false;
+        }
+
+        self.inner.put_slice(data);
+        true
+    }
+
+    /// This method overwrites a single u64 at an offset into the bytes already
+    /// written to the `WriteBuf` by the extension. Offsets are relative to the
+    /// first byte written by the extension, so the object's metadata can never
+    /// be overwritten.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset`: The offset at which the u64 should be written. All eight
+    ///             bytes at this offset must have been written already.
+    /// * `data`:   The u64 to be written into the `WriteBuf`.
+    /// * `le`:     The ordering to be used while performing the write. If true,
+    ///             little-endian will be used. If false, big-endian will be used.
+    ///
+    /// # Return
+    /// True if the u64 was written. False if it would have run past the bytes
+    /// written so far, in which case nothing was written.
+    pub fn write_u64_at(&mut self, offset: usize, data: u64, le: bool) -> bool {
+        if offset > self.len() || self.len() - offset < 8 {
+            return false;
+        }
+
+        let start = self.meta_len + offset;
+        for i in 0..8 {
+            let shift = match le {
+                true => 8 * i,
+                false => 8 * (7 - i),
+            };
+            self.inner[start + i] = (data >> shift) as u8;
+        }
+
+        true
+    }
+
     /// This method consumes the `WriteBuf`, returning a read-only view to the
     /// contained data.
     ///
@@ -392,7 +455,7 @@ mod tests {
         // Verify that the length reported by len() does not include the data
         // written above.
         unsafe {
-            let mut buf = WriteBuf::new(buf);
+            let mut buf = WriteBuf::new(0, buf);
             let data = &[1, 2, 3, 4];
             buf.inner.put_slice(data);
             assert_eq!(data.len(), buf.len());
@@ -411,7 +474,7 @@ mod tests {
         // Wrap up the above BytesMut inside a WriteBuf, and verify that the
         // WriteBuf's capacity does not include the data written above.
         unsafe {
-            let buf = WriteBuf::new(buf);
+            let buf = WriteBuf::new(0, buf);
             assert_eq!(100 - meta.len(), buf.capacity());
         }
     }
@@ -423,7 +486,7 @@ mod tests {
         // Create a WriteBuf, write into it with write_slice(), and the verify
         // that it's contents match what's expected.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(10));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(10));
             let data = &[1, 2, 3, 4, 5];
             buf.write_slice(data);
             assert_eq!(data, &buf.inner[..]);
@@ -437,7 +500,7 @@ mod tests {
     fn test_writebuf_writeslice_overflow() {
         // Create a WriteBuf, and write one byte more than it's capacity.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(100));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(100));
             let data = &[1; 101];
             buf.write_slice(data);
         }
@@ -450,7 +513,7 @@ mod tests {
         // Create a WriteBuf, write into it with write_u8(), and the verify
         // that it's contents match what's expected.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(10));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(10));
             buf.write_u8(200);
 
             let expected = &[200];
@@ -465,7 +528,7 @@ mod tests {
     fn test_writebuf_writeu8_overflow() {
         // Create a WriteBuf, and write one byte more than it's capacity.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(100));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(100));
             let data = &[1; 100];
             buf.write_slice(data);
 
@@ -480,7 +543,7 @@ mod tests {
         // Create a WriteBuf, write into it with write_u16(), and the verify
         // that it's contents match what's expected.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(10));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(10));
             buf.write_u16(258, true);
 
             let expected = &[2, 1];
@@ -495,7 +558,7 @@ mod tests {
         // Create a WriteBuf, write into it with write_u16(), and the verify
         // that it's contents match what's expected.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(10));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(10));
             buf.write_u16(258, false);
 
             let expected = &[1, 2];
@@ -510,7 +573,7 @@ mod tests {
     fn test_writebuf_writeu16_overflow() {
         // Create a WriteBuf, and write two bytes more than it's capacity.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(100));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(100));
             let data = &[1; 100];
             buf.write_slice(data);
 
@@ -525,7 +588,7 @@ mod tests {
         // Create a WriteBuf, write into it with write_u32(), and the verify
         // that it's contents match what's expected.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(10));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(10));
             buf.write_u32(84148994, true);
 
             let expected = &[2, 3, 4, 5];
@@ -540,7 +603,7 @@ mod tests {
         // Create a WriteBuf, write into it with write_u32(), and the verify
         // that it's contents match what's expected.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(10));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(10));
             buf.write_u32(84148994, false);
 
             let expected = &[5, 4, 3, 2];
@@ -555,7 +618,7 @@ mod tests {
     fn test_writebuf_writeu32_overflow() {
         // Create a WriteBuf, and write four bytes more than it's capacity.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(100));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(100));
             let data = &[1; 100];
             buf.write_slice(data);
 
@@ -570,7 +633,7 @@ mod tests {
         // Create a WriteBuf, write into it with write_u64(), and the verify
         // that it's contents match what's expected.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(10));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(10));
             buf.write_u64(8674083586, true);
 
             let expected = &[2, 3, 4, 5, 2, 0, 0, 0];
@@ -585,7 +648,7 @@ mod tests {
         // Create a WriteBuf, write into it with write_u64(), and then verify
         // that it's contents match what's expected.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(10));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(10));
             buf.write_u64(8674083586, false);
 
             let expected = &[0, 0, 0, 2, 5, 4, 3, 2];
@@ -600,11 +663,75 @@ mod tests {
     fn test_writebuf_writeu64_overflow() {
         // Create a WriteBuf, and write eight bytes more than it's capacity.
         unsafe {
-            let mut buf = WriteBuf::new(BytesMut::with_capacity(100));
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(100));
             let data = &[1; 100];
             buf.write_slice(data);
 
             buf.write_u64(8674083586, true);
         }
     }
+
+    // This method tests that "remaining_capacity()" on WriteBuf excludes both
+    // the metadata and the bytes written so far.
+    #[test]
+    fn test_writebuf_remaining_capacity() {
+        let mut buf = BytesMut::with_capacity(100);
+        buf.put_slice(&[1; 30]);
+
+        unsafe {
+            let mut buf = WriteBuf::new(0, buf);
+            assert_eq!(70, buf.remaining_capacity());
+            buf.write_slice(&[2; 10]);
+            assert_eq!(60, buf.remaining_capacity());
+        }
+    }
+
+    // This method tests that "write_bytes()" on WriteBuf writes a slice that
+    // fits, and refuses one that doesn't without writing any of it.
+    #[test]
+    fn test_writebuf_writebytes() {
+        unsafe {
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(8));
+            assert!(buf.write_bytes(&[1, 2, 3, 4, 5]));
+            assert!(!buf.write_bytes(&[6, 7, 8, 9]));
+            assert!(buf.write_bytes(&[6, 7, 8]));
+            assert!(!buf.write_bytes(&[9]));
+
+            let expected = &[1, 2, 3, 4, 5, 6, 7, 8];
+            assert_eq!(expected, &buf.inner[..]);
+        }
+    }
+
+    // This method tests that "write_u64_at()" on WriteBuf overwrites bytes at
+    // an offset past the metadata, in both orders.
+    #[test]
+    fn test_writebuf_writeu64at() {
+        let mut buf = BytesMut::with_capacity(100);
+        buf.put_slice(&[9; 2]);
+
+        unsafe {
+            let mut buf = WriteBuf::new(0, buf);
+            buf.write_slice(&[0; 17]);
+            assert!(buf.write_u64_at(0, 8674083586, true));
+            assert!(buf.write_u64_at(9, 8674083586, false));
+
+            let expected = &[9, 9, 2, 3, 4, 5, 2, 0, 0, 0, 0, 0, 0, 0, 2, 5, 4, 3, 2];
+            assert_eq!(expected, &buf.inner[..]);
+        }
+    }
+
+    // This method tests that "write_u64_at()" on WriteBuf refuses to write
+    // past the bytes written so far.
+    #[test]
+    fn test_writebuf_writeu64at_bounds() {
+        unsafe {
+            let mut buf = WriteBuf::new(0, BytesMut::with_capacity(100));
+            buf.write_slice(&[0; 10]);
+            assert!(buf.write_u64_at(2, 1, true));
+            assert!(!buf.write_u64_at(3, 1, true));
+            assert!(!buf.write_u64_at(11, 1, true));
+            assert!(!buf.write_u64_at(usize::max_value(), 1, true));
+            assert_eq!(10, buf.len());
+        }
+    }
 }
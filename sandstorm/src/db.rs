@@ -14,6 +14,25 @@
  */
 
 use super::buf::{ReadBuf, WriteBuf, MultiReadBuf};
+use super::future::DBFuture;
+use super::schema::Schema;
+
+use std::sync::Arc;
+
+/// The outcome of an in-place update of an object. Refer to `DB::update()`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UpdateStatus {
+    /// The object was updated.
+    Updated,
+
+    /// An overlapping range of the object is being concurrently updated.
+    /// The caller should yield and retry the update.
+    Busy,
+
+    /// The object does not exist, the range lies outside its value, or the
+    /// update could not be logged.
+    Failed,
+}
 
 /// Definition of the DB trait that will allow extensions to access
 /// the database.
@@ -34,8 +53,78 @@ pub trait DB {
     /// exists inside the database.
     fn get(&self, table: u64, key: &[u8]) -> Option<ReadBuf>;
 
+    /// This method will lookup a list of keys inside the database much
+    /// like `get()`, reading all of them as of a single point in time:
+    /// every object returned was current at one instant, after the first
+    /// pass over the keys completed. The database does not write several
+    /// keys atomically, so a snapshot can land in between two puts (ex:
+    /// the puts linking two nodes of a graph), and return one without the
+    /// other.
+    ///
+    /// # Arguments
+    ///
+    /// * `table`:   An identifier of the data table the keys belong to.
+    /// * `key_len`: The length of every key.
+    /// * `keys`:    The keys to be looked up, one after the other.
+    ///
+    /// # Return
+    ///
+    /// A handle that can be used to read the values if every key exists
+    /// inside the database. None if a key does not exist, or if the keys
+    /// kept being written to and could not be read consistently.
     fn multiget(&self, table: u64, key_len: u16, keys: &[u8]) -> Option<MultiReadBuf>;
 
+    /// This method will perform a lookup on a key-value pair inside the
+    /// database much like `get()`, but allows the database to ask the
+    /// extension to yield before the result is handed back, so that a long
+    /// running extension issuing many lookups does not hold up the rest of
+    /// the core. Use `await_db!` to retrieve the result from inside the
+    /// extension's generator.
+    ///
+    /// # Arguments
+    ///
+    /// * `table`: An identifier of the data table the key-value pair
+    ///            belongs to.
+    /// * `key`:   A slice of bytes over the key to be looked up.
+    ///
+    /// # Return
+    ///
+    /// A future that returns what `get()` would have once polled.
+    fn async_get(&self, table: u64, key: &[u8]) -> DBFuture<Option<ReadBuf>>;
+
+    /// This method will perform a lookup on a list of keys much like
+    /// `multiget()`, but allows the database to ask the extension to yield
+    /// before the result is handed back. Refer to `async_get()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table`:   An identifier of the data table the keys belong to.
+    /// * `key_len`: The length of each key in bytes.
+    /// * `keys`:    A slice of bytes over the keys to be looked up.
+    ///
+    /// # Return
+    ///
+    /// A future that returns what `multiget()` would have once polled.
+    fn async_multiget(&self, table: u64, key_len: u16, keys: &[u8])
+        -> DBFuture<Option<MultiReadBuf>>;
+
+    /// This method will perform a lookup on a list of keys that can each
+    /// belong to a different data table, and return a handle that can be
+    /// used to read their values if all of them exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_len`: The length of every key to be looked up.
+    /// * `keys`:    A slice of (table, key) pairs. Each table identifier is
+    ///              eight bytes long and little-endian, and is immediately
+    ///              followed by a key that is `key_len` bytes long.
+    ///
+    /// # Return
+    ///
+    /// A handle that can be used to read the values in the order that their
+    /// keys were supplied in, if all key-value pairs exist inside the database.
+    fn multitable_get(&self, key_len: u16, keys: &[u8]) -> Option<MultiReadBuf>;
+
     /// This method will allocate space for a key-value pair inside the
     /// database, and if the allocation was successfull, return a handle that
     /// can be used to write a value into the allocation, and that can be
@@ -81,6 +170,61 @@ pub trait DB {
     /// * `key`:   A slice of bytes over the key of the object to be deleted.
     fn del(&self, table: u64, key: &[u8]);
 
+    /// This method will overwrite a range of bytes inside the value of an
+    /// existing object in place. Updates to disjoint ranges of the same
+    /// object can proceed in parallel, while overlapping updates are
+    /// serialized. This method never blocks; if it returns `Busy`, the
+    /// extension should yield and then retry.
+    ///
+    /// On tables covered by the server's write-ahead log, the update is instead
+    /// applied to a copy of the object that replaces it, so that the update is
+    /// logged like a put(). `Busy` is then also returned if another write
+    /// replaced the object first.
+    ///
+    /// # Arguments
+    ///
+    /// * `table`:  An identifier of the data table the object belongs to.
+    /// * `key`:    A slice of bytes over the key of the object.
+    /// * `offset`: The offset within the object's value at which to write.
+    /// * `data`:   The bytes to be written into the value.
+    ///
+    /// # Return
+    ///
+    /// The outcome of the update.
+    fn update(&self, table: u64, key: &[u8], offset: usize, data: &[u8]) -> UpdateStatus;
+
+    /// This method will atomically move an object from one data table into
+    /// another, replacing any object under the same key in the destination.
+    /// Unlike a get(), put() and del() issued one after the other, a reader
+    /// never observes the object in both tables or in neither, and a failed
+    /// move leaves both tables untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `src_table`: An identifier of the data table the object is in.
+    /// * `dst_table`: An identifier of the data table the object should be
+    ///                moved into. Must differ from `src_table`.
+    /// * `key`:       A slice of bytes over the key of the object.
+    ///
+    /// # Return
+    ///
+    /// True if the object was moved. False if either table or the object does
+    /// not exist, or if the move failed.
+    fn move_key(&self, src_table: u64, dst_table: u64, key: &[u8]) -> bool;
+
+    /// This method orders the writes issued by the extension. Every `put()`,
+    /// `del()` and `update()` issued before the fence becomes visible to all
+    /// cores no later than any write issued after it: a concurrent reader that
+    /// observes a write issued after the fence also observes every write
+    /// issued before it.
+    ///
+    /// Puts and deletes are already published in the order they were issued,
+    /// and become visible to every read that starts after they return. In
+    /// place updates are not; an extension that updates objects in place must
+    /// call this method before a write that publishes those updates (ex: a
+    /// put() to an index or a flag that readers check first).
+    fn fence(&self);
+
     /// This method will return a serialized version of the arguments that were
     /// passed in by the tenant invoking the extension.
     ///
@@ -99,6 +243,82 @@ pub trait DB {
     ///               extension should perform said serialization for now.
     fn resp(&self, response: &[u8]);
 
+    /// This method will fail the invocation, and return an error to the
+    /// tenant that invoked the extension. Anything previously written to the
+    /// response through `resp()` is discarded, and subsequent calls to
+    /// `resp()` have no effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `code`: An extension defined error code.
+    /// * `msg`:  A short human readable description of the error. Messages
+    ///           longer than 128 bytes are truncated.
+    fn resp_error(&self, code: u32, msg: &str);
+
+    /// This method will end the invocation early with partial results. An
+    /// extension should call this when it decides to stop before completing
+    /// (ex: when `now_cycles()` indicates that it has run for too long).
+    /// Anything previously written to the response through `resp()` is
+    /// returned to the tenant along with a continuation token, and
+    /// subsequent calls to `resp()` have no effect. The tenant can resume
+    /// from where the extension stopped by passing the token back in the
+    /// arguments to a fresh invocation; interpreting the token is entirely
+    /// upto the extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `token`: An extension defined continuation token. Tokens longer than
+    ///            64 bytes are truncated.
+    fn resp_partial(&self, token: &[u8]);
+
+    /// This method hands out short-lived scratch memory to the extension,
+    /// for use as a temporary buffer during computation. Unlike `alloc()`,
+    /// scratch memory does not count against the tenant's quota and is never
+    /// written to the database; it is reclaimed once the extension completes.
+    ///
+    /// # Arguments
+    ///
+    /// * `len`: The length of the scratch buffer in bytes.
+    ///
+    /// # Return
+    ///
+    /// A zeroed buffer aligned to eight bytes. If the extension has used up
+    /// all scratch memory available to it, an empty buffer is returned.
+    #[cfg_attr(feature = "cargo-clippy", allow(mut_from_ref))]
+    fn scratch(&self, len: usize) -> &mut [u8];
+
+    /// This method returns the current value of a monotonically increasing
+    /// clock. Extensions should use this method for timestamps instead of
+    /// reading the timestamp counter themselves.
+    ///
+    /// # Return
+    ///
+    /// The current time in cycles.
+    fn now_cycles(&self) -> u64;
+
+    /// This method fills a buffer with random bytes. Extensions should use
+    /// this method for randomness (ex: sampling) instead of linking in their
+    /// own generators. The bytes are not suitable for cryptographic use.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf`: The buffer to be filled with random bytes.
+    fn random_bytes(&self, buf: &mut [u8]);
+
+    /// This method looks up the schema the tenant registered for a table, so
+    /// that extensions can find fields on the table's values by name instead
+    /// of hardcoding their offsets.
+    ///
+    /// # Arguments
+    ///
+    /// * `table`: An identifier for the table.
+    ///
+    /// # Return
+    ///
+    /// The table's schema. None if the table does not exist or if the tenant
+    /// never registered a schema for it.
+    fn schema(&self, table: u64) -> Option<Arc<Schema>>;
+
     /// This method is meant for testing, and will not do anything in the real
     /// system.
     fn debug_log(&self, msg: &str);
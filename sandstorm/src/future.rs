@@ -0,0 +1,158 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+/// The outcome of polling a `DBFuture`.
+#[derive(Debug, PartialEq)]
+pub enum Poll<T> {
+    /// The operation completed, and this is it's result.
+    Ready(T),
+
+    /// The database wants the extension to yield before it hands back the result. The extension
+    /// should yield, and poll the future again once it is resumed.
+    Pending,
+}
+
+/// This type represents the result of an asynchronous operation issued through the `DB` trait
+/// (ex: `async_get()`). The database decides when the operation is issued whether the extension
+/// has run for long enough since it was last resumed that it should yield first. If so, the
+/// first poll returns `Pending`, and only the poll after returns the result. Extensions that
+/// issue many operations back to back should use the `await_db!` macro, which yields on their
+/// behalf, so that they do not hold up the rest of the core.
+pub struct DBFuture<T> {
+    // The result of the operation. Taken once it is returned by a poll.
+    value: Option<T>,
+
+    // True if the result can be returned on the next poll.
+    ready: bool,
+}
+
+// Methods on DBFuture.
+impl<T> DBFuture<T> {
+    /// This method returns a future holding the result of an operation.
+    ///
+    /// This function is marked `unsafe` to prevent extensions from constructing
+    /// a `DBFuture` on their own. The only way an extension should be able to
+    /// see a `DBFuture` is by making an async call on some type that implements
+    /// the `DB` trait.
+    ///
+    /// # Arguments
+    ///
+    /// * `value`: The result of the operation.
+    /// * `ready`: True if the result can be returned without the extension yielding first.
+    ///
+    /// # Return
+    /// The `DBFuture` holding the passed in result.
+    pub unsafe fn new(value: T, ready: bool) -> DBFuture<T> {
+        DBFuture {
+            value: Some(value),
+            ready: ready,
+        }
+    }
+
+    /// This method indicates if the next poll on the future will return it's result.
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// This method polls the future for the result of it's operation.
+    ///
+    /// # Return
+    ///
+    /// The result if the extension does not need to yield first. Otherwise, `Pending`; the next
+    /// poll then returns the result.
+    ///
+    /// # Panics
+    ///
+    /// If the future is polled again after it returned it's result.
+    pub fn poll(&mut self) -> Poll<T> {
+        if !self.ready {
+            self.ready = true;
+            return Poll::Pending;
+        }
+
+        match self.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => panic!("DBFuture polled after it returned it's result."),
+        }
+    }
+}
+
+/// Evaluates to the result of a `DBFuture`, yielding to the database from the enclosing
+/// generator for as long as the future is pending. Can only be used inside an extension's
+/// generator. Extensions import it with `#[macro_use] extern crate sandstorm;`.
+#[macro_export]
+macro_rules! await_db {
+    ($future:expr) => {{
+        let mut future = $future;
+        loop {
+            match future.poll() {
+                $crate::future::Poll::Ready(value) => break value,
+                $crate::future::Poll::Pending => yield 0,
+            }
+        }
+    }};
+}
+
+// This module contains simple unit tests for DBFuture.
+#[cfg(test)]
+mod tests {
+    use super::{DBFuture, Poll};
+    use std::ops::{Generator, GeneratorState};
+
+    // This test verifies that a future that is not ready returns it's result only on the poll
+    // after the first one, and that a ready one returns it right away.
+    #[test]
+    fn test_future_poll() {
+        let mut pending = unsafe { DBFuture::new(7, false) };
+        assert!(!pending.is_ready());
+        assert_eq!(Poll::Pending, pending.poll());
+        assert!(pending.is_ready());
+        assert_eq!(Poll::Ready(7), pending.poll());
+
+        let mut ready = unsafe { DBFuture::new(8, true) };
+        assert_eq!(Poll::Ready(8), ready.poll());
+    }
+
+    // This test verifies that polling a future after it returned it's result panics.
+    #[test]
+    #[should_panic]
+    fn test_future_poll_twice() {
+        let mut ready = unsafe { DBFuture::new(8, true) };
+        let _ = ready.poll();
+        let _ = ready.poll();
+    }
+
+    // This test verifies that await_db!() yields from a generator only for pending futures.
+    #[test]
+    fn test_future_await() {
+        let mut gen = || {
+            let a = await_db!(unsafe { DBFuture::new(1, true) });
+            let b = await_db!(unsafe { DBFuture::new(2, false) });
+            return a + b;
+        };
+
+        unsafe {
+            match gen.resume() {
+                GeneratorState::Yielded(0) => {}
+                _ => panic!("Expected a yield on a pending future."),
+            }
+
+            match gen.resume() {
+                GeneratorState::Complete(3) => {}
+                _ => panic!("Expected the sum of both results."),
+            }
+        }
+    }
+}
@@ -16,6 +16,7 @@
 #![feature(type_ascription)]
 #![feature(generator_trait)]
 #![feature(rustc_private)]
+#![cfg_attr(test, feature(generators))]
 
 pub mod db;
 pub mod buf;
@@ -23,6 +24,9 @@ pub mod null;
 pub mod mock;
 pub mod pack;
 pub mod allocator;
+pub mod arena;
+pub mod schema;
+pub mod future;
 
 pub use std::vec;
 pub use std::result;
@@ -16,16 +16,24 @@
 use std::fmt::Debug;
 
 use super::buf::{ReadBuf, WriteBuf, MultiReadBuf};
-use super::db::DB;
+use super::arena::Arena;
+use super::db::{UpdateStatus, DB};
+use super::future::DBFuture;
+use super::schema::Schema;
 
 extern crate bytes;
 use self::bytes::{Bytes, BytesMut};
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 pub struct MockDB {
     messages: RefCell<Vec<String>>,
     args: [u8; 30],
+    clock: Cell<u64>,
+    scratch: Arena,
+    schemas: RefCell<HashMap<u64, Arc<Schema>>>,
 }
 
 impl MockDB {
@@ -33,6 +41,9 @@ impl MockDB {
         MockDB {
             messages: RefCell::new(Vec::new()),
             args: [97; 30],
+            clock: Cell::new(0),
+            scratch: Arena::new(1 << 16),
+            schemas: RefCell::new(HashMap::new()),
         }
     }
 
@@ -48,6 +59,11 @@ impl MockDB {
         let mut messages = self.messages.borrow_mut();
         messages.clear();
     }
+
+    /// Registers a schema for a table, to be returned by schema().
+    pub fn register_schema(&self, table: u64, schema: Schema) {
+        self.schemas.borrow_mut().insert(table, Arc::new(schema));
+    }
 }
 
 impl DB for MockDB {
@@ -69,6 +85,36 @@ impl DB for MockDB {
         unsafe { Some(MultiReadBuf::new(Vec::new())) }
     }
 
+    fn async_get(&self, table: u64, key: &[u8]) -> DBFuture<Option<ReadBuf>> {
+        self.debug_log(&format!(
+            "Invoked async_get() on table {} for key {:?}",
+            table, key
+        ));
+
+        // Always ask the extension to yield, so that tests exercise it's yield points.
+        unsafe { DBFuture::new(Some(ReadBuf::new(Bytes::with_capacity(0))), false) }
+    }
+
+    fn async_multiget(&self, table: u64, key_len: u16, keys: &[u8])
+        -> DBFuture<Option<MultiReadBuf>>
+    {
+        self.debug_log(&format!(
+            "Invoked async_multiget() on table {} for keys {:?} with key length {}",
+            table, keys, key_len
+        ));
+
+        unsafe { DBFuture::new(Some(MultiReadBuf::new(Vec::new())), false) }
+    }
+
+    fn multitable_get(&self, key_len: u16, keys: &[u8]) -> Option<MultiReadBuf> {
+        self.debug_log(&format!(
+            "Invoked multitable_get() for keys {:?} with key length {}",
+            keys, key_len
+        ));
+
+        unsafe { Some(MultiReadBuf::new(Vec::new())) }
+    }
+
     fn alloc(&self, table: u64, key: &[u8], val_len: u64) -> Option<WriteBuf> {
         self.debug_log(&format!(
             "Invoked alloc(), table {}, key {:?}, val_len {}",
@@ -93,6 +139,24 @@ impl DB for MockDB {
         ));
     }
 
+    fn update(&self, table: u64, key: &[u8], offset: usize, data: &[u8]) -> UpdateStatus {
+        self.debug_log(&format!(
+            "Invoked update() on table {} for key {:?}, offset {}, data {:?}",
+            table, key, offset, data
+        ));
+
+        return UpdateStatus::Updated;
+    }
+
+    fn move_key(&self, src_table: u64, dst_table: u64, key: &[u8]) -> bool {
+        self.debug_log(&format!(
+            "Invoked move_key() from table {} to table {} for key {:?}",
+            src_table, dst_table, key
+        ));
+
+        return true;
+    }
+
     fn args(&self) -> &[u8] {
         self.debug_log(&format!("Invoked args()"));
 
@@ -103,6 +167,47 @@ impl DB for MockDB {
         self.debug_log(&format!("Invoked resp(), data {:?}", data));
     }
 
+    fn resp_error(&self, code: u32, msg: &str) {
+        self.debug_log(&format!("Invoked resp_error(), code {}, msg {}", code, msg));
+    }
+
+    fn resp_partial(&self, token: &[u8]) {
+        self.debug_log(&format!("Invoked resp_partial(), token {:?}", token));
+    }
+
+    fn scratch(&self, len: usize) -> &mut [u8] {
+        self.debug_log(&format!("Invoked scratch(), len {}", len));
+
+        self.scratch.alloc(len)
+    }
+
+    fn now_cycles(&self) -> u64 {
+        self.debug_log(&format!("Invoked now_cycles()"));
+
+        // Advance the clock by one on every call so that tests are deterministic.
+        let now = self.clock.get() + 1;
+        self.clock.set(now);
+        return now;
+    }
+
+    fn random_bytes(&self, buf: &mut [u8]) {
+        self.debug_log(&format!("Invoked random_bytes(), len {}", buf.len()));
+
+        for byte in buf.iter_mut() {
+            *byte = 0;
+        }
+    }
+
+    fn schema(&self, table: u64) -> Option<Arc<Schema>> {
+        self.debug_log(&format!("Invoked schema() on table {}", table));
+
+        self.schemas.borrow().get(&table).cloned()
+    }
+
+    fn fence(&self) {
+        self.debug_log(&format!("Invoked fence()"));
+    }
+
     fn debug_log(&self, message: &str) {
         let mut messages = self.messages.borrow_mut();
         messages.push(String::from(message));
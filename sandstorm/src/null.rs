@@ -15,9 +15,13 @@
 
 use std::fmt::Debug;
 
-use super::db::DB;
+use super::db::{UpdateStatus, DB};
 
 use super::buf::{ReadBuf, WriteBuf, MultiReadBuf};
+use super::future::DBFuture;
+use super::schema::Schema;
+
+use std::sync::Arc;
 
 pub struct NullDB {}
 
@@ -44,6 +48,20 @@ impl DB for NullDB {
         return None;
     }
 
+    fn async_get(&self, _table: u64, _key: &[u8]) -> DBFuture<Option<ReadBuf>> {
+        unsafe { DBFuture::new(None, true) }
+    }
+
+    fn async_multiget(&self, _table: u64, _key_len: u16, _keys: &[u8])
+        -> DBFuture<Option<MultiReadBuf>>
+    {
+        unsafe { DBFuture::new(None, true) }
+    }
+
+    fn multitable_get(&self, _key_len: u16, _keys: &[u8]) -> Option<MultiReadBuf> {
+        return None;
+    }
+
     fn alloc(&self, _table: u64, _key: &[u8], _val_len: u64) -> Option<WriteBuf> {
         return None;
     }
@@ -54,11 +72,39 @@ impl DB for NullDB {
 
     fn del(&self, _table: u64, _key: &[u8]) {}
 
+    fn update(&self, _table: u64, _key: &[u8], _offset: usize, _data: &[u8]) -> UpdateStatus {
+        return UpdateStatus::Failed;
+    }
+
+    fn move_key(&self, _src_table: u64, _dst_table: u64, _key: &[u8]) -> bool {
+        return false;
+    }
+
     fn args(&self) -> &[u8] {
         return &[];
     }
 
     fn resp(&self, _data: &[u8]) {}
 
+    fn resp_error(&self, _code: u32, _msg: &str) {}
+
+    fn resp_partial(&self, _token: &[u8]) {}
+
+    fn scratch(&self, _len: usize) -> &mut [u8] {
+        return &mut [];
+    }
+
+    fn now_cycles(&self) -> u64 {
+        return 0;
+    }
+
+    fn random_bytes(&self, _buf: &mut [u8]) {}
+
+    fn schema(&self, _table: u64) -> Option<Arc<Schema>> {
+        return None;
+    }
+
+    fn fence(&self) {}
+
     fn debug_log(&self, _message: &str) {}
 }
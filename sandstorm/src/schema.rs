@@ -0,0 +1,326 @@
+/* Copyright (c) 2018 University of Utah
+ *
+ * Permission to use, copy, modify, and distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR(S) DISCLAIM ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL AUTHORS BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+use std::collections::HashSet;
+use std::str;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+/// The longest name a field on a schema can have, in bytes.
+pub const MAX_FIELD_NAME: usize = 64;
+
+/// The largest number of fields a schema can have.
+pub const MAX_FIELDS: usize = 256;
+
+/// The type of a field on a value. Fixed width types are laid out little-endian.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldType {
+    U8 = 1,
+    U16 = 2,
+    U32 = 3,
+    U64 = 4,
+    I8 = 5,
+    I16 = 6,
+    I32 = 7,
+    I64 = 8,
+    F32 = 9,
+    F64 = 10,
+    Bool = 11,
+
+    /// An opaque run of bytes of the length declared on the field (ex: a fixed length string).
+    Bytes = 12,
+}
+
+// Implementation of methods on FieldType.
+impl FieldType {
+    // Returns the type encoded as `kind` on a schema, if there is one.
+    fn from_u8(kind: u8) -> Option<FieldType> {
+        match kind {
+            1 => Some(FieldType::U8),
+            2 => Some(FieldType::U16),
+            3 => Some(FieldType::U32),
+            4 => Some(FieldType::U64),
+            5 => Some(FieldType::I8),
+            6 => Some(FieldType::I16),
+            7 => Some(FieldType::I32),
+            8 => Some(FieldType::I64),
+            9 => Some(FieldType::F32),
+            10 => Some(FieldType::F64),
+            11 => Some(FieldType::Bool),
+            12 => Some(FieldType::Bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns the width of the type in bytes. None for `Bytes`, whose width is declared on the
+    /// field.
+    pub fn width(&self) -> Option<u32> {
+        match *self {
+            FieldType::U8 | FieldType::I8 | FieldType::Bool => Some(1),
+            FieldType::U16 | FieldType::I16 => Some(2),
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => Some(4),
+            FieldType::U64 | FieldType::I64 | FieldType::F64 => Some(8),
+            FieldType::Bytes => None,
+        }
+    }
+}
+
+/// A single named field on a value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    /// The name of the field. Unique within the schema.
+    pub name: String,
+
+    /// The type of the field.
+    pub kind: FieldType,
+
+    /// The offset of the field from the start of the value in bytes.
+    pub offset: u32,
+
+    /// The length of the field in bytes. Must be the width of `kind` for fixed width types.
+    pub len: u32,
+}
+
+// Implementation of methods on Field.
+impl Field {
+    /// Returns the bytes of this field on a value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value`: A value laid out according to the schema this field belongs to.
+    ///
+    /// # Return
+    ///
+    /// A slice over the field. None if the value is too short to contain it.
+    pub fn slice<'a>(&self, value: &'a [u8]) -> Option<&'a [u8]> {
+        let start = self.offset as usize;
+        let end = start + self.len as usize;
+        if end > value.len() {
+            return None;
+        }
+
+        Some(&value[start..end])
+    }
+}
+
+/// This type describes the layout of the values on a table, so that independently developed
+/// extensions of a tenant can look up fields on values by name instead of baking offsets into
+/// their code. Schemas are registered per table by the tenant, and are purely descriptive; the
+/// database never checks values written to the table against their schema.
+///
+/// A schema is encoded as the number of fields (u16) followed by every field, each of which is
+/// laid out as: type(1) | offset(4) | len(4) | name_len(1) | name. All integers are
+/// little-endian.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    // The fields on the schema, in the order they were declared.
+    fields: Vec<Field>,
+}
+
+// Implementation of methods on Schema.
+impl Schema {
+    /// Constructs a schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `fields`: The fields on values. Names must be unique and non-empty, no longer than
+    ///             `MAX_FIELD_NAME` bytes, and fixed width fields must have the length of their
+    ///             type.
+    ///
+    /// # Return
+    ///
+    /// The schema. None if any of the fields are invalid, or if there are more than
+    /// `MAX_FIELDS` of them.
+    pub fn new(fields: Vec<Field>) -> Option<Schema> {
+        if fields.len() > MAX_FIELDS {
+            return None;
+        }
+
+        let mut names = HashSet::new();
+        for field in fields.iter() {
+            if field.name.is_empty() || field.name.len() > MAX_FIELD_NAME {
+                return None;
+            }
+
+            if !names.insert(field.name.as_str()) {
+                return None;
+            }
+
+            match field.kind.width() {
+                Some(width) if width != field.len => return None,
+                _ => {}
+            }
+
+            if field.offset.checked_add(field.len).is_none() {
+                return None;
+            }
+        }
+
+        Some(Schema { fields: fields })
+    }
+
+    /// Returns every field on the schema, in the order they were declared.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Looks up a field on the schema by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The name of the field.
+    ///
+    /// # Return
+    ///
+    /// The field if it exists.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Returns the number of bytes a value must have to contain every field on the schema.
+    pub fn min_len(&self) -> u32 {
+        self.fields
+            .iter()
+            .map(|field| field.offset + field.len)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Encodes the schema, so that it can be sent to the server.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let _ = buf.write_u16::<LittleEndian>(self.fields.len() as u16);
+
+        for field in self.fields.iter() {
+            let _ = buf.write_u8(field.kind as u8);
+            let _ = buf.write_u32::<LittleEndian>(field.offset);
+            let _ = buf.write_u32::<LittleEndian>(field.len);
+            let _ = buf.write_u8(field.name.len() as u8);
+            buf.extend_from_slice(field.name.as_bytes());
+        }
+
+        buf
+    }
+
+    /// Decodes a schema encoded by `encode()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf`: The encoded schema.
+    ///
+    /// # Return
+    ///
+    /// The schema. None if the encoding is malformed, has trailing bytes, or describes an
+    /// invalid schema.
+    pub fn decode(mut buf: &[u8]) -> Option<Schema> {
+        let count = buf.read_u16::<LittleEndian>().ok()? as usize;
+        if count > MAX_FIELDS {
+            return None;
+        }
+
+        let mut fields = Vec::with_capacity(count);
+        for _ in 0..count {
+            let kind = FieldType::from_u8(buf.read_u8().ok()?)?;
+            let offset = buf.read_u32::<LittleEndian>().ok()?;
+            let len = buf.read_u32::<LittleEndian>().ok()?;
+            let name_len = buf.read_u8().ok()? as usize;
+            if buf.len() < name_len {
+                return None;
+            }
+
+            let (name, rest) = buf.split_at(name_len);
+            buf = rest;
+            fields.push(Field {
+                name: str::from_utf8(name).ok()?.to_string(),
+                kind: kind,
+                offset: offset,
+                len: len,
+            });
+        }
+
+        if !buf.is_empty() {
+            return None;
+        }
+
+        Schema::new(fields)
+    }
+}
+
+// This module contains simple unit tests for Schema.
+#[cfg(test)]
+mod tests {
+    use super::{Field, FieldType, Schema};
+
+    // Returns a schema for a value with an 8 byte id followed by a 16 byte name.
+    fn user() -> Schema {
+        Schema::new(vec![
+            Field {
+                name: "id".to_string(),
+                kind: FieldType::U64,
+                offset: 0,
+                len: 8,
+            },
+            Field {
+                name: "name".to_string(),
+                kind: FieldType::Bytes,
+                offset: 8,
+                len: 16,
+            },
+        ]).expect("Failed to create schema.")
+    }
+
+    // This test verifies that an encoded schema decodes back to the original, and that fields
+    // can be looked up on it by name.
+    #[test]
+    fn test_schema_roundtrip() {
+        let schema = user();
+        let decoded = Schema::decode(&schema.encode()).expect("Failed to decode schema.");
+        assert_eq!(schema, decoded);
+
+        let name = decoded.field("name").expect("Missing field.");
+        assert_eq!((8, 16), (name.offset, name.len));
+        assert!(decoded.field("email").is_none());
+        assert_eq!(24, decoded.min_len());
+
+        let mut value = vec![0; 24];
+        value[8] = 0x41;
+        assert_eq!(Some(&value[8..24]), name.slice(&value));
+        assert_eq!(None, name.slice(&value[..20]));
+    }
+
+    // This test verifies that invalid schemas and malformed encodings are rejected.
+    #[test]
+    fn test_schema_invalid() {
+        let mut fields = user().fields().to_vec();
+        fields[1].name = "id".to_string();
+        assert!(Schema::new(fields.clone()).is_none());
+
+        fields[1].name = "name".to_string();
+        fields[0].len = 4;
+        assert!(Schema::new(fields).is_none());
+
+        let encoded = user().encode();
+        assert!(Schema::decode(&encoded[..encoded.len() - 1]).is_none());
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(Schema::decode(&trailing).is_none());
+
+        let mut kind = encoded.clone();
+        kind[2] = 0xff;
+        assert!(Schema::decode(&kind).is_none());
+    }
+}